@@ -0,0 +1,21 @@
+#version 330 core
+in vec4 varying_color;
+in vec2 varying_texcoord;
+out vec4 color;
+
+uniform sampler2D al_tex;
+// One texel along the direction of this pass, the blur is done in two.
+uniform vec2 blur_step;
+
+void main()
+{
+	float weights[5] = float[](0.227, 0.195, 0.122, 0.054, 0.016);
+	vec4 sum = weights[0] * texture(al_tex, varying_texcoord);
+	for (int i = 1; i < 5; i++)
+	{
+		vec2 offset = 2. * float(i) * blur_step;
+		sum += weights[i] * texture(al_tex, varying_texcoord + offset);
+		sum += weights[i] * texture(al_tex, varying_texcoord - offset);
+	}
+	color = varying_color * sum;
+}
@@ -2,12 +2,15 @@
 in vec2 varying_texcoord;
 layout(location = 0) out vec4 out_color;
 
-//uniform vec3 camera_pos;
+uniform vec3 camera_pos;
 
 uniform sampler2D al_tex; // Light buffer.
 uniform sampler2D position_buffer;
 uniform sampler2D normal_buffer;
 uniform sampler2D albedo_buffer;
+uniform vec3 tint;
+// 0 is the final image, the rest show one of the buffers, see DebugView.
+uniform int debug_view;
 
 void main()
 {
@@ -22,8 +25,15 @@ void main()
     vec3 final_light_color = (1 - fullbright) * light_color.xyz + fullbright;
 
     float specular = light_color.w;
-    out_color = vec4(final_light_color * color.xyz + 1. * vec3(specular), 1.);
-    //out_color = vec4(mod(pos.xyz, 1), 1);
-    //out_color = vec4(normal, 1);
-    //out_color = vec4(1., material, 0., 1.);
+    out_color = vec4(tint * (final_light_color * color.xyz + 1. * vec3(specular)), 1.);
+    if (debug_view == 1)
+        out_color = vec4(color.xyz, 1.);
+    else if (debug_view == 2)
+        out_color = vec4(0.5 * normal + 0.5, 1.);
+    else if (debug_view == 3)
+        out_color = vec4(mod(pos.xyz, 10.) / 10., 1.);
+    else if (debug_view == 4)
+        out_color = vec4(vec3(1. - clamp(length(pos - camera_pos) / 200., 0., 1.)), 1.);
+    else if (debug_view == 5)
+        out_color = vec4(final_light_color + vec3(specular), 1.);
 }
@@ -11,12 +11,21 @@ layout(location = 2) out vec4 albedo_buffer;
 uniform sampler2D al_tex;
 
 uniform float material;
+// From comps::RenderFlags.
+uniform float unlit;
+uniform float fade;
+// How far into the murk something underwater is.
+uniform float underwater;
 
 void main()
 {
     vec4 tex_color = texture(al_tex, varying_texcoord);
     if (tex_color.a == 0.0) discard;
+    // Screen-door fade, there's no blending into the G-buffer.
+    float noise = fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453);
+    if (noise >= fade) discard;
     position_buffer = varying_pos;
-    normal_buffer = vec4(normalize(varying_normal), material);
-	albedo_buffer = varying_color * tex_color;
+    // Material 2 is fullbright.
+    normal_buffer = vec4(normalize(varying_normal), mix(material, 2., unlit));
+	albedo_buffer = mix(varying_color * tex_color, vec4(0.05, 0.2, 0.25, 1.), underwater);
 }
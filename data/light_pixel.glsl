@@ -4,6 +4,9 @@ out vec4 color;
 uniform vec4 light_color;
 uniform vec3 light_pos;
 uniform float light_intensity;
+uniform vec3 light_dir;
+// Cosine of the cone half-angle, < -1 for omni lights.
+uniform float light_cone;
 uniform vec2 buffer_size;
 uniform vec3 camera_pos;
 
@@ -29,5 +32,6 @@ void main()
     vec3 diff = (light_pos - pos) / light_intensity;
     float dist_sq = dot(diff, diff);
     float dist_frac = 1 / (1 + dist_sq * dist_sq);
+    dist_frac *= smoothstep(light_cone, mix(light_cone, 1., 0.3), dot(-ray_dir, light_dir));
 	color = dist_frac * vec4((light_color * diffuse_dot).xyz, specular_dot * float(material == 1.));
 }
@@ -1,5 +1,8 @@
 #version 330 core
 uniform float time;
+uniform float cell_size;
+// Global position of the origin, wrapped to the wave pattern.
+uniform vec2 water_origin;
 in vec3 varying_pos;
 
 layout(location = 0) out vec3 position_buffer;
@@ -9,19 +12,42 @@ layout(location = 2) out vec4 albedo_buffer;
 uniform sampler2D al_tex;
 
 #define PI 3.14159265359
+#define CELL_RADIUS 2
+#define CELL_GRID (2 * CELL_RADIUS + 1)
+
+// Per-cell RGB albedo + wave strength, centered on the player's cell.
+uniform vec4 water_params[CELL_GRID * CELL_GRID];
+
+vec4 cell_params(ivec2 cell)
+{
+    cell = clamp(cell + CELL_RADIUS, 0, CELL_GRID - 1);
+    return water_params[cell.y * CELL_GRID + cell.x];
+}
 
 void main()
 {
     position_buffer = varying_pos;
     //float v = dot(normalize(vec2(1., 1.)), vec2(varying_pos.x + cos(time) * sin(varying_pos.z), varying_pos.z));
     
-    float u = 2 * varying_pos.z + varying_pos.x;
-    float v = varying_pos.z + 2 * varying_pos.x;
+    vec2 wave_pos = varying_pos.xz + water_origin;
+    float u = 2 * wave_pos.y + wave_pos.x;
+    float v = wave_pos.y + 2 * wave_pos.x;
+
+    // Blend between the neighboring cell centers, mostly near the cell edges.
+    vec2 c = varying_pos.xz / cell_size;
+    vec2 base = floor(c);
+    vec2 f = smoothstep(0.3, 0.7, c - base);
+    ivec2 ibase = ivec2(base);
+    vec4 params = mix(
+        mix(cell_params(ibase), cell_params(ibase + ivec2(1, 0)), f.x),
+        mix(cell_params(ibase + ivec2(0, 1)), cell_params(ibase + ivec2(1, 1)), f.x),
+        f.y);
+    float amp = params.a;
 
     float var2 = sin((u / 8 * 2. * PI) / 2. + time / 4.);
     float var = sin((v / 8 * 2. * PI) + var2 + time / 2.);
-    vec3 normal = normalize(vec3(0.5 * sign(var) * pow(abs(var), 0.5), 1., var2));
+    vec3 normal = normalize(vec3(amp * 0.5 * sign(var) * pow(abs(var), 0.5), 1., amp * var2));
     //vec3 normal = normalize(vec3(0., 1., 0.));
     normal_buffer = vec4(normal, 1.);
-    albedo_buffer = vec4(0.1, 0.1, 0.8, 1.);
+    albedo_buffer = vec4(params.rgb, 1.);
 }
@@ -130,6 +130,38 @@ impl Atlas
 		}
 	}
 
+	// Like insert_from_file, but remembers what's been packed and refuses bitmaps
+	// larger than max_size on either side.
+	pub fn cache_from_file(
+		&mut self, core: &Core, filename: &str, max_size: i32,
+	) -> Result<Option<AtlasBitmap>>
+	{
+		if let Some(atlas_bitmap) = self.bitmaps.get(filename)
+		{
+			return Ok(Some(atlas_bitmap.clone()));
+		}
+		let old_flags = core.get_new_bitmap_flags();
+		core.set_new_bitmap_flags(MEMORY_BITMAP);
+		let bitmap = utils::load_bitmap(core, filename);
+		core.set_new_bitmap_flags(old_flags);
+		let bitmap = bitmap?;
+		if bitmap.get_width() > max_size || bitmap.get_height() > max_size
+		{
+			return Ok(None);
+		}
+		let atlas_bitmap = self
+			.insert(core, &bitmap)
+			.map_err(|e| e.context(format!("Couldn't fit bitmap {}", filename)))?;
+		self.bitmaps
+			.insert(filename.to_string(), atlas_bitmap.clone());
+		Ok(Some(atlas_bitmap))
+	}
+
+	pub fn page_size(&self) -> i32
+	{
+		self.page_size
+	}
+
 	pub fn insert_from_file(&mut self, core: &Core, filename: &str) -> Result<AtlasBitmap>
 	{
 		let old_flags = core.get_new_bitmap_flags();
@@ -0,0 +1,170 @@
+use crate::game::Commodity;
+use crate::journal::DAY_LENGTH;
+
+use std::fmt;
+
+// Days to a season, four seasons to a year.
+pub const SEASON_DAYS: i32 = 10;
+// The voyage sets out on the first day of spring.
+const START_YEAR: i32 = 1715;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Season
+{
+	Spring,
+	Summer,
+	Autumn,
+	Winter,
+}
+
+impl Season
+{
+	const ALL: [Season; 4] = [
+		Season::Spring,
+		Season::Summer,
+		Season::Autumn,
+		Season::Winter,
+	];
+
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Season::Spring => "Spring",
+			Season::Summer => "Summer",
+			Season::Autumn => "Autumn",
+			Season::Winter => "Winter",
+		}
+	}
+
+	// What everything gets lit with.
+	pub fn tint(&self) -> [f32; 3]
+	{
+		match self
+		{
+			Season::Spring => [1., 1., 1.],
+			Season::Summer => [1.05, 1., 0.92],
+			Season::Autumn => [1., 0.9, 0.8],
+			Season::Winter => [0.85, 0.9, 1.],
+		}
+	}
+
+	// The water color the open sea drifts toward, and how far.
+	pub fn water(&self) -> ([f32; 3], f32)
+	{
+		match self
+		{
+			Season::Spring => ([0.1, 0.1, 0.8], 0.),
+			Season::Summer => ([0.1, 0.3, 0.75], 0.25),
+			Season::Autumn => ([0.15, 0.2, 0.5], 0.25),
+			Season::Winter => ([0.2, 0.25, 0.35], 0.4),
+		}
+	}
+
+	// Multiplies the chance of squalls.
+	pub fn storms(&self) -> f64
+	{
+		match self
+		{
+			Season::Spring => 1.,
+			Season::Summer => 0.5,
+			Season::Autumn => 1.5,
+			Season::Winter => 2.,
+		}
+	}
+
+	// Harvests flood the market, winter makes everything dearer to ship.
+	pub fn price_factor(&self, commodity: Commodity) -> f32
+	{
+		match (self, commodity)
+		{
+			(Season::Summer, Commodity::Cotton) => 0.8,
+			(Season::Autumn, Commodity::Tobacco) => 0.7,
+			(Season::Winter, Commodity::Goods) => 1.2,
+			_ => 1.,
+		}
+	}
+
+	// What gets said when it starts.
+	pub fn herald(&self) -> &'static str
+	{
+		match self
+		{
+			Season::Spring => "Spring has come, the weather settles.",
+			Season::Summer => "Summer has come, and with it the cotton harvest.",
+			Season::Autumn => "Autumn has come, the tobacco harvest floods the markets.",
+			Season::Winter => "Winter has come, expect foul weather.",
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Date
+{
+	pub year: i32,
+	pub season: Season,
+	// Counting from 1.
+	pub day: i32,
+	pub hour: f64,
+}
+
+impl Date
+{
+	// The date this long since the voyage set out.
+	pub fn at(time: f64) -> Self
+	{
+		let days = (time.max(0.) / DAY_LENGTH) as i32;
+		let seasons = days / SEASON_DAYS;
+		Self {
+			year: START_YEAR + seasons / 4,
+			season: Season::ALL[(seasons % 4) as usize],
+			day: days % SEASON_DAYS + 1,
+			hour: (time.max(0.) % DAY_LENGTH) / DAY_LENGTH * 24.,
+		}
+	}
+
+	// 0 through the night, up to 1 at noon.
+	pub fn daylight(&self) -> f32
+	{
+		let f = ((self.hour - 6.) / 12. * std::f64::consts::PI)
+			.sin()
+			.max(0.);
+		f.sqrt() as f32
+	}
+}
+
+impl fmt::Display for Date
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(
+			f,
+			"{} {} {}, {:02}:{:02}",
+			self.season.name(),
+			self.day,
+			self.year,
+			self.hour as i32,
+			(self.hour.fract() * 60.) as i32
+		)
+	}
+}
+
+#[test]
+fn test_date()
+{
+	let date = Date::at(0.);
+	assert_eq!(
+		(date.year, date.season, date.day),
+		(START_YEAR, Season::Spring, 1)
+	);
+	assert_eq!(date.to_string(), "Spring 1 1715, 00:00");
+
+	let date = Date::at(DAY_LENGTH * (SEASON_DAYS * 4 + SEASON_DAYS + 2) as f64 + DAY_LENGTH / 2.);
+	assert_eq!(
+		(date.year, date.season, date.day),
+		(START_YEAR + 1, Season::Summer, 3)
+	);
+	assert_eq!(date.hour, 12.);
+	assert_eq!(date.daylight(), 1.);
+	assert_eq!(Date::at(0.).daylight(), 0.);
+}
@@ -1,4 +1,4 @@
-use crate::{game_state, sprite};
+use crate::{game_state, sprite, utils};
 use allegro::*;
 use na::{Point2, Point3, Vector3};
 use nalgebra as na;
@@ -22,6 +22,54 @@ pub fn enemy_experience(level: i32) -> f32
 	2. * (level as f32).powf(2.)
 }
 
+pub fn experience_level(experience: f32) -> i32
+{
+	let mut level = 1;
+	while level_experience(level + 1) <= experience
+	{
+		level += 1;
+	}
+	level
+}
+
+// What the crew gets better at by doing it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Specialty
+{
+	Gunnery,
+	Sailing,
+	Repair,
+}
+
+pub const SPECIALTIES: [Specialty; 3] = [Specialty::Gunnery, Specialty::Sailing, Specialty::Repair];
+
+// Experience per shot fired, per unit sailed and per repair done.
+pub const GUNNERY_TRAINING: f32 = 0.03;
+pub const SAILING_TRAINING: f32 = 0.002;
+pub const REPAIR_TRAINING: f32 = 0.02;
+
+impl Specialty
+{
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Specialty::Gunnery => "Gunnery",
+			Specialty::Sailing => "Sailing",
+			Specialty::Repair => "Repair",
+		}
+	}
+}
+
+// Components holding positions in the world frame, which moves every time the map recenters on
+// the player's cell. Map::recenter shifts every registered component that implements this, see
+// registry::COMPONENTS.
+pub trait WorldSpace
+{
+	fn shift(&mut self, offt: Vector3<f32>);
+	fn world_positions(&self) -> Vec<Point3<f32>>;
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Position
 {
@@ -29,11 +77,110 @@ pub struct Position
 	pub dir: f32,
 }
 
+pub const MAX_INTERPOLATION_DIST: f32 = 10.;
+
+impl Position
+{
+	// For drawing in between logic ticks.
+	pub fn interpolate(&self, prev: Option<&PrevPosition>, alpha: f32) -> Position
+	{
+		match prev
+		{
+			// Teleported, don't smear it across the map.
+			Some(prev) if (self.pos - prev.pos).magnitude() > MAX_INTERPOLATION_DIST => *self,
+			Some(prev) => Position {
+				pos: prev.pos + alpha * (self.pos - prev.pos),
+				dir: utils::lerp_angle(prev.dir, self.dir, alpha),
+			},
+			None => *self,
+		}
+	}
+
+	// From the entity's own frame to the world, tipped over by the tilt if it has one.
+	pub fn to_isometry(&self, tilt: Option<&Tilt>) -> na::Isometry3<f32>
+	{
+		let mut iso = na::Isometry3::new(self.pos.coords, self.dir * Vector3::y());
+		if let Some(tilt) = tilt
+		{
+			iso.rotation *= tilt.rotation();
+		}
+		iso
+	}
+}
+
+impl WorldSpace for Position
+{
+	fn shift(&mut self, offt: Vector3<f32>)
+	{
+		self.pos -= offt;
+	}
+
+	fn world_positions(&self) -> Vec<Point3<f32>>
+	{
+		vec![self.pos]
+	}
+}
+
+// Where the entity was on the previous logic tick.
 #[derive(Copy, Clone, Debug)]
+pub struct PrevPosition
+{
+	pub pos: Point3<f32>,
+	pub dir: f32,
+}
+
+impl WorldSpace for PrevPosition
+{
+	fn shift(&mut self, offt: Vector3<f32>)
+	{
+		self.pos -= offt;
+	}
+
+	fn world_positions(&self) -> Vec<Point3<f32>>
+	{
+		vec![self.pos]
+	}
+}
+
+// Freshly spawned ship, not yet announced to the triggers.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Spawned;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Tilt
 {
 	pub tilt: f32,
 	pub target_tilt: f32,
+	// Roll, positive with the port side down.
+	#[serde(default)]
+	pub heel: f32,
+}
+
+impl Tilt
+{
+	// Anything else that tips the hull (e.g. pitching in a swell) belongs in here too, so the
+	// meshes and everything riding on them agree.
+	pub fn rotation(&self) -> na::UnitQuaternion<f32>
+	{
+		na::UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.tilt)
+			* na::UnitQuaternion::from_axis_angle(&Vector3::z_axis(), -self.heel)
+	}
+}
+
+// Rides along on another entity, e.g. a muzzle flash on the ship that fired it.
+#[derive(Copy, Clone, Debug)]
+pub struct Attached
+{
+	pub parent: hecs::Entity,
+	// In the parent's frame.
+	pub offset: Point3<f32>,
+}
+
+// Draws the mesh bigger or smaller than it was modeled.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Scale
+{
+	pub scale: f32,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -54,6 +201,9 @@ pub struct Waypoint
 pub struct Target
 {
 	pub waypoints: Vec<Waypoint>,
+	// Reached waypoints get sent to the back of the queue.
+	pub patrol: bool,
+	pub attack_en_route: bool,
 }
 
 impl Target
@@ -68,6 +218,24 @@ impl Target
 			}
 		}
 		self.waypoints.clear();
+		self.patrol = false;
+		self.attack_en_route = false;
+	}
+}
+
+impl WorldSpace for Target
+{
+	fn shift(&mut self, offt: Vector3<f32>)
+	{
+		for waypoint in &mut self.waypoints
+		{
+			waypoint.pos -= offt;
+		}
+	}
+
+	fn world_positions(&self) -> Vec<Point3<f32>>
+	{
+		self.waypoints.iter().map(|w| w.pos).collect()
 	}
 }
 
@@ -77,6 +245,55 @@ pub struct Mesh
 	pub mesh: String,
 }
 
+// Distances from the camera between which faded meshes go from opaque to gone.
+pub const RENDER_FADE_NEAR: f32 = 15.;
+pub const RENDER_FADE_FAR: f32 = 50.;
+
+// How a Mesh gets drawn, for things that aren't really part of the world.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RenderFlags
+{
+	// Doesn't write depth, so it never hides what's drawn after it.
+	pub no_shadow: bool,
+	// Ignores the lights and is drawn at full brightness.
+	pub no_light: bool,
+	// Drawn after everything else, ignoring depth.
+	pub always_on_top: bool,
+	// Fades out as the camera moves away.
+	pub fade_with_distance: bool,
+}
+
+impl RenderFlags
+{
+	pub const MARKER: RenderFlags = RenderFlags {
+		no_shadow: true,
+		no_light: true,
+		always_on_top: true,
+		fade_with_distance: true,
+	};
+
+	pub fn fade(&self, camera_dist: f32) -> f32
+	{
+		if self.fade_with_distance
+		{
+			1. - ((camera_dist - RENDER_FADE_NEAR) / (RENDER_FADE_FAR - RENDER_FADE_NEAR))
+				.max(0.)
+				.min(1.)
+		}
+		else
+		{
+			1.
+		}
+	}
+}
+
+// Overrides the team's flag.
+#[derive(Clone, Debug)]
+pub struct Flag
+{
+	pub flag: String,
+}
+
 #[derive(Clone, Debug)]
 pub enum AIState
 {
@@ -87,6 +304,15 @@ pub enum AIState
 	{
 		time_to_unpause: f64,
 	},
+	Escorting
+	{
+		leader: hecs::Entity,
+		slot: usize,
+	},
+	// Like Pursuing, but never gives up the chase.
+	Hunting(hecs::Entity),
+	// Civilians running from a fight.
+	Fleeing(hecs::Entity),
 }
 
 #[derive(Clone, Debug)]
@@ -96,17 +322,68 @@ pub struct AI
 	pub name: String,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct WispSpawner
 {
 	pub time_to_spawn: f64,
 }
 
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct EscortSpawner
+{
+	pub time_to_spawn: f64,
+	pub max_escorts: usize,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Wisp
+{
+	pub speed: f32,
+	pub time_to_hum: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CreatureKind
+{
+	// Wheel about in flocks near the lighthouses.
+	Gull,
+	// Harmless, dive and surface as they swim along.
+	Whale,
+	// Lurk in the deep northern waters, and bite small ships from below.
+	Leviathan,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Creature
+{
+	pub kind: CreatureKind,
+	// Where it keeps returning to.
+	pub home: Point3<f32>,
+	pub surfaced: bool,
+	// When it next dives or surfaces.
+	pub time_to_change: f64,
+	pub time_to_bite: f64,
+}
+
+impl WorldSpace for Creature
+{
+	fn shift(&mut self, offt: Vector3<f32>)
+	{
+		self.home -= offt;
+	}
+
+	fn world_positions(&self) -> Vec<Point3<f32>>
+	{
+		vec![self.home]
+	}
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum CollideKind
 {
 	Big,
 	Small,
+	Hazard,
 }
 
 impl CollideKind
@@ -119,6 +396,9 @@ impl CollideKind
 			(CollideKind::Big, CollideKind::Small) => true,
 			(CollideKind::Small, CollideKind::Big) => true,
 			(CollideKind::Small, CollideKind::Small) => false,
+			(CollideKind::Hazard, CollideKind::Hazard) => false,
+			(CollideKind::Hazard, _) => true,
+			(_, CollideKind::Hazard) => true,
 		}
 	}
 }
@@ -147,9 +427,10 @@ pub enum OfficerPrefix
 	Speed(usize, f32),
 	Accurate(usize, f32),
 	Critical(usize, f32),
+	Gunnery(usize, f32),
 }
 
-pub const OFFICER_PREFIX_WEIGHTS: [i32; 4] = [4, 1, 10, 4];
+pub const OFFICER_PREFIX_WEIGHTS: [i32; 5] = [4, 1, 10, 4, 3];
 
 impl OfficerPrefix
 {
@@ -185,6 +466,13 @@ impl OfficerPrefix
 				2 => "Sectanted ",
 				_ => unreachable!(),
 			},
+			OfficerPrefix::Gunnery(tier, _) => match tier
+			{
+				0 => "Drilled ",
+				1 => "Broadsiding ",
+				2 => "Raking ",
+				_ => unreachable!(),
+			},
 		}
 	}
 
@@ -228,6 +516,15 @@ impl OfficerPrefix
 
 				stats.critical_chance += effect;
 			}
+			OfficerPrefix::Gunnery(tier, f) =>
+			{
+				let breakpoints = [0.2, 0.4, 0.7, 1.0];
+				let min = breakpoints[tier];
+				let max = breakpoints[tier + 1];
+				let effect = min + f * (max - min);
+
+				stats.out_of_arc += effect;
+			}
 		}
 	}
 }
@@ -241,6 +538,7 @@ pub enum OfficerSuffix
 	SailRepair(usize, f32),
 	ItemProtect(usize, f32),
 	Medic(usize, f32),
+	Spyglass(usize, f32),
 }
 
 impl OfficerSuffix
@@ -291,6 +589,13 @@ impl OfficerSuffix
 				2 => " of Curing",
 				_ => unreachable!(),
 			},
+			OfficerSuffix::Spyglass(tier, _) => match tier
+			{
+				0 => ", Lookout",
+				1 => ", Spotter",
+				2 => ", Spymaster",
+				_ => unreachable!(),
+			},
 		}
 	}
 
@@ -352,11 +657,20 @@ impl OfficerSuffix
 
 				stats.medic += effect;
 			}
+			OfficerSuffix::Spyglass(tier, f) =>
+			{
+				let breakpoints = [0.25, 0.5, 1., 1.5];
+				let min = breakpoints[tier];
+				let max = breakpoints[tier + 1];
+				let effect = min + f * (max - min);
+
+				stats.spyglass += effect;
+			}
 		}
 	}
 }
 
-pub const OFFICER_SUFFIX_WEIGHTS: [i32; 6] = [10, 10, 10, 10, 1, 5];
+pub const OFFICER_SUFFIX_WEIGHTS: [i32; 7] = [10, 10, 10, 10, 1, 5, 5];
 
 #[derive(Clone, Debug)]
 pub enum WeaponPrefix
@@ -681,6 +995,80 @@ pub struct WeaponStats
 	pub infirmary_weight: f32,
 }
 
+pub const RANGE_FALLOFF_DAMAGE: f32 = 0.5;
+pub const RANGE_FALLOFF_ARMOR: f32 = 0.25;
+
+impl WeaponStats
+{
+	// Damage per shot, counting the criticals.
+	pub fn expected_damage(&self) -> f32
+	{
+		self.damage * (1. + self.critical_chance * (self.critical_multiplier - 1.))
+	}
+
+	// Shots hit full force out to here. Faster shots carry further.
+	pub fn effective_range(&self) -> f32
+	{
+		0.3 * self.speed
+	}
+
+	// Shots are spent past here, see `at_range`.
+	pub fn max_range(&self) -> f32
+	{
+		2. * self.effective_range()
+	}
+
+	// Past the effective range the damage falls off until twice the range, and the shot loses its
+	// ability to punch through armor even faster.
+	pub fn at_range(&self, dist: f32) -> WeaponStats
+	{
+		let range = self.effective_range();
+		let f = utils::clamp((dist - range) / range, 0., 1.);
+		let mut stats = *self;
+		stats.damage *= 1. - f * (1. - RANGE_FALLOFF_DAMAGE);
+		stats.armor_damage *= 1. - f * (1. - RANGE_FALLOFF_ARMOR);
+		stats
+	}
+}
+
+// How big a gun a slot can carry, bigger mounts take smaller guns too.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MountSize
+{
+	#[default]
+	Small,
+	Medium,
+	Large,
+}
+
+impl MountSize
+{
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			MountSize::Small => "Small",
+			MountSize::Medium => "Medium",
+			MountSize::Large => "Large",
+		}
+	}
+
+	pub fn fits(&self, weapon_size: MountSize) -> bool
+	{
+		weapon_size <= *self
+	}
+
+	fn cannon_name(&self) -> &'static str
+	{
+		match self
+		{
+			MountSize::Small => "Cannon",
+			MountSize::Medium => "Long Gun",
+			MountSize::Large => "Heavy Cannon",
+		}
+	}
+}
+
 #[derive(Clone, Debug)]
 pub struct Weapon
 {
@@ -691,13 +1079,14 @@ pub struct Weapon
 	pub suffixes: Vec<WeaponSuffix>,
 	pub name: String,
 	pub level: i32,
+	pub size: MountSize,
 }
 
 impl Weapon
 {
 	pub fn stats(&self) -> WeaponStats
 	{
-		let mut stats = default_weapon_stats(self.level);
+		let mut stats = default_weapon_stats(self.level, self.size);
 		for prefix in &self.prefixes
 		{
 			prefix.apply(&mut stats);
@@ -710,14 +1099,21 @@ impl Weapon
 	}
 }
 
-fn default_weapon_stats(level: i32) -> WeaponStats
+fn default_weapon_stats(level: i32, size: MountSize) -> WeaponStats
 {
+	// Heavier guns hit harder and reach further, but take longer to load.
+	let (damage, speed, fire_interval) = match size
+	{
+		MountSize::Small => (1., 1., 1.),
+		MountSize::Medium => (1.5, 1.2, 1.3),
+		MountSize::Large => (2.2, 1.45, 1.6),
+	};
 	WeaponStats {
-		fire_interval: 1.,
-		speed: 50.,
+		fire_interval: fire_interval,
+		speed: 50. * speed,
 		arc: PI / 2.,
 		spread: PI / 12.,
-		damage: 10. * level_effectiveness(level).sqrt(),
+		damage: damage * 10. * level_effectiveness(level).sqrt(),
 		critical_chance: 0.05,
 		critical_multiplier: 1.,
 		armor_damage: 1.,
@@ -731,6 +1127,41 @@ fn default_weapon_stats(level: i32) -> WeaponStats
 	}
 }
 
+// Wisps go after the rigging and the crew, and don't bother with the armor.
+pub fn wisp_weapon_stats(level: i32) -> WeaponStats
+{
+	WeaponStats {
+		damage: 5. * level_effectiveness(level).sqrt(),
+		critical_chance: 0.,
+		armor_damage: 0.,
+		sail_damage: 2.,
+		crew_damage: 2.,
+		item_chance: 0.,
+		hull_weight: 1.,
+		sail_weight: 1.,
+		crew_weight: 5.,
+		infirmary_weight: 0.,
+		..default_weapon_stats(level, MountSize::Small)
+	}
+}
+
+pub fn leviathan_weapon_stats(level: i32) -> WeaponStats
+{
+	WeaponStats {
+		damage: 15. * level_effectiveness(level),
+		critical_chance: 0.1,
+		armor_damage: 5.,
+		sail_damage: 0.,
+		crew_damage: 1.,
+		item_chance: 0.1,
+		hull_weight: 5.,
+		sail_weight: 0.,
+		crew_weight: 1.,
+		infirmary_weight: 0.,
+		..default_weapon_stats(level, MountSize::Large)
+	}
+}
+
 #[derive(Clone, Debug)]
 pub struct Officer
 {
@@ -740,6 +1171,122 @@ pub struct Officer
 	pub suffixes: Vec<OfficerSuffix>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RiggingKind
+{
+	SailPlan,
+	MastReinforcement,
+	CopperHull,
+}
+
+impl RiggingKind
+{
+	pub const ALL: [RiggingKind; 3] = [
+		RiggingKind::SailPlan,
+		RiggingKind::MastReinforcement,
+		RiggingKind::CopperHull,
+	];
+
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			RiggingKind::SailPlan => "Sail Plan",
+			RiggingKind::MastReinforcement => "Mast Reinforcement",
+			RiggingKind::CopperHull => "Copper Hull",
+		}
+	}
+}
+
+// Fittings for the dedicated rigging slots, which work passively like officers do.
+#[derive(Clone, Debug)]
+pub struct Rigging
+{
+	pub kind: RiggingKind,
+	pub level: i32,
+}
+
+impl Rigging
+{
+	pub fn apply(&self, stats: &mut DerivedShipStats)
+	{
+		let f = level_effectiveness(self.level).sqrt();
+		match self.kind
+		{
+			RiggingKind::SailPlan => stats.speed += 0.08 * f,
+			RiggingKind::MastReinforcement =>
+			{
+				stats.sail_repair += 0.25 * f;
+				stats.item_protect += 0.1 * f;
+			}
+			RiggingKind::CopperHull =>
+			{
+				stats.speed += 0.04 * f;
+				stats.hull_repair += 0.25 * f;
+			}
+		}
+	}
+}
+
+// Repair stock, used up by anything past the slow repairs a crew can manage without it.
+pub const MATERIAL_AMOUNT: f32 = 50.;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MaterialKind
+{
+	IronPlates,
+	Timber,
+	Canvas,
+}
+
+impl MaterialKind
+{
+	pub const ALL: [MaterialKind; 3] = [
+		MaterialKind::IronPlates,
+		MaterialKind::Timber,
+		MaterialKind::Canvas,
+	];
+
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			MaterialKind::IronPlates => "Iron Plates",
+			MaterialKind::Timber => "Timber",
+			MaterialKind::Canvas => "Canvas",
+		}
+	}
+
+	pub fn color(&self) -> Color
+	{
+		match self
+		{
+			MaterialKind::IronPlates => Color::from_rgb_f(0.6, 0.6, 0.7),
+			MaterialKind::Timber => Color::from_rgb_f(0.8, 0.55, 0.3),
+			MaterialKind::Canvas => Color::from_rgb_f(1., 0.95, 0.8),
+		}
+	}
+
+	// What each repair part needs, in the repair logic's order. The infirmary makes do.
+	pub fn for_part(part: usize) -> Option<MaterialKind>
+	{
+		match part
+		{
+			0..=3 => Some(MaterialKind::IronPlates),
+			4 => Some(MaterialKind::Timber),
+			6 => Some(MaterialKind::Canvas),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Material
+{
+	pub kind: MaterialKind,
+	pub amount: f32,
+}
+
 fn mod_string(name: &str, base: f32, new: f32) -> Option<String>
 {
 	let change = (100. * (new - base) / base) as i32;
@@ -761,6 +1308,8 @@ pub enum ItemKind
 	Cotton(i32),
 	Tobacco(i32),
 	Officer(Officer),
+	Material(Material),
+	Rigging(Rigging),
 }
 
 impl ItemKind
@@ -774,6 +1323,28 @@ impl ItemKind
 			ItemKind::Cotton(_) => "Cotton",
 			ItemKind::Tobacco(_) => "Tobacco",
 			ItemKind::Officer(officer) => &officer.name,
+			ItemKind::Material(material) => material.kind.name(),
+			ItemKind::Rigging(rigging) => rigging.kind.name(),
+		}
+	}
+
+	pub fn is_cargo(&self) -> bool
+	{
+		matches!(
+			self,
+			ItemKind::Goods(_) | ItemKind::Cotton(_) | ItemKind::Tobacco(_)
+		)
+	}
+
+	// Same kind of cargo of the same level, so interchangeable when trading.
+	pub fn same_cargo(&self, other: &ItemKind) -> bool
+	{
+		match (self, other)
+		{
+			(ItemKind::Goods(l1), ItemKind::Goods(l2))
+			| (ItemKind::Cotton(l1), ItemKind::Cotton(l2))
+			| (ItemKind::Tobacco(l1), ItemKind::Tobacco(l2)) => l1 == l2,
+			_ => false,
 		}
 	}
 
@@ -791,6 +1362,8 @@ impl ItemKind
 			ItemKind::Cotton(_) => Color::from_rgb_f(0.2, 1., 0.2),
 			ItemKind::Tobacco(_) => Color::from_rgb_f(0.2, 1., 0.2),
 			ItemKind::Officer(_) => Color::from_rgb_f(1., 0.2, 0.2),
+			ItemKind::Rigging(_) => Color::from_rgb_f(0.9, 0.7, 0.3),
+			ItemKind::Material(_) => Color::from_rgb_f(0.2, 1., 0.2),
 		}
 	}
 
@@ -806,18 +1379,21 @@ impl ItemKind
 				let arc = (stats.arc / PI * 180.) as i32;
 				let spread = (stats.spread / PI * 180.) as i32;
 				let damage = stats.damage as i32;
+				let range = stats.effective_range() as i32;
 				let level = weapon.level;
 				let mut desc = vec![
 					"".into(),
 					format!("Level: {level}"),
+					format!("Mount: {}", weapon.size.name()),
 					format!("Damage: {damage}"),
 					format!("Reload Time: {fire_interval:.1} sec"),
 					format!("Arc: {arc}°"),
 					format!("Spread: {spread}°"),
+					format!("Effective Range: {range}"),
 					"".into(),
 				];
 
-				let base_stats = default_weapon_stats(level);
+				let base_stats = default_weapon_stats(level, weapon.size);
 
 				if let Some(mod_string) = mod_string(
 					"Fire Interval: ",
@@ -923,6 +1499,20 @@ impl ItemKind
 				let desc = ["".into(), format!("Level: {level}")];
 				desc.join("\n")
 			}
+			ItemKind::Material(material) =>
+			{
+				let desc = ["".into(), format!("Repairs left: {:.0}", material.amount)];
+				desc.join("\n")
+			}
+			ItemKind::Rigging(rigging) =>
+			{
+				let mut desc = vec!["".into(), format!("Level: {}", rigging.level), "".into()];
+				let mut stats = DerivedShipStats::new();
+				rigging.apply(&mut stats);
+				desc.extend(stats.describe());
+				desc.push("Fits a rigging slot".into());
+				desc.join("\n")
+			}
 			ItemKind::Officer(officer) =>
 			{
 				let level = officer.level;
@@ -937,67 +1527,7 @@ impl ItemKind
 					suffix.apply(&mut stats);
 				}
 
-				if stats.reload_speed != 0.0
-				{
-					desc.push(format!(
-						"Fire rate: {:+}%",
-						(stats.reload_speed * 100.) as i32
-					));
-				}
-				if stats.speed != 0.0
-				{
-					desc.push(format!("Speed: {:+}%", (stats.speed * 100.) as i32));
-				}
-				if stats.accuracy != 0.0
-				{
-					desc.push(format!("Accuracy: {:+}%", (stats.accuracy * 100.) as i32));
-				}
-				if stats.critical_chance != 0.0
-				{
-					desc.push(format!(
-						"Critical chance: {:+}%",
-						(stats.critical_chance * 100.) as i32
-					));
-				}
-				if stats.armor_repair != 0.0
-				{
-					desc.push(format!(
-						"Armour repair: {:+}%",
-						(stats.armor_repair * 100.) as i32
-					));
-				}
-				if stats.hull_repair != 0.0
-				{
-					desc.push(format!(
-						"Hull repair: {:+}%",
-						(stats.hull_repair * 100.) as i32
-					));
-				}
-				if stats.infirmary_repair != 0.0
-				{
-					desc.push(format!(
-						"Infirmary repair: {:+}%",
-						(stats.infirmary_repair * 100.) as i32
-					));
-				}
-				if stats.sail_repair != 0.0
-				{
-					desc.push(format!(
-						"Sail repair: {:+}%",
-						(stats.sail_repair * 100.) as i32
-					));
-				}
-				if stats.item_protect != 0.0
-				{
-					desc.push(format!(
-						"Item protection: {:+}%",
-						(stats.item_protect * 100.) as i32
-					));
-				}
-				if stats.medic != 0.0
-				{
-					desc.push(format!("Healing: {:+}%", (stats.medic * 100.) as i32));
-				}
+				desc.extend(stats.describe());
 
 				desc.join("\n")
 			}
@@ -1050,6 +1580,15 @@ impl ItemKind
 					state,
 				);
 			}
+			ItemKind::Material(material) =>
+			{
+				state.get_sprite("data/goods.cfg").unwrap().draw(
+					pos,
+					0,
+					material.kind.color(),
+					state,
+				);
+			}
 			ItemKind::Officer(_) =>
 			{
 				state.get_sprite("data/officer.cfg").unwrap().draw(
@@ -1059,12 +1598,32 @@ impl ItemKind
 					state,
 				);
 			}
+			ItemKind::Rigging(rigging) =>
+			{
+				let sprite = match rigging.kind
+				{
+					RiggingKind::SailPlan => "data/repair_sails.cfg",
+					RiggingKind::MastReinforcement => "data/repair_armor.cfg",
+					RiggingKind::CopperHull => "data/repair_hull.cfg",
+				};
+				state.get_sprite(sprite).unwrap().draw(
+					pos,
+					0,
+					Color::from_rgb_f(0.9, 0.7, 0.3),
+					state,
+				);
+			}
 		}
 	}
 }
 
-pub fn generate_weapon(level: i32, rng: &mut impl Rng) -> Item
+pub fn generate_weapon(level: i32, max_size: MountSize, rng: &mut impl Rng) -> Item
 {
+	let size = *[MountSize::Small, MountSize::Medium, MountSize::Large]
+		.choose_weighted(rng, |size| [6., 3., 1.][*size as usize])
+		.unwrap();
+	let size = size.min(max_size);
+
 	let num_prefixes = *[0, 1, 2, 3]
 		.choose_weighted(rng, |idx| [25., 10., 2., 1.][*idx])
 		.unwrap();
@@ -1143,11 +1702,11 @@ pub fn generate_weapon(level: i32, rng: &mut impl Rng) -> Item
 
 	let name = match rarity
 	{
-		Rarity::Normal => "Cannon".into(),
+		Rarity::Normal => size.cannon_name().into(),
 		Rarity::Magic => format!(
 			"{}{}{}",
 			prefixes.first().map(|a| a.name()).unwrap_or(""),
-			"Cannon",
+			size.cannon_name(),
 			suffixes.first().map(|a| a.name()).unwrap_or("")
 		),
 		Rarity::Rare => generate_weapon_name(rng),
@@ -1162,8 +1721,11 @@ pub fn generate_weapon(level: i32, rng: &mut impl Rng) -> Item
 			readiness: 0.,
 			time_to_fire: None,
 			level: level,
+			size: size,
 		}),
 		price: 10,
+		locked: false,
+		junk: false,
 	}
 }
 
@@ -1210,6 +1772,7 @@ pub fn generate_officer(level: i32, rng: &mut impl Rng) -> Item
 			1 => OfficerPrefix::Speed(tier, f),
 			2 => OfficerPrefix::Accurate(tier, f),
 			3 => OfficerPrefix::Critical(tier, f),
+			4 => OfficerPrefix::Gunnery(tier, f),
 			_ => unreachable!(),
 		};
 		prefixes.push(prefix);
@@ -1230,6 +1793,7 @@ pub fn generate_officer(level: i32, rng: &mut impl Rng) -> Item
 			3 => OfficerSuffix::SailRepair(tier, f),
 			4 => OfficerSuffix::ItemProtect(tier, f),
 			5 => OfficerSuffix::Medic(tier, f),
+			6 => OfficerSuffix::Spyglass(tier, f),
 			_ => unreachable!(),
 		};
 		suffixes.push(suffix);
@@ -1243,37 +1807,73 @@ pub fn generate_officer(level: i32, rng: &mut impl Rng) -> Item
 	);
 
 	Item {
-		kind: ItemKind::Officer(Officer {
-			name: name,
-			prefixes: prefixes,
-			suffixes: suffixes,
+		kind: ItemKind::Officer(Officer {
+			name: name,
+			prefixes: prefixes,
+			suffixes: suffixes,
+			level: level,
+		}),
+		price: 10,
+		locked: false,
+		junk: false,
+	}
+}
+
+pub fn generate_material(kind: MaterialKind) -> Item
+{
+	Item {
+		kind: ItemKind::Material(Material {
+			kind: kind,
+			amount: MATERIAL_AMOUNT,
+		}),
+		price: 10,
+		locked: false,
+		junk: false,
+	}
+}
+
+pub fn generate_rigging(level: i32, rng: &mut impl Rng) -> Item
+{
+	Item {
+		kind: ItemKind::Rigging(Rigging {
+			kind: *RiggingKind::ALL.choose(rng).unwrap(),
 			level: level,
 		}),
 		price: 10,
+		locked: false,
+		junk: false,
 	}
 }
 
 pub fn generate_item(level: i32, rng: &mut impl Rng) -> Item
 {
-	let idx = rand_distr::WeightedIndex::new([1., 1., 1., 1., 1.])
+	let idx = rand_distr::WeightedIndex::new([1., 1., 1., 1., 1., 1., 0.5])
 		.unwrap()
 		.sample(rng);
 	match idx
 	{
-		0 => generate_weapon(level, rng),
+		0 => generate_weapon(level, MountSize::Large, rng),
 		1 => Item {
 			kind: ItemKind::Goods(level),
 			price: 10,
+			locked: false,
+			junk: false,
 		},
 		2 => Item {
 			kind: ItemKind::Cotton(level),
 			price: 10,
+			locked: false,
+			junk: false,
 		},
 		3 => Item {
 			kind: ItemKind::Tobacco(level),
 			price: 10,
+			locked: false,
+			junk: false,
 		},
 		4 => generate_officer(level, rng),
+		5 => generate_material(*MaterialKind::ALL.choose(rng).unwrap()),
+		6 => generate_rigging(level, rng),
 		_ => unreachable!(),
 	}
 }
@@ -1283,6 +1883,10 @@ pub struct Item
 {
 	pub kind: ItemKind,
 	pub price: i32,
+	// Kept out of trades, the loot filter and harm's way.
+	pub locked: bool,
+	// Sold at the next dock that trades.
+	pub junk: bool,
 }
 
 impl Item
@@ -1300,6 +1904,68 @@ impl Item
 	}
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LootAction
+{
+	Keep,
+	Discard,
+	Sell,
+}
+
+// Which inventory items to get rid of when docking.
+#[derive(Clone, Debug)]
+pub struct LootFilter
+{
+	pub action: LootAction,
+	pub weapons: bool,
+	pub officers: bool,
+	pub cargo: bool,
+	pub rigging: bool,
+	// Weapons that are rarer than this are always kept. 0 is Normal, 2 is Rare.
+	pub max_rarity: usize,
+	pub max_level: i32,
+}
+
+impl LootFilter
+{
+	pub fn new() -> Self
+	{
+		Self {
+			action: LootAction::Keep,
+			weapons: true,
+			officers: false,
+			cargo: false,
+			rigging: false,
+			max_rarity: 0,
+			max_level: 1,
+		}
+	}
+
+	pub fn matches(&self, item: &Item) -> bool
+	{
+		if item.locked
+		{
+			return false;
+		}
+		match &item.kind
+		{
+			ItemKind::Weapon(weapon) =>
+			{
+				self.weapons
+					&& weapon.rarity.clone() as usize <= self.max_rarity
+					&& weapon.level <= self.max_level
+			}
+			ItemKind::Officer(officer) => self.officers && officer.level <= self.max_level,
+			ItemKind::Goods(level) | ItemKind::Cotton(level) | ItemKind::Tobacco(level) =>
+			{
+				self.cargo && *level <= self.max_level
+			}
+			ItemKind::Material(_) => self.cargo,
+			ItemKind::Rigging(rigging) => self.rigging && rigging.level <= self.max_level,
+		}
+	}
+}
+
 #[derive(Clone, Debug)]
 pub struct ItemSlot
 {
@@ -1308,6 +1974,11 @@ pub struct ItemSlot
 	pub dir: Option<f32>,
 	pub is_inventory: bool,
 	pub weapons_allowed: bool,
+	pub size: MountSize,
+	// Only takes rigging, which does nothing anywhere else.
+	pub rigging: bool,
+	// Stood down, so the gun here neither fires nor takes up the gunners' time reloading.
+	pub disabled: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -1323,6 +1994,10 @@ pub struct DerivedShipStats
 	pub sail_repair: f32,
 	pub item_protect: f32,
 	pub medic: f32,
+	pub spyglass: f32,
+	// How far past a weapon's arc (as a fraction of it) targets can be and still get shot at, along
+	// the edge of the arc.
+	pub out_of_arc: f32,
 }
 
 impl DerivedShipStats
@@ -1340,7 +2015,91 @@ impl DerivedShipStats
 			sail_repair: 0.,
 			item_protect: 0.,
 			medic: 0.,
+			spyglass: 0.,
+			out_of_arc: 0.,
+		}
+	}
+
+	// A line for each stat that's been changed.
+	pub fn describe(&self) -> Vec<String>
+	{
+		let mut lines = vec![];
+		if self.reload_speed != 0.0
+		{
+			lines.push(format!(
+				"Fire rate: {:+}%",
+				(self.reload_speed * 100.) as i32
+			));
+		}
+		if self.speed != 0.0
+		{
+			lines.push(format!("Speed: {:+}%", (self.speed * 100.) as i32));
+		}
+		if self.accuracy != 0.0
+		{
+			lines.push(format!("Accuracy: {:+}%", (self.accuracy * 100.) as i32));
+		}
+		if self.critical_chance != 0.0
+		{
+			lines.push(format!(
+				"Critical chance: {:+}%",
+				(self.critical_chance * 100.) as i32
+			));
+		}
+		if self.out_of_arc != 0.0
+		{
+			lines.push(format!(
+				"Out of arc shots: {:+}%",
+				(self.out_of_arc * 100.) as i32
+			));
+		}
+		if self.armor_repair != 0.0
+		{
+			lines.push(format!(
+				"Armour repair: {:+}%",
+				(self.armor_repair * 100.) as i32
+			));
+		}
+		if self.hull_repair != 0.0
+		{
+			lines.push(format!(
+				"Hull repair: {:+}%",
+				(self.hull_repair * 100.) as i32
+			));
+		}
+		if self.infirmary_repair != 0.0
+		{
+			lines.push(format!(
+				"Infirmary repair: {:+}%",
+				(self.infirmary_repair * 100.) as i32
+			));
+		}
+		if self.sail_repair != 0.0
+		{
+			lines.push(format!(
+				"Sail repair: {:+}%",
+				(self.sail_repair * 100.) as i32
+			));
+		}
+		if self.item_protect != 0.0
+		{
+			lines.push(format!(
+				"Item protection: {:+}%",
+				(self.item_protect * 100.) as i32
+			));
+		}
+		if self.medic != 0.0
+		{
+			lines.push(format!("Healing: {:+}%", (self.medic * 100.) as i32));
+		}
+		if self.spyglass != 0.0
+		{
+			lines.push(format!(
+				"Inspection range: {:+}%",
+				(self.spyglass * 100.) as i32
+			));
 		}
+		lines
 	}
 }
 
@@ -1350,14 +2109,24 @@ pub struct Equipment
 	pub slots: Vec<ItemSlot>,
 	pub want_attack: bool,
 	pub target_pos: Point3<f32>,
-	pub allow_out_of_arc_shots: bool,
+}
+
+impl WorldSpace for Equipment
+{
+	fn shift(&mut self, offt: Vector3<f32>)
+	{
+		self.target_pos -= offt;
+	}
+
+	fn world_positions(&self) -> Vec<Point3<f32>>
+	{
+		vec![self.target_pos]
+	}
 }
 
 impl Equipment
 {
-	pub fn new(
-		inventory_size: usize, allow_out_of_arc_shots: bool, mut slots: Vec<ItemSlot>,
-	) -> Self
+	pub fn new(inventory_size: usize, mut slots: Vec<ItemSlot>) -> Self
 	{
 		for i in 0..inventory_size
 		{
@@ -1369,16 +2138,46 @@ impl Equipment
 				dir: None,
 				is_inventory: true,
 				weapons_allowed: true,
+				size: MountSize::Large,
+				rigging: false,
+				disabled: false,
 			})
 		}
 		Self {
 			slots: slots,
 			want_attack: false,
 			target_pos: Point3::origin(),
-			allow_out_of_arc_shots: allow_out_of_arc_shots,
 		}
 	}
 
+	// The longest range any mounted gun hits at full force.
+	pub fn weapon_range(&self) -> f32
+	{
+		self.slots
+			.iter()
+			.filter(|slot| !slot.is_inventory)
+			.filter_map(|slot| match slot.item.as_ref().map(|item| &item.kind)
+			{
+				Some(ItemKind::Weapon(weapon)) => Some(weapon.stats().effective_range()),
+				_ => None,
+			})
+			.fold(0., f32::max)
+	}
+
+	// The longest range any mounted gun still does damage at, 0 without guns.
+	pub fn weapon_max_range(&self) -> f32
+	{
+		self.slots
+			.iter()
+			.filter(|slot| !slot.is_inventory)
+			.filter_map(|slot| match slot.item.as_ref().map(|item| &item.kind)
+			{
+				Some(ItemKind::Weapon(weapon)) => Some(weapon.stats().max_range()),
+				_ => None,
+			})
+			.fold(0., f32::max)
+	}
+
 	pub fn derived_stats(&self) -> DerivedShipStats
 	{
 		let mut stats = DerivedShipStats::new();
@@ -1388,39 +2187,180 @@ impl Equipment
 			{
 				continue;
 			}
-			if let Some(ItemKind::Officer(officer)) = item_slot.item.as_ref().map(|a| &a.kind)
+			match item_slot.item.as_ref().map(|a| &a.kind)
 			{
-				for prefix in &officer.prefixes
+				Some(ItemKind::Officer(officer)) =>
 				{
-					prefix.apply(&mut stats);
+					for prefix in &officer.prefixes
+					{
+						prefix.apply(&mut stats);
+					}
+					for suffix in &officer.suffixes
+					{
+						suffix.apply(&mut stats);
+					}
 				}
-				for suffix in &officer.suffixes
+				Some(ItemKind::Rigging(rigging)) if item_slot.rigging => rigging.apply(&mut stats),
+				_ => (),
+			}
+		}
+		stats
+	}
+
+	pub fn material_left(&self, kind: MaterialKind) -> f32
+	{
+		self.slots
+			.iter()
+			.filter(|slot| slot.is_inventory)
+			.filter_map(|slot| match slot.item.as_ref().map(|item| &item.kind)
+			{
+				Some(ItemKind::Material(material)) if material.kind == kind =>
 				{
-					suffix.apply(&mut stats);
+					Some(material.amount)
+				}
+				_ => None,
+			})
+			.sum()
+	}
+
+	// Takes up to `amount` of the material from the hold, returning how much there was. Spent
+	// crates get thrown overboard.
+	pub fn use_material(&mut self, kind: MaterialKind, amount: f32) -> f32
+	{
+		let mut used = 0.;
+		for slot in &mut self.slots
+		{
+			if !slot.is_inventory || used >= amount
+			{
+				continue;
+			}
+			if let Some(ItemKind::Material(material)) =
+				slot.item.as_mut().map(|item| &mut item.kind)
+			{
+				if material.kind != kind
+				{
+					continue;
+				}
+				let take = material.amount.min(amount - used);
+				material.amount -= take;
+				used += take;
+				if material.amount <= 0.
+				{
+					slot.item = None;
 				}
 			}
 		}
-		stats
+		used
 	}
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TimeToDie
 {
 	pub time_to_die: f64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AffectedByGravity;
 
-#[derive(Clone, Debug)]
-pub struct CollidesWithWater;
+// What happens when something hits the water. With a sink_time of 0 it's gone right away,
+// otherwise it keeps going for a bit, slowed down by the water and fading out.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CollidesWithWater
+{
+	pub sink_time: f32,
+	pub drag: f32,
+	// Keeps its contact effects underwater, e.g. for depth charges.
+	pub live_underwater: bool,
+}
 
-#[derive(Copy, Clone, Debug)]
+// Things underwater look shallower than they are.
+pub const WATER_REFRACTION: f32 = 1.33;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Submerged
+{
+	pub time_left: f32,
+	pub sink_time: f32,
+	pub drag: f32,
+}
+
+impl Submerged
+{
+	// 1 as it goes in, 0 when it's gone.
+	pub fn fade(&self) -> f32
+	{
+		(self.time_left / self.sink_time).max(0.)
+	}
+}
+
+pub const SPLASH_DURATION: f32 = 0.8;
+
+// A ring spreading on the water where something went in.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Splash
+{
+	pub radius: f32,
+	pub start_time: f64,
+}
+
+// A husk that stays where it went down, and settles deeper each time its cell is visited.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Wreck
+{
+	pub visits: i32,
+}
+
+// A patch of foul weather drifting across the sea.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Squall
+{
+	pub radius: f32,
+	pub time_to_lightning: f64,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Lighthouse
+{
+	pub id: u64,
+	pub spin: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rival;
+
+// A ship crewed by the player's men, but not the one they're captaining.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Fleet;
+
+// The ship the other player captains in co-op.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Consort;
+
+// The desc file a ship was made from, so it can be made again elsewhere.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShipDesc
+{
+	pub desc: String,
+}
+
+// A fleet ship keeping its place in the player's formation, until ordered elsewhere.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Station
+{
+	// Broke off to fight something nearby.
+	pub engaged: bool,
+}
+
+#[derive(Clone, Debug)]
 pub struct Damage
 {
 	pub weapon_stats: WeaponStats,
 	pub team: Team,
+	// What dealt it, for the damage log.
+	pub source: String,
+	// Hurts the dealer's own team too.
+	pub friendly_fire: bool,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -1429,9 +2369,24 @@ pub struct DamageReport
 	pub damaged: bool,
 	pub item_destroy_chance: f32,
 	pub crit: bool,
+	pub hit: Option<HitKind>,
+	pub amount: f32,
+	// Armor segment struck, None if it went into the rigging.
+	pub facing: Option<usize>,
 }
 
-#[derive(Copy, Clone, Debug)]
+// What a shot ended up hitting, for the impact effects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HitKind
+{
+	Armor,
+	Hull,
+	Sails,
+	Crew,
+	Infirmary,
+}
+
+#[derive(Clone, Debug)]
 pub enum ContactEffect
 {
 	Die,
@@ -1447,6 +2402,82 @@ pub struct OnContactEffect
 	pub effects: Vec<ContactEffect>,
 }
 
+// Where and when a shot was fired from, for range falloff and sparing the firer's side.
+#[derive(Copy, Clone, Debug)]
+pub struct Projectile
+{
+	pub spawn_pos: Point3<f32>,
+	pub spawn_time: f64,
+	pub team: Team,
+}
+
+impl WorldSpace for Projectile
+{
+	fn shift(&mut self, offt: Vector3<f32>)
+	{
+		self.spawn_pos -= offt;
+	}
+
+	fn world_positions(&self) -> Vec<Point3<f32>>
+	{
+		vec![self.spawn_pos]
+	}
+}
+
+pub const POWDER_SURGE_DAMAGE: f32 = 1.5;
+pub const POWDER_SURGE_MISFIRE: f32 = 0.15;
+pub const HARD_TURN_DURATION: f64 = 5.;
+pub const HARD_TURN_FACTOR: f32 = 2.;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Ability
+{
+	HullPatch,
+	PowderSurge,
+	HardToPort,
+}
+
+pub const ABILITIES: [Ability; 3] = [
+	Ability::HullPatch,
+	Ability::PowderSurge,
+	Ability::HardToPort,
+];
+
+impl Ability
+{
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Ability::HullPatch => "Emergency Patch",
+			Ability::PowderSurge => "Powder Surge",
+			Ability::HardToPort => "Hard to Port",
+		}
+	}
+
+	pub fn short_name(&self) -> &'static str
+	{
+		match self
+		{
+			Ability::HullPatch => "Patch",
+			Ability::PowderSurge => "Surge",
+			Ability::HardToPort => "Turn",
+		}
+	}
+
+	// Officers that are good at the relevant job get it ready sooner.
+	pub fn cooldown(&self, derived_stats: &DerivedShipStats) -> f64
+	{
+		let (base, bonus) = match self
+		{
+			Ability::HullPatch => (60., derived_stats.hull_repair),
+			Ability::PowderSurge => (30., derived_stats.reload_speed),
+			Ability::HardToPort => (20., derived_stats.speed),
+		};
+		base / (1. + bonus as f64)
+	}
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ShipStats
 {
@@ -1475,7 +2506,7 @@ impl ShipStats
 	}
 }
 
-#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ShipState
 {
 	pub hull: f32,
@@ -1483,6 +2514,8 @@ pub struct ShipState
 	pub wounded: i32,
 	pub experience: f32,
 	pub level: i32,
+	// Experience in each Specialty.
+	pub proficiency: [f32; 3],
 	pub team: Team,
 	pub sails: f32,
 	pub infirmary: f32,
@@ -1490,6 +2523,12 @@ pub struct ShipState
 
 	pub repair_boost: Vec<usize>,
 	pub is_boss: bool,
+
+	// Time at which each ability is usable again.
+	pub ability_ready: [f64; 3],
+	pub ability_cooldowns: [f64; 3],
+	pub powder_surge: bool,
+	pub hard_turn_until: f64,
 }
 
 impl ShipState
@@ -1503,22 +2542,49 @@ impl ShipState
 			team: team,
 			experience: level_experience(level),
 			level: level,
+			proficiency: [level_experience(level); 3],
 			sails: stats.sails,
 			infirmary: stats.infirmary,
 			armor: stats.armor,
 			repair_boost: vec![],
 			is_boss: false,
+			ability_ready: [0.; 3],
+			ability_cooldowns: [1.; 3],
+			powder_surge: false,
+			hard_turn_until: 0.,
 		}
 	}
 
-	pub fn damage(&mut self, damage: &Damage, dir: Vector3<f32>, rng: &mut impl Rng)
-		-> DamageReport
+	pub fn ability_cooldown(&self, ability: Ability, time: f64) -> f64
+	{
+		(self.ability_ready[ability as usize] - time).max(0.)
+	}
+
+	// How much of the cooldown remains, from 1 down to 0.
+	pub fn ability_cooldown_frac(&self, ability: Ability, time: f64) -> f32
+	{
+		(self.ability_cooldown(ability, time) / self.ability_cooldowns[ability as usize]) as f32
+	}
+
+	pub fn start_cooldown(&mut self, ability: Ability, time: f64, derived_stats: &DerivedShipStats)
+	{
+		let cooldown = ability.cooldown(derived_stats);
+		self.ability_ready[ability as usize] = time + cooldown;
+		self.ability_cooldowns[ability as usize] = cooldown;
+	}
+
+	// Hull damage is scaled separately, so it can be changed for everyone at once.
+	pub fn damage(
+		&mut self, damage: &Damage, dir: Vector3<f32>, hull_damage: f32, rng: &mut impl Rng,
+	) -> DamageReport
 	{
 		let dir = dir.zx().normalize();
 		let mut crit = false;
 		let mut item_destroy_chance = 0.;
-		if damage.team.can_damage(&self.team)
+		let mut facing = None;
+		if damage.friendly_fire || damage.team.can_damage(&self.team)
 		{
+			let mut hit;
 			let weapon_stats = &damage.weapon_stats;
 			let mut base_damage = weapon_stats.damage;
 			if rng.gen_bool(weapon_stats.critical_chance as f64)
@@ -1533,19 +2599,29 @@ impl ShipState
 			)
 			{
 				self.sails = (self.sails - weapon_stats.sail_damage * base_damage / 2.).max(0.);
+				hit = HitKind::Sails;
 			}
 			else
 			{
 				let armor_segment =
 					((4. * (PI + dir.y.atan2(-dir.x)) / (2. * PI)) - 0.5).round() as usize;
+				facing = Some(armor_segment);
 				self.armor[armor_segment] =
 					(self.armor[armor_segment] - weapon_stats.armor_damage * base_damage).max(0.);
 				let bleed_through_frac =
 					1. - (0.1 * self.armor[armor_segment] / base_damage).min(1.);
 				item_destroy_chance = 0.01 * bleed_through_frac * weapon_stats.item_chance;
-				let bleed_through = base_damage * bleed_through_frac;
+				let bleed_through = base_damage * bleed_through_frac * hull_damage;
 
 				self.hull = (self.hull - bleed_through).max(0.);
+				hit = if bleed_through_frac < 0.25
+				{
+					HitKind::Armor
+				}
+				else
+				{
+					HitKind::Hull
+				};
 
 				let weights = [2., weapon_stats.crew_weight, weapon_stats.infirmary_weight];
 				match rand_distr::WeightedIndex::new(&weights)
@@ -1567,11 +2643,16 @@ impl ShipState
 								self.wounded += 1;
 							}
 						}
+						if crew_damage > 0
+						{
+							hit = HitKind::Crew;
+						}
 					}
 					2 =>
 					{
 						// Hit infirmary.
 						self.infirmary = (self.infirmary - bleed_through).max(0.);
+						hit = HitKind::Infirmary;
 					}
 					_ => unreachable!(),
 				}
@@ -1580,6 +2661,9 @@ impl ShipState
 				damaged: true,
 				item_destroy_chance: item_destroy_chance,
 				crit: crit,
+				hit: Some(hit),
+				amount: base_damage,
+				facing: facing,
 			}
 		}
 		else
@@ -1588,18 +2672,31 @@ impl ShipState
 				damaged: false,
 				item_destroy_chance: 0.,
 				crit: false,
+				hit: None,
+				amount: 0.,
+				facing: None,
 			}
 		}
 	}
 
 	pub fn compute_level(&mut self)
 	{
-		let mut level = 1;
-		while level_experience(level + 1) <= self.experience
-		{
-			level += 1;
-		}
-		self.level = level;
+		self.level = experience_level(self.experience);
+	}
+
+	pub fn specialty_level(&self, specialty: Specialty) -> i32
+	{
+		experience_level(self.proficiency[specialty as usize])
+	}
+
+	pub fn specialty_effectiveness(&self, specialty: Specialty) -> f32
+	{
+		level_effectiveness(self.specialty_level(specialty))
+	}
+
+	pub fn train(&mut self, specialty: Specialty, experience: f32)
+	{
+		self.proficiency[specialty as usize] += experience;
 	}
 
 	pub fn is_active(&self) -> bool
@@ -1618,20 +2715,25 @@ impl ShipState
 	}
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Team
 {
 	English,
 	French,
 	Pirate,
 	Neutral,
+	// Fishing boats and traders, nobody's enemy but fair game for the unscrupulous.
+	Civilian,
 }
 
 impl Team
 {
 	pub fn is_enemy(&self, other: &Team) -> bool
 	{
-		if *self == Team::Neutral || *other == Team::Neutral
+		if *self == Team::Neutral
+			|| *other == Team::Neutral
+			|| *self == Team::Civilian
+			|| *other == Team::Civilian
 		{
 			false
 		}
@@ -1649,13 +2751,16 @@ impl Team
 		}
 		else
 		{
-			*self == *other
+			*self == *other || *self == Team::Civilian || *other == Team::Civilian
 		}
 	}
 
 	pub fn dock_with(&self, other: &Team) -> bool
 	{
-		if *self == Team::Neutral || *other == Team::Neutral
+		if *self == Team::Neutral
+			|| *other == Team::Neutral
+			|| *self == Team::Civilian
+			|| *other == Team::Civilian
 		{
 			true
 		}
@@ -1674,9 +2779,95 @@ impl Team
 #[derive(Clone, Debug)]
 pub struct Light
 {
+	// In the entity's own frame like Attached::offset, so recentering leaves it be.
 	pub pos: Point3<f32>,
 	pub color: Color,
 	pub intensity: f32,
+	// Direction and cosine of the half-angle of the cone, for spotlights.
+	pub spot: Option<(Vector3<f32>, f32)>,
+	pub animation: LightAnimation,
+}
+
+// What a lantern at noon burns at, compared to at night.
+const LANTERN_DAY_INTENSITY: f32 = 0.3;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum LightStyle
+{
+	#[default]
+	Steady,
+	// Wavers unevenly, like a flame.
+	Flicker,
+	// Swells and fades.
+	Pulse,
+	// Comes up to full after being lit.
+	Windup,
+}
+
+// How a light's intensity changes over time.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightAnimation
+{
+	pub style: LightStyle,
+	// Flickers or pulses a second. Winding up takes 1 / rate seconds.
+	pub rate: f32,
+	// How much of the intensity flickering and pulsing takes away.
+	pub amount: f32,
+	// Lanterns dim during the day.
+	pub lantern: bool,
+	// When it was lit, for winding up.
+	#[serde(skip)]
+	pub lit: f64,
+}
+
+impl Default for LightAnimation
+{
+	fn default() -> Self
+	{
+		Self {
+			style: LightStyle::Steady,
+			rate: 1.,
+			amount: 0.,
+			lantern: false,
+			lit: 0.,
+		}
+	}
+}
+
+impl LightAnimation
+{
+	// What to multiply the intensity by. `phase` keeps lights from flickering in unison,
+	// `daylight` goes from 0 at night to 1 at noon.
+	pub fn factor(&self, time: f64, phase: f32, daylight: f32) -> f32
+	{
+		let t = time * self.rate as f64 + phase as f64;
+		let f = match self.style
+		{
+			LightStyle::Steady => 1.,
+			LightStyle::Flicker =>
+			{
+				// A few unrelated waves look random enough.
+				let noise = 0.5 * (t * 2. * std::f64::consts::PI).sin()
+					+ 0.3 * (t * 5.7 + 1.3).sin()
+					+ 0.2 * (t * 11.3 + 0.7).sin();
+				1. - self.amount * (0.5 + 0.5 * noise as f32)
+			}
+			LightStyle::Pulse =>
+			{
+				1. - self.amount * (0.5 - 0.5 * (t * 2. * std::f64::consts::PI).cos() as f32)
+			}
+			LightStyle::Windup => (((time - self.lit) * self.rate as f64) as f32).clamp(0., 1.),
+		};
+		if self.lantern
+		{
+			f * (1. - (1. - LANTERN_DAY_INTENSITY) * daylight)
+		}
+		else
+		{
+			f
+		}
+	}
 }
 
 #[derive(Clone, Debug)]
@@ -2086,6 +3277,11 @@ pub fn generate_captain_name(team: Team, rng: &mut impl Rng) -> String
 		.choose(rng)
 		.unwrap()
 		.to_string(),
+		Team::Civilian =>
+		{
+			let team = *[Team::English, Team::French].choose(rng).unwrap();
+			return generate_captain_name(team, rng);
+		}
 		Team::Neutral => unreachable!(),
 	};
 	format!("Captain {name}")
@@ -8,6 +8,7 @@ pub enum Action
 {
 	Move,
 	Stop,
+	// Retired, replaced by QueueMove.
 	Queue,
 	Attack,
 	Inventory,
@@ -16,6 +17,37 @@ pub enum Action
 	ZoomIn,
 	Board,
 	Target,
+	Patrol,
+	AttackEnRoute,
+	BroadsideLeft,
+	BroadsideRight,
+	Journal,
+	Economy,
+	DamageLog,
+	HullPatch,
+	PowderSurge,
+	HardToPort,
+	Select,
+	Formation,
+	RepairFore,
+	RepairStarboard,
+	RepairAft,
+	RepairPort,
+	RepairHull,
+	RepairInfirmary,
+	RepairSails,
+	Inspect,
+	RangeRing,
+	EngageTarget,
+	Chart,
+	ShipSummary,
+	QueueMove,
+	FastMove,
+	DebugView,
+	PanLeft,
+	PanRight,
+	PanUp,
+	PanDown,
 }
 
 impl Action
@@ -34,6 +66,37 @@ impl Action
 			Action::ZoomIn => "ZoomIn",
 			Action::Board => "Board",
 			Action::Target => "Target",
+			Action::Patrol => "Patrol",
+			Action::AttackEnRoute => "AttackEnRoute",
+			Action::BroadsideLeft => "BroadsideLeft",
+			Action::BroadsideRight => "BroadsideRight",
+			Action::Journal => "Journal",
+			Action::Economy => "Economy",
+			Action::DamageLog => "DamageLog",
+			Action::HullPatch => "HullPatch",
+			Action::PowderSurge => "PowderSurge",
+			Action::HardToPort => "HardToPort",
+			Action::Select => "Select",
+			Action::Formation => "Formation",
+			Action::RepairFore => "RepairFore",
+			Action::RepairStarboard => "RepairStarboard",
+			Action::RepairAft => "RepairAft",
+			Action::RepairPort => "RepairPort",
+			Action::RepairHull => "RepairHull",
+			Action::RepairInfirmary => "RepairInfirmary",
+			Action::RepairSails => "RepairSails",
+			Action::Inspect => "Inspect",
+			Action::RangeRing => "RangeRing",
+			Action::EngageTarget => "EngageTarget",
+			Action::Chart => "Chart",
+			Action::ShipSummary => "ShipSummary",
+			Action::QueueMove => "QueueMove",
+			Action::FastMove => "FastMove",
+			Action::DebugView => "DebugView",
+			Action::PanLeft => "PanLeft",
+			Action::PanRight => "PanRight",
+			Action::PanUp => "PanUp",
+			Action::PanDown => "PanDown",
 		}
 	}
 }
@@ -425,6 +488,198 @@ impl<'de> serde::Deserialize<'de> for Input
 	}
 }
 
+// Modifier keys held alongside an input.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, PartialOrd, Ord, Default)]
+pub struct Modifiers
+{
+	pub ctrl: bool,
+	pub shift: bool,
+	pub alt: bool,
+}
+
+impl Modifiers
+{
+	pub fn none() -> Self
+	{
+		Self::default()
+	}
+
+	pub fn ctrl() -> Self
+	{
+		Self {
+			ctrl: true,
+			..Self::default()
+		}
+	}
+
+	pub fn shift() -> Self
+	{
+		Self {
+			shift: true,
+			..Self::default()
+		}
+	}
+
+	// The modifier this key stands for, if any.
+	fn from_keycode(keycode: allegro::KeyCode) -> Option<Self>
+	{
+		match keycode
+		{
+			allegro::KeyCode::LCtrl | allegro::KeyCode::RCtrl => Some(Self::ctrl()),
+			allegro::KeyCode::LShift | allegro::KeyCode::RShift => Some(Self::shift()),
+			allegro::KeyCode::Alt | allegro::KeyCode::AltGr => Some(Self {
+				alt: true,
+				..Self::default()
+			}),
+			_ => None,
+		}
+	}
+
+	fn count(&self) -> usize
+	{
+		self.ctrl as usize + self.shift as usize + self.alt as usize
+	}
+
+	fn contains(&self, other: &Modifiers) -> bool
+	{
+		(self.ctrl || !other.ctrl) && (self.shift || !other.shift) && (self.alt || !other.alt)
+	}
+
+	fn set(&mut self, other: &Modifiers, on: bool)
+	{
+		if other.ctrl
+		{
+			self.ctrl = on;
+		}
+		if other.shift
+		{
+			self.shift = on;
+		}
+		if other.alt
+		{
+			self.alt = on;
+		}
+	}
+}
+
+// An input, possibly held together with some modifiers, e.g. Shift+Mouse Left.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, PartialOrd, Ord)]
+pub struct Binding
+{
+	pub modifiers: Modifiers,
+	pub input: Input,
+}
+
+impl Binding
+{
+	pub fn new(input: Input) -> Self
+	{
+		Self {
+			modifiers: Modifiers::none(),
+			input: input,
+		}
+	}
+
+	pub fn with(modifiers: Modifiers, input: Input) -> Self
+	{
+		Self {
+			modifiers: modifiers,
+			input: input,
+		}
+	}
+
+	// Plain input names parse too, which is how bindings were saved before modifiers.
+	pub fn from_str(s: &str) -> Option<Self>
+	{
+		let mut modifiers = Modifiers::none();
+		let mut rest = s;
+		loop
+		{
+			if let Some(r) = rest.strip_prefix("Ctrl+")
+			{
+				modifiers.ctrl = true;
+				rest = r;
+			}
+			else if let Some(r) = rest.strip_prefix("Shift+")
+			{
+				modifiers.shift = true;
+				rest = r;
+			}
+			else if let Some(r) = rest.strip_prefix("Alt+")
+			{
+				modifiers.alt = true;
+				rest = r;
+			}
+			else
+			{
+				break;
+			}
+		}
+		Input::from_str(rest).map(|input| Self::with(modifiers, input))
+	}
+}
+
+impl fmt::Display for Binding
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		if self.modifiers.ctrl
+		{
+			write!(f, "Ctrl+")?;
+		}
+		if self.modifiers.shift
+		{
+			write!(f, "Shift+")?;
+		}
+		if self.modifiers.alt
+		{
+			write!(f, "Alt+")?;
+		}
+		write!(f, "{}", self.input.to_str())
+	}
+}
+
+impl serde::Serialize for Binding
+{
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: serde::Serializer,
+	{
+		serializer.serialize_str(&self.to_string())
+	}
+}
+
+struct BindingVisitor;
+
+impl<'de> serde::de::Visitor<'de> for BindingVisitor
+{
+	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(formatter, "a Binding")
+	}
+
+	type Value = Binding;
+	fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+	where
+		E: serde::de::Error,
+	{
+		Binding::from_str(&value).ok_or(serde::de::Error::invalid_value(
+			serde::de::Unexpected::Str(value),
+			&self,
+		))
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for Binding
+{
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		deserializer.deserialize_str(BindingVisitor)
+	}
+}
+
 #[derive(Debug, Clone)]
 struct InputState
 {
@@ -468,10 +723,77 @@ impl InputState
 	}
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Preset
+{
+	ClassicRts,
+	NavalSim,
+	LeftHanded,
+}
+
+impl Preset
+{
+	pub const ALL: [Preset; 3] = [Preset::ClassicRts, Preset::NavalSim, Preset::LeftHanded];
+
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Preset::ClassicRts => "Classic RTS",
+			Preset::NavalSim => "Naval Sim",
+			Preset::LeftHanded => "Left-handed",
+		}
+	}
+}
+
+// Left-handed keys, mirrored across the keyboard so the right hand finds them.
+const MIRRORED_KEYS: [(allegro::KeyCode, allegro::KeyCode); 15] = [
+	(allegro::KeyCode::Q, allegro::KeyCode::P),
+	(allegro::KeyCode::W, allegro::KeyCode::O),
+	(allegro::KeyCode::E, allegro::KeyCode::I),
+	(allegro::KeyCode::R, allegro::KeyCode::U),
+	(allegro::KeyCode::T, allegro::KeyCode::Y),
+	(allegro::KeyCode::A, allegro::KeyCode::Semicolon),
+	(allegro::KeyCode::S, allegro::KeyCode::L),
+	(allegro::KeyCode::D, allegro::KeyCode::K),
+	(allegro::KeyCode::F, allegro::KeyCode::J),
+	(allegro::KeyCode::G, allegro::KeyCode::H),
+	(allegro::KeyCode::Z, allegro::KeyCode::Slash),
+	(allegro::KeyCode::X, allegro::KeyCode::Fullstop),
+	(allegro::KeyCode::C, allegro::KeyCode::Comma),
+	(allegro::KeyCode::V, allegro::KeyCode::M),
+	(allegro::KeyCode::B, allegro::KeyCode::N),
+];
+
+fn mirror(input: Input) -> Input
+{
+	match input
+	{
+		Input::Keyboard(k) =>
+		{
+			for (a, b) in MIRRORED_KEYS
+			{
+				if k == a
+				{
+					return Input::Keyboard(b);
+				}
+				if k == b
+				{
+					return Input::Keyboard(a);
+				}
+			}
+			input
+		}
+		Input::MouseButton(1) => Input::MouseButton(2),
+		Input::MouseButton(2) => Input::MouseButton(1),
+		_ => input,
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Controls
 {
-	action_to_inputs: BTreeMap<Action, [Option<Input>; 2]>,
+	action_to_inputs: BTreeMap<Action, [Option<Binding>; 2]>,
 	mouse_sensitivity: f32,
 }
 
@@ -480,34 +802,271 @@ impl Controls
 	pub fn new() -> Self
 	{
 		let mut action_to_inputs = BTreeMap::new();
-		action_to_inputs.insert(Action::Move, [Some(Input::MouseButton(1)), None]);
 		action_to_inputs.insert(
-			Action::Stop,
-			[Some(Input::Keyboard(allegro::KeyCode::S)), None],
+			Action::Move,
+			[Some(Binding::new(Input::MouseButton(1))), None],
 		);
 		action_to_inputs.insert(
-			Action::Queue,
-			[Some(Input::Keyboard(allegro::KeyCode::LShift)), None],
+			Action::Stop,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::S))),
+				None,
+			],
 		);
 		action_to_inputs.insert(
 			Action::Attack,
-			[Some(Input::Keyboard(allegro::KeyCode::Space)), None],
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::Space))),
+				None,
+			],
 		);
 		action_to_inputs.insert(
 			Action::Inventory,
-			[Some(Input::Keyboard(allegro::KeyCode::I)), None],
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::I))),
+				None,
+			],
 		);
 		action_to_inputs.insert(
 			Action::Dock,
-			[Some(Input::Keyboard(allegro::KeyCode::D)), None],
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::D))),
+				None,
+			],
+		);
+		action_to_inputs.insert(Action::ZoomIn, [Some(Binding::new(Input::MouseZPos)), None]);
+		action_to_inputs.insert(
+			Action::ZoomOut,
+			[Some(Binding::new(Input::MouseZNeg)), None],
 		);
-		action_to_inputs.insert(Action::ZoomIn, [Some(Input::MouseZPos), None]);
-		action_to_inputs.insert(Action::ZoomOut, [Some(Input::MouseZNeg), None]);
 		action_to_inputs.insert(
 			Action::Board,
-			[Some(Input::Keyboard(allegro::KeyCode::Space)), None],
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::Space))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::Target,
+			[Some(Binding::new(Input::MouseButton(2))), None],
+		);
+		action_to_inputs.insert(
+			Action::Patrol,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::P))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::AttackEnRoute,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::E))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::BroadsideLeft,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::Z))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::BroadsideRight,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::X))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::Journal,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::J))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::Economy,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::M))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::DamageLog,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::K))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::HullPatch,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::Q))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::PowderSurge,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::W))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::HardToPort,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::R))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::Select,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::LCtrl))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::Formation,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::RepairFore,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F1))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::RepairStarboard,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F2))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::RepairAft,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F3))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::RepairPort,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F4))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::RepairHull,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F5))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::RepairInfirmary,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F6))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::RepairSails,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F7))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::Inspect,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::V))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::RangeRing,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::G))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::EngageTarget,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::T))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::Chart,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::C))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::ShipSummary,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::B))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::QueueMove,
+			[
+				Some(Binding::with(Modifiers::shift(), Input::MouseButton(1))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::FastMove,
+			[
+				Some(Binding::with(Modifiers::ctrl(), Input::MouseButton(1))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::DebugView,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::F10))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::PanLeft,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::Left))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::PanRight,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::Right))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::PanUp,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::Up))),
+				None,
+			],
+		);
+		action_to_inputs.insert(
+			Action::PanDown,
+			[
+				Some(Binding::new(Input::Keyboard(allegro::KeyCode::Down))),
+				None,
+			],
 		);
-		action_to_inputs.insert(Action::Target, [Some(Input::MouseButton(2)), None]);
 
 		Self {
 			action_to_inputs: action_to_inputs,
@@ -515,6 +1074,65 @@ impl Controls
 		}
 	}
 
+	pub fn preset(preset: Preset) -> Self
+	{
+		let mut controls = Controls::new();
+		match preset
+		{
+			Preset::ClassicRts => (),
+			// The broadsides go under the left hand, the rest moves out of their way.
+			Preset::NavalSim =>
+			{
+				for (action, key) in [
+					(Action::BroadsideLeft, allegro::KeyCode::Q),
+					(Action::BroadsideRight, allegro::KeyCode::E),
+					(Action::HardToPort, allegro::KeyCode::A),
+					(Action::HullPatch, allegro::KeyCode::H),
+					(Action::AttackEnRoute, allegro::KeyCode::U),
+				]
+				{
+					controls.action_to_inputs.get_mut(&action).unwrap()[0] =
+						Some(Binding::new(Input::Keyboard(key)));
+				}
+				controls.action_to_inputs.get_mut(&Action::ZoomIn).unwrap()[1] =
+					Some(Binding::new(Input::Keyboard(allegro::KeyCode::Equals)));
+				controls.action_to_inputs.get_mut(&Action::ZoomOut).unwrap()[1] =
+					Some(Binding::new(Input::Keyboard(allegro::KeyCode::Minus)));
+			}
+			Preset::LeftHanded =>
+			{
+				for inputs in controls.action_to_inputs.values_mut()
+				{
+					for binding in inputs.iter_mut().flatten()
+					{
+						binding.input = mirror(binding.input);
+					}
+				}
+			}
+		}
+		controls
+	}
+
+	// Which preset these are, if they haven't been changed since.
+	pub fn matching_preset(&self) -> Option<Preset>
+	{
+		Preset::ALL
+			.into_iter()
+			.find(|preset| Controls::preset(*preset).action_to_inputs == self.action_to_inputs)
+	}
+
+	// Old configs can lack actions added since, or have ones that were retired.
+	fn upgrade(&mut self)
+	{
+		let defaults = Controls::new();
+		self.action_to_inputs
+			.retain(|action, _| defaults.action_to_inputs.contains_key(action));
+		for (action, inputs) in defaults.action_to_inputs
+		{
+			self.action_to_inputs.entry(action).or_insert(inputs);
+		}
+	}
+
 	pub fn get_action_string(&self, action: Action) -> String
 	{
 		let mut inputs = vec![];
@@ -522,7 +1140,7 @@ impl Controls
 		{
 			if let Some(input) = input
 			{
-				inputs.push(input.to_str());
+				inputs.push(input.to_string());
 			}
 		}
 		inputs.join("/")
@@ -533,18 +1151,21 @@ impl Controls
 pub struct ControlsHandler
 {
 	controls: Controls,
-	input_to_action: BTreeMap<Input, Action>,
-	input_state: HashMap<Input, InputState>,
+	input_to_action: BTreeMap<Binding, Action>,
+	input_state: HashMap<Binding, InputState>,
+	modifiers: Modifiers,
 }
 
 impl ControlsHandler
 {
-	pub fn new(controls: Controls) -> Self
+	pub fn new(mut controls: Controls) -> Self
 	{
+		controls.upgrade();
 		let mut ret = Self {
 			controls: controls,
 			input_to_action: BTreeMap::new(),
 			input_state: HashMap::new(),
+			modifiers: Modifiers::none(),
 		};
 		ret.update_derived();
 		ret
@@ -582,6 +1203,13 @@ impl ControlsHandler
 		&self.controls
 	}
 
+	pub fn set_controls(&mut self, mut controls: Controls)
+	{
+		controls.upgrade();
+		self.controls = controls;
+		self.update_derived();
+	}
+
 	pub fn get_mouse_sensitivity(&self) -> f32
 	{
 		self.controls.mouse_sensitivity
@@ -592,102 +1220,144 @@ impl ControlsHandler
 		self.controls.mouse_sensitivity = mouse_sensitivity;
 	}
 
-	pub fn get_actions_to_inputs(&self) -> impl Iterator<Item = (&Action, &[Option<Input>; 2])>
+	pub fn get_actions_to_inputs(&self) -> impl Iterator<Item = (&Action, &[Option<Binding>; 2])>
 	{
 		self.controls.action_to_inputs.iter()
 	}
 
-	pub fn get_inputs(&self, action: Action) -> Option<&[Option<Input>; 2]>
+	pub fn get_inputs(&self, action: Action) -> Option<&[Option<Binding>; 2]>
 	{
 		self.controls.action_to_inputs.get(&action)
 	}
 
-	pub fn decode_event(&mut self, event: &allegro::Event) -> Vec<(f32, Action)>
+	// Keeps track of the held modifiers. This needs to see every event, even the ones the UI eats.
+	pub fn track_modifiers(&mut self, event: &allegro::Event)
 	{
 		match event
 		{
 			allegro::Event::KeyDown { keycode, .. } =>
 			{
-				if let Some(state) = self.input_state.get_mut(&Input::Keyboard(*keycode))
+				if let Some(modifier) = Modifiers::from_keycode(*keycode)
 				{
-					state.push(1.);
+					self.modifiers.set(&modifier, true);
 				}
 			}
 			allegro::Event::KeyUp { keycode, .. } =>
 			{
-				if let Some(state) = self.input_state.get_mut(&Input::Keyboard(*keycode))
+				if let Some(modifier) = Modifiers::from_keycode(*keycode)
 				{
-					state.push(0.);
+					self.modifiers.set(&modifier, false);
 				}
 			}
+			allegro::Event::DisplaySwitchOut { .. } => self.modifiers = Modifiers::none(),
+			_ => (),
+		}
+	}
+
+	// The binding a press of this input triggers with the current modifiers. The most specific
+	// one wins, so Shift+Mouse Left doesn't also count as Mouse Left.
+	fn find_binding(&self, input: Input) -> Option<Binding>
+	{
+		self.input_state
+			.keys()
+			.filter(|binding| binding.input == input && self.modifiers.contains(&binding.modifiers))
+			.max_by_key(|binding| binding.modifiers.count())
+			.copied()
+	}
+
+	fn press(&mut self, input: Input, strength: f32)
+	{
+		if let Some(binding) = self.find_binding(input)
+		{
+			self.input_state.get_mut(&binding).unwrap().push(strength);
+		}
+	}
+
+	// Releases every binding of the input, the modifiers might have changed since the press.
+	fn release(&mut self, input: Input)
+	{
+		for (binding, state) in &mut self.input_state
+		{
+			if binding.input == input
+			{
+				state.push(0.);
+			}
+		}
+	}
+
+	fn pulse(&mut self, input: Input, strength: f32)
+	{
+		if let Some(binding) = self.find_binding(input)
+		{
+			let state = self.input_state.get_mut(&binding).unwrap();
+			state.push(strength);
+			state.push(0.);
+		}
+	}
+
+	// Whether this event presses one of the action's bindings.
+	pub fn matches(&self, action: Action, event: &allegro::Event) -> bool
+	{
+		let input = match event
+		{
+			allegro::Event::KeyDown { keycode, .. } => Input::Keyboard(*keycode),
+			allegro::Event::MouseButtonDown { button, .. } => Input::MouseButton(*button as i32),
+			_ => return false,
+		};
+		match (self.find_binding(input), self.get_inputs(action))
+		{
+			(Some(binding), Some(inputs)) => inputs.contains(&Some(binding)),
+			_ => false,
+		}
+	}
+
+	pub fn decode_event(&mut self, event: &allegro::Event) -> Vec<(f32, Action)>
+	{
+		let sensitivity = self.controls.mouse_sensitivity;
+		match event
+		{
+			allegro::Event::KeyDown { keycode, .. } =>
+			{
+				self.press(Input::Keyboard(*keycode), 1.);
+			}
+			allegro::Event::KeyUp { keycode, .. } =>
+			{
+				self.release(Input::Keyboard(*keycode));
+			}
 			allegro::Event::MouseButtonDown { button, .. } =>
 			{
-				if let Some(state) = self
-					.input_state
-					.get_mut(&Input::MouseButton(*button as i32))
-				{
-					state.push(1.);
-				}
+				self.press(Input::MouseButton(*button as i32), 1.);
 			}
 			allegro::Event::MouseButtonUp { button, .. } =>
 			{
-				if let Some(state) = self
-					.input_state
-					.get_mut(&Input::MouseButton(*button as i32))
-				{
-					state.push(0.);
-				}
+				self.release(Input::MouseButton(*button as i32));
 			}
 			allegro::Event::MouseAxes { dx, dy, dz, .. } =>
 			{
 				if *dx < 0
 				{
-					if let Some(state) = self.input_state.get_mut(&Input::MouseXNeg)
-					{
-						state.push(self.controls.mouse_sensitivity * -*dx as f32);
-						state.push(0.);
-					}
+					self.pulse(Input::MouseXNeg, sensitivity * -*dx as f32);
 				}
 				else if *dx > 0
 				{
-					if let Some(state) = self.input_state.get_mut(&Input::MouseXPos)
-					{
-						state.push(self.controls.mouse_sensitivity * *dx as f32);
-						state.push(0.);
-					}
+					self.pulse(Input::MouseXPos, sensitivity * *dx as f32);
 				}
 				if *dy < 0
 				{
-					if let Some(state) = self.input_state.get_mut(&Input::MouseYNeg)
-					{
-						state.push(self.controls.mouse_sensitivity * -*dy as f32);
-						state.push(0.);
-					}
+					self.pulse(Input::MouseYNeg, sensitivity * -*dy as f32);
 				}
 				else if *dy > 0
 				{
-					if let Some(state) = self.input_state.get_mut(&Input::MouseYPos)
-					{
-						state.push(self.controls.mouse_sensitivity * *dy as f32);
-						state.push(0.);
-					}
+					self.pulse(Input::MouseYPos, sensitivity * *dy as f32);
 				}
+				// Is the lack of sensitivity here a hack?
 				if *dz < 0
 				{
-					if let Some(state) = self.input_state.get_mut(&Input::MouseZNeg)
-					{
-						// Is the lack of sensitivity here a hack?
-						state.push(-*dz as f32);
-						state.push(0.);
-					}
+					self.pulse(Input::MouseZNeg, -*dz as f32);
 				}
 				else if *dz > 0
 				{
-					if let Some(state) = self.input_state.get_mut(&Input::MouseZPos)
-					{
-						state.push(*dz as f32);
-						state.push(0.);
-					}
+					self.pulse(Input::MouseZPos, *dz as f32);
 				}
 			}
 			_ => (),
@@ -738,6 +1408,19 @@ impl ControlsHandler
 		let mut handled = false;
 		let new_input = match event
 		{
+			// A modifier might be the start of a combo, so it's only bound by itself once it's
+			// released without anything else being pressed.
+			allegro::Event::KeyDown { keycode, .. }
+				if Modifiers::from_keycode(*keycode).is_some() =>
+			{
+				None
+			}
+			allegro::Event::KeyUp { keycode, .. }
+				if Modifiers::from_keycode(*keycode).is_some() =>
+			{
+				handled = true;
+				Some(Input::Keyboard(*keycode))
+			}
 			allegro::Event::KeyDown { keycode, .. } =>
 			{
 				handled = true;
@@ -767,7 +1450,8 @@ impl ControlsHandler
 				}
 			}
 			_ => None,
-		};
+		}
+		.map(|input| Binding::with(self.modifiers, input));
 		if let Some(new_input) = new_input
 		{
 			if self.input_to_action.contains_key(&new_input)
@@ -802,3 +1486,44 @@ impl ControlsHandler
 		}
 	}
 }
+
+#[test]
+fn test_binding_str()
+{
+	let binding = Binding::with(Modifiers::shift(), Input::MouseButton(1));
+	assert_eq!(binding.to_string(), "Shift+Mouse Left");
+	assert_eq!(Binding::from_str("Shift+Mouse Left"), Some(binding));
+	assert_eq!(
+		Binding::from_str("MouseX+"),
+		Some(Binding::new(Input::MouseXPos))
+	);
+	assert_eq!(Binding::from_str("Ctrl+"), None);
+}
+
+#[test]
+fn test_presets()
+{
+	let classic = Controls::preset(Preset::ClassicRts);
+	assert_eq!(classic.matching_preset(), Some(Preset::ClassicRts));
+
+	let left_handed = Controls::preset(Preset::LeftHanded);
+	assert_eq!(left_handed.matching_preset(), Some(Preset::LeftHanded));
+	assert_eq!(
+		left_handed.action_to_inputs[&Action::Stop][0],
+		Some(Binding::new(Input::Keyboard(allegro::KeyCode::L)))
+	);
+	assert_eq!(
+		left_handed.action_to_inputs[&Action::Move][0],
+		Some(Binding::new(Input::MouseButton(2)))
+	);
+
+	// Every preset still has every action.
+	for preset in Preset::ALL
+	{
+		let controls = Controls::preset(preset);
+		assert_eq!(
+			controls.action_to_inputs.keys().collect::<Vec<_>>(),
+			classic.action_to_inputs.keys().collect::<Vec<_>>()
+		);
+	}
+}
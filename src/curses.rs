@@ -0,0 +1,93 @@
+use serde_derive::{Deserialize, Serialize};
+
+// Optional run modifiers, each trading a harder voyage for better spoils.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Curse
+{
+	BrittleHulls,
+	HauntedWaters,
+	GreedyMerchants,
+}
+
+pub const CURSES: [Curse; 3] = [
+	Curse::BrittleHulls,
+	Curse::HauntedWaters,
+	Curse::GreedyMerchants,
+];
+
+impl Curse
+{
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Curse::BrittleHulls => "Brittle Hulls",
+			Curse::HauntedWaters => "Haunted Waters",
+			Curse::GreedyMerchants => "Greedy Merchants",
+		}
+	}
+
+	pub fn description(&self) -> &'static str
+	{
+		match self
+		{
+			Curse::BrittleHulls => "+50% hull damage for everyone, +25% loot",
+			Curse::HauntedWaters => "Wisps come twice as often, officers are 2 levels higher",
+			Curse::GreedyMerchants => "Merchants charge 30% more, +50% experience from victories",
+		}
+	}
+
+	// What this curse changes, the rest of the game only ever looks at the sum of these.
+	fn apply(&self, modifiers: &mut Modifiers)
+	{
+		match self
+		{
+			Curse::BrittleHulls =>
+			{
+				modifiers.hull_damage *= 1.5;
+				modifiers.loot_chance *= 1.25;
+			}
+			Curse::HauntedWaters =>
+			{
+				modifiers.wisp_interval *= 0.5;
+				modifiers.officer_levels += 2;
+			}
+			Curse::GreedyMerchants =>
+			{
+				modifiers.merchant_markup *= 1.3;
+				modifiers.experience *= 1.5;
+			}
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Modifiers
+{
+	pub hull_damage: f32,
+	pub loot_chance: f32,
+	pub wisp_interval: f32,
+	pub officer_levels: i32,
+	pub merchant_markup: f32,
+	pub experience: f32,
+}
+
+impl Modifiers
+{
+	pub fn new(curses: &[Curse]) -> Self
+	{
+		let mut modifiers = Self {
+			hull_damage: 1.,
+			loot_chance: 1.,
+			wisp_interval: 1.,
+			officer_levels: 0,
+			merchant_markup: 1.,
+			experience: 1.,
+		};
+		for curse in curses
+		{
+			curse.apply(&mut modifiers);
+		}
+		modifiers
+	}
+}
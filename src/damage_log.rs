@@ -0,0 +1,148 @@
+use circular_buffer::CircularBuffer;
+
+// How many hits are kept around.
+const LOG_SIZE: usize = 256;
+// A lull this long ends the combat, and the next hit starts a new one.
+const COMBAT_TIMEOUT: f64 = 20.;
+
+pub const FACINGS: [&str; 4] = ["Fore", "Starboard", "Aft", "Port"];
+
+#[derive(Clone, Debug)]
+pub struct Hit
+{
+	pub time: f64,
+	// Dealt by the player, rather than taken.
+	pub outgoing: bool,
+	pub weapon: String,
+	pub damage: f32,
+	// Armor segment struck, None for the rigging.
+	pub facing: Option<usize>,
+	pub crit: bool,
+	pub items_destroyed: Vec<String>,
+}
+
+impl Hit
+{
+	fn describe(&self) -> String
+	{
+		let mut text = format!(
+			"{} {} {:.1}{} to the {}",
+			if self.outgoing { "Your" } else { "Their" },
+			self.weapon,
+			self.damage,
+			if self.crit { " (crit)" } else { "" },
+			self.facing.map(|f| FACINGS[f]).unwrap_or("Sails"),
+		);
+		if !self.items_destroyed.is_empty()
+		{
+			text.push_str(&format!(", destroyed {}", self.items_destroyed.join(", ")));
+		}
+		text
+	}
+}
+
+// Every hit involving the player, and how each of their weapons is doing in the current combat.
+pub struct DamageLog
+{
+	hits: CircularBuffer<LOG_SIZE, Hit>,
+	combat_start: f64,
+	last_hit: f64,
+	// Weapon name and the damage it dealt since the combat started.
+	totals: Vec<(String, f32)>,
+}
+
+impl DamageLog
+{
+	pub fn new() -> Self
+	{
+		Self {
+			hits: CircularBuffer::new(),
+			combat_start: 0.,
+			last_hit: -COMBAT_TIMEOUT,
+			totals: vec![],
+		}
+	}
+
+	pub fn record(&mut self, hit: Hit)
+	{
+		if hit.time - self.last_hit > COMBAT_TIMEOUT
+		{
+			self.combat_start = hit.time;
+			self.totals.clear();
+		}
+		self.last_hit = hit.time;
+		if hit.outgoing
+		{
+			match self.totals.iter_mut().find(|(name, _)| *name == hit.weapon)
+			{
+				Some((_, total)) => *total += hit.damage,
+				None => self.totals.push((hit.weapon.clone(), hit.damage)),
+			}
+		}
+		self.hits.push_back(hit);
+	}
+
+	// Damage per second of each weapon since the combat started, best first.
+	pub fn dps(&self, time: f64) -> Vec<(String, f32)>
+	{
+		let end = if time - self.last_hit > COMBAT_TIMEOUT
+		{
+			self.last_hit
+		}
+		else
+		{
+			time
+		};
+		let duration = (end - self.combat_start).max(1.) as f32;
+		let mut dps: Vec<_> = self
+			.totals
+			.iter()
+			.map(|(name, total)| (name.clone(), total / duration))
+			.collect();
+		dps.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+		dps
+	}
+
+	// The panel's text, oldest hit first.
+	pub fn lines(&self, time: f64) -> Vec<String>
+	{
+		let mut lines = vec![];
+		for hit in &self.hits
+		{
+			lines.push(format!("{:>5.0}s ago  {}", time - hit.time, hit.describe()));
+		}
+		lines.push("".into());
+		lines.push("Damage per second this combat:".into());
+		for (name, dps) in self.dps(time)
+		{
+			lines.push(format!("  {name}: {dps:.1}"));
+		}
+		lines
+	}
+}
+
+#[test]
+fn test_dps()
+{
+	let hit = |time, weapon: &str, damage| Hit {
+		time: time,
+		outgoing: true,
+		weapon: weapon.into(),
+		damage: damage,
+		facing: Some(0),
+		crit: false,
+		items_destroyed: vec![],
+	};
+	let mut log = DamageLog::new();
+	log.record(hit(100., "Cannon", 10.));
+	log.record(hit(105., "Cannon", 10.));
+	log.record(hit(110., "Long Gun", 5.));
+	assert_eq!(
+		log.dps(110.),
+		vec![("Cannon".to_string(), 2.), ("Long Gun".to_string(), 0.5)]
+	);
+
+	// A new combat starts over.
+	log.record(hit(200., "Long Gun", 5.));
+	assert_eq!(log.dps(201.), vec![("Long Gun".to_string(), 5.)]);
+}
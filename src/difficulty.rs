@@ -0,0 +1,131 @@
+// Adaptive difficulty, if turned on in the options. Fights the player wins handily push the
+// rating up, fights they lose or barely scrape through push it down, and the rating nudges
+// hostile ships within bounds.
+
+// How far one fight can move the rating.
+const RATE: f32 = 0.15;
+// Seconds between looks at the damage traded outside of decisive fights.
+const REVIEW_INTERVAL: f64 = 60.;
+const MAX_LEVEL_SHIFT: f32 = 2.;
+const MAX_ACCURACY_SHIFT: f32 = 0.2;
+const MAX_RELOAD_SHIFT: f32 = 0.2;
+
+#[derive(Clone, Debug)]
+pub struct Difficulty
+{
+	// From -1 (going easy) to 1 (stiffer patrols).
+	rating: f32,
+	// Damage traded since the last fight was settled.
+	dealt: f32,
+	taken: f32,
+	time_to_review: f64,
+}
+
+impl Difficulty
+{
+	pub fn new(time: f64) -> Self
+	{
+		Self {
+			rating: 0.,
+			dealt: 0.,
+			taken: 0.,
+			time_to_review: time + REVIEW_INTERVAL,
+		}
+	}
+
+	pub fn dealt(&mut self, amount: f32)
+	{
+		self.dealt += amount;
+	}
+
+	pub fn taken(&mut self, amount: f32)
+	{
+		self.taken += amount;
+	}
+
+	// 1 for a win, -1 for a loss, 0 if nothing was decided. The damage traded counts as much.
+	pub fn fight(&mut self, outcome: f32)
+	{
+		let total = self.dealt + self.taken;
+		let margin = if total > 0.
+		{
+			(self.dealt - self.taken) / total
+		}
+		else
+		{
+			0.
+		};
+		self.rating = (self.rating + RATE * (outcome + margin) / 2.).clamp(-1., 1.);
+		self.dealt = 0.;
+		self.taken = 0.;
+	}
+
+	pub fn logic(&mut self, time: f64)
+	{
+		if time > self.time_to_review
+		{
+			if self.dealt + self.taken > 0.
+			{
+				self.fight(0.);
+			}
+			self.time_to_review = time + REVIEW_INTERVAL;
+		}
+	}
+
+	pub fn level_shift(&self) -> i32
+	{
+		(self.rating * MAX_LEVEL_SHIFT).round() as i32
+	}
+
+	pub fn accuracy(&self) -> f32
+	{
+		1. + MAX_ACCURACY_SHIFT * self.rating
+	}
+
+	pub fn reload(&self) -> f32
+	{
+		1. + MAX_RELOAD_SHIFT * self.rating
+	}
+
+	pub fn describe(&self) -> String
+	{
+		format!(
+			"Enemy levels {:+}, accuracy {:+.0}%, reload {:+.0}%",
+			self.level_shift(),
+			100. * (self.accuracy() - 1.),
+			100. * (self.reload() - 1.)
+		)
+	}
+}
+
+#[test]
+fn test_bounds()
+{
+	let mut difficulty = Difficulty::new(0.);
+	for _ in 0..100
+	{
+		difficulty.dealt(10.);
+		difficulty.fight(1.);
+	}
+	assert_eq!(difficulty.level_shift(), 2);
+	assert!((difficulty.accuracy() - 1.2).abs() < 1e-6);
+
+	for _ in 0..100
+	{
+		difficulty.taken(10.);
+		difficulty.fight(-1.);
+	}
+	assert_eq!(difficulty.level_shift(), -2);
+	assert!((difficulty.reload() - 0.8).abs() < 1e-6);
+}
+
+#[test]
+fn test_close_win()
+{
+	// Winning while taking as good as you gave only counts for half.
+	let mut difficulty = Difficulty::new(0.);
+	difficulty.dealt(10.);
+	difficulty.taken(10.);
+	difficulty.fight(1.);
+	assert!((difficulty.rating - RATE / 2.).abs() < 1e-6);
+}
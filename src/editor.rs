@@ -0,0 +1,478 @@
+use crate::error::Result;
+use crate::utils::ColorExt;
+use crate::{components as comps, game, game_state, ui, ui_script, utils};
+
+use allegro::*;
+use allegro_font::*;
+use nalgebra::{Point2, Vector2};
+use std::f32::consts::PI;
+
+// How far out the slot direction handle sits, in ship units.
+const DIR_HANDLE_LENGTH: f32 = 3.;
+const PICK_RADIUS: f32 = 10.;
+// Weapons fire in this arc by default, most weapons don't change it much.
+const NOMINAL_ARC: f32 = PI / 2.;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Handle
+{
+	Slot(usize),
+	SlotDir(usize),
+	Light(usize),
+}
+
+pub struct Editor
+{
+	file: String,
+	desc: game::ShipDesc,
+	triangles: Vec<[Point2<f32>; 3]>,
+	scale: f32,
+	selected: Option<Handle>,
+	dragging: bool,
+	message: Option<(String, f64)>,
+}
+
+fn snap(v: f32, step: f32) -> f32
+{
+	(v / step).round() * step
+}
+
+impl Editor
+{
+	pub fn new(file: &str, state: &mut game_state::GameState) -> Result<Self>
+	{
+		state.paused = false;
+		let desc = game::load_ship_desc(file, state)?;
+		let mesh = state.get_mesh(&desc.mesh)?;
+		let (min, max) = mesh.bounds();
+		let extent = (max.z - min.z).max(max.x - min.x).max(1.);
+
+		Ok(Self {
+			file: file.to_string(),
+			triangles: mesh.top_down_triangles(),
+			scale: 0.8 * state.display_width.min(state.display_height) / extent,
+			desc: desc,
+			selected: None,
+			dragging: false,
+			message: None,
+		})
+	}
+
+	fn center(&self, state: &game_state::GameState) -> Point2<f32>
+	{
+		Point2::new(state.display_width / 2., state.display_height / 2.)
+	}
+
+	// Ship (z, x) plane to screen, with the bow pointing right.
+	fn ship_to_screen(&self, pos: Point2<f32>, state: &game_state::GameState) -> Point2<f32>
+	{
+		let c = self.center(state);
+		Point2::new(c.x + pos.x * self.scale, c.y - pos.y * self.scale)
+	}
+
+	fn screen_to_ship(&self, pos: Point2<f32>, state: &game_state::GameState) -> Point2<f32>
+	{
+		let c = self.center(state);
+		Point2::new((pos.x - c.x) / self.scale, -(pos.y - c.y) / self.scale)
+	}
+
+	fn dir_handle(&self, idx: usize) -> Option<Point2<f32>>
+	{
+		let slot = &self.desc.slots[idx];
+		slot.dir.map(|dir| {
+			let theta = dir * PI;
+			Point2::new(slot.pos[0], slot.pos[1])
+				+ DIR_HANDLE_LENGTH * Vector2::new(theta.cos(), theta.sin())
+		})
+	}
+
+	fn handle_pos(&self, handle: Handle) -> Option<Point2<f32>>
+	{
+		match handle
+		{
+			Handle::Slot(i) =>
+			{
+				let slot = &self.desc.slots[i];
+				Some(Point2::new(slot.pos[0], slot.pos[1]))
+			}
+			Handle::SlotDir(i) => self.dir_handle(i),
+			Handle::Light(i) =>
+			{
+				let light = &self.desc.lights[i];
+				Some(Point2::new(light.pos[2], light.pos[0]))
+			}
+		}
+	}
+
+	fn handles(&self) -> Vec<Handle>
+	{
+		let mut handles = vec![];
+		for i in 0..self.desc.slots.len()
+		{
+			handles.push(Handle::SlotDir(i));
+			handles.push(Handle::Slot(i));
+		}
+		for i in 0..self.desc.lights.len()
+		{
+			handles.push(Handle::Light(i));
+		}
+		handles
+	}
+
+	fn pick(&self, mouse_pos: Point2<f32>, state: &game_state::GameState) -> Option<Handle>
+	{
+		let mut best = None;
+		let mut best_dist = PICK_RADIUS;
+		for handle in self.handles()
+		{
+			if let Some(pos) = self.handle_pos(handle)
+			{
+				let dist = (self.ship_to_screen(pos, state) - mouse_pos).magnitude();
+				if dist < best_dist
+				{
+					best = Some(handle);
+					best_dist = dist;
+				}
+			}
+		}
+		best
+	}
+
+	fn drag_to(&mut self, pos: Point2<f32>)
+	{
+		match self.selected
+		{
+			Some(Handle::Slot(i)) =>
+			{
+				self.desc.slots[i].pos = [snap(pos.x, 0.1), snap(pos.y, 0.1)];
+			}
+			Some(Handle::SlotDir(i)) =>
+			{
+				let slot = &mut self.desc.slots[i];
+				let diff = pos - Point2::new(slot.pos[0], slot.pos[1]);
+				if diff.magnitude() > 0.1
+				{
+					slot.dir = Some(snap(diff.y.atan2(diff.x) / PI, 0.05));
+				}
+			}
+			Some(Handle::Light(i)) =>
+			{
+				let light = &mut self.desc.lights[i];
+				light.pos[2] = snap(pos.x, 0.1);
+				light.pos[0] = snap(pos.y, 0.1);
+			}
+			None => (),
+		}
+	}
+
+	fn remove_selected(&mut self)
+	{
+		match self.selected.take()
+		{
+			Some(Handle::Slot(i)) | Some(Handle::SlotDir(i)) =>
+			{
+				self.desc.slots.remove(i);
+			}
+			Some(Handle::Light(i)) =>
+			{
+				self.desc.lights.remove(i);
+			}
+			None => (),
+		}
+	}
+
+	fn save(&mut self, state: &mut game_state::GameState)
+	{
+		// Check what was saved, not what was loaded before.
+		state.ship_descs.remove(&self.file);
+		let res = utils::save_config(&self.file, &self.desc)
+			.and_then(|_| game::load_ship_desc(&self.file, state));
+		let message = match res
+		{
+			Ok(_) => format!("Saved {}", self.file),
+			Err(e) =>
+			{
+				println!("{}", e);
+				format!("Problem saving {}, see the console", self.file)
+			}
+		};
+		self.message = Some((message, state.core.get_time()));
+	}
+
+	pub fn logic(
+		&mut self, _state: &mut game_state::GameState,
+	) -> Result<Option<game_state::NextScreen>>
+	{
+		Ok(None)
+	}
+
+	pub fn input(
+		&mut self, event: &Event, state: &mut game_state::GameState,
+	) -> Result<Option<game_state::NextScreen>>
+	{
+		let mouse_pos = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
+		let ship_pos = self.screen_to_ship(mouse_pos, state);
+		match *event
+		{
+			Event::MouseAxes { x, y, .. } =>
+			{
+				if state.track_mouse
+				{
+					state.mouse_pos = Point2::new(x as i32, y as i32);
+				}
+				if self.dragging
+				{
+					let ship_pos = self.screen_to_ship(Point2::new(x as f32, y as f32), state);
+					self.drag_to(ship_pos);
+				}
+			}
+			Event::MouseButtonDown { button: 1, .. } =>
+			{
+				self.selected = self.pick(mouse_pos, state);
+				self.dragging = self.selected.is_some();
+			}
+			Event::MouseButtonUp { button: 1, .. } =>
+			{
+				self.dragging = false;
+			}
+			Event::KeyDown { keycode, .. } => match keycode
+			{
+				KeyCode::Escape => return Ok(Some(game_state::NextScreen::Menu)),
+				KeyCode::S => self.save(state),
+				KeyCode::N =>
+				{
+					self.desc.slots.push(game::SlotDesc {
+						pos: [snap(ship_pos.x, 0.1), snap(ship_pos.y, 0.1)],
+						dir: Some(0.),
+						weapons_allowed: true,
+						size: comps::MountSize::Small,
+						rigging: false,
+					});
+					self.selected = Some(Handle::Slot(self.desc.slots.len() - 1));
+				}
+				KeyCode::L =>
+				{
+					self.desc.lights.push(game::LightDesc {
+						pos: [snap(ship_pos.y, 0.1), 3., snap(ship_pos.x, 0.1)],
+						color: [1., 1., 1.],
+						intensity: 4.,
+						animation: Default::default(),
+					});
+					self.selected = Some(Handle::Light(self.desc.lights.len() - 1));
+				}
+				KeyCode::Delete | KeyCode::Backspace => self.remove_selected(),
+				KeyCode::W =>
+				{
+					if let Some(Handle::Slot(i)) | Some(Handle::SlotDir(i)) = self.selected
+					{
+						let slot = &mut self.desc.slots[i];
+						slot.weapons_allowed = !slot.weapons_allowed;
+					}
+				}
+				KeyCode::M =>
+				{
+					if let Some(Handle::Slot(i)) | Some(Handle::SlotDir(i)) = self.selected
+					{
+						let slot = &mut self.desc.slots[i];
+						slot.size = match slot.size
+						{
+							comps::MountSize::Small => comps::MountSize::Medium,
+							comps::MountSize::Medium => comps::MountSize::Large,
+							comps::MountSize::Large => comps::MountSize::Small,
+						};
+					}
+				}
+				KeyCode::R =>
+				{
+					if let Some(Handle::Slot(i)) | Some(Handle::SlotDir(i)) = self.selected
+					{
+						let slot = &mut self.desc.slots[i];
+						slot.dir = if slot.dir.is_some() { None } else { Some(0.) };
+						self.selected = Some(Handle::Slot(i));
+					}
+				}
+				KeyCode::Up | KeyCode::Down =>
+				{
+					if let Some(Handle::Light(i)) = self.selected
+					{
+						let dy = if keycode == KeyCode::Up { 0.5 } else { -0.5 };
+						self.desc.lights[i].pos[1] += dy;
+					}
+				}
+				KeyCode::PadPlus | KeyCode::PadMinus =>
+				{
+					if let Some(Handle::Light(i)) = self.selected
+					{
+						let light = &mut self.desc.lights[i];
+						let di = if keycode == KeyCode::PadPlus { 1. } else { -1. };
+						light.intensity = (light.intensity + di).max(0.);
+					}
+				}
+				_ => (),
+			},
+			_ => (),
+		}
+		Ok(None)
+	}
+
+	pub fn draw(&mut self, state: &game_state::GameState) -> Result<()>
+	{
+		state.core.clear_to_color(Color::from_rgb_f(0.05, 0.1, 0.2));
+		let ui_color = ui::ui_color();
+		let lh = state.ui_font.get_line_height() as f32;
+		let (dw, dh) = (state.display_width, state.display_height);
+
+		// Grid, one line per ship unit.
+		let c = self.center(state);
+		let grid_color = Color::from_rgb_f(0.1, 0.15, 0.3);
+		let n = (dw.max(dh) / self.scale / 2.) as i32 + 1;
+		for i in -n..=n
+		{
+			let f = c + Vector2::new(i as f32, i as f32) * self.scale;
+			state.prim.draw_line(f.x, 0., f.x, dh, grid_color, 1.);
+			state.prim.draw_line(0., f.y, dw, f.y, grid_color, 1.);
+		}
+		state
+			.prim
+			.draw_line(c.x, 0., c.x, dh, grid_color.interpolate(ui_color, 0.3), 1.);
+		state
+			.prim
+			.draw_line(0., c.y, dw, c.y, grid_color.interpolate(ui_color, 0.3), 1.);
+
+		let hull_color = Color::from_rgba_f(0.3, 0.3, 0.3, 0.3);
+		for tri in &self.triangles
+		{
+			let [a, b, c] = tri.map(|p| self.ship_to_screen(p, state));
+			state
+				.prim
+				.draw_filled_triangle(a.x, a.y, b.x, b.y, c.x, c.y, hull_color);
+		}
+
+		let highlight = |handle: Handle| self.selected == Some(handle);
+		for (i, slot) in self.desc.slots.iter().enumerate()
+		{
+			let pos = self.ship_to_screen(Point2::new(slot.pos[0], slot.pos[1]), state);
+			let color = if slot.weapons_allowed
+			{
+				Color::from_rgb_f(0.2, 1., 0.2)
+			}
+			else
+			{
+				Color::from_rgb_f(0.6, 0.6, 0.6)
+			};
+			if let (Some(dir), Some(handle)) = (slot.dir, self.dir_handle(i))
+			{
+				let theta = -dir * PI;
+				state.prim.draw_filled_pieslice(
+					pos.x,
+					pos.y,
+					DIR_HANDLE_LENGTH * self.scale,
+					theta - NOMINAL_ARC / 2.,
+					NOMINAL_ARC,
+					color.interpolate(Color::from_rgba(0, 0, 0, 0), 0.8),
+				);
+				let handle = self.ship_to_screen(handle, state);
+				state
+					.prim
+					.draw_line(pos.x, pos.y, handle.x, handle.y, color, 2.);
+				state.prim.draw_filled_circle(
+					handle.x,
+					handle.y,
+					if highlight(Handle::SlotDir(i))
+					{
+						8.
+					}
+					else
+					{
+						5.
+					},
+					color,
+				);
+			}
+			state.prim.draw_filled_circle(
+				pos.x,
+				pos.y,
+				if highlight(Handle::Slot(i)) { 10. } else { 7. },
+				color,
+			);
+			state.core.draw_text(
+				&state.ui_font,
+				color,
+				pos.x,
+				pos.y + 12.,
+				FontAlign::Centre,
+				&format!("{} {}", i, slot.size.name()),
+			);
+		}
+
+		for (i, light) in self.desc.lights.iter().enumerate()
+		{
+			let pos = self.ship_to_screen(Point2::new(light.pos[2], light.pos[0]), state);
+			let color = Color::from_rgb_f(light.color[0], light.color[1], light.color[2]);
+			state.prim.draw_circle(
+				pos.x,
+				pos.y,
+				if highlight(Handle::Light(i)) { 10. } else { 7. },
+				color,
+				3.,
+			);
+			state.core.draw_text(
+				&state.ui_font,
+				color,
+				pos.x,
+				pos.y + 12.,
+				FontAlign::Centre,
+				&format!("h {:.1}, i {:.0}", light.pos[1], light.intensity),
+			);
+		}
+
+		let help = [
+			format!("Editing {}", self.file),
+			"Drag slots, slot directions and lights".into(),
+			"N: New slot, L: New light, Del: Remove".into(),
+			"W: Toggle weapons, R: Toggle direction, M: Mount size".into(),
+			"Up/Down: Light height, +/-: Light intensity".into(),
+			"S: Save, Esc: Quit".into(),
+		];
+		for (i, line) in help.iter().enumerate()
+		{
+			state.core.draw_text(
+				&state.ui_font,
+				ui_color,
+				state.m,
+				state.m + i as f32 * lh,
+				FontAlign::Left,
+				line,
+			);
+		}
+		if let Some((message, time)) = &self.message
+		{
+			if state.core.get_time() - time < 3.
+			{
+				state.core.draw_text(
+					&state.ui_font,
+					ui_color,
+					dw / 2.,
+					dh - state.m * 2.,
+					FontAlign::Centre,
+					message,
+				);
+			}
+		}
+		Ok(())
+	}
+
+	pub fn probe(&self) -> ui_script::Probe
+	{
+		ui_script::Probe {
+			screen: "Editor",
+			subscreen: None,
+			dragging: false,
+		}
+	}
+
+	pub fn change_buffers(&mut self, _state: &mut game_state::GameState) -> Result<()>
+	{
+		Ok(())
+	}
+}
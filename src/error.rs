@@ -21,6 +21,12 @@ impl Error
 		}
 	}
 
+	// Just the message, without the cause and backtrace.
+	pub fn short(&self) -> &str
+	{
+		&self.message
+	}
+
 	pub fn context(self, message: String) -> Self
 	{
 		Error::new(message, Some(Box::new(self)))
@@ -87,6 +93,18 @@ impl From<gltf::Error> for Error
 	}
 }
 
+impl From<std::io::Error> for Error
+{
+	fn from(error: std::io::Error) -> Self
+	{
+		Self {
+			message: format!("{}", error),
+			inner: Some(Box::new(error)),
+			backtrace: Backtrace::capture(),
+		}
+	}
+}
+
 impl fmt::Display for Error
 {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
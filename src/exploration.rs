@@ -0,0 +1,126 @@
+use nalgebra::Point2;
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Landmark
+{
+	// Somewhere we've traded.
+	Market,
+	// Where a ship went down.
+	Wreck,
+	// Where the Voidwind was last seen.
+	Boss,
+}
+
+impl Landmark
+{
+	pub fn color(&self) -> [f32; 3]
+	{
+		match self
+		{
+			Landmark::Market => [0.4, 0.9, 0.4],
+			Landmark::Wreck => [0.6, 0.5, 0.4],
+			Landmark::Boss => [0.8, 0.2, 0.9],
+		}
+	}
+}
+
+// Something the player wrote on the chart.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Note
+{
+	// In fractional global cells, like the chart markers.
+	pub pos: [f32; 2],
+	pub text: String,
+}
+
+fn cell_of(pos: [f32; 2]) -> [i32; 2]
+{
+	[pos[0].floor() as i32, pos[1].floor() as i32]
+}
+
+// What the voyage has turned up, kept for the chart long after the cells themselves are gone.
+// Positions are in fractional global cells.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Exploration
+{
+	visited: HashSet<[i32; 2]>,
+	landmarks: Vec<([f32; 2], Landmark)>,
+	pub notes: Vec<Note>,
+}
+
+impl Exploration
+{
+	pub fn new() -> Self
+	{
+		Self::default()
+	}
+
+	pub fn visit(&mut self, cell: Point2<i32>)
+	{
+		self.visited.insert([cell.x, cell.y]);
+	}
+
+	pub fn visited(&self, cell: Point2<i32>) -> bool
+	{
+		self.visited.contains(&[cell.x, cell.y])
+	}
+
+	// There's only one boss, so a new sighting replaces the old one. Everything else is only
+	// marked once per cell.
+	pub fn mark(&mut self, pos: Point2<f32>, landmark: Landmark)
+	{
+		let pos = [pos.x, pos.y];
+		if landmark == Landmark::Boss
+		{
+			self.landmarks.retain(|(_, other)| *other != landmark);
+		}
+		else
+		{
+			self.clear(Point2::from(cell_of(pos)), landmark);
+		}
+		self.landmarks.push((pos, landmark));
+	}
+
+	// Nothing left to see in the cell, e.g. the wrecks there have sunk.
+	pub fn clear(&mut self, cell: Point2<i32>, landmark: Landmark)
+	{
+		self.landmarks.retain(|(other_pos, other)| {
+			*other != landmark || cell_of(*other_pos) != [cell.x, cell.y]
+		});
+	}
+
+	pub fn landmarks(&self) -> impl Iterator<Item = (Point2<f32>, Landmark)> + '_
+	{
+		self.landmarks
+			.iter()
+			.map(|(pos, landmark)| (Point2::from(*pos), *landmark))
+	}
+}
+
+#[test]
+fn test_exploration()
+{
+	let mut exploration = Exploration::new();
+	exploration.visit(Point2::new(1, -2));
+	assert!(exploration.visited(Point2::new(1, -2)));
+	assert!(!exploration.visited(Point2::new(0, 0)));
+
+	exploration.mark(Point2::new(0.2, 0.2), Landmark::Market);
+	exploration.mark(Point2::new(0.7, 0.7), Landmark::Market);
+	exploration.mark(Point2::new(1.5, 0.5), Landmark::Market);
+	exploration.mark(Point2::new(0.5, 0.5), Landmark::Boss);
+	exploration.mark(Point2::new(3.5, 0.5), Landmark::Boss);
+	exploration.mark(Point2::new(1.2, 0.2), Landmark::Wreck);
+	exploration.clear(Point2::new(1, 0), Landmark::Wreck);
+	assert_eq!(
+		exploration.landmarks().collect::<Vec<_>>(),
+		vec![
+			(Point2::new(0.7, 0.7), Landmark::Market),
+			(Point2::new(1.5, 0.5), Landmark::Market),
+			(Point2::new(3.5, 0.5), Landmark::Boss),
+		]
+	);
+}
@@ -0,0 +1,120 @@
+use crate::utils;
+
+use nalgebra::Vector3;
+
+// Most the camera will shake, however much is going on.
+const MAX_SHAKE: f32 = 2.;
+// Impacts further than this go unfelt.
+const FEEL_RANGE: f32 = 60.;
+// Shake per point of damage, getting hit is felt more than hitting.
+const DEALT_SHAKE: f32 = 0.005;
+const TAKEN_SHAKE: f32 = 0.015;
+// Seconds the world holds still after landing a critical.
+const HIT_STOP: f32 = 0.06;
+// How far a full broadside kicks the camera back.
+const RECOIL: f32 = 0.5;
+
+// The game-feel effects: camera shake, hit-stop and recoil. Everything gets scaled by the
+// intensity option as it comes in, so at zero none of it happens.
+pub struct Feedback
+{
+	shake: f32,
+	recoil: Vector3<f32>,
+	// Seconds of hit-stop left.
+	freeze: f32,
+}
+
+impl Feedback
+{
+	pub fn new() -> Self
+	{
+		Self {
+			shake: 0.,
+			recoil: Vector3::zeros(),
+			freeze: 0.,
+		}
+	}
+
+	// Something went off `dist` away from the player.
+	pub fn impact(&mut self, shake: f32, dist: f32, intensity: f32)
+	{
+		self.add_shake(shake * (1. - dist / FEEL_RANGE).max(0.), intensity);
+	}
+
+	// The player's ship hit something, or got hit.
+	pub fn damage(&mut self, amount: f32, taken: bool, intensity: f32)
+	{
+		let f = if taken { TAKEN_SHAKE } else { DEALT_SHAKE };
+		self.add_shake(amount * f, intensity);
+	}
+
+	pub fn hit_stop(&mut self, intensity: f32)
+	{
+		self.freeze = self.freeze.max(HIT_STOP * intensity);
+	}
+
+	// A gun went off, `frac` being its share of a full broadside.
+	pub fn recoil(&mut self, dir: Vector3<f32>, frac: f32, intensity: f32)
+	{
+		let dir = Vector3::new(dir.x, 0., dir.z);
+		if dir.magnitude() > 0.
+		{
+			self.recoil -= dir.normalize() * RECOIL * frac * intensity;
+			if self.recoil.magnitude() > RECOIL
+			{
+				self.recoil = self.recoil.normalize() * RECOIL;
+			}
+		}
+	}
+
+	fn add_shake(&mut self, shake: f32, intensity: f32)
+	{
+		self.shake = (self.shake + shake * intensity).min(MAX_SHAKE);
+	}
+
+	// Counts down the hit-stop, true while the world should stay put.
+	pub fn frozen(&mut self, dt: f32) -> bool
+	{
+		if self.freeze > 0.
+		{
+			self.freeze -= dt;
+			true
+		}
+		else
+		{
+			false
+		}
+	}
+
+	pub fn logic(&mut self, dt: f32)
+	{
+		self.shake *= 1. - utils::approach_frac(4., dt);
+		self.recoil *= 1. - utils::approach_frac(6., dt);
+	}
+
+	// Where to nudge the camera this frame.
+	pub fn camera_offset(&self, time: f64) -> Vector3<f32>
+	{
+		utils::camera_shake(self.shake, time) + self.recoil
+	}
+}
+
+#[test]
+fn test_zero_intensity()
+{
+	let mut feedback = Feedback::new();
+	feedback.impact(1., 0., 0.);
+	feedback.damage(100., true, 0.);
+	feedback.hit_stop(0.);
+	feedback.recoil(Vector3::new(1., 0., 0.), 1., 0.);
+	assert!(!feedback.frozen(0.01));
+	assert_eq!(feedback.camera_offset(1.), Vector3::zeros());
+
+	feedback.hit_stop(1.);
+	assert!(feedback.frozen(0.05));
+	assert!(feedback.frozen(0.05));
+	assert!(!feedback.frozen(0.05));
+
+	feedback.damage(1e6, true, 1.);
+	assert_eq!(feedback.shake, MAX_SHAKE);
+}
@@ -1,9 +1,13 @@
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::journal;
 use crate::utils::ColorExt;
 use crate::{
-	astar, components as comps, controls, game_state, mesh, spatial_grid, sprite, ui, utils,
+	astar, calendar, components as comps, controls, curses, damage_log, difficulty, exploration,
+	feedback, game_state, intel, mesh, message, net, packs, registry, rumors, spatial_grid, sprite,
+	systems, triggers, ui, ui_layer, ui_script, utils,
 };
 use allegro::*;
+use allegro_audio::SampleInstance;
 use allegro_font::*;
 use allegro_primitives::*;
 use gl::CULL_FACE;
@@ -16,22 +20,118 @@ use na::{
 use nalgebra as na;
 use rand::prelude::*;
 use serde_derive::{Deserialize, Serialize};
+use slr_config::ConfigElement;
 
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, IndexMut};
 
 use std::f32::consts::PI;
 
 const CELL_SIZE: i32 = 128;
 const CELL_RADIUS: i32 = 2;
+// The water pattern repeats every this many world units, and its animation every this many
+// seconds, so both can be wrapped before going to the shader without a seam.
+const WATER_WRAP: i32 = 16;
+const WATER_PERIOD: f64 = 64. * std::f64::consts::TAU;
 const SLOT_WIDTH: f32 = 64.;
 const CREW_COST: i32 = 20;
-const MESSAGE_DURATION: f32 = 10.;
+// How often a ship from an enabled content pack sails in place of a stock one.
+const PACK_SHIP_CHANCE: f64 = 0.25;
+// Turning a mount to face another side.
+const REFIT_COST: i32 = 50;
 const EQUIPMENT_FRAC: f32 = 0.6;
+// How close you need to be to see what another ship is carrying, before spyglass bonuses.
+const INSPECT_RANGE: f32 = 40.;
+const DAMAGE_INDICATOR_TIME: f64 = 1.;
+// Gap between hulls to dock, and how parallel (cosine of the heading difference) to be.
+const DOCK_DISTANCE: f32 = 2.;
+const DOCK_ALIGNMENT: f32 = 0.9;
+// How far the consort's own ship can get from where the host has it before it's moved back.
+const CONSORT_DRIFT: f32 = 5.;
+// How many cells the chart shows around the player, which is also as far as a course can go.
+const CHART_RADIUS: i32 = 7;
+// How far the chart can be panned from the player.
+const CHART_VIEW_RADIUS: i32 = 24;
+// Enemies this close interrupt autosailing.
+const CONTACT_RADIUS: f32 = 80.;
+// Wildlife.
+const GULL_CHANCE: f64 = 0.6;
+const GULL_HEIGHT: f32 = 12.;
+const GULL_SPEED: f32 = 10.;
+// How far gulls keep track of each other, and how far they stray from home.
+const GULL_SIGHT: f32 = 10.;
+const GULL_RANGE: f32 = 25.;
+const WHALE_CHANCE: f64 = 0.3;
+const WHALE_SPEED: f32 = 2.;
+const WHALE_RANGE: f32 = 50.;
+const DIVE_DEPTH: f32 = -8.;
+const LEVIATHAN_CHANCE: f64 = 0.1;
+// Leviathans only live this far north.
+const LEVIATHAN_LEVEL: i32 = 5;
+const LEVIATHAN_SPEED: f32 = 7.;
+const LEVIATHAN_SENSE: f32 = 50.;
+const LEVIATHAN_BITE_RANGE: f32 = 4.;
+const LEVIATHAN_BITE_INTERVAL: f64 = 8.;
+// Ships this size or smaller are prey for leviathans.
+const LEVIATHAN_PREY_SIZE: f32 = 2.;
+// Time to watch the ship go down before the end screen.
+const END_SCREEN_DELAY: f64 = 3.;
+const GRAVITY: f32 = 100.;
+// Guns fire from this high up, angled upwards by this much.
+const GUN_HEIGHT: f32 = 3.;
+const GUN_ELEVATION: f32 = 0.5;
+const PROJECTILE_LIFETIME: f64 = 1.;
+// With friendly fire on, shots still fly past their own side for this long.
+const PROJECTILE_GRACE: f64 = 0.3;
+const BARK_VARIANTS: i32 = 2;
+const BARK_INTERVAL: f64 = 0.5;
+const BARK_REPEAT_INTERVAL: f64 = 2.;
 const ECONOMY_INTERVAL: f64 = 30.;
-
-#[derive(Clone, Debug)]
+const ECONOMY_HISTORY: usize = 30;
+const TRADE_IMPACT: f32 = 0.03;
+const FLEET_SIZE: i32 = 4;
+const FLEET_RESPAWN_DELAY: f64 = 10.;
+const ESCORT_INTERVAL: f64 = 20.;
+const SHALLOWS_DRAFT: f32 = 4.;
+const SHALLOWS_SPEED: f32 = 0.5;
+// How much lower a wreck sits on each return, and how many returns until it's gone.
+const WRECK_SINK_DEPTH: f32 = 0.6;
+const WRECK_VISITS: i32 = 4;
+// Hull lost per second scraping along a wreck.
+const WRECK_SCRAPE_DAMAGE: f32 = 2.;
+// The part of a repair our crews manage without materials.
+const FREE_REPAIR_FRAC: f32 = 0.25;
+const VOID_WATER_RADIUS: f32 = 1.5 * CELL_SIZE as f32;
+const VOID_OMEN_RADIUS: f32 = 4. * CELL_SIZE as f32;
+// Cells south of the start where the void storms begin, and how many cells it takes them to reach
+// full strength.
+const SOUTHERN_EDGE: i32 = 2;
+const STORM_DEPTH: f32 = 2.;
+const STORM_PUSH: f32 = 6.;
+const STORM_SAIL_DAMAGE: f32 = 2.;
+const SQUALL_CHANCE: f64 = 0.25;
+const SQUALL_SAIL_DAMAGE: f32 = 1.5;
+// How far outside a squall the AI keeps.
+const SQUALL_MARGIN: f32 = 8.;
+// Heel per unit of turn rate, and from a squall's wind on the beam.
+const TURN_HEEL: f32 = 0.7;
+const SQUALL_HEEL: f32 = 0.25;
+const MAX_HEEL: f32 = 0.5;
+// Heel the gunners don't mind, and what a full heel does to spread and the low side's arcs.
+const FREE_HEEL: f32 = 0.1;
+const HEEL_SPREAD: f32 = 2.;
+const HEEL_ARC_LOSS: f32 = 0.5;
+const LIGHTHOUSE_DISCOVER_RADIUS: f32 = 60.;
+const RIVAL_FIRST_DELAY: f64 = 90.;
+const RIVAL_RETURN_DELAY: f64 = 150.;
+// The rival doesn't escape on this encounter.
+const RIVAL_FINAL_ENCOUNTER: i32 = 2;
+
+// The markets, see Markets for anything kept for each of them.
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(usize)]
-pub enum Price
+pub enum Commodity
 {
 	Weapon,
 	Goods,
@@ -40,6 +140,87 @@ pub enum Price
 	Officer,
 }
 
+impl Commodity
+{
+	const ALL: [Commodity; 5] = [
+		Commodity::Weapon,
+		Commodity::Goods,
+		Commodity::Cotton,
+		Commodity::Tobacco,
+		Commodity::Officer,
+	];
+
+	fn name(self, state: &game_state::GameState) -> String
+	{
+		let key = match self
+		{
+			Commodity::Weapon => "commodity_weapon",
+			Commodity::Goods => "commodity_goods",
+			Commodity::Cotton => "commodity_cotton",
+			Commodity::Tobacco => "commodity_tobacco",
+			Commodity::Officer => "commodity_officer",
+		};
+		state.locale.text(key, &[])
+	}
+
+	fn color(self) -> Color
+	{
+		match self
+		{
+			Commodity::Weapon => Color::from_rgb_f(0.9, 0.3, 0.3),
+			Commodity::Goods => Color::from_rgb_f(0.9, 0.8, 0.3),
+			Commodity::Cotton => Color::from_rgb_f(0.9, 0.9, 0.9),
+			Commodity::Tobacco => Color::from_rgb_f(0.6, 0.4, 0.2),
+			Commodity::Officer => Color::from_rgb_f(0.4, 0.6, 1.0),
+		}
+	}
+}
+
+// Something for each market, e.g. its price.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Markets<T>([T; Commodity::ALL.len()]);
+
+impl<T: Copy> Markets<T>
+{
+	fn new(value: T) -> Self
+	{
+		Self([value; Commodity::ALL.len()])
+	}
+
+	fn iter(&self) -> impl Iterator<Item = (Commodity, T)> + '_
+	{
+		Commodity::ALL.into_iter().zip(self.0.iter().copied())
+	}
+
+	fn values(&self) -> impl Iterator<Item = &T>
+	{
+		self.0.iter()
+	}
+
+	fn values_mut(&mut self) -> impl Iterator<Item = &mut T>
+	{
+		self.0.iter_mut()
+	}
+}
+
+impl<T> Index<Commodity> for Markets<T>
+{
+	type Output = T;
+
+	fn index(&self, commodity: Commodity) -> &T
+	{
+		&self.0[commodity as usize]
+	}
+}
+
+impl<T> IndexMut<Commodity> for Markets<T>
+{
+	fn index_mut(&mut self, commodity: Commodity) -> &mut T
+	{
+		&mut self.0[commodity as usize]
+	}
+}
+
 struct Timer
 {
 	name: &'static str,
@@ -98,96 +279,23 @@ fn frac_to_color(f: f32) -> Color
 	}
 }
 
-#[derive(Clone)]
-pub struct Button
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Biome
 {
-	loc: Point2<f32>,
-	size: Vector2<f32>,
-	sprite: String,
-	on: bool,
-	hover: bool,
-	is_toggle: bool,
+	DeepOcean,
+	Shallows,
 }
 
-impl Button
+impl Biome
 {
-	fn new(loc: Point2<f32>, size: Vector2<f32>, is_toggle: bool, sprite: String) -> Self
-	{
-		Self {
-			loc: loc,
-			size: size,
-			sprite: sprite,
-			on: false,
-			hover: false,
-			is_toggle: is_toggle,
-		}
-	}
-
-	fn logic(&mut self) -> bool
-	{
-		let old_on = self.on;
-		if !self.is_toggle
-		{
-			self.on = false;
-		}
-		old_on
-	}
-
-	fn draw(&self, state: &game_state::GameState)
-	{
-		let variant = if self.on
-		{
-			2
-		}
-		else if self.hover
-		{
-			1
-		}
-		else
-		{
-			0
-		};
-		state.get_sprite(&self.sprite).unwrap().draw(
-			self.loc,
-			variant,
-			Color::from_rgb_f(1., 1., 1.),
-			state,
-		);
-	}
-
-	fn input(&mut self, event: &Event) -> bool
+	// RGB albedo + wave strength.
+	fn water_params(&self) -> [f32; 4]
 	{
-		let start = self.loc - self.size / 2.;
-		let end = self.loc + self.size / 2.;
-		let mut handled = false;
-		match event
+		match self
 		{
-			Event::MouseButtonDown {
-				button: 1, x, y, ..
-			} =>
-			{
-				let (x, y) = (*x as f32, *y as f32);
-				if x > start.x && x < end.x && y < end.y && y > start.y
-				{
-					if self.is_toggle
-					{
-						self.on = !self.on;
-					}
-					else
-					{
-						self.on = true;
-					}
-					handled = true;
-				}
-			}
-			Event::MouseAxes { x, y, .. } =>
-			{
-				let (x, y) = (*x as f32, *y as f32);
-				self.hover = x > start.x && x < end.x && y < end.y && y > start.y;
-			}
-			_ => (),
+			Biome::DeepOcean => [0.1, 0.1, 0.8, 1.],
+			Biome::Shallows => [0.2, 0.55, 0.7, 0.4],
 		}
-		handled
 	}
 }
 
@@ -195,12 +303,15 @@ impl Button
 pub struct Cell
 {
 	center: Point2<i32>,
+	biome: Biome,
 }
 
 impl Cell
 {
+	// Enemies get level_shift on top of the cell's level.
 	fn new<R: Rng>(
-		center: Point2<i32>, level: i32, rng: &mut R, world: &mut hecs::World,
+		center: Point2<i32>, level: i32, level_shift: i32, season: calendar::Season,
+		terrain_seed: u64, populate: bool, rng: &mut R, world: &mut hecs::World,
 		state: &mut game_state::GameState,
 	) -> Result<Self>
 	{
@@ -210,7 +321,28 @@ impl Cell
 
 		let w = CELL_SIZE as f32 / 2. - 10.;
 
-		let num_enemies = if center == Point2::origin() { 0 } else { 1 };
+		// The terrain is the same every time the cell is visited.
+		let mut terrain_rng = StdRng::seed_from_u64(terrain_seed);
+
+		let biome = Self::roll_biome(center == Point2::origin(), level, &mut terrain_rng);
+
+		let mut lighthouse_pos = None;
+		if biome == Biome::Shallows && terrain_rng.gen_bool(0.5)
+		{
+			let dx = world_center.x + terrain_rng.gen_range(-w..w);
+			let dy = world_center.z + terrain_rng.gen_range(-w..w);
+			make_lighthouse(Point3::new(dx, 0., dy), terrain_seed, world, state)?;
+			lighthouse_pos = Some(Point3::new(dx, 0., dy));
+		}
+
+		let num_enemies = if center == Point2::origin() || !populate
+		{
+			0
+		}
+		else
+		{
+			1
+		};
 
 		for _ in 0..num_enemies
 		{
@@ -245,21 +377,34 @@ impl Cell
 					dy
 				);
 			}
+			let pack_ships = packs::enabled_ships(state, team);
+			let mut ship_desc = [
+				"data/small_ship.cfg",
+				"data/medium_ship.cfg",
+				"data/big_ship.cfg",
+			][idx];
+			if !pack_ships.is_empty() && rng.gen_bool(PACK_SHIP_CHANCE)
+			{
+				// Checked when the packs were scanned, but something could still go wrong loading
+				// it for real. One of ours takes her place then.
+				let pack_ship = pack_ships.choose(rng).unwrap();
+				match load_ship_desc(pack_ship, state)
+				{
+					Ok(_) => ship_desc = pack_ship,
+					Err(e) => println!("Skipping {pack_ship}: {e}"),
+				}
+			}
 			let ship = make_ship(
 				ship_pos,
-				[
-					"data/small_ship.cfg",
-					"data/medium_ship.cfg",
-					"data/big_ship.cfg",
-				][idx],
+				ship_desc,
 				team,
 				if team == comps::Team::Pirate
 				{
-					level + 3
+					level + level_shift + 3
 				}
 				else
 				{
-					level
+					level + level_shift
 				},
 				rng,
 				world,
@@ -277,6 +422,89 @@ impl Cell
 			//world.get::<&mut comps::ShipState>(ship).unwrap().hull = 1.;
 		}
 
+		// Fishing boats and traders, who keep out of fights.
+		let num_civilians = if center == Point2::origin() || !populate
+		{
+			0
+		}
+		else
+		{
+			rng.gen_range(0..=1)
+		};
+
+		for _ in 0..num_civilians
+		{
+			let dx = world_center.x + rng.gen_range(-w..w);
+			let dy = world_center.z + rng.gen_range(-w..w);
+			let ship_desc = *["data/fishing_boat.cfg", "data/trader.cfg"]
+				.choose(rng)
+				.unwrap();
+			let team = comps::Team::Civilian;
+			let ship = make_ship(
+				Point3::new(dx, 0., dy),
+				ship_desc,
+				team,
+				level,
+				rng,
+				world,
+				state,
+			)?;
+			world.insert_one(
+				ship,
+				comps::AI {
+					state: comps::AIState::Idle,
+					name: comps::generate_captain_name(team, rng),
+				},
+			)?;
+		}
+
+		// Wildlife. Gulls gather where there's land, the rest keep to the deep.
+		if center != Point2::origin() && populate
+		{
+			let random_pos = |rng: &mut R| {
+				world_center + Vector3::new(rng.gen_range(-w..w), 0., rng.gen_range(-w..w))
+			};
+			if biome == Biome::Shallows && rng.gen_bool(GULL_CHANCE)
+			{
+				let home = lighthouse_pos.unwrap_or_else(|| random_pos(rng))
+					+ Vector3::new(0., GULL_HEIGHT, 0.);
+				for _ in 0..rng.gen_range(4..=8)
+				{
+					let offt = Vector3::new(rng.gen_range(-5.0..5.), 0., rng.gen_range(-5.0..5.));
+					make_creature(comps::CreatureKind::Gull, home + offt, rng, world, state)?;
+				}
+			}
+			if biome == Biome::DeepOcean && rng.gen_bool(WHALE_CHANCE)
+			{
+				let pos = random_pos(rng);
+				make_creature(comps::CreatureKind::Whale, pos, rng, world, state)?;
+			}
+			if biome == Biome::DeepOcean
+				&& level >= LEVIATHAN_LEVEL
+				&& rng.gen_bool(LEVIATHAN_CHANCE)
+			{
+				let pos = random_pos(rng);
+				make_creature(comps::CreatureKind::Leviathan, pos, rng, world, state)?;
+			}
+		}
+
+		if center != Point2::origin()
+			&& populate
+			&& rng.gen_bool((SQUALL_CHANCE * season.storms()).min(1.))
+		{
+			let dx = world_center.x + rng.gen_range(-w..w);
+			let dy = world_center.z + rng.gen_range(-w..w);
+			let dir = rng.gen_range(0.0..PI * 2.0);
+			let vel = Vector3::new(dir.cos(), 0., dir.sin()) * rng.gen_range(1.0..4.0);
+			make_squall(
+				Point3::new(dx, 0., dy),
+				vel,
+				rng.gen_range(15.0..30.),
+				world,
+				state,
+			);
+		}
+
 		//for _ in 0..2
 		//{
 		//	let dx = world_center.x + rng.gen_range(-w..w);
@@ -288,7 +516,10 @@ impl Cell
 		//	make_wisp(Point3::new(dx, 0., dy), vel, world, state)?;
 		//}
 
-		Ok(Self { center: center })
+		Ok(Self {
+			center: center,
+			biome: biome,
+		})
 	}
 
 	pub fn world_center(&self) -> Point3<f32>
@@ -296,11 +527,50 @@ impl Cell
 		Self::cell_to_world(self.center)
 	}
 
+	// Shallow banks are more common in the calmer southern waters.
+	fn roll_biome(start: bool, level: i32, terrain_rng: &mut StdRng) -> Biome
+	{
+		if !start && terrain_rng.gen_bool((0.4 / level as f64).max(0.1))
+		{
+			Biome::Shallows
+		}
+		else
+		{
+			Biome::DeepOcean
+		}
+	}
+
+	// The terrain only depends on the seed, so cells can be charted before they're visited.
+	fn chart_biome(terrain_seed: u64, global_center: Point2<i32>) -> Biome
+	{
+		let mut terrain_rng = StdRng::seed_from_u64(cell_seed(terrain_seed, global_center));
+		Self::roll_biome(
+			global_center == Point2::origin(),
+			Self::latitude_level(global_center.y),
+			&mut terrain_rng,
+		)
+	}
+
 	pub fn cell_to_world(pos: Point2<i32>) -> Point3<f32>
 	{
 		Point3::new((pos.x * CELL_SIZE) as f32, 0., (pos.y * CELL_SIZE) as f32)
 	}
 
+	// Levels go up the further north the cell is, and bottom out at the start.
+	pub fn latitude_level(global_y: i32) -> i32
+	{
+		(-global_y).max(1)
+	}
+
+	// How hard the void storms blow at a position, from 0 to 1. They make up the southern edge of
+	// the world.
+	pub fn southern_storm(pos: &Point3<f32>, global_offset: Vector2<i32>) -> f32
+	{
+		let global_z = pos.z + (global_offset.y * CELL_SIZE) as f32;
+		let edge = (SOUTHERN_EDGE * CELL_SIZE) as f32;
+		utils::clamp((global_z - edge) / (STORM_DEPTH * CELL_SIZE as f32), 0., 1.)
+	}
+
 	pub fn world_to_cell(pos: &Point3<f32>) -> Point2<i32>
 	{
 		let sz = CELL_SIZE as f32;
@@ -315,12 +585,89 @@ impl Cell
 	}
 }
 
+// A splitmix64 step. Seeds have to come out the same on every toolchain, which DefaultHasher
+// doesn't promise.
+fn mix_seed(seed: u64, value: u64) -> u64
+{
+	let mut z = (seed ^ value).wrapping_add(0x9e3779b97f4a7c15);
+	z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+	z ^ (z >> 31)
+}
+
+// Each subsystem gets its own stream, so e.g. generating a different amount of loot doesn't change
+// the terrain.
+fn stream_rng(seed: u64, stream: &str) -> StdRng
+{
+	StdRng::seed_from_u64(
+		stream
+			.bytes()
+			.fold(seed, |seed, b| mix_seed(seed, b as u64)),
+	)
+}
+
+fn cell_seed(seed: u64, global_center: Point2<i32>) -> u64
+{
+	let seed = mix_seed(seed, global_center.x as u32 as u64);
+	mix_seed(seed, global_center.y as u32 as u64)
+}
+
+fn biome_at(cells: &[Cell], pos: &Point3<f32>) -> Biome
+{
+	cells
+		.iter()
+		.find(|c| c.contains(pos))
+		.map(|c| c.biome)
+		.unwrap_or(Biome::DeepOcean)
+}
+
+// In the same order as the parts in the repair logic.
+const REPAIR_PARTS: [(&str, &str, controls::Action); 7] = [
+	(
+		"Fore Armor",
+		"data/repair_armor.cfg",
+		controls::Action::RepairFore,
+	),
+	(
+		"Starboard Armor",
+		"data/repair_armor.cfg",
+		controls::Action::RepairStarboard,
+	),
+	(
+		"Aft Armor",
+		"data/repair_armor.cfg",
+		controls::Action::RepairAft,
+	),
+	(
+		"Port Armor",
+		"data/repair_armor.cfg",
+		controls::Action::RepairPort,
+	),
+	("Hull", "data/repair_hull.cfg", controls::Action::RepairHull),
+	(
+		"Infirmary",
+		"data/repair_infirmary.cfg",
+		controls::Action::RepairInfirmary,
+	),
+	(
+		"Sails",
+		"data/repair_sails.cfg",
+		controls::Action::RepairSails,
+	),
+];
+
+const MAX_REPAIR_BOOSTS: usize = 2;
+
 struct HUD
 {
 	buffer_height: f32,
 	buffer_width: f32,
-	buttons: Vec<Button>,
+	buttons: Vec<ui::Button>,
 	toggled: Vec<usize>,
+	player_status: ui_layer::UiLayer<Vec<f32>>,
+	target_status: ui_layer::UiLayer<Vec<f32>>,
+	// Top bar, formation widget and the repair toggles.
+	chrome: ui_layer::BitmapLayer,
 }
 
 impl HUD
@@ -331,66 +678,74 @@ impl HUD
 		// Sigh... why does it always end up like this? Despicable, regrettable garbage...
 		let (dw, dh) = (state.display_width, state.display_height);
 		let m = state.m;
-		let (x, mut y) = (dw - m * 6., dh - m * 9.);
+		let (x, y) = (dw - m * 6., dh - m * 9.);
 
 		let size = Vector2::new(m * 2., m);
 
-		let sprite_name = "data/repair.cfg".to_string();
-
-		for i in 0..4
+		for (i, (_, sprite, _)) in REPAIR_PARTS.iter().enumerate()
 		{
-			let theta = -PI / 2. + i as f32 * PI / 2.;
+			let pos = if i < 4
+			{
+				// The armor goes around the ship.
+				let theta = -PI / 2. + i as f32 * PI / 2.;
 
-			let r = m * 1.5;
-			let cx = x;
+				let r = m * 1.5;
+				let cx = x;
 
-			let lx = cx + (m * 1.5 + r) * theta.cos();
-			let ly = y + (m + r) * theta.sin();
+				let lx = cx + (m * 1.5 + r) * theta.cos();
+				let ly = y + (m + r) * theta.sin();
 
-			let offt = if i == 3 { -m * 2. } else { m * 2. };
-			buttons.push(Button::new(
-				Point2::new(lx + offt, ly),
-				size,
-				true,
-				sprite_name.clone(),
-			));
+				let offt = if i == 3 { -m * 2. } else { m * 2. };
+				Point2::new(lx + offt, ly)
+			}
+			else
+			{
+				// The rest go in a column below, with a gap after the hull.
+				let row = if i == 4 { 0. } else { i as f32 - 3. };
+				Point2::new(x - m * 5., y + m * 4. + row * m)
+			};
+			buttons.push(ui::Button::new_sprite(pos, size, true, sprite));
 		}
 
-		y += m * 4.;
-
-		let h = m;
-		let x = x - m * 5.;
-
-		buttons.push(Button::new(
-			Point2::new(x, y),
-			size,
-			true,
-			sprite_name.clone(),
-		));
-		y += h;
-		y += h;
-		buttons.push(Button::new(
-			Point2::new(x, y),
-			size,
-			true,
-			sprite_name.clone(),
-		));
-		y += h;
-		buttons.push(Button::new(
-			Point2::new(x, y),
-			size,
-			true,
-			sprite_name.clone(),
-		));
-
 		Self {
 			buffer_width: dw,
 			buffer_height: dh,
 			buttons: buttons,
 			toggled: vec![],
+			player_status: ui_layer::UiLayer::new(),
+			target_status: ui_layer::UiLayer::new(),
+			chrome: ui_layer::BitmapLayer::new(),
 		}
 	}
 
+	// The deck as seen from astern, red once the gunners start to struggle.
+	fn draw_heel(&self, heel: f32, x: f32, y: f32, state: &game_state::GameState)
+	{
+		let m = state.m;
+		let color = if heel.abs() > FREE_HEEL
+		{
+			Color::from_rgb_f(0.9, 0.3, 0.2)
+		}
+		else
+		{
+			ui::ui_color()
+		};
+		let (s, c) = heel.sin_cos();
+		let (w, h) = (m * 1.5, m * 2.);
+		state
+			.prim
+			.draw_line(x - w * c, y + w * s, x + w * c, y - w * s, color, 3.);
+		state.prim.draw_line(x, y, x - h * s, y - h * c, color, 2.);
+		state.core.draw_text(
+			&state.ui_font,
+			color,
+			x - w - m,
+			y - state.ui_font.get_line_height() as f32 / 2.,
+			FontAlign::Right,
+			&format!("Heel: {:.0}°", heel.abs().to_degrees()),
+		);
+	}
+
 	fn status_pos(&self, idx: i32, m: f32) -> Point2<f32>
 	{
 		let (dw, dh) = (self.buffer_width, self.buffer_height);
@@ -416,53 +771,684 @@ impl HUD
 			|| (mouse_pos.x > status_pos_1.x - w && mouse_pos.y > status_pos_1.y - h)
 	}
 
-	fn input(&mut self, event: &Event, _map: &mut Map, state: &mut game_state::GameState) -> bool
+	// The parts of the HUD that only change when the numbers on them do.
+	fn draw_chrome(&self, map: &Map, keys: &[String], state: &game_state::GameState)
 	{
-		let mut over_ui = false;
-		for (i, button) in &mut self.buttons.iter_mut().enumerate()
-		{
-			let old_on = button.on;
-			over_ui |= button.input(event);
-			if !old_on && button.on
+		let ui_color = ui::ui_color();
+		let (dw, m) = (self.buffer_width, state.m);
+		let lh = state.ui_font.get_line_height() as f32;
+		state.core.draw_text(
+			&state.ui_font,
+			ui_color,
+			dw / 2.0,
+			16.,
+			FontAlign::Centre,
+			&if map.spectator
 			{
-				self.toggled.push(i);
+				"Spectating".to_string()
 			}
+			else
+			{
+				match map.outcome
+				{
+					Outcome::InProgress => format!("Money: £{}", map.money),
+					Outcome::Victory { .. } => "The Voidwind is no more!".to_string(),
+					Outcome::Defeat { .. } =>
+					{
+						"Your ship has been disabled. Spectating...".to_string()
+					}
+				}
+			},
+		);
+		if !map.spectator && !state.curses.is_empty()
+		{
+			let names: Vec<_> = state.curses.iter().map(|c| c.name()).collect();
+			state.core.draw_text(
+				&state.ui_font,
+				Color::from_rgb_f(0.7, 0.4, 0.8),
+				dw / 2.0,
+				16. + lh,
+				FontAlign::Centre,
+				&format!("Cursed: {}", names.join(", ")),
+			);
 		}
-		self.toggled.retain(|i| self.buttons[*i].on);
-		//self.over_ui(state)
-		over_ui
-	}
 
-	fn logic(&mut self, map: &mut Map, _state: &game_state::GameState)
-	{
-		if let Ok(mut ship_state) = map.world.get::<&mut comps::ShipState>(map.player)
+		if !map.spectator && map.has_fleet()
 		{
-			let max_repair = 2;
-			if self.toggled.len() > max_repair as usize
+			let current = map.station_formation();
+			state.core.draw_text(
+				&state.ui_font,
+				ui_color,
+				m,
+				16.,
+				FontAlign::Left,
+				"Formation: ",
+			);
+			for (formation, r) in self.formation_rects(state)
 			{
-				for i in &self.toggled[..(self.toggled.len() - max_repair as usize)]
+				if formation == current
 				{
-					self.buttons[*i].on = false;
+					state
+						.prim
+						.draw_filled_rectangle(r[0], r[1], r[2], r[3], ui::backdrop(0.6));
 				}
-				self.toggled
-					.drain(..(self.toggled.len() - max_repair as usize));
+				state.prim.draw_rectangle(
+					r[0],
+					r[1],
+					r[2],
+					r[3],
+					ui_color.interpolate(
+						Color::from_rgba_f(0., 0., 0., 0.),
+						if formation == current { 0. } else { 0.6 },
+					),
+					2.,
+				);
+				state.core.draw_text(
+					&state.ui_font,
+					ui_color,
+					(r[0] + r[2]) / 2.,
+					16.,
+					FontAlign::Centre,
+					formation_name(formation),
+				);
 			}
-			ship_state.repair_boost.clone_from(&self.toggled);
 		}
-	}
 
-	fn draw(&self, map: &Map, state: &game_state::GameState)
-	{
+		if !map.spectator
+		{
+			self.draw_latitude_chart(map, state);
+		}
+
+		if !map.spectator
+		{
+			for (i, (toggle, key)) in self.buttons.iter().zip(keys).enumerate()
+			{
+				toggle.draw(state);
+				// Put the key on whichever side isn't covered by the status text.
+				let (kx, align) = if i < 3
+				{
+					(toggle.loc.x + toggle.size.x / 2., FontAlign::Left)
+				}
+				else
+				{
+					(toggle.loc.x - toggle.size.x / 2., FontAlign::Right)
+				};
+				state.core.draw_text(
+					&state.ui_font,
+					ui_color,
+					kx,
+					toggle.loc.y - lh / 2.,
+					align,
+					key,
+				);
+			}
+		}
+	}
+
+	// Nearby rows of cells and their levels, with the player's place among them.
+	fn draw_latitude_chart(&self, map: &Map, state: &game_state::GameState)
+	{
+		let ui_color = ui::ui_color();
+		let (dw, m) = (self.buffer_width, state.m);
+		let lh = state.ui_font.get_line_height() as f32;
+		let player_y = map.player_latitude();
+		let x = dw - m;
+		let mut y = 16.;
+		state
+			.core
+			.draw_text(&state.ui_font, ui_color, x, y, FontAlign::Right, "North");
+		y += lh * 1.5;
+		for global_y in player_y - 3..=player_y + 3
+		{
+			let storm = Cell::southern_storm(
+				&Cell::cell_to_world(Point2::new(0, global_y)),
+				Vector2::new(0, 0),
+			);
+			if storm > 0.
+			{
+				state.prim.draw_filled_rectangle(
+					x - m * 5.,
+					y,
+					x,
+					y + lh,
+					Color::from_rgba_f(0.25 * storm, 0.05 * storm, 0.35 * storm, 0.7 * storm),
+				);
+			}
+			let color = if global_y == player_y
+			{
+				Color::from_rgb_f(1., 1., 1.)
+			}
+			else
+			{
+				ui_color.interpolate(Color::from_rgba_f(0., 0., 0., 0.), 0.4)
+			};
+			state.core.draw_text(
+				&state.ui_font,
+				color,
+				x,
+				y,
+				FontAlign::Right,
+				&format!("Level {}", Cell::latitude_level(global_y)),
+			);
+			if global_y == player_y
+			{
+				let s = lh / 3.;
+				let (tx, ty) = (x - m * 5.5, y + lh / 2.);
+				state.prim.draw_filled_triangle(
+					tx - s,
+					ty - s,
+					tx - s,
+					ty + s,
+					tx + s / 2.,
+					ty,
+					color,
+				);
+			}
+			y += lh;
+		}
+		y += lh / 2.;
+		state
+			.core
+			.draw_text(&state.ui_font, ui_color, x, y, FontAlign::Right, "South");
+	}
+
+	// The formation widget, with None standing for letting the fleet sail freely.
+	fn formation_rects(&self, state: &game_state::GameState) -> Vec<(Option<Formation>, [f32; 4])>
+	{
+		let m = state.m;
+		let lh = state.ui_font.get_line_height() as f32;
+		let mut x = m + state.ui_font.get_text_width("Formation: ") as f32;
+		let y = 16.;
+		[
+			Some(Formation::Column),
+			Some(Formation::Line),
+			Some(Formation::Wedge),
+			None,
+		]
+		.into_iter()
+		.map(|formation| {
+			let w = state.ui_font.get_text_width(formation_name(formation)) as f32 + m;
+			let rect = [x, y - lh / 4., x + w, y + lh * 1.25];
+			x += w + m / 2.;
+			(formation, rect)
+		})
+		.collect()
+	}
+
+	// Only shown while spectating after the player's ship was disabled.
+	fn concede_rect(&self, state: &game_state::GameState) -> [f32; 4]
+	{
+		let lh = state.ui_font.get_line_height() as f32;
+		let w = state.ui_font.get_text_width("Concede") as f32 + 2. * state.m;
+		let (x, y) = (self.buffer_width / 2., 16. + lh * 2.5);
+		[x - w / 2., y, x + w / 2., y + lh * 1.5]
+	}
+
+	fn toast_pos(&self) -> Point2<f32>
+	{
+		Point2::new(self.buffer_width / 2., self.buffer_height * 0.35)
+	}
+
+	// Screen rectangles of the visible messages, along with their index in the log.
+	fn message_rects(&self, map: &Map, state: &game_state::GameState) -> Vec<(usize, [f32; 4])>
+	{
+		let (dw, dh) = (self.buffer_width, self.buffer_height);
+		let lh = state.ui_font.get_line_height() as f32;
+		let visible: Vec<_> = map
+			.messages
+			.visible(&state.options.muted_messages)
+			.collect();
+		let num_messages = visible.len();
+		visible
+			.iter()
+			.enumerate()
+			.map(|(i, (idx, message))| {
+				let y = dh / 4.0 - i as f32 * lh * 1.5 + num_messages as f32 * lh * 1.5;
+				let w = state.ui_font.get_text_width(&message.text) as f32;
+				(
+					*idx,
+					[dw / 2. - w / 2. - lh * 1.5, y, dw / 2. + w / 2., y + lh],
+				)
+			})
+			.collect()
+	}
+
+	fn input(&mut self, event: &Event, map: &mut Map, state: &mut game_state::GameState) -> bool
+	{
+		if let Event::MouseButtonDown {
+			button: 1, x, y, ..
+		} = *event
+		{
+			let (x, y) = (x as f32, y as f32);
+			let inside = |r: &[f32; 4]| x > r[0] && x < r[2] && y > r[1] && y < r[3];
+			if !map.spectator && map.has_fleet()
+			{
+				for (formation, rect) in self.formation_rects(state)
+				{
+					if inside(&rect)
+					{
+						state.sfx.play_sound("data/order.ogg").unwrap();
+						map.set_formation(formation, state);
+						return true;
+					}
+				}
+			}
+			if map.defeated() && inside(&self.concede_rect(state))
+			{
+				state.sfx.play_sound("data/ui2.ogg").unwrap();
+				map.conceded = true;
+				return true;
+			}
+			let toast_pos = self.toast_pos();
+			if map
+				.messages
+				.toast_rect(toast_pos.x, toast_pos.y, state)
+				.map(|r| inside(&r))
+				.unwrap_or(false)
+			{
+				map.messages.dismiss_toast(state.time());
+				return true;
+			}
+			for (idx, rect) in self.message_rects(map, state)
+			{
+				if inside(&rect)
+				{
+					map.messages.dismiss(idx);
+					return true;
+				}
+			}
+			if !map.spectator
+			{
+				for (idx, rect) in self.weapon_rects(map, state)
+				{
+					if inside(&rect)
+					{
+						if let Ok(mut equipment) =
+							map.world.get::<&mut comps::Equipment>(map.player)
+						{
+							let slot = &mut equipment.slots[idx];
+							slot.disabled = !slot.disabled;
+						}
+						state.sfx.play_sound("data/ui2.ogg").unwrap();
+						return true;
+					}
+				}
+			}
+		}
+		let mut over_ui = false;
+		for (i, button) in &mut self.buttons.iter_mut().enumerate()
+		{
+			let old_on = button.on;
+			// Hovering doesn't count, only clicks.
+			let action = button.input(state, event);
+			over_ui |= action.is_some() && !matches!(event, Event::MouseAxes { .. });
+			if !old_on && button.on
+			{
+				self.toggled.push(i);
+			}
+		}
+		self.toggled.retain(|i| self.buttons[*i].on);
+		//self.over_ui(state)
+		over_ui
+	}
+
+	// The gun readouts along the bottom, by equipment slot. Clicking one stands it down.
+	fn weapon_rects(&self, map: &Map, state: &game_state::GameState) -> Vec<(usize, [f32; 4])>
+	{
+		let (dw, dh) = (self.buffer_width, self.buffer_height);
+		let slots: Vec<_> = map
+			.world
+			.get::<&comps::Equipment>(map.player)
+			.map(|equipment| {
+				equipment
+					.slots
+					.iter()
+					.enumerate()
+					.filter(|(_, slot)| {
+						!slot.is_inventory
+							&& matches!(
+								slot.item.as_ref().map(|item| &item.kind),
+								Some(comps::ItemKind::Weapon(_))
+							)
+					})
+					.map(|(i, _)| i)
+					.collect()
+			})
+			.unwrap_or_default();
+		let w = state.m * 3.;
+		let offt = slots.len() as f32 * w / 2.;
+		slots
+			.into_iter()
+			.enumerate()
+			.map(|(j, i)| {
+				let x = j as f32 * w - offt + dw / 2.;
+				(i, [x, dh - 2. * w, x + w, dh])
+			})
+			.collect()
+	}
+
+	fn logic(&mut self, map: &mut Map, state: &mut game_state::GameState)
+	{
+		if !map.spectator
+		{
+			for (i, (_, _, action)) in REPAIR_PARTS.iter().enumerate()
+			{
+				if state.controls.get_action_state(*action) > 0.5
+				{
+					state.controls.clear_action_state(*action);
+					let button = &mut self.buttons[i];
+					button.on = !button.on;
+					if button.on
+					{
+						self.toggled.push(i);
+					}
+					else
+					{
+						self.toggled.retain(|j| *j != i);
+					}
+				}
+			}
+		}
+		if let Ok(mut ship_state) = map.world.get::<&mut comps::ShipState>(map.player)
+		{
+			if self.toggled.len() > MAX_REPAIR_BOOSTS
+			{
+				for i in &self.toggled[..(self.toggled.len() - MAX_REPAIR_BOOSTS)]
+				{
+					self.buttons[*i].on = false;
+				}
+				self.toggled
+					.drain(..(self.toggled.len() - MAX_REPAIR_BOOSTS));
+			}
+			ship_state.repair_boost.clone_from(&self.toggled);
+		}
+	}
+
+	fn draw(&self, map: &Map, state: &game_state::GameState)
+	{
 		let ui_color = ui::ui_color();
 		let (dw, dh) = (self.buffer_width, self.buffer_height);
 		let m = state.m;
 
+		// Discovered lighthouses, clamped to the screen edge when out of view.
+		let global_origin = Cell::cell_to_world(Point2::origin() + map.global_offset);
+		for global_pos in map.discovered_lighthouses.values()
+		{
+			let pos = map.world_to_screen(global_pos - global_origin.coords);
+			let border = m * 2.;
+			let x = pos.x.max(border).min(dw - border);
+			let y = pos.y.max(border).min(dh - border);
+			let s = m / 2.;
+			let color = Color::from_rgba_f(0.9, 0.8, 0.4, 0.75);
+			state
+				.prim
+				.draw_filled_triangle(x - s, y - 2. * s, x + s, y - 2. * s, x, y, color);
+			if x != pos.x || y != pos.y
+			{
+				state.prim.draw_circle(x, y - s, s * 1.5, color, 2.);
+			}
+		}
+
+		// Sightings heard about at the docks, fading as the news gets stale.
+		for (global_pos, f) in map.rumors.markers(state.time())
+		{
+			let pos = map.world_to_screen(global_pos - global_origin.coords);
+			let border = m * 2.;
+			let x = pos.x.max(border).min(dw - border);
+			let y = pos.y.max(border).min(dh - border);
+			let s = m / 2.;
+			let color = Color::from_rgba_f(0.6 * f, 0.9 * f, 0.6 * f, 0.75 * f);
+			state.prim.draw_circle(x, y, s, color, 2.);
+			state
+				.prim
+				.draw_line(x - s * 1.5, y, x + s * 1.5, y, color, 2.);
+			state
+				.prim
+				.draw_line(x, y - s * 1.5, x, y + s * 1.5, color, 2.);
+		}
+
+		// Splash rings, spreading and fading.
+		for (_, (pos, splash)) in map
+			.world
+			.query::<(&comps::Position, &comps::Splash)>()
+			.iter()
+		{
+			let f = ((state.time() - splash.start_time) as f32 / comps::SPLASH_DURATION).min(1.);
+			let r = splash.radius * (0.3 + 0.7 * f);
+			let color = Color::from_rgba_f(0.8, 0.9, 1., 0.6 * (1. - f));
+			let num_segments = 16;
+			let points: Vec<_> = (0..=num_segments)
+				.map(|i| {
+					let theta = 2. * PI * i as f32 / num_segments as f32;
+					map.world_to_screen(pos.pos + r * Vector3::new(theta.cos(), 0., theta.sin()))
+				})
+				.collect();
+			for (p1, p2) in points.iter().zip(points.iter().skip(1))
+			{
+				state.prim.draw_line(p1.x, p1.y, p2.x, p2.y, color, 2.);
+			}
+		}
+
+		// Squalls, as dark patches on the sea.
+		for (_, (pos, squall)) in map
+			.world
+			.query::<(&comps::Position, &comps::Squall)>()
+			.iter()
+		{
+			let num_segments = 32;
+			for (r, alpha) in [(squall.radius, 0.25), (0.7 * squall.radius, 0.2)]
+			{
+				let color = Color::from_rgba_f(0., 0., 0.02, alpha);
+				let center = map.world_to_screen(pos.pos);
+				let points: Vec<_> = (0..=num_segments)
+					.map(|i| {
+						let theta = 2. * PI * i as f32 / num_segments as f32;
+						map.world_to_screen(
+							pos.pos + r * Vector3::new(theta.cos(), 0., theta.sin()),
+						)
+					})
+					.collect();
+				for (p1, p2) in points.iter().zip(points.iter().skip(1))
+				{
+					state
+						.prim
+						.draw_filled_triangle(center.x, center.y, p1.x, p1.y, p2.x, p2.y, color);
+				}
+			}
+		}
+
+		// A rough bearing towards the Voidwind, once its omens are felt.
+		if let Some(boss_pos) = map
+			.boss
+			.filter(|_| map.void_omen > 0.1)
+			.and_then(|boss| map.world.get::<&comps::Position>(boss).ok())
+			.map(|pos| pos.pos)
+		{
+			let pos = map.world_to_screen(boss_pos);
+			let border = m * 2.;
+			let x = pos.x.max(border).min(dw - border);
+			let y = pos.y.max(border).min(dh - border);
+			let f = map.void_omen;
+			let pulse = 1. + 0.2 * (3. * state.time() as f32).sin();
+			let color = Color::from_rgba_f(0.5 * f, 0.2 * f, 0.8 * f, 0.8 * f);
+			state.prim.draw_circle(x, y, m * pulse, color, 2.);
+			state.prim.draw_circle(x, y, m * pulse / 3., color, 2.);
+		}
+
+		// Where the player is getting hit from.
+		if let Ok(pos) = map.world.get::<&comps::Position>(map.player)
+		{
+			let center = map.world_to_screen(pos.pos);
+			let radius = 0.45 * dw.min(dh);
+			for (dir, time) in &map.damage_indicators
+			{
+				let towards = map.world_to_screen(pos.pos + Vector3::new(dir.x, 0., dir.y));
+				let screen_dir = towards - center;
+				if screen_dir.norm() < 1e-3
+				{
+					continue;
+				}
+				let theta = screen_dir.y.atan2(screen_dir.x);
+				let f = 1. - ((state.time() - time) / DAMAGE_INDICATOR_TIME) as f32;
+				let color = Color::from_rgba_f(0.8 * f, 0.1 * f, 0.1 * f, 0.8 * f);
+				let span = PI / 6.;
+				state.prim.draw_arc(
+					center.x,
+					center.y,
+					radius,
+					theta - span / 2.,
+					span,
+					color,
+					m / 2.,
+				);
+			}
+		}
+
+		// Orders.
+		if let (Ok(pos), Ok(target)) = (
+			map.world.get::<&comps::Position>(map.player),
+			map.world.get::<&comps::Target>(map.player),
+		)
+		{
+			let color = if target.attack_en_route
+			{
+				Color::from_rgba_f(0.5, 0.1, 0.1, 0.5)
+			}
+			else
+			{
+				Color::from_rgba_f(0.3, 0.4, 0.45, 0.5)
+			};
+			let mut points = vec![map.world_to_screen(pos.pos)];
+			for waypoint in &target.waypoints
+			{
+				points.push(map.world_to_screen(waypoint.pos));
+			}
+			if target.patrol && target.waypoints.len() > 1
+			{
+				points.push(points[1]);
+			}
+			for (i, (p1, p2)) in points.iter().zip(points.iter().skip(1)).enumerate()
+			{
+				// Patrol loops are drawn dashed.
+				if target.patrol && i > 0
+				{
+					let diff = p2 - p1;
+					let num_dashes = (diff.magnitude() / (m / 2.)).ceil() as i32;
+					for j in (0..num_dashes).step_by(2)
+					{
+						let d1 = p1 + diff * j as f32 / num_dashes as f32;
+						let d2 = p1 + diff * (j + 1) as f32 / num_dashes as f32;
+						state.prim.draw_line(d1.x, d1.y, d2.x, d2.y, color, 3.);
+					}
+				}
+				else
+				{
+					state.prim.draw_line(p1.x, p1.y, p2.x, p2.y, color, 2.);
+				}
+			}
+		}
+
+		// How far the guns hit full force, and where they're spent.
+		if let (true, Ok(pos), Ok(equipment)) = (
+			map.show_range_ring,
+			map.world.get::<&comps::Position>(map.player),
+			map.world.get::<&comps::Equipment>(map.player),
+		)
+		{
+			let range = equipment.weapon_range();
+			if range > 0.
+			{
+				for (r, alpha) in [(range, 0.5), (2. * range, 0.2)]
+				{
+					let color = Color::from_rgba_f(0.8 * alpha, 0.6 * alpha, 0.2 * alpha, alpha);
+					let num_segments = 64;
+					let points: Vec<_> = (0..=num_segments)
+						.map(|i| {
+							let theta = 2. * PI * i as f32 / num_segments as f32;
+							map.world_to_screen(
+								pos.pos + r * Vector3::new(theta.cos(), 0., theta.sin()),
+							)
+						})
+						.collect();
+					for (p1, p2) in points.iter().zip(points.iter().skip(1))
+					{
+						state.prim.draw_line(p1.x, p1.y, p2.x, p2.y, color, 2.);
+					}
+				}
+			}
+		}
+
+		let mouse_ground_pos = map.get_mouse_ground_pos(state);
+
+		// Where the ready guns would send their shots. Red when out of arc or reach, yellow when
+		// past the effective range.
+		if let (true, Ok(pos), Ok(equipment)) = (
+			map.attack_held && !map.spectator && !map.defeated(),
+			map.world.get::<&comps::Position>(map.player),
+			map.world.get::<&comps::Equipment>(map.player),
+		)
+		{
+			let derived_stats = equipment.derived_stats();
+			let heel = map.player_heel();
+			for slot in &equipment.slots
+			{
+				let weapon = match slot.item.as_ref().map(|item| &item.kind)
+				{
+					Some(comps::ItemKind::Weapon(weapon))
+						if !slot.is_inventory && weapon.readiness >= 1. =>
+					{
+						weapon
+					}
+					_ => continue,
+				};
+				let stats = weapon.stats();
+				let (_, arc) = heel_penalty(heel, slot.dir, stats.arc);
+				let (slot_pos, aim) = aim_gun(
+					&pos,
+					slot.pos,
+					slot.dir,
+					arc,
+					derived_stats.out_of_arc,
+					mouse_ground_pos,
+				);
+				let (dir, on_target) = match aim
+				{
+					Some(aim) => aim,
+					None => continue,
+				};
+				let points = ballistic_arc(
+					Point3::new(slot_pos.y, GUN_HEIGHT, slot_pos.x),
+					gun_velocity(dir, stats.speed),
+				);
+				let reach = (points.last().unwrap().zx() - slot_pos).magnitude();
+				let dist = (mouse_ground_pos.zx() - slot_pos).magnitude();
+				let color = if !on_target || dist > reach
+				{
+					Color::from_rgba_f(0.6, 0.15, 0.1, 0.6)
+				}
+				else if dist > stats.effective_range()
+				{
+					Color::from_rgba_f(0.6, 0.5, 0.1, 0.6)
+				}
+				else
+				{
+					Color::from_rgba_f(0.2, 0.6, 0.2, 0.6)
+				};
+				let points: Vec<_> = points.iter().map(|p| map.world_to_screen(*p)).collect();
+				for (p1, p2) in points.iter().zip(points.iter().skip(1))
+				{
+					state.prim.draw_line(p1.x, p1.y, p2.x, p2.y, color, 2.);
+				}
+			}
+		}
+
 		let mut weapon_slots = vec![];
 		if let (Ok(pos), Ok(equipment)) = (
 			map.world.get::<&comps::Position>(map.player),
 			map.world.get::<&comps::Equipment>(map.player),
 		)
 		{
+			let heel = map.player_heel();
 			for slot in &equipment.slots
 			{
 				if slot.is_inventory
@@ -481,8 +1467,9 @@ impl HUD
 								weapon.readiness,
 								slot.pos,
 								slot.dir.unwrap_or(0.),
-								weapon.stats().arc,
+								heel_penalty(heel, slot.dir, weapon.stats().arc).1,
 								item.kind.clone(),
+								slot.disabled,
 							));
 						}
 						_ => (),
@@ -493,9 +1480,8 @@ impl HUD
 		let w = m * 3.;
 		let total = weapon_slots.len() as f32 * w;
 		let offt = total / 2.;
-		let mouse_ground_pos = map.get_mouse_ground_pos(state);
 
-		for (i, (pos, dir, fire_readiness, slot_pos, slot_dir, arc, kind)) in
+		for (i, (pos, dir, fire_readiness, slot_pos, slot_dir, arc, kind, disabled)) in
 			weapon_slots.iter().enumerate()
 		{
 			let x = i as f32 * w - offt + dw / 2.;
@@ -510,6 +1496,11 @@ impl HUD
 			let min_dot = (arc / 2.).cos();
 
 			draw_item(x + w / 2., y + 64. + w / 2., &kind, state);
+			if *disabled
+			{
+				draw_stood_down(x + w / 2., y + 64. + w / 2., w, state);
+				continue;
+			}
 			if slot_vec_dir.dot(&target_dir) > min_dot
 			{
 				state.prim.draw_filled_pieslice(
@@ -541,7 +1532,16 @@ impl HUD
 		)
 		{
 			let status_pos = self.status_pos(1, m);
-			draw_ship_state(&*ship_state, &*stats, status_pos.x, status_pos.y, state);
+			let (x, y) = (status_pos.x, status_pos.y);
+			self.player_status
+				.draw(ship_state_key(&ship_state, &stats, x, y), state, |batch| {
+					layout_ship_state(&ship_state, &stats, x, y, batch, state)
+				});
+			if !map.spectator
+			{
+				draw_abilities(&*ship_state, dw / 2., dh - 16. - m * 2., state);
+				self.draw_heel(map.player_heel(), x, y - m * 8., state);
+			}
 
 			let f = (ship_state.experience - comps::level_experience(ship_state.level))
 				/ (comps::level_experience(ship_state.level + 1)
@@ -555,18 +1555,55 @@ impl HUD
 			);
 		}
 
-		if let Some(target_entity) = map.target_entity
+		if let Some(pos) = map
+			.engage_target
+			.and_then(|e| map.world.get::<&comps::Position>(e).ok())
+		{
+			let pos = map.world_to_screen(pos.pos);
+			let color = Color::from_rgb_f(1., 0.3, 0.2);
+			let r = m * 2.;
+			state.prim.draw_circle(pos.x, pos.y, r, color, 2.);
+			for (dx, dy) in [(1., 0.), (-1., 0.), (0., 1.), (0., -1.)]
+			{
+				state.prim.draw_line(
+					pos.x + dx * r * 0.6,
+					pos.y + dy * r * 0.6,
+					pos.x + dx * r * 1.3,
+					pos.y + dy * r * 1.3,
+					color,
+					2.,
+				);
+			}
+		}
+
+		if let Some(target_entity) = map.target_entity
 		{
 			let status_pos = self.status_pos(0, m);
 			if let Ok(ai) = map.world.get::<&comps::AI>(target_entity)
 			{
-				state.core.draw_text(
-					&state.ui_font,
+				let text = map
+					.intel
+					.get(&ai.name)
+					.map(|intel| intel.summary(&ai.name))
+					.unwrap_or_else(|| "Unidentified Ship".into());
+				ui::draw_floating_text(
+					state,
 					Color::from_rgb_f(1., 1., 1.),
 					status_pos.x,
-					status_pos.y - m * 7.,
+					status_pos.y - m * 8.,
 					FontAlign::Centre,
-					&ai.name,
+					&text,
+				);
+			}
+			else if map.world.get::<&comps::Fleet>(target_entity).is_ok()
+			{
+				ui::draw_floating_text(
+					state,
+					Color::from_rgb_f(1., 1., 1.),
+					status_pos.x,
+					status_pos.y - m * 8.,
+					FontAlign::Centre,
+					"Your Fleet",
 				);
 			}
 
@@ -575,37 +1612,182 @@ impl HUD
 				map.world.get::<&comps::ShipStats>(target_entity),
 			)
 			{
-				draw_ship_state(&*ship_state, &*stats, status_pos.x, status_pos.y, state);
+				let (x, y) = (status_pos.x, status_pos.y);
+				self.target_status.draw(
+					ship_state_key(&ship_state, &stats, x, y),
+					state,
+					|batch| layout_ship_state(&ship_state, &stats, x, y, batch, state),
+				);
 			}
 		}
-		state.core.draw_text(
-			&state.ui_font,
-			ui_color,
-			dw / 2.0,
-			16.,
-			FontAlign::Centre,
-			&format!("Money: £{}", map.money),
+		let keys: Vec<_> = REPAIR_PARTS
+			.iter()
+			.map(|(_, _, action)| state.controls.get_controls().get_action_string(*action))
+			.collect();
+		let chrome_key = (
+			map.spectator,
+			map.player_latitude(),
+			std::mem::discriminant(&map.outcome),
+			map.money,
+			map.has_fleet(),
+			map.station_formation(),
+			self.buttons
+				.iter()
+				.map(|b| (b.on, b.hover))
+				.collect::<Vec<_>>(),
+			keys.clone(),
 		);
+		self.chrome
+			.draw(chrome_key, state, || self.draw_chrome(map, &keys, state));
 
 		let lh = state.ui_font.get_line_height() as f32;
 
-		let num_messages = map.messages.len();
-		for (i, (message, time)) in map.messages.iter().enumerate()
+		if map.defeated()
+		{
+			let r = self.concede_rect(state);
+			let mouse_pos = state.mouse_pos;
+			let (mx, my) = (mouse_pos.x as f32, mouse_pos.y as f32);
+			let hover = mx > r[0] && mx < r[2] && my > r[1] && my < r[3];
+			state.prim.draw_filled_rectangle(
+				r[0],
+				r[1],
+				r[2],
+				r[3],
+				ui::backdrop(if hover { 0.9 } else { 0.6 }),
+			);
+			state
+				.prim
+				.draw_rectangle(r[0], r[1], r[2], r[3], ui_color, 2.);
+			state.core.draw_text(
+				&state.ui_font,
+				ui_color,
+				(r[0] + r[2]) / 2.,
+				(r[1] + r[3]) / 2. - lh / 2.,
+				FontAlign::Centre,
+				"Concede",
+			);
+		}
+
+		for (idx, rect) in self.message_rects(map, state)
 		{
-			let f = 1. - (state.time() - time) as f32 / MESSAGE_DURATION;
+			let message = &map.messages.messages[idx];
+			let f = message.freshness(message.kind.duration(), state.time());
+			let color = message
+				.kind
+				.color()
+				.interpolate(Color::from_rgba(0, 0, 0, 0), 1. - f);
+			message
+				.kind
+				.draw_icon(rect[0] + lh / 2., rect[1] + lh / 2., lh * 0.6, color, state);
 			state.core.draw_text(
 				&state.ui_font,
 				ui_color.interpolate(Color::from_rgba(0, 0, 0, 0), 1. - f),
 				dw / 2.0,
-				dh / 4.0 - i as f32 * lh * 1.5 + num_messages as f32 * lh * 1.5,
+				rect[1],
 				FontAlign::Centre,
-				&message,
+				&message.text,
+			);
+		}
+		let toast_pos = self.toast_pos();
+		map.messages.draw_toast(toast_pos.x, toast_pos.y, state);
+
+		// Sound captions, under the latitude chart.
+		let mut y = 16. + lh * 10.;
+		for caption in state.sfx.captions()
+		{
+			let text = if caption.count > 1
+			{
+				format!("{} x{}", caption.text, caption.count)
+			}
+			else
+			{
+				caption.text.clone()
+			};
+			let f = caption.freshness();
+			let w = state.ui_font.get_text_width(&text) as f32;
+			state.prim.draw_filled_rectangle(
+				dw - m * 1.25 - w,
+				y - lh / 4.,
+				dw - m * 0.75,
+				y + lh * 1.25,
+				ui::backdrop(0.6 * f),
+			);
+			state.core.draw_text(
+				&state.ui_font,
+				ui_color.interpolate(Color::from_rgba(0, 0, 0, 0), 1. - f),
+				dw - m,
+				y,
+				FontAlign::Right,
+				&text,
 			);
+			y += lh * 1.5;
+		}
+
+		let fleet_color = Color::from_rgb_f(0.2, 1., 0.2);
+		for id in &map.selected
+		{
+			if let Ok(pos) = map.world.get::<&comps::Position>(*id)
+			{
+				let screen_pos = map.world_to_screen(pos.pos);
+				state
+					.prim
+					.draw_circle(screen_pos.x, screen_pos.y, m * 1.5, fleet_color, 2.);
+			}
+		}
+		if let Some(start) = map.box_start
+		{
+			let end = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
+			state
+				.prim
+				.draw_rectangle(start.x, start.y, end.x, end.y, fleet_color, 2.);
 		}
 
-		for toggle in &self.buttons
+		if !map.spectator
 		{
-			toggle.draw(state);
+			let lh = state.ui_font.get_line_height() as f32;
+			let equipment = map.world.get::<&comps::Equipment>(map.player).ok();
+			for (i, ((toggle, (name, _, _)), key)) in self
+				.buttons
+				.iter()
+				.zip(REPAIR_PARTS.iter())
+				.zip(&keys)
+				.enumerate()
+			{
+				if toggle.hover
+				{
+					let mut text = format!(
+						"Prioritize {name} repairs ({key}), up to {MAX_REPAIR_BOOSTS} at once"
+					);
+					if let (Some(kind), Some(equipment)) =
+						(comps::MaterialKind::for_part(i), equipment.as_ref())
+					{
+						text += &format!(
+							". {}: {:.0} left, using {:.1}/s",
+							kind.name(),
+							equipment.material_left(kind),
+							map.material_rate[kind as usize]
+						);
+					}
+					let w = state.ui_font.get_text_width(&text) as f32 + m;
+					let x = (toggle.loc.x - w / 2.).min(dw - w).max(0.);
+					let y = toggle.loc.y - toggle.size.y / 2. - lh * 1.5;
+					state.prim.draw_filled_rectangle(
+						x,
+						y,
+						x + w,
+						y + lh * 1.25,
+						ui::backdrop(0.75),
+					);
+					state.core.draw_text(
+						&state.ui_font,
+						ui_color,
+						x + m / 2.,
+						y + lh / 8.,
+						FontAlign::Left,
+						&text,
+					);
+				}
+			}
 		}
 	}
 }
@@ -614,32 +1796,209 @@ pub struct Game
 {
 	map: Map,
 	equipment_screen: Option<EquipmentScreen>,
+	inspect_panel: Option<InspectPanel>,
 	subscreens: Vec<ui::SubScreen>,
 	hud: HUD,
+	show_hud: bool,
+	// Blurred copy of the last frame before the menus came up, drawn instead of the world.
+	pause_snapshot: Option<Bitmap>,
 }
 
 impl Game
 {
 	pub fn new(state: &mut game_state::GameState) -> Result<Self>
 	{
+		state.loot_filter = comps::LootFilter::new();
+		state.curses = state.options.curses.clone();
+		state.modifiers = curses::Modifiers::new(&state.curses);
+		let map = Map::new(false, state)?;
+		if let Some(net) = state.net.as_mut()
+		{
+			// A broken link shows up on the next receive.
+			net.hello(map.seed).ok();
+		}
+		Ok(Self {
+			map: map,
+			subscreens: vec![],
+			equipment_screen: None,
+			inspect_panel: None,
+			hud: HUD::new(state),
+			show_hud: true,
+			pause_snapshot: None,
+		})
+	}
+
+	// Captains the consort in someone else's voyage, on their seed.
+	pub fn new_consort(state: &mut game_state::GameState) -> Result<Self>
+	{
+		state.loot_filter = comps::LootFilter::new();
+		state.curses.clear();
+		state.modifiers = curses::Modifiers::new(&[]);
+		let host_seed = state.net.as_ref().and_then(|net| net.seed);
+		let own_seed = std::mem::replace(&mut state.seed, host_seed);
+		let map = Map::new_consort(state);
+		state.seed = own_seed;
+		Ok(Self {
+			map: map?,
+			subscreens: vec![],
+			equipment_screen: None,
+			inspect_panel: None,
+			hud: HUD::new(state),
+			show_hud: true,
+			pause_snapshot: None,
+		})
+	}
+
+	pub fn new_spectator(state: &mut game_state::GameState) -> Result<Self>
+	{
+		state.curses.clear();
+		state.modifiers = curses::Modifiers::new(&[]);
 		Ok(Self {
-			map: Map::new(state)?,
+			map: Map::new(true, state)?,
 			subscreens: vec![],
 			equipment_screen: None,
+			inspect_panel: None,
 			hud: HUD::new(state),
+			show_hud: true,
+			pause_snapshot: None,
 		})
 	}
 
+	// A spectator game without any UI, for playing behind the main menu.
+	pub fn new_attract(state: &mut game_state::GameState) -> Result<Self>
+	{
+		let mut game = Self::new_spectator(state)?;
+		game.show_hud = false;
+		Ok(game)
+	}
+
+	// A few ships sailing about at dusk, for the title screen.
+	pub fn new_title(state: &mut game_state::GameState) -> Result<Self>
+	{
+		let mut game = Self::new_attract(state)?;
+		game.map.title = true;
+		game.map.tint = [1., 0.65, 0.5];
+		game.map.zoom = 1.5;
+		let fleets: Vec<_> = game
+			.map
+			.world
+			.query::<&comps::ShipState>()
+			.iter()
+			.map(|(id, _)| id)
+			.collect();
+		for entity in fleets
+		{
+			game.map.world.despawn(entity)?;
+		}
+		game.map.messages.clear();
+		game.map.spawn_title_ships(state)?;
+		Ok(game)
+	}
+
 	pub fn logic(
 		&mut self, state: &mut game_state::GameState,
 	) -> Result<Option<game_state::NextScreen>>
 	{
-		if self.subscreens.is_empty()
+		state
+			.sfx
+			.set_muffled(!self.subscreens.is_empty() || self.equipment_screen.is_some());
+		if self.subscreens.is_empty() && self.map.spectator
+		{
+			self.map.mouse_in_buffer = true;
+			self.map.logic(state)
+		}
+		else if self.subscreens.is_empty()
 		{
 			let want_inventory = state.controls.get_action_state(controls::Action::Inventory) > 0.5;
 			state
 				.controls
 				.clear_action_state(controls::Action::Inventory);
+			let want_journal = state.controls.get_action_state(controls::Action::Journal) > 0.5;
+			state.controls.clear_action_state(controls::Action::Journal);
+			let want_economy = state.controls.get_action_state(controls::Action::Economy) > 0.5;
+			state.controls.clear_action_state(controls::Action::Economy);
+			let want_damage_log =
+				state.controls.get_action_state(controls::Action::DamageLog) > 0.5;
+			state
+				.controls
+				.clear_action_state(controls::Action::DamageLog);
+			let want_chart = state.controls.get_action_state(controls::Action::Chart) > 0.5;
+			state.controls.clear_action_state(controls::Action::Chart);
+			let want_summary = state
+				.controls
+				.get_action_state(controls::Action::ShipSummary)
+				> 0.5;
+			state
+				.controls
+				.clear_action_state(controls::Action::ShipSummary);
+			if want_chart && !self.map.spectator
+			{
+				self.map.camera_pan = Vector2::zeros();
+				let chart = self.map.chart_menu(state);
+				self.subscreens.push(ui::SubScreen::ChartMenu(chart));
+				state.paused = true;
+				return Ok(None);
+			}
+			if want_economy
+			{
+				let series = Commodity::ALL
+					.into_iter()
+					.map(|commodity| {
+						(
+							commodity.name(state),
+							commodity.color(),
+							self.map
+								.economy_history
+								.iter()
+								.map(|e| e[commodity])
+								.collect(),
+						)
+					})
+					.collect();
+				self.map.camera_pan = Vector2::zeros();
+				self.subscreens
+					.push(ui::SubScreen::EconomyMenu(ui::EconomyMenu::new(
+						state, series,
+					)));
+				state.paused = true;
+				return Ok(None);
+			}
+			if want_journal
+			{
+				self.map.camera_pan = Vector2::zeros();
+				self.subscreens
+					.push(ui::SubScreen::JournalMenu(ui::JournalMenu::new(
+						state,
+						"Captain's Log",
+						self.map.journal.lines(),
+					)));
+				state.paused = true;
+				return Ok(None);
+			}
+			if want_summary && !self.map.spectator
+			{
+				self.map.camera_pan = Vector2::zeros();
+				self.subscreens
+					.push(ui::SubScreen::JournalMenu(ui::JournalMenu::new(
+						state,
+						"Ship Summary",
+						self.map.ship_summary(),
+					)));
+				state.paused = true;
+				return Ok(None);
+			}
+			if want_damage_log
+			{
+				self.map.camera_pan = Vector2::zeros();
+				self.subscreens
+					.push(ui::SubScreen::JournalMenu(ui::JournalMenu::new(
+						state,
+						"Damage Log",
+						self.map.damage_log.lines(state.time()),
+					)));
+				state.paused = true;
+				return Ok(None);
+			}
 
 			if want_inventory
 			{
@@ -648,7 +2007,7 @@ impl Game
 					self.equipment_screen
 						.as_mut()
 						.unwrap()
-						.finish_trade(&mut self.map);
+						.finish_trade(&mut self.map, state);
 					self.equipment_screen = None;
 					self.map.dock_entity = None;
 				}
@@ -659,17 +2018,86 @@ impl Game
 			}
 			if self.map.dock_entity.is_some() && self.equipment_screen.is_none()
 			{
+				self.map.apply_loot_filter(state);
 				self.equipment_screen = Some(EquipmentScreen::new(state));
 			}
 
+			let want_inspect = state.controls.get_action_state(controls::Action::Inspect) > 0.5;
+			state.controls.clear_action_state(controls::Action::Inspect);
+			if self.map.dock_entity.is_some()
+			{
+				// The trade partner's panel goes in the same spot.
+				self.inspect_panel = None;
+			}
+			else if want_inspect && self.inspect_panel.is_some()
+			{
+				self.inspect_panel = None;
+			}
+			else if want_inspect
+			{
+				self.inspect_panel = self.map.try_inspect(state);
+			}
+			if let Some(inspect_panel) = self.inspect_panel.as_ref()
+			{
+				if !inspect_panel.logic(&self.map)
+				{
+					self.inspect_panel = None;
+				}
+			}
+
 			if let Some(equipment_screen) = self.equipment_screen.as_mut()
 			{
 				self.map.mouse_in_buffer = equipment_screen.logic(&mut self.map, state);
+				if let Some((name, max_count)) = equipment_screen.quantity_request.take()
+				{
+					self.subscreens
+						.push(ui::SubScreen::QuantityMenu(ui::QuantityMenu::new(
+							state, &name, max_count,
+						)));
+					state.paused = true;
+				}
+				if std::mem::take(&mut self.map.open_fire_request)
+				{
+					let name = self
+						.map
+						.dock_entity
+						.and_then(|dock_entity| {
+							self.map
+								.world
+								.get::<&comps::AI>(dock_entity)
+								.ok()
+								.map(|ai| ai.name.clone())
+						})
+						.unwrap_or_else(|| "them".into());
+					self.subscreens
+						.push(ui::SubScreen::OpenFireMenu(ui::OpenFireMenu::new(
+							state, &name,
+						)));
+					state.paused = true;
+				}
+				if std::mem::take(&mut equipment_screen.voidwind_request)
+				{
+					self.subscreens
+						.push(ui::SubScreen::VoidwindMenu(ui::VoidwindMenu::new(state)));
+					state.paused = true;
+				}
+				if let Some(split) = equipment_screen.capture_request.take()
+				{
+					self.subscreens
+						.push(ui::SubScreen::CaptureMenu(ui::CaptureMenu::new(
+							state, split,
+						)));
+					state.paused = true;
+				}
 			}
 			else
 			{
 				self.map.mouse_in_buffer = true;
 			}
+			if let Some(inspect_panel) = self.inspect_panel.as_ref()
+			{
+				self.map.mouse_in_buffer &= !inspect_panel.over_ui(state);
+			}
 			self.hud.logic(&mut self.map, state);
 			self.map.logic(state)
 		}
@@ -684,11 +2112,18 @@ impl Game
 	) -> Result<Option<game_state::NextScreen>>
 	{
 		let mut handled = false;
-		if let Some(equipment_screen) = self.equipment_screen.as_mut()
+		// Menus on top are modal.
+		if let Some(equipment_screen) = self
+			.equipment_screen
+			.as_mut()
+			.filter(|_| self.subscreens.is_empty())
 		{
 			handled |= equipment_screen.input(event, &mut self.map, state);
 		}
-		handled |= self.hud.input(event, &mut self.map, state);
+		if !self.map.spectator
+		{
+			handled |= self.hud.input(event, &mut self.map, state);
+		}
 		if !handled
 		{
 			state.controls.decode_event(event);
@@ -698,7 +2133,7 @@ impl Game
 				self.equipment_screen
 					.as_mut()
 					.unwrap()
-					.finish_trade(&mut self.map);
+					.finish_trade(&mut self.map, state);
 				self.equipment_screen = None;
 				self.map.dock_entity = None;
 			}
@@ -728,7 +2163,7 @@ impl Game
 							self.equipment_screen
 								.as_mut()
 								.unwrap()
-								.finish_trade(&mut self.map);
+								.finish_trade(&mut self.map, state);
 							self.equipment_screen = None;
 							self.map.dock_entity = None;
 						}
@@ -737,7 +2172,7 @@ impl Game
 							in_game_menu = true;
 						}
 					}
-					_ => (),
+					_ => return self.map.input(event, state),
 				},
 				_ =>
 				{
@@ -754,6 +2189,7 @@ impl Game
 			}
 			if in_game_menu
 			{
+				self.map.camera_pan = Vector2::zeros();
 				self.subscreens
 					.push(ui::SubScreen::InGameMenu(ui::InGameMenu::new(state)));
 				state.paused = true;
@@ -775,13 +2211,70 @@ impl Game
 					ui::Action::Start =>
 					{
 						state.paused = false;
+						self.map.export_journal(state);
+						if self.map.spectator
+						{
+							return Ok(Some(game_state::NextScreen::Spectate));
+						}
 						return Ok(Some(game_state::NextScreen::Game));
 					}
-					ui::Action::MainMenu => return Ok(Some(game_state::NextScreen::Menu)),
+					ui::Action::MainMenu =>
+					{
+						self.map.export_journal(state);
+						return Ok(Some(game_state::NextScreen::Menu));
+					}
 					ui::Action::Back =>
 					{
 						self.subscreens.pop().unwrap();
 					}
+					ui::Action::SetCourse(dest) =>
+					{
+						self.subscreens.pop().unwrap();
+						self.map.set_course(dest, state)?;
+					}
+					ui::Action::ClearCourse =>
+					{
+						self.subscreens.pop().unwrap();
+						self.map.clear_course();
+					}
+					ui::Action::ChartNotes(notes) =>
+					{
+						self.map.exploration.notes =
+							notes.into_iter().filter(|n| !n.text.is_empty()).collect();
+					}
+					ui::Action::BuyQuantity(count) =>
+					{
+						self.subscreens.pop().unwrap();
+						if let Some(equipment_screen) = self.equipment_screen.as_mut()
+						{
+							equipment_screen.bulk_trade = equipment_screen
+								.quantity_slot
+								.take()
+								.map(|slot| BulkTrade::Buy(slot, count));
+						}
+					}
+					ui::Action::OpenFire =>
+					{
+						self.subscreens.pop().unwrap();
+						if let Some(mut equipment_screen) = self.equipment_screen.take()
+						{
+							equipment_screen.finish_trade(&mut self.map, state);
+						}
+						self.map.dock_entity = None;
+						self.map.add_message(
+							message::MessageKind::Combat,
+							"The truce is broken!".into(),
+							state,
+						);
+					}
+					ui::Action::Capture(crew, wounded, officers) =>
+					{
+						self.subscreens.pop().unwrap();
+						if let Some(equipment_screen) = self.equipment_screen.as_mut()
+						{
+							equipment_screen.capture = Some((crew, wounded, officers));
+						}
+					}
 					_ => (),
 				}
 			}
@@ -793,10 +2286,42 @@ impl Game
 		Ok(None)
 	}
 
+	// Blurs what's in the buffer, in two passes.
+	fn blur_buffer(&self, state: &game_state::GameState) -> Option<Bitmap>
+	{
+		let (dw, dh) = (state.display_width, state.display_height);
+		let scratch = Bitmap::new(&state.core, dw as i32, dh as i32).ok()?;
+		let snapshot = Bitmap::new(&state.core, dw as i32, dh as i32).ok()?;
+		let blur_shader = state.blur_shader.upgrade().unwrap();
+		for (src, dst, step) in [
+			(state.buffer.as_ref().unwrap(), &scratch, [1. / dw, 0.]),
+			(&scratch, &snapshot, [0., 1. / dh]),
+		]
+		{
+			state.core.set_target_bitmap(Some(dst));
+			// Shaders are per target bitmap.
+			state.core.use_shader(Some(&*blur_shader)).unwrap();
+			state.core.set_shader_uniform("blur_step", &[step][..]).ok();
+			state
+				.core
+				.set_blender(BlendOperation::Add, BlendMode::One, BlendMode::Zero);
+			state.core.draw_bitmap(src, 0., 0., Flag::zero());
+		}
+		state.core.set_target_bitmap(state.buffer.as_ref());
+		Some(snapshot)
+	}
+
 	pub fn draw(&mut self, state: &game_state::GameState) -> Result<()>
 	{
-		state.core.clear_to_color(Color::from_rgb_f(0.5, 0.5, 1.));
-		self.map.draw(state)?;
+		if self.subscreens.is_empty()
+		{
+			self.pause_snapshot = None;
+		}
+		if self.pause_snapshot.is_none()
+		{
+			state.core.clear_to_color(Color::from_rgb_f(0.5, 0.5, 1.));
+			self.map.draw(state)?;
+		}
 
 		let (dw, dh) = (state.display_width, state.display_height);
 		let ortho_mat = Matrix4::new_orthographic(0., dw, dh, 0., -1., 1.);
@@ -813,13 +2338,58 @@ impl Game
 			.core
 			.set_blender(BlendOperation::Add, BlendMode::One, BlendMode::InverseAlpha);
 
-		if self.subscreens.is_empty()
+		if self.pause_snapshot.is_none()
 		{
-			self.hud.draw(&self.map, state);
+			if self.subscreens.is_empty() && self.show_hud
+			{
+				self.hud.draw(&self.map, state);
+			}
+			if self.map.debug_view != DebugView::Final
+			{
+				state.core.draw_text(
+					&state.ui_font,
+					Color::from_rgb_f(1., 0.3, 1.),
+					dw / 2.,
+					16. + 2. * state.ui_font.get_line_height() as f32,
+					FontAlign::Centre,
+					&format!(
+						"Debug View: {}, Meshes: {:.1} MB",
+						self.map.debug_view.name(),
+						state.mesh_memory() as f32 / 1e6
+					),
+				);
+			}
+			if let Some(inspect_panel) = self.inspect_panel.as_ref()
+			{
+				inspect_panel.draw(&self.map, state);
+			}
+			if let Some(equipment_screen) = self.equipment_screen.as_ref()
+			{
+				equipment_screen.draw(&self.map, state);
+			}
+			if !self.subscreens.is_empty()
+			{
+				// The world is paused behind the menus, so it only needs drawing once.
+				self.pause_snapshot = self.blur_buffer(state);
+				state
+					.core
+					.use_projection_transform(&utils::mat4_to_transform(ortho_mat));
+				state.core.use_transform(&Transform::identity());
+				state
+					.core
+					.use_shader(Some(&*state.default_shader.upgrade().unwrap()))
+					.unwrap();
+			}
 		}
-		if let Some(equipment_screen) = self.equipment_screen.as_ref()
+		if let Some(snapshot) = self.pause_snapshot.as_ref()
 		{
-			equipment_screen.draw(&self.map, state);
+			state
+				.core
+				.set_blender(BlendOperation::Add, BlendMode::One, BlendMode::Zero);
+			state.core.draw_bitmap(snapshot, 0., 0., Flag::zero());
+			state
+				.core
+				.set_blender(BlendOperation::Add, BlendMode::One, BlendMode::InverseAlpha);
 		}
 		if let Some(subscreen) = self.subscreens.last_mut()
 		{
@@ -828,27 +2398,72 @@ impl Game
 				0.,
 				state.display_width,
 				state.display_height,
-				Color::from_rgba_f(0., 0., 0., 0.5),
+				ui::backdrop(0.3),
 			);
 			subscreen.draw(state);
+		}
+		Ok(())
+	}
 
-			// // This is dumb.
-			// let sprite = state.get_sprite("data/cursor.cfg").unwrap();
-			// sprite.draw(
-			// 	to_f32(state.mouse_pos),
-			// 	0,
-			// 	Color::from_rgb_f(1., 1., 1.),
-			// 	state,
-			// );
+	pub fn draw_cursor(&self, state: &game_state::GameState) -> Result<()>
+	{
+		let cursor = if !self.subscreens.is_empty()
+		{
+			Cursor::Pointer
+		}
+		else if self
+			.equipment_screen
+			.as_ref()
+			.map(|equipment_screen| equipment_screen.grabs(&self.map))
+			.unwrap_or(false)
+		{
+			Cursor::Grab
 		}
+		else if !self.map.mouse_in_buffer || (self.show_hud && self.hud.over_ui(state))
+		{
+			Cursor::Pointer
+		}
+		else
+		{
+			self.map.cursor
+		};
+		let sprite = state.get_sprite("data/cursor.cfg")?;
+		sprite.draw(
+			Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32),
+			cursor as i32,
+			Color::from_rgb_f(1., 1., 1.),
+			state,
+		);
 		Ok(())
 	}
 
+	pub fn probe(&self) -> ui_script::Probe
+	{
+		let subscreen = if let Some(subscreen) = self.subscreens.last()
+		{
+			Some(subscreen.name())
+		}
+		else
+		{
+			self.equipment_screen.as_ref().map(|_| "EquipmentScreen")
+		};
+		ui_script::Probe {
+			screen: "Game",
+			subscreen: subscreen,
+			dragging: self
+				.equipment_screen
+				.as_ref()
+				.map(|e| e.dragged_item.is_some())
+				.unwrap_or(false),
+		}
+	}
+
 	pub fn change_buffers(&mut self, state: &mut game_state::GameState) -> Result<()>
 	{
 		self.hud = HUD::new(state);
 		self.map.buffer_width = state.display_width;
 		self.map.buffer_height = state.display_height;
+		self.pause_snapshot = None;
 		self.subscreens.clear();
 		self.subscreens
 			.push(ui::SubScreen::InGameMenu(ui::InGameMenu::new(state)));
@@ -859,2616 +2474,9528 @@ impl Game
 	}
 }
 
-struct EquipmentScreen
+fn draw_silhouette(
+	triangles: &[[Point2<f32>; 3]], to_screen: impl Fn(Point2<f32>) -> Point2<f32>,
+	state: &game_state::GameState,
+)
 {
-	buffer_width: f32,
-	buffer_height: f32,
-	mouse_button_down: bool,
-	ctrl_down: bool,
-
-	// Source slot, equipment_idx
-	hover_slot: Option<(usize, i32)>,
-	// Source slot, equipment_idx, item
-	dragged_item: Option<(usize, i32, comps::Item)>,
+	if triangles.is_empty()
+	{
+		return;
+	}
+	let hull_color = Color::from_rgba_f(0.15, 0.15, 0.25, 0.5);
+	let (mut bow, mut stern) = (f32::NEG_INFINITY, f32::INFINITY);
+	for tri in triangles
+	{
+		for p in tri
+		{
+			bow = bow.max(p.x);
+			stern = stern.min(p.x);
+		}
+		let [a, b, c] = tri.map(&to_screen);
+		state
+			.prim
+			.draw_filled_triangle(a.x, a.y, b.x, b.y, c.x, c.y, hull_color);
+	}
 
-	switch_ships: Option<Button>,
-	recruit: Option<Button>,
+	let lh = state.ui_font.get_line_height() as f32;
+	let label_color = ui::ui_color().interpolate(hull_color, 0.5);
+	let bow_pos = to_screen(Point2::new(bow, 0.));
+	let stern_pos = to_screen(Point2::new(stern, 0.));
+	state.core.draw_text(
+		&state.ui_font,
+		label_color,
+		bow_pos.x,
+		bow_pos.y - lh * 1.5,
+		FontAlign::Centre,
+		"Bow",
+	);
+	state.core.draw_text(
+		&state.ui_font,
+		label_color,
+		stern_pos.x,
+		stern_pos.y + lh / 2.,
+		FontAlign::Centre,
+		"Stern",
+	);
+}
 
-	grab_attempted: bool,
+// Which way a slot fires, in words.
+// After switching ships, brings serving officers across from the old one, into empty mounts
+// before the hold.
+fn move_officers(map: &mut Map, count: i32)
+{
+	let old_ship = match map.dock_entity
+	{
+		Some(old_ship) => old_ship,
+		None => return,
+	};
+	let mut query = map.world.query::<&mut comps::Equipment>();
+	let mut view = query.view();
+	if let [Some(from), Some(to)] = view.get_mut_n([old_ship, map.player])
+	{
+		let mut berths: Vec<_> = to
+			.slots
+			.iter_mut()
+			.filter(|slot| slot.item.is_none())
+			.collect();
+		berths.sort_by_key(|slot| slot.is_inventory);
+		let officers = from
+			.slots
+			.iter_mut()
+			.filter(|slot| !slot.is_inventory)
+			.filter(|slot| {
+				matches!(
+					slot.item.as_ref().map(|item| &item.kind),
+					Some(comps::ItemKind::Officer(_))
+				)
+			})
+			.take((count as usize).min(berths.len()));
+		for (berth, slot) in berths.into_iter().zip(officers)
+		{
+			berth.item = slot.item.take();
+		}
+	}
 }
 
-impl EquipmentScreen
+fn slot_side(dir: Option<f32>) -> &'static str
 {
-	fn new(state: &mut game_state::GameState) -> Self
+	match dir
+	{
+		None => "Any",
+		Some(dir) =>
+		{
+			let dir = dir.rem_euclid(2. * PI);
+			if dir < PI / 4. || dir > 7. * PI / 4.
+			{
+				"Bow"
+			}
+			else if dir < 3. * PI / 4.
+			{
+				"Port"
+			}
+			else if dir < 5. * PI / 4.
+			{
+				"Stern"
+			}
+			else
+			{
+				"Starboard"
+			}
+		}
+	}
+}
+
+// A read-only look at another ship's weapons and officers.
+struct InspectPanel
+{
+	entity: hecs::Entity,
+	buffer_width: f32,
+	buffer_height: f32,
+	silhouette: Vec<[Point2<f32>; 3]>,
+}
+
+impl InspectPanel
+{
+	fn new(entity: hecs::Entity, map: &Map, state: &game_state::GameState) -> Self
 	{
+		let silhouette = map
+			.world
+			.get::<&comps::Mesh>(entity)
+			.ok()
+			.and_then(|mesh| state.get_mesh(&mesh.mesh).ok())
+			.map(|mesh| mesh.top_down_triangles())
+			.unwrap_or_default();
 		Self {
+			entity: entity,
 			buffer_width: state.display_width,
 			buffer_height: state.display_height,
-			hover_slot: None,
-			dragged_item: None,
-			mouse_button_down: false,
-			ctrl_down: false,
-			switch_ships: None,
-			recruit: None,
-			grab_attempted: false,
+			silhouette: silhouette,
 		}
 	}
 
-	fn get_slot_pos(&self, equipment_idx: i32, real_pos: Point2<f32>) -> Point2<f32>
+	fn get_slot_pos(&self, real_pos: Point2<f32>) -> Point2<f32>
 	{
 		let (bw, bh) = (self.buffer_width, self.buffer_height);
-		Point2::new(-real_pos.y, -real_pos.x) * 32.
-			+ Vector2::new(bw / 6. + bw * 2. / 3. * equipment_idx as f32, bh / 4.)
+		Point2::new(-real_pos.y, -real_pos.x) * 32. + Vector2::new(bw / 6., bh / 4.)
 	}
 
-	fn over_ui(&self, map: &mut Map, state: &game_state::GameState) -> bool
+	fn over_ui(&self, state: &game_state::GameState) -> bool
 	{
-		let mouse_pos = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
-		let in_right = mouse_pos.x > self.buffer_width * 2. / 3.
-			&& mouse_pos.y < self.buffer_height * EQUIPMENT_FRAC;
-		let in_left = mouse_pos.x < self.buffer_width * 1. / 3.
-			&& mouse_pos.y < self.buffer_height * EQUIPMENT_FRAC;
-		if map.dock_entity.is_some()
-		{
-			in_left || in_right
-		}
-		else
-		{
-			in_right
-		}
+		(state.mouse_pos.x as f32) < self.buffer_width / 3.
+			&& (state.mouse_pos.y as f32) < self.buffer_height * EQUIPMENT_FRAC
 	}
 
-	fn input(&mut self, event: &Event, map: &mut Map, state: &mut game_state::GameState) -> bool
+	// Returns false once the panel should close.
+	fn logic(&self, map: &Map) -> bool
 	{
-		if let Some(button) = self.switch_ships.as_mut()
-		{
-			button.input(event);
-		}
-		if let Some(button) = self.recruit.as_mut()
+		map.target_entity == Some(self.entity)
+			&& map
+				.inspect_range_to(self.entity)
+				.map(|(dist, range)| dist < range * 1.25)
+				.unwrap_or(false)
+	}
+
+	fn draw(&self, map: &Map, state: &game_state::GameState)
+	{
+		let equipment = match map.world.get::<&comps::Equipment>(self.entity)
 		{
-			button.input(event);
-		}
-		match *event
+			Ok(equipment) => equipment,
+			Err(_) => return,
+		};
+		let m = state.m;
+		let lh = state.ui_font.get_line_height() as f32;
+		let ui_color = ui::ui_color();
+		let (bw, bh) = (self.buffer_width, self.buffer_height);
+		state.prim.draw_filled_rectangle(
+			0.,
+			0.,
+			bw / 3.,
+			bh * EQUIPMENT_FRAC,
+			Color::from_rgba_f(0.05, 0.05, 0.1, 0.9),
+		);
+		let name = map
+			.world
+			.get::<&comps::AI>(self.entity)
+			.map(|ai| ai.name.clone())
+			.unwrap_or_else(|_| "Unknown".into());
+		state.core.draw_text(
+			&state.ui_font,
+			ui_color,
+			bw / 6.,
+			m,
+			FontAlign::Centre,
+			&format!("Inspecting {name}"),
+		);
+		draw_silhouette(&self.silhouette, |p| self.get_slot_pos(p), state);
+
+		let mouse_pos = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
+		let mut hover_item = None;
+		let mut lines = vec![];
+		for slot in &equipment.slots
 		{
-			Event::MouseButtonDown { button: 1, .. } =>
+			// Cargo is none of our business.
+			if slot.is_inventory
 			{
-				if self.over_ui(map, state)
-				{
-					self.mouse_button_down = true;
-					return true;
-				}
+				continue;
 			}
-			Event::MouseButtonUp { button: 1, .. } =>
+			let pos = self.get_slot_pos(slot.pos);
+			let w = SLOT_WIDTH;
+			state.prim.draw_rounded_rectangle(
+				pos.x - w / 2.,
+				pos.y - w / 2.,
+				pos.x + w / 2.,
+				pos.y + w / 2.,
+				8.,
+				8.,
+				ui_color,
+				3.,
+			);
+			if let Some(slot_dir) = slot.dir
 			{
-				self.grab_attempted = false;
-				self.mouse_button_down = false;
+				let arc = PI / 4.;
+				state.prim.draw_arc(
+					pos.x,
+					pos.y,
+					w,
+					-slot_dir - arc / 2. + PI * 3. / 2.,
+					arc,
+					ui_color,
+					4.,
+				);
 			}
-			Event::KeyDown {
-				keycode: KeyCode::LCtrl | KeyCode::RCtrl,
-				..
-			} =>
+			if let Some(item) = slot.item.as_ref()
 			{
-				if self.over_ui(map, state)
+				draw_item(pos.x, pos.y, &item.kind, state);
+				if (mouse_pos - pos).abs().max() < w / 2.
 				{
-					self.ctrl_down = true;
-					return true;
+					hover_item = Some(item);
+				}
+				match &item.kind
+				{
+					comps::ItemKind::Weapon(weapon) =>
+					{
+						let stats = weapon.stats();
+						lines.push((
+							format!(
+								"{}: {:.0} dmg every {:.1}s, {}",
+								weapon.name,
+								stats.damage,
+								stats.fire_interval,
+								slot_side(slot.dir)
+							),
+							item.kind.color(),
+						));
+					}
+					comps::ItemKind::Officer(officer) =>
+					{
+						lines.push((officer.name.clone(), item.kind.color()));
+					}
+					_ => (),
 				}
 			}
-			Event::KeyUp {
-				keycode: KeyCode::LCtrl | KeyCode::RCtrl,
-				..
-			} =>
+		}
+		if let Some(item) = hover_item
+		{
+			lines = vec![(item.kind.name().to_string(), item.kind.color())];
+			for line in item.kind.description().lines()
 			{
-				self.ctrl_down = false;
+				lines.push((line.to_string(), ui_color));
 			}
-			_ => (),
 		}
-		false
-	}
-
-	fn do_trade(&self, map: &Map) -> bool
-	{
-		let dock_team = map.dock_entity.and_then(|dock_entity| {
-			map.world
-				.get::<&comps::ShipState>(dock_entity)
-				.map(|ss| ss.team)
-				.ok()
-		});
-		let player_team = map
-			.world
-			.get::<&comps::ShipState>(map.player)
-			.map(|ss| ss.team)
-			.ok();
-
-		if let (Some(dock_team), Some(player_team)) = (dock_team, player_team)
+		let mut y = bh * EQUIPMENT_FRAC - m - lines.len() as f32 * lh;
+		for (line, color) in &lines
 		{
-			dock_team.trade_with(&player_team)
+			state
+				.core
+				.draw_text(&state.ui_font, *color, m, y, FontAlign::Left, line);
+			y += lh;
 		}
-		else
+
+		// Every registered component, for debugging, in a panel of its own under this one since
+		// some of them go on for a while.
+		if cfg!(debug_assertions)
 		{
-			false
+			let components = registry::describe_entity(&map.world, self.entity);
+			let top = bh * EQUIPMENT_FRAC;
+			state.prim.draw_filled_rectangle(
+				0.,
+				top,
+				bw / 3.,
+				top + 2. * m + components.len() as f32 * lh,
+				Color::from_rgba_f(0.05, 0.05, 0.1, 0.9),
+			);
+			let mut y = top + m;
+			for (id, text) in components
+			{
+				let line: String = format!("{id}: {text}").chars().take(60).collect();
+				state.core.draw_text(
+					&state.ui_font,
+					Color::from_rgb_f(0.5, 0.5, 0.5),
+					m,
+					y,
+					FontAlign::Left,
+					&line,
+				);
+				y += lh;
+			}
 		}
 	}
+}
 
-	fn logic(&mut self, map: &mut Map, state: &mut game_state::GameState) -> bool
+#[derive(Copy, Clone, Debug)]
+enum BulkTrade
+{
+	SellCargo,
+	// Index of a dock slot holding the kind of cargo to buy, and how many of it.
+	Buy(usize, i32),
+	AskQuantity(usize),
+}
+
+struct EquipmentScreen
+{
+	buffer_width: f32,
+	buffer_height: f32,
+	mouse_button_down: bool,
+	fast_move_click: bool,
+
+	// Source slot, equipment_idx
+	hover_slot: Option<(usize, i32)>,
+	// Source slot, equipment_idx, item
+	dragged_item: Option<(usize, i32, comps::Item)>,
+
+	switch_ships: Option<ui::Button>,
+	recruit: Option<ui::Button>,
+	give_crew: Option<ui::Button>,
+	take_crew: Option<ui::Button>,
+	join_fleet: Option<ui::Button>,
+
+	grab_attempted: bool,
+	// Money when the screen was opened, to sum up the trade afterwards.
+	start_money: Option<i32>,
+	// Net volume bought from each market this visit.
+	traded: Markets<f32>,
+	// Mesh name and its top-down triangles, indexed by equipment_idx.
+	silhouettes: [(Option<String>, Vec<[Point2<f32>; 3]>); 2],
+	// Slot outlines, keyed by (slot position, keel position, slot direction, mount size).
+	slot_frames: ui_layer::UiLayer<
+		Vec<(
+			Point2<f32>,
+			Option<Point2<f32>>,
+			Option<f32>,
+			Option<comps::MountSize>,
+		)>,
+	>,
+	bulk_trade: Option<BulkTrade>,
+	// Player slot to turn to the next side.
+	refit: Option<usize>,
+	// Cargo name and the most that can be bought, for Game to ask how many to buy.
+	quantity_request: Option<(String, i32)>,
+	quantity_slot: Option<usize>,
+	// What to split when switching ships, for Game to ask how.
+	capture_request: Option<ui::CrewSplit>,
+	// Tried to switch to the Voidwind, for Game to explain why that's not happening.
+	voidwind_request: bool,
+	// Crew, wounded and officers to take across to the prize.
+	capture: Option<(i32, i32, i32)>,
+}
+
+impl EquipmentScreen
+{
+	fn new(state: &mut game_state::GameState) -> Self
+	{
+		Self {
+			buffer_width: state.display_width,
+			buffer_height: state.display_height,
+			hover_slot: None,
+			dragged_item: None,
+			mouse_button_down: false,
+			fast_move_click: false,
+			switch_ships: None,
+			recruit: None,
+			give_crew: None,
+			take_crew: None,
+			join_fleet: None,
+			grab_attempted: false,
+			start_money: None,
+			traded: Markets::new(0.),
+			silhouettes: [(None, vec![]), (None, vec![])],
+			slot_frames: ui_layer::UiLayer::new(),
+			bulk_trade: None,
+			refit: None,
+			quantity_request: None,
+			quantity_slot: None,
+			capture_request: None,
+			voidwind_request: false,
+			capture: None,
+		}
+	}
+
+	fn sell_cargo_rect(&self, state: &game_state::GameState) -> [f32; 4]
 	{
-		if map.dock_entity.is_some() && (self.switch_ships.is_none() && self.recruit.is_none())
+		let lh = state.ui_font.get_line_height() as f32;
+		let w = state.ui_font.get_text_width("Sell All Cargo (S)") as f32 + 2. * state.m;
+		let (x, y) = (
+			self.buffer_width * 5. / 6.,
+			self.buffer_height * EQUIPMENT_FRAC + lh / 2.,
+		);
+		[x - w / 2., y, x + w / 2., y + lh * 1.5]
+	}
+
+	// Whether the mouse holds an item or is over one it could pick up.
+	fn grabs(&self, map: &Map) -> bool
+	{
+		if self.dragged_item.is_some()
 		{
-			if let (Ok(dock_state), Ok(player_state)) = (
-				map.world.get::<&comps::ShipState>(map.dock_entity.unwrap()),
-				map.world.get::<&comps::ShipState>(map.player),
-			)
-			{
-				if dock_state.hull > 0.
-					&& dock_state.team != player_state.team
-					&& !player_state.is_boss
+			return true;
+		}
+		self.hover_slot
+			.and_then(|(i, equipment_idx)| {
+				let entity = if equipment_idx == 0
 				{
-					self.switch_ships = Some(Button::new(
-						Point2::new(state.display_width / 3. - 64., 32.),
-						Vector2::new(64., 32.),
-						false,
-						"data/switch.cfg".into(),
-					));
+					map.dock_entity?
 				}
-				if dock_state.team == player_state.team
+				else
 				{
-					self.recruit = Some(Button::new(
-						Point2::new(state.display_width / 3. - 64., 32.),
-						Vector2::new(64., 32.),
-						false,
-						"data/recruit.cfg".into(),
-					));
-				}
-			}
-		}
-		else if map.dock_entity.is_none()
-		{
-			self.switch_ships = None;
-			self.recruit = None;
-		}
-		let do_switch = if let Some(button) = self.switch_ships.as_mut()
+					map.player
+				};
+				let equipment = map.world.get::<&comps::Equipment>(entity).ok()?;
+				Some(equipment.slots[i].item.is_some())
+			})
+			.unwrap_or(false)
+	}
+
+	// Dock slot of the hovered cargo, if any.
+	fn hover_cargo(&self, map: &Map) -> Option<usize>
+	{
+		let (i, equipment_idx) = self.hover_slot?;
+		if equipment_idx != 0
 		{
-			button.logic()
+			return None;
 		}
-		else
-		{
-			false
-		};
-		let do_recruit = if let Some(button) = self.recruit.as_mut()
+		let equipment = map.world.get::<&comps::Equipment>(map.dock_entity?).ok()?;
+		equipment.slots[i]
+			.item
+			.as_ref()
+			.filter(|item| item.kind.is_cargo())
+			.map(|_| i)
+	}
+
+	fn do_bulk_trade(&mut self, bulk_trade: BulkTrade, map: &mut Map, state: &game_state::GameState)
+	{
+		let dock_entity = if let Some(dock_entity) = map.dock_entity
 		{
-			button.logic()
+			dock_entity
 		}
 		else
 		{
-			false
+			return;
 		};
-		let do_trade = self.do_trade(map);
-		let mouse_pos = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
-		self.hover_slot = None;
-		let mut old_item = None;
-		let over_ui = self.over_ui(map, state);
-
 		let mut query = map.world.query::<&mut comps::Equipment>();
 		let mut view = query.view();
-		let [mut dock_equipment, player_equipment] = if let Some(dock_entity) = map.dock_entity
-		{
+		let (dock_equipment, player_equipment) = if let [Some(dock_equipment), Some(player_equipment)] =
 			view.get_mut_n([dock_entity, map.player])
+		{
+			(dock_equipment, player_equipment)
 		}
 		else
 		{
-			[None, view.get_mut(map.player)]
+			return;
 		};
 
+		let message = match bulk_trade
 		{
-			let dock_slots = dock_equipment.iter_mut().flat_map(|eq| eq.slots.iter_mut());
-			let mut fast_move = false;
-			if let Some(equipment) = player_equipment
+			BulkTrade::SellCargo =>
 			{
-				for (i, (equipment_idx, slot)) in
-					(equipment.slots.iter_mut().map(|slot| (1, slot)).enumerate())
-						.chain(dock_slots.map(|slot| (0, slot)).enumerate())
+				let mut count = 0;
+				let mut earned = 0;
+				for slot in &mut player_equipment.slots
 				{
-					if do_trade && equipment_idx == 0 && !slot.is_inventory
+					if !slot.is_inventory
+						|| !slot
+							.item
+							.as_ref()
+							.is_some_and(|i| i.kind.is_cargo() && !i.locked)
 					{
 						continue;
 					}
-
-					let pos = self.get_slot_pos(equipment_idx, slot.pos);
-					let w = SLOT_WIDTH;
-					if mouse_pos.x > pos.x - w / 2.
-						&& mouse_pos.x < pos.x + w / 2.
-						&& mouse_pos.y > pos.y - w / 2.
-						&& mouse_pos.y < pos.y + w / 2.
+					let item = slot.item.take().unwrap();
+					earned += item.price;
+					record_trade(&mut self.traded, &item.kind, -1.);
+					count += 1;
+					// The merchant keeps what fits in their hold, so it can be bought back.
+					if let Some(dock_slot) = dock_equipment
+						.slots
+						.iter_mut()
+						.find(|s| s.is_inventory && s.item.is_none())
 					{
-						if self.mouse_button_down && self.dragged_item.is_none()
-						{
-							// Grab item.
-							// Don't grab if grabbing from trade partner and not enough money.
-							let mut start_grab = true;
-							if equipment_idx == 0 && do_trade
-							{
-								if let Some(item) = slot.item.as_ref()
-								{
-									if item.price > map.money
-									{
-										start_grab = false;
-										if !self.grab_attempted
-										{
-											map.messages.push((
-												"Not enough money!".to_string(),
-												state.time(),
-											));
-										}
-										self.grab_attempted = true;
-									}
-									else
-									{
-										map.money -= item.price;
-									}
-								}
-							}
-							if start_grab
-							{
-								self.dragged_item =
-									slot.item.take().map(|item| (i, equipment_idx, item));
-								if self.dragged_item.is_some()
-								{
-									state.sfx.play_sound("data/equipment.ogg").unwrap();
-								}
-								if self.ctrl_down
-								{
-									fast_move = true;
-								}
-							}
-						}
-						else if !self.mouse_button_down && self.dragged_item.is_some()
-						{
-							state.sfx.play_sound("data/equipment.ogg").unwrap();
-							let is_weapon = if let Some(comps::ItemKind::Weapon(_)) =
-								self.dragged_item.as_ref().map(|(_, _, i)| &i.kind)
-							{
-								true
-							}
-							else
-							{
-								false
-							};
-							if is_weapon && !slot.weapons_allowed
-							{
-								old_item = self.dragged_item.take();
-							}
-							else
-							{
-								let mut do_transaction = true;
-								if let Some(item) = slot.item.as_ref()
-								{
-									if equipment_idx == 0 && do_trade
-									{
-										if map.money >= item.price
-										{
-											map.money -= item.price;
-										}
-										else
-										{
-											do_transaction = false;
-											map.messages.push((
-												"Not enough money!".to_string(),
-												state.time(),
-											));
-										}
-									}
-								}
-								if do_transaction
-								{
-									// Drop item.
-									// If dropping into trade partner's window, grab the money.
-									let (source_i, source_equipment_idx, mut item) =
-										self.dragged_item.take().unwrap();
-									if equipment_idx == 0 && do_trade
-									{
-										map.money += item.price;
-									}
-									old_item = slot
-										.item
-										.take()
-										.map(|item| (source_i, source_equipment_idx, item));
-									item.reset_cooldowns();
-									slot.item = Some(item);
-								}
-								else
-								{
-									old_item = self.dragged_item.take();
-								}
-							}
-						}
-						if !self.mouse_button_down
-						{
-							self.hover_slot = Some((i, equipment_idx));
-						}
+						dock_slot.item = Some(item);
 					}
 				}
-				if !self.mouse_button_down && self.dragged_item.is_some()
+				map.money += earned;
+				if count == 0
 				{
-					old_item = self.dragged_item.take();
+					"No cargo to sell!".to_string()
 				}
-				if let Some((i, equipment_idx, item)) = old_item
+				else
 				{
-					if equipment_idx == 1
+					format!("Sold {count} cargo for £{earned}.")
+				}
+			}
+			BulkTrade::Buy(dock_slot, max_count) =>
+			{
+				let kind = if let Some(item) = dock_equipment.slots[dock_slot].item.as_ref()
+				{
+					item.kind.clone()
+				}
+				else
+				{
+					return;
+				};
+				let mut count = 0;
+				let mut spent = 0;
+				let mut no_money = false;
+				let mut no_room = false;
+				for slot in &mut dock_equipment.slots
+				{
+					if count >= max_count
 					{
-						equipment.slots[i].item = Some(item);
+						break;
 					}
-					else if let Some(dock_equipment) = dock_equipment.as_mut()
+					let price = match slot.item.as_ref()
 					{
-						if do_trade
+						Some(item) if slot.is_inventory && item.kind.same_cargo(&kind) =>
 						{
-							map.money += item.price;
+							item.price
 						}
-
-						dock_equipment.slots[i].item = Some(item);
-					}
-				}
-				if fast_move
-				{
-					let mut moved = false;
-					if let Some((_, equipment_idx, item)) = self.dragged_item.as_ref()
+						_ => continue,
+					};
+					if price > map.money
 					{
-						if *equipment_idx == 1
-						{
-							let item_price = item.price;
-							if let Some(dock_equipment) = dock_equipment.as_mut()
-							{
-								for slot in &mut dock_equipment.slots
-								{
-									if slot.is_inventory && slot.item.is_none()
-									{
-										slot.item = Some(item.clone());
-										moved = true;
-										break;
-									}
-								}
-							}
-							// This is in lieu of the logic for dropping.
-							if do_trade && moved
-							{
-								map.money += item_price;
-							}
-						}
-						else
-						{
-							// We took care of the price when we grabbed it earlier.
-							for slot in &mut equipment.slots
-							{
-								if slot.is_inventory && slot.item.is_none()
-								{
-									slot.item = Some(item.clone());
-									moved = true;
-									break;
-								}
-							}
-						}
+						no_money = true;
+						continue;
 					}
-					if moved
+					let player_slot = if let Some(player_slot) = player_equipment
+						.slots
+						.iter_mut()
+						.find(|s| s.is_inventory && s.item.is_none())
 					{
-						self.dragged_item = None;
+						player_slot
 					}
+					else
+					{
+						no_room = true;
+						break;
+					};
+					let item = slot.item.take().unwrap();
+					map.money -= price;
+					spent += price;
+					record_trade(&mut self.traded, &item.kind, 1.);
+					player_slot.item = Some(item);
+					count += 1;
+				}
+				if count > 0
+				{
+					format!("Bought {count} {} for £{spent}.", kind.name())
+				}
+				else if no_room
+				{
+					"No room in the hold!".to_string()
+				}
+				else if no_money
+				{
+					"Not enough money!".to_string()
+				}
+				else
+				{
+					return;
 				}
 			}
-		}
-		if do_switch || do_recruit
-		{
-			let mut query = map.world.query::<&mut comps::ShipState>();
-			let mut view = query.view();
-			let [mut dock_state, mut player_state] =
-				view.get_mut_n([map.dock_entity.unwrap(), map.player]);
-			if let (Some(dock_state), Some(player_state)) =
-				(dock_state.as_mut(), player_state.as_mut())
+			BulkTrade::AskQuantity(dock_slot) =>
 			{
-				if do_switch
+				let kind = if let Some(item) = dock_equipment.slots[dock_slot].item.as_ref()
 				{
-					let player_crew = player_state.crew;
-					let player_wounded = player_state.wounded;
-					let player_experience = player_state.experience;
-					let player_team = player_state.team;
-
-					player_state.crew = dock_state.crew;
-					player_state.wounded = dock_state.wounded;
-					player_state.experience = dock_state.experience;
-					player_state.team = dock_state.team;
-
-					dock_state.crew = player_crew;
-					dock_state.wounded = player_wounded;
-					dock_state.experience = player_experience;
-					dock_state.team = player_team;
-
-					if dock_state.is_boss
-					{
-						map.messages
-							.push(("You are now a slave to the Voidwind!".into(), state.time()));
-						map.messages.push((
-							"Now that you are on board, you can never leave...".into(),
-							state.time(),
-						));
-						dock_state.team = comps::Team::Pirate;
-					}
+					item.kind.clone()
 				}
-				if do_recruit
+				else
 				{
-					if let (Ok(dock_stats), Ok(player_stats)) = (
-						map.world.get::<&comps::ShipStats>(map.dock_entity.unwrap()),
-						map.world.get::<&comps::ShipStats>(map.player),
-					)
+					return;
+				};
+				let free_slots = player_equipment
+					.slots
+					.iter()
+					.filter(|s| s.is_inventory && s.item.is_none())
+					.count() as i32;
+				// In slot order, the same order Buy goes through them in.
+				let mut max_count = 0;
+				let mut money = map.money;
+				for item in dock_equipment
+					.slots
+					.iter()
+					.filter(|s| s.is_inventory)
+					.filter_map(|s| s.item.as_ref())
+					.filter(|item| item.kind.same_cargo(&kind))
+				{
+					if max_count < free_slots && item.price <= money
 					{
-						if dock_state.crew < dock_stats.crew * 2 / 3
-						{
-							map.messages
-								.push(("Not enough crew to recruit!".to_string(), state.time()));
-						}
-						else if map.money < dock_state.level * CREW_COST
-						{
-							map.messages
-								.push(("Not enough money!".to_string(), state.time()));
-						}
-						else if player_state.crew >= player_stats.crew
-						{
-							map.messages
-								.push(("No room for more crew!".to_string(), state.time()));
-						}
-						else
-						{
-							let player_count = (player_state.crew + player_state.wounded) as f32;
-							let new_experience =
-								(player_count * player_state.experience + 1.) / (player_count + 1.);
-							dock_state.crew -= 1;
-							player_state.crew += 1;
-							player_state.experience = new_experience;
-							player_state.compute_level();
-							//dbg!(player_state.experience);
-							map.money -= dock_state.level * CREW_COST;
-						}
+						money -= item.price;
+						max_count += 1;
 					}
 				}
-			}
-
-			if do_switch
-			{
-				let player = map.player;
-				map.player = map.dock_entity.unwrap();
-				map.dock_entity = Some(player);
-				if map.target_entity.is_some()
+				if max_count > 0
 				{
-					map.target_entity = Some(map.dock_entity.unwrap());
+					self.quantity_request = Some((kind.name().to_string(), max_count));
+					self.quantity_slot = Some(dock_slot);
+					return;
+				}
+				else if free_slots == 0
+				{
+					"No room in the hold!".to_string()
+				}
+				else
+				{
+					"Not enough money!".to_string()
 				}
 			}
-		}
-		!over_ui
+		};
+		map.messages
+			.add(message::MessageKind::Trade, message, state.time());
 	}
 
-	fn finish_trade(&mut self, map: &mut Map)
+	fn refit_cost(state: &game_state::GameState) -> i32
 	{
-		let do_trade = self.do_trade(map);
+		(REFIT_COST as f32 * state.modifiers.merchant_markup) as i32
+	}
 
-		{
-			let mut query = map.world.query::<&mut comps::Equipment>();
-			let mut view = query.view();
-			let [dock_equipment, player_equipment] = if let Some(dock_entity) = map.dock_entity
-			{
-				view.get_mut_n([dock_entity, map.player])
-			}
-			else
+	// Turns a mount a quarter turn to port, e.g. a stern chaser into a broadside. This changes
+	// only this ship, not others built from the same desc.
+	fn do_refit(&mut self, slot_idx: usize, map: &mut Map, state: &mut game_state::GameState)
+	{
+		let cost = Self::refit_cost(state);
+		let message = {
+			let mut equipment = match map.world.get::<&mut comps::Equipment>(map.player)
 			{
-				[None, view.get_mut(map.player)]
+				Ok(equipment) => equipment,
+				Err(_) => return,
 			};
-
-			if let Some(equipment) = player_equipment
+			let slot = &mut equipment.slots[slot_idx];
+			match slot.dir
 			{
-				if let Some((i, equipment_idx, item)) = self.dragged_item.take()
+				Some(dir) if !slot.is_inventory =>
 				{
-					if equipment_idx == 1
+					if map.money < cost
 					{
-						equipment.slots[i].item = Some(item);
+						"Not enough money!".to_string()
 					}
-					else if let Some(dock_equipment) = dock_equipment
+					else
 					{
-						// When returning item to the trade partner, refund the price.
-						if do_trade
+						map.money -= cost;
+						let dir = (dir + PI / 2.).rem_euclid(2. * PI);
+						slot.dir = Some(dir);
+						if let Some(item) = slot.item.as_mut()
 						{
-							map.money += item.price;
+							item.reset_cooldowns();
 						}
-						dock_equipment.slots[i].item = Some(item);
+						format!(
+							"Refitted the mount to face {} for £{cost}.",
+							slot_side(Some(dir)).to_lowercase()
+						)
 					}
 				}
+				_ => return,
 			}
-		}
-		if let (Ok(mut ship_state), Ok(stats)) = (
-			map.world.get::<&mut comps::ShipState>(map.player),
-			map.world.get::<&comps::ShipStats>(map.player),
-		)
+		};
+		state.sfx.play_sound("data/equipment.ogg").unwrap();
+		map.messages
+			.add(message::MessageKind::Trade, message, state.time());
+	}
+
+	fn update_silhouettes(&mut self, map: &Map, state: &game_state::GameState)
+	{
+		for (equipment_idx, entity) in [map.dock_entity, Some(map.player)].iter().enumerate()
 		{
-			let overflow = ship_state.crew + ship_state.wounded - stats.crew;
-			if overflow > 0
-			{
-				// ...I guess we dump them overboard? LOL...
-				ship_state.wounded -= overflow.min(ship_state.wounded);
-			}
-			let overflow = ship_state.crew + ship_state.wounded - stats.crew;
-			if overflow > 0
+			let mesh = entity
+				.and_then(|e| map.world.get::<&comps::Mesh>(e).ok())
+				.map(|mesh| mesh.mesh.clone());
+			if mesh != self.silhouettes[equipment_idx].0
 			{
-				ship_state.crew -= overflow;
+				let triangles = mesh
+					.as_ref()
+					.and_then(|mesh| state.get_mesh(mesh).ok())
+					.map(|mesh| mesh.top_down_triangles())
+					.unwrap_or_default();
+				self.silhouettes[equipment_idx] = (mesh, triangles);
 			}
 		}
 	}
 
-	fn draw(&self, map: &Map, state: &game_state::GameState)
+	fn draw_silhouette(&self, equipment_idx: i32, state: &game_state::GameState)
 	{
-		let m = state.m;
-		let lh = state.ui_font.get_line_height() as f32;
-		let ui_color = ui::ui_color();
+		draw_silhouette(
+			&self.silhouettes[equipment_idx as usize].1,
+			|p| self.get_slot_pos(equipment_idx, p),
+			state,
+		);
+	}
+
+	fn get_slot_pos(&self, equipment_idx: i32, real_pos: Point2<f32>) -> Point2<f32>
+	{
+		let (bw, bh) = (self.buffer_width, self.buffer_height);
+		Point2::new(-real_pos.y, -real_pos.x) * 32.
+			+ Vector2::new(bw / 6. + bw * 2. / 3. * equipment_idx as f32, bh / 4.)
+	}
+
+	fn over_ui(&self, map: &mut Map, state: &game_state::GameState) -> bool
+	{
+		let mouse_pos = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
+		let in_right = mouse_pos.x > self.buffer_width * 2. / 3.
+			&& mouse_pos.y < self.buffer_height * EQUIPMENT_FRAC;
+		let in_left = mouse_pos.x < self.buffer_width * 1. / 3.
+			&& mouse_pos.y < self.buffer_height * EQUIPMENT_FRAC;
 		if map.dock_entity.is_some()
 		{
-			state.prim.draw_filled_rectangle(
-				0.,
-				0.,
-				self.buffer_width * 1. / 3.,
-				self.buffer_height * EQUIPMENT_FRAC,
-				Color::from_rgb_f(0.1, 0.1, 0.2),
-			);
+			in_left || in_right
 		}
-		state.prim.draw_filled_rectangle(
-			self.buffer_width * 2. / 3.,
-			0.,
-			self.buffer_width,
-			self.buffer_height * EQUIPMENT_FRAC,
-			Color::from_rgb_f(0.1, 0.1, 0.2),
-		);
-		let do_trade = self.do_trade(map);
-		let mouse_pos = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
-		let crew_level = map
-			.dock_entity
-			.and_then(|e| map.world.get::<&comps::ShipState>(e).ok())
-			.map(|ss| ss.level)
-			.unwrap_or(1);
+		else
+		{
+			in_right
+		}
+	}
 
-		let mut query = map.world.query::<&comps::Equipment>();
-		let view = query.view();
-		let [dock_equipment, player_equipment] = if let Some(dock_entity) = map.dock_entity
+	fn input(&mut self, event: &Event, map: &mut Map, state: &mut game_state::GameState) -> bool
+	{
+		if let Some(button) = self.switch_ships.as_mut()
 		{
-			[view.get(dock_entity), view.get(map.player)]
+			button.input(state, event);
 		}
-		else
+		if let Some(button) = self.recruit.as_mut()
 		{
-			[None, view.get(map.player)]
-		};
-		let dock_slots = dock_equipment.iter().flat_map(|eq| eq.slots.iter());
-		if let Some(equipment) = player_equipment
+			button.input(state, event);
+		}
+		if let Some(button) = self.give_crew.as_mut()
 		{
-			let mut hover_item = None;
-			for (i, (equipment_idx, slot)) in
-				(equipment.slots.iter().map(|slot| (1, slot)).enumerate())
-					.chain(dock_slots.map(|slot| (0, slot)).enumerate())
+			button.input(state, event);
+		}
+		if let Some(button) = self.take_crew.as_mut()
+		{
+			button.input(state, event);
+		}
+		if let Some(button) = self.join_fleet.as_mut()
+		{
+			button.input(state, event);
+		}
+		match *event
+		{
+			Event::MouseButtonDown {
+				button: 1, x, y, ..
+			} =>
 			{
-				if do_trade && equipment_idx == 0 && !slot.is_inventory
+				let (x, y) = (x as f32, y as f32);
+				let r = self.sell_cargo_rect(state);
+				if self.do_trade(map) && x > r[0] && x < r[2] && y > r[1] && y < r[3]
 				{
-					continue;
+					state.sfx.play_sound("data/ui2.ogg").unwrap();
+					self.bulk_trade = Some(BulkTrade::SellCargo);
+					return true;
 				}
-				let pos = self.get_slot_pos(equipment_idx, slot.pos);
-				if let Some(item) = &slot.item
+				if self.over_ui(map, state)
 				{
-					if Some((i, equipment_idx)) == self.hover_slot
-					{
-						hover_item = Some((pos, equipment_idx, item.clone()));
-					}
+					self.mouse_button_down = true;
+					self.fast_move_click =
+						state.controls.matches(controls::Action::FastMove, event);
+					return true;
 				}
-				let w = SLOT_WIDTH;
-				state.prim.draw_rounded_rectangle(
-					pos.x - w / 2.,
-					pos.y - w / 2.,
-					pos.x + w / 2.,
-					pos.y + w / 2.,
-					8.,
-					8.,
-					ui_color,
-					3.,
-				);
-
-				if let Some(item) = slot.item.as_ref()
+			}
+			Event::KeyDown {
+				keycode: keycode @ (KeyCode::S | KeyCode::B | KeyCode::N),
+				..
+			} if self.do_trade(map) && self.dragged_item.is_none() =>
+			{
+				let hover_cargo = self.hover_cargo(map);
+				match (keycode, hover_cargo)
 				{
-					draw_item(pos.x, pos.y, &item.kind, state);
+					(KeyCode::S, _) => self.bulk_trade = Some(BulkTrade::SellCargo),
+					(KeyCode::B, Some(slot)) =>
+					{
+						self.bulk_trade = Some(BulkTrade::Buy(slot, i32::MAX))
+					}
+					(KeyCode::N, Some(slot)) =>
+					{
+						self.bulk_trade = Some(BulkTrade::AskQuantity(slot))
+					}
+					_ => return false,
 				}
-				if let Some(slot_dir) = slot.dir
+				return true;
+			}
+			Event::KeyDown {
+				keycode: keycode @ (KeyCode::L | KeyCode::J),
+				..
+			} if self.dragged_item.is_none() =>
+			{
+				if let (Some((i, 1)), Ok(mut equipment)) = (
+					self.hover_slot,
+					map.world.get::<&mut comps::Equipment>(map.player),
+				)
 				{
-					let arc = PI / 4.;
-					state.prim.draw_arc(
-						pos.x,
-						pos.y,
-						w,
-						-slot_dir - arc / 2. + PI * 3. / 2.,
-						arc,
-						ui_color,
-						4.,
-					);
+					if let Some(item) = equipment.slots[i].item.as_mut()
+					{
+						if keycode == KeyCode::L
+						{
+							item.locked = !item.locked;
+							item.junk &= !item.locked;
+						}
+						else
+						{
+							item.junk = !item.junk;
+							item.locked &= !item.junk;
+						}
+						state.sfx.play_sound("data/ui2.ogg").unwrap();
+						return true;
+					}
 				}
 			}
-
-			if let Some((pos, equipment_idx, item)) = hover_item
+			Event::KeyDown {
+				keycode: KeyCode::O,
+				..
+			} if self.dragged_item.is_none() =>
 			{
-				let ui_color = ui::ui_color();
-				let price_desc = if do_trade
+				if let (Some((i, 1)), Ok(mut equipment)) = (
+					self.hover_slot,
+					map.world.get::<&mut comps::Equipment>(map.player),
+				)
 				{
-					let price = item.price;
-					vec![
-						(format!("Price: {price}"), Color::from_rgb_f(1., 0.6, 0.2)),
-						("".into(), ui_color),
-					]
+					let slot = &mut equipment.slots[i];
+					if !slot.is_inventory
+						&& matches!(
+							slot.item.as_ref().map(|item| &item.kind),
+							Some(comps::ItemKind::Weapon(_))
+						)
+					{
+						slot.disabled = !slot.disabled;
+						state.sfx.play_sound("data/ui2.ogg").unwrap();
+						return true;
+					}
 				}
-				else
-				{
-					vec![]
-				};
-
-				let name = vec![(item.kind.name(), item.kind.color())];
-				let desc = item.kind.description();
-
-				let lines: Vec<_> = price_desc
-					.iter()
-					.map(|(s, c)| (s.as_str(), *c))
-					.chain(name.iter().map(|(s, c)| (*s, *c)))
-					.chain(desc.lines().map(|s| (s, ui_color)))
-					.collect();
-
-				state.prim.draw_filled_rectangle(
-					pos.x + m * 16. * [1., -1.][equipment_idx as usize],
-					pos.y,
-					pos.x,
-					pos.y + m * (lines.len() as f32 + 2.),
-					Color::from_rgba_f(0., 0., 0., 0.75),
-				);
-
-				let x = pos.x + m * 8. * [1., -1.][equipment_idx as usize];
-				let mut y = pos.y + m * 1.;
-
-				for (line, color) in lines
+			}
+			Event::KeyDown {
+				keycode: KeyCode::R,
+				..
+			} if self.do_trade(map) && self.dragged_item.is_none() =>
+			{
+				if let Some((i, 1)) = self.hover_slot
 				{
-					state
-						.core
-						.draw_text(&state.ui_font, color, x, y, FontAlign::Centre, line);
-					y += lh;
+					self.refit = Some(i);
+					return true;
 				}
 			}
-
-			if let Some((_, _, ref item)) = self.dragged_item
+			Event::MouseButtonUp { button: 1, .. } =>
 			{
-				draw_item(mouse_pos.x, mouse_pos.y, &item.kind, state);
+				self.grab_attempted = false;
+				self.mouse_button_down = false;
 			}
+			_ => (),
 		}
+		false
+	}
 
-		if let Some(button) = self.switch_ships.as_ref()
+	fn is_transfer(&self, map: &Map) -> bool
+	{
+		map.dock_entity
+			.map(|dock_entity| map.world.get::<&comps::Fleet>(dock_entity).is_ok())
+			.unwrap_or(false)
+	}
+
+	// What an allied captain asks to put her under your command, if she's willing.
+	fn join_cost(&self, map: &Map) -> Option<i32>
+	{
+		let dock_entity = map.dock_entity?;
+		if self.is_transfer(map)
 		{
-			button.draw(state);
-			state.core.draw_text(
-				&state.ui_font,
-				Color::from_rgb_f(1., 1., 1.),
-				button.loc.x - button.size.x,
-				button.loc.y - lh / 2.,
-				FontAlign::Right,
-				"Switch Ships",
-			);
+			return None;
 		}
-		if let Some(button) = self.recruit.as_ref()
+		let dock_state = map.world.get::<&comps::ShipState>(dock_entity).ok()?;
+		let player_state = map.world.get::<&comps::ShipState>(map.player).ok()?;
+		if dock_state.hull > 0.
+			&& dock_state.team == player_state.team
+			&& dock_state.team != comps::Team::Neutral
+			&& !dock_state.is_boss
+			&& !player_state.is_boss
 		{
-			button.draw(state);
-			state.core.draw_text(
-				&state.ui_font,
-				Color::from_rgb_f(1., 1., 1.),
-				button.loc.x - button.size.x,
-				button.loc.y - lh / 2.,
-				FontAlign::Right,
-				&format!("Recruit Crew £{}", crew_level * CREW_COST),
-			);
+			Some(dock_state.crew.max(1) * dock_state.level * CREW_COST)
+		}
+		else
+		{
+			None
 		}
 	}
-}
-
-fn draw_item(x: f32, y: f32, item_kind: &comps::ItemKind, state: &game_state::GameState)
-{
-	item_kind.draw(Point2::new(x, y), state);
-}
-
-fn draw_ship_state(
-	ship_state: &comps::ShipState, stats: &comps::ShipStats, x: f32, y: f32,
-	state: &game_state::GameState,
-)
-{
-	let mut y = y;
-	let ui_color = ui::ui_color();
 
-	let m = state.m;
-	let lh = state.ui_font.get_line_height() as f32;
+	fn do_trade(&self, map: &Map) -> bool
+	{
+		map.can_trade_with_dock()
+	}
 
-	state.core.draw_text(
-		&state.ui_font,
+	fn logic(&mut self, map: &mut Map, state: &mut game_state::GameState) -> bool
+	{
+		self.update_silhouettes(map, state);
+		let is_transfer = self.is_transfer(map);
+		let join_cost = self.join_cost(map);
+		if map.dock_entity.is_some()
+			&& (self.switch_ships.is_none()
+				&& self.recruit.is_none()
+				&& self.give_crew.is_none()
+				&& self.take_crew.is_none()
+				&& self.join_fleet.is_none())
+		{
+			if let (Ok(dock_state), Ok(player_state)) = (
+				map.world.get::<&comps::ShipState>(map.dock_entity.unwrap()),
+				map.world.get::<&comps::ShipState>(map.player),
+			)
+			{
+				if dock_state.hull > 0.
+					&& dock_state.team != player_state.team
+					&& !player_state.is_boss
+				{
+					self.switch_ships = Some(ui::Button::new_sprite(
+						Point2::new(state.display_width / 3. - 64., 32.),
+						Vector2::new(64., 32.),
+						false,
+						"data/switch.cfg",
+					));
+				}
+				if is_transfer
+				{
+					self.give_crew = Some(ui::Button::new_sprite(
+						Point2::new(state.display_width / 3. - 64., 32.),
+						Vector2::new(64., 32.),
+						false,
+						"data/give_crew.cfg",
+					));
+					self.take_crew = Some(ui::Button::new_sprite(
+						Point2::new(state.display_width / 3. - 64., 80.),
+						Vector2::new(64., 32.),
+						false,
+						"data/take_crew.cfg",
+					));
+				}
+				else if dock_state.team == player_state.team
+				{
+					self.recruit = Some(ui::Button::new_sprite(
+						Point2::new(state.display_width / 3. - 64., 32.),
+						Vector2::new(64., 32.),
+						false,
+						"data/recruit.cfg",
+					));
+					if join_cost.is_some()
+					{
+						self.join_fleet = Some(ui::Button::new_sprite(
+							Point2::new(state.display_width / 3. - 64., 80.),
+							Vector2::new(64., 32.),
+							false,
+							"data/join_fleet.cfg",
+						));
+					}
+				}
+			}
+		}
+		else if map.dock_entity.is_none()
+		{
+			self.switch_ships = None;
+			self.recruit = None;
+			self.give_crew = None;
+			self.take_crew = None;
+			self.join_fleet = None;
+		}
+		let mut switch = None;
+		let boss_dock = map
+			.dock_entity
+			.and_then(|dock| map.world.get::<&comps::ShipState>(dock).ok())
+			.map(|dock_state| dock_state.is_boss)
+			.unwrap_or(false);
+		if self
+			.switch_ships
+			.as_mut()
+			.is_some_and(|button| button.logic())
+		{
+			if boss_dock
+			{
+				self.voidwind_request = true;
+			}
+			else if let Some((split, can_leave)) = self.crew_split(map)
+			{
+				if can_leave
+				{
+					self.capture_request = Some(split);
+				}
+				else
+				{
+					switch = Some((
+						split.crew,
+						split.wounded,
+						split.officers.min(split.officer_berths),
+					));
+				}
+			}
+		}
+		if self.switch_ships.is_some()
+		{
+			switch = self.capture.take().or(switch);
+		}
+		// Nothing gets the player aboard the Voidwind.
+		if boss_dock
+		{
+			switch = None;
+		}
+		let do_switch = switch.is_some();
+		let split = switch
+			.and_then(|_| self.crew_split(map))
+			.map(|(split, _)| split);
+		let do_recruit = if let Some(button) = self.recruit.as_mut()
+		{
+			button.logic()
+		}
+		else
+		{
+			false
+		};
+		let do_give_crew = if let Some(button) = self.give_crew.as_mut()
+		{
+			button.logic()
+		}
+		else
+		{
+			false
+		};
+		let do_take_crew = if let Some(button) = self.take_crew.as_mut()
+		{
+			button.logic()
+		}
+		else
+		{
+			false
+		};
+		let do_join_fleet = if let Some(button) = self.join_fleet.as_mut()
+		{
+			button.logic()
+		}
+		else
+		{
+			false
+		};
+		let do_trade = self.do_trade(map);
+		if do_trade && self.start_money.is_none()
+		{
+			self.start_money = Some(map.money);
+		}
+		if let Some(bulk_trade) = self.bulk_trade.take().filter(|_| do_trade)
+		{
+			self.do_bulk_trade(bulk_trade, map, state);
+		}
+		if let Some(slot_idx) = self.refit.take().filter(|_| do_trade)
+		{
+			self.do_refit(slot_idx, map, state);
+		}
+		let mouse_pos = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
+		self.hover_slot = None;
+		let mut old_item = None;
+		let over_ui = self.over_ui(map, state);
+
+		{
+			let mut query = map.world.query::<&mut comps::Equipment>();
+			let mut view = query.view();
+			let [mut dock_equipment, player_equipment] = if let Some(dock_entity) = map.dock_entity
+			{
+				view.get_mut_n([dock_entity, map.player])
+			}
+			else
+			{
+				[None, view.get_mut(map.player)]
+			};
+
+			let mut fast_move = false;
+			if let Some(equipment) = player_equipment
+			{
+				// Mount the dragged item came from, which whatever it's swapped with goes into.
+				let source_size = self
+					.dragged_item
+					.as_ref()
+					.and_then(|(i, equipment_idx, _)| {
+						let slot = if *equipment_idx == 1
+						{
+							&equipment.slots[*i]
+						}
+						else
+						{
+							&dock_equipment.as_ref()?.slots[*i]
+						};
+						(!slot.is_inventory).then_some(slot.size)
+					});
+				let source_rigging =
+					self.dragged_item
+						.as_ref()
+						.and_then(|(i, equipment_idx, _)| {
+							let slot = if *equipment_idx == 1
+							{
+								&equipment.slots[*i]
+							}
+							else
+							{
+								&dock_equipment.as_ref()?.slots[*i]
+							};
+							(!slot.is_inventory).then_some(slot.rigging)
+						});
+				let dock_slots = dock_equipment.iter_mut().flat_map(|eq| eq.slots.iter_mut());
+				for (i, (equipment_idx, slot)) in
+					(equipment.slots.iter_mut().map(|slot| (1, slot)).enumerate())
+						.chain(dock_slots.map(|slot| (0, slot)).enumerate())
+				{
+					if do_trade && equipment_idx == 0 && !slot.is_inventory
+					{
+						continue;
+					}
+
+					let pos = self.get_slot_pos(equipment_idx, slot.pos);
+					let w = SLOT_WIDTH;
+					if mouse_pos.x > pos.x - w / 2.
+						&& mouse_pos.x < pos.x + w / 2.
+						&& mouse_pos.y > pos.y - w / 2.
+						&& mouse_pos.y < pos.y + w / 2.
+					{
+						if self.mouse_button_down && self.dragged_item.is_none()
+						{
+							// Grab item.
+							// Don't grab if grabbing from trade partner and not enough money.
+							let mut start_grab = true;
+							if equipment_idx == 0 && do_trade
+							{
+								if let Some(item) = slot.item.as_ref()
+								{
+									if item.price > map.money
+									{
+										start_grab = false;
+										if !self.grab_attempted
+										{
+											map.messages.add(
+												message::MessageKind::Trade,
+												"Not enough money!".to_string(),
+												state.time(),
+											);
+										}
+										self.grab_attempted = true;
+									}
+									else
+									{
+										map.money -= item.price;
+										record_trade(&mut self.traded, &item.kind, 1.);
+									}
+								}
+							}
+							if start_grab
+							{
+								self.dragged_item =
+									slot.item.take().map(|item| (i, equipment_idx, item));
+								if self.dragged_item.is_some()
+								{
+									state.sfx.play_sound("data/equipment.ogg").unwrap();
+								}
+								if self.fast_move_click
+									&& !self
+										.dragged_item
+										.as_ref()
+										.is_some_and(|(_, _, item)| item.locked)
+								{
+									fast_move = true;
+								}
+							}
+						}
+						else if !self.mouse_button_down && self.dragged_item.is_some()
+						{
+							state.sfx.play_sound("data/equipment.ogg").unwrap();
+							let weapon_size = if let Some(comps::ItemKind::Weapon(weapon)) =
+								self.dragged_item.as_ref().map(|(_, _, i)| &i.kind)
+							{
+								Some(weapon.size)
+							}
+							else
+							{
+								None
+							};
+							let swapped_size = if let Some(comps::ItemKind::Weapon(weapon)) =
+								slot.item.as_ref().map(|i| &i.kind)
+							{
+								Some(weapon.size)
+							}
+							else
+							{
+								None
+							};
+							let too_big = weapon_size
+								.filter(|size| !slot.is_inventory && !slot.size.fits(*size))
+								.map(|size| (size, slot.size))
+								.or_else(|| {
+									swapped_size
+										.zip(source_size)
+										.filter(|(size, source_size)| !source_size.fits(*size))
+								});
+							// Locked items can't end up with the other ship.
+							let locked = if let Some((_, source_equipment_idx, item)) =
+								self.dragged_item.as_ref()
+							{
+								(equipment_idx == 0 && item.locked)
+									|| (*source_equipment_idx == 0
+										&& slot.item.as_ref().is_some_and(|item| item.locked))
+							}
+							else
+							{
+								false
+							};
+							// Rigging goes in rigging slots and nothing else does, either way round
+							// for a swap.
+							let is_rigging = |item: Option<&comps::Item>| {
+								matches!(
+									item.map(|item| &item.kind),
+									Some(comps::ItemKind::Rigging(_))
+								)
+							};
+							let dragged_rigging =
+								is_rigging(self.dragged_item.as_ref().map(|(_, _, item)| item));
+							let wrong_slot = (!slot.is_inventory
+								&& dragged_rigging != slot.rigging)
+								|| (slot.item.is_some()
+									&& source_rigging
+										.is_some_and(|r| r != is_rigging(slot.item.as_ref())));
+							if weapon_size.is_some() && !slot.weapons_allowed
+							{
+								old_item = self.dragged_item.take();
+							}
+							else if wrong_slot
+							{
+								old_item = self.dragged_item.take();
+								map.messages.add(
+									message::MessageKind::Trade,
+									"Only rigging goes in a rigging slot!".to_string(),
+									state.time(),
+								);
+							}
+							else if locked
+							{
+								old_item = self.dragged_item.take();
+								map.messages.add(
+									message::MessageKind::Trade,
+									"That item is locked!".to_string(),
+									state.time(),
+								);
+							}
+							else if let Some((size, mount_size)) = too_big
+							{
+								old_item = self.dragged_item.take();
+								map.messages.add(
+									message::MessageKind::Trade,
+									format!(
+										"That's a {} gun, it won't fit a {} mount!",
+										size.name().to_lowercase(),
+										mount_size.name().to_lowercase()
+									),
+									state.time(),
+								);
+							}
+							else
+							{
+								let mut do_transaction = true;
+								if let Some(item) = slot.item.as_ref()
+								{
+									if equipment_idx == 0 && do_trade
+									{
+										if map.money >= item.price
+										{
+											map.money -= item.price;
+											record_trade(&mut self.traded, &item.kind, 1.);
+										}
+										else
+										{
+											do_transaction = false;
+											map.messages.add(
+												message::MessageKind::Trade,
+												"Not enough money!".to_string(),
+												state.time(),
+											);
+										}
+									}
+								}
+								if do_transaction
+								{
+									// Drop item.
+									// If dropping into trade partner's window, grab the money.
+									let (source_i, source_equipment_idx, mut item) =
+										self.dragged_item.take().unwrap();
+									if equipment_idx == 0 && do_trade
+									{
+										map.money += item.price;
+										record_trade(&mut self.traded, &item.kind, -1.);
+									}
+									old_item = slot
+										.item
+										.take()
+										.map(|item| (source_i, source_equipment_idx, item));
+									item.reset_cooldowns();
+									slot.item = Some(item);
+								}
+								else
+								{
+									old_item = self.dragged_item.take();
+								}
+							}
+						}
+						if !self.mouse_button_down
+						{
+							self.hover_slot = Some((i, equipment_idx));
+						}
+					}
+				}
+				if !self.mouse_button_down && self.dragged_item.is_some()
+				{
+					old_item = self.dragged_item.take();
+				}
+				if let Some((i, equipment_idx, item)) = old_item
+				{
+					if equipment_idx == 1
+					{
+						equipment.slots[i].item = Some(item);
+					}
+					else if let Some(dock_equipment) = dock_equipment.as_mut()
+					{
+						if do_trade
+						{
+							map.money += item.price;
+							record_trade(&mut self.traded, &item.kind, -1.);
+						}
+
+						dock_equipment.slots[i].item = Some(item);
+					}
+				}
+				if fast_move
+				{
+					let mut moved = false;
+					if let Some((_, equipment_idx, item)) = self.dragged_item.as_ref()
+					{
+						if *equipment_idx == 1
+						{
+							let item_price = item.price;
+							if let Some(dock_equipment) = dock_equipment.as_mut()
+							{
+								for slot in &mut dock_equipment.slots
+								{
+									if slot.is_inventory && slot.item.is_none()
+									{
+										slot.item = Some(item.clone());
+										moved = true;
+										break;
+									}
+								}
+							}
+							// This is in lieu of the logic for dropping.
+							if do_trade && moved
+							{
+								map.money += item_price;
+								record_trade(&mut self.traded, &item.kind, -1.);
+							}
+						}
+						else
+						{
+							// We took care of the price when we grabbed it earlier.
+							for slot in &mut equipment.slots
+							{
+								if slot.is_inventory && slot.item.is_none()
+								{
+									slot.item = Some(item.clone());
+									moved = true;
+									break;
+								}
+							}
+						}
+					}
+					if moved
+					{
+						self.dragged_item = None;
+					}
+				}
+			}
+		}
+		let mut prize_crew = false;
+		if do_switch || do_recruit || do_give_crew || do_take_crew
+		{
+			let mut query = map.world.query::<&mut comps::ShipState>();
+			let mut view = query.view();
+			let [mut dock_state, mut player_state] =
+				view.get_mut_n([map.dock_entity.unwrap(), map.player]);
+			if let (Some(dock_state), Some(player_state)) =
+				(dock_state.as_mut(), player_state.as_mut())
+			{
+				if let (Some((crew, wounded, _)), Some(split)) = (switch, split.as_ref())
+				{
+					// Whoever's left aboard the prize gets pressed into the crew, and whoever
+					// doesn't fit takes the old ship, unless a prize crew stays behind on her.
+					let (pressed_crew, pressed_wounded) = split.pressed(crew, wounded);
+					let leave_crew = player_state.crew - crew;
+					let leave_wounded = player_state.wounded - wounded;
+					let player_experience = player_state.experience;
+					let player_proficiency = player_state.proficiency;
+					let player_team = player_state.team;
+
+					player_state.crew = dock_state.crew - pressed_crew;
+					player_state.wounded = dock_state.wounded - pressed_wounded;
+					player_state.experience = dock_state.experience;
+					player_state.proficiency = dock_state.proficiency;
+					player_state.team = dock_state.team;
+
+					dock_state.crew = crew + pressed_crew;
+					dock_state.wounded = wounded + pressed_wounded;
+					dock_state.experience =
+						split.average(crew, wounded, player_experience, dock_state.experience);
+					for (p, ours) in dock_state.proficiency.iter_mut().zip(player_proficiency)
+					{
+						*p = split.average(crew, wounded, ours, *p);
+					}
+					dock_state.team = player_team;
+					dock_state.compute_level();
+					if pressed_crew + pressed_wounded > 0
+					{
+						map.messages.add(
+							message::MessageKind::System,
+							format!(
+								"Pressed {} of her hands into service.",
+								pressed_crew + pressed_wounded
+							),
+							state.time(),
+						);
+					}
+
+					if leave_crew > 0
+					{
+						player_state.crew = leave_crew;
+						player_state.wounded = leave_wounded;
+						player_state.experience = player_experience;
+						player_state.proficiency = player_proficiency;
+						player_state.team = player_team;
+						player_state.compute_level();
+						prize_crew = true;
+						map.messages.add(
+							message::MessageKind::System,
+							format!("Left {leave_crew} crew aboard your old ship."),
+							state.time(),
+						);
+					}
+				}
+				if do_recruit
+				{
+					if let (Ok(dock_stats), Ok(player_stats)) = (
+						map.world.get::<&comps::ShipStats>(map.dock_entity.unwrap()),
+						map.world.get::<&comps::ShipStats>(map.player),
+					)
+					{
+						if dock_state.crew < dock_stats.crew * 2 / 3
+						{
+							map.messages.add(
+								message::MessageKind::Trade,
+								"Not enough crew to recruit!".to_string(),
+								state.time(),
+							);
+						}
+						else if map.money < dock_state.level * CREW_COST
+						{
+							map.messages.add(
+								message::MessageKind::Trade,
+								"Not enough money!".to_string(),
+								state.time(),
+							);
+						}
+						else if player_state.crew >= player_stats.crew
+						{
+							map.messages.add(
+								message::MessageKind::System,
+								"No room for more crew!".to_string(),
+								state.time(),
+							);
+						}
+						else
+						{
+							let player_count = (player_state.crew + player_state.wounded) as f32;
+							let new_experience =
+								(player_count * player_state.experience + 1.) / (player_count + 1.);
+							dock_state.crew -= 1;
+							player_state.crew += 1;
+							player_state.experience = new_experience;
+							for p in &mut player_state.proficiency
+							{
+								*p = (player_count * *p + 1.) / (player_count + 1.);
+							}
+							player_state.compute_level();
+							//dbg!(player_state.experience);
+							map.money -= dock_state.level * CREW_COST;
+						}
+					}
+				}
+				if do_give_crew || do_take_crew
+				{
+					if let (Ok(dock_stats), Ok(player_stats)) = (
+						map.world.get::<&comps::ShipStats>(map.dock_entity.unwrap()),
+						map.world.get::<&comps::ShipStats>(map.player),
+					)
+					{
+						let (from_state, to_state, to_stats) = if do_give_crew
+						{
+							(player_state, dock_state, &*dock_stats)
+						}
+						else
+						{
+							(dock_state, player_state, &*player_stats)
+						};
+						if from_state.crew <= 1
+						{
+							map.messages.add(
+								message::MessageKind::System,
+								"A ship needs at least one hand aboard!".to_string(),
+								state.time(),
+							);
+						}
+						else if to_state.crew + to_state.wounded >= to_stats.crew
+						{
+							map.messages.add(
+								message::MessageKind::System,
+								"No room for more crew!".to_string(),
+								state.time(),
+							);
+						}
+						else
+						{
+							let to_count = (to_state.crew + to_state.wounded) as f32;
+							to_state.experience = (to_count * to_state.experience
+								+ from_state.experience) / (to_count + 1.);
+							for (to, from) in
+								to_state.proficiency.iter_mut().zip(from_state.proficiency)
+							{
+								*to = (to_count * *to + from) / (to_count + 1.);
+							}
+							from_state.crew -= 1;
+							to_state.crew += 1;
+							to_state.compute_level();
+						}
+					}
+				}
+			}
+
+			if do_switch
+			{
+				map.journal.record(
+					journal::Event::SwitchedShips,
+					state.time(),
+					&mut map.journal_rng,
+				);
+				let player = map.player;
+				map.player = map.dock_entity.unwrap();
+				map.dock_entity = Some(player);
+				if map.target_entity.is_some()
+				{
+					map.target_entity = Some(map.dock_entity.unwrap());
+				}
+			}
+		}
+		if let Some((_, _, officers)) = switch
+		{
+			move_officers(map, officers);
+			map.world.remove_one::<comps::Fleet>(map.player).ok();
+			// Refloated.
+			map.world.remove_one::<comps::Wreck>(map.player).ok();
+			if let Ok(mut solid) = map.world.get::<&mut comps::Solid>(map.player)
+			{
+				solid.mass = solid.size.powf(3.);
+			}
+			if prize_crew
+			{
+				map.world
+					.insert(
+						map.dock_entity.unwrap(),
+						(comps::Fleet, comps::Station::default()),
+					)
+					.unwrap();
+			}
+		}
+		if let (true, Some(join_cost)) = (do_join_fleet, join_cost)
+		{
+			if map.money < join_cost
+			{
+				map.messages.add(
+					message::MessageKind::Trade,
+					"Not enough money!".to_string(),
+					state.time(),
+				);
+			}
+			else
+			{
+				let dock_entity = map.dock_entity.unwrap();
+				map.money -= join_cost;
+				// She takes her orders from you now, not her own captain.
+				map.world.remove_one::<comps::AI>(dock_entity).ok();
+				let mut markers = vec![];
+				if let Ok(mut target) = map.world.get::<&mut comps::Target>(dock_entity)
+				{
+					target.clear(|m| markers.push(m));
+				}
+				for marker in markers
+				{
+					map.world.despawn(marker).ok();
+				}
+				if let Ok(mut equipment) = map.world.get::<&mut comps::Equipment>(dock_entity)
+				{
+					equipment.want_attack = false;
+				}
+				// Falls in with the rest of the fleet, like a prize crew does.
+				map.world
+					.insert(dock_entity, (comps::Fleet, comps::Station::default()))
+					.unwrap();
+				map.messages.add(
+					message::MessageKind::System,
+					"She joins your fleet.".to_string(),
+					state.time(),
+				);
+				// Rebuild the buttons for transferring instead of recruiting.
+				self.recruit = None;
+				self.join_fleet = None;
+			}
+		}
+		!over_ui
+	}
+
+	// What there is to split with the docked ship, and whether the old ship can keep a prize crew.
+	fn crew_split(&self, map: &Map) -> Option<(ui::CrewSplit, bool)>
+	{
+		let dock = map.dock_entity?;
+		let player_state = map.world.get::<&comps::ShipState>(map.player).ok()?;
+		let dock_state = map.world.get::<&comps::ShipState>(dock).ok()?;
+		let dock_stats = map.world.get::<&comps::ShipStats>(dock).ok()?;
+		let officers = map
+			.world
+			.get::<&comps::Equipment>(map.player)
+			.map(|equipment| {
+				equipment
+					.slots
+					.iter()
+					.filter(|slot| !slot.is_inventory)
+					.filter(|slot| {
+						matches!(
+							slot.item.as_ref().map(|item| &item.kind),
+							Some(comps::ItemKind::Officer(_))
+						)
+					})
+					.count() as i32
+			})
+			.unwrap_or(0);
+		let officer_berths = map
+			.world
+			.get::<&comps::Equipment>(dock)
+			.map(|equipment| {
+				equipment
+					.slots
+					.iter()
+					.filter(|slot| slot.item.is_none())
+					.count() as i32
+			})
+			.unwrap_or(0);
+		let can_leave =
+			player_state.is_structurally_sound() && player_state.crew >= 2 && !dock_state.is_boss;
+		Some((
+			ui::CrewSplit {
+				crew: player_state.crew,
+				wounded: player_state.wounded,
+				officers: officers,
+				berths: dock_stats.crew,
+				officer_berths: officer_berths,
+				experience: player_state.experience,
+				prize_crew: dock_state.crew,
+				prize_wounded: dock_state.wounded,
+				prize_experience: dock_state.experience,
+			},
+			can_leave,
+		))
+	}
+
+	fn finish_trade(&mut self, map: &mut Map, state: &game_state::GameState)
+	{
+		let do_trade = self.do_trade(map);
+
+		{
+			let mut query = map.world.query::<&mut comps::Equipment>();
+			let mut view = query.view();
+			let [dock_equipment, player_equipment] = if let Some(dock_entity) = map.dock_entity
+			{
+				view.get_mut_n([dock_entity, map.player])
+			}
+			else
+			{
+				[None, view.get_mut(map.player)]
+			};
+
+			if let Some(equipment) = player_equipment
+			{
+				if let Some((i, equipment_idx, item)) = self.dragged_item.take()
+				{
+					if equipment_idx == 1
+					{
+						equipment.slots[i].item = Some(item);
+					}
+					else if let Some(dock_equipment) = dock_equipment
+					{
+						// When returning item to the trade partner, refund the price.
+						if do_trade
+						{
+							map.money += item.price;
+							record_trade(&mut self.traded, &item.kind, -1.);
+						}
+						dock_equipment.slots[i].item = Some(item);
+					}
+				}
+			}
+		}
+		if let (Ok(mut ship_state), Ok(stats)) = (
+			map.world.get::<&mut comps::ShipState>(map.player),
+			map.world.get::<&comps::ShipStats>(map.player),
+		)
+		{
+			let overflow = ship_state.crew + ship_state.wounded - stats.crew;
+			if overflow > 0
+			{
+				// ...I guess we dump them overboard? LOL...
+				ship_state.wounded -= overflow.min(ship_state.wounded);
+			}
+			let overflow = ship_state.crew + ship_state.wounded - stats.crew;
+			if overflow > 0
+			{
+				ship_state.crew -= overflow;
+			}
+		}
+		if self.traded.values().any(|t| t.abs() > 1e-3)
+		{
+			apply_trade_impact(&mut map.economy, &self.traded);
+			map.economy_history.push(map.economy);
+			if map.economy_history.len() > ECONOMY_HISTORY
+			{
+				map.economy_history.remove(0);
+			}
+			self.traded = Markets::new(0.);
+		}
+		if let Some(start_money) = self.start_money.take()
+		{
+			if start_money != map.money
+			{
+				let name = map
+					.dock_entity
+					.and_then(|e| map.world.get::<&comps::AI>(e).ok())
+					.map(|ai| ai.name.clone());
+				map.journal.record(
+					journal::Event::Traded {
+						name: name,
+						profit: map.money - start_money,
+					},
+					state.time(),
+					&mut map.journal_rng,
+				);
+			}
+		}
+	}
+
+	fn draw(&self, map: &Map, state: &game_state::GameState)
+	{
+		let m = state.m;
+		let lh = state.ui_font.get_line_height() as f32;
+		let ui_color = ui::ui_color();
+		if map.dock_entity.is_some()
+		{
+			state.prim.draw_filled_rectangle(
+				0.,
+				0.,
+				self.buffer_width * 1. / 3.,
+				self.buffer_height * EQUIPMENT_FRAC,
+				Color::from_rgb_f(0.1, 0.1, 0.2),
+			);
+		}
+		state.prim.draw_filled_rectangle(
+			self.buffer_width * 2. / 3.,
+			0.,
+			self.buffer_width,
+			self.buffer_height * EQUIPMENT_FRAC,
+			Color::from_rgb_f(0.1, 0.1, 0.2),
+		);
+		if map.dock_entity.is_some()
+		{
+			self.draw_silhouette(0, state);
+		}
+		self.draw_silhouette(1, state);
+		let do_trade = self.do_trade(map);
+		let mouse_pos = Point2::new(state.mouse_pos.x as f32, state.mouse_pos.y as f32);
+		let crew_level = map
+			.dock_entity
+			.and_then(|e| map.world.get::<&comps::ShipState>(e).ok())
+			.map(|ss| ss.level)
+			.unwrap_or(1);
+
+		let mut query = map.world.query::<&comps::Equipment>();
+		let view = query.view();
+		let [dock_equipment, player_equipment] = if let Some(dock_entity) = map.dock_entity
+		{
+			[view.get(dock_entity), view.get(map.player)]
+		}
+		else
+		{
+			[None, view.get(map.player)]
+		};
+		let dock_slots = dock_equipment.iter().flat_map(|eq| eq.slots.iter());
+		if let Some(equipment) = player_equipment
+		{
+			let slots: Vec<_> = (equipment.slots.iter().map(|slot| (1, slot)).enumerate())
+				.chain(dock_slots.map(|slot| (0, slot)).enumerate())
+				.filter(|(_, (equipment_idx, slot))| {
+					!(do_trade && *equipment_idx == 0 && !slot.is_inventory)
+				})
+				.collect();
+
+			let frames: Vec<_> = slots
+				.iter()
+				.map(|(_, (equipment_idx, slot))| {
+					let keel = (!slot.is_inventory)
+						.then(|| self.get_slot_pos(*equipment_idx, Point2::new(slot.pos.x, 0.)));
+					let size = (!slot.is_inventory && slot.weapons_allowed).then_some(slot.size);
+					(
+						self.get_slot_pos(*equipment_idx, slot.pos),
+						keel,
+						slot.dir,
+						size,
+					)
+				})
+				.collect();
+			self.slot_frames.draw(frames.clone(), state, |batch| {
+				let w = SLOT_WIDTH;
+				for (pos, keel, slot_dir, size) in &frames
+				{
+					// A pip per mount size, along the bottom edge.
+					if let Some(size) = size
+					{
+						for i in 0..=*size as i32
+						{
+							let x = pos.x - w / 2. + 8. + i as f32 * 8.;
+							let y = pos.y + w / 2. - 8.;
+							batch.filled_rectangle(x - 2., y - 2., x + 2., y + 2., ui_color);
+						}
+					}
+					// Connect the slot to the keel, so it's clear which side it's on.
+					if let Some(keel) = keel.filter(|keel| (keel.x - pos.x).abs() > w / 2.)
+					{
+						let edge = pos.x + (keel.x - pos.x).signum() * w / 2.;
+						batch.line(
+							edge,
+							pos.y,
+							keel.x,
+							keel.y,
+							ui_color.interpolate(Color::from_rgba_f(0., 0., 0., 0.), 0.6),
+							2.,
+						);
+					}
+					batch.rounded_rectangle(
+						pos.x - w / 2.,
+						pos.y - w / 2.,
+						pos.x + w / 2.,
+						pos.y + w / 2.,
+						8.,
+						ui_color,
+						3.,
+					);
+					if let Some(slot_dir) = slot_dir
+					{
+						let arc = PI / 4.;
+						batch.arc(
+							pos.x,
+							pos.y,
+							w,
+							-slot_dir - arc / 2. + PI * 3. / 2.,
+							arc,
+							ui_color,
+							4.,
+						);
+					}
+				}
+			});
+
+			let mut hover_item = None;
+			for (i, (equipment_idx, slot)) in slots
+			{
+				let pos = self.get_slot_pos(equipment_idx, slot.pos);
+				if let Some(item) = &slot.item
+				{
+					if Some((i, equipment_idx)) == self.hover_slot
+					{
+						let slot_dir = slot.dir.filter(|_| !slot.is_inventory);
+						hover_item =
+							Some((pos, equipment_idx, slot_dir, slot.disabled, item.clone()));
+					}
+					draw_item(pos.x, pos.y, &item.kind, state);
+					draw_item_flags(pos.x, pos.y, item, state);
+					if slot.disabled && !slot.is_inventory
+					{
+						draw_stood_down(pos.x, pos.y, SLOT_WIDTH, state);
+					}
+					// Weapons reload only when mounted, and moving them resets it.
+					if let (false, comps::ItemKind::Weapon(weapon)) =
+						(slot.is_inventory, &item.kind)
+					{
+						if weapon.readiness < 1.
+						{
+							let w = SLOT_WIDTH;
+							state.prim.draw_filled_rectangle(
+								pos.x - w / 2.,
+								pos.y - w / 2.,
+								pos.x + w / 2.,
+								pos.y + w / 2.,
+								ui::backdrop(0.6),
+							);
+							state.prim.draw_arc(
+								pos.x,
+								pos.y,
+								w / 2. - 6.,
+								-PI / 2.,
+								2. * PI * weapon.readiness,
+								frac_to_color(weapon.readiness),
+								4.,
+							);
+						}
+					}
+				}
+			}
+
+			if let Some((pos, equipment_idx, slot_dir, disabled, item)) = hover_item
+			{
+				let ui_color = ui::ui_color();
+				let price_desc = if do_trade
+				{
+					let price = item.price;
+					let (commodity, volume) = trade_volume(&item.kind);
+					let (verb, sign) = if equipment_idx == 0
+					{
+						("market_buying", 1.)
+					}
+					else
+					{
+						("market_selling", -1.)
+					};
+					let mut traded = self.traded;
+					traded[commodity] += sign * volume;
+					let mut projected = map.economy;
+					apply_trade_impact(&mut projected, &traded);
+					vec![
+						(format!("Price: {price}"), Color::from_rgb_f(1., 0.6, 0.2)),
+						(
+							state.locale.text(
+								"market_trade",
+								&[
+									("verb", &state.locale.text(verb, &[])),
+									("name", &commodity.name(state)),
+									("price", &round_price(projected[commodity]).to_string()),
+								],
+							),
+							ui_color,
+						),
+						(
+							if !item.kind.is_cargo()
+							{
+								"".into()
+							}
+							else if equipment_idx == 0
+							{
+								"B: Buy all, N: Buy some".into()
+							}
+							else
+							{
+								"S: Sell all cargo".into()
+							},
+							ui_color,
+						),
+						(
+							match (equipment_idx, slot_dir)
+							{
+								(1, Some(dir)) => format!(
+									"R: Refit to face {} for £{}",
+									slot_side(Some((dir + PI / 2.).rem_euclid(2. * PI))),
+									Self::refit_cost(state)
+								),
+								_ => "".into(),
+							},
+							ui_color,
+						),
+						("".into(), ui_color),
+					]
+				}
+				else
+				{
+					vec![]
+				};
+
+				let name = vec![(item.kind.name(), item.kind.color())];
+				let mut desc = item.kind.description();
+				if let comps::ItemKind::Weapon(weapon) = &item.kind
+				{
+					if weapon.readiness < 1.
+					{
+						desc.push_str(&format!(
+							"\nReloading: {}%\nMoving a weapon resets its reload",
+							(weapon.readiness * 100.) as i32
+						));
+					}
+				}
+				if item.locked
+				{
+					desc.push_str("\nLocked, it won't be sold or lost");
+				}
+				if item.junk
+				{
+					desc.push_str("\nJunk, sold at the next market");
+				}
+				let mounted_weapon =
+					slot_dir.is_some() && matches!(item.kind, comps::ItemKind::Weapon(_));
+				if mounted_weapon && disabled
+				{
+					desc.push_str("\nStood down, its crew help reload the other guns");
+				}
+				if equipment_idx == 1
+				{
+					desc.push_str("\nL: Lock, J: Mark as junk");
+					if mounted_weapon
+					{
+						desc.push_str(
+							if disabled
+							{
+								"\nO: Man the gun"
+							}
+							else
+							{
+								"\nO: Stand the gun down"
+							},
+						);
+					}
+				}
+
+				let lines: Vec<_> = price_desc
+					.iter()
+					.map(|(s, c)| (s.as_str(), *c))
+					.chain(name.iter().map(|(s, c)| (*s, *c)))
+					.chain(desc.lines().map(|s| (s, ui_color)))
+					.collect();
+
+				state.prim.draw_filled_rectangle(
+					pos.x + m * 16. * [1., -1.][equipment_idx as usize],
+					pos.y,
+					pos.x,
+					pos.y + m * (lines.len() as f32 + 2.),
+					ui::backdrop(0.75),
+				);
+
+				let x = pos.x + m * 8. * [1., -1.][equipment_idx as usize];
+				let mut y = pos.y + m * 1.;
+
+				for (line, color) in lines
+				{
+					state
+						.core
+						.draw_text(&state.ui_font, color, x, y, FontAlign::Centre, line);
+					y += lh;
+				}
+			}
+
+			if let Some((_, _, ref item)) = self.dragged_item
+			{
+				draw_item(mouse_pos.x, mouse_pos.y, &item.kind, state);
+				draw_item_flags(mouse_pos.x, mouse_pos.y, item, state);
+			}
+		}
+
+		if let Some(button) = self.switch_ships.as_ref()
+		{
+			button.draw(state);
+			state.core.draw_text(
+				&state.ui_font,
+				Color::from_rgb_f(1., 1., 1.),
+				button.loc.x - button.size.x,
+				button.loc.y - lh / 2.,
+				FontAlign::Right,
+				"Switch Ships",
+			);
+		}
+		if let Some(button) = self.recruit.as_ref()
+		{
+			button.draw(state);
+			state.core.draw_text(
+				&state.ui_font,
+				Color::from_rgb_f(1., 1., 1.),
+				button.loc.x - button.size.x,
+				button.loc.y - lh / 2.,
+				FontAlign::Right,
+				&format!("Recruit Crew £{}", crew_level * CREW_COST),
+			);
+		}
+		if do_trade
+		{
+			let r = self.sell_cargo_rect(state);
+			let hover = mouse_pos.x > r[0]
+				&& mouse_pos.x < r[2]
+				&& mouse_pos.y > r[1]
+				&& mouse_pos.y < r[3];
+			state.prim.draw_filled_rectangle(
+				r[0],
+				r[1],
+				r[2],
+				r[3],
+				ui::backdrop(if hover { 0.9 } else { 0.6 }),
+			);
+			state
+				.prim
+				.draw_rectangle(r[0], r[1], r[2], r[3], ui_color, 2.);
+			state.core.draw_text(
+				&state.ui_font,
+				ui_color,
+				(r[0] + r[2]) / 2.,
+				(r[1] + r[3]) / 2. - lh / 2.,
+				FontAlign::Centre,
+				"Sell All Cargo (S)",
+			);
+
+			// Market summary under the trade partner's hold.
+			let x = m;
+			let mut y = self.buffer_height * EQUIPMENT_FRAC;
+			state.prim.draw_filled_rectangle(
+				0.,
+				y,
+				m * 10.,
+				y + lh * (Commodity::ALL.len() as f32 + 1.),
+				ui::backdrop(0.75),
+			);
+			y += lh / 2.;
+			for (commodity, price) in map.economy.iter()
+			{
+				state.core.draw_text(
+					&state.ui_font,
+					commodity.color(),
+					x,
+					y,
+					FontAlign::Left,
+					&commodity.name(state),
+				);
+				state.core.draw_text(
+					&state.ui_font,
+					ui_color,
+					x + m * 7.,
+					y,
+					FontAlign::Right,
+					&format!("£{}", round_price(price)),
+				);
+				draw_trend_arrow(
+					x + m * 8.,
+					y + lh / 2.,
+					lh / 2.,
+					map.economy_trend(commodity),
+					state,
+				);
+				y += lh;
+			}
+
+			// Dockside rumors, under the market summary.
+			if !map.rumors.feed.is_empty()
+			{
+				let w = map
+					.rumors
+					.feed
+					.iter()
+					.map(|r| state.ui_font.get_text_width(&r.text) as f32)
+					.fold(state.ui_font.get_text_width("Rumors") as f32, f32::max);
+				y += lh;
+				state.prim.draw_filled_rectangle(
+					0.,
+					y,
+					w + 2. * m,
+					y + lh * (map.rumors.feed.len() as f32 + 2.),
+					ui::backdrop(0.75),
+				);
+				y += lh / 2.;
+				state
+					.core
+					.draw_text(&state.ui_font, ui_color, x, y, FontAlign::Left, "Rumors");
+				y += lh;
+				for rumor in &map.rumors.feed
+				{
+					let color = if rumor.pos.is_some()
+					{
+						Color::from_rgb_f(0.6, 0.9, 0.6)
+					}
+					else
+					{
+						ui_color
+					};
+					state
+						.core
+						.draw_text(&state.ui_font, color, x, y, FontAlign::Left, &rumor.text);
+					y += lh;
+				}
+			}
+		}
+		if let Some(button) = self.give_crew.as_ref()
+		{
+			button.draw(state);
+			state.core.draw_text(
+				&state.ui_font,
+				Color::from_rgb_f(1., 1., 1.),
+				button.loc.x - button.size.x,
+				button.loc.y - lh / 2.,
+				FontAlign::Right,
+				"Send Crew Over",
+			);
+		}
+		if let Some(button) = self.take_crew.as_ref()
+		{
+			button.draw(state);
+			state.core.draw_text(
+				&state.ui_font,
+				Color::from_rgb_f(1., 1., 1.),
+				button.loc.x - button.size.x,
+				button.loc.y - lh / 2.,
+				FontAlign::Right,
+				"Take Crew Aboard",
+			);
+		}
+		if let (Some(button), Some(join_cost)) = (self.join_fleet.as_ref(), self.join_cost(map))
+		{
+			button.draw(state);
+			state.core.draw_text(
+				&state.ui_font,
+				Color::from_rgb_f(1., 1., 1.),
+				button.loc.x - button.size.x,
+				button.loc.y - lh / 2.,
+				FontAlign::Right,
+				&format!("Join Fleet £{}", join_cost),
+			);
+		}
+	}
+}
+
+fn draw_trend_arrow(x: f32, y: f32, size: f32, trend: i32, state: &game_state::GameState)
+{
+	let s = size / 2.;
+	match trend.signum()
+	{
+		1 => state.prim.draw_filled_triangle(
+			x - s,
+			y + s,
+			x + s,
+			y + s,
+			x,
+			y - s,
+			Color::from_rgb_f(0.3, 0.9, 0.3),
+		),
+		-1 => state.prim.draw_filled_triangle(
+			x - s,
+			y - s,
+			x + s,
+			y - s,
+			x,
+			y + s,
+			Color::from_rgb_f(0.9, 0.3, 0.3),
+		),
+		_ => state.prim.draw_line(x - s, y, x + s, y, ui::ui_color(), 2.),
+	}
+}
+
+fn draw_item(x: f32, y: f32, item_kind: &comps::ItemKind, state: &game_state::GameState)
+{
+	item_kind.draw(Point2::new(x, y), state);
+}
+
+// Small marks in the corners of the slot.
+fn draw_item_flags(x: f32, y: f32, item: &comps::Item, state: &game_state::GameState)
+{
+	let w = SLOT_WIDTH / 2. - 8.;
+	if item.locked
+	{
+		// A padlock.
+		let (cx, cy) = (x + w - 4., y - w + 6.);
+		let color = Color::from_rgb_f(0.9, 0.8, 0.3);
+		state.prim.draw_arc(cx, cy - 2., 4., PI, PI, color, 2.);
+		state
+			.prim
+			.draw_filled_rectangle(cx - 6., cy - 2., cx + 6., cy + 6., color);
+	}
+	if item.junk
+	{
+		let (cx, cy) = (x - w + 4., y - w + 4.);
+		let color = Color::from_rgb_f(0.9, 0.3, 0.2);
+		state
+			.prim
+			.draw_line(cx - 4., cy - 4., cx + 4., cy + 4., color, 3.);
+		state
+			.prim
+			.draw_line(cx - 4., cy + 4., cx + 4., cy - 4., color, 3.);
+	}
+}
+
+// Crossed out, for a gun that's been stood down.
+fn draw_stood_down(x: f32, y: f32, w: f32, state: &game_state::GameState)
+{
+	let s = w / 2. - 6.;
+	let color = Color::from_rgba_f(0.9, 0.3, 0.2, 0.8);
+	state.prim.draw_line(x - s, y - s, x + s, y + s, color, 4.);
+	state.prim.draw_line(x - s, y + s, x + s, y - s, color, 4.);
+}
+
+fn draw_abilities(ship_state: &comps::ShipState, x: f32, y: f32, state: &game_state::GameState)
+{
+	let m = state.m;
+	let lh = state.ui_font.get_line_height() as f32;
+	let w = m * 3.;
+	let actions = [
+		controls::Action::HullPatch,
+		controls::Action::PowderSurge,
+		controls::Action::HardToPort,
+	];
+	for (i, (ability, action)) in comps::ABILITIES.iter().zip(actions).enumerate()
+	{
+		let cx = x + (i as f32 - 1.) * (w + m / 2.);
+		let cooldown = ship_state.ability_cooldown(*ability, state.time());
+		let active = match ability
+		{
+			comps::Ability::PowderSurge => ship_state.powder_surge,
+			comps::Ability::HardToPort => state.time() < ship_state.hard_turn_until,
+			_ => false,
+		};
+		let color = if cooldown > 0.
+		{
+			Color::from_rgb_f(0.5, 0.5, 0.5)
+		}
+		else if active
+		{
+			Color::from_rgb_f(1., 1., 0.2)
+		}
+		else
+		{
+			ui::ui_color()
+		};
+		state.prim.draw_filled_rectangle(
+			cx - w / 2.,
+			y - w / 2.,
+			cx + w / 2.,
+			y + w / 2.,
+			ui::backdrop(0.5),
+		);
+		if cooldown > 0.
+		{
+			state.prim.draw_filled_rectangle(
+				cx - w / 2.,
+				y + w / 2. - w * ship_state.ability_cooldown_frac(*ability, state.time()),
+				cx + w / 2.,
+				y + w / 2.,
+				Color::from_rgba_f(0.3, 0.3, 0.3, 0.5),
+			);
+		}
+		state
+			.prim
+			.draw_rectangle(cx - w / 2., y - w / 2., cx + w / 2., y + w / 2., color, 2.);
+		state.core.draw_text(
+			&state.ui_font,
+			color,
+			cx,
+			y - lh,
+			FontAlign::Centre,
+			ability.short_name(),
+		);
+		state.core.draw_text(
+			&state.ui_font,
+			color,
+			cx,
+			y,
+			FontAlign::Centre,
+			&if cooldown > 0.
+			{
+				format!("{}", cooldown.ceil() as i32)
+			}
+			else
+			{
+				state.controls.get_controls().get_action_string(action)
+			},
+		);
+	}
+}
+
+// Everything the ship state panel shows, so it's only laid out again when some of it changes.
+fn ship_state_key(
+	ship_state: &comps::ShipState, stats: &comps::ShipStats, x: f32, y: f32,
+) -> Vec<f32>
+{
+	let mut key = vec![
+		x,
+		y,
+		ship_state.level as f32,
+		ship_state.specialty_level(comps::Specialty::Gunnery) as f32,
+		ship_state.specialty_level(comps::Specialty::Sailing) as f32,
+		ship_state.specialty_level(comps::Specialty::Repair) as f32,
+		ship_state.hull.floor(),
+		stats.hull,
+		ship_state.crew as f32,
+		ship_state.wounded as f32,
+		stats.crew as f32,
+		ship_state.infirmary.floor(),
+		stats.infirmary,
+		ship_state.sails.floor(),
+		stats.sails,
+	];
+	key.extend(ship_state.armor.iter().map(|a| a.floor()));
+	key.extend(stats.armor.iter());
+	key
+}
+
+fn layout_ship_state(
+	ship_state: &comps::ShipState, stats: &comps::ShipStats, x: f32, y: f32,
+	batch: &mut ui_layer::Batch, state: &game_state::GameState,
+)
+{
+	let mut y = y;
+	let ui_color = ui::ui_color();
+
+	let m = state.m;
+	let lh = state.ui_font.get_line_height() as f32;
+
+	batch.text(
 		ui_color,
 		x,
 		y - lh / 2. - m * 5.,
 		FontAlign::Centre,
 		&format!("Crew Level: {}", ship_state.level),
 	);
+	let specialties: Vec<_> = comps::SPECIALTIES
+		.iter()
+		.map(|s| format!("{} {}", s.name(), ship_state.specialty_level(*s)))
+		.collect();
+	batch.text(
+		ui_color,
+		x,
+		y - lh / 2. - m * 6.,
+		FontAlign::Centre,
+		&specialties.join(" / "),
+	);
+
+	batch.text(
+		ui_color,
+		x - m * 4.,
+		y - lh / 2. - m * 3.5,
+		FontAlign::Left,
+		"Armor:",
+	);
+	for (i, (armor, armor_max)) in ship_state.armor.iter().zip(stats.armor.iter()).enumerate()
+	{
+		let theta = -PI / 2. + i as f32 * PI / 2.;
+
+		let r = m * 1.5;
+		let cx = x;
+
+		let lx = cx + (m * 1.5 + r) * theta.cos();
+		let ly = y + (m + r) * theta.sin();
+
+		let f = armor / armor_max;
+		batch.arc(
+			cx,
+			y,
+			r,
+			theta - PI / 4. + 0.1,
+			PI / 2. - 0.2,
+			frac_to_color(f),
+			(f * 10.).ceil(),
+		);
+
+		batch.text(
+			frac_to_color(f),
+			lx,
+			ly - lh / 2.,
+			FontAlign::Centre,
+			&format!("{}", *armor as i32),
+		);
+	}
+
+	y += m * 4.;
+
+	let h = m;
+	batch.text(
+		frac_to_color(ship_state.hull / stats.hull),
+		x - m * 4.,
+		y - lh / 2.,
+		FontAlign::Left,
+		&format!("Hull: {}", ship_state.hull as i32),
+	);
+	y += h;
+	batch.text(
+		frac_to_color(ship_state.crew as f32 / stats.crew as f32),
+		x - m * 4.,
+		y - lh / 2.,
+		FontAlign::Left,
+		&format!("Crew: {} H / {} W", ship_state.crew, ship_state.wounded),
+	);
+	y += h;
+	batch.text(
+		frac_to_color(ship_state.infirmary / stats.infirmary),
+		x - m * 4.,
+		y - lh / 2.,
+		FontAlign::Left,
+		&format!("Infirmary: {}", ship_state.infirmary as i32),
+	);
+	y += h;
+	batch.text(
+		frac_to_color(ship_state.sails / stats.sails),
+		x - m * 4.,
+		y - lh / 2.,
+		FontAlign::Left,
+		&format!("Sails: {}", ship_state.sails as i32),
+	);
+}
+
+fn make_wisp(
+	pos: Point3<f32>, vel: Vector3<f32>, parent: hecs::Entity, level: i32, world: &mut hecs::World,
+	state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let mesh = "data/wisp.glb";
+	game_state::cache_mesh(state, mesh)?;
+	let res = world.spawn((
+		comps::Position {
+			pos: pos + Vector3::new(0., 2., 0.),
+			dir: 0.,
+		},
+		comps::Velocity {
+			vel: vel,
+			dir_vel: PI,
+		},
+		comps::Mesh { mesh: mesh.into() },
+		comps::Solid {
+			size: 1.,
+			mass: 0.,
+			kind: comps::CollideKind::Hazard,
+			parent: Some(parent),
+		},
+		comps::Wisp {
+			speed: 6.,
+			time_to_hum: state.time(),
+		},
+		comps::TimeToDie {
+			time_to_die: state.time() + 30.,
+		},
+		comps::OnContactEffect {
+			effects: vec![
+				comps::ContactEffect::Die,
+				comps::ContactEffect::Hurt {
+					damage: comps::Damage {
+						weapon_stats: comps::wisp_weapon_stats(level),
+						team: comps::Team::Pirate,
+						source: "Wisp".into(),
+						friendly_fire: false,
+					},
+				},
+			],
+		},
+		comps::Lights {
+			lights: vec![comps::Light {
+				pos: Point3::origin(),
+				color: Color::from_rgb_f(0.2, 0.9, 0.9),
+				intensity: 3.,
+				spot: None,
+				animation: comps::LightAnimation::default(),
+			}],
+		},
+	));
+	Ok(res)
+}
+
+fn make_creature<R: Rng>(
+	kind: comps::CreatureKind, pos: Point3<f32>, rng: &mut R, world: &mut hecs::World,
+	state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	// Stand-ins until the wildlife gets meshes of its own.
+	let (mesh, scale) = match kind
+	{
+		comps::CreatureKind::Gull => ("data/cannon_ball.glb", 0.8),
+		comps::CreatureKind::Whale => ("data/sphere.glb", 3.),
+		comps::CreatureKind::Leviathan => ("data/sphere.glb", 6.),
+	};
+	game_state::cache_mesh(state, mesh)?;
+	let gull = kind == comps::CreatureKind::Gull;
+	let res = world.spawn((
+		comps::Position {
+			pos: if gull
+			{
+				pos
+			}
+			else
+			{
+				Point3::new(pos.x, DIVE_DEPTH, pos.z)
+			},
+			dir: rng.gen_range(0.0..2. * PI),
+		},
+		comps::Velocity {
+			vel: Vector3::zeros(),
+			dir_vel: 0.,
+		},
+		comps::Mesh { mesh: mesh.into() },
+		comps::Scale { scale: scale },
+		comps::Creature {
+			kind: kind,
+			home: pos,
+			surfaced: gull,
+			time_to_change: state.time() + rng.gen_range(0.0..10.),
+			time_to_bite: state.time(),
+		},
+	));
+	if kind == comps::CreatureKind::Leviathan
+	{
+		world.insert_one(
+			res,
+			comps::Lights {
+				lights: vec![comps::Light {
+					pos: Point3::origin(),
+					color: Color::from_rgb_f(0.5, 0.2, 0.8),
+					intensity: 2.,
+					spot: None,
+					animation: comps::LightAnimation::default(),
+				}],
+			},
+		)?;
+	}
+	Ok(res)
+}
+
+// A leviathan's jaws closing, which hurts whatever's there through the usual contact effects.
+fn make_bite(
+	pos: Point3<f32>, level: i32, world: &mut hecs::World, state: &game_state::GameState,
+) -> hecs::Entity
+{
+	world.spawn((
+		comps::Position {
+			pos: Point3::new(pos.x, 0., pos.z),
+			dir: 0.,
+		},
+		comps::Solid {
+			size: 1.,
+			mass: 0.,
+			kind: comps::CollideKind::Hazard,
+			parent: None,
+		},
+		comps::TimeToDie {
+			time_to_die: state.time() + 0.1,
+		},
+		comps::OnContactEffect {
+			effects: vec![
+				comps::ContactEffect::Die,
+				comps::ContactEffect::Hurt {
+					damage: comps::Damage {
+						weapon_stats: comps::leviathan_weapon_stats(level),
+						team: comps::Team::Neutral,
+						source: "Leviathan".into(),
+						friendly_fire: false,
+					},
+				},
+			],
+		},
+	))
+}
+
+// A harmless wisp, just for atmosphere.
+fn make_drifting_wisp(
+	pos: Point3<f32>, vel: Vector3<f32>, world: &mut hecs::World, state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let mesh = "data/wisp.glb";
+	game_state::cache_mesh(state, mesh)?;
+	let res = world.spawn((
+		comps::Position {
+			pos: pos + Vector3::new(0., 3., 0.),
+			dir: 0.,
+		},
+		comps::Velocity {
+			vel: vel,
+			dir_vel: PI / 2.,
+		},
+		comps::Mesh { mesh: mesh.into() },
+		comps::TimeToDie {
+			time_to_die: state.time() + 8.,
+		},
+		comps::Lights {
+			lights: vec![comps::Light {
+				pos: Point3::origin(),
+				color: Color::from_rgb_f(0.5, 0.2, 0.8),
+				intensity: 1.5,
+				spot: None,
+				animation: comps::LightAnimation::default(),
+			}],
+		},
+	));
+	Ok(res)
+}
+
+fn make_target(
+	pos: Point3<f32>, world: &mut hecs::World, state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let mesh = "data/target.glb";
+	game_state::cache_mesh(state, mesh)?;
+	let res = world.spawn((
+		comps::Position {
+			pos: pos + Vector3::new(0., 1., 0.),
+			dir: 0.,
+		},
+		comps::Velocity {
+			vel: Vector3::zeros(),
+			dir_vel: PI,
+		},
+		comps::Mesh { mesh: mesh.into() },
+		comps::RenderFlags::MARKER,
+		comps::Lights {
+			lights: vec![comps::Light {
+				pos: Point3::origin(),
+				color: Color::from_rgb_f(0.2, 0.8, 0.2),
+				intensity: 4.,
+				spot: None,
+				animation: comps::LightAnimation::default(),
+			}],
+		},
+	));
+	Ok(res)
+}
+
+fn make_selection(
+	pos: Point3<f32>, world: &mut hecs::World, state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let mesh = "data/selection_indicator.glb";
+	game_state::cache_mesh(state, mesh)?;
+	let res = world.spawn((
+		comps::Position { pos: pos, dir: 0. },
+		comps::Velocity {
+			vel: Vector3::zeros(),
+			dir_vel: PI,
+		},
+		comps::Mesh { mesh: mesh.into() },
+		comps::RenderFlags::MARKER,
+	));
+	Ok(res)
+}
+
+fn make_projectile(
+	pos: Point3<f32>, dir: Vector3<f32>, parent: hecs::Entity, team: comps::Team,
+	weapon_stats: &comps::WeaponStats, source: &str, world: &mut hecs::World,
+	state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let mesh = "data/cannon_ball.glb";
+	game_state::cache_mesh(state, mesh)?;
+	let res = world.spawn((
+		comps::Position { pos: pos, dir: 0. },
+		comps::Velocity {
+			vel: dir * weapon_stats.speed,
+			dir_vel: 5. * PI,
+		},
+		comps::Solid {
+			size: 0.5,
+			mass: 0.,
+			kind: comps::CollideKind::Small,
+			parent: Some(parent),
+		},
+		comps::Mesh { mesh: mesh.into() },
+		comps::TimeToDie {
+			time_to_die: state.time() + PROJECTILE_LIFETIME,
+		},
+		comps::AffectedByGravity,
+		comps::CollidesWithWater {
+			sink_time: 0.6,
+			drag: 6.,
+			live_underwater: false,
+		},
+		comps::Projectile {
+			spawn_pos: pos,
+			spawn_time: state.time(),
+			team: team,
+		},
+		comps::OnContactEffect {
+			effects: vec![
+				comps::ContactEffect::Die,
+				comps::ContactEffect::Hurt {
+					damage: comps::Damage {
+						weapon_stats: weapon_stats.clone(),
+						team: team,
+						source: source.into(),
+						friendly_fire: state.options.friendly_fire,
+					},
+				},
+			],
+		},
+		comps::Lights {
+			lights: vec![comps::Light {
+				pos: Point3::origin(),
+				color: Color::from_rgb_f(1., 0.8, 0.2),
+				intensity: 2.,
+				spot: None,
+				animation: comps::LightAnimation::default(),
+			}],
+		},
+	));
+	Ok(res)
+}
+
+fn make_muzzle_flash(
+	pos: Point3<f32>, parent: hecs::Entity, world: &mut hecs::World,
+	state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	// Keep up with the ship for as long as the flash lasts.
+	let attached = world
+		.query_one_mut::<(&comps::Position, Option<&comps::Tilt>)>(parent)
+		.ok()
+		.map(|(parent_pos, tilt)| comps::Attached {
+			parent: parent,
+			offset: parent_pos.to_isometry(tilt).inverse_transform_point(&pos),
+		});
+	let res = world.spawn((
+		comps::Position { pos: pos, dir: 0. },
+		comps::TimeToDie {
+			time_to_die: state.time() + 0.2,
+		},
+		comps::Lights {
+			lights: vec![comps::Light {
+				pos: Point3::origin(),
+				color: Color::from_rgb_f(1., 0.8, 0.2),
+				intensity: 4.,
+				spot: None,
+				animation: comps::LightAnimation::default(),
+			}],
+		},
+	));
+	if let Some(attached) = attached
+	{
+		world.insert_one(res, attached)?;
+	}
+	Ok(res)
+}
+
+struct Impact
+{
+	sound: &'static str,
+	volume: f32,
+	debris: usize,
+	color: Color,
+	shake: f32,
+}
+
+fn impact_effect(hit: comps::HitKind, crit: bool) -> Impact
+{
+	let mut impact = match hit
+	{
+		comps::HitKind::Armor => Impact {
+			sound: "data/armor_clang.wav",
+			volume: 0.4,
+			debris: 2,
+			color: Color::from_rgb_f(1., 0.9, 0.6),
+			shake: 0.15,
+		},
+		comps::HitKind::Hull => Impact {
+			sound: "data/hull_crunch.wav",
+			volume: 0.5,
+			debris: 5,
+			color: Color::from_rgb_f(1., 0.6, 0.3),
+			shake: 0.3,
+		},
+		comps::HitKind::Sails => Impact {
+			sound: "data/sail_rip.wav",
+			volume: 0.4,
+			debris: 1,
+			color: Color::from_rgb_f(0.9, 0.9, 0.8),
+			shake: 0.05,
+		},
+		comps::HitKind::Crew => Impact {
+			sound: "data/screams.ogg",
+			volume: 0.25,
+			debris: 3,
+			color: Color::from_rgb_f(1., 0.3, 0.2),
+			shake: 0.3,
+		},
+		comps::HitKind::Infirmary => Impact {
+			sound: "data/hull_crunch.wav",
+			volume: 0.4,
+			debris: 3,
+			color: Color::from_rgb_f(0.6, 1., 0.6),
+			shake: 0.25,
+		},
+	};
+	if crit
+	{
+		impact.volume = (impact.volume * 1.5).min(1.);
+		impact.debris *= 2;
+		impact.shake *= 2.5;
+	}
+	impact
+}
+
+// Where a gun in this slot points to hit the target, if it can swing that way at all. The flag is
+// false when it can only fire along the edge of its arc.
+// Whether a ship on the shooter's side sits between the gun and where it's aimed.
+fn line_of_fire_blocked(
+	from: Point3<f32>, to: Point3<f32>, shooter: hecs::Entity, team: comps::Team,
+	ships: &[(hecs::Entity, comps::Team, Point3<f32>, f32)],
+) -> bool
+{
+	let seg = (to - from).xz();
+	ships.iter().any(|&(id, other_team, pos, size)| {
+		if id == shooter || other_team != team
+		{
+			return false;
+		}
+		let disp = (pos - from).xz();
+		let t = utils::clamp(disp.dot(&seg) / seg.norm_squared().max(1e-3), 0., 1.);
+		(disp - t * seg).norm() < size
+	})
+}
+
+fn aim_gun(
+	ship_pos: &comps::Position, slot_pos: Point2<f32>, slot_dir: Option<f32>, arc: f32,
+	out_of_arc: f32, target_pos: Point3<f32>,
+) -> (Point2<f32>, Option<(Vector2<f32>, bool)>)
+{
+	let rot = Rotation2::new(ship_pos.dir);
+	let slot_dir = slot_dir.unwrap_or(0.);
+	let slot_pos = ship_pos.pos.zx() + rot * slot_pos.coords;
+	let slot_dir_vec = Rotation2::new(slot_dir) * rot * Vector2::new(1., 0.);
+	let target_dir = (target_pos.zx() - slot_pos).normalize();
+	let min_dot = (arc / 2.).cos();
+	let min_dot_2 = ((1. + out_of_arc) * arc / 2.).min(PI).cos();
+
+	if slot_dir_vec.dot(&target_dir) > min_dot
+	{
+		(slot_pos, Some((target_dir, true)))
+	}
+	else if out_of_arc > 0. && slot_dir_vec.dot(&target_dir) > min_dot_2
+	{
+		let cand_dir1 = Rotation2::new(slot_dir + arc / 2.) * rot * Vector2::new(1., 0.);
+		let cand_dir2 = Rotation2::new(slot_dir - arc / 2.) * rot * Vector2::new(1., 0.);
+		let cand_dir = if target_dir.dot(&cand_dir1) > target_dir.dot(&cand_dir2)
+		{
+			cand_dir1
+		}
+		else
+		{
+			cand_dir2
+		};
+		(slot_pos, Some((cand_dir, false)))
+	}
+	else
+	{
+		(slot_pos, None)
+	}
+}
+
+// What the heel does to a gun: the spread multiplier and the arc that's left. Guns on the low side
+// are pointed at the water.
+fn heel_penalty(heel: f32, slot_dir: Option<f32>, arc: f32) -> (f32, f32)
+{
+	let heaviness = utils::clamp((heel.abs() - FREE_HEEL) / (MAX_HEEL - FREE_HEEL), 0., 1.);
+	let side = slot_dir.unwrap_or(0.).sin();
+	let arc = if side * heel > 0.
+	{
+		arc * (1. - HEEL_ARC_LOSS * heaviness * side.abs())
+	}
+	else
+	{
+		arc
+	};
+	(1. + HEEL_SPREAD * heaviness, arc)
+}
+
+// Muzzle velocity for a shot along `dir` (in the zx plane, like the aiming code).
+fn gun_velocity(dir: Vector2<f32>, speed: f32) -> Vector3<f32>
+{
+	speed * Vector3::new(dir.y, GUN_ELEVATION, dir.x).normalize()
+}
+
+// The path of a shot until it hits the water or burns out.
+fn ballistic_arc(spawn_pos: Point3<f32>, vel: Vector3<f32>) -> Vec<Point3<f32>>
+{
+	let t_land = (vel.y + (vel.y * vel.y + 2. * GRAVITY * spawn_pos.y).sqrt()) / GRAVITY;
+	let t_end = t_land.min(PROJECTILE_LIFETIME as f32);
+	let num_points = 24;
+	(0..=num_points)
+		.map(|i| {
+			let t = t_end * i as f32 / num_points as f32;
+			spawn_pos + t * vel - Vector3::new(0., 0.5 * GRAVITY * t * t, 0.)
+		})
+		.collect()
+}
+
+fn make_debris(
+	pos: Point3<f32>, vel: Vector3<f32>, world: &mut hecs::World, state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let mesh = "data/cannon_ball.glb";
+	game_state::cache_mesh(state, mesh)?;
+	let res = world.spawn((
+		comps::Position { pos: pos, dir: 0. },
+		comps::Velocity {
+			vel: vel,
+			dir_vel: 10. * PI,
+		},
+		comps::Mesh { mesh: mesh.into() },
+		comps::TimeToDie {
+			time_to_die: state.time() + 1.5,
+		},
+		comps::AffectedByGravity,
+		comps::CollidesWithWater {
+			sink_time: 0.,
+			drag: 0.,
+			live_underwater: false,
+		},
+	));
+	Ok(res)
+}
+
+fn make_splash(
+	pos: Point3<f32>, radius: f32, world: &mut hecs::World, state: &game_state::GameState,
+) -> hecs::Entity
+{
+	world.spawn((
+		comps::Position {
+			pos: Point3::new(pos.x, 0., pos.z),
+			dir: 0.,
+		},
+		comps::Splash {
+			radius: radius,
+			start_time: state.time(),
+		},
+		comps::TimeToDie {
+			time_to_die: state.time() + comps::SPLASH_DURATION as f64,
+		},
+	))
+}
+
+fn make_impact_flash(
+	pos: Point3<f32>, color: Color, intensity: f32, world: &mut hecs::World,
+	state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let res = world.spawn((
+		comps::Position { pos: pos, dir: 0. },
+		comps::TimeToDie {
+			time_to_die: state.time() + 0.15,
+		},
+		comps::Lights {
+			lights: vec![comps::Light {
+				pos: Point3::origin(),
+				color: color,
+				intensity: intensity,
+				spot: None,
+				animation: comps::LightAnimation::default(),
+			}],
+		},
+	));
+	Ok(res)
+}
+
+fn make_squall(
+	pos: Point3<f32>, vel: Vector3<f32>, radius: f32, world: &mut hecs::World,
+	state: &game_state::GameState,
+) -> hecs::Entity
+{
+	world.spawn((
+		comps::Position { pos: pos, dir: 0. },
+		comps::Velocity {
+			vel: vel,
+			dir_vel: 0.,
+		},
+		comps::Squall {
+			radius: radius,
+			time_to_lightning: state.time() + 1.,
+		},
+	))
+}
+
+fn make_lighthouse(
+	pos: Point3<f32>, id: u64, world: &mut hecs::World, state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let mesh = "data/lighthouse.glb";
+	game_state::cache_mesh(state, mesh)?;
+
+	let res = world.spawn((
+		comps::Position { pos: pos, dir: 0. },
+		comps::Mesh { mesh: mesh.into() },
+		comps::Solid {
+			size: 1.6,
+			mass: f32::INFINITY,
+			kind: comps::CollideKind::Big,
+			parent: None,
+		},
+		comps::Lighthouse { id: id, spin: 0.5 },
+		comps::Lights {
+			lights: vec![
+				comps::Light {
+					pos: Point3::new(0., 7.8, 0.),
+					color: Color::from_rgb_f(1., 0.9, 0.6),
+					intensity: 2.,
+					spot: None,
+					animation: comps::LightAnimation::default(),
+				},
+				comps::Light {
+					pos: Point3::new(0., 7.8, 0.),
+					color: Color::from_rgb_f(1., 0.9, 0.6),
+					intensity: 25.,
+					spot: Some((Vector3::new(1., -0.15, 0.).normalize(), 0.97)),
+					animation: comps::LightAnimation::default(),
+				},
+			],
+		},
+	));
+	Ok(res)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SlotDesc
+{
+	pub pos: [f32; 2],
+	pub dir: Option<f32>,
+	pub weapons_allowed: bool,
+	#[serde(default)]
+	pub size: comps::MountSize,
+	// For sail plans and the like rather than officers.
+	#[serde(default)]
+	pub rigging: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LightDesc
+{
+	pub pos: [f32; 3],
+	pub color: [f32; 3],
+	pub intensity: f32,
+	#[serde(default)]
+	pub animation: comps::LightAnimation,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShipDesc
+{
+	pub mesh: String,
+	pub slots: Vec<SlotDesc>,
+	pub lights: Vec<LightDesc>,
+	pub stats: comps::ShipStats,
+	pub inventory_size: i32,
+	pub size: f32,
+	// Flag bitmap, if it shouldn't be the team's.
+	#[serde(default)]
+	pub flag: String,
+}
+
+// Factions can have their own take on a ship, e.g. data/small_ship_pirate.cfg, which usually
+// just names the generic one as its base.
+fn ship_variant(ship_desc: &str, team: comps::Team) -> String
+{
+	let faction = match team
+	{
+		comps::Team::English => "english",
+		comps::Team::French => "french",
+		comps::Team::Pirate => "pirate",
+		_ => return ship_desc.to_string(),
+	};
+	let variant = ship_desc.replace(".cfg", &format!("_{faction}.cfg"));
+	if std::path::Path::new(&variant).exists()
+	{
+		variant
+	}
+	else
+	{
+		ship_desc.to_string()
+	}
+}
+
+// Validated the first time it's loaded only, the desc is cached after that.
+pub fn load_ship_desc(file: &str, state: &mut game_state::GameState) -> Result<ShipDesc>
+{
+	if let Some(ship_desc) = state.ship_descs.get(file)
+	{
+		return Ok(ship_desc.clone());
+	}
+	let ship_desc: ShipDesc = utils::load_validated_config(file, |desc, element| {
+		validate_ship_desc(desc, element, |mesh| {
+			game_state::cache_mesh(state, mesh).ok()?;
+			state.get_mesh(mesh).ok().map(|m| m.bounds())
+		})
+	})?;
+	game_state::cache_mesh(state, &ship_desc.mesh)?;
+	if !ship_desc.flag.is_empty()
+	{
+		state.cache_bitmap(&ship_desc.flag)?;
+	}
+	state.ship_descs.insert(file.to_string(), ship_desc.clone());
+	Ok(ship_desc)
+}
+
+fn validate_ship_desc<'l>(
+	desc: &ShipDesc, element: &'l ConfigElement,
+	mesh_bounds: impl FnOnce(&str) -> Option<(Point3<f32>, Point3<f32>)>,
+) -> Vec<(&'l ConfigElement, String)>
+{
+	let mut problems = vec![];
+	let field = |path: &[&str]| utils::config_field(element, path);
+
+	if !std::path::Path::new(&desc.mesh).exists()
+	{
+		problems.push((
+			field(&["mesh"]),
+			format!("mesh '{}' does not exist", desc.mesh),
+		));
+	}
+	else if let Some((min, max)) = mesh_bounds(&desc.mesh)
+	{
+		// Slot positions are in the (z, x) plane of the mesh. Inventory slots are just UI
+		// positions, so only check the weapon slots.
+		let margin = 0.5;
+		for (i, slot) in desc.slots.iter().enumerate()
+		{
+			if slot.weapons_allowed
+				&& (slot.pos[0] < min.z - margin
+					|| slot.pos[0] > max.z + margin
+					|| slot.pos[1] < min.x - margin
+					|| slot.pos[1] > max.x + margin)
+			{
+				problems.push((
+					field(&["slots", &i.to_string(), "pos"]),
+					format!(
+						"slot is outside the hull bounds ({:.1}..{:.1}, {:.1}..{:.1})",
+						min.z, max.z, min.x, max.x
+					),
+				));
+			}
+		}
+	}
+
+	let stats = &desc.stats;
+	for (name, value) in [
+		("hull", stats.hull),
+		("crew", stats.crew as f32),
+		("sails", stats.sails),
+		("infirmary", stats.infirmary),
+		("speed", stats.speed),
+		("dir_speed", stats.dir_speed),
+		("exp_bonus", stats.exp_bonus),
+	]
+	{
+		if value < 0.
+		{
+			problems.push((
+				field(&["stats", name]),
+				format!("{name} must not be negative"),
+			));
+		}
+	}
+	for (i, armor) in stats.armor.iter().enumerate()
+	{
+		if *armor < 0.
+		{
+			problems.push((
+				field(&["stats", "armor", &i.to_string()]),
+				"armor must not be negative".to_string(),
+			));
+		}
+	}
+	if desc.inventory_size < 0
+	{
+		problems.push((
+			field(&["inventory_size"]),
+			"inventory_size must not be negative".to_string(),
+		));
+	}
+	if desc.size <= 0.
+	{
+		problems.push((field(&["size"]), "size must be positive".to_string()));
+	}
+
+	for (i, light) in desc.lights.iter().enumerate()
+	{
+		if light.color.iter().any(|c| !(0.0..=1.0).contains(c))
+		{
+			problems.push((
+				field(&["lights", &i.to_string(), "color"]),
+				"light color components must be between 0 and 1".to_string(),
+			));
+		}
+		if light.intensity < 0.
+		{
+			problems.push((
+				field(&["lights", &i.to_string(), "intensity"]),
+				"light intensity must not be negative".to_string(),
+			));
+		}
+		if light.animation.rate <= 0.
+		{
+			problems.push((
+				field(&["lights", &i.to_string(), "animation", "rate"]),
+				"light animation rate must be positive".to_string(),
+			));
+		}
+		if !(0.0..=1.0).contains(&light.animation.amount)
+		{
+			problems.push((
+				field(&["lights", &i.to_string(), "animation", "amount"]),
+				"light animation amount must be between 0 and 1".to_string(),
+			));
+		}
+	}
+	problems
+}
+
+// Like load_ship_desc, but without a display to load the mesh onto.
+pub fn check_ship_desc(file: &str) -> Result<()>
+{
+	utils::load_validated_config::<ShipDesc, _>(file, |desc, element| {
+		validate_ship_desc(desc, element, |mesh| {
+			mesh::MultiMesh::load(mesh).ok().map(|m| m.bounds())
+		})
+	})
+	.map(|_| ())
+}
+
+// Checks every config in data/, printing out all the problems.
+fn data_configs() -> Result<Vec<String>>
+{
+	let mut files = vec![];
+	for entry in std::fs::read_dir("data").map_err(|_| "Couldn't read data/".to_string())?
+	{
+		let path = entry.map_err(|_| "Couldn't read data/".to_string())?.path();
+		if path.extension().map(|e| e == "cfg").unwrap_or(false)
+		{
+			files.push(path.to_str().unwrap().to_string());
+		}
+	}
+	files.sort();
+	Ok(files)
+}
+
+pub fn validate_data() -> Result<bool>
+{
+	let files = data_configs()?;
+
+	let mut num_bad = 0;
+	for file in &files
+	{
+		let res = utils::read_config(file).and_then(|element| {
+			let table = element.as_table();
+			let has = |key: &str| table.map(|t| t.contains_key(key)).unwrap_or(false);
+			if has("mesh") && has("slots")
+			{
+				check_ship_desc(file)
+			}
+			else if has("bitmap")
+			{
+				sprite::validate_sprite(file)
+			}
+			else if has("texture")
+			{
+				mesh::validate_material(file)
+			}
+			else
+			{
+				Ok(())
+			}
+		});
+		if let Err(e) = res
+		{
+			println!("{}", e);
+			num_bad += 1;
+		}
+	}
+	for missing in missing_assets()?
+	{
+		println!("Missing: {}", missing);
+		num_bad += 1;
+	}
+	println!(
+		"Checked {} configs, {} with problems.",
+		files.len(),
+		num_bad
+	);
+	Ok(num_bad == 0)
+}
+
+// The ships that come with the game, packs aside.
+const SHIP_DESCS: &[&str] = &[
+	"data/small_ship.cfg",
+	"data/medium_ship.cfg",
+	"data/big_ship.cfg",
+	"data/boss_ship.cfg",
+	"data/fishing_boat.cfg",
+	"data/trader.cfg",
+];
+
+// Files the code loads by name, rather than through some config.
+const ASSET_MANIFEST: &[&str] = &[
+	"data/basic_vertex.glsl",
+	"data/basic_pixel.glsl",
+	"data/water_vertex.glsl",
+	"data/water_pixel.glsl",
+	"data/forward_vertex.glsl",
+	"data/forward_pixel.glsl",
+	"data/light_vertex.glsl",
+	"data/light_pixel.glsl",
+	"data/final_vertex.glsl",
+	"data/final_pixel.glsl",
+	"data/blur_vertex.glsl",
+	"data/blur_pixel.glsl",
+	"data/LibreBaskerville-Bold.ttf",
+	"data/new124.it",
+	"data/armor_clang.wav",
+	"data/cannon_shot.ogg",
+	"data/equipment.ogg",
+	"data/explosion.ogg",
+	"data/hull_crunch.wav",
+	"data/order.ogg",
+	"data/sail_rip.wav",
+	"data/screams.ogg",
+	"data/sink.ogg",
+	"data/splash.wav",
+	"data/ui1.ogg",
+	"data/ui2.ogg",
+	"data/void_drone.wav",
+	"data/wisp_hum.wav",
+	"data/cannon_ball.glb",
+	"data/lighthouse.glb",
+	"data/selection_indicator.glb",
+	"data/sphere.glb",
+	"data/target.glb",
+	"data/wisp.glb",
+	"data/english_flag.png",
+	"data/french_flag.png",
+	"data/pirate_flag.png",
+	"data/small_ship.cfg",
+	"data/medium_ship.cfg",
+	"data/big_ship.cfg",
+	"data/boss_ship.cfg",
+	"data/fishing_boat.cfg",
+	"data/trader.cfg",
+	"data/cannon_normal.cfg",
+	"data/cannon_magic.cfg",
+	"data/cannon_rare.cfg",
+	"data/goods.cfg",
+	"data/cotton.cfg",
+	"data/tobacco.cfg",
+	"data/officer.cfg",
+	"data/repair_armor.cfg",
+	"data/repair_hull.cfg",
+	"data/repair_infirmary.cfg",
+	"data/repair_sails.cfg",
+	"data/switch.cfg",
+	"data/recruit.cfg",
+	"data/cursor.cfg",
+	"data/triggers.cfg",
+];
+
+const ASSET_EXTENSIONS: &[&str] = &[".cfg", ".glb", ".png", ".ogg", ".wav", ".glsl", ".ttf"];
+
+fn collect_asset_paths(element: &slr_config::ConfigElement, paths: &mut Vec<String>)
+{
+	if let Some(table) = element.as_table()
+	{
+		for child in table.values()
+		{
+			collect_asset_paths(child, paths);
+		}
+	}
+	else if let Some(array) = element.as_array()
+	{
+		for child in array
+		{
+			collect_asset_paths(child, paths);
+		}
+	}
+	else if let Some(value) = element.as_value()
+	{
+		if value.starts_with("data/") && ASSET_EXTENSIONS.iter().any(|e| value.ends_with(e))
+		{
+			paths.push(value.clone());
+		}
+	}
+}
+
+// Checks that everything the game will want to load is there, so a missing file shows up at
+// startup rather than as a crash in the middle of a run. Returns the problems found.
+pub fn missing_assets() -> Result<Vec<String>>
+{
+	let mut referenced: Vec<(String, String)> = ASSET_MANIFEST
+		.iter()
+		.map(|f| (f.to_string(), "the game".to_string()))
+		.collect();
+	for team in [
+		comps::Team::English,
+		comps::Team::French,
+		comps::Team::Pirate,
+	]
+	{
+		for order in [Order::Move, Order::Attack, Order::Dock]
+		{
+			for variant in 1..=BARK_VARIANTS
+			{
+				referenced.push((bark_sample(team, order, variant), "the game".to_string()));
+			}
+		}
+	}
+
+	let mut missing = vec![];
+	for file in data_configs()?
+	{
+		match utils::read_config(&file)
+		{
+			Ok(element) =>
+			{
+				let mut paths = vec![];
+				collect_asset_paths(&element, &mut paths);
+				referenced.extend(paths.into_iter().map(|p| (p, file.clone())));
+			}
+			Err(_) => missing.push(format!("{file} can't be read")),
+		}
+	}
+
+	for (file, by) in referenced
+	{
+		let problem = format!("{file} (used by {by})");
+		if !std::path::Path::new(&file).exists() && !missing.contains(&problem)
+		{
+			missing.push(problem);
+		}
+	}
+	Ok(missing)
+}
+
+// One of ours, or from an enabled pack.
+fn known_ship_desc(desc: &str, state: &game_state::GameState) -> bool
+{
+	SHIP_DESCS.contains(&desc)
+		|| state
+			.packs
+			.iter()
+			.filter(|pack| state.options.packs.contains(&pack.id) && pack.usable())
+			.any(|pack| pack.ship_descs().iter().any(|d| d == desc))
+}
+
+fn make_ship(
+	pos: Point3<f32>, ship_desc: &str, team: comps::Team, level: i32, rng: &mut impl Rng,
+	world: &mut hecs::World, state: &mut game_state::GameState,
+) -> Result<hecs::Entity>
+{
+	let desc_file = ship_desc.to_string();
+	let ship_desc = load_ship_desc(&ship_variant(ship_desc, team), state)?;
+
+	let mut stats = ship_desc.stats.clone();
+	stats.dir_speed *= PI;
+	stats.scale_to_level(level);
+
+	let mut slots = vec![];
+	for slot_desc in &ship_desc.slots
+	{
+		slots.push(comps::ItemSlot {
+			pos: Point2::new(slot_desc.pos[0], slot_desc.pos[1]),
+			dir: slot_desc.dir.map(|d| d * PI),
+			item: if slot_desc.weapons_allowed
+			{
+				Some(comps::generate_weapon(level, slot_desc.size, rng).clone())
+			}
+			else
+			{
+				None
+			},
+			is_inventory: false,
+			weapons_allowed: slot_desc.weapons_allowed,
+			size: slot_desc.size,
+			rigging: slot_desc.rigging,
+			disabled: false,
+		});
+	}
+
+	let mut lights = vec![];
+	for light_desc in &ship_desc.lights
+	{
+		lights.push(comps::Light {
+			pos: Point3::new(light_desc.pos[0], light_desc.pos[1], light_desc.pos[2]),
+			color: Color::from_rgb_f(
+				light_desc.color[0],
+				light_desc.color[1],
+				light_desc.color[2],
+			),
+			intensity: light_desc.intensity,
+			spot: None,
+			animation: comps::LightAnimation {
+				lit: state.time(),
+				..light_desc.animation
+			},
+		});
+	}
+
+	let mut equipment = comps::Equipment::new(ship_desc.inventory_size.max(0) as usize, slots);
+
+	for slot in &mut equipment.slots
+	{
+		if !slot.is_inventory
+		{
+			continue;
+		}
+		if rng.gen_bool((0.5 * state.modifiers.loot_chance).min(1.) as f64)
+		{
+			let mut item = comps::generate_item(level, rng);
+			if let (comps::ItemKind::Officer(_), officer_levels @ 1..) =
+				(&item.kind, state.modifiers.officer_levels)
+			{
+				item = comps::generate_officer(level + officer_levels, rng);
+			}
+			slot.item = Some(item);
+		}
+	}
+
+	let res = world.spawn((
+		comps::Position {
+			pos: pos,
+			dir: PI / 2.,
+		},
+		comps::Velocity {
+			vel: Vector3::zeros(),
+			dir_vel: 0.,
+		},
+		comps::Mesh {
+			mesh: ship_desc.mesh.clone(),
+		},
+		comps::Target {
+			waypoints: vec![],
+			patrol: false,
+			attack_en_route: false,
+		},
+		stats.clone(),
+		comps::Solid {
+			size: ship_desc.size,
+			mass: ship_desc.size.powf(3.),
+			kind: comps::CollideKind::Big,
+			parent: None,
+		},
+		equipment,
+		comps::ShipState::new(&stats, team, level),
+		comps::Tilt {
+			tilt: 0.,
+			target_tilt: 0.,
+			heel: 0.,
+		},
+		comps::Lights { lights: lights },
+		comps::Spawned,
+		comps::ShipDesc { desc: desc_file },
+	));
+	if !ship_desc.flag.is_empty()
+	{
+		world.insert_one(
+			res,
+			comps::Flag {
+				flag: ship_desc.flag.clone(),
+			},
+		)?;
+	}
+	Ok(res)
+}
+
+#[derive(Copy, Clone, Debug)]
+struct CollisionEntry
+{
+	entity: hecs::Entity,
+	pos: Point3<f32>,
+}
+
+fn update_economy(economy: &mut Markets<f32>, rng: &mut impl Rng) -> (Commodity, bool)
+{
+	let commodity = Commodity::ALL[rng.gen_range(0..Commodity::ALL.len())];
+
+	let dir = *([-1., 1.].choose(rng).unwrap());
+	economy[commodity] *= (1.25_f32).powf(dir);
+	let mut cur_sum = 0.;
+	for e in economy.values()
+	{
+		cur_sum += *e;
+	}
+	for e in economy.values_mut()
+	{
+		*e *= 1000. / cur_sum;
+	}
+	(commodity, dir > 0.)
+}
+
+// How fast each gun reloads compared to its fire interval, with the gunners split between the
+// guns being reloaded.
+fn reload_rate(gunnery_crew: f32, num_weapons: i32, derived_stats: &comps::DerivedShipStats)
+	-> f32
+{
+	// X crew per weapon to reload it effectively.
+	let crew_per_weapon = 10;
+	1. / crew_per_weapon as f32 * gunnery_crew.sqrt() / num_weapons as f32
+		* (1. + derived_stats.reload_speed)
+}
+
+// Which market an item trades in, and how much of that market it represents.
+fn trade_volume(kind: &comps::ItemKind) -> (Commodity, f32)
+{
+	match kind
+	{
+		comps::ItemKind::Weapon(weapon) =>
+		{
+			(Commodity::Weapon, comps::level_effectiveness(weapon.level))
+		}
+		comps::ItemKind::Officer(officer) => (
+			Commodity::Officer,
+			comps::level_effectiveness(officer.level),
+		),
+		comps::ItemKind::Goods(level) => (Commodity::Goods, comps::level_effectiveness(*level)),
+		comps::ItemKind::Tobacco(level) => (Commodity::Tobacco, comps::level_effectiveness(*level)),
+		comps::ItemKind::Cotton(level) => (Commodity::Cotton, comps::level_effectiveness(*level)),
+		comps::ItemKind::Material(material) =>
+		{
+			(Commodity::Goods, material.amount / comps::MATERIAL_AMOUNT)
+		}
+		comps::ItemKind::Rigging(rigging) =>
+		{
+			(Commodity::Weapon, comps::level_effectiveness(rigging.level))
+		}
+	}
+}
+
+// Positive sign for purchases, negative for sales.
+fn record_trade(traded: &mut Markets<f32>, kind: &comps::ItemKind, sign: f32)
+{
+	let (commodity, volume) = trade_volume(kind);
+	traded[commodity] += sign * volume;
+}
+
+// Buying up a market drives its price up, flooding it drives it down.
+fn apply_trade_impact(economy: &mut Markets<f32>, traded: &Markets<f32>)
+{
+	for (commodity, amount) in traded.iter()
+	{
+		economy[commodity] *= (1. + TRADE_IMPACT).powf(amount);
+	}
+}
+
+// How the run is going. It only ever moves on from InProgress, see `Map::update_outcome`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Outcome
+{
+	InProgress,
+	// The Voidwind was sunk.
+	Victory
+	{
+		time: f64,
+	},
+	// The player's ship was disabled. The camera then roams freely until they concede.
+	Defeat
+	{
+		time: f64,
+	},
+}
+
+// What a click would do, as shown by the cursor. In the order of the variants in cursor.cfg.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Cursor
+{
+	Pointer = 0,
+	Move = 1,
+	Attack = 2,
+	Friendly = 3,
+	Grab = 4,
+}
+
+// What the final pass shows, for diagnosing the deferred renderer. Matches debug_view in
+// final_pixel.glsl.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum DebugView
+{
+	Final = 0,
+	Albedo = 1,
+	Normals = 2,
+	Positions = 3,
+	Depth = 4,
+	Light = 5,
+}
+
+impl DebugView
+{
+	fn next(self) -> Self
+	{
+		match self
+		{
+			DebugView::Final => DebugView::Albedo,
+			DebugView::Albedo => DebugView::Normals,
+			DebugView::Normals => DebugView::Positions,
+			DebugView::Positions => DebugView::Depth,
+			DebugView::Depth => DebugView::Light,
+			DebugView::Light => DebugView::Final,
+		}
+	}
+
+	fn name(&self) -> &'static str
+	{
+		match self
+		{
+			DebugView::Final => "Final",
+			DebugView::Albedo => "Albedo",
+			DebugView::Normals => "Normals",
+			DebugView::Positions => "Positions",
+			DebugView::Depth => "Depth",
+			DebugView::Light => "Light",
+		}
+	}
+}
+
+// Orders the crew acknowledges out loud.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Order
+{
+	Move,
+	Attack,
+	Dock,
+}
+
+impl Order
+{
+	fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Order::Move => "move",
+			Order::Attack => "attack",
+			Order::Dock => "dock",
+		}
+	}
+}
+
+fn bark_sample(team: comps::Team, order: Order, variant: i32) -> String
+{
+	let team = match team
+	{
+		comps::Team::French => "french",
+		comps::Team::Pirate => "pirate",
+		_ => "english",
+	};
+	format!("data/bark_{}_{}_{}.wav", team, order.name(), variant)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Hash)]
+enum Formation
+{
+	Column,
+	Line,
+	Wedge,
+}
+
+impl Formation
+{
+	fn name(&self) -> &'static str
+	{
+		match self
+		{
+			Formation::Column => "Column",
+			Formation::Line => "Line abreast",
+			Formation::Wedge => "Wedge",
+		}
+	}
+
+	fn next(&self) -> Self
+	{
+		match self
+		{
+			Formation::Column => Formation::Line,
+			Formation::Line => Formation::Wedge,
+			Formation::Wedge => Formation::Column,
+		}
+	}
+
+	// Offset of the i-th ship in (forward, left) coordinates.
+	fn offset(&self, i: usize, n: usize) -> Vector2<f32>
+	{
+		let spacing = 8.;
+		match self
+		{
+			Formation::Column => Vector2::new(-spacing * i as f32, 0.),
+			Formation::Line => Vector2::new(0., spacing * (i as f32 - (n - 1) as f32 / 2.)),
+			Formation::Wedge =>
+			{
+				let k = ((i + 1) / 2) as f32;
+				let side = if i % 2 == 1 { 1. } else { -1. };
+				Vector2::new(-0.75 * spacing * k, side * 0.75 * spacing * k)
+			}
+		}
+	}
+}
+
+// Everything in the world frame should be near the loaded cells after a recenter (with some slack
+// for the ones about to be culled and for aim points). Something further out most likely kept a
+// position from before it, or was never shifted at all, and will drift another cell every time.
+// Components missing from the registry can't have been shifted, so they're not allowed at all.
+fn check_world_space(world: &hecs::World, player_pos: Point3<f32>)
+{
+	let unregistered = registry::unregistered_types(world);
+	assert!(
+		unregistered.is_empty(),
+		"Components {:?} aren't in registry::COMPONENTS, so recentering can't shift them",
+		unregistered
+	);
+	let limit = ((CELL_RADIUS + 3) * CELL_SIZE) as f32;
+	for (component, id, pos) in registry::world_positions(world)
+	{
+		let dist = (pos - player_pos).xz().amax();
+		assert!(
+			dist < limit,
+			"{:?} has a {} at {:?}, {:.0} away from the player after recentering",
+			id,
+			component,
+			pos,
+			dist
+		);
+	}
+}
+
+fn formation_name(formation: Option<Formation>) -> &'static str
+{
+	formation.map(|f| f.name()).unwrap_or("Free")
+}
+
+fn digit_key(keycode: KeyCode) -> Option<usize>
+{
+	[
+		KeyCode::_0,
+		KeyCode::_1,
+		KeyCode::_2,
+		KeyCode::_3,
+		KeyCode::_4,
+		KeyCode::_5,
+		KeyCode::_6,
+		KeyCode::_7,
+		KeyCode::_8,
+		KeyCode::_9,
+	]
+	.iter()
+	.position(|k| *k == keycode)
+}
+
+fn round_price(price: f32) -> i32
+{
+	((price / 10.) as i32) * 10
+}
+
+// A wreck in a cell that isn't loaded.
+struct WreckRecord
+{
+	desc: String,
+	mesh: String,
+	level: i32,
+	// From the cell's center.
+	offset: Vector3<f32>,
+	dir: f32,
+	ship_state: comps::ShipState,
+	equipment: comps::Equipment,
+	visits: i32,
+}
+
+struct Rival
+{
+	name: String,
+	team: comps::Team,
+	encounters: i32,
+	entity: Option<hecs::Entity>,
+	time_to_spawn: Option<f64>,
+	sighted: bool,
+}
+
+struct Map
+{
+	world: hecs::World,
+	// Kept around so a voyage can be identified and replayed.
+	seed: u64,
+	rng: StdRng,
+	worldgen_rng: StdRng,
+	loot_rng: StdRng,
+	combat_rng: StdRng,
+	// Only for the journal's wording, so writing it up doesn't change anything that happens.
+	journal_rng: StdRng,
+	player: hecs::Entity,
+	player_pos: Point3<f32>,
+	prev_player_pos: Point3<f32>,
+	zoom: f32,
+	target_entity: Option<hecs::Entity>,
+	dock_entity: Option<hecs::Entity>,
+	selection_indicator: Option<hecs::Entity>,
+	buffer_width: f32,
+	buffer_height: f32,
+	mouse_in_buffer: bool,
+	cursor: Cursor,
+	cells: Vec<Cell>,
+	money: i32,
+	messages: message::MessageLog,
+	level: i32,
+	global_offset: Vector2<i32>,
+	economy: Markets<f32>,
+	economy_history: Vec<Markets<f32>>,
+	time_to_economy: f64,
+	season: calendar::Season,
+	boss: Option<hecs::Entity>,
+	spawn_boss: bool,
+	start_time: f64,
+	spectator: bool,
+	camera_pan: Vector2<f32>,
+	time_to_fleets: Option<f64>,
+	terrain_seed: u64,
+	// Positions are relative to the global origin.
+	discovered_lighthouses: HashMap<u64, Point3<f32>>,
+	// Wrecks left behind, by global cell.
+	wrecks: HashMap<Point2<i32>, Vec<WreckRecord>>,
+	journal: journal::Journal,
+	rival: Rival,
+	difficulty: difficulty::Difficulty,
+	damage_log: damage_log::DamageLog,
+	intel: intel::IntelRegistry,
+	exploration: exploration::Exploration,
+	queries: systems::Queries,
+	// The ship being looked over, and since when.
+	scrutiny: Option<(hecs::Entity, f64)>,
+	// Smoothed materials per second going into the player's repairs.
+	material_rate: [f32; 3],
+	// The title screen scene, with no fleets and a fixed camera.
+	title: bool,
+	tint: [f32; 3],
+	feedback: feedback::Feedback,
+	// Fleet ships that follow the player's orders.
+	selected: Vec<hecs::Entity>,
+	box_start: Option<Point2<f32>>,
+	control_groups: [Vec<hecs::Entity>; 10],
+	formation: Formation,
+	show_range_ring: bool,
+	debug_view: DebugView,
+	// Standing order to fire at this ship whenever it's in range.
+	engage_target: Option<hecs::Entity>,
+	// Ship we're coming alongside of, docking once we're lined up.
+	docking: Option<hecs::Entity>,
+	// Co-op. The host keeps the consort's ship, the consort keeps the host's ships by their IDs.
+	is_consort: bool,
+	consort: Option<hecs::Entity>,
+	remote_ships: HashMap<u64, hecs::Entity>,
+	net_problems: Vec<String>,
+	time_to_snapshot: f64,
+	rumors: rumors::Rumors,
+	// Global cells left to sail through, in order.
+	course: Vec<Point2<i32>>,
+	// Whether an enemy was in sight last tick, so only new contacts interrupt the course.
+	course_contact: bool,
+	// Direction (in the xz plane) from the player towards whoever hit them, and when.
+	damage_indicators: Vec<(Vector2<f32>, f64)>,
+	// Goes down as the player robs civilians, merchants charge more for it.
+	reputation: i32,
+	// Real time, since orders can be given while paused.
+	last_bark: Option<(Order, f64)>,
+	attack_held: bool,
+	// Attack was pressed while docked, ask before breaking the truce.
+	open_fire_request: bool,
+	ships_disabled: i32,
+	// Damage each ship has taken and who dealt it, so everyone who fought gets a share of the
+	// rewards.
+	assists: HashMap<hecs::Entity, Vec<(hecs::Entity, f32)>>,
+	outcome: Outcome,
+	// The player gave up watching after being defeated.
+	conceded: bool,
+	// How close the Voidwind is, 0 to 1, and how many of its omens were announced.
+	void_omen: f32,
+	omen_stage: i32,
+	omen_drone: Option<SampleInstance>,
+	time_to_omen_wisp: f64,
+	// How hard the southern void storms blow where the player is, 0 to 1.
+	storm: f32,
+	storm_warned: bool,
+	triggers: triggers::Triggers,
+}
+
+impl Map
+{
+	fn new(spectator: bool, state: &mut game_state::GameState) -> Result<Self>
+	{
+		let seed = state.seed.unwrap_or_else(|| thread_rng().gen::<u64>());
+		let mut rng = stream_rng(seed, "misc");
+		let mut worldgen_rng = stream_rng(seed, "worldgen");
+		let mut loot_rng = stream_rng(seed, "loot");
+		let combat_rng = stream_rng(seed, "combat");
+		let journal_rng = stream_rng(seed, "journal");
+		let terrain_seed = worldgen_rng.gen::<u64>();
+		let mut world = hecs::World::new();
+
+		let player = if spectator
+		{
+			// The spectator's player is just a camera.
+			world.spawn((comps::Position {
+				pos: Point3::new(0., 0., 0.),
+				dir: 0.,
+			},))
+		}
+		else
+		{
+			make_ship(
+				Point3::new(0., 0., 0.),
+				"data/small_ship.cfg",
+				comps::Team::English,
+				2,
+				&mut loot_rng,
+				&mut world,
+				state,
+			)?
+		};
+		{
+			//let mut ship_state = world.get::<&mut comps::ShipState>(player).unwrap();
+			//ship_state.hull = 10.;
+			//ship_state.crew = 1;
+			//ship_state.wounded = 0;
+			//ship_state.infirmary = 0.;
+			//ship_state.sails = 30.;
+			//ship_state.armor[0] = 50.;
+			//ship_state.armor[1] = 0.;
+			//ship_state.experience = comps::level_experience(10);
+			//ship_state.compute_level();
+		}
+
+		let mut cells = vec![];
+		for y in -CELL_RADIUS..=CELL_RADIUS
+		{
+			for x in -CELL_RADIUS..=CELL_RADIUS
+			{
+				cells.push(Cell::new(
+					Point2::new(x, y),
+					1,
+					0,
+					calendar::Date::at(0.).season,
+					cell_seed(terrain_seed, Point2::new(x, y)),
+					!spectator,
+					&mut worldgen_rng,
+					&mut world,
+					state,
+				)?);
+			}
+		}
+
+		state.cache_bitmap("data/english_flag.png")?;
+		state.cache_bitmap("data/pirate_flag.png")?;
+		state.cache_bitmap("data/french_flag.png")?;
+		state.cache_sprite("data/cursor.cfg")?;
+		state.cache_sprite("data/cannon_normal.cfg")?;
+		state.cache_sprite("data/cannon_magic.cfg")?;
+		state.cache_sprite("data/goods.cfg")?;
+		state.cache_sprite("data/cotton.cfg")?;
+		state.cache_sprite("data/tobacco.cfg")?;
+		state.cache_sprite("data/officer.cfg")?;
+		state.cache_sprite("data/cannon_rare.cfg")?;
+		state.cache_sprite("data/repair_armor.cfg")?;
+		state.cache_sprite("data/repair_hull.cfg")?;
+		state.cache_sprite("data/repair_infirmary.cfg")?;
+		state.cache_sprite("data/repair_sails.cfg")?;
+		state.cache_sprite("data/switch.cfg")?;
+		state.cache_sprite("data/recruit.cfg")?;
+		state.cache_sprite("data/give_crew.cfg")?;
+		state.cache_sprite("data/take_crew.cfg")?;
+		state.cache_sprite("data/join_fleet.cfg")?;
+		state.sfx.cache_sample("data/order.ogg")?;
+		state.sfx.cache_sample("data/equipment.ogg")?;
+		state.sfx.cache_sample("data/cannon_shot.ogg")?;
+		state.sfx.cache_sample("data/screams.ogg")?;
+		state.sfx.cache_sample("data/sink.ogg")?;
+		state.sfx.cache_sample("data/explosion.ogg")?;
+		state.sfx.cache_sample("data/armor_clang.wav")?;
+		state.sfx.cache_sample("data/hull_crunch.wav")?;
+		state.sfx.cache_sample("data/sail_rip.wav")?;
+		state.sfx.cache_sample("data/splash.wav")?;
+		state.sfx.cache_sample("data/wisp_hum.wav")?;
+		for team in [
+			comps::Team::English,
+			comps::Team::French,
+			comps::Team::Pirate,
+		]
+		{
+			for order in [Order::Move, Order::Attack, Order::Dock]
+			{
+				for variant in 1..=BARK_VARIANTS
+				{
+					state.sfx.cache_sample(&bark_sample(team, order, variant))?;
+				}
+			}
+		}
+		// Big battles fire a lot of cannons, keep the ones that matter audible.
+		state.sfx.set_voice_limits("data/cannon_shot.ogg", 1., 8);
+		state.sfx.set_voice_limits("data/explosion.ogg", 2., 6);
+		state.sfx.set_voice_limits("data/sink.ogg", 4., 3);
+		state.sfx.set_voice_limits("data/screams.ogg", 0.5, 2);
+		state.sfx.set_voice_limits("data/splash.wav", 0.3, 4);
+		state.sfx.set_voice_limits("data/wisp_hum.wav", 0.5, 3);
+		game_state::cache_mesh(state, "data/sphere.glb")?;
+		game_state::cache_mesh(state, "data/lighthouse.glb")?;
+
+		let rival_team = *[comps::Team::French, comps::Team::Pirate]
+			.choose(&mut rng)
+			.unwrap();
+		let rival = Rival {
+			name: comps::generate_captain_name(rival_team, &mut rng),
+			team: rival_team,
+			encounters: 0,
+			entity: None,
+			time_to_spawn: if spectator
+			{
+				None
+			}
+			else
+			{
+				Some(state.time() + RIVAL_FIRST_DELAY)
+			},
+			sighted: false,
+		};
+
+		let mut economy = Markets::new(0.);
+
+		for e in economy.values_mut()
+		{
+			*e = rng.gen_range(100.0..200.0);
+		}
+		update_economy(&mut economy, &mut rng);
+
+		let mut map = Self {
+			world: world,
+			seed: seed,
+			rng: rng,
+			worldgen_rng: worldgen_rng,
+			loot_rng: loot_rng,
+			combat_rng: combat_rng,
+			journal_rng: journal_rng,
+			player_pos: Point3::new(0., 0., 0.),
+			prev_player_pos: Point3::new(0., 0., 0.),
+			player: player,
+			target_entity: None,
+			selection_indicator: None,
+			buffer_width: state.display_width,
+			buffer_height: state.display_height,
+			mouse_in_buffer: true,
+			cursor: Cursor::Pointer,
+			dock_entity: None,
+			cells: cells,
+			zoom: 1.,
+			money: 500,
+			messages: message::MessageLog::new(),
+			level: 1,
+			global_offset: Vector2::new(0, 0),
+			economy: economy,
+			economy_history: vec![economy],
+			time_to_economy: state.time() + ECONOMY_INTERVAL,
+			season: calendar::Date::at(0.).season,
+			boss: None,
+			start_time: state.time(),
+			spawn_boss: !spectator,
+			spectator: spectator,
+			camera_pan: Vector2::zeros(),
+			time_to_fleets: None,
+			terrain_seed: terrain_seed,
+			discovered_lighthouses: HashMap::new(),
+			wrecks: HashMap::new(),
+			journal: journal::Journal::new(state.time()),
+			rival: rival,
+			difficulty: difficulty::Difficulty::new(state.time()),
+			damage_log: damage_log::DamageLog::new(),
+			intel: intel::IntelRegistry::new(),
+			exploration: exploration::Exploration::new(),
+			queries: systems::Queries::new(),
+			scrutiny: None,
+			material_rate: [0.; 3],
+			title: false,
+			tint: [1., 1., 1.],
+			feedback: feedback::Feedback::new(),
+			selected: vec![],
+			box_start: None,
+			control_groups: Default::default(),
+			formation: Formation::Column,
+			show_range_ring: false,
+			debug_view: DebugView::Final,
+			engage_target: None,
+			docking: None,
+			is_consort: false,
+			consort: None,
+			remote_ships: HashMap::new(),
+			net_problems: vec![],
+			time_to_snapshot: 0.,
+			rumors: rumors::Rumors::new(),
+			course: vec![],
+			course_contact: false,
+			damage_indicators: vec![],
+			reputation: 0,
+			last_bark: None,
+			attack_held: false,
+			open_fire_request: false,
+			ships_disabled: 0,
+			assists: HashMap::new(),
+			outcome: Outcome::InProgress,
+			conceded: false,
+			void_omen: 0.,
+			omen_stage: 0,
+			omen_drone: None,
+			time_to_omen_wisp: 0.,
+			storm: 0.,
+			storm_warned: false,
+			triggers: triggers::Triggers::load("data/triggers.cfg")?,
+		};
+		for text in ["Transcend the Sea", "Hunt the Voidwind", "Sail North"]
+		{
+			map.messages
+				.add(message::MessageKind::Quest, text.into(), state.time());
+		}
+		map.messages.add(
+			message::MessageKind::System,
+			format!("Voyage seed: {:016x}", map.seed),
+			state.time(),
+		);
+		if spectator
+		{
+			map.messages.clear();
+			map.spawn_fleets(state)?;
+		}
+		Ok(map)
+	}
+
+	// The same sea as the host's, but every ship in it comes from the host.
+	fn new_consort(state: &mut game_state::GameState) -> Result<Self>
+	{
+		let mut map = Self::new(false, state)?;
+		map.is_consort = true;
+		map.spawn_boss = false;
+		map.rival.time_to_spawn = None;
+		let ships: Vec<_> = map
+			.world
+			.query::<&comps::ShipState>()
+			.iter()
+			.map(|(id, _)| id)
+			.filter(|id| *id != map.player)
+			.collect();
+		for ship in ships
+		{
+			map.world.despawn(ship)?;
+		}
+		map.messages.clear();
+		map.messages.add(
+			message::MessageKind::Quest,
+			"Sail with the host".into(),
+			state.time(),
+		);
+		Ok(map)
+	}
+
+	fn spawn_fleets(&mut self, state: &mut game_state::GameState) -> Result<()>
+	{
+		let level = self.rng.gen_range(1..=5);
+		for (team, side) in [(comps::Team::English, -1.), (comps::Team::French, 1.)]
+		{
+			for _ in 0..FLEET_SIZE
+			{
+				let ship_pos = self.player_pos
+					+ Vector3::new(
+						side * 30. + self.rng.gen_range(-10.0..10.0),
+						0.,
+						self.rng.gen_range(-20.0..20.0),
+					);
+				let idx = rand_distr::WeightedIndex::new([10., 5., 1.])
+					.unwrap()
+					.sample(&mut self.rng);
+				let ship = make_ship(
+					ship_pos,
+					[
+						"data/small_ship.cfg",
+						"data/medium_ship.cfg",
+						"data/big_ship.cfg",
+					][idx],
+					team,
+					level,
+					&mut self.loot_rng,
+					&mut self.world,
+					state,
+				)?;
+				self.world.get::<&mut comps::Position>(ship)?.dir = -side * PI / 2.;
+				// Sail towards the enemy, the AI will take over once in sensing range.
+				let dx = self.rng.gen_range(-10.0..10.0);
+				let dz = self.rng.gen_range(-10.0..10.0);
+				self.world
+					.get::<&mut comps::Target>(ship)?
+					.waypoints
+					.push(comps::Waypoint {
+						pos: self.player_pos + Vector3::new(dx, 0., dz),
+						marker: None,
+					});
+				let name = comps::generate_captain_name(team, &mut self.rng);
+				self.world.insert_one(
+					ship,
+					comps::AI {
+						state: comps::AIState::Idle,
+						name: name,
+					},
+				)?;
+			}
+		}
+		self.add_message(
+			message::MessageKind::Combat,
+			"The fleets set sail!".into(),
+			state,
+		);
+		Ok(())
+	}
+
+	fn spawn_title_ships(&mut self, state: &mut game_state::GameState) -> Result<()>
+	{
+		for (i, ship_desc) in ["data/medium_ship.cfg", "data/small_ship.cfg"]
+			.iter()
+			.enumerate()
+		{
+			// Each ship sails a lazy loop across the view.
+			let r = 15. + 10. * i as f32;
+			let phase = i as f32 * PI;
+			let ship = make_ship(
+				self.player_pos + r * Vector3::new(phase.cos(), 0., phase.sin()),
+				ship_desc,
+				comps::Team::English,
+				1,
+				&mut self.loot_rng,
+				&mut self.world,
+				state,
+			)?;
+			let mut target = self.world.get::<&mut comps::Target>(ship)?;
+			target.patrol = true;
+			for j in 0..8
+			{
+				let theta = phase + (j + 1) as f32 * PI / 4.;
+				target.waypoints.push(comps::Waypoint {
+					pos: self.player_pos + r * Vector3::new(theta.cos(), 0., theta.sin()),
+					marker: None,
+				});
+			}
+		}
+		Ok(())
+	}
+
+	fn make_project(&self) -> Perspective3<f32>
+	{
+		utils::projection_transform(self.buffer_width, self.buffer_height, PI / 2.)
+	}
+
+	fn camera_pos(&self) -> Point3<f32>
+	{
+		self.camera_pos_from(self.player_pos)
+	}
+
+	fn camera_pos_from(&self, player_pos: Point3<f32>) -> Point3<f32>
+	{
+		let height = 30. / self.zoom;
+		player_pos + Vector3::new(0., height, height / 2.)
+	}
+
+	fn draw_player_pos(&self, state: &game_state::GameState) -> Point3<f32>
+	{
+		let delta = self.player_pos - self.prev_player_pos;
+		if delta.magnitude() > comps::MAX_INTERPOLATION_DIST
+		{
+			self.player_pos
+		}
+		else
+		{
+			self.prev_player_pos + state.alpha * delta
+		}
+	}
+
+	// Distance to the entity and how far the player can inspect.
+	fn inspect_range_to(&self, entity: hecs::Entity) -> Option<(f32, f32)>
+	{
+		let pos = self.world.get::<&comps::Position>(entity).ok()?;
+		let player_pos = self.world.get::<&comps::Position>(self.player).ok()?;
+		let spyglass = self
+			.world
+			.get::<&comps::Equipment>(self.player)
+			.map(|e| e.derived_stats().spyglass)
+			.unwrap_or(0.);
+		Some((
+			(pos.pos - player_pos.pos).magnitude(),
+			INSPECT_RANGE * (1. + spyglass),
+		))
+	}
+
+	fn try_inspect(&mut self, state: &mut game_state::GameState) -> Option<InspectPanel>
+	{
+		let target = self.target_entity.filter(|e| {
+			*e != self.player
+				&& self.world.get::<&comps::Equipment>(*e).is_ok()
+				&& self.world.get::<&comps::ShipState>(*e).is_ok()
+		});
+		let target = match target
+		{
+			Some(target) => target,
+			None =>
+			{
+				self.add_message(
+					message::MessageKind::System,
+					"Select a ship to inspect first.".into(),
+					state,
+				);
+				return None;
+			}
+		};
+		let (dist, range) = self.inspect_range_to(target)?;
+		if dist > range
+		{
+			self.add_message(
+				message::MessageKind::System,
+				format!("Too far away to inspect, get within {range:.0} yards."),
+				state,
+			);
+			return None;
+		}
+		Some(InspectPanel::new(target, self, state))
+	}
+
+	fn can_trade_with_dock(&self) -> bool
+	{
+		// Nobody charges for moving cargo between your own ships.
+		let is_transfer = self
+			.dock_entity
+			.map(|dock_entity| self.world.get::<&comps::Fleet>(dock_entity).is_ok())
+			.unwrap_or(false);
+		if is_transfer
+		{
+			return false;
+		}
+		let dock_team = self.dock_entity.and_then(|dock_entity| {
+			self.world
+				.get::<&comps::ShipState>(dock_entity)
+				.map(|ss| ss.team)
+				.ok()
+		});
+		let player_team = self
+			.world
+			.get::<&comps::ShipState>(self.player)
+			.map(|ss| ss.team)
+			.ok();
+
+		if let (Some(dock_team), Some(player_team)) = (dock_team, player_team)
+		{
+			dock_team.trade_with(&player_team)
+		}
+		else
+		{
+			false
+		}
+	}
+
+	fn apply_loot_filter(&mut self, state: &mut game_state::GameState)
+	{
+		let filter = &state.loot_filter;
+		let can_trade = self.can_trade_with_dock();
+		// Whether the filter's catch gets sold, if it does anything at all.
+		let filter_sells = match filter.action
+		{
+			comps::LootAction::Keep => None,
+			comps::LootAction::Discard => Some(false),
+			comps::LootAction::Sell => Some(true).filter(|_| can_trade),
+		};
+		let mut traded = Markets::new(0.);
+		let mut sold = 0;
+		let mut discarded = 0;
+		let mut earned = 0;
+		if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(self.player)
+		{
+			for slot in &mut equipment.slots
+			{
+				let sell = match slot.item.as_ref()
+				{
+					Some(item) if item.junk && can_trade => true,
+					Some(item) if slot.is_inventory && filter.matches(item) => match filter_sells
+					{
+						Some(sell) => sell,
+						None => continue,
+					},
+					_ => continue,
+				};
+				let item = slot.item.take().unwrap();
+				if sell
+				{
+					earned += item.price;
+					record_trade(&mut traded, &item.kind, -1.);
+					sold += 1;
+				}
+				else
+				{
+					discarded += 1;
+				}
+			}
+		}
+		if sold > 0
+		{
+			self.money += earned;
+			apply_trade_impact(&mut self.economy, &traded);
+			self.economy_history.push(self.economy);
+			if self.economy_history.len() > ECONOMY_HISTORY
+			{
+				self.economy_history.remove(0);
+			}
+			self.messages.add(
+				message::MessageKind::Trade,
+				format!("Auto-sold {sold} items for £{earned}."),
+				state.time(),
+			);
+		}
+		if discarded > 0
+		{
+			self.messages.add(
+				message::MessageKind::Trade,
+				format!("Discarded {discarded} items."),
+				state.time(),
+			);
+		}
+	}
+
+	fn make_camera(&self) -> Isometry3<f32>
+	{
+		utils::make_camera(self.camera_pos(), self.player_pos)
+	}
+
+	// Only for drawing, so the shaking doesn't throw off the mouse picking.
+	fn make_shaken_camera(&self, state: &game_state::GameState) -> Isometry3<f32>
+	{
+		let shake = self.feedback.camera_offset(state.core.get_time());
+		let player_pos = self.draw_player_pos(state);
+		utils::make_camera(self.camera_pos_from(player_pos) + shake, player_pos + shake)
+	}
+
+	fn impact(
+		&mut self, pos: Point3<f32>, hit: comps::HitKind, crit: bool,
+		state: &mut game_state::GameState,
+	) -> Result<()>
+	{
+		let impact = impact_effect(hit, crit);
+		state.sfx.play_positional_sound(
+			impact.sound,
+			pos.xz(),
+			self.player_pos.xz(),
+			impact.volume,
+		)?;
+		if crit
+		{
+			state.sfx.play_positional_sound(
+				"data/explosion.ogg",
+				pos.xz(),
+				self.player_pos.xz(),
+				0.5,
+			)?;
+		}
+		for _ in 0..impact.debris
+		{
+			let theta = self.rng.gen_range(0.0..PI * 2.0);
+			let speed = self.rng.gen_range(5.0..15.0);
+			let vel = Vector3::new(
+				speed * theta.cos(),
+				self.rng.gen_range(10.0..25.0),
+				speed * theta.sin(),
+			);
+			make_debris(pos, vel, &mut self.world, state)?;
+		}
+		make_impact_flash(
+			pos,
+			impact.color,
+			if crit { 6. } else { 3. },
+			&mut self.world,
+			state,
+		)?;
+		// Only feel the ones nearby.
+		let dist = (pos - self.player_pos).magnitude();
+		self.feedback
+			.impact(impact.shake, dist, state.options.feedback_intensity);
+		Ok(())
+	}
+
+	fn bark(&mut self, order: Order, state: &mut game_state::GameState) -> Result<()>
+	{
+		// Don't let click spam turn into a cacophony.
+		let time = state.core.get_time();
+		if let Some((last_order, last_time)) = self.last_bark
+		{
+			let since = time - last_time;
+			if since < BARK_INTERVAL || (last_order == order && since < BARK_REPEAT_INTERVAL)
+			{
+				return Ok(());
+			}
+		}
+		let team = self
+			.world
+			.get::<&comps::ShipState>(self.player)
+			.map(|s| s.team)
+			.unwrap_or(comps::Team::English);
+		let variant = self.rng.gen_range(1..=BARK_VARIANTS);
+		state.sfx.play_sound(&bark_sample(team, order, variant))?;
+		self.last_bark = Some((order, time));
+		Ok(())
+	}
+
+	// A lead-in point astern of the target's side nearest to us, then the spot alongside her.
+	fn docking_waypoints(&self, dock_entity: hecs::Entity) -> Option<[Point3<f32>; 2]>
+	{
+		let player_pos = self.world.get::<&comps::Position>(self.player).ok()?;
+		let player_solid = self.world.get::<&comps::Solid>(self.player).ok()?;
+		let pos = self.world.get::<&comps::Position>(dock_entity).ok()?;
+		let solid = self.world.get::<&comps::Solid>(dock_entity).ok()?;
+
+		let rot = Rotation2::new(pos.dir);
+		let forward = rot * Vector2::new(1., 0.);
+		let mut left = rot * Vector2::new(0., 1.);
+		if left.dot(&(player_pos.pos.zx() - pos.pos.zx())) < 0.
+		{
+			left = -left;
+		}
+		let side = pos.pos.zx() + left * (solid.size + player_solid.size + DOCK_DISTANCE / 2.);
+		let lead_in = side - forward * (solid.size + player_solid.size) * 2.;
+		Some([
+			Point3::new(lead_in.y, pos.pos.y, lead_in.x),
+			Point3::new(side.y, pos.pos.y, side.x),
+		])
+	}
+
+	// Dockside talk about the markets, the Voidwind and whoever else is out there.
+	fn gather_rumors(&self, state: &game_state::GameState) -> Vec<rumors::Rumor>
+	{
+		let mut rumors = vec![];
+		let average = self.economy.values().sum::<f32>() / Commodity::ALL.len() as f32;
+		for (commodity, price) in self.economy.iter()
+		{
+			if let Some(text) = rumors::market(
+				&commodity.name(state),
+				price,
+				average,
+				self.economy_trend(commodity),
+				&state.locale,
+			)
+			{
+				rumors.push(rumors::Rumor::new(text));
+			}
+		}
+
+		let global_origin = Cell::cell_to_world(Point2::origin() + self.global_offset);
+		let whereabouts =
+			|pos: Point3<f32>| rumors::whereabouts((pos - self.player_pos).xz(), CELL_SIZE as f32);
+		if let Some(pos) = self
+			.boss
+			.and_then(|boss| self.world.get::<&comps::Position>(boss).ok())
+		{
+			rumors.push(rumors::Rumor::at(
+				format!("The Voidwind was sighted {}.", whereabouts(pos.pos)),
+				pos.pos + global_origin.coords,
+			));
+		}
+
+		// The worst pirate around, and the merchant with the fullest hold.
+		let mut pirate = None;
+		let mut merchant = None;
+		for (id, (pos, ai, ship_state, equipment)) in self
+			.world
+			.query::<(
+				&comps::Position,
+				&comps::AI,
+				&comps::ShipState,
+				&comps::Equipment,
+			)>()
+			.iter()
+		{
+			if Some(id) == self.dock_entity || Some(id) == self.boss || !ship_state.is_active()
+			{
+				continue;
+			}
+			match ship_state.team
+			{
+				comps::Team::Pirate =>
+				{
+					if pirate
+						.as_ref()
+						.map(|(_, _, level)| ship_state.level > *level)
+						.unwrap_or(true)
+					{
+						pirate = Some((pos.pos, ai.name.clone(), ship_state.level));
+					}
+				}
+				comps::Team::Civilian =>
+				{
+					let cargo: Vec<_> = equipment
+						.slots
+						.iter()
+						.filter_map(|slot| slot.item.as_ref())
+						.filter(|item| item.kind.is_cargo())
+						.collect();
+					if let Some(item) = cargo.first()
+					{
+						if merchant
+							.as_ref()
+							.map(|(_, _, _, count)| cargo.len() > *count)
+							.unwrap_or(true)
+						{
+							merchant = Some((
+								pos.pos,
+								ai.name.clone(),
+								item.kind.name().to_lowercase(),
+								cargo.len(),
+							));
+						}
+					}
+				}
+				_ => (),
+			}
+		}
+		if let Some((pos, name, _)) = pirate
+		{
+			rumors.push(rumors::Rumor::at(
+				format!(
+					"There's a bounty on {name}, last seen {}.",
+					whereabouts(pos)
+				),
+				pos + global_origin.coords,
+			));
+		}
+		if let Some((pos, name, cargo, _)) = merchant
+		{
+			rumors.push(rumors::Rumor::at(
+				format!("{name} is carrying {cargo}, {}.", whereabouts(pos)),
+				pos + global_origin.coords,
+			));
+		}
+		rumors
+	}
+
+	fn chart_menu(&self, state: &game_state::GameState) -> ui::ChartMenu
+	{
+		let global_origin = Cell::cell_to_world(Point2::origin() + self.global_offset);
+		let to_chart = |global_pos: Point3<f32>| global_pos.xz() / CELL_SIZE as f32;
+		let center = Cell::world_to_cell(&self.player_pos) + self.global_offset;
+		let mut cells = vec![];
+		for y in -CHART_VIEW_RADIUS..=CHART_VIEW_RADIUS
+		{
+			for x in -CHART_VIEW_RADIUS..=CHART_VIEW_RADIUS
+			{
+				let pos = center + Vector2::new(x, y);
+				let kind = if Cell::southern_storm(&Cell::cell_to_world(pos), Vector2::zeros()) > 0.
+				{
+					ui::ChartCell::Storm
+				}
+				else if Cell::chart_biome(self.terrain_seed, pos) == Biome::Shallows
+				{
+					ui::ChartCell::Shallows
+				}
+				else
+				{
+					ui::ChartCell::Deep
+				};
+				cells.push((pos, kind, self.exploration.visited(pos)));
+			}
+		}
+		let mut markers: Vec<_> = self
+			.discovered_lighthouses
+			.values()
+			.map(|pos| (to_chart(*pos), Color::from_rgb_f(0.9, 0.8, 0.4)))
+			.collect();
+		// Where the captains we know of were last seen.
+		for (_, intel) in self.intel.ships()
+		{
+			if intel.level >= intel::IntelLevel::Identified
+			{
+				let pos = Point3::new(intel.last_pos[0], 0., intel.last_pos[1]);
+				markers.push((to_chart(pos), Color::from_rgb_f(0.9, 0.3, 0.2)));
+			}
+		}
+		for (pos, landmark) in self.exploration.landmarks()
+		{
+			let [r, g, b] = landmark.color();
+			markers.push((pos, Color::from_rgb_f(r, g, b)));
+		}
+		for (pos, f) in self.rumors.markers(state.time())
+		{
+			markers.push((
+				to_chart(pos),
+				Color::from_rgba_f(0.6 * f, 0.9 * f, 0.6 * f, f),
+			));
+		}
+		ui::ChartMenu::new(
+			state,
+			center,
+			CHART_RADIUS,
+			cells,
+			markers,
+			self.exploration.notes.clone(),
+			to_chart(self.player_pos + global_origin.coords),
+			self.course.clone(),
+		)
+	}
+
+	fn clear_course(&mut self)
+	{
+		if !self.course.is_empty()
+		{
+			self.course.clear();
+			self.clear_player_waypoints();
+		}
+	}
+
+	// For when there's no to_die list to put the markers on.
+	fn clear_player_waypoints(&mut self)
+	{
+		let mut markers = vec![];
+		if let Ok(mut target) = self.world.get::<&mut comps::Target>(self.player)
+		{
+			target.clear(|m| markers.push(m));
+		}
+		for marker in markers
+		{
+			self.world.despawn(marker).ok();
+		}
+	}
+
+	// Plots a course through the charted cells, steering clear of the storms and, for big ships,
+	// the shallows.
+	fn set_course(&mut self, dest: Point2<i32>, state: &mut game_state::GameState) -> Result<()>
+	{
+		let from = Cell::world_to_cell(&self.player_pos) + self.global_offset;
+		let corner = from - Vector2::new(CHART_RADIUS, CHART_RADIUS);
+		let big = self
+			.world
+			.get::<&comps::Solid>(self.player)
+			.map(|s| s.size >= SHALLOWS_DRAFT)
+			.unwrap_or(false);
+		let terrain_seed = self.terrain_seed;
+		let mut context = astar::AStarContext::new((2 * CHART_RADIUS + 1) as usize);
+		let path = context.solve(
+			Point2::from(from - corner),
+			Point2::from(dest - corner),
+			|p| {
+				Cell::southern_storm(&Cell::cell_to_world(p + corner.coords), Vector2::zeros()) > 0.
+			},
+			|p| {
+				if big && Cell::chart_biome(terrain_seed, p + corner.coords) == Biome::Shallows
+				{
+					3.
+				}
+				else
+				{
+					0.
+				}
+			},
+		);
+		self.course = path
+			.into_iter()
+			.rev()
+			.skip(1)
+			.map(|p| p + corner.coords)
+			.collect();
+		// Whoever's already in sight doesn't count as a new contact.
+		self.course_contact = true;
+		self.docking = None;
+		if self.course.is_empty()
+		{
+			self.add_message(
+				message::MessageKind::Quest,
+				"There's no course to be plotted there.".into(),
+				state,
+			);
+			return Ok(());
+		}
+		self.clear_player_waypoints();
+		self.bark(Order::Move, state)
+	}
+
+	fn abort_docking(
+		&mut self, reason: &str, to_die: &mut Vec<hecs::Entity>, state: &game_state::GameState,
+	)
+	{
+		if self.docking.take().is_none()
+		{
+			return;
+		}
+		if let Ok(mut player_target) = self.world.get::<&mut comps::Target>(self.player)
+		{
+			player_target.clear(|m| to_die.push(m));
+		}
+		self.add_message(message::MessageKind::Combat, reason.into(), state);
+	}
+
+	fn dock(&mut self, dock_entity: hecs::Entity, state: &game_state::GameState)
+	{
+		let trade = if let (Ok(player_ship_state), Ok(ship_state)) = (
+			self.world.get::<&comps::ShipState>(self.player),
+			self.world.get::<&comps::ShipState>(dock_entity),
+		)
+		{
+			ship_state.team.trade_with(&player_ship_state.team)
+		}
+		else
+		{
+			return;
+		};
+		self.dock_entity = Some(dock_entity);
+		self.triggers.fire(triggers::Event::Docked { trade: trade });
+		if trade
+		{
+			if let Ok(pos) = self.world.get::<&comps::Position>(dock_entity)
+			{
+				let global_origin = Cell::cell_to_world(Point2::origin() + self.global_offset);
+				let pos = (pos.pos + global_origin.coords).xz() / CELL_SIZE as f32;
+				self.exploration.mark(pos, exploration::Landmark::Market);
+			}
+			let rumors = self.gather_rumors(state);
+			self.rumors.hear(rumors, state.time(), &mut self.rng);
+		}
+		if trade
+		{
+			// Each robbed civilian adds a tenth to what merchants ask.
+			let markup =
+				(1. + 0.1 * (-self.reputation).max(0) as f32) * state.modifiers.merchant_markup;
+			for entity in [self.player, dock_entity]
+			{
+				if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(entity)
+				{
+					for slot in &mut equipment.slots
+					{
+						if let Some(item) = slot.item.as_mut()
+						{
+							item.price = self.item_value(&item.kind);
+							if entity != self.player
+							{
+								item.price = round_price(item.price as f32 * markup);
+							}
+						}
+					}
+				}
+			}
+		}
+	}
+
+	// What an item goes for at today's prices, before any markup.
+	fn item_value(&self, kind: &comps::ItemKind) -> i32
+	{
+		match kind
+		{
+			comps::ItemKind::Weapon(weapon) => round_price(
+				comps::level_effectiveness(weapon.level)
+					* (1 + weapon.prefixes.len() + weapon.suffixes.len()) as f32
+					* self.economy[Commodity::Weapon],
+			),
+			comps::ItemKind::Officer(officer) => round_price(
+				comps::level_effectiveness(officer.level)
+					* (1 + officer.prefixes.len() + officer.suffixes.len()) as f32
+					* self.economy[Commodity::Officer],
+			),
+			comps::ItemKind::Goods(level) =>
+			{
+				round_price(comps::level_effectiveness(*level) * self.economy[Commodity::Goods])
+			}
+			comps::ItemKind::Tobacco(level) =>
+			{
+				round_price(comps::level_effectiveness(*level) * self.economy[Commodity::Tobacco])
+			}
+			comps::ItemKind::Cotton(level) =>
+			{
+				round_price(comps::level_effectiveness(*level) * self.economy[Commodity::Cotton])
+			}
+			comps::ItemKind::Rigging(rigging) => round_price(
+				comps::level_effectiveness(rigging.level) * self.economy[Commodity::Weapon],
+			),
+			// Priced like the lowest grade of goods, less what's been used.
+			comps::ItemKind::Material(material) => round_price(
+				comps::level_effectiveness(1) * material.amount / comps::MATERIAL_AMOUNT
+					* self.economy[Commodity::Goods],
+			),
+		}
+	}
+
+	// The quick look at the player's ship: guns, officers, cargo and crew.
+	fn ship_summary(&self) -> Vec<String>
+	{
+		let mut lines = vec![];
+		let (Ok(ship_state), Ok(stats), Ok(equipment)) = (
+			self.world.get::<&comps::ShipState>(self.player),
+			self.world.get::<&comps::ShipStats>(self.player),
+			self.world.get::<&comps::Equipment>(self.player),
+		)
+		else
+		{
+			return lines;
+		};
+		let derived_stats = equipment.derived_stats();
+
+		lines.push(format!(
+			"Crew: {}/{}, {} wounded",
+			ship_state.crew, stats.crew, ship_state.wounded
+		));
+
+		// Sustained fire, with every manned gun reloading at once.
+		let weapons: Vec<_> = equipment
+			.slots
+			.iter()
+			.filter(|slot| !slot.is_inventory)
+			.filter_map(|slot| match slot.item.as_ref().map(|item| &item.kind)
+			{
+				Some(comps::ItemKind::Weapon(weapon)) => Some((weapon, slot.disabled)),
+				_ => None,
+			})
+			.collect();
+		let manned = weapons.iter().filter(|(_, disabled)| !disabled).count() as i32;
+		let gunnery_crew =
+			ship_state.crew as f32 * ship_state.specialty_effectiveness(comps::Specialty::Gunnery);
+		let rate = reload_rate(gunnery_crew, manned.max(1), &derived_stats);
+		let mut weapon_lines = vec![];
+		let mut total_dps = 0.;
+		for (weapon, disabled) in &weapons
+		{
+			let weapon_stats = weapon.stats();
+			if *disabled
+			{
+				weapon_lines.push(format!(
+					"  {} (Level {}): stood down",
+					weapon.name, weapon.level
+				));
+				continue;
+			}
+			let mut expected = weapon_stats;
+			expected.critical_chance *= 1. + derived_stats.critical_chance;
+			let dps = expected.expected_damage() * rate / weapon_stats.fire_interval;
+			total_dps += dps;
+			weapon_lines.push(format!(
+				"  {} (Level {}): {:.1} damage/s",
+				weapon.name, weapon.level, dps
+			));
+		}
+		lines.push(String::new());
+		lines.push(format!(
+			"Guns: {manned} manned, about {total_dps:.1} damage/s"
+		));
+		lines.extend(weapon_lines);
+
+		lines.push(String::new());
+		lines.push("Officers:".into());
+		let bonuses = derived_stats.describe();
+		if bonuses.is_empty()
+		{
+			lines.push("  No bonuses".into());
+		}
+		lines.extend(bonuses.into_iter().map(|line| format!("  {line}")));
+
+		// Like items are lumped together.
+		let mut cargo: Vec<(String, i32, i32)> = vec![];
+		for item in equipment
+			.slots
+			.iter()
+			.filter(|slot| slot.is_inventory)
+			.filter_map(|slot| slot.item.as_ref())
+		{
+			let name = item.kind.name().to_string();
+			let value = self.item_value(&item.kind);
+			if let Some(entry) = cargo.iter_mut().find(|(other, _, _)| *other == name)
+			{
+				entry.1 += 1;
+				entry.2 += value;
+			}
+			else
+			{
+				cargo.push((name, 1, value));
+			}
+		}
+		let total: i32 = cargo.iter().map(|(_, _, value)| value).sum();
+		lines.push(String::new());
+		lines.push(format!("Cargo, worth {total} at today's prices:"));
+		if cargo.is_empty()
+		{
+			lines.push("  Empty".into());
+		}
+		for (name, count, value) in cargo
+		{
+			lines.push(format!("  {count}x {name}: {value}"));
+		}
+		lines
+	}
+
+	fn add_message(
+		&mut self, kind: message::MessageKind, message: String, state: &game_state::GameState,
+	)
+	{
+		self.messages.add(kind, message, state.time());
+	}
+
+	// Snapshots come every SNAPSHOT_INTERVAL, so each problem with them is only told once.
+	fn net_problem(&mut self, problem: String, state: &game_state::GameState)
+	{
+		if !self.net_problems.contains(&problem)
+		{
+			self.add_message(message::MessageKind::System, problem.clone(), state);
+			self.net_problems.push(problem);
+		}
+	}
+
+	// Shots fly past their own side, unless friendly fire is on and they're clear of the gun.
+	fn shot_passes(
+		&self, shot: hecs::Entity, other: hecs::Entity, friendly_fire: bool, time: f64,
+	) -> bool
+	{
+		let projectile = match self.world.get::<&comps::Projectile>(shot)
+		{
+			Ok(projectile) => *projectile,
+			Err(_) => return false,
+		};
+		let same_team = self
+			.world
+			.get::<&comps::ShipState>(other)
+			.map(|ship_state| ship_state.team == projectile.team)
+			.unwrap_or(false);
+		same_team && (!friendly_fire || time - projectile.spawn_time < PROJECTILE_GRACE)
+	}
+
+	// Remembers a wreck leaving the loaded cells, so it's still there on the way back.
+	fn stash_wreck(&mut self, id: hecs::Entity, cell: Point2<i32>) -> Option<()>
+	{
+		let visits = self.world.get::<&comps::Wreck>(id).ok()?.visits;
+		let pos = *self.world.get::<&comps::Position>(id).ok()?;
+		let record = WreckRecord {
+			desc: self.world.get::<&comps::ShipDesc>(id).ok()?.desc.clone(),
+			mesh: self.world.get::<&comps::Mesh>(id).ok()?.mesh.clone(),
+			level: self.world.get::<&comps::ShipState>(id).ok()?.level,
+			offset: pos.pos - Cell::cell_to_world(cell),
+			dir: pos.dir,
+			ship_state: (*self.world.get::<&comps::ShipState>(id).ok()?).clone(),
+			equipment: (*self.world.get::<&comps::Equipment>(id).ok()?).clone(),
+			visits: visits,
+		};
+		self.wrecks
+			.entry(cell + self.global_offset)
+			.or_default()
+			.push(record);
+		Some(())
+	}
+
+	// Puts back the wrecks left in a cell, each a little lower in the water than last time.
+	fn raise_wrecks(&mut self, cell: Point2<i32>, state: &mut game_state::GameState) -> Result<()>
+	{
+		let records = self
+			.wrecks
+			.remove(&(cell + self.global_offset))
+			.unwrap_or_default();
+		if !records.is_empty() && records.iter().all(|r| r.visits >= WRECK_VISITS)
+		{
+			self.exploration
+				.clear(cell + self.global_offset, exploration::Landmark::Wreck);
+		}
+		for mut record in records
+		{
+			let visits = record.visits + 1;
+			if visits > WRECK_VISITS
+			{
+				continue;
+			}
+			let pos = Cell::cell_to_world(cell) + record.offset
+				- Vector3::new(0., WRECK_SINK_DEPTH * visits as f32, 0.);
+			let id = make_ship(
+				pos,
+				&record.desc,
+				comps::Team::Neutral,
+				record.level,
+				&mut self.rng,
+				&mut self.world,
+				state,
+			)?;
+			record.equipment.target_pos = pos;
+			self.world.insert(
+				id,
+				(
+					comps::Position {
+						pos: pos,
+						dir: record.dir,
+					},
+					comps::Mesh { mesh: record.mesh },
+					record.ship_state,
+					record.equipment,
+					comps::Wreck { visits: visits },
+				),
+			)?;
+			self.world.remove_one::<comps::Flag>(id).ok();
+			// Settled on the bottom, nothing's moving it now.
+			self.world.get::<&mut comps::Solid>(id).unwrap().mass = f32::INFINITY;
+		}
+		Ok(())
+	}
+
+	// Moves the origin to the player's cell, so positions stay small and precise however far the
+	// voyage goes.
+	fn recenter(&mut self, player_cell: Point2<i32>)
+	{
+		self.global_offset += player_cell.coords;
+		for cell in &mut self.cells
+		{
+			cell.center -= player_cell.coords;
+		}
+		let offt = Cell::cell_to_world(player_cell).coords;
+		registry::shift_world_space(&mut self.world, offt);
+		self.player_pos -= offt;
+
+		if cfg!(debug_assertions)
+		{
+			check_world_space(&self.world, self.player_pos);
+		}
+	}
+
+	fn to_wire(&self, pos: Point3<f32>) -> net::WirePos
+	{
+		net::WirePos {
+			cell: [self.global_offset.x, self.global_offset.y],
+			x: pos.x,
+			z: pos.z,
+		}
+	}
+
+	fn from_wire(&self, pos: &net::WirePos) -> Point3<f32>
+	{
+		let cell = Point2::new(pos.cell[0], pos.cell[1]) - self.global_offset;
+		Cell::cell_to_world(cell) + Vector3::new(pos.x, 0., pos.z)
+	}
+
+	fn net_logic(
+		&mut self, to_die: &mut Vec<hecs::Entity>, state: &mut game_state::GameState,
+	) -> Result<()>
+	{
+		// The menu's backdrop sails on while the lobby has the link.
+		if self.spectator
+		{
+			return Ok(());
+		}
+		let messages = match state.net.as_mut().map(|net| net.receive())
+		{
+			None => return Ok(()),
+			Some(Ok(messages)) => messages,
+			Some(Err(e)) =>
+			{
+				state.net = None;
+				self.add_message(
+					message::MessageKind::System,
+					format!("Lost the co-op link: {}", e.short()),
+					state,
+				);
+				return Ok(());
+			}
+		};
+		if self.is_consort
+		{
+			self.mirror_host(messages, to_die, state)
+		}
+		else
+		{
+			self.host_consort(messages, to_die, state)
+		}
+	}
+
+	// Follows the consort's orders, and tells them where everyone is.
+	fn host_consort(
+		&mut self, messages: Vec<net::Message>, to_die: &mut Vec<hecs::Entity>,
+		state: &mut game_state::GameState,
+	) -> Result<()>
+	{
+		let consort = match self.consort.filter(|e| self.world.contains(*e))
+		{
+			Some(consort) => consort,
+			None =>
+			{
+				let consort = make_ship(
+					self.player_pos + Vector3::new(10., 0., 0.),
+					"data/small_ship.cfg",
+					comps::Team::English,
+					2,
+					&mut self.loot_rng,
+					&mut self.world,
+					state,
+				)?;
+				self.world.insert_one(consort, comps::Consort)?;
+				self.consort = Some(consort);
+				self.add_message(
+					message::MessageKind::System,
+					"Your consort has joined the voyage.".into(),
+					state,
+				);
+				consort
+			}
+		};
+
+		for message in messages
+		{
+			let input = match message
+			{
+				net::Message::Input(input) => input,
+				_ => continue,
+			};
+			let pos = self.from_wire(&input.pos);
+			match input.action
+			{
+				controls::Action::Move | controls::Action::Stop =>
+				{
+					if let Ok(mut target) = self.world.get::<&mut comps::Target>(consort)
+					{
+						if !input.queue
+						{
+							target.clear(|m| to_die.push(m));
+						}
+						if input.action == controls::Action::Move
+						{
+							target.waypoints.push(comps::Waypoint {
+								pos: pos,
+								marker: None,
+							});
+						}
+					}
+				}
+				controls::Action::Attack =>
+				{
+					if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(consort)
+					{
+						equipment.want_attack = input.on;
+						equipment.target_pos = pos;
+					}
+				}
+				_ => (),
+			}
+		}
+
+		if state.time() > self.time_to_snapshot
+		{
+			self.time_to_snapshot = state.time() + net::SNAPSHOT_INTERVAL;
+			let mut ships = vec![];
+			for (id, (pos, ship_state, desc)) in self
+				.world
+				.query::<(&comps::Position, &comps::ShipState, &comps::ShipDesc)>()
+				.iter()
+			{
+				let components = registry::save_components(&self.world, id, net::SHIP_COMPONENTS)?;
+				ships.push(net::ShipSnapshot {
+					id: id.to_bits().get(),
+					desc: desc.desc.clone(),
+					team: ship_state.team,
+					level: ship_state.level,
+					pos: self.to_wire(pos.pos),
+					dir: pos.dir,
+					components: format!("{components}"),
+				});
+			}
+			let snapshot = net::Snapshot {
+				consort: consort.to_bits().get(),
+				ships: ships,
+			};
+			if let Some(net) = state.net.as_mut()
+			{
+				net.send(&net::Message::Snapshot(snapshot)).ok();
+			}
+		}
+		Ok(())
+	}
+
+	// Puts every ship where the host says it is. Ours is left alone unless it strays, so that
+	// orders feel immediate.
+	fn mirror_host(
+		&mut self, messages: Vec<net::Message>, to_die: &mut Vec<hecs::Entity>,
+		state: &mut game_state::GameState,
+	) -> Result<()>
+	{
+		for message in messages
+		{
+			let snapshot = match message
+			{
+				net::Message::Snapshot(snapshot) => snapshot,
+				_ => continue,
+			};
+			let mut seen = vec![];
+			for ship in &snapshot.ships
+			{
+				let pos = self.from_wire(&ship.pos);
+				let existing = self
+					.remote_ships
+					.get(&ship.id)
+					.copied()
+					.filter(|e| self.world.contains(*e));
+				let entity = if ship.id == snapshot.consort
+				{
+					self.player
+				}
+				else if let Some(entity) = existing
+				{
+					entity
+				}
+				else
+				{
+					// The host could name any file at all, so only take ships this side could
+					// have spawned itself.
+					if !known_ship_desc(&ship.desc, state)
+					{
+						self.net_problem(
+							format!("Skipped the host's unknown ship '{}'", ship.desc),
+							state,
+						);
+						continue;
+					}
+					match make_ship(
+						pos,
+						&ship.desc,
+						ship.team,
+						ship.level,
+						&mut self.loot_rng,
+						&mut self.world,
+						state,
+					)
+					{
+						Ok(entity) =>
+						{
+							self.remote_ships.insert(ship.id, entity);
+							entity
+						}
+						Err(e) =>
+						{
+							self.net_problem(
+								format!("Skipped the host's ship '{}': {e}", ship.desc),
+								state,
+							);
+							continue;
+						}
+					}
+				};
+				seen.push(entity);
+				if let Ok(mut position) = self.world.get::<&mut comps::Position>(entity)
+				{
+					if entity != self.player || (position.pos - pos).magnitude() > CONSORT_DRIFT
+					{
+						position.pos = pos;
+						position.dir = ship.dir;
+					}
+				}
+				// The two sides' clocks differ, so ours keeps its own ability timers.
+				let timers = self
+					.world
+					.get::<&comps::ShipState>(entity)
+					.ok()
+					.filter(|_| entity == self.player)
+					.map(|ship_state| (ship_state.ability_ready, ship_state.hard_turn_until));
+				let loaded = ConfigElement::from_str(&ship.components)
+					.map_err(|e| Error::new("Couldn't read a ship".to_string(), Some(Box::new(e))))
+					.and_then(|components| {
+						registry::load_entity(&mut self.world, entity, &components)
+					});
+				if let Err(e) = loaded
+				{
+					self.net_problem(format!("Couldn't update the host's ship: {e}"), state);
+				}
+				if let (Some((ability_ready, hard_turn_until)), Ok(mut ship_state)) =
+					(timers, self.world.get::<&mut comps::ShipState>(entity))
+				{
+					ship_state.ability_ready = ability_ready;
+					ship_state.hard_turn_until = hard_turn_until;
+				}
+			}
+			// Sunk, or out of the host's sight.
+			self.remote_ships.retain(|_, entity| {
+				let keep = seen.contains(entity);
+				if !keep
+				{
+					to_die.push(*entity);
+				}
+				keep
+			});
+		}
+		Ok(())
+	}
+
+	// Orders for the consort's ship are carried out on the host, this side just predicts them.
+	fn send_order(
+		&self, action: controls::Action, on: bool, queue: bool, pos: Point3<f32>,
+		state: &mut game_state::GameState,
+	)
+	{
+		if !self.is_consort
+		{
+			return;
+		}
+		let input = net::RemoteInput {
+			action: action,
+			on: on,
+			queue: queue,
+			pos: self.to_wire(pos),
+		};
+		if let Some(net) = state.net.as_mut()
+		{
+			net.send(&net::Message::Input(input)).ok();
+		}
+	}
+
+	// The row of cells the player is in, counting from the start.
+	fn player_latitude(&self) -> i32
+	{
+		Cell::world_to_cell(&self.player_pos).y + self.global_offset.y
+	}
+
+	fn player_heel(&self) -> f32
+	{
+		self.world
+			.get::<&comps::Tilt>(self.player)
+			.map(|tilt| tilt.heel)
+			.unwrap_or(0.)
+	}
+
+	fn world_to_screen(&self, pos: Point3<f32>) -> Point2<f32>
+	{
+		let screen_pos = (self.make_project().to_homogeneous()
+			* self.make_camera().to_homogeneous())
+		.transform_point(&pos);
+		Point2::new(
+			(screen_pos.x + 1.) / 2. * self.buffer_width,
+			(1. - screen_pos.y) / 2. * self.buffer_height,
+		)
+	}
+
+	fn get_mouse_ground_pos(&self, state: &game_state::GameState) -> Point3<f32>
+	{
+		let (x, y) = (state.mouse_pos.x, state.mouse_pos.y);
+		let fx = -1. + 2. * x as f32 / self.buffer_width;
+		let fy = -1. + 2. * y as f32 / self.buffer_height;
+		let camera = self.make_camera();
+		utils::get_ground_from_screen(fx, -fy, self.make_project(), camera)
+	}
+
+	fn logic(&mut self, state: &mut game_state::GameState)
+		-> Result<Option<game_state::NextScreen>>
+	{
+		let mut to_die = vec![];
+		let mut boss_defeated = false;
+		let dt = state.dt();
+
+		self.damage_indicators
+			.retain(|(_, time)| state.time() - time < DAMAGE_INDICATOR_TIME);
+
+		let new_prev = self.queries.prev_positions(&mut self.world);
+		for (id, prev) in new_prev
+		{
+			self.world.insert_one(id, prev)?;
+		}
+
+		self.net_logic(&mut to_die, state)?;
+
+		// Hold still for a moment after a big hit.
+		if self.feedback.frozen(dt)
+		{
+			return Ok(None);
+		}
+
+		// Messages
+		self.messages.update(state.time());
+		if state.options.adaptive_difficulty
+		{
+			self.difficulty.logic(state.time());
+		}
+		self.intel_logic(state);
+		self.exploration_logic();
+
+		let season = calendar::Date::at(state.time() - self.start_time).season;
+		if season != self.season
+		{
+			for commodity in Commodity::ALL
+			{
+				self.economy[commodity] *=
+					season.price_factor(commodity) / self.season.price_factor(commodity);
+			}
+			self.season = season;
+			self.add_message(message::MessageKind::System, season.herald().into(), state);
+		}
+
+		if state.time() > self.time_to_economy
+		{
+			let (commodity, increased) = update_economy(&mut self.economy, &mut self.rng);
+			self.economy_history.push(self.economy);
+			if self.economy_history.len() > ECONOMY_HISTORY
+			{
+				self.economy_history.remove(0);
+			}
+
+			let message = state.locale.text(
+				if increased
+				{
+					"market_rising"
+				}
+				else
+				{
+					"market_falling"
+				},
+				&[
+					("name", &commodity.name(state)),
+					("price", &round_price(self.economy[commodity]).to_string()),
+				],
+			);
+
+			self.messages
+				.add(message::MessageKind::Trade, message, state.time());
+
+			self.time_to_economy = state.time() + ECONOMY_INTERVAL;
+		}
+		self.rumors.logic(state.time());
+
+		let mut timer = Timer::new("cell changes", state);
+		// Cell changes
+		let mut new_cell_centers = vec![];
+		let player_cell = Cell::world_to_cell(&self.player_pos);
+		let mut good_cells = vec![];
+
+		for cell in &self.cells
+		{
+			let disp = cell.center - player_cell;
+			if disp.x.abs() <= CELL_RADIUS && disp.y.abs() <= CELL_RADIUS
+			{
+				good_cells.push(cell.clone());
+			}
+		}
+		self.cells.clear();
+
+		let mut leaving = vec![];
+		for (id, (position, rival)) in self
+			.world
+			.query::<(&mut comps::Position, Option<&comps::Rival>)>()
+			.iter()
+		{
+			let cell = Cell::world_to_cell(&position.pos);
+			let disp = cell - player_cell;
+			if disp.x.abs() > CELL_RADIUS || disp.y.abs() > CELL_RADIUS
+			{
+				if rival.is_some()
+				{
+					// The rival follows the player wherever they go.
+					let sz = CELL_SIZE as f32;
+					let min =
+						Cell::cell_to_world(player_cell - Vector2::new(CELL_RADIUS, CELL_RADIUS))
+							- Vector3::new(sz / 2. - 1., 0., sz / 2. - 1.);
+					let max =
+						Cell::cell_to_world(player_cell + Vector2::new(CELL_RADIUS, CELL_RADIUS))
+							+ Vector3::new(sz / 2. - 1., 0., sz / 2. - 1.);
+					position.pos.x = position.pos.x.max(min.x).min(max.x);
+					position.pos.z = position.pos.z.max(min.z).min(max.z);
+				}
+				else
+				{
+					to_die.push(id);
+					leaving.push((id, cell));
+				}
+			}
+		}
+		for (id, cell) in leaving
+		{
+			self.stash_wreck(id, cell);
+		}
+
+		for dy in -CELL_RADIUS..=CELL_RADIUS
+		{
+			for dx in -CELL_RADIUS..=CELL_RADIUS
+			{
+				let cell_center = player_cell + Vector2::new(dx, dy);
+
+				let mut found = false;
+				for cell in &good_cells
+				{
+					if cell.center == cell_center
+					{
+						self.cells.push(cell.clone());
+						found = true;
+						break;
+					}
+				}
+
+				if !found
+				{
+					new_cell_centers.push(cell_center);
+					//println!("New cell {}", cell_center);
+				}
+			}
+		}
+
+		new_cell_centers.shuffle(&mut self.worldgen_rng);
+
+		if self.spawn_boss && !new_cell_centers.is_empty()
+		{
+			if let Some(boss) = self.boss
+			{
+				if !self.world.contains(boss)
+				{
+					self.boss = None;
+				}
+			}
+			if self.boss.is_none()
+			{
+				//println!("Spawned boss");
+				let boss = make_ship(
+					Cell::cell_to_world(new_cell_centers[0]),
+					//self.player_pos + Vector3::new(0., 0., 100.),
+					"data/boss_ship.cfg",
+					comps::Team::Pirate,
+					(-self.global_offset.y + 10).max(15),
+					&mut self.loot_rng,
+					&mut self.world,
+					state,
+				)?;
+				self.world.insert(
+					boss,
+					(
+						comps::AI {
+							state: comps::AIState::Idle,
+							name: "Voidwind".into(),
+						},
+						comps::WispSpawner {
+							time_to_spawn: state.time(),
+						},
+						comps::EscortSpawner {
+							time_to_spawn: state.time(),
+							max_escorts: 3,
+						},
+					),
+				)?;
+				{
+					let mut ship_state = self.world.get::<&mut comps::ShipState>(boss).unwrap();
+					ship_state.is_boss = true;
+				}
+				self.boss = Some(boss);
+			}
+		}
+
+		let level_shift = self.adaptive(state).map(|d| d.level_shift()).unwrap_or(0);
+		for cell_center in new_cell_centers
+		{
+			let level = Cell::latitude_level(cell_center.y + self.global_offset.y);
+			//println!("LEVEL {} {:?}", level, self.global_offset);
+			self.cells.push(Cell::new(
+				cell_center,
+				level,
+				level_shift,
+				self.season,
+				cell_seed(self.terrain_seed, cell_center + self.global_offset),
+				!self.spectator && !self.is_consort,
+				&mut self.worldgen_rng,
+				&mut self.world,
+				state,
+			)?);
+			self.raise_wrecks(cell_center, state)?;
+		}
+
+		if player_cell != Point2::origin()
+		{
+			self.recenter(player_cell);
+		}
+		timer.record(&state.core);
+
+		let mut timer = Timer::new("physics", state);
+		// Collision.
+		let center = self.player_pos.zx();
+		let mut grid = spatial_grid::SpatialGrid::new(128, 128, 8.0, 8.0);
+		for (id, (position, solid)) in self
+			.world
+			.query::<(&comps::Position, &comps::Solid)>()
+			.iter()
+		{
+			let pos = Point2::from(position.pos.zx() - center);
+			let disp = Vector2::new(solid.size, solid.size);
+			grid.push(spatial_grid::entry(
+				pos - disp,
+				pos + disp,
+				CollisionEntry {
+					entity: id,
+					pos: position.pos,
+				},
+			));
+		}
+		timer.record(&state.core);
+
+		let mut timer = Timer::new("physics", state);
+		// Physics
+		self.queries.physics(&mut self.world, dt, GRAVITY);
+		self.queries.attachments(&mut self.world);
+		timer.record(&state.core);
+
+		// Collides with water.
+		let mut splashes = vec![];
+		let mut submerge = vec![];
+		for (id, (water, pos, solid)) in self
+			.world
+			.query::<(
+				&comps::CollidesWithWater,
+				&mut comps::Position,
+				Option<&comps::Solid>,
+			)>()
+			.without::<&comps::Submerged>()
+			.iter()
+		{
+			if pos.pos.y < -0.0
+			{
+				if water.sink_time > 0.
+				{
+					submerge.push((id, water.clone()));
+				}
+				else
+				{
+					to_die.push(id);
+				}
+				// Debris just sinks quietly.
+				if solid.is_some()
+				{
+					splashes.push(pos.pos);
+				}
+			}
+		}
+		for (id, water) in submerge
+		{
+			self.world.insert_one(
+				id,
+				comps::Submerged {
+					time_left: water.sink_time,
+					sink_time: water.sink_time,
+					drag: water.drag,
+				},
+			)?;
+			self.world.remove_one::<comps::Lights>(id).ok();
+			if !water.live_underwater
+			{
+				self.world.remove_one::<comps::OnContactEffect>(id).ok();
+			}
+			if let Ok(mut time_to_die) = self.world.get::<&mut comps::TimeToDie>(id)
+			{
+				time_to_die.time_to_die = state.time() + water.sink_time as f64;
+			}
+		}
+		self.queries.submerged(&mut self.world, dt, &mut to_die);
+		for pos in splashes
+		{
+			make_splash(pos, 1.5, &mut self.world, state);
+			state.sfx.play_positional_sound(
+				"data/splash.wav",
+				pos.xz(),
+				self.player_pos.xz(),
+				0.3,
+			)?;
+		}
+		self.feedback.logic(dt);
+
+		let mut timer = Timer::new("ship_state", state);
+		// Ship state simulation.
+		let mut num_ships = 0;
+		let hostile = self.hostile_difficulty(state);
+		let mut material_used = [0.; 3];
+		for (id, (ship_state, stats, equipment)) in self
+			.world
+			.query::<(
+				&mut comps::ShipState,
+				&comps::ShipStats,
+				&mut comps::Equipment,
+			)>()
+			.iter()
+		{
+			num_ships += 1;
+			if !ship_state.is_structurally_sound()
+			{
+				// Can't fix a broken ship.
+				continue;
+			}
+			let derived_stats = equipment.derived_stats();
+
+			ship_state.compute_level();
+
+			let repair_crew = ship_state.crew as f32
+				* ship_state.specialty_effectiveness(comps::Specialty::Repair);
+			let gunnery_crew = ship_state.crew as f32
+				* ship_state.specialty_effectiveness(comps::Specialty::Gunnery);
+
+			// Each crew member can repair 0.1 point per 1 second, probabilistically
+			let repair_prob = utils::rate_to_prob(1., dt as f64);
+			let num_repaired =
+				rand_distr::Binomial::new((repair_crew.sqrt() * 0.5).ceil() as u64, repair_prob)
+					.unwrap()
+					.sample(&mut self.rng);
+
+			let mut parts = [
+				stats.armor[0] - ship_state.armor[0],
+				stats.armor[1] - ship_state.armor[1],
+				stats.armor[2] - ship_state.armor[2],
+				stats.armor[3] - ship_state.armor[3],
+				10. * (stats.hull - ship_state.hull), // No hull, no ship.
+				stats.infirmary - ship_state.infirmary,
+				stats.sails - ship_state.sails,
+			];
+			for i in &ship_state.repair_boost
+			{
+				parts[*i] *= 5.;
+			}
+			if let Ok(dist) = rand_distr::WeightedIndex::new(&parts)
+			{
+				let to_repair = dist.sample(&mut self.rng);
+				let mut num_repaired = num_repaired as f32;
+				// Our crews patch things up slowly with whatever's lying around, anything faster
+				// takes materials from the hold.
+				if let (true, Some(kind)) = (
+					self.is_player_side(id),
+					comps::MaterialKind::for_part(to_repair),
+				)
+				{
+					let free = num_repaired * FREE_REPAIR_FRAC;
+					let used = equipment.use_material(kind, num_repaired - free);
+					if id == self.player
+					{
+						material_used[kind as usize] += used;
+					}
+					num_repaired = free + used;
+				}
+				ship_state.train(
+					comps::Specialty::Repair,
+					comps::REPAIR_TRAINING * num_repaired,
+				);
+				match to_repair
+				{
+					0 =>
+					{
+						ship_state.armor[0] = (ship_state.armor[0]
+							+ num_repaired * (1. + derived_stats.armor_repair))
+							.min(stats.armor[0])
+					}
+					1 =>
+					{
+						ship_state.armor[1] = (ship_state.armor[1]
+							+ num_repaired * (1. + derived_stats.armor_repair))
+							.min(stats.armor[1])
+					}
+					2 =>
+					{
+						ship_state.armor[2] = (ship_state.armor[2]
+							+ num_repaired * (1. + derived_stats.armor_repair))
+							.min(stats.armor[2])
+					}
+					3 =>
+					{
+						ship_state.armor[3] = (ship_state.armor[3]
+							+ num_repaired * (1. + derived_stats.armor_repair))
+							.min(stats.armor[3])
+					}
+					4 =>
+					{
+						ship_state.hull = (ship_state.hull
+							+ num_repaired * (1. + derived_stats.hull_repair))
+							.min(stats.hull)
+					}
+					5 =>
+					{
+						ship_state.infirmary = (ship_state.infirmary
+							+ num_repaired * (1. + derived_stats.infirmary_repair))
+							.min(stats.infirmary)
+					}
+					6 =>
+					{
+						ship_state.sails = (ship_state.sails
+							+ num_repaired * (1. + derived_stats.sail_repair))
+							.min(stats.sails)
+					}
+					_ => unreachable!(),
+				}
+			}
+
+			// Each patient has a chance of getting better, weighed by infirmary strength... I
+			// guess it has more drugs?
+			let heal_rate = ship_state.infirmary.sqrt() / 100.0 / ship_state.wounded as f32
+				* (1. + derived_stats.medic);
+			let heal_prob = utils::rate_to_prob(heal_rate as f64, dt as f64);
+			for _ in 0..ship_state.wounded
+			{
+				if self.rng.gen_bool(heal_prob)
+				{
+					ship_state.wounded -= 1;
+					ship_state.crew += 1;
+				}
+			}
+
+			// Weapon handling.
+			let mut num_weapons = 0;
+			for slot in &equipment.slots
+			{
+				if slot.is_inventory || slot.disabled
+				{
+					continue;
+				}
+				if let Some(item) = slot.item.as_ref()
+				{
+					match &item.kind
+					{
+						comps::ItemKind::Weapon(weapon) =>
+						{
+							if weapon.readiness < 1.
+							{
+								num_weapons += 1;
+							}
+						}
+						_ => (),
+					}
+				}
+			}
+
+			let fire_rate_adjustment = reload_rate(gunnery_crew, num_weapons, &derived_stats)
+				* hostile(ship_state.team).map(|d| d.reload()).unwrap_or(1.);
+			for slot in &mut equipment.slots
+			{
+				if slot.is_inventory || slot.disabled
+				{
+					continue;
+				}
+				if let Some(item) = slot.item.as_mut()
+				{
+					match &mut item.kind
+					{
+						comps::ItemKind::Weapon(weapon) =>
+						{
+							weapon.readiness = (weapon.readiness
+								+ dt * (fire_rate_adjustment / weapon.stats().fire_interval))
+								.min(1.0);
+						}
+						_ => (),
+					}
+				}
+			}
+		}
+		for (rate, used) in self.material_rate.iter_mut().zip(material_used)
+		{
+			*rate += (used / dt - *rate) * utils::approach_frac(1., dt);
+		}
+		timer.record(&state.core);
+		if state.tick % 64 == 0
+		{
+			//println!("Num ships: {}", num_ships);
+		}
+
+		// Tilt.
+		let squalls: Vec<_> = self
+			.world
+			.query::<(&comps::Position, &comps::Squall)>()
+			.iter()
+			.map(|(_, (pos, squall))| (pos.pos, squall.radius))
+			.collect();
+		for (_, (tilt, pos, vel, ship_state)) in self
+			.world
+			.query::<(
+				&mut comps::Tilt,
+				&comps::Position,
+				&comps::Velocity,
+				&comps::ShipState,
+			)>()
+			.iter()
+		{
+			tilt.target_tilt = state.time().sin() as f32 * PI / 4.;
+			if !ship_state.is_structurally_sound()
+			{
+				tilt.target_tilt -= PI / 2.;
+			}
+			tilt.tilt += utils::approach_frac(0.1, dt) * (tilt.target_tilt - tilt.tilt);
 
-	state.core.draw_text(
-		&state.ui_font,
-		ui_color,
-		x - m * 4.,
-		y - lh / 2. - m * 3.5,
-		FontAlign::Left,
-		"Armor:",
-	);
-	for (i, (armor, armor_max)) in ship_state.armor.iter().zip(stats.armor.iter()).enumerate()
-	{
-		let theta = -PI / 2. + i as f32 * PI / 2.;
+			// Leaning out of turns, and away from the wind.
+			let port = Rotation2::new(pos.dir + PI / 2.) * Vector2::new(1., 0.);
+			let mut target_heel = -TURN_HEEL * vel.dir_vel;
+			for &(center, radius) in &squalls
+			{
+				let disp = (pos.pos - center).zx();
+				if disp.norm() < radius
+				{
+					let wind = disp.try_normalize(1e-3).unwrap_or(Vector2::zeros());
+					target_heel += SQUALL_HEEL * wind.dot(&port);
+				}
+			}
+			let target_heel = utils::clamp(target_heel, -MAX_HEEL, MAX_HEEL);
+			tilt.heel += utils::approach_frac(1., dt) * (target_heel - tilt.heel);
+		}
 
-		let r = m * 1.5;
-		let cx = x;
+		self.journal
+			.flush(state.time(), false, &mut self.journal_rng);
 
-		let lx = cx + (m * 1.5 + r) * theta.cos();
-		let ly = y + (m + r) * theta.sin();
+		// Omens of the Voidwind, growing stronger as the player closes in.
+		let boss_pos = self
+			.boss
+			.and_then(|boss| self.world.get::<&comps::Position>(boss).ok())
+			.map(|pos| pos.pos);
+		let target_omen = match boss_pos
+		{
+			Some(boss_pos) if !self.spectator && !self.title => utils::clamp(
+				1. - (boss_pos - self.player_pos).magnitude() / VOID_OMEN_RADIUS,
+				0.,
+				1.,
+			),
+			_ => 0.,
+		};
+		self.void_omen += utils::approach_frac(0.5, dt) * (target_omen - self.void_omen);
+		let omen_stage = ((self.void_omen * 4.) as i32).min(3);
+		if omen_stage > self.omen_stage
+		{
+			self.omen_stage = omen_stage;
+			self.triggers.fire(triggers::Event::BossPhase(omen_stage));
+		}
+		else if self.void_omen < 0.05
+		{
+			// Lost the trail, warn again next time.
+			self.omen_stage = 0;
+		}
+		if self.void_omen > 0.01 && self.omen_drone.is_none()
+		{
+			self.omen_drone = state
+				.sfx
+				.play_continuous_sound("data/void_drone.wav", 0.)
+				.ok();
+		}
+		if let Some(drone) = &self.omen_drone
+		{
+			drone
+				.set_gain(state.options.sfx_volume * self.void_omen)
+				.ok();
+		}
+		if let Some(boss_pos) = boss_pos
+		{
+			if self.void_omen > 0.5 && state.time() > self.time_to_omen_wisp
+			{
+				self.time_to_omen_wisp = state.time() + 1.5 / self.void_omen as f64;
+				let theta = self.rng.gen_range(0.0..2. * PI);
+				let r = self.rng.gen_range(15.0..40.);
+				let pos = self.player_pos + Vector3::new(r * theta.cos(), 0., r * theta.sin());
+				// Blown away from the Voidwind, so they hint where it lies.
+				let mut dir = pos - boss_pos;
+				dir.y = 0.;
+				let vel = 4. * dir.try_normalize(1e-3).unwrap_or(Vector3::x());
+				make_drifting_wisp(pos, vel, &mut self.world, state)?;
+			}
+		}
 
-		let f = armor / armor_max;
-		state.prim.draw_arc(
-			cx,
-			y,
-			r,
-			theta - PI / 4. + 0.1,
-			PI / 2. - 0.2,
-			frac_to_color(f),
-			(f * 10.).ceil(),
-		);
+		// Void storms past the southern edge, tearing at the sails and driving ships back north.
+		for (_, (pos, ship_state)) in self
+			.world
+			.query::<(&mut comps::Position, &mut comps::ShipState)>()
+			.iter()
+		{
+			let storm = Cell::southern_storm(&pos.pos, self.global_offset);
+			if storm > 0.
+			{
+				pos.pos.z -= STORM_PUSH * storm * dt;
+				ship_state.sails = (ship_state.sails - STORM_SAIL_DAMAGE * storm * dt).max(0.);
+			}
+		}
+		let target_storm = if self.spectator || self.title
+		{
+			0.
+		}
+		else
+		{
+			Cell::southern_storm(&self.player_pos, self.global_offset)
+		};
+		self.storm += utils::approach_frac(1., dt) * (target_storm - self.storm);
+		if target_storm > 0. && !self.storm_warned
+		{
+			self.storm_warned = true;
+			self.messages.add(
+				message::MessageKind::Quest,
+				"Void storms rage to the south, tearing at the sails. Turn back north!".into(),
+				state.time(),
+			);
+		}
+		else if target_storm == 0.
+		{
+			self.storm_warned = false;
+		}
 
-		state.core.draw_text(
-			&state.ui_font,
-			frac_to_color(f),
-			lx,
-			ly - lh / 2.,
-			FontAlign::Centre,
-			&format!("{}", *armor as i32),
-		);
-	}
+		// Lighthouses.
+		let global_origin = Cell::cell_to_world(Point2::origin() + self.global_offset);
+		for (_, (pos, lighthouse)) in self
+			.world
+			.query::<(&mut comps::Position, &comps::Lighthouse)>()
+			.iter()
+		{
+			pos.dir += lighthouse.spin * dt;
+			if !self.spectator
+				&& (pos.pos - self.player_pos).magnitude() < LIGHTHOUSE_DISCOVER_RADIUS
+				&& !self.discovered_lighthouses.contains_key(&lighthouse.id)
+			{
+				self.discovered_lighthouses
+					.insert(lighthouse.id, pos.pos + global_origin.coords);
+				self.messages.add(
+					message::MessageKind::Quest,
+					"Discovered a lighthouse.".into(),
+					state.time(),
+				);
+				self.journal.record(
+					journal::Event::LighthouseDiscovered,
+					state.time(),
+					&mut self.journal_rng,
+				);
+			}
+		}
 
-	y += m * 4.;
+		let mut timer = Timer::new("collision", state);
+		// Collision resolution.
+		let mut colliding_pairs = vec![];
+		let friendly_fire = state.options.friendly_fire;
+		let time = state.time();
+		for (a, b) in grid.all_pairs(|a, b| {
+			let a = a.inner.entity;
+			let b = b.inner.entity;
+			let a_solid = self.world.get::<&comps::Solid>(a).unwrap();
+			let b_solid = self.world.get::<&comps::Solid>(b).unwrap();
+			a_solid.kind.collides_with(&b_solid.kind)
+				&& a_solid.parent != Some(b)
+				&& b_solid.parent != Some(a)
+				&& !self.shot_passes(a, b, friendly_fire, time)
+				&& !self.shot_passes(b, a, friendly_fire, time)
+		})
+		{
+			colliding_pairs.push((a.inner, b.inner));
+		}
+		if state.tick % 64 == 0
+		{
+			//println!("Colliding pairs: {}", colliding_pairs.len());
+		}
+
+		let mut on_contact_effects = vec![];
+		let mut scraped = vec![];
+		for pass in 0..5
+		{
+			for &(inner1, inner2) in &colliding_pairs
+			{
+				let id1 = inner1.entity;
+				let id2 = inner2.entity;
+				let pos1 = self.world.get::<&comps::Position>(id1)?.pos;
+				let pos2 = self.world.get::<&comps::Position>(id2)?.pos;
+
+				let solid1 = *self.world.get::<&comps::Solid>(id1)?;
+				let solid2 = *self.world.get::<&comps::Solid>(id2)?;
+
+				let diff = pos2.zx() - pos1.zx();
+				let diff_norm = utils::max(0.1, diff.norm());
+
+				if diff_norm > solid1.size + solid2.size
+				{
+					continue;
+				}
+
+				//if solid1.collision_class.interacts() && solid2.collision_class.interacts()
+				if true
+				{
+					let diff = 0.9 * diff * (solid1.size + solid2.size - diff_norm) / diff_norm;
+					let diff = Vector3::new(diff.y, 0., diff.x);
+
+					let f1 = 1. - solid1.mass / (solid2.mass + solid1.mass);
+					let f2 = 1. - solid2.mass / (solid2.mass + solid1.mass);
+					if f32::is_finite(f1)
+					{
+						self.world.get::<&mut comps::Position>(id1)?.pos -= diff * f1;
+					}
+					if f32::is_finite(f2)
+					{
+						self.world.get::<&mut comps::Position>(id2)?.pos += diff * f2;
+					}
+				}
+
+				if pass == 0
+				{
+					for (id, other_id, pos, other_pos) in
+						[(id1, id2, pos1, pos2), (id2, id1, pos2, pos1)]
+					{
+						if self.world.get::<&comps::Wreck>(id).is_ok()
+						{
+							scraped.push(other_id);
+						}
+						if let Ok(on_contact_effect) = self.world.get::<&comps::OnContactEffect>(id)
+						{
+							on_contact_effects.push((
+								id,
+								other_id,
+								pos,
+								other_pos,
+								on_contact_effect.effects.clone(),
+							));
+						}
+					}
+				}
+			}
+		}
+
+		for id in scraped
+		{
+			if let Ok(mut ship_state) = self.world.get::<&mut comps::ShipState>(id)
+			{
+				// Scraping along hurts, but won't sink anyone by itself.
+				if ship_state.is_active() && ship_state.hull > 1.
+				{
+					ship_state.hull = (ship_state.hull - WRECK_SCRAPE_DAMAGE * dt).max(1.);
+				}
+			}
+		}
+
+		// On contact effects.
+		for (id, other_id, pos, other_pos, effects) in on_contact_effects
+		{
+			for effect in effects
+			{
+				match (effect, other_id)
+				{
+					(comps::ContactEffect::Die, _) => to_die.push(id),
+					(comps::ContactEffect::Hurt { mut damage }, other_id) =>
+					{
+						if let Ok(projectile) = self.world.get::<&comps::Projectile>(id)
+						{
+							let dist = (pos - projectile.spawn_pos).xz().norm();
+							damage.weapon_stats = damage.weapon_stats.at_range(dist);
+						}
+						let mut damage_report = None;
+						let mut disabled = None;
+						let mut shares = vec![];
+						let mut destroyed = false;
+						let mut civilian = false;
+						if let (Ok(mut ship_state), Ok(ship_stats)) = (
+							self.world.get::<&mut comps::ShipState>(other_id),
+							self.world.get::<&comps::ShipStats>(other_id),
+						)
+						{
+							civilian = ship_state.team == comps::Team::Civilian;
+							let was_active = ship_state.is_active();
+							let was_sound = ship_state.is_structurally_sound();
+							let had_crew = ship_state.has_crew();
+							let crew_before = ship_state.crew + ship_state.wounded;
+							let report = ship_state.damage(
+								&damage,
+								(pos - other_pos).normalize(),
+								state.modifiers.hull_damage,
+								&mut self.combat_rng,
+							);
+							let crew_lost = crew_before - (ship_state.crew + ship_state.wounded);
+							if other_id == self.player && crew_lost > 0
+							{
+								self.journal.record(
+									journal::Event::CrewLost(crew_lost),
+									state.time(),
+									&mut self.journal_rng,
+								);
+							}
+							if report.damaged && was_active != ship_state.is_active()
+							{
+								disabled = Some((ship_state.level, ship_stats.exp_bonus));
+								destroyed = !ship_state.is_structurally_sound();
+							}
+							if report.damaged && had_crew != ship_state.has_crew()
+							{
+								state.sfx.play_positional_sound(
+									"data/screams.ogg",
+									pos.xz(),
+									self.player_pos.xz(),
+									0.5,
+								)?;
+							}
+							if report.damaged && was_sound != ship_state.is_structurally_sound()
+							{
+								state.sfx.play_positional_sound(
+									"data/sink.ogg",
+									pos.xz(),
+									self.player_pos.xz(),
+									0.5,
+								)?;
+							}
+							damage_report = Some(report);
+						}
+						if let Some(hit) = damage_report.as_ref().and_then(|r| r.hit)
+						{
+							let crit = damage_report.as_ref().map(|r| r.crit).unwrap_or(false);
+							self.impact(pos, hit, crit, state)?;
+						}
+						if let Some(report) = damage_report
+						{
+							let shooter = self
+								.world
+								.get::<&comps::Solid>(id)
+								.ok()
+								.and_then(|s| s.parent);
+							if let Some(shooter) =
+								shooter.filter(|s| report.damaged && *s != other_id)
+							{
+								let amount = damage.weapon_stats.damage;
+								let ledger = self.assists.entry(other_id).or_default();
+								match ledger.iter_mut().find(|(id, _)| *id == shooter)
+								{
+									Some((_, total)) => *total += amount,
+									None => ledger.push((shooter, amount)),
+								}
+								match (self.is_player_side(shooter), self.is_player_side(other_id))
+								{
+									(true, false) => self.difficulty.dealt(amount),
+									(false, true) => self.difficulty.taken(amount),
+									_ => (),
+								}
+								let intensity = state.options.feedback_intensity;
+								if shooter == self.player || other_id == self.player
+								{
+									self.engaged(
+										if shooter == self.player
+										{
+											other_id
+										}
+										else
+										{
+											shooter
+										},
+									);
+									self.feedback.damage(
+										report.amount,
+										other_id == self.player,
+										intensity,
+									);
+								}
+								if shooter == self.player && report.crit
+								{
+									self.feedback.hit_stop(intensity);
+								}
+							}
+							if disabled.is_some()
+							{
+								shares = self.take_shares(other_id);
+							}
+							let player_share: f32 = shares
+								.iter()
+								.filter(|(id, _)| self.is_player_side(*id))
+								.map(|(_, share)| share)
+								.sum();
+							if disabled.is_some() && state.options.adaptive_difficulty
+							{
+								if self.is_player_side(other_id)
+								{
+									self.difficulty.fight(-1.);
+								}
+								else if player_share >= 0.5
+								{
+									self.difficulty.fight(1.);
+								}
+							}
+							// The more of it was your doing, the likelier word gets around.
+							if civilian
+								&& player_share > 0. && self
+								.rng
+								.gen_bool(player_share.min(1.) as f64)
+							{
+								self.reputation -= 1;
+								self.add_message(
+									message::MessageKind::Combat,
+									"Word of your piracy spreads, merchants will charge you more."
+										.into(),
+									state,
+								);
+							}
+							if other_id == self.player && report.damaged
+							{
+								// Point at the shooter if they're still around.
+								let source_pos = self
+									.world
+									.get::<&comps::Solid>(id)
+									.ok()
+									.and_then(|s| s.parent)
+									.and_then(|parent_id| {
+										self.world
+											.get::<&comps::Position>(parent_id)
+											.ok()
+											.map(|p| p.pos)
+									})
+									.unwrap_or(pos);
+								let dir = (source_pos - other_pos).xz();
+								if dir.norm() > 1e-3
+								{
+									self.damage_indicators.push((dir.normalize(), state.time()));
+								}
+								self.abort_docking(
+									"Under fire, docking aborted!",
+									&mut to_die,
+									state,
+								);
+								self.course.clear();
+							}
+							// A stray shot from your side doesn't end the truce, and nobody turns
+							// on their own side over friendly fire.
+							let team_of = |id| {
+								self.world
+									.get::<&comps::ShipState>(id)
+									.map(|ship_state| ship_state.team)
+									.ok()
+							};
+							let truce = (Some(other_id) == self.dock_entity
+								&& shooter.is_some_and(|s| self.is_player_side(s)))
+								|| shooter.is_some_and(|s| team_of(s) == team_of(other_id));
+							if let Some(mut ai) = self
+								.world
+								.get::<&mut comps::AI>(other_id)
+								.ok()
+								.filter(|_| !truce)
+							{
+								// Escorts stay with their leader.
+								let escorting =
+									matches!(ai.state, comps::AIState::Escorting { .. });
+								if let Some(parent_id) = shooter
+								{
+									if civilian
+									{
+										ai.state = comps::AIState::Fleeing(parent_id);
+									}
+									else if !escorting
+									{
+										ai.state = comps::AIState::Pursuing(parent_id);
+									}
+								}
+							}
 
-	let h = m;
-	state.core.draw_text(
-		&state.ui_font,
-		frac_to_color(ship_state.hull / stats.hull),
-		x - m * 4.,
-		y - lh / 2.,
-		FontAlign::Left,
-		&format!("Hull: {}", ship_state.hull as i32),
-	);
-	y += h;
-	state.core.draw_text(
-		&state.ui_font,
-		frac_to_color(ship_state.crew as f32 / stats.crew as f32),
-		x - m * 4.,
-		y - lh / 2.,
-		FontAlign::Left,
-		&format!("Crew: {} H / {} W", ship_state.crew, ship_state.wounded),
-	);
-	y += h;
-	state.core.draw_text(
-		&state.ui_font,
-		frac_to_color(ship_state.infirmary / stats.infirmary),
-		x - m * 4.,
-		y - lh / 2.,
-		FontAlign::Left,
-		&format!("Infirmary: {}", ship_state.infirmary as i32),
-	);
-	y += h;
-	state.core.draw_text(
-		&state.ui_font,
-		frac_to_color(ship_state.sails / stats.sails),
-		x - m * 4.,
-		y - lh / 2.,
-		FontAlign::Left,
-		&format!("Sails: {}", ship_state.sails as i32),
-	);
-}
+							let destroy_prob = if destroyed
+							{
+								0.75
+							}
+							else
+							{
+								report.item_destroy_chance
+							};
+							let mut items_destroyed = vec![];
+							if let Ok(mut equipment) =
+								self.world.get::<&mut comps::Equipment>(other_id)
+							{
+								let derived_stats = equipment.derived_stats();
+								for slot in &mut equipment.slots
+								{
+									let locked = slot.item.as_ref().is_some_and(|item| item.locked);
+									if (destroyed || !locked)
+										&& self.combat_rng.gen_bool(
+											(destroy_prob / (1. + derived_stats.item_protect))
+												as f64,
+										)
+									{
+										//println!("Destroyed {:?}", slot.item);
+										if let Some(item) = slot.item.as_ref()
+										{
+											items_destroyed.push(item.kind.name().to_string());
+										}
+										if !destroyed && other_id == self.player
+										{
+											if let Some(item) = slot.item.as_ref()
+											{
+												self.messages.add(
+													message::MessageKind::Combat,
+													format!("{} destroyed!", item.kind.name()),
+													state.time(),
+												);
+											}
+										}
+										slot.item = None;
+									}
+									if disabled.is_some()
+									{
+										// Officers die.
+										if let Some(item) = slot.item.as_ref()
+										{
+											if let comps::ItemKind::Officer(_) = item.kind
+											{
+												slot.item = None;
+											}
+										}
+										if Some(other_id) == self.boss
+										{
+											boss_defeated = true;
+										}
+									}
+								}
+							}
+							if report.damaged
+								&& (shooter == Some(self.player) || other_id == self.player)
+							{
+								self.damage_log.record(damage_log::Hit {
+									time: state.time(),
+									outgoing: other_id != self.player,
+									weapon: damage.source.clone(),
+									damage: report.amount,
+									facing: report.facing,
+									crit: report.crit,
+									items_destroyed: items_destroyed,
+								});
+							}
+						}
+						if let Some((level, exp_bonus)) = disabled
+						{
+							// Credit goes to whoever did most of the work, not the last shot.
+							let by_player = shares
+								.iter()
+								.any(|(id, share)| *id == self.player && *share >= 0.5);
+							self.triggers.fire(triggers::Event::Disabled {
+								boss: self
+									.world
+									.get::<&comps::ShipState>(other_id)
+									.map(|s| s.is_boss)
+									.unwrap_or(false),
+								by_player: by_player,
+							});
+							self.claim_loot(other_id, &shares, state);
+							if by_player
+							{
+								let name = self
+									.world
+									.get::<&comps::AI>(other_id)
+									.map(|ai| ai.name.clone())
+									.unwrap_or_else(|_| "a ship".into());
+								let event = if destroyed
+								{
+									journal::Event::Sunk { name: name }
+								}
+								else
+								{
+									journal::Event::Disabled { name: name }
+								};
+								self.journal
+									.record(event, state.time(), &mut self.journal_rng);
+								self.ships_disabled += 1;
+							}
+							if destroyed
+							{
+								if let Ok(ai) = self.world.get::<&comps::AI>(other_id)
+								{
+									self.intel.forget(&ai.name);
+								}
+							}
+							for &(parent_id, share) in &shares
+							{
+								let mut ship_state =
+									match self.world.get::<&mut comps::ShipState>(parent_id)
+									{
+										Ok(ship_state) => ship_state,
+										Err(_) => continue,
+									};
+								ship_state.experience += share
+									* exp_bonus * comps::enemy_experience(
+									level,
+								) * state.modifiers.experience;
+								//dbg!(ship_state.experience);
+								let old_level = ship_state.level;
+								ship_state.compute_level();
+								if old_level != ship_state.level && parent_id == self.player
+								{
+									self.messages.toast(
+										message::MessageKind::System,
+										"Crew got more experienced!".into(),
+										state.time(),
+									);
+									self.journal.record(
+										journal::Event::CrewExperienced(ship_state.level),
+										state.time(),
+										&mut self.journal_rng,
+									);
+								}
+							}
+						}
+					}
+				}
+			}
+		}
+		timer.record(&state.core);
 
-fn make_wisp(
-	pos: Point3<f32>, vel: Vector3<f32>, world: &mut hecs::World, state: &mut game_state::GameState,
-) -> Result<hecs::Entity>
-{
-	let mesh = "data/wisp.glb";
-	game_state::cache_mesh(state, mesh)?;
-	let res = world.spawn((
-		comps::Position {
-			pos: pos + Vector3::new(0., 2., 0.),
-			dir: 0.,
-		},
-		comps::Velocity {
-			vel: vel,
-			dir_vel: PI,
-		},
-		comps::Mesh { mesh: mesh.into() },
-		comps::Lights {
-			lights: vec![comps::Light {
-				pos: Point3::origin(),
-				color: Color::from_rgb_f(0.2, 0.9, 0.9),
-				intensity: 3.,
-			}],
-		},
-	));
-	Ok(res)
-}
+		// Spectator fleets.
+		if self.spectator && !self.title
+		{
+			let mut num_active = [0, 0];
+			for (_, ship_state) in self.world.query::<&comps::ShipState>().iter()
+			{
+				if !ship_state.is_active()
+				{
+					continue;
+				}
+				match ship_state.team
+				{
+					comps::Team::English => num_active[0] += 1,
+					comps::Team::French => num_active[1] += 1,
+					_ => (),
+				}
+			}
+			if num_active.contains(&0)
+			{
+				if let Some(time_to_fleets) = self.time_to_fleets
+				{
+					if state.time() > time_to_fleets
+					{
+						self.time_to_fleets = None;
+						self.spawn_fleets(state)?;
+					}
+				}
+				else
+				{
+					let message = match num_active
+					{
+						[0, 0] => "Both fleets have been destroyed!",
+						[0, _] => "The French fleet is victorious!",
+						_ => "The English fleet is victorious!",
+					};
+					self.add_message(message::MessageKind::Combat, message.into(), state);
+					self.time_to_fleets = Some(state.time() + FLEET_RESPAWN_DELAY);
+				}
+			}
+		}
 
-fn make_target(
-	pos: Point3<f32>, world: &mut hecs::World, state: &mut game_state::GameState,
-) -> Result<hecs::Entity>
-{
-	let mesh = "data/target.glb";
-	game_state::cache_mesh(state, mesh)?;
-	let res = world.spawn((
-		comps::Position {
-			pos: pos + Vector3::new(0., 1., 0.),
-			dir: 0.,
-		},
-		comps::Velocity {
-			vel: Vector3::zeros(),
-			dir_vel: PI,
-		},
-		comps::Mesh { mesh: mesh.into() },
-		comps::Lights {
-			lights: vec![comps::Light {
-				pos: Point3::origin(),
-				color: Color::from_rgb_f(0.2, 0.8, 0.2),
-				intensity: 4.,
-			}],
-		},
-	));
-	Ok(res)
-}
+		// Rival.
+		if let Some(time_to_spawn) = self.rival.time_to_spawn
+		{
+			let player_active = self
+				.world
+				.get::<&comps::ShipState>(self.player)
+				.map(|s| s.is_active())
+				.unwrap_or(false);
+			if state.time() > time_to_spawn && player_active
+			{
+				self.rival.time_to_spawn = None;
+				self.spawn_rival(state)?;
+			}
+		}
+		if let Some(rival) = self.rival.entity
+		{
+			if !self.world.contains(rival)
+			{
+				// Lost track of them somehow, they'll be back.
+				self.rival.entity = None;
+				self.rival.time_to_spawn = Some(state.time() + RIVAL_RETURN_DELAY);
+			}
+			else if !self.rival.sighted
+			{
+				let close = self
+					.world
+					.get::<&comps::Position>(rival)
+					.map(|pos| (pos.pos - self.player_pos).magnitude() < 40.)
+					.unwrap_or(false);
+				if close
+				{
+					self.rival.sighted = true;
+					let bark = *[
+						"There you are!",
+						"Run all you like, I'll find you.",
+						"Ready the guns, lads!",
+					]
+					.choose(&mut self.rng)
+					.unwrap();
+					let name = self.rival.name.clone();
+					self.add_message(
+						message::MessageKind::Quest,
+						format!("{name}: \"{bark}\""),
+						state,
+					);
+				}
+			}
+		}
 
-fn make_selection(
-	pos: Point3<f32>, world: &mut hecs::World, state: &mut game_state::GameState,
-) -> Result<hecs::Entity>
-{
-	let mesh = "data/selection_indicator.glb";
-	game_state::cache_mesh(state, mesh)?;
-	let res = world.spawn((
-		comps::Position { pos: pos, dir: 0. },
-		comps::Velocity {
-			vel: Vector3::zeros(),
-			dir_vel: PI,
-		},
-		comps::Mesh { mesh: mesh.into() },
-	));
-	Ok(res)
-}
+		// Player Input
+		let player_alive = self
+			.world
+			.get::<&comps::ShipState>(self.player)
+			.map(|s| s.is_active())
+			.unwrap_or(false);
+		let selected: Vec<_> = self
+			.selected
+			.iter()
+			.copied()
+			.filter(|id| self.is_commandable(*id))
+			.collect();
+		self.selected = selected;
+		for group in 0..self.control_groups.len()
+		{
+			let members: Vec<_> = self.control_groups[group]
+				.iter()
+				.copied()
+				.filter(|id| self.is_commandable(*id))
+				.collect();
+			self.control_groups[group] = members;
+		}
+		if self.box_start.is_some()
+		{
+			// The click is for selecting, not moving.
+			state.controls.clear_action_state(controls::Action::Move);
+			state
+				.controls
+				.clear_action_state(controls::Action::QueueMove);
+		}
+		if state.controls.get_action_state(controls::Action::Formation) > 0.5
+		{
+			state
+				.controls
+				.clear_action_state(controls::Action::Formation);
+			self.formation = self.formation.next();
+			self.add_message(
+				message::MessageKind::System,
+				format!("Formation: {}", self.formation.name()),
+				state,
+			);
+		}
 
-fn make_projectile(
-	pos: Point3<f32>, dir: Vector3<f32>, parent: hecs::Entity, team: comps::Team,
-	weapon_stats: &comps::WeaponStats, world: &mut hecs::World, state: &mut game_state::GameState,
-) -> Result<hecs::Entity>
-{
-	let mesh = "data/cannon_ball.glb";
-	game_state::cache_mesh(state, mesh)?;
-	let res = world.spawn((
-		comps::Position { pos: pos, dir: 0. },
-		comps::Velocity {
-			vel: dir * weapon_stats.speed,
-			dir_vel: 5. * PI,
-		},
-		comps::Solid {
-			size: 0.5,
-			mass: 0.,
-			kind: comps::CollideKind::Small,
-			parent: Some(parent),
-		},
-		comps::Mesh { mesh: mesh.into() },
-		comps::TimeToDie {
-			time_to_die: state.time() + 1.,
-		},
-		comps::AffectedByGravity,
-		comps::CollidesWithWater,
-		comps::OnContactEffect {
-			effects: vec![
-				comps::ContactEffect::Die,
-				comps::ContactEffect::Hurt {
-					damage: comps::Damage {
-						weapon_stats: weapon_stats.clone(),
-						team: team,
-					},
-				},
-			],
-		},
-		comps::Lights {
-			lights: vec![comps::Light {
-				pos: Point3::origin(),
-				color: Color::from_rgb_f(1., 0.8, 0.2),
-				intensity: 2.,
-			}],
-		},
-	));
-	Ok(res)
-}
+		if state.controls.get_action_state(controls::Action::RangeRing) > 0.5
+		{
+			state
+				.controls
+				.clear_action_state(controls::Action::RangeRing);
+			self.show_range_ring = !self.show_range_ring;
+		}
 
-fn make_muzzle_flash(
-	pos: Point3<f32>, world: &mut hecs::World, state: &mut game_state::GameState,
-) -> Result<hecs::Entity>
-{
-	let res = world.spawn((
-		comps::Position { pos: pos, dir: 0. },
-		comps::TimeToDie {
-			time_to_die: state.time() + 0.2,
-		},
-		comps::Lights {
-			lights: vec![comps::Light {
-				pos: Point3::origin(),
-				color: Color::from_rgb_f(1., 0.8, 0.2),
-				intensity: 4.,
-			}],
-		},
-	));
-	Ok(res)
-}
+		if state.controls.get_action_state(controls::Action::DebugView) > 0.5
+		{
+			state
+				.controls
+				.clear_action_state(controls::Action::DebugView);
+			self.debug_view = self.debug_view.next();
+		}
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct SlotDesc
-{
-	pos: [f32; 2],
-	dir: Option<f32>,
-	weapons_allowed: bool,
-}
+		if state
+			.controls
+			.get_action_state(controls::Action::EngageTarget)
+			> 0.5
+		{
+			state
+				.controls
+				.clear_action_state(controls::Action::EngageTarget);
+			let player_team = self
+				.world
+				.get::<&comps::ShipState>(self.player)
+				.map(|s| s.team)
+				.ok();
+			let target = self.target_entity.filter(|e| {
+				self.world
+					.get::<&comps::ShipState>(*e)
+					.map(|s| {
+						s.is_active() && player_team.map(|t| s.team.is_enemy(&t)).unwrap_or(false)
+					})
+					.unwrap_or(false)
+			});
+			let message = if self.engage_target.is_some() && self.engage_target == target
+			{
+				self.engage_target = None;
+				Some("Ceasing fire.".to_string())
+			}
+			else if let Some(target) = target
+			{
+				self.engage_target = Some(target);
+				let name = self
+					.world
+					.get::<&comps::AI>(target)
+					.map(|ai| ai.name.clone())
+					.unwrap_or_else(|_| "the enemy".into());
+				Some(format!("Engaging {name} whenever she's in range."))
+			}
+			else
+			{
+				None
+			};
+			if let Some(message) = message
+			{
+				state.sfx.play_sound("data/order.ogg")?;
+				self.add_message(message::MessageKind::System, message, state);
+			}
+		}
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct LightDesc
-{
-	pos: [f32; 3],
-	color: [f32; 3],
-	intensity: f32,
-}
+		let want_queue = state.controls.get_action_state(controls::Action::QueueMove) > 0.5;
+		let want_move = state.controls.get_action_state(controls::Action::Move) > 0.5 || want_queue;
+		let want_dock = state.controls.get_action_state(controls::Action::Dock) > 0.5;
+		let want_stop = state.controls.get_action_state(controls::Action::Stop) > 0.5;
+		let want_attack = state.controls.get_action_state(controls::Action::Attack) > 0.5;
+		let want_zoom_in = state.controls.get_action_state(controls::Action::ZoomIn) > 0.5;
+		let want_zoom_out = state.controls.get_action_state(controls::Action::ZoomOut) > 0.5;
+		let want_target = state.controls.get_action_state(controls::Action::Target) > 0.5;
+		let want_patrol = state.controls.get_action_state(controls::Action::Patrol) > 0.5;
+		let want_attack_en_route = state
+			.controls
+			.get_action_state(controls::Action::AttackEnRoute)
+			> 0.5;
+		let want_broadside_left = state
+			.controls
+			.get_action_state(controls::Action::BroadsideLeft)
+			> 0.5;
+		let want_broadside_right = state
+			.controls
+			.get_action_state(controls::Action::BroadsideRight)
+			> 0.5;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct ShipDesc
-{
-	mesh: String,
-	slots: Vec<SlotDesc>,
-	lights: Vec<LightDesc>,
-	stats: comps::ShipStats,
-	inventory_size: i32,
-	size: f32,
-}
+		let mouse_in_buffer = self.mouse_in_buffer;
+		// Guns stay quiet while docked, until the truce is broken on purpose.
+		let want_attack = if want_attack && self.dock_entity.is_some()
+		{
+			state.controls.clear_action_state(controls::Action::Attack);
+			self.open_fire_request |= mouse_in_buffer && player_alive;
+			false
+		}
+		else
+		{
+			want_attack
+		};
+		let mouse_ground_pos = self.get_mouse_ground_pos(state);
+		// What a click would do there.
+		self.cursor = Cursor::Pointer;
+		let player_team = self
+			.world
+			.get::<&comps::ShipState>(self.player)
+			.map(|ship_state| ship_state.team);
+		if let (true, true, Ok(player_team)) = (mouse_in_buffer, player_alive, player_team)
+		{
+			self.cursor = Cursor::Move;
+			let d = 1.;
+			let mouse_entries = grid.query_rect(
+				mouse_ground_pos.zx() - Vector2::new(d, d) - center.coords,
+				mouse_ground_pos.zx() + Vector2::new(d, d) - center.coords,
+				|entry| entry.inner.entity != self.player,
+			);
+			for entry in mouse_entries
+			{
+				if let (Ok(pos), Ok(solid), Ok(ship_state)) = (
+					self.world.get::<&comps::Position>(entry.inner.entity),
+					self.world.get::<&comps::Solid>(entry.inner.entity),
+					self.world.get::<&comps::ShipState>(entry.inner.entity),
+				)
+				{
+					if (pos.pos - mouse_ground_pos).magnitude() < 1.5 * solid.size
+					{
+						if ship_state.team.is_enemy(&player_team)
+						{
+							self.cursor = Cursor::Attack;
+						}
+						else if ship_state.team.dock_with(&player_team)
+						{
+							self.cursor = Cursor::Friendly;
+						}
+						break;
+					}
+				}
+			}
+		}
+		if mouse_in_buffer && (want_dock || want_attack || want_target)
+		{
+			let d = 1.;
+			let mouse_entries = grid.query_rect(
+				mouse_ground_pos.zx() - Vector2::new(d, d) - center.coords,
+				mouse_ground_pos.zx() + Vector2::new(d, d) - center.coords,
+				|_| true,
+			);
 
-fn make_ship(
-	pos: Point3<f32>, ship_desc: &str, team: comps::Team, level: i32, rng: &mut impl Rng,
-	world: &mut hecs::World, state: &mut game_state::GameState,
-) -> Result<hecs::Entity>
-{
-	let ship_desc: ShipDesc = utils::load_config(ship_desc)?;
-	game_state::cache_mesh(state, &ship_desc.mesh)?;
+			if want_target
+			{
+				self.target_entity = None;
+				state.controls.clear_action_state(controls::Action::Target);
+			}
+			if let Some(entry) = mouse_entries.first()
+			{
+				if entry.inner.entity != self.player
+				{
+					if let (Ok(pos), Ok(solid), Ok(_)) = (
+						self.world.get::<&comps::Position>(entry.inner.entity),
+						self.world.get::<&comps::Solid>(entry.inner.entity),
+						self.world.get::<&comps::ShipState>(entry.inner.entity),
+					)
+					{
+						if (pos.pos - mouse_ground_pos).magnitude() < 1.5 * solid.size
+						{
+							self.target_entity = Some(entry.inner.entity);
+						}
+					}
+				}
+			}
+		}
 
-	let mut stats = ship_desc.stats.clone();
-	stats.dir_speed *= PI;
-	stats.scale_to_level(level);
+		if want_move && mouse_in_buffer && player_alive
+		{
+			self.bark(Order::Move, state)?;
+			state.controls.clear_action_state(controls::Action::Move);
+			state
+				.controls
+				.clear_action_state(controls::Action::QueueMove);
+			self.dock_entity = None;
+			self.docking = None;
+			self.course.clear();
+			let marker = make_target(mouse_ground_pos, &mut self.world, state)?;
+			let despawn;
+			if let Ok(mut target) = self.world.get::<&mut comps::Target>(self.player)
+			{
+				if !want_queue
+				{
+					target.clear(|m| to_die.push(m));
+				}
+				target.waypoints.push(comps::Waypoint {
+					pos: mouse_ground_pos,
+					marker: Some(marker),
+				});
+				despawn = false;
+				self.send_order(
+					controls::Action::Move,
+					true,
+					want_queue,
+					mouse_ground_pos,
+					state,
+				);
+			}
+			else
+			{
+				despawn = true;
+			}
+			if despawn
+			{
+				to_die.push(marker);
+			}
 
-	let mut slots = vec![];
-	for slot_desc in &ship_desc.slots
-	{
-		slots.push(comps::ItemSlot {
-			pos: Point2::new(slot_desc.pos[0], slot_desc.pos[1]),
-			dir: slot_desc.dir.map(|d| d * PI),
-			item: if slot_desc.weapons_allowed
+			// The rest of the fleet falls in behind the flagship.
+			let forward = (mouse_ground_pos - self.player_pos).zx();
+			let rot = if forward.magnitude() > 0.
+			{
+				Rotation2::new(forward.y.atan2(forward.x))
+			}
+			else
+			{
+				Rotation2::identity()
+			};
+			let n = self.selected.len() + 1;
+			for (i, id) in self.selected.iter().enumerate()
+			{
+				let offset = rot * self.formation.offset(i + 1, n);
+				self.world.remove_one::<comps::Station>(*id).ok();
+				if let Ok(mut target) = self.world.get::<&mut comps::Target>(*id)
+				{
+					if !want_queue
+					{
+						target.clear(|m| to_die.push(m));
+					}
+					target.waypoints.push(comps::Waypoint {
+						pos: mouse_ground_pos + Vector3::new(offset.y, 0., offset.x),
+						marker: None,
+					});
+				}
+			}
+		}
+		for (ability, action) in comps::ABILITIES.iter().zip([
+			controls::Action::HullPatch,
+			controls::Action::PowderSurge,
+			controls::Action::HardToPort,
+		])
+		{
+			if state.controls.get_action_state(action) > 0.5 && player_alive
+			{
+				state.controls.clear_action_state(action);
+				self.use_ability(*ability, state)?;
+			}
+		}
+		if want_stop && player_alive
+		{
+			state.sfx.play_sound("data/order.ogg").unwrap();
+			state.controls.clear_action_state(controls::Action::Stop);
+			self.send_order(controls::Action::Stop, true, false, self.player_pos, state);
+			for id in std::iter::once(self.player).chain(self.selected.iter().copied())
+			{
+				if let Ok(mut target) = self.world.get::<&mut comps::Target>(id)
+				{
+					target.clear(|m| to_die.push(m));
+				}
+			}
+		}
+		if (want_patrol || want_attack_en_route) && player_alive
+		{
+			state.controls.clear_action_state(controls::Action::Patrol);
+			state
+				.controls
+				.clear_action_state(controls::Action::AttackEnRoute);
+			let mut message = None;
+			if let Ok(mut target) = self.world.get::<&mut comps::Target>(self.player)
 			{
-				Some(comps::generate_weapon(level, rng).clone())
+				if !target.waypoints.is_empty()
+				{
+					if want_patrol
+					{
+						target.patrol = !target.patrol;
+						message = Some(
+							if target.patrol
+							{
+								"Patrolling the route"
+							}
+							else
+							{
+								"Sailing the route once"
+							},
+						);
+					}
+					if want_attack_en_route
+					{
+						target.attack_en_route = !target.attack_en_route;
+						message = Some(
+							if target.attack_en_route
+							{
+								"Attacking enemies en route"
+							}
+							else
+							{
+								"Ignoring enemies en route"
+							},
+						);
+					}
+				}
 			}
-			else
+			if let Some(message) = message
 			{
-				None
-			},
-			is_inventory: false,
-			weapons_allowed: slot_desc.weapons_allowed,
-		});
-	}
-
-	let mut lights = vec![];
-	for light_desc in &ship_desc.lights
-	{
-		lights.push(comps::Light {
-			pos: Point3::new(light_desc.pos[0], light_desc.pos[1], light_desc.pos[2]),
-			color: Color::from_rgb_f(
-				light_desc.color[0],
-				light_desc.color[1],
-				light_desc.color[2],
-			),
-			intensity: light_desc.intensity,
-		});
-	}
-
-	let mut equipment =
-		comps::Equipment::new(ship_desc.inventory_size.max(0) as usize, true, slots);
-
-	for slot in &mut equipment.slots
-	{
-		if !slot.is_inventory
+				state.sfx.play_sound("data/order.ogg").unwrap();
+				self.add_message(message::MessageKind::System, message.into(), state);
+			}
+		}
+		if want_attack && !self.attack_held && mouse_in_buffer && player_alive
 		{
-			continue;
+			self.bark(Order::Attack, state)?;
 		}
-		if rng.gen_bool(0.5)
+		if want_attack || self.attack_held
 		{
-			slot.item = Some(comps::generate_item(level, rng));
+			self.send_order(
+				controls::Action::Attack,
+				want_attack && mouse_in_buffer && player_alive,
+				false,
+				mouse_ground_pos,
+				state,
+			);
 		}
-	}
-
-	let res = world.spawn((
-		comps::Position {
-			pos: pos,
-			dir: PI / 2.,
-		},
-		comps::Velocity {
-			vel: Vector3::zeros(),
-			dir_vel: 0.,
-		},
-		comps::Mesh {
-			mesh: ship_desc.mesh.clone(),
-		},
-		comps::Target { waypoints: vec![] },
-		stats.clone(),
-		comps::Solid {
-			size: ship_desc.size,
-			mass: ship_desc.size.powf(3.),
-			kind: comps::CollideKind::Big,
-			parent: None,
-		},
-		equipment,
-		comps::ShipState::new(&stats, team, level),
-		comps::Tilt {
-			tilt: 0.,
-			target_tilt: 0.,
-		},
-		comps::Lights { lights: lights },
-	));
-	Ok(res)
-}
-
-#[derive(Copy, Clone, Debug)]
-struct CollisionEntry
-{
-	entity: hecs::Entity,
-	pos: Point3<f32>,
-}
-
-fn update_economy(economy: &mut [f32; 5], rng: &mut impl Rng) -> (usize, bool)
-{
-	let idx = rng.gen_range(0..economy.len());
-
-	let dir = *([-1., 1.].choose(rng).unwrap());
-	economy[idx] *= (1.25_f32).powf(dir);
-	let mut cur_sum = 0.;
-	for e in economy.iter()
-	{
-		cur_sum += *e;
-	}
-	for e in economy.iter_mut()
-	{
-		*e *= 1000. / cur_sum;
-	}
-	(idx, dir > 0.)
-}
-
-fn round_price(price: f32) -> i32
-{
-	((price / 10.) as i32) * 10
-}
-
-struct Map
-{
-	world: hecs::World,
-	rng: StdRng,
-	player: hecs::Entity,
-	player_pos: Point3<f32>,
-	zoom: f32,
-	target_entity: Option<hecs::Entity>,
-	dock_entity: Option<hecs::Entity>,
-	selection_indicator: Option<hecs::Entity>,
-	buffer_width: f32,
-	buffer_height: f32,
-	mouse_in_buffer: bool,
-	cells: Vec<Cell>,
-	money: i32,
-	messages: Vec<(String, f64)>,
-	level: i32,
-	global_offset: Vector2<i32>,
-	economy: [f32; 5],
-	time_to_economy: f64,
-	boss: Option<hecs::Entity>,
-	spawn_boss: bool,
-	start_time: f64,
-}
-
-impl Map
-{
-	fn new(state: &mut game_state::GameState) -> Result<Self>
-	{
-		let mut rng = StdRng::seed_from_u64(thread_rng().gen::<u16>() as u64);
-		let mut world = hecs::World::new();
-
-		let player = make_ship(
-			Point3::new(0., 0., 0.),
-			"data/small_ship.cfg",
-			comps::Team::English,
-			2,
-			&mut rng,
-			&mut world,
-			state,
-		)?;
+		self.attack_held = want_attack;
+		for id in std::iter::once(self.player).chain(self.selected.iter().copied())
 		{
-			//let mut ship_state = world.get::<&mut comps::ShipState>(player).unwrap();
-			//ship_state.hull = 10.;
-			//ship_state.crew = 1;
-			//ship_state.wounded = 0;
-			//ship_state.infirmary = 0.;
-			//ship_state.sails = 30.;
-			//ship_state.armor[0] = 50.;
-			//ship_state.armor[1] = 0.;
-			//ship_state.experience = comps::level_experience(10);
-			//ship_state.compute_level();
+			if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(id)
+			{
+				if want_attack && mouse_in_buffer && player_alive
+				{
+					equipment.want_attack = true;
+					equipment.target_pos = mouse_ground_pos;
+				}
+				else if !want_attack
+				{
+					equipment.want_attack = false;
+				}
+			}
 		}
-
-		let mut cells = vec![];
-		for y in -CELL_RADIUS..=CELL_RADIUS
+		if let Some(engage_target) = self.engage_target
 		{
-			for x in -CELL_RADIUS..=CELL_RADIUS
+			let target_pos = self
+				.world
+				.get::<&comps::ShipState>(engage_target)
+				.ok()
+				.filter(|s| s.is_active())
+				.and_then(|_| self.world.get::<&comps::Position>(engage_target).ok())
+				.map(|p| p.pos);
+			if target_pos.is_none()
 			{
-				cells.push(Cell::new(
-					Point2::new(x, y),
-					1,
-					&mut rng,
-					&mut world,
-					state,
-				)?);
+				self.engage_target = None;
+			}
+			if let Some(target_pos) = target_pos.filter(|_| !want_attack && player_alive)
+			{
+				for id in std::iter::once(self.player).chain(self.selected.iter().copied())
+				{
+					if let (Ok(pos), Ok(mut equipment)) = (
+						self.world.get::<&comps::Position>(id),
+						self.world.get::<&mut comps::Equipment>(id),
+					)
+					{
+						let range = equipment.weapon_max_range();
+						equipment.want_attack = (target_pos - pos.pos).magnitude() < range;
+						equipment.target_pos = target_pos;
+					}
+				}
 			}
 		}
-
-		state.cache_bitmap("data/english_flag.png")?;
-		state.cache_bitmap("data/pirate_flag.png")?;
-		state.cache_bitmap("data/french_flag.png")?;
-		state.cache_sprite("data/cannon_normal.cfg")?;
-		state.cache_sprite("data/cannon_magic.cfg")?;
-		state.cache_sprite("data/goods.cfg")?;
-		state.cache_sprite("data/cotton.cfg")?;
-		state.cache_sprite("data/tobacco.cfg")?;
-		state.cache_sprite("data/officer.cfg")?;
-		state.cache_sprite("data/cannon_rare.cfg")?;
-		state.cache_sprite("data/repair.cfg")?;
-		state.cache_sprite("data/switch.cfg")?;
-		state.cache_sprite("data/recruit.cfg")?;
-		state.sfx.cache_sample("data/order.ogg")?;
-		state.sfx.cache_sample("data/equipment.ogg")?;
-		state.sfx.cache_sample("data/cannon_shot.ogg")?;
-		state.sfx.cache_sample("data/screams.ogg")?;
-		state.sfx.cache_sample("data/sink.ogg")?;
-		state.sfx.cache_sample("data/explosion.ogg")?;
-		game_state::cache_mesh(state, "data/sphere.glb")?;
-
-		let mut economy = [0.; 5];
-
-		for e in &mut economy
+		if (want_broadside_left || want_broadside_right) && player_alive
 		{
-			*e = rng.gen_range(100.0..200.0);
+			// Aim perpendicular to the heading, so only that side's arcs fire, and as far out as the
+			// guns reach. Without guns there's nothing to fire.
+			if let (Ok(pos), Ok(mut equipment)) = (
+				self.world.get::<&comps::Position>(self.player),
+				self.world.get::<&mut comps::Equipment>(self.player),
+			)
+			{
+				let range = equipment.weapon_max_range();
+				if range > 0.
+				{
+					let side = if want_broadside_left { 1. } else { -1. };
+					let left = Rotation2::new(pos.dir) * Vector2::new(0., side);
+					equipment.want_attack = true;
+					equipment.target_pos = pos.pos + range * Vector3::new(left.y, 0., left.x);
+				}
+			}
 		}
-		update_economy(&mut economy, &mut rng);
-
-		Ok(Self {
-			world: world,
-			rng: rng,
-			player_pos: Point3::new(0., 0., 0.),
-			player: player,
-			target_entity: None,
-			selection_indicator: None,
-			buffer_width: state.display_width,
-			buffer_height: state.display_height,
-			mouse_in_buffer: true,
-			dock_entity: None,
-			cells: cells,
-			zoom: 1.,
-			money: 500,
-			messages: vec![
-				("Transcend the Sea".into(), state.time()),
-				("Hunt the Voidwind".into(), state.time()),
-				("Sail North".into(), state.time()),
-			],
-			level: 1,
-			global_offset: Vector2::new(0, 0),
-			economy: economy,
-			time_to_economy: state.time() + ECONOMY_INTERVAL,
-			boss: None,
-			start_time: state.time(),
-			spawn_boss: true,
-		})
-	}
-
-	fn make_project(&self) -> Perspective3<f32>
-	{
-		utils::projection_transform(self.buffer_width, self.buffer_height, PI / 2.)
-	}
-
-	fn camera_pos(&self) -> Point3<f32>
-	{
-		let height = 30. / self.zoom;
-		self.player_pos + Vector3::new(0., height, height / 2.)
-	}
-
-	fn make_camera(&self) -> Isometry3<f32>
-	{
-		utils::make_camera(self.camera_pos(), self.player_pos)
-	}
-
-	fn add_message(&mut self, message: String, state: &game_state::GameState)
-	{
-		self.messages.push((message, state.time()));
-	}
-
-	fn get_mouse_ground_pos(&self, state: &game_state::GameState) -> Point3<f32>
-	{
-		let (x, y) = (state.mouse_pos.x, state.mouse_pos.y);
-		let fx = -1. + 2. * x as f32 / self.buffer_width;
-		let fy = -1. + 2. * y as f32 / self.buffer_height;
-		let camera = self.make_camera();
-		utils::get_ground_from_screen(fx, -fy, self.make_project(), camera)
-	}
-
-	fn logic(&mut self, state: &mut game_state::GameState)
-		-> Result<Option<game_state::NextScreen>>
-	{
-		let mut to_die = vec![];
-		let dt = utils::DT as f32;
-
-		// Messages
-		self.messages
-			.retain(|(_, t)| state.time() - t < MESSAGE_DURATION as f64);
-
-		if state.time() > self.time_to_economy
+		if want_dock && player_alive && self.target_entity != Some(self.player)
+		{
+			state.controls.clear_action_state(controls::Action::Dock);
+			self.dock_entity = None;
+			self.docking = None;
+			self.course.clear();
+			if let Some(target_entity) = self.target_entity
+			{
+				let mut acknowledged = false;
+				if let (Ok(player_ship_state), Ok(_), Ok(ship_state)) = (
+					self.world.get::<&comps::ShipState>(self.player),
+					self.world.get::<&comps::Equipment>(target_entity),
+					self.world.get::<&comps::ShipState>(target_entity),
+				)
+				{
+					acknowledged = ship_state.team.dock_with(&player_ship_state.team);
+				}
+				if acknowledged
+				{
+					self.bark(Order::Dock, state)?;
+					self.docking = Some(target_entity);
+					let waypoints = self.docking_waypoints(target_entity);
+					if let (Ok(mut player_target), Some(waypoints)) =
+						(self.world.get::<&mut comps::Target>(self.player), waypoints)
+					{
+						player_target.clear(|m| to_die.push(m));
+						for pos in waypoints
+						{
+							player_target.waypoints.push(comps::Waypoint {
+								pos: pos,
+								marker: None,
+							});
+						}
+					}
+				}
+			}
+		}
+		// Autosail, steering for one cell of the course at a time.
+		if !self.course.is_empty()
 		{
-			let (idx, increased) = update_economy(&mut self.economy, &mut self.rng);
-
-			let name = match idx
+			let player_cell = Cell::world_to_cell(&self.player_pos) + self.global_offset;
+			let mut next_leg = false;
+			while self.course.first() == Some(&player_cell)
 			{
-				0 => "Weapon",
-				1 => "Goods",
-				2 => "Cotton",
-				3 => "Tobacco",
-				4 => "Officer",
-				_ => unreachable!(),
-			};
+				self.course.remove(0);
+				next_leg = true;
+			}
 
-			let message = if increased
+			let player_team = self
+				.world
+				.get::<&comps::ShipState>(self.player)
+				.map(|s| s.team)
+				.unwrap_or(comps::Team::English);
+			let contact = self
+				.world
+				.query::<(&comps::Position, &comps::ShipState)>()
+				.iter()
+				.any(|(_, (pos, ship_state))| {
+					ship_state.is_active()
+						&& ship_state.team.is_enemy(&player_team)
+						&& (pos.pos - self.player_pos).magnitude() < CONTACT_RADIUS
+				});
+			let new_contact = contact && !self.course_contact;
+			self.course_contact = contact;
+
+			if !player_alive || new_contact
 			{
-				format!(
-					"{name} markets are going up! Now at £{}",
-					round_price(self.economy[idx])
-				)
+				self.course.clear();
+				if let Ok(mut target) = self.world.get::<&mut comps::Target>(self.player)
+				{
+					target.clear(|m| to_die.push(m));
+				}
+				if new_contact
+				{
+					self.add_message(
+						message::MessageKind::Combat,
+						"Enemy sail sighted, heaving to.".into(),
+						state,
+					);
+				}
+			}
+			else if let Some(next) = self.course.first()
+			{
+				let pos = Cell::cell_to_world(next - self.global_offset);
+				if let Ok(mut target) = self.world.get::<&mut comps::Target>(self.player)
+				{
+					if next_leg || target.waypoints.is_empty()
+					{
+						target.clear(|m| to_die.push(m));
+						target.waypoints.push(comps::Waypoint {
+							pos: pos,
+							marker: None,
+						});
+					}
+				}
 			}
 			else
 			{
-				format!(
-					"{name} markets are falling down! Now at £{}",
-					round_price(self.economy[idx])
-				)
-			};
-
-			self.messages.push((message, state.time()));
-
-			self.time_to_economy = state.time() + ECONOMY_INTERVAL;
+				self.add_message(
+					message::MessageKind::Quest,
+					"We've made our destination.".into(),
+					state,
+				);
+			}
 		}
 
-		let mut timer = Timer::new("cell changes", state);
-		// Cell changes
-		let mut new_cell_centers = vec![];
-		let player_cell = Cell::world_to_cell(&self.player_pos);
-		let mut good_cells = vec![];
-
-		for cell in &self.cells
+		// Pull up alongside whoever we're docking with.
+		if let Some(dock_target) = self.docking
 		{
-			let disp = cell.center - player_cell;
-			if disp.x.abs() <= CELL_RADIUS && disp.y.abs() <= CELL_RADIUS
+			let mut abort = None;
+			let mut arrived = false;
+			if let (
+				Ok(player_pos),
+				Ok(player_target),
+				Ok(player_ship_state),
+				Ok(player_solid),
+				Ok(pos),
+				Ok(ship_state),
+				Ok(solid),
+			) = (
+				self.world.get::<&comps::Position>(self.player),
+				self.world.get::<&comps::Target>(self.player),
+				self.world.get::<&comps::ShipState>(self.player),
+				self.world.get::<&comps::Solid>(self.player),
+				self.world.get::<&comps::Position>(dock_target),
+				self.world.get::<&comps::ShipState>(dock_target),
+				self.world.get::<&comps::Solid>(dock_target),
+			)
 			{
-				good_cells.push(cell.clone());
+				if !player_alive || !ship_state.team.dock_with(&player_ship_state.team)
+				{
+					abort = Some("Docking aborted.");
+				}
+				else if (player_pos.pos.zx() - pos.pos.zx()).magnitude()
+					< DOCK_DISTANCE + solid.size + player_solid.size
+				{
+					// Either lined up with her, or out of waypoints and as close as we'll get.
+					let alignment = (player_pos.dir - pos.dir).cos().abs();
+					arrived = alignment > DOCK_ALIGNMENT || player_target.waypoints.is_empty();
+				}
+				else if player_target.waypoints.is_empty()
+				{
+					abort = Some("Couldn't come alongside, docking aborted.");
+				}
+			}
+			else
+			{
+				abort = Some("Docking aborted.");
+			}
+			if let Ok(mut ai) = self.world.get::<&mut comps::AI>(dock_target)
+			{
+				// Hold still while we come alongside.
+				ai.state = comps::AIState::Pause {
+					time_to_unpause: state.time() + 1.,
+				};
+			}
+			if let Ok(mut target) = self.world.get::<&mut comps::Target>(dock_target)
+			{
+				target.clear(|m| to_die.push(m));
+			}
+			if let Some(reason) = abort
+			{
+				self.abort_docking(reason, &mut to_die, state);
+			}
+			else if arrived
+			{
+				self.docking = None;
+				if let Ok(mut player_target) = self.world.get::<&mut comps::Target>(self.player)
+				{
+					player_target.clear(|m| to_die.push(m));
+				}
+				self.dock(dock_target, state);
 			}
 		}
-		self.cells.clear();
+		if want_zoom_in
+		{
+			self.zoom *= 1.25;
+		}
+		if want_zoom_out
+		{
+			self.zoom /= 1.25;
+		}
+		self.zoom = utils::clamp(self.zoom, 1., 4.);
 
-		for (id, position) in self.world.query::<&comps::Position>().iter()
+		// Spectator camera.
+		if self.spectator || self.defeated()
 		{
-			let cell = Cell::world_to_cell(&position.pos);
-			let disp = cell - player_cell;
-			if disp.x.abs() > CELL_RADIUS || disp.y.abs() > CELL_RADIUS
+			let mut pan = |action| state.controls.get_action_state(action);
+			self.camera_pan = Vector2::new(
+				pan(controls::Action::PanRight) - pan(controls::Action::PanLeft),
+				pan(controls::Action::PanDown) - pan(controls::Action::PanUp),
+			);
+		}
+		if self.spectator
+		{
+			if self.camera_pan.magnitude() > 0.
 			{
-				to_die.push(id);
+				self.target_entity = None;
+			}
+			let mut camera_pos = self.player_pos
+				+ 60. / self.zoom * dt * Vector3::new(self.camera_pan.x, 0., self.camera_pan.y);
+			if let Some(pos) = self
+				.target_entity
+				.and_then(|e| self.world.get::<&comps::Position>(e).ok())
+			{
+				camera_pos = pos.pos;
+			}
+			if let Ok(mut pos) = self.world.get::<&mut comps::Position>(self.player)
+			{
+				pos.pos = camera_pos;
 			}
 		}
 
-		for dy in -CELL_RADIUS..=CELL_RADIUS
+		// Station keeping.
+		let flagship = self
+			.world
+			.get::<&comps::Position>(self.player)
+			.ok()
+			.filter(|_| player_alive)
+			.map(|pos| (pos.pos, pos.dir));
+		let mut stations: Vec<_> = self
+			.world
+			.query::<(&comps::Station, &comps::Fleet)>()
+			.iter()
+			.map(|(id, _)| id)
+			.collect();
+		stations.sort_by_key(|id| id.to_bits());
+		let n = stations.len() + 1;
+		for (i, id) in stations.into_iter().enumerate()
 		{
-			for dx in -CELL_RADIUS..=CELL_RADIUS
+			let flagship = if let Some(flagship) = flagship
 			{
-				let cell_center = player_cell + Vector2::new(dx, dy);
+				flagship
+			}
+			else
+			{
+				break;
+			};
+			let mut query = self.world.query_one::<(
+				&comps::Position,
+				&mut comps::Target,
+				&mut comps::Equipment,
+				&comps::ShipState,
+				&mut comps::Station,
+			)>(id)?;
+			let (pos, target, equipment, ship_state, station) = if let Some(components) =
+				query.get()
+			{
+				components
+			}
+			else
+			{
+				continue;
+			};
+			if !ship_state.is_active()
+			{
+				continue;
+			}
+			let sense_radius = 30.;
+			let attack_radius = 20.;
+			let entries = grid.query_rect(
+				pos.pos.zx() - Vector2::new(sense_radius, sense_radius) - center.coords,
+				pos.pos.zx() + Vector2::new(sense_radius, sense_radius) - center.coords,
+				|other| {
+					other.inner.entity != id
+						&& self
+							.world
+							.get::<&comps::ShipState>(other.inner.entity)
+							.map(|s| s.is_active() && s.team.is_enemy(&ship_state.team))
+							.unwrap_or(false)
+				},
+			);
+			let threat = entries
+				.iter()
+				.map(|e| e.inner.pos)
+				.filter(|p| (p - pos.pos).magnitude() < sense_radius)
+				.min_by(|a, b| {
+					(a - pos.pos)
+						.magnitude()
+						.partial_cmp(&(b - pos.pos).magnitude())
+						.unwrap()
+				});
 
-				let mut found = false;
-				for cell in &good_cells
+			let dest = if let Some(threat_pos) = threat
+			{
+				station.engaged = true;
+				equipment.want_attack = (threat_pos - pos.pos).magnitude() < attack_radius;
+				equipment.target_pos = threat_pos;
+				// Close in, but no further than needed to get a shot.
+				let away = (pos.pos - threat_pos).zx();
+				let away = if away.magnitude() > 1e-3
 				{
-					if cell.center == cell_center
-					{
-						self.cells.push(cell.clone());
-						found = true;
-						break;
-					}
+					away.normalize()
 				}
-
-				if !found
+				else
 				{
-					new_cell_centers.push(cell_center);
-					//println!("New cell {}", cell_center);
+					Vector2::new(1., 0.)
+				};
+				threat_pos + 0.75 * attack_radius * Vector3::new(away.y, 0., away.x)
+			}
+			else
+			{
+				if station.engaged
+				{
+					station.engaged = false;
+					equipment.want_attack = false;
 				}
+				let (flag_pos, flag_dir) = flagship;
+				let offset = Rotation2::new(flag_dir) * self.formation.offset(i + 1, n);
+				flag_pos + Vector3::new(offset.y, 0., offset.x)
+			};
+			if (dest - pos.pos).magnitude() > 3.
+			{
+				target.clear(|m| to_die.push(m));
+				target.waypoints.push(comps::Waypoint {
+					pos: dest,
+					marker: None,
+				});
 			}
 		}
 
-		new_cell_centers.shuffle(&mut self.rng);
+		// Attack en route.
+		for (id, (pos, target, equipment, ship_state)) in self
+			.world
+			.query::<(
+				&comps::Position,
+				&comps::Target,
+				&mut comps::Equipment,
+				&comps::ShipState,
+			)>()
+			.iter()
+		{
+			if !target.attack_en_route || !ship_state.is_active()
+			{
+				continue;
+			}
+			let attack_radius = 20.;
+			let entries = grid.query_rect(
+				pos.pos.zx() - Vector2::new(attack_radius, attack_radius) - center.coords,
+				pos.pos.zx() + Vector2::new(attack_radius, attack_radius) - center.coords,
+				|other| {
+					other.inner.entity != id
+						&& self
+							.world
+							.get::<&comps::ShipState>(other.inner.entity)
+							.map(|s| s.is_active() && s.team.is_enemy(&ship_state.team))
+							.unwrap_or(false)
+				},
+			);
+			if let Some(enemy_pos) = entries
+				.iter()
+				.map(|e| e.inner.pos)
+				.filter(|p| (p - pos.pos).magnitude() < attack_radius)
+				.min_by(|a, b| {
+					(a - pos.pos)
+						.magnitude()
+						.partial_cmp(&(b - pos.pos).magnitude())
+						.unwrap()
+				})
+			{
+				equipment.want_attack = true;
+				equipment.target_pos = enemy_pos;
+			}
+			else
+			{
+				equipment.want_attack = false;
+			}
+		}
 
-		if self.spawn_boss && !new_cell_centers.is_empty()
+		let mut timer = Timer::new("equipment actions", state);
+		// Equipment actions
+		let mut spawn_projectiles = vec![];
+		let mut misfires = vec![];
+		let hostile = self.hostile_difficulty(state);
+		let truce = self
+			.dock_entity
+			.map(|dock_entity| [self.player, dock_entity]);
+		let mut ships = vec![];
+		for (id, (pos, solid, ship_state)) in self
+			.world
+			.query::<(&comps::Position, &comps::Solid, &comps::ShipState)>()
+			.iter()
 		{
-			if let Some(boss) = self.boss
+			if ship_state.is_active()
 			{
-				if !self.world.contains(boss)
-				{
-					self.boss = None;
-				}
+				ships.push((id, ship_state.team, pos.pos, solid.size));
 			}
-			if self.boss.is_none()
+		}
+		for (id, (pos, equipment, ship_state, stats, tilt, ai)) in self
+			.world
+			.query::<(
+				&comps::Position,
+				&mut comps::Equipment,
+				&mut comps::ShipState,
+				&comps::ShipStats,
+				Option<&comps::Tilt>,
+				Option<&comps::AI>,
+			)>()
+			.iter()
+		{
+			let derived_stats = equipment.derived_stats();
+			let powder_surge = ship_state.powder_surge;
+			let heel = tilt.map(|tilt| tilt.heel).unwrap_or(0.);
+			// No buffering
+			let want_attack =
+				equipment.want_attack && !truce.is_some_and(|truce| truce.contains(&id));
+			//equipment.want_attack = false;
+			for slot in &mut equipment.slots
 			{
-				//println!("Spawned boss");
-				let boss = make_ship(
-					Cell::cell_to_world(new_cell_centers[0]),
-					//self.player_pos + Vector3::new(0., 0., 100.),
-					"data/boss_ship.cfg",
-					comps::Team::Pirate,
-					(-self.global_offset.y + 10).max(15),
-					&mut self.rng,
-					&mut self.world,
-					state,
-				)?;
-				self.world.insert(
-					boss,
-					(
-						comps::AI {
-							state: comps::AIState::Idle,
-							name: "Voidwind".into(),
-						},
-						comps::WispSpawner {
-							time_to_spawn: state.time(),
-						},
-					),
-				)?;
+				if slot.is_inventory || slot.disabled
 				{
-					let mut ship_state = self.world.get::<&mut comps::ShipState>(boss).unwrap();
-					ship_state.is_boss = true;
+					continue;
+				}
+				if let Some(item) = slot.item.as_mut()
+				{
+					match &mut item.kind
+					{
+						comps::ItemKind::Weapon(weapon) =>
+						{
+							if weapon.readiness >= 1.0
+								&& want_attack && weapon.time_to_fire.is_none()
+							{
+								weapon.time_to_fire =
+									Some(state.time() + self.rng.gen_range(0.0..0.2));
+							}
+							if weapon
+								.time_to_fire
+								.map(|ttf| state.time() > ttf)
+								.unwrap_or(false)
+							{
+								weapon.time_to_fire = None;
+								let weapon_stats = weapon.stats();
+								let (heel_spread, arc) =
+									heel_penalty(heel, slot.dir, weapon_stats.arc);
+								let (slot_pos, aim) = aim_gun(
+									pos,
+									slot.pos,
+									slot.dir,
+									arc,
+									derived_stats.out_of_arc,
+									equipment.target_pos,
+								);
+
+								let spawn_pos = Point3::new(slot_pos.y, GUN_HEIGHT, slot_pos.x);
+								// The AI holds its shot rather than put it through a friend.
+								let blocked = ai.is_some()
+									&& line_of_fire_blocked(
+										spawn_pos,
+										equipment.target_pos,
+										id,
+										ship_state.team,
+										&ships,
+									);
+								if let Some((spawn_dir, _)) = aim.filter(|_| !blocked)
+								{
+									let f = (1. + derived_stats.accuracy)
+										* hostile(ship_state.team)
+											.map(|d| d.accuracy())
+											.unwrap_or(1.) / heel_spread;
+									let rot = Rotation2::new(self.combat_rng.gen_range(
+										-weapon_stats.spread / f..=weapon_stats.spread / f,
+									));
+									let spawn_dir = gun_velocity(rot * spawn_dir, 1.);
+									let mut weapon_stats = weapon.stats().clone();
+									weapon_stats.critical_chance *=
+										1. + derived_stats.critical_chance;
+									let misfire = powder_surge
+										&& self.combat_rng.gen_bool(
+											(comps::POWDER_SURGE_MISFIRE
+												/ (1. + derived_stats.accuracy)) as f64,
+										);
+									if misfire
+									{
+										// Blew up in our faces.
+										ship_state.hull =
+											(ship_state.hull - 0.05 * stats.hull).max(0.);
+										misfires.push((id, spawn_pos));
+									}
+									else
+									{
+										if powder_surge
+										{
+											weapon_stats.damage *= comps::POWDER_SURGE_DAMAGE;
+										}
+										spawn_projectiles.push((
+											spawn_pos,
+											spawn_dir,
+											id,
+											ship_state.team,
+											weapon_stats,
+											weapon.name.clone(),
+										));
+									}
+									ship_state.powder_surge = false;
+									ship_state
+										.train(comps::Specialty::Gunnery, comps::GUNNERY_TRAINING);
+									state.sfx.play_positional_sound(
+										"data/cannon_shot.ogg",
+										spawn_pos.xz(),
+										self.player_pos.xz(),
+										0.5,
+									)?;
+									weapon.readiness = 0.;
+								}
+							}
+						}
+						_ => (),
+					}
 				}
-				self.boss = Some(boss);
 			}
 		}
 
-		for cell_center in new_cell_centers
+		for (id, spawn_pos) in misfires
 		{
-			let level = (-(cell_center.y + self.global_offset.y)).max(1);
-			//println!("LEVEL {} {:?}", level, self.global_offset);
-			self.cells.push(Cell::new(
-				cell_center,
-				level,
-				&mut self.rng,
+			make_muzzle_flash(spawn_pos, id, &mut self.world, state)?;
+			if id == self.player
+			{
+				self.add_message(
+					message::MessageKind::Combat,
+					"A gun misfired!".to_string(),
+					state,
+				);
+			}
+		}
+		// Half the guns make a broadside.
+		let player_guns = self
+			.world
+			.get::<&comps::Equipment>(self.player)
+			.map(|equipment| {
+				equipment
+					.slots
+					.iter()
+					.filter(|slot| {
+						!slot.is_inventory
+							&& matches!(
+								slot.item.as_ref().map(|item| &item.kind),
+								Some(comps::ItemKind::Weapon(_))
+							)
+					})
+					.count()
+			})
+			.unwrap_or(0);
+		for (spawn_pos, spawn_dir, parent, team, stats, source) in spawn_projectiles
+		{
+			if parent == self.player
+			{
+				self.feedback.recoil(
+					spawn_dir,
+					2. / player_guns.max(1) as f32,
+					state.options.feedback_intensity,
+				);
+			}
+			make_muzzle_flash(spawn_pos, parent, &mut self.world, state)?;
+			make_projectile(
+				spawn_pos,
+				spawn_dir,
+				parent,
+				team,
+				&stats,
+				&source,
 				&mut self.world,
 				state,
-			)?);
+			)?;
 		}
+		timer.record(&state.core);
 
-		// Recenter.
-		let cell_offt = player_cell.coords;
-		self.global_offset += cell_offt;
-		for cell in &mut self.cells
+		let mut spawn_wisps = vec![];
+		for (id, (pos, wisp_spawner, ship_state)) in self
+			.world
+			.query::<(&comps::Position, &mut comps::WispSpawner, &comps::ShipState)>()
+			.iter()
 		{
-			cell.center -= player_cell.coords;
+			if state.time() > wisp_spawner.time_to_spawn && ship_state.is_active()
+			{
+				spawn_wisps.push((pos.pos, id, ship_state.level));
+				wisp_spawner.time_to_spawn =
+					state.time() + 4. * state.modifiers.wisp_interval as f64;
+			}
 		}
-		let offt = Cell::cell_to_world(player_cell).coords;
-		for (_, pos) in self.world.query::<&mut comps::Position>().iter()
+		for (pos, parent, level) in spawn_wisps
 		{
-			pos.pos -= offt;
+			let dir = self.rng.gen_range(0.0..PI * 2.0);
+			let vel = Vector3::new(dir.cos(), 0., dir.sin()) * 5.;
+			make_wisp(pos, vel, parent, level, &mut self.world, state)?;
 		}
-		for (_, target) in self.world.query::<&mut comps::Target>().iter()
+
+		// Boss escorts.
+		let mut taken_slots = HashMap::new();
+		for (_, (ai, ship_state)) in self.world.query::<(&comps::AI, &comps::ShipState)>().iter()
 		{
-			for waypoint in &mut target.waypoints
+			if let comps::AIState::Escorting { leader, slot } = ai.state
 			{
-				waypoint.pos -= offt;
+				if ship_state.is_active()
+				{
+					taken_slots.entry(leader).or_insert(vec![]).push(slot);
+				}
 			}
 		}
-		for (_, equipment) in self.world.query::<&mut comps::Equipment>().iter()
+		let mut spawn_escorts = vec![];
+		for (id, (pos, escort_spawner, ship_state)) in self
+			.world
+			.query::<(
+				&comps::Position,
+				&mut comps::EscortSpawner,
+				&comps::ShipState,
+			)>()
+			.iter()
 		{
-			equipment.target_pos -= offt;
+			let taken = taken_slots.get(&id).map(|t| &t[..]).unwrap_or(&[]);
+			// Fill in for the lost escorts first.
+			let free_slot = (0..escort_spawner.max_escorts).find(|slot| !taken.contains(slot));
+			if state.time() > escort_spawner.time_to_spawn && ship_state.is_active()
+			{
+				if let Some(slot) = free_slot
+				{
+					spawn_escorts.push((
+						pos.pos,
+						id,
+						ship_state.team,
+						(2 * ship_state.level / 3).max(1),
+						slot,
+					));
+					escort_spawner.time_to_spawn = state.time() + ESCORT_INTERVAL;
+				}
+			}
 		}
-		self.player_pos -= offt;
-		if offt.magnitude() > 0.0
+		for (pos, leader, team, level, slot) in spawn_escorts
 		{
-			//dbg!("recentered");
+			let dir = self.rng.gen_range(0.0..PI * 2.0);
+			let escort = make_ship(
+				pos + 15. * Vector3::new(dir.cos(), 0., dir.sin()),
+				"data/small_ship.cfg",
+				team,
+				level,
+				&mut self.loot_rng,
+				&mut self.world,
+				state,
+			)?;
+			self.world.insert_one(
+				escort,
+				comps::AI {
+					state: comps::AIState::Escorting {
+						leader: leader,
+						slot: slot,
+					},
+					name: "Ghost Ship".into(),
+				},
+			)?;
 		}
-		timer.record(&state.core);
 
-		let mut timer = Timer::new("physics", state);
-		// Collision.
-		let center = self.player_pos.zx();
-		let mut grid = spatial_grid::SpatialGrid::new(128, 128, 8.0, 8.0);
-		for (id, (position, solid)) in self
+		// Wisps home in on the nearest non-pirate ship.
+		let mut prey = vec![];
+		for (_, (pos, ship_state)) in self
 			.world
-			.query::<(&comps::Position, &comps::Solid)>()
+			.query::<(&comps::Position, &comps::ShipState)>()
 			.iter()
 		{
-			let pos = Point2::from(position.pos.zx() - center);
-			let disp = Vector2::new(solid.size, solid.size);
-			grid.push(spatial_grid::entry(
-				pos - disp,
-				pos + disp,
-				CollisionEntry {
-					entity: id,
-					pos: position.pos,
-				},
-			));
+			if ship_state.is_active() && ship_state.team != comps::Team::Pirate
+			{
+				prey.push(pos.pos);
+			}
 		}
-		timer.record(&state.core);
-
-		let mut timer = Timer::new("physics", state);
-		// Physics
-		for (_, (_, vel)) in self
+		for (_, (pos, vel, wisp)) in self
 			.world
-			.query::<(&comps::AffectedByGravity, &mut comps::Velocity)>()
+			.query::<(&comps::Position, &mut comps::Velocity, &mut comps::Wisp)>()
 			.iter()
 		{
-			vel.vel.y -= dt * 100.0;
+			let sense_radius = 60.;
+			let mut best = None;
+			let mut best_dist = sense_radius;
+			for prey_pos in &prey
+			{
+				let dist = (prey_pos.zx() - pos.pos.zx()).magnitude();
+				if dist < best_dist
+				{
+					best_dist = dist;
+					best = Some(*prey_pos);
+				}
+			}
+			if let Some(prey_pos) = best
+			{
+				let diff = prey_pos - pos.pos;
+				let want_vel = wisp.speed * Vector3::new(diff.x, 0., diff.z).normalize();
+				vel.vel += utils::approach_frac(0.5, dt) * (want_vel - vel.vel);
+			}
+
+			if state.time() > wisp.time_to_hum
+			{
+				state.sfx.play_positional_sound(
+					"data/wisp_hum.wav",
+					pos.pos.xz(),
+					self.player_pos.xz(),
+					0.3,
+				)?;
+				wisp.time_to_hum = state.time() + 1.;
+			}
 		}
 
-		for (_, (pos, vel)) in self
-			.world
-			.query::<(&mut comps::Position, &comps::Velocity)>()
-			.iter()
+		self.wildlife_logic(dt, state)?;
+
+		// Update player pos.
+		self.prev_player_pos = self.player_pos;
+		if self.defeated()
 		{
-			pos.pos += dt * vel.vel;
-			pos.dir += dt * vel.dir_vel;
+			self.player_pos +=
+				60. / self.zoom * dt * Vector3::new(self.camera_pan.x, 0., self.camera_pan.y);
 		}
-		timer.record(&state.core);
-
-		// Collides with water.
-		for (id, (_, pos)) in self
-			.world
-			.query::<(&comps::CollidesWithWater, &mut comps::Position)>()
-			.iter()
+		else if let Ok(pos) = self.world.get::<&comps::Position>(self.player)
 		{
-			if pos.pos.y < -0.0
-			{
-				to_die.push(id);
-			}
+			self.player_pos = pos.pos;
 		}
 
-		let mut timer = Timer::new("ship_state", state);
-		// Ship state simulation.
-		let mut num_ships = 0;
-		for (_, (ship_state, stats, equipment)) in self
+		// Target movement.
+		for (_, (target, pos, vel, ship_state, stats, equipment, solid)) in self
 			.world
 			.query::<(
+				&mut comps::Target,
+				&comps::Position,
+				&mut comps::Velocity,
 				&mut comps::ShipState,
 				&comps::ShipStats,
-				&mut comps::Equipment,
+				&comps::Equipment,
+				&comps::Solid,
 			)>()
 			.iter()
 		{
-			num_ships += 1;
-			if !ship_state.is_structurally_sound()
+			if target.waypoints.is_empty()
 			{
-				// Can't fix a broken ship.
+				vel.vel = Vector3::zeros();
+				vel.dir_vel = 0.;
+				continue;
+			}
+			let waypoint = target.waypoints.first().unwrap();
+			let diff = waypoint.pos - pos.pos;
+			if diff.magnitude() < 0.1
+			{
+				if target.waypoints.len() == 1 && !target.patrol
+				{
+					vel.vel = Vector3::zeros();
+					vel.dir_vel = 0.;
+				}
+				let waypoint = target.waypoints.remove(0);
+				if target.patrol
+				{
+					target.waypoints.push(waypoint);
+				}
+				else if let Some(marker) = waypoint.marker
+				{
+					to_die.push(marker);
+				}
 				continue;
 			}
-			let derived_stats = equipment.derived_stats();
-
-			ship_state.compute_level();
 
-			let effective_crew =
-				ship_state.crew as f32 * comps::level_effectiveness(ship_state.level);
+			let diff = diff.zx().normalize();
+			let rot = Rotation2::new(pos.dir);
+			let forward = rot * Vector2::new(1., 0.);
+			let left = rot * Vector2::new(0., 1.);
 
-			// Each crew member can repair 0.1 point per 1 second, probabilistically
-			let repair_prob = dt as f64;
-			let num_repaired =
-				rand_distr::Binomial::new((effective_crew.sqrt() * 0.5).ceil() as u64, repair_prob)
-					.unwrap()
-					.sample(&mut self.rng);
+			let mut speed_factor = 0.1
+				+ 0.9 * (ship_state.sails / stats.sails) * (1. + equipment.derived_stats().speed);
+			// Big ships have to pick their way through the shallows.
+			if solid.size >= SHALLOWS_DRAFT && biome_at(&self.cells, &pos.pos) == Biome::Shallows
+			{
+				speed_factor *= SHALLOWS_SPEED;
+			}
 
-			let mut parts = [
-				stats.armor[0] - ship_state.armor[0],
-				stats.armor[1] - ship_state.armor[1],
-				stats.armor[2] - ship_state.armor[2],
-				stats.armor[3] - ship_state.armor[3],
-				10. * (stats.hull - ship_state.hull), // No hull, no ship.
-				stats.infirmary - ship_state.infirmary,
-				stats.sails - ship_state.sails,
-			];
-			for i in &ship_state.repair_boost
+			let dot = diff.dot(&left);
+			if dot > 0.05
 			{
-				parts[*i] *= 5.;
+				vel.dir_vel = speed_factor
+					* stats.dir_speed
+					* ship_state.specialty_effectiveness(comps::Specialty::Sailing);
 			}
-			if let Ok(dist) = rand_distr::WeightedIndex::new(&parts)
+			else if dot < -0.05
 			{
-				let to_repair = dist.sample(&mut self.rng);
-				let num_repaired = num_repaired as f32;
-				match to_repair
-				{
-					0 =>
-					{
-						ship_state.armor[0] = (ship_state.armor[0]
-							+ num_repaired * (1. + derived_stats.armor_repair))
-							.min(stats.armor[0])
-					}
-					1 =>
-					{
-						ship_state.armor[1] = (ship_state.armor[1]
-							+ num_repaired * (1. + derived_stats.armor_repair))
-							.min(stats.armor[1])
-					}
-					2 =>
-					{
-						ship_state.armor[2] = (ship_state.armor[2]
-							+ num_repaired * (1. + derived_stats.armor_repair))
-							.min(stats.armor[2])
-					}
-					3 =>
-					{
-						ship_state.armor[3] = (ship_state.armor[3]
-							+ num_repaired * (1. + derived_stats.armor_repair))
-							.min(stats.armor[3])
-					}
-					4 =>
-					{
-						ship_state.hull = (ship_state.hull
-							+ num_repaired * (1. + derived_stats.hull_repair))
-							.min(stats.hull)
-					}
-					5 =>
-					{
-						ship_state.infirmary = (ship_state.infirmary
-							+ num_repaired * (1. + derived_stats.infirmary_repair))
-							.min(stats.infirmary)
-					}
-					6 =>
-					{
-						ship_state.sails = (ship_state.sails
-							+ num_repaired * (1. + derived_stats.sail_repair))
-							.min(stats.sails)
-					}
-					_ => unreachable!(),
-				}
+				vel.dir_vel = speed_factor * -stats.dir_speed;
+			}
+			else
+			{
+				vel.dir_vel = 0.;
+			}
+			if state.time() < ship_state.hard_turn_until
+			{
+				vel.dir_vel *= comps::HARD_TURN_FACTOR;
+			}
+			vel.vel = speed_factor * stats.speed * Vector3::new(forward.y, 0., forward.x);
+			ship_state.train(
+				comps::Specialty::Sailing,
+				comps::SAILING_TRAINING * vel.vel.norm() * dt,
+			);
+		}
+
+		// Ships with their guns out, for civilians to steer clear of.
+		let mut combatants = vec![];
+		for (id, (pos, equipment, ship_state)) in self
+			.world
+			.query::<(&comps::Position, &comps::Equipment, &comps::ShipState)>()
+			.iter()
+		{
+			if equipment.want_attack && ship_state.is_active()
+			{
+				combatants.push((id, pos.pos));
 			}
+		}
+		let player_pos = self
+			.world
+			.get::<&comps::Position>(self.player)
+			.map(|p| p.pos)
+			.ok();
 
-			// Each patient has a chance of getting better, weighed by infirmary strength... I
-			// guess it has more drugs?
-			let heal_prob = (dt as f32 * ship_state.infirmary.sqrt()
-				/ 100.0 / ship_state.wounded as f32
-				* (1. + derived_stats.medic))
-				.min(1.);
-			for _ in 0..ship_state.wounded
+		// AI
+		let mut timer = Timer::new("ai", state);
+		for (id, (pos, target, ai, equipment, ship_state)) in self
+			.world
+			.query::<(
+				&comps::Position,
+				&mut comps::Target,
+				&mut comps::AI,
+				&mut comps::Equipment,
+				&comps::ShipState,
+			)>()
+			.iter()
+		{
+			let sense_radius = 40.;
+			let attack_radius = 20.;
+			if Some(id) == self.dock_entity
 			{
-				if self.rng.gen_bool(heal_prob as f64)
-				{
-					ship_state.wounded -= 1;
-					ship_state.crew += 1;
-				}
+				target.clear(|m| to_die.push(m));
+				continue;
 			}
-
-			// Weapon handling.
-			let mut num_weapons = 0;
-			for slot in &equipment.slots
+			match ai.state
 			{
-				if slot.is_inventory
+				comps::AIState::Pause { time_to_unpause } =>
 				{
-					continue;
+					if state.time() > time_to_unpause
+					{
+						ai.state = comps::AIState::Idle;
+					}
 				}
-				if let Some(item) = slot.item.as_ref()
+				comps::AIState::Escorting { leader, slot } =>
 				{
-					match &item.kind
+					let leader_pos = self
+						.world
+						.get::<&comps::ShipState>(leader)
+						.ok()
+						.filter(|s| s.is_active())
+						.and_then(|_| self.world.get::<&comps::Position>(leader).ok())
+						.map(|p| p.pos);
+					if let Some(leader_pos) = leader_pos
 					{
-						comps::ItemKind::Weapon(weapon) =>
+						// The closest enemy to the leader is the one to guard against.
+						let entries = grid.query_rect(
+							leader_pos.zx()
+								- Vector2::new(sense_radius, sense_radius)
+								- center.coords,
+							leader_pos.zx() + Vector2::new(sense_radius, sense_radius)
+								- center.coords,
+							|other| {
+								self.world
+									.get::<&comps::ShipState>(other.inner.entity)
+									.map(|s| s.is_active() && s.team.is_enemy(&ship_state.team))
+									.unwrap_or(false)
+							},
+						);
+						let threat = entries
+							.iter()
+							.map(|e| e.inner.pos)
+							.filter(|p| (p - leader_pos).magnitude() < sense_radius)
+							.min_by(|a, b| {
+								(a - leader_pos)
+									.magnitude()
+									.partial_cmp(&(b - leader_pos).magnitude())
+									.unwrap()
+							});
+
+						let side = if slot % 2 == 0 { 1. } else { -1. };
+						let spacing = 8. * ((slot / 2) as f32 + 1.) * side;
+						let guard_pos = if let Some(threat_pos) = threat
 						{
-							if weapon.readiness < 1.
-							{
-								num_weapons += 1;
-							}
+							// Stand between the leader and the threat.
+							let forward = (threat_pos - leader_pos).zx().normalize();
+							let left = Vector2::new(-forward.y, forward.x);
+							let disp = forward * 10. + left * spacing;
+							leader_pos + Vector3::new(disp.y, 0., disp.x)
+						}
+						else
+						{
+							let max_escorts = self
+								.world
+								.get::<&comps::EscortSpawner>(leader)
+								.map(|s| s.max_escorts)
+								.unwrap_or(1)
+								.max(1);
+							let theta = (slot as f32 + 0.5) * 2. * PI / max_escorts as f32;
+							leader_pos + 10. * Vector3::new(theta.cos(), 0., theta.sin())
+						};
+
+						if (guard_pos - pos.pos).magnitude() > 3.
+						{
+							target.clear(|m| to_die.push(m));
+							target.waypoints.push(comps::Waypoint {
+								pos: guard_pos,
+								marker: None,
+							});
+						}
+						if let Some(threat_pos) = threat
+						{
+							equipment.want_attack =
+								(threat_pos - pos.pos).magnitude() < attack_radius;
+							equipment.target_pos = threat_pos;
+						}
+						else
+						{
+							equipment.want_attack = false;
 						}
-						_ => (),
+					}
+					else
+					{
+						// Lost the leader, go back to being a normal ship.
+						ai.state = comps::AIState::Idle;
+						equipment.want_attack = false;
 					}
 				}
-			}
-
-			// X crew per weapon to reload it effectively.
-			let crew_per_weapon = 10;
-			let fire_rate_adjustment = 1. / crew_per_weapon as f32 * effective_crew.sqrt()
-				/ num_weapons as f32
-				* (1. + derived_stats.reload_speed);
-			for slot in &mut equipment.slots
-			{
-				if slot.is_inventory
-				{
-					continue;
-				}
-				if let Some(item) = slot.item.as_mut()
+				comps::AIState::Hunting(target_entity) =>
 				{
-					match &mut item.kind
+					let target_pos = self
+						.world
+						.get::<&comps::ShipState>(target_entity)
+						.ok()
+						.filter(|s| s.is_active() && s.team.is_enemy(&ship_state.team))
+						.and_then(|_| self.world.get::<&comps::Position>(target_entity).ok())
+						.map(|p| p.pos);
+					if let Some(target_pos) = target_pos
 					{
-						comps::ItemKind::Weapon(weapon) =>
+						target.clear(|m| to_die.push(m));
+						if (target_pos - pos.pos).magnitude() < attack_radius
 						{
-							weapon.readiness = (weapon.readiness
-								+ dt * (fire_rate_adjustment / weapon.stats().fire_interval))
-								.min(1.0);
+							ai.state = comps::AIState::Attacking(target_entity);
 						}
-						_ => (),
+						else
+						{
+							target.waypoints.push(comps::Waypoint {
+								pos: target_pos,
+								marker: None,
+							});
+						}
+					}
+					else
+					{
+						ai.state = comps::AIState::Idle;
 					}
 				}
-			}
-		}
-		timer.record(&state.core);
-		if state.tick % 64 == 0
-		{
-			//println!("Num ships: {}", num_ships);
-		}
-
-		// Tilt.
-		for (_, (tilt, ship_state)) in self
-			.world
-			.query::<(&mut comps::Tilt, &comps::ShipState)>()
-			.iter()
-		{
-			tilt.target_tilt = state.time().sin() as f32 * PI / 4.;
-			if !ship_state.is_structurally_sound()
-			{
-				tilt.target_tilt -= PI / 2.;
-			}
-			tilt.tilt += 0.1 * dt * (tilt.target_tilt - tilt.tilt);
-		}
-
-		let mut timer = Timer::new("collision", state);
-		// Collision resolution.
-		let mut colliding_pairs = vec![];
-		for (a, b) in grid.all_pairs(|a, b| {
-			let a = a.inner.entity;
-			let b = b.inner.entity;
-			let a_solid = self.world.get::<&comps::Solid>(a).unwrap();
-			let b_solid = self.world.get::<&comps::Solid>(b).unwrap();
-			a_solid.kind.collides_with(&b_solid.kind)
-				&& a_solid.parent != Some(b)
-				&& b_solid.parent != Some(a)
-		})
-		{
-			colliding_pairs.push((a.inner, b.inner));
-		}
-		if state.tick % 64 == 0
-		{
-			//println!("Colliding pairs: {}", colliding_pairs.len());
-		}
-
-		let mut on_contact_effects = vec![];
-		for pass in 0..5
-		{
-			for &(inner1, inner2) in &colliding_pairs
-			{
-				let id1 = inner1.entity;
-				let id2 = inner2.entity;
-				let pos1 = self.world.get::<&comps::Position>(id1)?.pos;
-				let pos2 = self.world.get::<&comps::Position>(id2)?.pos;
-
-				let solid1 = *self.world.get::<&comps::Solid>(id1)?;
-				let solid2 = *self.world.get::<&comps::Solid>(id2)?;
-
-				let diff = pos2.zx() - pos1.zx();
-				let diff_norm = utils::max(0.1, diff.norm());
-
-				if diff_norm > solid1.size + solid2.size
+				comps::AIState::Fleeing(threat) =>
 				{
-					continue;
+					let threat_pos = self
+						.world
+						.get::<&comps::Position>(threat)
+						.ok()
+						.map(|p| p.pos)
+						.filter(|p| (p - pos.pos).magnitude() < 1.5 * sense_radius);
+					target.clear(|m| to_die.push(m));
+					if let Some(threat_pos) = threat_pos
+					{
+						let away = (pos.pos - threat_pos).zx();
+						let away = if away.magnitude() > 1e-3
+						{
+							away.normalize()
+						}
+						else
+						{
+							Vector2::new(1., 0.)
+						};
+						target.waypoints.push(comps::Waypoint {
+							pos: pos.pos + 20. * Vector3::new(away.y, 0., away.x),
+							marker: None,
+						});
+					}
+					else
+					{
+						ai.state = comps::AIState::Idle;
+					}
 				}
-
-				//if solid1.collision_class.interacts() && solid2.collision_class.interacts()
-				if true
+				comps::AIState::Idle if ship_state.team == comps::Team::Civilian =>
 				{
-					let diff = 0.9 * diff * (solid1.size + solid2.size - diff_norm) / diff_norm;
-					let diff = Vector3::new(diff.y, 0., diff.x);
-
-					let f1 = 1. - solid1.mass / (solid2.mass + solid1.mass);
-					let f2 = 1. - solid2.mass / (solid2.mass + solid1.mass);
-					if f32::is_finite(f1)
+					// Run from fights, and from the player once they've turned pirate.
+					let mut threats = combatants.clone();
+					if let Some(player_pos) = player_pos.filter(|_| self.reputation < 0)
 					{
-						self.world.get::<&mut comps::Position>(id1)?.pos -= diff * f1;
+						threats.push((self.player, player_pos));
 					}
-					if f32::is_finite(f2)
+					let threat = threats
+						.iter()
+						.filter(|(threat, threat_pos)| {
+							*threat != id && (threat_pos - pos.pos).magnitude() < sense_radius
+						})
+						.min_by(|a, b| {
+							(a.1 - pos.pos)
+								.magnitude()
+								.partial_cmp(&(b.1 - pos.pos).magnitude())
+								.unwrap()
+						});
+					if let Some((threat, _)) = threat
 					{
-						self.world.get::<&mut comps::Position>(id2)?.pos += diff * f2;
+						ai.state = comps::AIState::Fleeing(*threat);
+					}
+					else if target.waypoints.is_empty()
+					{
+						let cell_id = (0..self.cells.len()).choose(&mut self.rng).unwrap();
+						let d = CELL_SIZE as f32 / 2.0;
+						let dx = self.rng.gen_range(-d..d);
+						let dy = self.rng.gen_range(-d..d);
+						target.waypoints.push(comps::Waypoint {
+							pos: self.cells[cell_id].world_center() + Vector3::new(dx, 0., dy),
+							marker: None,
+						});
 					}
 				}
-
-				if pass == 0
+				comps::AIState::Idle =>
 				{
-					for (id, other_id, pos, other_pos) in
-						[(id1, id2, pos1, pos2), (id2, id1, pos2, pos1)]
+					// The rival always picks the hunt back up.
+					if self.world.get::<&comps::Rival>(id).is_ok()
+						&& self
+							.world
+							.get::<&comps::ShipState>(self.player)
+							.map(|s| s.is_active() && s.team.is_enemy(&ship_state.team))
+							.unwrap_or(false)
 					{
-						if let Ok(on_contact_effect) = self.world.get::<&comps::OnContactEffect>(id)
-						{
-							on_contact_effects.push((
-								id,
-								other_id,
-								pos,
-								other_pos,
-								on_contact_effect.effects.clone(),
-							));
-						}
+						ai.state = comps::AIState::Hunting(self.player);
+						continue;
+					}
+					let mut entries = grid.query_rect(
+						pos.pos.zx() - Vector2::new(sense_radius, sense_radius) - center.coords,
+						pos.pos.zx() + Vector2::new(sense_radius, sense_radius) - center.coords,
+						|other| {
+							if other.inner.entity == id
+							{
+								return false;
+							}
+							if let (Ok(other_pos), Ok(other_ship_state)) = (
+								self.world.get::<&comps::Position>(other.inner.entity),
+								self.world.get::<&comps::ShipState>(other.inner.entity),
+							)
+							{
+								(pos.pos - other_pos.pos).magnitude() < sense_radius
+									&& other_ship_state.team.is_enemy(&ship_state.team)
+							}
+							else
+							{
+								false
+							}
+						},
+					);
+					if ship_state.is_boss
+					{
+						entries.clear();
+					}
+					if let Some(entry) = entries.choose(&mut self.rng)
+					{
+						ai.state = comps::AIState::Pursuing(entry.inner.entity);
+					}
+					else if target.waypoints.is_empty()
+					{
+						let cell_id = (0..self.cells.len()).choose(&mut self.rng).unwrap();
+						let d = CELL_SIZE as f32 / 2.0;
+						let dx = self.rng.gen_range(-d..d);
+						let dy = self.rng.gen_range(-d..d);
+						target.waypoints.push(comps::Waypoint {
+							pos: self.cells[cell_id].world_center() + Vector3::new(dx, 0., dy),
+							marker: None,
+						});
 					}
 				}
-			}
-		}
-
-		// On contact effects.
-		for (id, other_id, pos, other_pos, effects) in on_contact_effects
-		{
-			for effect in effects
-			{
-				match (effect, other_id)
+				comps::AIState::Pursuing(target_entity) =>
 				{
-					(comps::ContactEffect::Die, _) => to_die.push(id),
-					(comps::ContactEffect::Hurt { damage }, other_id) =>
+					if self.world.contains(target_entity)
 					{
-						let mut damage_report = None;
-						let mut disabled = None;
-						let mut destroyed = false;
-						if let (Ok(mut ship_state), Ok(ship_stats)) = (
-							self.world.get::<&mut comps::ShipState>(other_id),
-							self.world.get::<&comps::ShipStats>(other_id),
-						)
+						if self
+							.world
+							.get::<&comps::ShipState>(target_entity)
+							.map(|other_ship_state| {
+								!other_ship_state.team.is_enemy(&ship_state.team)
+							})
+							.unwrap_or(false)
 						{
-							let was_active = ship_state.is_active();
-							let was_sound = ship_state.is_structurally_sound();
-							let had_crew = ship_state.has_crew();
-							let report = ship_state.damage(
-								&damage,
-								(pos - other_pos).normalize(),
-								&mut self.rng,
-							);
-							if report.damaged
-							{
-								state.sfx.play_positional_sound(
-									"data/explosion.ogg",
-									pos.xz(),
-									self.player_pos.xz(),
-									0.5,
-								)?;
-							}
-							if report.damaged && was_active != ship_state.is_active()
+							ai.state = comps::AIState::Idle;
+						}
+						else
+						{
+							let target_pos =
+								self.world.get::<&comps::Position>(target_entity).unwrap();
+							let diff = pos.pos - target_pos.pos;
+							if diff.magnitude() < attack_radius
 							{
-								disabled = Some((ship_state.level, ship_stats.exp_bonus));
-								destroyed = !ship_state.is_structurally_sound();
+								target.clear(|m| to_die.push(m));
+								ai.state = comps::AIState::Attacking(target_entity);
 							}
-							if report.damaged && had_crew != ship_state.has_crew()
+							else if diff.magnitude() > sense_radius
 							{
-								state.sfx.play_positional_sound(
-									"data/screams.ogg",
-									pos.xz(),
-									self.player_pos.xz(),
-									0.5,
-								)?;
+								ai.state = comps::AIState::Idle;
 							}
-							if report.damaged && was_sound != ship_state.is_structurally_sound()
+							else if Some(id) != self.dock_entity
 							{
-								state.sfx.play_positional_sound(
-									"data/sink.ogg",
-									pos.xz(),
-									self.player_pos.xz(),
-									0.5,
-								)?;
+								target.clear(|m| to_die.push(m));
+								target.waypoints.push(comps::Waypoint {
+									pos: target_pos.pos,
+									marker: None,
+								})
 							}
-							damage_report = Some(report);
 						}
-						if let Some(report) = damage_report
+					}
+					else
+					{
+						ai.state = comps::AIState::Idle;
+					}
+				}
+				comps::AIState::Attacking(target_entity) =>
+				{
+					if self.world.contains(target_entity)
+					{
+						if self
+							.world
+							.get::<&comps::ShipState>(target_entity)
+							.map(|other_ship_state| {
+								!other_ship_state.team.is_enemy(&ship_state.team)
+							})
+							.unwrap_or(false)
 						{
-							if let Ok(mut ai) = self.world.get::<&mut comps::AI>(other_id)
-							{
-								if let Some(parent_id) = self
-									.world
-									.get::<&comps::Solid>(id)
-									.ok()
-									.and_then(|s| s.parent)
-								{
-									ai.state = comps::AIState::Pursuing(parent_id);
-								}
-							}
-
-							let destroy_prob = if destroyed
+							ai.state = comps::AIState::Idle;
+							equipment.want_attack = false;
+						}
+						else
+						{
+							let target_pos =
+								self.world.get::<&comps::Position>(target_entity).unwrap();
+							let diff = target_pos.pos - pos.pos;
+							// Too far to shoot.
+							if diff.magnitude() > attack_radius
 							{
-								0.75
+								ai.state = comps::AIState::Pursuing(target_entity);
+								equipment.want_attack = false;
 							}
 							else
 							{
-								report.item_destroy_chance
-							};
-							if let Ok(mut equipment) =
-								self.world.get::<&mut comps::Equipment>(other_id)
-							{
-								let derived_stats = equipment.derived_stats();
-								for slot in &mut equipment.slots
-								{
-									if self.rng.gen_bool(
-										(destroy_prob / (1. + derived_stats.item_protect)) as f64,
-									)
-									{
-										//println!("Destroyed {:?}", slot.item);
-										if !destroyed && other_id == self.player
-										{
-											if let Some(item) = slot.item.as_ref()
-											{
-												self.messages.push((
-													format!("{} destroyed!", item.kind.name()),
-													state.time(),
-												));
-											}
-										}
-										slot.item = None;
-									}
-									if disabled.is_some()
-									{
-										// Officers die.
-										if let Some(item) = slot.item.as_ref()
-										{
-											if let comps::ItemKind::Officer(_) = item.kind
-											{
-												slot.item = None;
-											}
-										}
-										if Some(other_id) == self.boss
-										{
-											self.messages
-												.push(("You are victorious!".into(), state.time()));
-											self.messages.push((format!("Voidwind has been defeated after {:.1} minutes!", (state.time() - self.start_time) / 60.), state.time()));
-											self.spawn_boss = false;
-											self.boss = None;
-										}
-									}
-								}
-							}
-						}
-						if let Some((level, exp_bonus)) = disabled
-						{
-							let parent_id = self
-								.world
-								.get::<&comps::Solid>(id)
-								.ok()
-								.and_then(|s| s.parent);
-							if let Some(mut ship_state) = parent_id
-								.and_then(|id| self.world.get::<&mut comps::ShipState>(id).ok())
-							{
-								ship_state.experience += exp_bonus * comps::enemy_experience(level);
-								//dbg!(ship_state.experience);
-								let old_level = ship_state.level;
-								ship_state.compute_level();
-								if old_level != ship_state.level && parent_id == Some(self.player)
+								if target.waypoints.is_empty() && Some(id) != self.dock_entity
 								{
-									self.messages
-										.push(("Crew got more experienced!".into(), state.time()));
+									let theta = [-PI / 3., PI / 3.].choose(&mut self.rng).unwrap();
+									let rot = Rotation2::new(*theta);
+									let new_disp = rot * diff.zx() * 2.;
+									target.waypoints.push(comps::Waypoint {
+										pos: pos.pos + Vector3::new(new_disp.y, 0., new_disp.x),
+										marker: None,
+									});
 								}
+								equipment.want_attack = true;
+								equipment.target_pos = target_pos.pos;
 							}
 						}
 					}
+					else
+					{
+						ai.state = comps::AIState::Idle;
+					}
 				}
 			}
 		}
 		timer.record(&state.core);
 
-		// Player Input
-		let player_alive = self
+		// Squalls.
+		let mut squalls = vec![];
+		let mut flashes = vec![];
+		for (_, (pos, squall)) in self
 			.world
-			.get::<&comps::ShipState>(self.player)
-			.map(|s| s.is_active())
-			.unwrap_or(false);
-		let want_move = state.controls.get_action_state(controls::Action::Move) > 0.5;
-		let want_dock = state.controls.get_action_state(controls::Action::Dock) > 0.5;
-		let want_stop = state.controls.get_action_state(controls::Action::Stop) > 0.5;
-		let want_queue = state.controls.get_action_state(controls::Action::Queue) > 0.5;
-		let want_attack = state.controls.get_action_state(controls::Action::Attack) > 0.5;
-		let want_zoom_in = state.controls.get_action_state(controls::Action::ZoomIn) > 0.5;
-		let want_zoom_out = state.controls.get_action_state(controls::Action::ZoomOut) > 0.5;
-		let want_target = state.controls.get_action_state(controls::Action::Target) > 0.5;
-
-		let mouse_in_buffer = self.mouse_in_buffer;
-		let mouse_ground_pos = self.get_mouse_ground_pos(state);
-		if mouse_in_buffer && (want_dock || want_attack || want_target)
+			.query::<(&comps::Position, &mut comps::Squall)>()
+			.iter()
 		{
-			let d = 1.;
-			let mouse_entries = grid.query_rect(
-				mouse_ground_pos.zx() - Vector2::new(d, d) - center.coords,
-				mouse_ground_pos.zx() + Vector2::new(d, d) - center.coords,
-				|_| true,
-			);
-
-			if want_target
+			squalls.push((pos.pos, squall.radius));
+			if state.time() > squall.time_to_lightning
 			{
-				self.target_entity = None;
-				state.controls.clear_action_state(controls::Action::Target);
+				squall.time_to_lightning = state.time() + self.rng.gen_range(1.0..4.0);
+				let theta = self.rng.gen_range(0.0..2. * PI);
+				let r = self.rng.gen_range(0.0..squall.radius);
+				flashes.push(pos.pos + Vector3::new(r * theta.cos(), 10., r * theta.sin()));
 			}
-			if let Some(entry) = mouse_entries.first()
+		}
+		for flash_pos in flashes
+		{
+			make_impact_flash(
+				flash_pos,
+				Color::from_rgb_f(0.7, 0.8, 1.),
+				20.,
+				&mut self.world,
+				state,
+			)?;
+			state.sfx.play_positional_sound(
+				"data/explosion.ogg",
+				flash_pos.xz(),
+				self.player_pos.xz(),
+				0.2,
+			)?;
+		}
+		let scatter_prob = utils::rate_to_prob(0.5, dt as f64);
+		for (id, (pos, ship_state, target, ai)) in self
+			.world
+			.query::<(
+				&comps::Position,
+				&mut comps::ShipState,
+				&mut comps::Target,
+				Option<&comps::AI>,
+			)>()
+			.iter()
+		{
+			for &(center, radius) in &squalls
 			{
-				if entry.inner.entity != self.player
+				let disp = (pos.pos - center).xz();
+				if disp.norm() < radius
 				{
-					if let (Ok(pos), Ok(solid), Ok(_)) = (
-						self.world.get::<&comps::Position>(entry.inner.entity),
-						self.world.get::<&comps::Solid>(entry.inner.entity),
-						self.world.get::<&comps::ShipState>(entry.inner.entity),
-					)
+					// Caught inside, the wind tears at the sails and blows the ship off course.
+					ship_state.sails = (ship_state.sails - SQUALL_SAIL_DAMAGE * dt).max(0.);
+					if self.rng.gen_bool(scatter_prob)
 					{
-						if (pos.pos - mouse_ground_pos).magnitude() < 1.5 * solid.size
+						for waypoint in &mut target.waypoints
 						{
-							self.target_entity = Some(entry.inner.entity);
+							let theta = self.rng.gen_range(0.0..2. * PI);
+							let r = self.rng.gen_range(0.0..15.);
+							waypoint.pos += Vector3::new(r * theta.cos(), 0., r * theta.sin());
+							if let Some(mut marker_pos) = waypoint
+								.marker
+								.and_then(|m| self.world.get::<&mut comps::Position>(m).ok())
+							{
+								marker_pos.pos = waypoint.pos;
+							}
 						}
 					}
 				}
+				// The AI keeps out, but the Voidwind fears no weather.
+				if ai.is_none() || ship_state.is_boss || Some(id) == self.boss
+				{
+					continue;
+				}
+				let keep_out = radius + SQUALL_MARGIN;
+				let detour = if disp.norm() < keep_out
+				{
+					let away = disp.try_normalize(1e-3).unwrap_or(Vector2::x());
+					Some(center + keep_out * Vector3::new(away.x, 0., away.y))
+				}
+				else if let Some(waypoint) = target.waypoints.first()
+				{
+					// Go around if the way there passes through.
+					let seg = (waypoint.pos - pos.pos).xz();
+					let t = utils::clamp(-disp.dot(&seg) / seg.norm_squared().max(1e-3), 0., 1.);
+					let closest = disp + t * seg;
+					if closest.norm() < keep_out
+					{
+						let side = closest
+							.try_normalize(1e-3)
+							.unwrap_or(Vector2::new(-seg.y, seg.x).normalize());
+						Some(center + 1.1 * keep_out * Vector3::new(side.x, 0., side.y))
+					}
+					else
+					{
+						None
+					}
+				}
+				else
+				{
+					None
+				};
+				if let Some(detour) = detour
+				{
+					target.waypoints.insert(
+						0,
+						comps::Waypoint {
+							pos: detour,
+							marker: None,
+						},
+					);
+				}
 			}
 		}
 
-		if want_move && mouse_in_buffer && player_alive
+		// Ship state death
+		let mut remove_ai = vec![];
+		let mut player_defeated = false;
+		let mut rival_defeated = false;
+		for (id, (target, ship_state)) in self
+			.world
+			.query_mut::<(&mut comps::Target, &mut comps::ShipState)>()
 		{
-			//state.sfx.play_sound("data/order.ogg").unwrap();
-			state.controls.clear_action_state(controls::Action::Move);
-			self.dock_entity = None;
-			let marker = make_target(mouse_ground_pos, &mut self.world, state)?;
-			let despawn;
-			if let Ok(mut target) = self.world.get::<&mut comps::Target>(self.player)
+			if !ship_state.is_active() && ship_state.team != comps::Team::Neutral
 			{
-				if !want_queue
+				if id == self.player
 				{
-					target.clear(|m| to_die.push(m));
+					player_defeated = true;
 				}
-				target.waypoints.push(comps::Waypoint {
-					pos: mouse_ground_pos,
-					marker: Some(marker),
-				});
-				despawn = false;
+				if Some(id) == self.rival.entity
+				{
+					rival_defeated = true;
+				}
+				target.clear(|m| to_die.push(m));
+				ship_state.team = comps::Team::Neutral;
+				ship_state.crew = 0;
+				ship_state.wounded = 0;
+				remove_ai.push(id);
 			}
-			else
+		}
+		if rival_defeated
+		{
+			let rival = self.rival.entity.take().unwrap();
+			self.world.remove_one::<comps::Rival>(rival).ok();
+			let name = self.rival.name.clone();
+			if self.rival.encounters >= RIVAL_FINAL_ENCOUNTER
 			{
-				despawn = true;
+				self.messages.toast(
+					message::MessageKind::Quest,
+					format!("{name} has been defeated for good!"),
+					state.time(),
+				);
+				self.journal.record(
+					journal::Event::RivalDefeated { name: name },
+					state.time(),
+					&mut self.journal_rng,
+				);
 			}
-			if despawn
+			else
 			{
-				to_die.push(marker);
+				let bark = *[
+					"You haven't seen the last of me!",
+					"I'll be back, mark my words!",
+					"This isn't over!",
+				]
+				.choose(&mut self.rng)
+				.unwrap();
+				self.add_message(
+					message::MessageKind::Quest,
+					format!("{name} escapes in a longboat: \"{bark}\""),
+					state,
+				);
+				self.journal.record(
+					journal::Event::RivalEscaped { name: name },
+					state.time(),
+					&mut self.journal_rng,
+				);
+				self.rival.encounters += 1;
+				self.rival.time_to_spawn = Some(state.time() + RIVAL_RETURN_DELAY);
 			}
 		}
-		if want_stop && player_alive
+		self.update_outcome(boss_defeated, player_defeated, state);
+		for id in remove_ai
 		{
-			state.sfx.play_sound("data/order.ogg").unwrap();
-			state.controls.clear_action_state(controls::Action::Stop);
-			if let Ok(mut target) = self.world.get::<&mut comps::Target>(self.player)
+			if id != self.player
 			{
-				target.clear(|m| to_die.push(m));
+				self.world.insert_one(id, comps::Wreck { visits: 0 }).ok();
+				if let Ok(pos) = self.world.get::<&comps::Position>(id)
+				{
+					let global_origin = Cell::cell_to_world(Point2::origin() + self.global_offset);
+					let pos = (pos.pos + global_origin.coords).xz() / CELL_SIZE as f32;
+					self.exploration.mark(pos, exploration::Landmark::Wreck);
+				}
+			}
+			// Player has no AI.
+			self.world.remove_one::<comps::AI>(id).ok();
+			if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(id)
+			{
+				equipment.want_attack = false;
 			}
 		}
-		if want_attack && mouse_in_buffer && player_alive
+
+		// Selection indicator
+		let mut target_pos = None;
+		if let Some(pos) = self
+			.target_entity
+			.and_then(|e| self.world.get::<&comps::Position>(e).ok())
+		{
+			target_pos = Some(pos.pos);
+		}
+		else
 		{
-			if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(self.player)
+			if let Some(selection_entity) = self.selection_indicator
 			{
-				equipment.want_attack = true;
-				equipment.target_pos = mouse_ground_pos;
+				to_die.push(selection_entity);
+				self.selection_indicator = None;
 			}
 		}
-		if !want_attack
+		if let Some(target_pos) = target_pos
 		{
-			if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(self.player)
+			let mut make_new = true;
+			if let Some(mut pos) = self
+				.selection_indicator
+				.and_then(|e| self.world.get::<&mut comps::Position>(e).ok())
 			{
-				equipment.want_attack = false;
+				pos.pos = target_pos;
+				make_new = false;
+			}
+			if make_new
+			{
+				self.selection_indicator =
+					Some(make_selection(target_pos, &mut self.world, state)?);
 			}
 		}
-		if want_dock && player_alive && self.target_entity != Some(self.player)
+
+		// Triggers
+		let mut spawned = vec![];
+		for (id, ship_state) in self
+			.world
+			.query::<&comps::ShipState>()
+			.with::<&comps::Spawned>()
+			.iter()
 		{
-			state.controls.clear_action_state(controls::Action::Dock);
-			self.dock_entity = None;
-			if let Some(target_entity) = self.target_entity
+			spawned.push(id);
+			self.triggers.fire(triggers::Event::Spawned {
+				boss: ship_state.is_boss,
+			});
+		}
+		for id in spawned
+		{
+			self.world.remove_one::<comps::Spawned>(id)?;
+		}
+		for effect in self.triggers.process()
+		{
+			match effect
 			{
-				let mut move_to = None;
-				let mut do_trade = false;
-				if let (
-					Ok(player_pos),
-					Ok(mut player_target),
-					Ok(player_ship_state),
-					Ok(player_solid),
-					Ok(pos),
-					Ok(_),
-					Ok(ship_state),
-					Ok(solid),
-				) = (
-					self.world.get::<&comps::Position>(self.player),
-					self.world.get::<&mut comps::Target>(self.player),
-					self.world.get::<&comps::ShipState>(self.player),
-					self.world.get::<&comps::Solid>(self.player),
-					self.world.get::<&comps::Position>(target_entity),
-					self.world.get::<&comps::Equipment>(target_entity),
-					self.world.get::<&comps::ShipState>(target_entity),
-					self.world.get::<&comps::Solid>(target_entity),
-				)
-				{
-					if ship_state.team.dock_with(&player_ship_state.team)
-					{
-						state.sfx.play_sound("data/order.ogg").unwrap();
-						if (player_pos.pos.zx() - pos.pos.zx()).magnitude()
-							< 2.0 + solid.size + player_solid.size
-						{
-							player_target.clear(|m| to_die.push(m));
-							self.dock_entity = Some(target_entity);
-							do_trade = ship_state.team.trade_with(&player_ship_state.team);
-						}
-						else
-						{
-							move_to = Some(player_pos.pos);
-						}
-					}
-				}
-				if let (Ok(mut ai), Ok(mut target), Some(move_to)) = (
-					self.world.get::<&mut comps::AI>(target_entity),
-					self.world.get::<&mut comps::Target>(target_entity),
-					move_to,
-				)
-				{
-					ai.state = comps::AIState::Pause {
-						time_to_unpause: state.time() + 1.,
-					};
-					target.clear(|m| to_die.push(m));
-					target.waypoints.push(comps::Waypoint {
-						pos: move_to,
-						marker: None,
-					});
-				}
-				if do_trade
+				triggers::Effect::Message(kind, text) => self.add_message(kind, text, state),
+				triggers::Effect::Toast(kind, text) =>
 				{
-					for entity in [self.player, self.target_entity.unwrap()]
-					{
-						if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(entity)
-						{
-							for slot in &mut equipment.slots
-							{
-								if let Some(item) = slot.item.as_mut()
-								{
-									match &mut item.kind
-									{
-										comps::ItemKind::Weapon(weapon) =>
-										{
-											item.price = round_price(
-												comps::level_effectiveness(weapon.level)
-													* (1 + weapon.prefixes.len()
-														+ weapon.suffixes.len()) as f32 * self.economy
-													[Price::Weapon as usize],
-											)
-										}
-										comps::ItemKind::Officer(officer) =>
-										{
-											item.price = round_price(
-												comps::level_effectiveness(officer.level)
-													* (1 + officer.prefixes.len()
-														+ officer.suffixes.len()) as f32 * self.economy
-													[Price::Officer as usize],
-											)
-										}
-										comps::ItemKind::Goods(level) =>
-										{
-											item.price = round_price(
-												comps::level_effectiveness(*level)
-													* self.economy[Price::Goods as usize],
-											)
-										}
-										comps::ItemKind::Tobacco(level) =>
-										{
-											item.price = round_price(
-												comps::level_effectiveness(*level)
-													* self.economy[Price::Tobacco as usize],
-											)
-										}
-										comps::ItemKind::Cotton(level) =>
-										{
-											item.price = round_price(
-												comps::level_effectiveness(*level)
-													* self.economy[Price::Cotton as usize],
-											)
-										}
-									}
-								}
-							}
-						}
-					}
+					self.messages.toast(kind, text, state.time())
 				}
 			}
 		}
-		if want_zoom_in
+
+		// Time to die
+		self.queries
+			.expired(&mut self.world, state.time(), &mut to_die);
+
+		// Remove dead entities
+		to_die.sort();
+		to_die.dedup();
+		if !to_die.is_empty()
 		{
-			self.zoom *= 1.25;
+			//println!("   Despawned: {}", to_die.len());
 		}
-		if want_zoom_out
+		for id in to_die
 		{
-			self.zoom /= 1.25;
+			//println!("died {id:?}");
+			if self.world.contains(id)
+			{
+				self.world.despawn(id)?;
+			}
 		}
-		self.zoom = utils::clamp(self.zoom, 1., 4.);
+		// Forget ships that sank or were left behind, on either end of the ledger.
+		let world = &self.world;
+		self.assists.retain(|victim, ledger| {
+			ledger.retain(|(id, _)| world.contains(*id));
+			world.contains(*victim) && !ledger.is_empty()
+		});
 
-		let mut timer = Timer::new("equipment actions", state);
-		// Equipment actions
-		let mut spawn_projectiles = vec![];
-		for (id, (pos, equipment, ship_state)) in self
+		let end_screen = match self.outcome
+		{
+			Outcome::InProgress => false,
+			Outcome::Victory { time } => state.time() - time > END_SCREEN_DELAY,
+			Outcome::Defeat { .. } => self.conceded,
+		};
+		if end_screen
+		{
+			return Ok(Some(game_state::NextScreen::End(self.run_summary(state))));
+		}
+
+		Ok(None)
+	}
+
+	// The adaptive difficulty, if it's turned on.
+	fn adaptive(&self, state: &game_state::GameState) -> Option<&difficulty::Difficulty>
+	{
+		state
+			.options
+			.adaptive_difficulty
+			.then_some(&self.difficulty)
+	}
+
+	// The adaptive difficulty for ships of a team, if it leans on them. Looked up ahead of time
+	// so it can be used while the ship states are borrowed.
+	fn hostile_difficulty(
+		&self, state: &game_state::GameState,
+	) -> impl Fn(comps::Team) -> Option<difficulty::Difficulty>
+	{
+		let player_team = self
+			.world
+			.get::<&comps::ShipState>(self.player)
+			.map(|s| s.team)
+			.ok();
+		let difficulty = self.adaptive(state).cloned();
+		move |team| {
+			difficulty
+				.clone()
+				.filter(|_| player_team.is_some_and(|player_team| team.is_enemy(&player_team)))
+		}
+	}
+
+	// Keeps tabs on known ships, and learns about the one under the glass.
+	fn intel_logic(&mut self, state: &game_state::GameState)
+	{
+		let time = state.time();
+		let target = self
+			.target_entity
+			.filter(|e| self.world.get::<&comps::AI>(*e).is_ok());
+		if self.scrutiny.map(|(e, _)| e) != target
+		{
+			self.scrutiny = target.map(|e| (e, time));
+		}
+		let global_origin = Cell::cell_to_world(Point2::origin() + self.global_offset);
+		for (id, (ai, pos, ship_desc, ship_state)) in self
 			.world
-			.query::<(&comps::Position, &mut comps::Equipment, &comps::ShipState)>()
+			.query::<(
+				&comps::AI,
+				&comps::Position,
+				&comps::ShipDesc,
+				&comps::ShipState,
+			)>()
 			.iter()
 		{
-			let derived_stats = equipment.derived_stats();
-			// No buffering
-			let want_attack = equipment.want_attack;
-			//equipment.want_attack = false;
-			for slot in &mut equipment.slots
+			if let Some((_, since)) = self.scrutiny.filter(|(e, _)| *e == id)
 			{
-				if slot.is_inventory
+				let level = if time - since > intel::IDENTIFY_TIME
 				{
-					continue;
+					intel::IntelLevel::Identified
 				}
-				if let Some(item) = slot.item.as_mut()
+				else
 				{
-					match &mut item.kind
-					{
-						comps::ItemKind::Weapon(weapon) =>
-						{
-							if weapon.readiness >= 1.0
-								&& want_attack && weapon.time_to_fire.is_none()
-							{
-								weapon.time_to_fire =
-									Some(state.time() + self.rng.gen_range(0.0..0.2));
-							}
-							if weapon
-								.time_to_fire
-								.map(|ttf| state.time() > ttf)
-								.unwrap_or(false)
-							{
-								weapon.time_to_fire = None;
-								let rot = Rotation2::new(pos.dir);
-								let slot_dir = slot.dir.unwrap_or(0.);
-								let rot_slot = Rotation2::new(slot_dir);
-								let slot_pos = pos.pos.zx() + rot * slot.pos.coords;
-								let slot_dir_vec = rot_slot * rot * Vector2::new(1., 0.);
-								let target_dir = (equipment.target_pos.zx() - slot_pos).normalize();
-								let weapon_stats = weapon.stats();
-								let arc = weapon_stats.arc;
-								let min_dot = (arc / 2.).cos();
-								let min_dot_2 = (2. * arc / 2.).cos();
+					intel::IntelLevel::Sighted
+				};
+				self.intel.learn(
+					&ai.name,
+					level,
+					&intel::class_name(&ship_desc.desc),
+					ship_state.level,
+				);
+			}
+			let pos = pos.pos + global_origin.coords;
+			self.intel
+				.seen(&ai.name, [pos.x, pos.z], ship_state.level, time);
+		}
+	}
 
-								let spawn_pos = Point3::new(slot_pos.y, 3., slot_pos.x);
-								let mut spawn_dir = None;
-								if slot_dir_vec.dot(&target_dir) > min_dot
-								{
-									spawn_dir = Some(target_dir);
-								}
-								else if slot_dir_vec.dot(&target_dir) > min_dot_2
-									&& equipment.allow_out_of_arc_shots
-								{
-									let cand_dir1 = Rotation2::new(slot_dir + arc / 2.)
-										* rot * Vector2::new(1., 0.);
-									let cand_dir2 = Rotation2::new(slot_dir - arc / 2.)
-										* rot * Vector2::new(1., 0.);
+	fn exploration_logic(&mut self)
+	{
+		self.exploration
+			.visit(Cell::world_to_cell(&self.player_pos) + self.global_offset);
+		let boss_pos = self
+			.boss
+			.and_then(|boss| self.world.get::<&comps::Position>(boss).ok().map(|p| p.pos));
+		if let Some(pos) =
+			boss_pos.filter(|pos| (pos - self.player_pos).magnitude() < CONTACT_RADIUS)
+		{
+			let global_origin = Cell::cell_to_world(Point2::origin() + self.global_offset);
+			self.exploration.mark(
+				(pos + global_origin.coords).xz() / CELL_SIZE as f32,
+				exploration::Landmark::Boss,
+			);
+		}
+	}
 
-									let cand_dir;
-									if target_dir.dot(&cand_dir1) > target_dir.dot(&cand_dir2)
-									{
-										cand_dir = cand_dir1;
-									}
-									else
-									{
-										cand_dir = cand_dir2;
-									}
+	// Trading shots tells you all about a crew.
+	fn engaged(&mut self, id: hecs::Entity)
+	{
+		if let (Ok(ai), Ok(ship_desc), Ok(ship_state)) = (
+			self.world.get::<&comps::AI>(id),
+			self.world.get::<&comps::ShipDesc>(id),
+			self.world.get::<&comps::ShipState>(id),
+		)
+		{
+			self.intel.learn(
+				&ai.name,
+				intel::IntelLevel::Engaged,
+				&intel::class_name(&ship_desc.desc),
+				ship_state.level,
+			);
+		}
+	}
 
-									spawn_dir = Some(cand_dir);
-								}
-								if let Some(spawn_dir) = spawn_dir
-								{
-									let f = 1. + derived_stats.accuracy;
-									let rot = Rotation2::new(self.rng.gen_range(
-										-weapon_stats.spread / f..=weapon_stats.spread / f,
-									));
-									let spawn_dir = rot * spawn_dir;
-									let spawn_dir =
-										Vector3::new(spawn_dir.y, 0.5, spawn_dir.x).normalize();
-									let mut weapon_stats = weapon.stats().clone();
-									weapon_stats.critical_chance *=
-										1. + derived_stats.critical_chance;
-									spawn_projectiles.push((
-										spawn_pos,
-										spawn_dir,
-										id,
-										ship_state.team,
-										weapon_stats,
-									));
-									state.sfx.play_positional_sound(
-										"data/cannon_shot.ogg",
-										spawn_pos.xz(),
-										self.player_pos.xz(),
-										0.5,
-									)?;
-									weapon.readiness = 0.;
-								}
-							}
-						}
-						_ => (),
-					}
+	fn is_player_side(&self, id: hecs::Entity) -> bool
+	{
+		id == self.player || self.world.get::<&comps::Fleet>(id).is_ok()
+	}
+
+	// Everyone who damaged the ship with their fraction of the damage, which is then forgotten.
+	fn take_shares(&mut self, victim: hecs::Entity) -> Vec<(hecs::Entity, f32)>
+	{
+		let mut shares = self.assists.remove(&victim).unwrap_or_default();
+		let total: f32 = shares.iter().map(|(_, amount)| amount).sum();
+		for (_, amount) in &mut shares
+		{
+			*amount /= total.max(1e-3);
+		}
+		shares
+	}
+
+	// The other ships that fought a prize carry off their share of her hold.
+	fn claim_loot(
+		&mut self, prize: hecs::Entity, shares: &[(hecs::Entity, f32)],
+		state: &game_state::GameState,
+	)
+	{
+		let others_share: f32 = shares
+			.iter()
+			.filter(|(id, _)| !self.is_player_side(*id))
+			.map(|(_, share)| share)
+			.sum();
+		if others_share <= 0.
+		{
+			return;
+		}
+		let mut claimed = 0;
+		if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(prize)
+		{
+			for slot in &mut equipment.slots
+			{
+				if slot.item.is_some() && self.combat_rng.gen_bool(others_share.min(1.) as f64)
+				{
+					slot.item = None;
+					claimed += 1;
 				}
 			}
 		}
-
-		for (spawn_pos, spawn_dir, parent, team, stats) in spawn_projectiles
+		if claimed > 0 && others_share < 1.
 		{
-			make_muzzle_flash(spawn_pos, &mut self.world, state)?;
-			make_projectile(
-				spawn_pos,
-				spawn_dir,
-				parent,
-				team,
-				&stats,
-				&mut self.world,
-				state,
-			)?;
+			self.messages.add(
+				message::MessageKind::Combat,
+				format!("The other ships that fought her claimed {claimed} items."),
+				state.time(),
+			);
 		}
-		timer.record(&state.core);
+	}
 
-		let mut spawn_wisps = vec![];
-		for (_, (pos, wisp_spawner)) in self
-			.world
-			.query::<(&comps::Position, &mut comps::WispSpawner)>()
-			.iter()
+	fn defeated(&self) -> bool
+	{
+		matches!(self.outcome, Outcome::Defeat { .. })
+	}
+
+	// The one place the outcome changes. Sinking the Voidwind with the last shot before going
+	// down still counts as a victory.
+	fn update_outcome(
+		&mut self, boss_defeated: bool, player_defeated: bool, state: &game_state::GameState,
+	)
+	{
+		let time = state.time();
+		let minutes = (time - self.start_time) / 60.;
+		if boss_defeated
 		{
-			if state.time() > wisp_spawner.time_to_spawn
+			self.spawn_boss = false;
+			self.boss = None;
+			self.messages.toast(
+				message::MessageKind::Quest,
+				"You are victorious!".into(),
+				time,
+			);
+			self.messages.toast(
+				message::MessageKind::Quest,
+				format!("Voidwind has been defeated after {:.1} minutes!", minutes),
+				time,
+			);
+			self.journal.record(
+				journal::Event::BossDefeated { minutes: minutes },
+				time,
+				&mut self.journal_rng,
+			);
+			self.export_journal(state);
+			if !self.spectator && self.outcome == Outcome::InProgress
 			{
-				spawn_wisps.push(pos.pos);
-				wisp_spawner.time_to_spawn = state.time() + 4.;
+				self.outcome = Outcome::Victory { time: time };
 			}
 		}
-		for pos in spawn_wisps
+		if player_defeated && !self.spectator && self.outcome == Outcome::InProgress
 		{
-			let dir = self.rng.gen_range(0.0..PI * 2.0);
-			let vel = Vector3::new(dir.cos(), 0., dir.sin()) * 5.;
-			make_wisp(pos, vel, &mut self.world, state)?;
+			self.messages.toast(
+				message::MessageKind::Combat,
+				"You've been defeated!".into(),
+				time,
+			);
+			self.journal
+				.record(journal::Event::Defeated, time, &mut self.journal_rng);
+			self.export_journal(state);
+			self.outcome = Outcome::Defeat { time: time };
 		}
+	}
 
-		// Update player pos.
-		if let Ok(pos) = self.world.get::<&comps::Position>(self.player)
+	fn run_summary(&self, state: &game_state::GameState) -> ui::RunSummary
+	{
+		let (victory, end_time) = match self.outcome
 		{
-			self.player_pos = pos.pos;
+			Outcome::Victory { time } => (true, time),
+			Outcome::Defeat { time } => (false, time),
+			Outcome::InProgress => (false, self.start_time),
+		};
+		let level = self
+			.world
+			.get::<&comps::ShipState>(self.player)
+			.map(|s| s.level)
+			.unwrap_or(1);
+		let fleet_size = self.world.query::<&comps::Fleet>().iter().count();
+		ui::RunSummary {
+			victory: victory,
+			minutes: (end_time - self.start_time) / 60.,
+			crew_level: level,
+			money: self.money,
+			ships_disabled: self.ships_disabled,
+			fleet_size: fleet_size as i32,
+			difficulty: self.adaptive(state).map(|d| d.describe()),
 		}
+	}
 
-		// Target movement.
-		for (_, (target, pos, vel, ship_state, stats, equipment)) in self
-			.world
-			.query::<(
-				&mut comps::Target,
-				&comps::Position,
-				&mut comps::Velocity,
-				&comps::ShipState,
-				&comps::ShipStats,
-				&comps::Equipment,
-			)>()
-			.iter()
+	fn input(
+		&mut self, event: &Event, state: &mut game_state::GameState,
+	) -> Result<Option<game_state::NextScreen>>
+	{
+		if self.spectator || self.defeated()
 		{
-			if target.waypoints.is_empty()
+			return Ok(None);
+		}
+		let want_select = state.controls.get_action_state(controls::Action::Select) > 0.5;
+		match *event
+		{
+			Event::MouseButtonDown {
+				button: 1, x, y, ..
+			} =>
 			{
-				vel.vel = Vector3::zeros();
-				vel.dir_vel = 0.;
-				continue;
+				if want_select && self.mouse_in_buffer
+				{
+					self.box_start = Some(Point2::new(x as f32, y as f32));
+				}
 			}
-			let waypoint = target.waypoints.first().unwrap();
-			let diff = waypoint.pos - pos.pos;
-			if diff.magnitude() < 0.1
+			Event::MouseButtonUp {
+				button: 1, x, y, ..
+			} =>
 			{
-				if target.waypoints.len() == 1
+				if let Some(start) = self.box_start.take()
 				{
-					vel.vel = Vector3::zeros();
-					vel.dir_vel = 0.;
+					let end = Point2::new(x as f32, y as f32);
+					self.box_select(start, end);
 				}
-				if let Some(marker) = waypoint.marker
+			}
+			Event::KeyDown { keycode, .. } =>
+			{
+				if let Some(group) = digit_key(keycode)
 				{
-					to_die.push(marker);
+					if want_select
+					{
+						self.control_groups[group] = self.selected.clone();
+						self.add_message(
+							message::MessageKind::System,
+							format!("Assigned {} ships to group {group}", self.selected.len()),
+							state,
+						);
+					}
+					else
+					{
+						self.selected = self.control_groups[group].clone();
+					}
 				}
-				target.waypoints.remove(0);
-				continue;
 			}
+			_ => (),
+		}
+		Ok(None)
+	}
 
-			let diff = diff.zx().normalize();
-			let rot = Rotation2::new(pos.dir);
-			let forward = rot * Vector2::new(1., 0.);
-			let left = rot * Vector2::new(0., 1.);
-
-			let speed_factor = 0.1
-				+ 0.9 * (ship_state.sails / stats.sails) * (1. + equipment.derived_stats().speed);
-
-			let dot = diff.dot(&left);
-			if dot > 0.05
+	fn box_select(&mut self, start: Point2<f32>, end: Point2<f32>)
+	{
+		let min = Point2::new(start.x.min(end.x), start.y.min(end.y));
+		let max = Point2::new(start.x.max(end.x), start.y.max(end.y));
+		let mut selected = vec![];
+		for (id, (pos, ship_state, _)) in self
+			.world
+			.query::<(&comps::Position, &comps::ShipState, &comps::Fleet)>()
+			.iter()
+		{
+			let screen_pos = self.world_to_screen(pos.pos);
+			if ship_state.is_active()
+				&& screen_pos.x > min.x
+				&& screen_pos.x < max.x
+				&& screen_pos.y > min.y
+				&& screen_pos.y < max.y
 			{
-				vel.dir_vel =
-					speed_factor * stats.dir_speed * comps::level_effectiveness(ship_state.level);
+				selected.push(id);
 			}
-			else if dot < -0.05
+		}
+		self.selected = selected;
+	}
+
+	fn has_fleet(&self) -> bool
+	{
+		self.world.query::<&comps::Fleet>().iter().next().is_some()
+	}
+
+	// The formation the fleet is holding, if any of it is holding station.
+	fn station_formation(&self) -> Option<Formation>
+	{
+		let holding = self
+			.world
+			.query::<(&comps::Station, &comps::Fleet)>()
+			.iter()
+			.next()
+			.is_some();
+		holding.then_some(self.formation)
+	}
+
+	fn set_formation(&mut self, formation: Option<Formation>, state: &game_state::GameState)
+	{
+		let fleet: Vec<_> = self
+			.world
+			.query::<&comps::Fleet>()
+			.iter()
+			.map(|(id, _)| id)
+			.collect();
+		for id in fleet
+		{
+			if formation.is_some()
 			{
-				vel.dir_vel = speed_factor * -stats.dir_speed;
+				self.world
+					.insert_one(id, comps::Station::default())
+					.unwrap();
 			}
 			else
 			{
-				vel.dir_vel = 0.;
+				self.world.remove_one::<comps::Station>(id).ok();
 			}
-			vel.vel = speed_factor * stats.speed * Vector3::new(forward.y, 0., forward.x);
 		}
+		if let Some(formation) = formation
+		{
+			self.formation = formation;
+		}
+		self.add_message(
+			message::MessageKind::System,
+			format!("Formation: {}", formation_name(formation)),
+			state,
+		);
+	}
 
-		// AI
-		let mut timer = Timer::new("ai", state);
-		for (id, (pos, target, ai, equipment, ship_state)) in self
-			.world
-			.query::<(
-				&comps::Position,
-				&mut comps::Target,
-				&mut comps::AI,
-				&mut comps::Equipment,
-				&comps::ShipState,
-			)>()
+	fn is_commandable(&self, id: hecs::Entity) -> bool
+	{
+		self.world.get::<&comps::Fleet>(id).is_ok()
+			&& self
+				.world
+				.get::<&comps::ShipState>(id)
+				.map(|s| s.is_active())
+				.unwrap_or(false)
+	}
+
+	// 1 if the price went up last time it changed, -1 if it went down, 0 if it never changed.
+	fn economy_trend(&self, commodity: Commodity) -> i32
+	{
+		for (new, old) in self
+			.economy_history
 			.iter()
+			.rev()
+			.zip(self.economy_history.iter().rev().skip(1))
 		{
-			let sense_radius = 40.;
-			let attack_radius = 20.;
-			if Some(id) == self.dock_entity
+			if new[commodity] > old[commodity] * 1.01
 			{
-				target.clear(|m| to_die.push(m));
-				continue;
+				return 1;
 			}
-			match ai.state
+			else if new[commodity] < old[commodity] * 0.99
 			{
-				comps::AIState::Pause { time_to_unpause } =>
-				{
-					if state.time() > time_to_unpause
-					{
-						ai.state = comps::AIState::Idle;
-					}
-				}
-				comps::AIState::Idle =>
+				return -1;
+			}
+		}
+		0
+	}
+
+	fn use_ability(
+		&mut self, ability: comps::Ability, state: &mut game_state::GameState,
+	) -> Result<()>
+	{
+		let time = state.time();
+		let mut message = None;
+		if let (Ok(mut ship_state), Ok(stats), Ok(mut equipment)) = (
+			self.world.get::<&mut comps::ShipState>(self.player),
+			self.world.get::<&comps::ShipStats>(self.player),
+			self.world.get::<&mut comps::Equipment>(self.player),
+		)
+		{
+			let cooldown = ship_state.ability_cooldown(ability, time);
+			if cooldown > 0.
+			{
+				message = Some(format!(
+					"{} will be ready in {}s",
+					ability.name(),
+					cooldown.ceil() as i32
+				));
+			}
+			else
+			{
+				let derived_stats = equipment.derived_stats();
+				let used = match ability
 				{
-					let mut entries = grid.query_rect(
-						pos.pos.zx() - Vector2::new(sense_radius, sense_radius) - center.coords,
-						pos.pos.zx() + Vector2::new(sense_radius, sense_radius) - center.coords,
-						|other| {
-							if other.inner.entity == id
-							{
-								return false;
-							}
-							if let (Ok(other_pos), Ok(other_ship_state)) = (
-								self.world.get::<&comps::Position>(other.inner.entity),
-								self.world.get::<&comps::ShipState>(other.inner.entity),
-							)
-							{
-								(pos.pos - other_pos.pos).magnitude() < sense_radius
-									&& other_ship_state.team.is_enemy(&ship_state.team)
-							}
-							else
-							{
-								false
-							}
-						},
-					);
-					if ship_state.is_boss
+					comps::Ability::HullPatch =>
 					{
-						entries.clear();
+						// Tear up some cargo to plug the holes.
+						let materials = equipment.slots.iter_mut().find(|slot| {
+							slot.is_inventory
+								&& matches!(
+									slot.item.as_ref().map(|item| &item.kind),
+									Some(comps::ItemKind::Goods(_))
+								)
+						});
+						if let Some(slot) = materials
+						{
+							slot.item = None;
+							ship_state.hull = (ship_state.hull
+								+ 0.3 * stats.hull * (1. + derived_stats.hull_repair))
+								.min(stats.hull);
+							true
+						}
+						else
+						{
+							message = Some("Need Goods in the hold to patch the hull".to_string());
+							false
+						}
 					}
-					if let Some(entry) = entries.choose(&mut self.rng)
+					comps::Ability::PowderSurge =>
 					{
-						ai.state = comps::AIState::Pursuing(entry.inner.entity);
+						ship_state.powder_surge = true;
+						true
 					}
-					else if target.waypoints.is_empty()
+					comps::Ability::HardToPort =>
 					{
-						let cell_id = (0..self.cells.len()).choose(&mut self.rng).unwrap();
-						let d = CELL_SIZE as f32 / 2.0;
-						let dx = self.rng.gen_range(-d..d);
-						let dy = self.rng.gen_range(-d..d);
-						target.waypoints.push(comps::Waypoint {
-							pos: self.cells[cell_id].world_center() + Vector3::new(dx, 0., dy),
-							marker: None,
-						});
+						ship_state.hard_turn_until = time + comps::HARD_TURN_DURATION;
+						true
 					}
-				}
-				comps::AIState::Pursuing(target_entity) =>
+				};
+				if used
 				{
-					if self.world.contains(target_entity)
-					{
-						if self
-							.world
-							.get::<&comps::ShipState>(target_entity)
-							.map(|other_ship_state| {
-								!other_ship_state.team.is_enemy(&ship_state.team)
-							})
-							.unwrap_or(false)
-						{
-							ai.state = comps::AIState::Idle;
-						}
-						else
-						{
-							let target_pos =
-								self.world.get::<&comps::Position>(target_entity).unwrap();
-							let diff = pos.pos - target_pos.pos;
-							if diff.magnitude() < attack_radius
-							{
-								target.clear(|m| to_die.push(m));
-								ai.state = comps::AIState::Attacking(target_entity);
-							}
-							else if diff.magnitude() > sense_radius
-							{
-								ai.state = comps::AIState::Idle;
-							}
-							else if Some(id) != self.dock_entity
-							{
-								target.clear(|m| to_die.push(m));
-								target.waypoints.push(comps::Waypoint {
-									pos: target_pos.pos,
-									marker: None,
-								})
-							}
+					ship_state.start_cooldown(ability, time, &derived_stats);
+					message = Some(format!("{}!", ability.name()));
+					state.sfx.play_sound("data/order.ogg")?;
+				}
+			}
+		}
+		if let Some(message) = message
+		{
+			self.add_message(message::MessageKind::System, message, state);
+		}
+		Ok(())
+	}
+
+	fn spawn_rival(&mut self, state: &mut game_state::GameState) -> Result<()>
+	{
+		let encounters = self.rival.encounters;
+		let theta = self.rng.gen_range(0.0..PI * 2.0);
+		let pos = self.player_pos + 100. * Vector3::new(theta.cos(), 0., theta.sin());
+		let ship = make_ship(
+			pos,
+			[
+				"data/small_ship.cfg",
+				"data/medium_ship.cfg",
+				"data/big_ship.cfg",
+			][encounters.min(2) as usize],
+			self.rival.team,
+			Cell::latitude_level(self.global_offset.y) + 2 * encounters + 1,
+			&mut self.loot_rng,
+			&mut self.world,
+			state,
+		)?;
+		self.world.insert(
+			ship,
+			(
+				comps::AI {
+					state: comps::AIState::Hunting(self.player),
+					name: self.rival.name.clone(),
+				},
+				comps::Rival,
+			),
+		)?;
+		self.rival.entity = Some(ship);
+		self.rival.sighted = false;
+
+		let bark = if encounters == RIVAL_FINAL_ENCOUNTER
+		{
+			"This ends today!"
+		}
+		else if encounters > 0
+		{
+			*[
+				"Remember me? I've got a bigger ship now!",
+				"You'll pay for what you did to me!",
+			]
+			.choose(&mut self.rng)
+			.unwrap()
+		}
+		else
+		{
+			"So you're the upstart everyone's talking about."
+		};
+		let name = self.rival.name.clone();
+		self.add_message(
+			message::MessageKind::Quest,
+			format!("{name}: \"{bark}\""),
+			state,
+		);
+		Ok(())
+	}
+
+	fn wildlife_logic(&mut self, dt: f32, state: &mut game_state::GameState) -> Result<()>
+	{
+		let gulls: Vec<_> = self
+			.world
+			.query::<(&comps::Position, &comps::Velocity, &comps::Creature)>()
+			.iter()
+			.filter(|(_, (_, _, creature))| creature.kind == comps::CreatureKind::Gull)
+			.map(|(_, (pos, vel, _))| (pos.pos, vel.vel))
+			.collect();
+		let mut prey = vec![];
+		for (_, (pos, solid, ship_state)) in self
+			.world
+			.query::<(&comps::Position, &comps::Solid, &comps::ShipState)>()
+			.iter()
+		{
+			if ship_state.is_active() && solid.size <= LEVIATHAN_PREY_SIZE
+			{
+				prey.push(pos.pos);
+			}
+		}
+
+		let mut bites = vec![];
+		let mut surfaced = vec![];
+		for (_, (pos, vel, creature)) in self
+			.world
+			.query::<(
+				&mut comps::Position,
+				&mut comps::Velocity,
+				&mut comps::Creature,
+			)>()
+			.iter()
+		{
+			let mut want_vel = match creature.kind
+			{
+				comps::CreatureKind::Gull =>
+				{
+					// Keep with the flock without crowding it, and circle back home.
+					let mut center = Vector3::zeros();
+					let mut heading = Vector3::zeros();
+					let mut push = Vector3::zeros();
+					let mut count = 0;
+					for (other_pos, other_vel) in &gulls
+					{
+						let diff = pos.pos - other_pos;
+						let dist = diff.magnitude();
+						if dist > 0. && dist < GULL_SIGHT
+						{
+							center += other_pos.coords;
+							heading += other_vel;
+							push += diff / (dist * dist);
+							count += 1;
 						}
 					}
+					let mut want_vel = vel.vel;
+					if count > 0
+					{
+						want_vel += 0.5 * (center / count as f32 - pos.pos.coords)
+							+ 0.5 * (heading / count as f32 - vel.vel)
+							+ 10. * push;
+					}
+					let to_home = creature.home - pos.pos;
+					if to_home.magnitude() > GULL_RANGE
+					{
+						want_vel += GULL_SPEED * to_home.normalize();
+					}
+					if want_vel.magnitude() < 1e-3
+					{
+						want_vel = Vector3::new(pos.dir.sin(), 0., pos.dir.cos());
+					}
+					GULL_SPEED * want_vel.normalize()
+				}
+				comps::CreatureKind::Whale =>
+				{
+					let to_home = creature.home - pos.pos;
+					if to_home.zx().magnitude() > WHALE_RANGE
+					{
+						WHALE_SPEED * Vector3::new(to_home.x, 0., to_home.z).normalize()
+					}
 					else
 					{
-						ai.state = comps::AIState::Idle;
+						WHALE_SPEED * Vector3::new(pos.dir.sin(), 0., pos.dir.cos())
 					}
 				}
-				comps::AIState::Attacking(target_entity) =>
+				comps::CreatureKind::Leviathan =>
 				{
-					if self.world.contains(target_entity)
+					let target = prey
+						.iter()
+						.filter(|prey_pos| (*prey_pos - pos.pos).zx().magnitude() < LEVIATHAN_SENSE)
+						.min_by(|a, b| {
+							let dist_a = (*a - pos.pos).zx().magnitude();
+							let dist_b = (*b - pos.pos).zx().magnitude();
+							dist_a.partial_cmp(&dist_b).unwrap()
+						});
+					match target
 					{
-						if self
-							.world
-							.get::<&comps::ShipState>(target_entity)
-							.map(|other_ship_state| {
-								!other_ship_state.team.is_enemy(&ship_state.team)
-							})
-							.unwrap_or(false)
+						Some(prey_pos) =>
 						{
-							ai.state = comps::AIState::Idle;
-							equipment.want_attack = false;
-						}
-						else
-						{
-							let target_pos =
-								self.world.get::<&comps::Position>(target_entity).unwrap();
-							let diff = target_pos.pos - pos.pos;
-							// Too far to shoot.
-							if diff.magnitude() > attack_radius
-							{
-								ai.state = comps::AIState::Pursuing(target_entity);
-								equipment.want_attack = false;
-							}
-							else
+							let diff = prey_pos - pos.pos;
+							if diff.zx().magnitude() < LEVIATHAN_BITE_RANGE
+								&& state.time() > creature.time_to_bite
 							{
-								if target.waypoints.is_empty() && Some(id) != self.dock_entity
-								{
-									let theta = [-PI / 3., PI / 3.].choose(&mut self.rng).unwrap();
-									let rot = Rotation2::new(*theta);
-									let new_disp = rot * diff.zx() * 2.;
-									target.waypoints.push(comps::Waypoint {
-										pos: pos.pos + Vector3::new(new_disp.y, 0., new_disp.x),
-										marker: None,
-									});
-								}
-								equipment.want_attack = true;
-								equipment.target_pos = target_pos.pos;
+								bites.push(*prey_pos);
+								creature.time_to_bite = state.time() + LEVIATHAN_BITE_INTERVAL;
+								// Burst out of the water to do it.
+								creature.surfaced = false;
+								creature.time_to_change = 0.;
 							}
+							LEVIATHAN_SPEED * Vector3::new(diff.x, 0., diff.z).normalize()
 						}
-					}
-					else
-					{
-						ai.state = comps::AIState::Idle;
+						None => Vector3::zeros(),
 					}
 				}
-			}
-		}
-		timer.record(&state.core);
+			};
 
-		// Ship state death
-		let mut remove_ai = vec![];
-		for (id, (target, ship_state)) in self
-			.world
-			.query_mut::<(&mut comps::Target, &mut comps::ShipState)>()
-		{
-			if !ship_state.is_active() && ship_state.team != comps::Team::Neutral
+			if creature.kind != comps::CreatureKind::Gull
 			{
-				if id == self.player
+				if state.time() > creature.time_to_change
 				{
-					self.messages
-						.push(("You've been defeated!".into(), state.time()));
+					creature.surfaced = !creature.surfaced;
+					creature.time_to_change = state.time()
+						+ if creature.surfaced
+						{
+							self.rng.gen_range(3.0..6.)
+						}
+						else
+						{
+							self.rng.gen_range(8.0..20.)
+						};
+					if creature.surfaced
+					{
+						surfaced.push((creature.kind, pos.pos));
+					}
 				}
-				target.clear(|m| to_die.push(m));
-				ship_state.team = comps::Team::Neutral;
-				ship_state.crew = 0;
-				ship_state.wounded = 0;
-				remove_ai.push(id);
+				// Most of the body stays under the water even when surfaced.
+				let depth = match (creature.surfaced, creature.kind)
+				{
+					(false, _) => DIVE_DEPTH,
+					(true, comps::CreatureKind::Leviathan) => -4.,
+					(true, _) => -2.2,
+				};
+				want_vel.y = depth - pos.pos.y;
 			}
-		}
-		for id in remove_ai
-		{
-			// Player has no AI.
-			self.world.remove_one::<comps::AI>(id).ok();
-			if let Ok(mut equipment) = self.world.get::<&mut comps::Equipment>(id)
+
+			vel.vel += utils::approach_frac(0.5, dt) * (want_vel - vel.vel);
+			if vel.vel.zx().magnitude() > 0.1
 			{
-				equipment.want_attack = false;
+				pos.dir = vel.vel.x.atan2(vel.vel.z);
 			}
 		}
 
-		// Selection indicator
-		let mut target_pos = None;
-		if let Some(pos) = self
-			.target_entity
-			.and_then(|e| self.world.get::<&comps::Position>(e).ok())
-		{
-			target_pos = Some(pos.pos);
-		}
-		else
+		let level = Cell::latitude_level(self.player_latitude());
+		for pos in bites
 		{
-			if let Some(selection_entity) = self.selection_indicator
-			{
-				to_die.push(selection_entity);
-				self.selection_indicator = None;
-			}
+			make_bite(pos, level, &mut self.world, state);
 		}
-		if let Some(target_pos) = target_pos
+		for (kind, pos) in surfaced
 		{
-			let mut make_new = true;
-			if let Some(mut pos) = self
-				.selection_indicator
-				.and_then(|e| self.world.get::<&mut comps::Position>(e).ok())
+			let radius = if kind == comps::CreatureKind::Leviathan
 			{
-				pos.pos = target_pos;
-				make_new = false;
+				8.
 			}
-			if make_new
+			else
 			{
-				self.selection_indicator =
-					Some(make_selection(target_pos, &mut self.world, state)?);
-			}
+				4.
+			};
+			make_splash(pos, radius, &mut self.world, state);
+			state.sfx.play_positional_sound(
+				if kind == comps::CreatureKind::Leviathan
+				{
+					"data/void_drone.wav"
+				}
+				else
+				{
+					"data/splash.wav"
+				},
+				pos.xz(),
+				self.player_pos.xz(),
+				0.5,
+			)?;
 		}
+		Ok(())
+	}
 
-		// Time to die
-		for (id, time_to_die) in self.world.query_mut::<&comps::TimeToDie>()
+	fn export_journal(&self, state: &game_state::GameState)
+	{
+		if self.spectator
 		{
-			if state.time() > time_to_die.time_to_die
-			{
-				to_die.push(id);
-			}
+			return;
 		}
-
-		// Remove dead entities
-		to_die.sort();
-		to_die.dedup();
-		if !to_die.is_empty()
+		if let Err(e) = game_state::save_journal(&state.core, &self.journal.to_text())
 		{
-			//println!("   Despawned: {}", to_die.len());
+			println!("Couldn't save the captain's log: {e}");
 		}
-		for id in to_die
+	}
+
+	fn water_params(&self) -> Vec<[f32; 4]>
+	{
+		let grid = 2 * CELL_RADIUS + 1;
+		let boss_pos = self
+			.boss
+			.and_then(|boss| self.world.get::<&comps::Position>(boss).ok())
+			.map(|pos| pos.pos);
+		let mut params = vec![Biome::DeepOcean.water_params(); (grid * grid) as usize];
+		for cell in &self.cells
 		{
-			//println!("died {id:?}");
-			if self.world.contains(id)
+			let idx = cell.center + Vector2::new(CELL_RADIUS, CELL_RADIUS);
+			if idx.x < 0 || idx.y < 0 || idx.x >= grid || idx.y >= grid
 			{
-				self.world.despawn(id)?;
+				continue;
+			}
+			let mut cell_params = cell.biome.water_params();
+			let (season_water, season_f) = self.season.water();
+			for (p, v) in cell_params.iter_mut().zip(season_water)
+			{
+				*p += season_f * (v - *p);
 			}
+			// The water darkens around the Voidwind, and in the storms to the south.
+			let boss_f = boss_pos
+				.map(|boss_pos| {
+					(1. - (cell.world_center() - boss_pos).magnitude() / VOID_WATER_RADIUS).max(0.)
+				})
+				.unwrap_or(0.);
+			let storm_f = Cell::southern_storm(&cell.world_center(), self.global_offset);
+			let f = boss_f.max(storm_f);
+			let void_params = [0.12, 0.02, 0.18, 1.5];
+			for (p, v) in cell_params.iter_mut().zip(void_params)
+			{
+				*p += f * (v - *p);
+			}
+			params[(idx.y * grid + idx.x) as usize] = cell_params;
 		}
-
-		Ok(None)
-	}
-
-	fn input(
-		&mut self, _event: &Event, _state: &mut game_state::GameState,
-	) -> Result<Option<game_state::NextScreen>>
-	{
-		Ok(None)
+		params
 	}
 
 	fn draw(&mut self, state: &game_state::GameState) -> Result<()>
@@ -3476,7 +12003,7 @@ impl Map
 		// Forward pass.
 
 		let project = self.make_project();
-		let camera = self.make_camera();
+		let camera = self.make_shaken_camera(state);
 		state
 			.core
 			.use_projection_transform(&utils::mat4_to_transform(project.to_homogeneous()));
@@ -3529,7 +12056,24 @@ impl Map
 			.unwrap();
 		state
 			.core
-			.set_shader_uniform("time", &[state.core.get_time() as f32][..])
+			.set_shader_uniform("time", &[(state.core.get_time() % WATER_PERIOD) as f32][..])
+			.ok();
+		// Waves are laid out in global coordinates, so they line up across recenters.
+		let water_origin = (self.global_offset * CELL_SIZE).map(|v| v.rem_euclid(WATER_WRAP));
+		state
+			.core
+			.set_shader_uniform(
+				"water_origin",
+				&[[water_origin.x as f32, water_origin.y as f32]][..],
+			)
+			.ok();
+		state
+			.core
+			.set_shader_uniform("cell_size", &[CELL_SIZE as f32][..])
+			.ok();
+		state
+			.core
+			.set_shader_uniform("water_params", &self.water_params()[..])
 			.ok();
 		state.prim.draw_prim(
 			&vtxs[..],
@@ -3544,11 +12088,43 @@ impl Map
 			.use_shader(Some(&*state.forward_shader.upgrade().unwrap()))
 			.unwrap();
 
-		for (id, (pos, mesh)) in self
-			.world
-			.query::<(&comps::Position, &comps::Mesh)>()
-			.iter()
+		let camera_pos = self.camera_pos_from(self.draw_player_pos(state));
+		// Things underwater go first, ignoring the depth of the water, and the overlays go last,
+		// ignoring the depth of everything else.
+		let mut query = self.world.query::<(
+			&comps::Position,
+			Option<&comps::PrevPosition>,
+			&comps::Mesh,
+			Option<&comps::RenderFlags>,
+			Option<&comps::Submerged>,
+		)>();
+		let entities: Vec<_> = query.iter().collect();
+		let layer = |flags: Option<&comps::RenderFlags>, submerged: Option<&comps::Submerged>| {
+			if submerged.is_some()
+			{
+				0
+			}
+			else if flags.map(|f| f.always_on_top).unwrap_or(false)
+			{
+				2
+			}
+			else
+			{
+				1
+			}
+		};
+		for (id, (pos, prev, mesh, flags, submerged)) in (0..3).flat_map(|l| {
+			entities
+				.iter()
+				.filter(move |(_, (_, _, _, f, s))| layer(*f, *s) == l)
+		})
 		{
+			let id = *id;
+			let mut pos = pos.interpolate(*prev, state.alpha);
+			if submerged.is_some()
+			{
+				pos.pos.y /= comps::WATER_REFRACTION;
+			}
 			let screen_pos =
 				(project.to_homogeneous() * camera.to_homogeneous()).transform_point(&pos.pos);
 			if screen_pos.x < -1.5
@@ -3558,12 +12134,63 @@ impl Map
 			{
 				continue;
 			}
+			let flags = flags.copied().unwrap_or_default();
+			let underwater = submerged.map(|s| 1. - s.fade()).unwrap_or(0.);
+			let fade = flags.fade((camera_pos - pos.pos).magnitude())
+				* submerged.map(|s| s.fade()).unwrap_or(1.);
+			if fade <= 0.
+			{
+				continue;
+			}
+			state.core.set_shader_uniform("fade", &[fade][..]).ok();
+			state
+				.core
+				.set_shader_uniform("unlit", &[flags.no_light as i32 as f32][..])
+				.ok();
+			state
+				.core
+				.set_shader_uniform(
+					"underwater",
+					&[
+						if submerged.is_some()
+						{
+							0.5 + 0.5 * underwater
+						}
+						else
+						{
+							0.
+						},
+					][..],
+				)
+				.ok();
+			state.core.set_depth_test(
+				if flags.always_on_top || submerged.is_some()
+				{
+					None
+				}
+				else
+				{
+					Some(DepthFunction::Less)
+				},
+			);
+			unsafe {
+				gl::DepthMask(
+					if flags.no_shadow || submerged.is_some()
+					{
+						gl::FALSE
+					}
+					else
+					{
+						gl::TRUE
+					},
+				);
+			}
 
-			let mut shift = Isometry3::new(pos.pos.coords, pos.dir * Vector3::y()).to_homogeneous();
-			if let Ok(tilt) = self.world.get::<&comps::Tilt>(id)
+			let tilt = self.world.get::<&comps::Tilt>(id).ok();
+			let mut shift = pos.to_isometry(tilt.as_deref()).to_homogeneous();
+			if let Ok(scale) = self.world.get::<&comps::Scale>(id)
 			{
-				shift = shift
-					* Rotation3::from_axis_angle(&Vector3::x_axis(), tilt.tilt).to_homogeneous();
+				shift = shift * Matrix4::new_scaling(scale.scale);
 			}
 
 			state
@@ -3581,7 +12208,11 @@ impl Map
 						unsafe {
 							gl::Disable(gl::CULL_FACE);
 						}
-						if let Ok(ship_state) = self.world.get::<&comps::ShipState>(id)
+						if let Ok(flag) = self.world.get::<&comps::Flag>(id)
+						{
+							state.get_material_bitmap(material, &flag.flag)
+						}
+						else if let Ok(ship_state) = self.world.get::<&comps::ShipState>(id)
 						{
 							let texture_name = match ship_state.team
 							{
@@ -3590,11 +12221,11 @@ impl Map
 								comps::Team::Pirate => "data/pirate_flag.png",
 								_ => texture_name,
 							};
-							state.get_bitmap(texture_name)
+							state.get_material_bitmap(material, texture_name)
 						}
 						else
 						{
-							state.get_bitmap(texture_name)
+							state.get_material_bitmap(material, texture_name)
 						}
 					}
 					else if material.name == "fire_material"
@@ -3602,14 +12233,14 @@ impl Map
 						unsafe {
 							gl::Enable(gl::CULL_FACE);
 						}
-						state.get_bitmap(texture_name)
+						state.get_material_bitmap(material, texture_name)
 					}
 					else
 					{
 						unsafe {
 							gl::Disable(gl::CULL_FACE);
 						}
-						state.get_bitmap(texture_name)
+						state.get_material_bitmap(material, texture_name)
 					}
 				};
 
@@ -3618,6 +12249,9 @@ impl Map
 				.unwrap()
 				.draw(&state.core, &state.prim, material_mapper) //|s| state.get_bitmap(s));
 		}
+		unsafe {
+			gl::DepthMask(gl::TRUE);
+		}
 
 		// Light pass.
 		state.core.set_target_bitmap(state.light_buffer.as_ref());
@@ -3660,7 +12294,7 @@ impl Map
 				&[[self.buffer_width, self.buffer_height]][..],
 			)
 			.ok(); //.unwrap();
-		let camera_pos = self.camera_pos();
+		let camera_pos = self.camera_pos_from(self.draw_player_pos(state));
 		state
 			.core
 			.set_shader_uniform(
@@ -3677,27 +12311,48 @@ impl Map
 			gl::BindTexture(gl::TEXTURE_2D, g_buffer.normal_tex);
 		}
 
-		for (_, (pos, lights)) in self
+		let daylight = calendar::Date::at(state.time() - self.start_time).daylight();
+		for (id, (pos, prev, tilt, lights)) in self
 			.world
-			.query::<(&comps::Position, &comps::Lights)>()
+			.query::<(
+				&comps::Position,
+				Option<&comps::PrevPosition>,
+				Option<&comps::Tilt>,
+				&comps::Lights,
+			)>()
 			.iter()
 		{
-			let common_shift = Isometry3::new(pos.pos.coords, pos.dir * Vector3::y());
-			for light in &lights.lights
+			let pos = pos.interpolate(prev, state.alpha);
+			// Same as the mesh, so the lanterns stay put when the ship heels over.
+			let common_shift = pos.to_isometry(tilt);
+			for (i, light) in lights.lights.iter().enumerate()
 			{
+				let phase = 0.37 * id.id() as f32 + 0.61 * i as f32;
+				let intensity =
+					light.intensity * light.animation.factor(state.time(), phase, daylight);
+				if intensity <= 0.
+				{
+					continue;
+				}
 				let shift = common_shift * Isometry3::new(light.pos.coords, Vector3::zeros());
-				let transform = Similarity3::from_isometry(shift, 20. * light.intensity.sqrt());
+				let transform = Similarity3::from_isometry(shift, 20. * intensity.sqrt());
 				let light_pos = transform.transform_point(&Point3::origin());
 
 				let screen_pos = (project.to_homogeneous() * camera.to_homogeneous())
 					.transform_point(&light_pos);
-				if screen_pos.x < -1.5
-					|| screen_pos.x > 1.5
-					|| screen_pos.y < -1.5
-					|| screen_pos.y > 1.5
+				// Spotlight beams reach well past their source.
+				let margin = if light.spot.is_some() { 3. } else { 1.5 };
+				if screen_pos.x < -margin
+					|| screen_pos.x > margin
+					|| screen_pos.y < -margin
+					|| screen_pos.y > margin
 				{
 					continue;
 				}
+				let (spot_dir, spot_cos) = light
+					.spot
+					.map(|(dir, cos)| (common_shift.transform_vector(&dir), cos))
+					.unwrap_or((Vector3::y(), -2.));
 
 				let (r, g, b) = light.color.to_rgb_f();
 
@@ -3711,8 +12366,16 @@ impl Map
 					.ok(); //.unwrap();
 				state
 					.core
-					.set_shader_uniform("light_intensity", &[light.intensity][..])
+					.set_shader_uniform("light_intensity", &[intensity][..])
 					.ok(); //.unwrap();
+				state
+					.core
+					.set_shader_uniform("light_dir", &[[spot_dir.x, spot_dir.y, spot_dir.z]][..])
+					.ok();
+				state
+					.core
+					.set_shader_uniform("light_cone", &[spot_cos][..])
+					.ok();
 
 				state.core.use_transform(&utils::mat4_to_transform(
 					camera.to_homogeneous() * transform.to_homogeneous(),
@@ -3720,7 +12383,9 @@ impl Map
 
 				if let Ok(mesh) = state.get_mesh("data/sphere.glb")
 				{
-					mesh.draw(&state.core, &state.prim, |_, s| state.get_bitmap(s));
+					mesh.draw(&state.core, &state.prim, |m, s| {
+						state.get_material_bitmap(m, s)
+					});
 				}
 			}
 		}
@@ -3779,13 +12444,37 @@ impl Map
 			.core
 			.set_shader_uniform("albedo_buffer", &[3_i32][..])
 			.ok(); //.unwrap();
-	   //state
-	   //	.core
-	   //	.set_shader_uniform(
-	   //		"camera_pos",
-	   //		&[[camera_pos[0], camera_pos[1], camera_pos[2]]][..],
-	   //	)
-	   //	.ok(); //unwrap();
+		  // The sky sickens near the Voidwind.
+		let void_tint = [0.55, 0.45, 0.75];
+		let storm_tint = [0.45, 0.45, 0.55];
+		let mut tint = self.tint;
+		for (t, s) in tint.iter_mut().zip(self.season.tint())
+		{
+			*t *= s;
+		}
+		for (tint_to, f) in [
+			(void_tint, 0.6 * self.void_omen),
+			(storm_tint, 0.7 * self.storm),
+		]
+		{
+			for (t, v) in tint.iter_mut().zip(tint_to)
+			{
+				*t += f * (v - *t);
+			}
+		}
+		state.core.set_shader_uniform("tint", &[tint][..]).ok(); //.unwrap();
+		let camera_pos = self.camera_pos();
+		state
+			.core
+			.set_shader_uniform(
+				"camera_pos",
+				&[[camera_pos.x, camera_pos.y, camera_pos.z]][..],
+			)
+			.ok(); //unwrap();
+		state
+			.core
+			.set_shader_uniform("debug_view", &[self.debug_view as i32][..])
+			.ok(); //unwrap();
 		unsafe {
 			gl::Disable(gl::CULL_FACE);
 			gl::ActiveTexture(gl::TEXTURE1);
@@ -1,5 +1,8 @@
 use crate::error::Result;
-use crate::{atlas, controls, deferred, mesh, sfx, sprite, utils};
+use crate::{
+	atlas, components, controls, curses, deferred, game, locale, mesh, net, packs, sfx, sprite, ui,
+	utils,
+};
 use allegro::*;
 use allegro_font::*;
 use allegro_image::*;
@@ -23,6 +26,49 @@ pub struct Options
 	pub vsync_method: i32,
 	pub sfx_volume: f32,
 	pub music_volume: f32,
+	// Indexed by message::MessageKind.
+	#[serde(default)]
+	pub muted_messages: [bool; 4],
+	// One of utils::TICK_RATES.
+	#[serde(default = "default_tick_rate")]
+	pub tick_rate: i32,
+	// Video adapter the window goes on.
+	#[serde(default)]
+	pub monitor: i32,
+	// Show text for important sounds.
+	#[serde(default)]
+	pub captions: bool,
+	// Picked before setting sail, remembered for the next run.
+	#[serde(default)]
+	pub curses: Vec<curses::Curse>,
+	// Host to join for co-op.
+	#[serde(default = "default_coop_address")]
+	pub coop_address: String,
+	// Where to listen when hosting co-op. Only this computer can join unless it's changed, e.g. to
+	// 0.0.0.0 for the whole network.
+	#[serde(default = "default_coop_address")]
+	pub coop_host_address: String,
+	// Picks data/locale_<language>.cfg.
+	#[serde(default = "default_language")]
+	pub language: String,
+	// Enemies get tougher or easier depending on how the voyage is going.
+	#[serde(default)]
+	pub adaptive_difficulty: bool,
+	// Stray shots hurt your own side, once they're clear of the gun.
+	#[serde(default)]
+	pub friendly_fire: bool,
+	// Scales camera shake, hit-stop and recoil, zero turns them off.
+	#[serde(default = "default_feedback_intensity")]
+	pub feedback_intensity: f32,
+	// Makes the UI text bigger, without changing the size of everything else.
+	#[serde(default = "default_ui_font_scale")]
+	pub ui_font_scale: f32,
+	// Brighter UI colors and darker backdrops.
+	#[serde(default)]
+	pub high_contrast: bool,
+	// IDs of the enabled content packs.
+	#[serde(default)]
+	pub packs: Vec<String>,
 
 	pub controls: controls::Controls,
 }
@@ -39,17 +85,61 @@ impl Default for Options
 			vsync_method: 2,
 			sfx_volume: 1.,
 			music_volume: 1.,
+			muted_messages: [false; 4],
+			tick_rate: utils::DEFAULT_TICK_RATE,
+			monitor: 0,
+			captions: false,
+			curses: vec![],
+			coop_address: default_coop_address(),
+			coop_host_address: default_coop_address(),
+			language: default_language(),
+			packs: vec![],
+			adaptive_difficulty: false,
+			friendly_fire: false,
+			feedback_intensity: default_feedback_intensity(),
+			ui_font_scale: default_ui_font_scale(),
+			high_contrast: false,
 			controls: controls::Controls::new(),
 		}
 	}
 }
 
+fn default_tick_rate() -> i32
+{
+	utils::DEFAULT_TICK_RATE
+}
+
+fn default_coop_address() -> String
+{
+	"127.0.0.1".into()
+}
+
+fn default_feedback_intensity() -> f32
+{
+	1.
+}
+
+fn default_ui_font_scale() -> f32
+{
+	1.
+}
+
+fn default_language() -> String
+{
+	"en".into()
+}
+
 #[derive(Debug)]
 pub enum NextScreen
 {
 	Game,
+	// Captain the consort in someone else's co-op voyage.
+	Consort,
+	Spectate,
 	Menu,
 	InGameMenu,
+	// The run is over, show how it went.
+	End(ui::RunSummary),
 	Quit,
 }
 
@@ -109,6 +199,29 @@ fn make_default_shader(core: &Core, disp: &mut Display) -> Result<sync::Weak<Sha
 	Ok(shader)
 }
 
+// Set from the command line, see set_profile.
+static PROFILE: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+// Keeps a separate set of options under this name, e.g. for different sets of enabled packs.
+pub fn set_profile(profile: &str)
+{
+	PROFILE.set(profile.to_string()).ok();
+}
+
+pub fn profile() -> Option<&'static str>
+{
+	PROFILE.get().map(|profile| profile.as_str())
+}
+
+fn options_file() -> String
+{
+	match profile()
+	{
+		Some(profile) => format!("options_{profile}.cfg"),
+		None => "options.cfg".to_string(),
+	}
+}
+
 pub fn load_options(core: &Core) -> Result<Options>
 {
 	let mut path_buf = path::PathBuf::new();
@@ -119,7 +232,7 @@ pub fn load_options(core: &Core) -> Result<Options>
 				.map_err(|_| "Couldn't get standard path".to_string())?,
 		);
 	}
-	path_buf.push("options.cfg");
+	path_buf.push(options_file());
 	if path_buf.exists()
 	{
 		utils::load_config(path_buf.to_str().unwrap())
@@ -141,10 +254,55 @@ pub fn save_options(core: &Core, options: &Options) -> Result<()>
 		);
 	}
 	std::fs::create_dir_all(&path_buf).map_err(|_| "Couldn't create directory".to_string())?;
-	path_buf.push("options.cfg");
+	path_buf.push(options_file());
 	utils::save_config(path_buf.to_str().unwrap(), &options)
 }
 
+// A standalone copy of the bindings, to share or bring over from another install.
+fn controls_path(core: &Core) -> Result<path::PathBuf>
+{
+	let mut path_buf = path::PathBuf::new();
+	if cfg!(feature = "use_user_settings")
+	{
+		path_buf.push(
+			core.get_standard_path(StandardPath::UserSettings)
+				.map_err(|_| "Couldn't get standard path".to_string())?,
+		);
+	}
+	std::fs::create_dir_all(&path_buf).map_err(|_| "Couldn't create directory".to_string())?;
+	path_buf.push("controls.cfg");
+	Ok(path_buf)
+}
+
+pub fn export_controls(core: &Core, controls: &controls::Controls) -> Result<String>
+{
+	let path_buf = controls_path(core)?;
+	utils::save_config(path_buf.to_str().unwrap(), controls)?;
+	Ok(path_buf.to_string_lossy().into_owned())
+}
+
+pub fn import_controls(core: &Core) -> Result<controls::Controls>
+{
+	utils::load_config(controls_path(core)?.to_str().unwrap())
+}
+
+pub fn save_journal(core: &Core, text: &str) -> Result<()>
+{
+	let mut path_buf = path::PathBuf::new();
+	if cfg!(feature = "use_user_settings")
+	{
+		path_buf.push(
+			core.get_standard_path(StandardPath::UserSettings)
+				.map_err(|_| "Couldn't get standard path".to_string())?,
+		);
+	}
+	std::fs::create_dir_all(&path_buf).map_err(|_| "Couldn't create directory".to_string())?;
+	path_buf.push("captains_log.txt");
+	std::fs::write(&path_buf, text)
+		.map_err(|_| format!("Couldn't write {}", path_buf.to_string_lossy()))?;
+	Ok(())
+}
+
 pub struct GameState
 {
 	pub core: Core,
@@ -153,20 +311,39 @@ pub struct GameState
 	pub font: FontAddon,
 	pub ttf: TtfAddon,
 	pub tick: i64,
+	// Accumulated separately from tick, since the tick rate can change mid-game.
+	pub game_time: f64,
 	pub paused: bool,
+	// How far we are between the last logic tick and the next one, for interpolation.
+	pub alpha: f32,
+	// Reset at the start of each run.
+	pub loot_filter: components::LootFilter,
+	// The curses on this run, and what they add up to.
+	pub curses: Vec<curses::Curse>,
+	pub modifiers: curses::Modifiers,
+	// Fixed voyage seed from the command line.
+	pub seed: Option<u64>,
+	// Co-op link, from the lobby until the voyage ends.
+	pub net: Option<net::Net>,
 
 	pub sfx: sfx::Sfx,
 	pub atlas: atlas::Atlas,
+	pub mesh_atlas: atlas::Atlas,
 	pub ui_font: Font,
 	pub title_font: Font,
 	//pub number_font: Font,
 	pub options: Options,
+	pub locale: locale::Locale,
+	// Everything in the packs directory, as of the last scan.
+	pub packs: Vec<packs::Pack>,
 	pub draw_scale: f32,
 	pub display_width: f32,
 	pub display_height: f32,
 	bitmaps: HashMap<String, Bitmap>,
 	sprites: HashMap<String, sprite::Sprite>,
-	meshes: HashMap<String, mesh::MultiMesh>,
+	meshes: mesh::MeshCache,
+	// Ship descs that passed validation, see `game::load_ship_desc`.
+	pub ship_descs: HashMap<String, game::ShipDesc>,
 	pub controls: controls::ControlsHandler,
 	pub track_mouse: bool,
 	pub mouse_pos: Point2<i32>,
@@ -178,6 +355,7 @@ pub struct GameState
 	pub forward_shader: sync::Weak<Shader>,
 	pub light_shader: sync::Weak<Shader>,
 	pub final_shader: sync::Weak<Shader>,
+	pub blur_shader: sync::Weak<Shader>,
 
 	pub buffer: Option<Bitmap>,
 	pub light_buffer: Option<Bitmap>,
@@ -206,6 +384,8 @@ impl GameState
 
 		let mut sfx = sfx::Sfx::new(options.sfx_volume, options.music_volume, &core)?;
 		sfx.set_music_file("data/new124.it");
+		sfx.set_captions(options.captions);
+		ui::set_high_contrast(options.high_contrast);
 		sfx.play_music()?;
 
 		let ui_font =
@@ -214,20 +394,32 @@ impl GameState
 			Font::new_builtin(&font).map_err(|_| "Could't create builtin font.".to_string())?;
 
 		let controls = controls::ControlsHandler::new(options.controls.clone());
+		let locale = locale::Locale::load(&options.language)?;
 		Ok(Self {
+			locale: locale,
+			packs: packs::scan(),
 			options: options,
 			core: core,
 			prim: prim,
 			image: image,
 			tick: 0,
+			game_time: 0.,
 			bitmaps: HashMap::new(),
 			sprites: HashMap::new(),
-			meshes: HashMap::new(),
+			meshes: mesh::MeshCache::new(),
+			ship_descs: HashMap::new(),
 			font: font,
 			ttf: ttf,
 			sfx: sfx,
 			paused: false,
+			alpha: 0.,
+			loot_filter: components::LootFilter::new(),
+			curses: vec![],
+			modifiers: curses::Modifiers::new(&[]),
+			seed: None,
+			net: None,
 			atlas: atlas::Atlas::new(512),
+			mesh_atlas: atlas::Atlas::new(1024),
 			ui_font: ui_font,
 			title_font: title_font,
 			draw_scale: 1.,
@@ -242,6 +434,7 @@ impl GameState
 			forward_shader: sync::Weak::new(),
 			light_shader: sync::Weak::new(),
 			final_shader: sync::Weak::new(),
+			blur_shader: sync::Weak::new(),
 			buffer: None,
 			light_buffer: None,
 			g_buffer: None,
@@ -264,6 +457,7 @@ impl GameState
 			make_shader(display, "data/light_vertex.glsl", "data/light_pixel.glsl")?;
 		self.final_shader =
 			make_shader(display, "data/final_vertex.glsl", "data/final_pixel.glsl")?;
+		self.blur_shader = make_shader(display, "data/blur_vertex.glsl", "data/blur_pixel.glsl")?;
 
 		self.default_shader = make_default_shader(&self.core, display)?;
 
@@ -298,17 +492,22 @@ impl GameState
 			self.display_width as i32,
 			self.display_height as i32,
 		)?);
+		self.load_fonts()
+	}
+
+	pub fn load_fonts(&mut self) -> Result<()>
+	{
+		let height = self.display_height as i32;
+		let scale = self.options.ui_font_scale.clamp(1., 1.5);
 		let ui_font = utils::load_ttf_font(
 			&self.ttf,
 			"data/LibreBaskerville-Bold.ttf",
-			display.get_height() / 45,
+			(scale * (height / 45) as f32) as i32,
 		)?;
-		let title_font = utils::load_ttf_font(
-			&self.ttf,
-			"data/LibreBaskerville-Bold.ttf",
-			display.get_height() / 24,
-		)?;
-		let m = ui_font.get_line_height() as f32;
+		let title_font =
+			utils::load_ttf_font(&self.ttf, "data/LibreBaskerville-Bold.ttf", height / 24)?;
+		// The rest of the UI is sized as if the text wasn't scaled.
+		let m = ui_font.get_line_height() as f32 / scale;
 		self.ui_font = ui_font;
 		self.title_font = title_font;
 		self.m = m;
@@ -335,12 +534,13 @@ impl GameState
 
 	fn cache_mesh<'l>(&'l mut self, name: &str) -> Result<&'l mesh::MultiMesh>
 	{
-		let mesh = match self.meshes.entry(name.to_string())
-		{
-			Entry::Occupied(o) => o.into_mut(),
-			Entry::Vacant(v) => v.insert(mesh::MultiMesh::load(name)?),
-		};
-		Ok(mesh)
+		let (core, mesh_atlas) = (&self.core, &mut self.mesh_atlas);
+		self.meshes.cache(name, |name| {
+			let mut mesh = mesh::MultiMesh::load(name)?;
+			mesh.pack_textures(core, mesh_atlas)?;
+			mesh.upload();
+			Ok(mesh)
+		})
 	}
 
 	pub fn get_bitmap<'l>(&'l self, name: &str) -> Result<&'l Bitmap>
@@ -351,6 +551,19 @@ impl GameState
 			.ok_or_else(|| format!("{name} is not cached!"))?)
 	}
 
+	// Resolves a material's texture, going through the mesh atlas if the
+	// texture got packed there.
+	pub fn get_material_bitmap<'l>(
+		&'l self, material: &mesh::Material, name: &str,
+	) -> Result<&'l Bitmap>
+	{
+		match material.atlas_page
+		{
+			Some(page) if name == material.desc.texture => Ok(&self.mesh_atlas.pages[page].bitmap),
+			_ => self.get_bitmap(name),
+		}
+	}
+
 	pub fn get_sprite<'l>(&'l self, name: &str) -> Result<&'l sprite::Sprite>
 	{
 		Ok(self
@@ -367,9 +580,19 @@ impl GameState
 			.ok_or_else(|| format!("{name} is not cached!"))?)
 	}
 
+	pub fn mesh_memory(&self) -> usize
+	{
+		self.meshes.gpu_memory()
+	}
+
 	pub fn time(&self) -> f64
 	{
-		self.tick as f64 * utils::DT as f64
+		self.game_time
+	}
+
+	pub fn dt(&self) -> f32
+	{
+		utils::dt(self.options.tick_rate)
 	}
 }
 
@@ -379,7 +602,7 @@ pub fn cache_mesh(state: &mut GameState, name: &str) -> Result<()>
 	let mut textures = vec![];
 	for mesh in &mesh.meshes
 	{
-		if let Some(material) = mesh.material.as_ref()
+		if let Some(material) = mesh.material.as_ref().filter(|m| m.atlas_page.is_none())
 		{
 			textures.push(material.desc.texture.clone());
 		}
@@ -0,0 +1,143 @@
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+// Seconds a ship has to stay targeted before the lookouts can make out who's aboard.
+pub const IDENTIFY_TIME: f64 = 2.;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum IntelLevel
+{
+	// Seen through the glass, so the class is known.
+	Sighted,
+	// Watched long enough to know the captain.
+	Identified,
+	// Traded shots with, so we know how good the crew is.
+	Engaged,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Intel
+{
+	pub level: IntelLevel,
+	pub class: String,
+	pub crew_level: i32,
+	// Global position.
+	pub last_pos: [f32; 2],
+	pub last_seen: f64,
+}
+
+impl Intel
+{
+	// What the target readout says about the ship.
+	pub fn summary(&self, name: &str) -> String
+	{
+		match self.level
+		{
+			IntelLevel::Sighted => format!("Unidentified {}", self.class),
+			IntelLevel::Identified => format!("{name}, {}", self.class),
+			IntelLevel::Engaged => format!("{name}, {} (Level {})", self.class, self.crew_level),
+		}
+	}
+}
+
+// Everything known about the ships met on the voyage, by captain. Outlives the ships themselves
+// going out of range.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct IntelRegistry
+{
+	ships: HashMap<String, Intel>,
+}
+
+impl IntelRegistry
+{
+	pub fn new() -> Self
+	{
+		Self::default()
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Intel>
+	{
+		self.ships.get(name)
+	}
+
+	// Learn at least `level` about the ship. Anything known already stays known.
+	pub fn learn(&mut self, name: &str, level: IntelLevel, class: &str, crew_level: i32)
+	{
+		let intel = self.ships.entry(name.to_string()).or_insert_with(|| Intel {
+			level: level,
+			class: class.to_string(),
+			crew_level: crew_level,
+			last_pos: [0., 0.],
+			last_seen: 0.,
+		});
+		intel.level = intel.level.max(level);
+		intel.crew_level = crew_level;
+	}
+
+	// Keeps track of a known ship, strangers are ignored.
+	pub fn seen(&mut self, name: &str, pos: [f32; 2], crew_level: i32, time: f64)
+	{
+		if let Some(intel) = self.ships.get_mut(name)
+		{
+			intel.last_pos = pos;
+			intel.last_seen = time;
+			if intel.level >= IntelLevel::Engaged
+			{
+				intel.crew_level = crew_level;
+			}
+		}
+	}
+
+	pub fn forget(&mut self, name: &str)
+	{
+		self.ships.remove(name);
+	}
+
+	pub fn ships(&self) -> impl Iterator<Item = (&String, &Intel)>
+	{
+		self.ships.iter()
+	}
+}
+
+// E.g. data/small_ship.cfg is a Small Ship.
+pub fn class_name(desc: &str) -> String
+{
+	let stem = std::path::Path::new(desc)
+		.file_stem()
+		.and_then(|stem| stem.to_str())
+		.unwrap_or("ship");
+	stem.split('_')
+		.filter(|word| !word.is_empty())
+		.map(|word| {
+			let mut chars = word.chars();
+			chars
+				.next()
+				.map(|c| c.to_uppercase().chain(chars).collect::<String>())
+				.unwrap_or_default()
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+#[test]
+fn test_intel()
+{
+	assert_eq!(class_name("data/small_ship.cfg"), "Small Ship");
+	assert_eq!(class_name("packs/test/sloop.cfg"), "Sloop");
+
+	let mut intel = IntelRegistry::new();
+	intel.seen("Blackbeard", [1., 2.], 3, 1.);
+	assert!(intel.get("Blackbeard").is_none());
+
+	intel.learn("Blackbeard", IntelLevel::Engaged, "Big Ship", 3);
+	intel.learn("Blackbeard", IntelLevel::Sighted, "Big Ship", 3);
+	intel.seen("Blackbeard", [1., 2.], 4, 1.);
+	let known = intel.get("Blackbeard").unwrap();
+	assert_eq!(known.level, IntelLevel::Engaged);
+	assert_eq!(known.crew_level, 4);
+	assert_eq!(
+		known.summary("Blackbeard"),
+		"Blackbeard, Big Ship (Level 4)"
+	);
+}
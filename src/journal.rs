@@ -0,0 +1,201 @@
+use crate::calendar;
+use rand::prelude::*;
+
+// How long an in-game day lasts, in seconds.
+pub const DAY_LENGTH: f64 = 120.;
+// Crew losses this close together get written up as one entry.
+const CREW_LOSS_GRACE: f64 = 10.;
+
+#[derive(Clone, Debug)]
+pub enum Event
+{
+	Disabled
+	{
+		name: String,
+	},
+	Sunk
+	{
+		name: String,
+	},
+	Defeated,
+	CrewLost(i32),
+	CrewExperienced(i32),
+	Traded
+	{
+		name: Option<String>,
+		profit: i32,
+	},
+	SwitchedShips,
+	LighthouseDiscovered,
+	BossDefeated
+	{
+		minutes: f64,
+	},
+	RivalEscaped
+	{
+		name: String,
+	},
+	RivalDefeated
+	{
+		name: String,
+	},
+}
+
+impl Event
+{
+	fn narrate(&self, rng: &mut impl Rng) -> String
+	{
+		match self
+		{
+			Event::Disabled { name } => [
+				format!("Engaged {name}. Her crew struck their colours."),
+				format!("Took {name} after a sharp exchange of broadsides."),
+				format!("{name} is ours. The men fought well."),
+			]
+			.choose(rng)
+			.unwrap()
+			.clone(),
+			Event::Sunk { name } => [
+				format!("Sent {name} to the bottom."),
+				format!("{name} broke apart under our guns and went down."),
+			]
+			.choose(rng)
+			.unwrap()
+			.clone(),
+			Event::Defeated => "We are beaten. These will be my last words in this log.".into(),
+			Event::CrewLost(1) => "Committed one of our men to the deep.".into(),
+			Event::CrewLost(n) => format!("Committed {n} of our men to the deep."),
+			Event::CrewExperienced(level) =>
+			{
+				format!("The crew grows seasoned. I'd put them at level {level} now.")
+			}
+			Event::Traded { name, profit } =>
+			{
+				let with = name
+					.as_ref()
+					.map(|n| format!(" with {n}"))
+					.unwrap_or_default();
+				if *profit > 0
+				{
+					format!("Traded{with} and came out £{profit} richer.")
+				}
+				else if *profit < 0
+				{
+					format!("Traded{with}. Spent £{} on supplies.", -profit)
+				}
+				else
+				{
+					format!("Exchanged goods{with}. No money changed hands.")
+				}
+			}
+			Event::SwitchedShips => "Moved my flag to a new ship.".into(),
+			Event::LighthouseDiscovered =>
+			{
+				"Sighted a lighthouse and marked it on the chart.".into()
+			}
+			Event::RivalEscaped { name } =>
+			{
+				format!("Bested {name} again, but the scoundrel slipped away in a longboat.")
+			}
+			Event::RivalDefeated { name } =>
+			{
+				format!("{name} will trouble us no longer. The feud is over.")
+			}
+			Event::BossDefeated { minutes } =>
+			{
+				format!("The Voidwind is no more, after {minutes:.1} minutes of hunting her.")
+			}
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry
+{
+	pub time: f64,
+	pub text: String,
+}
+
+impl Entry
+{
+	pub fn timestamp(&self) -> String
+	{
+		calendar::Date::at(self.time).to_string()
+	}
+}
+
+pub struct Journal
+{
+	start_time: f64,
+	entries: Vec<Entry>,
+	crew_lost: i32,
+	last_crew_loss: f64,
+}
+
+impl Journal
+{
+	pub fn new(start_time: f64) -> Self
+	{
+		Self {
+			start_time: start_time,
+			entries: vec![Entry {
+				time: 0.,
+				text: "Set sail in search of the Voidwind.".into(),
+			}],
+			crew_lost: 0,
+			last_crew_loss: 0.,
+		}
+	}
+
+	pub fn record(&mut self, event: Event, time: f64, rng: &mut impl Rng)
+	{
+		if let Event::CrewLost(n) = event
+		{
+			self.crew_lost += n;
+			self.last_crew_loss = time;
+			return;
+		}
+		self.flush(time, true, rng);
+		self.entries.push(Entry {
+			time: time - self.start_time,
+			text: event.narrate(rng),
+		});
+	}
+
+	// Writes up any losses that have been accumulating.
+	pub fn flush(&mut self, time: f64, force: bool, rng: &mut impl Rng)
+	{
+		if self.crew_lost > 0 && (force || time > self.last_crew_loss + CREW_LOSS_GRACE)
+		{
+			self.entries.push(Entry {
+				time: self.last_crew_loss - self.start_time,
+				text: Event::CrewLost(self.crew_lost).narrate(rng),
+			});
+			self.crew_lost = 0;
+		}
+	}
+
+	pub fn entries(&self) -> &[Entry]
+	{
+		&self.entries
+	}
+
+	pub fn lines(&self) -> Vec<String>
+	{
+		self.entries
+			.iter()
+			.map(|e| format!("{}: {}", e.timestamp(), e.text))
+			.collect()
+	}
+
+	pub fn to_text(&self) -> String
+	{
+		let mut text = "Captain's Log\n\n".to_string();
+		for line in self.lines()
+		{
+			text.push_str(&line);
+			text.push('\n');
+		}
+		text
+	}
+}
@@ -0,0 +1,58 @@
+use crate::error::Result;
+use crate::utils;
+
+use std::collections::HashMap;
+
+// Player-facing text by key, so it can be swapped out for another language. Templates name
+// their arguments in braces, e.g. "{name} is dear".
+#[derive(Clone, Debug, Default)]
+pub struct Locale
+{
+	strings: HashMap<String, String>,
+}
+
+impl Locale
+{
+	pub fn load(language: &str) -> Result<Self>
+	{
+		Ok(Self {
+			strings: utils::load_config(&format!("data/locale_{language}.cfg"))?,
+		})
+	}
+
+	// Missing keys come out as the key itself, so they're easy to spot.
+	pub fn text(&self, key: &str, args: &[(&str, &str)]) -> String
+	{
+		let mut text = self
+			.strings
+			.get(key)
+			.cloned()
+			.unwrap_or_else(|| key.to_string());
+		for (name, value) in args
+		{
+			text = text.replace(&format!("{{{name}}}"), value);
+		}
+		text
+	}
+}
+
+#[test]
+fn test_text()
+{
+	let mut locale = Locale::default();
+	locale
+		.strings
+		.insert("dear".into(), "{name} is dear, {name}!".into());
+	assert_eq!(
+		locale.text("dear", &[("name", "Cotton")]),
+		"Cotton is dear, Cotton!"
+	);
+	assert_eq!(locale.text("missing", &[]), "missing");
+}
+
+#[test]
+fn test_english()
+{
+	let locale = Locale::load("en").unwrap();
+	assert_eq!(locale.text("commodity_goods", &[]), "Goods");
+}
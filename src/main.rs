@@ -4,18 +4,37 @@
 
 mod astar;
 mod atlas;
+mod calendar;
 mod components;
 mod controls;
+mod curses;
+mod damage_log;
 mod deferred;
+mod difficulty;
+mod editor;
 mod error;
+mod exploration;
+mod feedback;
 mod game;
 mod game_state;
+mod intel;
+mod journal;
+mod locale;
 mod menu;
 mod mesh;
+mod message;
+mod net;
+mod packs;
+mod registry;
+mod rumors;
 mod sfx;
 mod spatial_grid;
 mod sprite;
+mod systems;
+mod triggers;
 mod ui;
+mod ui_layer;
+mod ui_script;
 mod utils;
 
 use crate::error::Result;
@@ -30,21 +49,155 @@ enum Screen
 {
 	Game(game::Game),
 	Menu(menu::Menu),
+	Editor(editor::Editor),
+}
+
+// Session overrides, handy for development and automated runs.
+#[derive(Default)]
+struct Args
+{
+	windowed: Option<(i32, i32)>,
+	seed: Option<u64>,
+	start_game: bool,
+	no_sound: bool,
+	ui_script: Option<String>,
+}
+
+fn parse_args() -> Result<Args>
+{
+	let mut args = Args::default();
+	let mut iter = std::env::args().skip(1);
+	while let Some(arg) = iter.next()
+	{
+		match arg.as_str()
+		{
+			"--windowed" =>
+			{
+				let size = iter.next().unwrap_or_default();
+				let (w, h) = size
+					.split_once('x')
+					.and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+					.ok_or_else(|| format!("Bad window size '{size}', expected WxH"))?;
+				args.windowed = Some((w, h));
+			}
+			"--seed" =>
+			{
+				// In hex, as shown at the start of a voyage.
+				let seed = iter.next().unwrap_or_default();
+				let digits = seed
+					.strip_prefix("0x")
+					.or_else(|| seed.strip_prefix("0X"))
+					.unwrap_or(&seed);
+				args.seed = Some(
+					u64::from_str_radix(digits, 16)
+						.map_err(|_| format!("Bad seed '{seed}', expected a hex number"))?,
+				);
+			}
+			"--start-game" => args.start_game = true,
+			"--no-sound" => args.no_sound = true,
+			"--profile" =>
+			{
+				let profile = iter.next().unwrap_or_default();
+				if profile.is_empty()
+					|| !profile
+						.chars()
+						.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+				{
+					return Err(format!(
+						"Bad profile '{profile}', expected letters, digits, - or _"
+					)
+					.into());
+				}
+				game_state::set_profile(&profile);
+			}
+			"--ui-script" =>
+			{
+				args.ui_script = Some(iter.next().ok_or_else(|| "Missing UI script".to_string())?);
+			}
+			// Handled before the rest, since it needs no display.
+			"--validate-data" => (),
+			_ => return Err(format!("Unknown argument '{arg}'").into()),
+		}
+	}
+	Ok(args)
+}
+
+// Puts the window on the chosen monitor, centered if it's not fullscreen.
+fn move_to_monitor(display: &Display, state: &game_state::GameState)
+{
+	let info = if let Ok(info) = state.core.get_monitor_info(state.options.monitor)
+	{
+		info
+	}
+	else
+	{
+		return;
+	};
+	// The window can't move while it covers the old monitor.
+	display.set_flag(FULLSCREEN_WINDOW, false);
+	let x = (info.x1 + info.x2 - display.get_width()) / 2;
+	let y = (info.y1 + info.y2 - display.get_height()) / 2;
+	display.set_window_position(x.max(info.x1), y.max(info.y1));
 }
 
 fn real_main() -> Result<()>
 {
 	println!("Version: {}", game_state::VERSION);
 
+	if std::env::args().any(|arg| arg == "--validate-data")
+	{
+		let valid = game::validate_data()?;
+		std::process::exit(if valid { 0 } else { 1 });
+	}
+
+	let args = parse_args()?;
+	let missing = game::missing_assets()?;
+	if !missing.is_empty()
+	{
+		let mut lines: Vec<_> = missing.iter().take(20).cloned().collect();
+		if missing.len() > lines.len()
+		{
+			lines.push(format!("...and {} more.", missing.len() - lines.len()));
+		}
+		show_native_message_box(
+			None,
+			"Missing Files",
+			"Some game files are missing or broken, try reinstalling the game.",
+			&lines.join("\n"),
+			None,
+			MESSAGEBOX_ERROR,
+		);
+		return Ok(());
+	}
+	let mut ui_script = args
+		.ui_script
+		.as_deref()
+		.map(ui_script::Script::load)
+		.transpose()?;
 	let mut state = game_state::GameState::new()?;
+	if let Some((width, height)) = args.windowed
+	{
+		state.options.fullscreen = false;
+		state.options.width = width;
+		state.options.height = height;
+	}
+	state.seed = args.seed;
+	if args.no_sound
+	{
+		state.sfx.mute();
+	}
 
-	let mut flags = OPENGL | OPENGL_3_0 | PROGRAMMABLE_PIPELINE;
+	let mut flags = OPENGL | OPENGL_3_0 | PROGRAMMABLE_PIPELINE | RESIZABLE;
 
 	if state.options.fullscreen
 	{
 		flags = flags | FULLSCREEN_WINDOW;
 	}
 	state.core.set_new_display_flags(flags);
+	if state.options.monitor < state.core.get_num_video_adapters()
+	{
+		state.core.set_new_display_adapter(state.options.monitor);
+	}
 
 	if state.options.vsync_method == 1
 	{
@@ -67,7 +220,7 @@ fn real_main() -> Result<()>
 
 	state.post_init(&mut display)?;
 
-	let timer = Timer::new(&state.core, utils::DT as f64)
+	let timer = Timer::new(&state.core, state.dt() as f64)
 		.map_err(|_| "Couldn't create timer".to_string())?;
 
 	let queue =
@@ -90,22 +243,45 @@ fn real_main() -> Result<()>
 	let mut quit = false;
 	let mut draw = true;
 
-	let mut cur_screen = Screen::Menu(menu::Menu::new(&mut state)?);
+	// Dev tool for laying out ship slots and lights.
+	let edit_ship = std::env::args()
+		.skip_while(|arg| arg != "--edit-ship")
+		.nth(1);
+	let mut cur_screen = if let Some(file) = edit_ship
+	{
+		Screen::Editor(editor::Editor::new(&file, &mut state)?)
+	}
+	else if args.start_game
+	{
+		Screen::Game(game::Game::new(&mut state)?)
+	}
+	else
+	{
+		Screen::Menu(menu::Menu::new(&mut state)?)
+	};
 	//let mut cur_screen = Screen::Game(game::Game::new(&mut state)?);
 
 	let mut logics_without_draw = 0;
 	let mut old_fullscreen = state.options.fullscreen;
+	let mut old_monitor = state.options.monitor;
+	let mut resized = false;
+	let mut old_tick_rate = state.options.tick_rate;
 
 	let mut frame_times = circular_buffer::CircularBuffer::<16, _>::new();
 	let mut logic_times = circular_buffer::CircularBuffer::<16, _>::new();
 	//state.core.grab_mouse(&display).ok();
-	//display.show_cursor(false).ok();
+	// The game draws its own cursor.
+	let mut cursor_hidden = false;
 
+	let mut last_tick_time = state.core.get_time();
+	let mut scripted_events = std::collections::VecDeque::new();
 	timer.start();
 	while !quit
 	{
 		if draw && queue.is_empty()
 		{
+			state.alpha =
+				((state.core.get_time() - last_tick_time) / state.dt() as f64).min(1.) as f32;
 			if state.display_width != display.get_width() as f32
 				|| state.display_height != display.get_height() as f32
 			{
@@ -125,8 +301,19 @@ fn real_main() -> Result<()>
 
 			match &mut cur_screen
 			{
-				Screen::Game(game) => game.draw(&state)?,
+				Screen::Game(game) =>
+				{
+					game.draw(&state)?;
+					game.draw_cursor(&state)?;
+				}
 				Screen::Menu(menu) => menu.draw(&state)?,
+				Screen::Editor(editor) => editor.draw(&state)?,
+			}
+			let in_game = matches!(cur_screen, Screen::Game(_));
+			if in_game != cursor_hidden
+			{
+				display.show_cursor(!in_game).ok();
+				cursor_hidden = in_game;
 			}
 
 			if state.options.vsync_method == 2
@@ -160,16 +347,30 @@ fn real_main() -> Result<()>
 			draw = false;
 		}
 
-		let event = queue.wait_for_event();
+		let event = if let Some(event) = scripted_events.pop_front()
+		{
+			event
+		}
+		else
+		{
+			queue.wait_for_event()
+		};
+		state.controls.track_modifiers(&event);
 		let mut next_screen = match &mut cur_screen
 		{
 			Screen::Game(game) => game.input(&event, &mut state)?,
 			Screen::Menu(menu) => menu.input(&event, &mut state)?,
+			Screen::Editor(editor) => editor.input(&event, &mut state)?,
 		};
 
 		match event
 		{
 			Event::DisplayClose { .. } => quit = true,
+			Event::DisplayResize { .. } =>
+			{
+				display.acknowledge_resize().ok();
+				resized = true;
+			}
 			Event::DisplaySwitchIn { .. } =>
 			{
 				//state.core.grab_mouse(&display).ok();
@@ -201,31 +402,73 @@ fn real_main() -> Result<()>
 					next_screen = match &mut cur_screen
 					{
 						Screen::Game(game) => game.logic(&mut state)?,
-						_ => None,
+						Screen::Menu(menu) => menu.logic(&mut state)?,
+						Screen::Editor(editor) => editor.logic(&mut state)?,
 					}
 				}
 				logic_times.push_back(state.core.get_time() - frame_start);
 
-				if old_fullscreen != state.options.fullscreen
+				if old_monitor != state.options.monitor
+				{
+					old_monitor = state.options.monitor;
+					move_to_monitor(&display, &state);
+					resized = true;
+				}
+				if old_fullscreen != state.options.fullscreen || resized
 				{
 					display.set_flag(FULLSCREEN_WINDOW, state.options.fullscreen);
 					old_fullscreen = state.options.fullscreen;
+					resized = false;
 					state.create_buffers(&mut display)?;
 
 					match &mut cur_screen
 					{
 						Screen::Game(game) => game.change_buffers(&mut state)?,
 						Screen::Menu(menu) => menu.change_buffers(&mut state)?,
+						Screen::Editor(editor) => editor.change_buffers(&mut state)?,
+					}
+				}
+
+				if old_tick_rate != state.options.tick_rate
+				{
+					old_tick_rate = state.options.tick_rate;
+					timer.set_speed(state.dt() as f64);
+				}
+
+				if let Some(script) = ui_script.as_mut()
+				{
+					let probe = match &cur_screen
+					{
+						Screen::Game(game) => game.probe(),
+						Screen::Menu(menu) => menu.probe(),
+						Screen::Editor(editor) => editor.probe(),
+					};
+					match script.tick(&probe, &state)
+					{
+						Ok(Some(events)) => scripted_events.extend(events),
+						Ok(None) =>
+						{
+							println!("UI script passed");
+							std::process::exit(0);
+						}
+						Err(e) =>
+						{
+							println!("{e}");
+							std::process::exit(1);
+						}
 					}
 				}
 
 				logics_without_draw += 1;
-				state.sfx.update_sounds()?;
+				let dt = state.dt();
+				state.sfx.update_sounds(dt)?;
 
 				if !state.paused
 				{
 					state.tick += 1;
+					state.game_time += state.dt() as f64;
 				}
+				last_tick_time = state.core.get_time();
 				draw = true;
 			}
 			_ => (),
@@ -239,10 +482,24 @@ fn real_main() -> Result<()>
 				{
 					cur_screen = Screen::Game(game::Game::new(&mut state)?);
 				}
+				game_state::NextScreen::Consort =>
+				{
+					cur_screen = Screen::Game(game::Game::new_consort(&mut state)?);
+				}
+				game_state::NextScreen::Spectate =>
+				{
+					cur_screen = Screen::Game(game::Game::new_spectator(&mut state)?);
+				}
 				game_state::NextScreen::Menu =>
 				{
+					state.net = None;
 					cur_screen = Screen::Menu(menu::Menu::new(&mut state)?);
 				}
+				game_state::NextScreen::End(summary) =>
+				{
+					state.net = None;
+					cur_screen = Screen::Menu(menu::Menu::new_end(&mut state, summary)?);
+				}
 				game_state::NextScreen::Quit =>
 				{
 					quit = true;
@@ -1,5 +1,5 @@
 use crate::error::Result;
-use crate::{components, controls, game_state, ui, utils};
+use crate::{components, controls, game, game_state, ui, ui_script, utils};
 
 use allegro::*;
 use allegro_font::*;
@@ -7,9 +7,15 @@ use allegro_sys::*;
 use nalgebra::{Matrix4, Point2};
 use rand::prelude::*;
 
+// How long the menu sits idle before a battle starts playing behind it.
+const ATTRACT_DELAY: f64 = 60.;
+
 pub struct Menu
 {
 	subscreens: Vec<ui::SubScreen>,
+	attract: Option<game::Game>,
+	title_scene: game::Game,
+	last_input_time: f64,
 }
 
 fn to_f32(pos: Point2<i32>) -> Point2<f32>
@@ -22,18 +28,78 @@ impl Menu
 	pub fn new(state: &mut game_state::GameState) -> Result<Self>
 	{
 		state.paused = false;
-
-		state.cache_bitmap("data/title.png")?;
+		state.sfx.set_muffled(false);
 
 		Ok(Self {
 			subscreens: vec![ui::SubScreen::MainMenu(ui::MainMenu::new(state))],
+			attract: None,
+			title_scene: game::Game::new_title(state)?,
+			last_input_time: state.time(),
 		})
 	}
 
+	// The menu with the run summary on top, backing out of it leads to the main menu.
+	pub fn new_end(state: &mut game_state::GameState, summary: ui::RunSummary) -> Result<Self>
+	{
+		let mut menu = Self::new(state)?;
+		menu.subscreens
+			.push(ui::SubScreen::EndScreen(ui::EndScreen::new(state, summary)));
+		Ok(menu)
+	}
+
+	pub fn logic(
+		&mut self, state: &mut game_state::GameState,
+	) -> Result<Option<game_state::NextScreen>>
+	{
+		// Leaving the lobby hangs up.
+		if self.subscreens.last().map(|s| s.name()) != Some("LobbyMenu")
+		{
+			state.net = None;
+		}
+		if let Some(net) = state.net.as_mut()
+		{
+			match net.lobby_logic()
+			{
+				Ok(true) if net.is_host => return Ok(Some(game_state::NextScreen::Game)),
+				Ok(true) => return Ok(Some(game_state::NextScreen::Consort)),
+				Ok(false) => (),
+				Err(e) =>
+				{
+					state.net = None;
+					if let Some(ui::SubScreen::LobbyMenu(lobby)) = self.subscreens.last_mut()
+					{
+						lobby.set_error(format!("Lost the connection: {}", e.short()));
+					}
+				}
+			}
+		}
+		if self.attract.is_none() && state.time() - self.last_input_time > ATTRACT_DELAY
+		{
+			self.attract = Some(game::Game::new_attract(state)?);
+		}
+		if let Some(attract) = self.attract.as_mut()
+		{
+			attract.logic(state)?;
+		}
+		else
+		{
+			self.title_scene.logic(state)?;
+		}
+		Ok(None)
+	}
+
 	pub fn input(
 		&mut self, event: &Event, state: &mut game_state::GameState,
 	) -> Result<Option<game_state::NextScreen>>
 	{
+		match *event
+		{
+			Event::KeyDown { .. } | Event::MouseButtonDown { .. } =>
+			{
+				self.last_input_time = state.time();
+			}
+			_ => (),
+		}
 		match *event
 		{
 			Event::MouseAxes { x, y, .. } =>
@@ -66,6 +132,7 @@ impl Menu
 					self.subscreens.push(subscreen_fn(state));
 				}
 				ui::Action::Start => return Ok(Some(game_state::NextScreen::Game)),
+				ui::Action::Spectate => return Ok(Some(game_state::NextScreen::Spectate)),
 				ui::Action::Quit => return Ok(Some(game_state::NextScreen::Quit)),
 				ui::Action::Back =>
 				{
@@ -80,23 +147,34 @@ impl Menu
 	pub fn draw(&mut self, state: &game_state::GameState) -> Result<()>
 	{
 		state.core.clear_to_color(Color::from_rgb_f(0., 0., 0.));
-		let bmp = state.get_bitmap("data/title.png")?;
 		let dw = state.display_width;
 		let dh = state.display_height;
-		state.core.draw_bitmap(
-			bmp,
-			dw / 2. - bmp.get_width() as f32 / 2.,
-			dh / 2. - bmp.get_height() as f32 / 2.,
-			Flag::zero(),
-		);
-		state.core.draw_text(
-			&state.title_font,
-			Color::from_rgb_f(0.4, 0.1, 0.5),
-			dw / 2.,
-			dh / 4.,
-			FontAlign::Centre,
-			"Voidwind",
-		);
+		if let Some(attract) = self.attract.as_mut()
+		{
+			attract.draw(state)?;
+			state
+				.prim
+				.draw_filled_rectangle(0., 0., dw, dh, ui::backdrop(0.5));
+		}
+		else
+		{
+			self.title_scene.draw(state)?;
+			state
+				.prim
+				.draw_filled_rectangle(0., 0., dw, dh, ui::backdrop(0.3));
+		}
+		// The end screen has its own title.
+		if !matches!(self.subscreens.last(), Some(ui::SubScreen::EndScreen(_)))
+		{
+			state.core.draw_text(
+				&state.title_font,
+				Color::from_rgb_f(0.4, 0.1, 0.5),
+				dw / 2.,
+				dh / 4.,
+				FontAlign::Centre,
+				"Voidwind",
+			);
+		}
 		state.core.draw_text(
 			&state.ui_font,
 			ui::ui_color(),
@@ -109,8 +187,20 @@ impl Menu
 		Ok(())
 	}
 
+	pub fn probe(&self) -> ui_script::Probe
+	{
+		ui_script::Probe {
+			screen: "Menu",
+			subscreen: self.subscreens.last().map(|s| s.name()),
+			dragging: false,
+		}
+	}
+
 	pub fn change_buffers(&mut self, state: &mut game_state::GameState) -> Result<()>
 	{
+		self.attract = None;
+		self.title_scene = game::Game::new_title(state)?;
+		self.last_input_time = state.time();
 		self.subscreens
 			.push(ui::SubScreen::MainMenu(ui::MainMenu::new(state)));
 		self.subscreens
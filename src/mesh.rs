@@ -1,15 +1,209 @@
 use crate::error::Result;
-use crate::utils;
+use crate::{atlas, utils};
+use nalgebra::{Point2, Point3};
 use serde_derive::{Deserialize, Serialize};
+use slr_config::ConfigElement;
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+use std::path;
+use std::ptr;
+use std::rc::Rc;
 
 use allegro::*;
 use allegro_primitives::*;
+use allegro_sys::ALLEGRO_BITMAP;
+
+// allegro_primitives doesn't wrap the vertex/index buffer API, so bind it directly.
+#[repr(C)]
+struct VertexElement
+{
+	attribute: c_int,
+	storage: c_int,
+	offset: c_int,
+}
+
+const PRIM_POSITION: c_int = 1;
+const PRIM_COLOR_ATTR: c_int = 2;
+const PRIM_TEX_COORD: c_int = 3;
+const PRIM_USER_ATTR: c_int = 5;
+const PRIM_FLOAT_2: c_int = 0;
+const PRIM_FLOAT_3: c_int = 1;
+const PRIM_TRIANGLE_LIST: c_int = 3;
+const PRIM_BUFFER_STATIC: c_int = 0x02;
+
+extern "C" {
+	fn al_create_vertex_decl(elements: *const VertexElement, stride: c_int) -> *mut c_void;
+	fn al_destroy_vertex_decl(decl: *mut c_void);
+	fn al_create_vertex_buffer(
+		decl: *mut c_void, initial_data: *const c_void, num_vertices: c_int, flags: c_int,
+	) -> *mut c_void;
+	fn al_destroy_vertex_buffer(buffer: *mut c_void);
+	fn al_create_index_buffer(
+		index_size: c_int, initial_data: *const c_void, num_indices: c_int, flags: c_int,
+	) -> *mut c_void;
+	fn al_destroy_index_buffer(buffer: *mut c_void);
+	fn al_draw_indexed_buffer(
+		vertex_buffer: *mut c_void, texture: *mut ALLEGRO_BITMAP, index_buffer: *mut c_void,
+		start: c_int, end: c_int, prim_type: c_int,
+	) -> c_int;
+}
+
+// GPU-side copy of a mesh. Creating these can fail on drivers without buffer
+// support, in which case the mesh is streamed via draw_indexed_prim instead.
+#[derive(Debug)]
+pub struct MeshBuffers
+{
+	decl: *mut c_void,
+	vertex_buffer: *mut c_void,
+	index_buffer: *mut c_void,
+	num_idxs: i32,
+}
+
+impl MeshBuffers
+{
+	fn new(vtxs: &[NormVertex], idxs: &[i32]) -> Option<Self>
+	{
+		if vtxs.is_empty() || idxs.is_empty()
+		{
+			return None;
+		}
+		let elements = [
+			VertexElement {
+				attribute: PRIM_POSITION,
+				storage: PRIM_FLOAT_3,
+				offset: memoffset::offset_of!(NormVertex, x) as c_int,
+			},
+			VertexElement {
+				attribute: PRIM_TEX_COORD,
+				storage: PRIM_FLOAT_2,
+				offset: memoffset::offset_of!(NormVertex, u) as c_int,
+			},
+			VertexElement {
+				attribute: PRIM_COLOR_ATTR,
+				storage: 0,
+				offset: memoffset::offset_of!(NormVertex, color) as c_int,
+			},
+			VertexElement {
+				attribute: PRIM_USER_ATTR,
+				storage: PRIM_FLOAT_3,
+				offset: memoffset::offset_of!(NormVertex, nx) as c_int,
+			},
+			VertexElement {
+				attribute: 0,
+				storage: 0,
+				offset: 0,
+			},
+		];
+		unsafe {
+			let mut buffers = MeshBuffers {
+				decl: al_create_vertex_decl(
+					elements.as_ptr(),
+					std::mem::size_of::<NormVertex>() as c_int,
+				),
+				vertex_buffer: ptr::null_mut(),
+				index_buffer: ptr::null_mut(),
+				num_idxs: idxs.len() as i32,
+			};
+			if buffers.decl.is_null()
+			{
+				return None;
+			}
+			buffers.vertex_buffer = al_create_vertex_buffer(
+				buffers.decl,
+				vtxs.as_ptr() as *const c_void,
+				vtxs.len() as c_int,
+				PRIM_BUFFER_STATIC,
+			);
+			if buffers.vertex_buffer.is_null()
+			{
+				return None;
+			}
+			buffers.index_buffer = al_create_index_buffer(
+				std::mem::size_of::<i32>() as c_int,
+				idxs.as_ptr() as *const c_void,
+				idxs.len() as c_int,
+				PRIM_BUFFER_STATIC,
+			);
+			if buffers.index_buffer.is_null()
+			{
+				return None;
+			}
+			Some(buffers)
+		}
+	}
+
+	fn draw(&self, texture: Option<&Bitmap>)
+	{
+		unsafe {
+			al_draw_indexed_buffer(
+				self.vertex_buffer,
+				texture.map_or(ptr::null_mut(), |t| t.get_allegro_bitmap()),
+				self.index_buffer,
+				0,
+				self.num_idxs,
+				PRIM_TRIANGLE_LIST,
+			);
+		}
+	}
+}
+
+impl Drop for MeshBuffers
+{
+	fn drop(&mut self)
+	{
+		unsafe {
+			if !self.index_buffer.is_null()
+			{
+				al_destroy_index_buffer(self.index_buffer);
+			}
+			if !self.vertex_buffer.is_null()
+			{
+				al_destroy_vertex_buffer(self.vertex_buffer);
+			}
+			if !self.decl.is_null()
+			{
+				al_destroy_vertex_decl(self.decl);
+			}
+		}
+	}
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MaterialDesc
 {
 	pub texture: String,
 	pub material_id: i32,
+	#[serde(default = "default_atlas")]
+	pub atlas: bool,
+}
+
+fn default_atlas() -> bool
+{
+	true
+}
+
+// Material textures up to this size get packed into the mesh atlas.
+const MAX_ATLAS_TEXTURE_SIZE: i32 = 256;
+
+fn validate_material_desc<'l>(
+	desc: &MaterialDesc, element: &'l ConfigElement,
+) -> Vec<(&'l ConfigElement, String)>
+{
+	let mut problems = vec![];
+	if !path::Path::new(&desc.texture).exists()
+	{
+		problems.push((
+			utils::config_field(element, &["texture"]),
+			format!("texture '{}' does not exist", desc.texture),
+		));
+	}
+	problems
+}
+
+pub fn validate_material(material: &str) -> Result<()>
+{
+	utils::load_validated_config::<MaterialDesc, _>(material, validate_material_desc)?;
+	Ok(())
 }
 
 #[derive(Debug, Clone)]
@@ -17,6 +211,7 @@ pub struct Material
 {
 	pub name: String,
 	pub desc: MaterialDesc,
+	pub atlas_page: Option<usize>,
 }
 
 #[derive(Clone, Debug)]
@@ -25,6 +220,39 @@ pub struct Mesh
 	pub vtxs: Vec<NormVertex>,
 	pub idxs: Vec<i32>,
 	pub material: Option<Material>,
+	pub buffers: Option<Rc<MeshBuffers>>,
+}
+
+impl Mesh
+{
+	fn pack_texture(&mut self, core: &Core, atlas: &mut atlas::Atlas) -> Result<()>
+	{
+		let material = match self.material.as_mut()
+		{
+			Some(material) if material.desc.atlas && material.atlas_page.is_none() => material,
+			_ => return Ok(()),
+		};
+		// Tiled UVs can't be remapped into a sub-rectangle.
+		if !self
+			.vtxs
+			.iter()
+			.all(|vtx| (0.0..=1.).contains(&vtx.u) && (0.0..=1.).contains(&vtx.v))
+		{
+			return Ok(());
+		}
+		if let Some(atlas_bmp) =
+			atlas.cache_from_file(core, &material.desc.texture, MAX_ATLAS_TEXTURE_SIZE)?
+		{
+			let page_size = atlas.page_size() as f32;
+			for vtx in &mut self.vtxs
+			{
+				vtx.u = (atlas_bmp.start.x + vtx.u * atlas_bmp.width()) / page_size;
+				vtx.v = (atlas_bmp.start.y + vtx.v * atlas_bmp.height()) / page_size;
+			}
+			material.atlas_page = Some(atlas_bmp.page);
+		}
+		Ok(())
+	}
 }
 
 #[derive(Clone, Debug)]
@@ -88,7 +316,10 @@ impl MultiMesh
 						.map(|name| {
 							(
 								name.to_string(),
-								utils::load_config(&format!("data/{}.cfg", name)),
+								utils::load_validated_config(
+									&format!("data/{}.cfg", name),
+									validate_material_desc,
+								),
 							)
 						})
 						.map_or(Ok(None), |(name, desc)| {
@@ -96,6 +327,7 @@ impl MultiMesh
 								Some(Material {
 									name: name,
 									desc: desc,
+									atlas_page: None,
 								})
 							})
 						})?;
@@ -103,6 +335,7 @@ impl MultiMesh
 						vtxs: vtxs,
 						idxs: idxs,
 						material: material,
+						buffers: None,
 					});
 				}
 			}
@@ -110,6 +343,59 @@ impl MultiMesh
 		Ok(Self { meshes: meshes })
 	}
 
+	// Moves small material textures into the atlas, so that meshes sharing a
+	// page can be drawn without rebinding textures. Must happen before upload.
+	pub fn pack_textures(&mut self, core: &Core, atlas: &mut atlas::Atlas) -> Result<()>
+	{
+		for mesh in &mut self.meshes
+		{
+			mesh.pack_texture(core, atlas)?;
+		}
+		Ok(())
+	}
+
+	// Needs a display, so it's kept separate from loading (--validate-data loads without one).
+	pub fn upload(&mut self)
+	{
+		for mesh in &mut self.meshes
+		{
+			mesh.buffers = MeshBuffers::new(&mesh.vtxs, &mesh.idxs).map(Rc::new);
+		}
+	}
+
+	pub fn bounds(&self) -> (Point3<f32>, Point3<f32>)
+	{
+		let mut min = Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+		let mut max = Point3::new(-f32::INFINITY, -f32::INFINITY, -f32::INFINITY);
+		for mesh in &self.meshes
+		{
+			for vtx in &mesh.vtxs
+			{
+				min = Point3::new(min.x.min(vtx.x), min.y.min(vtx.y), min.z.min(vtx.z));
+				max = Point3::new(max.x.max(vtx.x), max.y.max(vtx.y), max.z.max(vtx.z));
+			}
+		}
+		(min, max)
+	}
+
+	// Triangles as seen from above, in the same (z, x) plane as the ship slots.
+	pub fn top_down_triangles(&self) -> Vec<[Point2<f32>; 3]>
+	{
+		let mut triangles = vec![];
+		for mesh in &self.meshes
+		{
+			for tri in mesh.idxs.chunks_exact(3)
+			{
+				let vtx = |i: i32| {
+					let vtx = &mesh.vtxs[i as usize];
+					Point2::new(vtx.z, vtx.x)
+				};
+				triangles.push([vtx(tri[0]), vtx(tri[1]), vtx(tri[2])]);
+			}
+		}
+		triangles
+	}
+
 	pub fn draw<'l, T: Fn(&Material, &str) -> Result<&'l Bitmap>>(
 		&self, core: &Core, prim: &PrimitivesAddon, bitmap_fn: T,
 	)
@@ -125,18 +411,89 @@ impl MultiMesh
 					.unwrap_or(0.)][..],
 			)
 			.ok();
-			prim.draw_indexed_prim(
-				&mesh.vtxs[..],
-				mesh.material
-					.as_ref()
-					.and_then(|m| Some(bitmap_fn(&m, &m.desc.texture).unwrap())),
-				&mesh.idxs[..],
-				0,
-				mesh.idxs.len() as u32,
-				PrimType::TriangleList,
-			);
+			let texture = mesh
+				.material
+				.as_ref()
+				.and_then(|m| Some(bitmap_fn(&m, &m.desc.texture).unwrap()));
+			if let Some(buffers) = mesh.buffers.as_ref()
+			{
+				buffers.draw(texture);
+			}
+			else
+			{
+				prim.draw_indexed_prim(
+					&mesh.vtxs[..],
+					texture,
+					&mesh.idxs[..],
+					0,
+					mesh.idxs.len() as u32,
+					PrimType::TriangleList,
+				);
+			}
+		}
+	}
+}
+
+// Loaded meshes by the file they came from, so descs that spell the path differently still share
+// the vertex data and GPU buffers.
+pub struct MeshCache
+{
+	files: HashMap<path::PathBuf, Rc<MultiMesh>>,
+	names: HashMap<String, Rc<MultiMesh>>,
+}
+
+impl MeshCache
+{
+	pub fn new() -> Self
+	{
+		Self {
+			files: HashMap::new(),
+			names: HashMap::new(),
 		}
 	}
+
+	pub fn get(&self, name: &str) -> Option<&MultiMesh>
+	{
+		self.names.get(name).map(|mesh| &**mesh)
+	}
+
+	pub fn cache<F: FnOnce(&str) -> Result<MultiMesh>>(
+		&mut self, name: &str, load: F,
+	) -> Result<&MultiMesh>
+	{
+		if !self.names.contains_key(name)
+		{
+			let file = path::Path::new(name)
+				.canonicalize()
+				.unwrap_or_else(|_| name.into());
+			let mesh = match self.files.get(&file)
+			{
+				Some(mesh) => mesh.clone(),
+				None =>
+				{
+					let mesh = Rc::new(load(name)?);
+					self.files.insert(file, mesh.clone());
+					mesh
+				}
+			};
+			self.names.insert(name.to_string(), mesh);
+		}
+		Ok(&self.names[name])
+	}
+
+	// Bytes in the vertex and index buffers of everything uploaded.
+	pub fn gpu_memory(&self) -> usize
+	{
+		self.files
+			.values()
+			.flat_map(|multi_mesh| multi_mesh.meshes.iter())
+			.filter(|mesh| mesh.buffers.is_some())
+			.map(|mesh| {
+				mesh.vtxs.len() * std::mem::size_of::<NormVertex>()
+					+ mesh.idxs.len() * std::mem::size_of::<i32>()
+			})
+			.sum()
+	}
 }
 
 #[derive(Clone, Debug)]
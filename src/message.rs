@@ -0,0 +1,243 @@
+use crate::utils::ColorExt;
+use crate::{game_state, ui};
+
+use allegro::*;
+use allegro_font::*;
+use allegro_primitives::*;
+use serde_derive::Deserialize;
+
+// How long a toast stays up once it reaches the front of the queue.
+pub const TOAST_DURATION: f64 = 4.;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum MessageKind
+{
+	Combat,
+	Trade,
+	Quest,
+	System,
+}
+
+pub const MESSAGE_KINDS: [MessageKind; 4] = [
+	MessageKind::Combat,
+	MessageKind::Trade,
+	MessageKind::Quest,
+	MessageKind::System,
+];
+
+impl MessageKind
+{
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			MessageKind::Combat => "Combat",
+			MessageKind::Trade => "Trade",
+			MessageKind::Quest => "Quest",
+			MessageKind::System => "System",
+		}
+	}
+
+	pub fn color(&self) -> Color
+	{
+		match self
+		{
+			MessageKind::Combat => Color::from_rgb_f(1., 0.5, 0.4),
+			MessageKind::Trade => Color::from_rgb_f(1., 0.85, 0.3),
+			MessageKind::Quest => Color::from_rgb_f(0.8, 0.5, 1.),
+			MessageKind::System => Color::from_rgb_f(0.6, 0.8, 0.9),
+		}
+	}
+
+	// Seconds.
+	pub fn duration(&self) -> f64
+	{
+		match self
+		{
+			MessageKind::Combat => 6.,
+			MessageKind::Trade => 8.,
+			MessageKind::Quest => 12.,
+			MessageKind::System => 10.,
+		}
+	}
+
+	pub fn draw_icon(&self, x: f32, y: f32, size: f32, color: Color, state: &game_state::GameState)
+	{
+		let s = size / 2.;
+		match self
+		{
+			MessageKind::Combat =>
+			{
+				// Crossed swords, more or less.
+				state.prim.draw_line(x - s, y - s, x + s, y + s, color, 2.);
+				state.prim.draw_line(x - s, y + s, x + s, y - s, color, 2.);
+			}
+			MessageKind::Trade =>
+			{
+				state.prim.draw_circle(x, y, s, color, 2.);
+				state.prim.draw_filled_circle(x, y, s / 3., color);
+			}
+			MessageKind::Quest =>
+			{
+				state
+					.prim
+					.draw_filled_triangle(x, y - s, x + s, y + s, x - s, y + s, color);
+			}
+			MessageKind::System =>
+			{
+				state
+					.prim
+					.draw_rectangle(x - s, y - s, x + s, y + s, color, 2.);
+			}
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Message
+{
+	pub text: String,
+	pub kind: MessageKind,
+	pub time: f64,
+}
+
+impl Message
+{
+	// 1 when fresh, fading to 0 as it expires.
+	pub fn freshness(&self, duration: f64, time: f64) -> f32
+	{
+		(1. - (time - self.time) / duration).max(0.) as f32
+	}
+}
+
+pub struct MessageLog
+{
+	pub messages: Vec<Message>,
+	pub toasts: Vec<Message>,
+}
+
+impl MessageLog
+{
+	pub fn new() -> Self
+	{
+		Self {
+			messages: vec![],
+			toasts: vec![],
+		}
+	}
+
+	pub fn add(&mut self, kind: MessageKind, text: String, time: f64)
+	{
+		self.messages.push(Message {
+			text: text,
+			kind: kind,
+			time: time,
+		});
+	}
+
+	// Important messages get shown in the middle of the screen, one at a time.
+	pub fn toast(&mut self, kind: MessageKind, text: String, time: f64)
+	{
+		self.toasts.push(Message {
+			text: text,
+			kind: kind,
+			time: time,
+		});
+	}
+
+	pub fn update(&mut self, time: f64)
+	{
+		self.messages.retain(|m| time - m.time < m.kind.duration());
+		if let Some(toast) = self.toasts.first()
+		{
+			if time - toast.time > TOAST_DURATION
+			{
+				self.toasts.remove(0);
+				// The next one starts its timer now.
+				if let Some(toast) = self.toasts.first_mut()
+				{
+					toast.time = time;
+				}
+			}
+		}
+	}
+
+	pub fn clear(&mut self)
+	{
+		self.messages.clear();
+		self.toasts.clear();
+	}
+
+	pub fn visible<'l>(
+		&'l self, muted: &'l [bool; 4],
+	) -> impl Iterator<Item = (usize, &'l Message)> + 'l
+	{
+		self.messages
+			.iter()
+			.enumerate()
+			.filter(move |(_, m)| !muted[m.kind as usize])
+	}
+
+	pub fn dismiss(&mut self, idx: usize)
+	{
+		if idx < self.messages.len()
+		{
+			self.messages.remove(idx);
+		}
+	}
+
+	pub fn dismiss_toast(&mut self, time: f64)
+	{
+		if !self.toasts.is_empty()
+		{
+			self.toasts.remove(0);
+			if let Some(toast) = self.toasts.first_mut()
+			{
+				toast.time = time;
+			}
+		}
+	}
+
+	pub fn toast_rect(&self, x: f32, y: f32, state: &game_state::GameState) -> Option<[f32; 4]>
+	{
+		let toast = self.toasts.first()?;
+		let lh = state.ui_font.get_line_height() as f32;
+		let m = state.m;
+		let w = state.ui_font.get_text_width(&toast.text) as f32 + m * 3.;
+		let h = lh + m;
+		Some([x - w / 2., y - h / 2., x + w / 2., y + h / 2.])
+	}
+
+	pub fn draw_toast(&self, x: f32, y: f32, state: &game_state::GameState)
+	{
+		let (toast, rect) = match (self.toasts.first(), self.toast_rect(x, y, state))
+		{
+			(Some(toast), Some(rect)) => (toast, rect),
+			_ => return,
+		};
+		let f = (toast.freshness(TOAST_DURATION, state.time()) * 4.).min(1.);
+		let lh = state.ui_font.get_line_height() as f32;
+		let m = state.m;
+		let transparent = Color::from_rgba(0, 0, 0, 0);
+		let color = toast.kind.color().interpolate(transparent, 1. - f);
+		state.prim.draw_filled_rectangle(
+			rect[0],
+			rect[1],
+			rect[2],
+			rect[3],
+			ui::backdrop(0.75 * f),
+		);
+		state
+			.prim
+			.draw_rectangle(rect[0], rect[1], rect[2], rect[3], color, 3.);
+		toast.kind.draw_icon(rect[0] + m, y, lh * 0.6, color, state);
+		state.core.draw_text(
+			&state.ui_font,
+			color,
+			x + m / 2.,
+			y - lh / 2.,
+			FontAlign::Centre,
+			&toast.text,
+		);
+	}
+}
@@ -0,0 +1,352 @@
+use crate::components as comps;
+use crate::controls;
+use crate::error::{Error, Result};
+
+use serde_derive::{Deserialize, Serialize};
+use slr_config::{from_element, to_element, ConfigElement};
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// Experimental LAN co-op. The host runs the world as usual, the other instance captains an allied
+// ship in it: its orders go to the host, and the host sends back where every ship is.
+
+pub const PORT: u16 = 27182;
+const VERSION: i32 = 1;
+const CONNECT_TIMEOUT: f64 = 3.;
+// Far more than a snapshot of a crowded cell, anything bigger is garbage.
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+// Seconds between the host's snapshots.
+pub const SNAPSHOT_INTERVAL: f64 = 0.1;
+// What the host's snapshots carry of each ship, by registry ID. Nothing in world space, which
+// means something different on each side.
+pub const SHIP_COMPONENTS: &[&str] = &["ship_state"];
+
+// A position that means the same thing on both sides, whatever cell each one is centered on.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct WirePos
+{
+	pub cell: [i32; 2],
+	pub x: f32,
+	pub z: f32,
+}
+
+// An order from the consort, using the same actions as the local controls.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemoteInput
+{
+	pub action: controls::Action,
+	pub on: bool,
+	pub queue: bool,
+	pub pos: WirePos,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShipSnapshot
+{
+	// The host's entity, which is how the consort tells the ships apart.
+	pub id: u64,
+	// What the consort spawns her from.
+	pub desc: String,
+	pub team: comps::Team,
+	pub level: i32,
+	pub pos: WirePos,
+	pub dir: f32,
+	// A registry table of her SHIP_COMPONENTS, as config text.
+	pub components: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snapshot
+{
+	pub consort: u64,
+	pub ships: Vec<ShipSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Message
+{
+	// The consort's first word, the host hangs up on anything else.
+	Join
+	{
+		version: i32,
+	},
+	Hello
+	{
+		version: i32,
+		seed: u64,
+	},
+	Input(RemoteInput),
+	Snapshot(Snapshot),
+}
+
+// Length-prefixed config text, buffered both ways so the game never waits on the socket.
+pub struct Connection
+{
+	stream: TcpStream,
+	incoming: Vec<u8>,
+	outgoing: Vec<u8>,
+}
+
+impl Connection
+{
+	fn new(stream: TcpStream) -> Result<Self>
+	{
+		stream.set_nonblocking(true)?;
+		stream.set_nodelay(true)?;
+		Ok(Self {
+			stream: stream,
+			incoming: vec![],
+			outgoing: vec![],
+		})
+	}
+
+	pub fn send(&mut self, message: &Message) -> Result<()>
+	{
+		let text = format!(
+			"{}",
+			to_element(message)
+				.map_err(|e| Error::new(format!("Couldn't write a message"), Some(Box::new(e))))?
+		);
+		if text.len() > MAX_MESSAGE_SIZE
+		{
+			return Err(format!("Message too long ({} bytes)", text.len()).into());
+		}
+		self.outgoing
+			.extend_from_slice(&(text.len() as u32).to_be_bytes());
+		self.outgoing.extend_from_slice(text.as_bytes());
+		self.flush()
+	}
+
+	fn flush(&mut self) -> Result<()>
+	{
+		while !self.outgoing.is_empty()
+		{
+			match self.stream.write(&self.outgoing)
+			{
+				Ok(0) => return Err("Connection closed".to_string().into()),
+				Ok(n) =>
+				{
+					self.outgoing.drain(..n);
+				}
+				Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+				Err(e) => return Err(e.into()),
+			}
+		}
+		Ok(())
+	}
+
+	pub fn receive(&mut self) -> Result<Vec<Message>>
+	{
+		self.flush()?;
+		let mut buf = [0; 4096];
+		loop
+		{
+			match self.stream.read(&mut buf)
+			{
+				Ok(0) => return Err("Connection closed".to_string().into()),
+				Ok(n) => self.incoming.extend_from_slice(&buf[..n]),
+				Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+				Err(e) => return Err(e.into()),
+			}
+		}
+
+		let mut messages = vec![];
+		while self.incoming.len() >= 4
+		{
+			let len = u32::from_be_bytes(self.incoming[..4].try_into().unwrap()) as usize;
+			if len > MAX_MESSAGE_SIZE
+			{
+				return Err(format!("Message too long ({len} bytes)").into());
+			}
+			if self.incoming.len() < 4 + len
+			{
+				break;
+			}
+			let text = String::from_utf8_lossy(&self.incoming[4..4 + len]).into_owned();
+			self.incoming.drain(..4 + len);
+			let element = ConfigElement::from_str(&text)
+				.map_err(|e| Error::new(format!("Couldn't read a message"), Some(Box::new(e))))?;
+			messages.push(
+				from_element::<Message>(&element, None).map_err(|e| {
+					Error::new(format!("Couldn't read a message"), Some(Box::new(e)))
+				})?,
+			);
+		}
+		Ok(messages)
+	}
+}
+
+// Plain io errors, since these have to cross threads.
+fn connect(address: &str) -> std::io::Result<TcpStream>
+{
+	let addr = (address, PORT).to_socket_addrs()?.next().ok_or_else(|| {
+		std::io::Error::new(ErrorKind::NotFound, format!("Couldn't find '{address}'"))
+	})?;
+	TcpStream::connect_timeout(&addr, Duration::from_secs_f64(CONNECT_TIMEOUT))
+}
+
+pub struct Net
+{
+	pub is_host: bool,
+	// Where the host listens, or the host the consort is joining.
+	address: String,
+	listener: Option<TcpListener>,
+	// The consort's connection attempt, made off the main thread so the menu doesn't freeze.
+	connecting: Option<mpsc::Receiver<std::io::Result<TcpStream>>>,
+	pub peer: Option<Connection>,
+	// The host only starts once the consort has said the right thing.
+	joined: bool,
+	// The host's voyage, which the consort sails in too.
+	pub seed: Option<u64>,
+}
+
+impl Net
+{
+	pub fn host(address: &str) -> Result<Self>
+	{
+		let listener = TcpListener::bind((address, PORT))?;
+		listener.set_nonblocking(true)?;
+		Ok(Self {
+			is_host: true,
+			address: address.to_string(),
+			listener: Some(listener),
+			connecting: None,
+			peer: None,
+			joined: false,
+			seed: None,
+		})
+	}
+
+	pub fn join(address: &str) -> Self
+	{
+		let (sender, receiver) = mpsc::channel();
+		let worker_address = address.to_string();
+		thread::spawn(move || {
+			// Nobody's listening if the lobby was left in the meantime.
+			sender.send(connect(&worker_address)).ok();
+		});
+		Self {
+			is_host: false,
+			address: address.to_string(),
+			listener: None,
+			connecting: Some(receiver),
+			peer: None,
+			joined: false,
+			seed: None,
+		}
+	}
+
+	// Waits for the other side, true once the voyage can start.
+	pub fn lobby_logic(&mut self) -> Result<bool>
+	{
+		if self.is_host
+		{
+			if self.peer.is_none()
+			{
+				match self.listener.as_ref().unwrap().accept()
+				{
+					Ok((stream, _)) => self.peer = Some(Connection::new(stream)?),
+					Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+					Err(e) => return Err(e.into()),
+				}
+			}
+			if self.peer.is_some() && !self.joined
+			{
+				let joined = match self.receive()
+				{
+					Ok(messages) => match messages.first()
+					{
+						Some(Message::Join { version }) => Some(*version == VERSION),
+						Some(_) => Some(false),
+						None => None,
+					},
+					Err(_) => Some(false),
+				};
+				match joined
+				{
+					Some(true) => self.joined = true,
+					// Not a consort, or the wrong version of one. Keep listening.
+					Some(false) => self.peer = None,
+					None => (),
+				}
+			}
+			Ok(self.joined)
+		}
+		else
+		{
+			if let Some(connecting) = self.connecting.as_ref()
+			{
+				match connecting.try_recv()
+				{
+					Ok(stream) =>
+					{
+						self.connecting = None;
+						self.peer = Some(Connection::new(stream?)?);
+						self.send(&Message::Join { version: VERSION })?;
+					}
+					Err(mpsc::TryRecvError::Empty) => return Ok(false),
+					Err(mpsc::TryRecvError::Disconnected) =>
+					{
+						return Err("Couldn't connect".to_string().into())
+					}
+				}
+			}
+			for message in self.receive()?
+			{
+				if let Message::Hello { version, seed } = message
+				{
+					if version != VERSION
+					{
+						return Err(format!(
+							"The host is on version {version}, this is version {VERSION}"
+						)
+						.into());
+					}
+					self.seed = Some(seed);
+				}
+			}
+			Ok(self.seed.is_some())
+		}
+	}
+
+	pub fn hello(&mut self, seed: u64) -> Result<()>
+	{
+		self.send(&Message::Hello {
+			version: VERSION,
+			seed: seed,
+		})
+	}
+
+	pub fn send(&mut self, message: &Message) -> Result<()>
+	{
+		match self.peer.as_mut()
+		{
+			Some(peer) => peer.send(message),
+			None => Ok(()),
+		}
+	}
+
+	pub fn receive(&mut self) -> Result<Vec<Message>>
+	{
+		match self.peer.as_mut()
+		{
+			Some(peer) => peer.receive(),
+			None => Ok(vec![]),
+		}
+	}
+
+	pub fn status(&self) -> String
+	{
+		match (self.is_host, &self.peer)
+		{
+			(true, None) => format!("Waiting for a consort on {}:{PORT}...", self.address),
+			(true, Some(_)) => "Consort aboard, setting sail!".into(),
+			(false, None) => format!("Connecting to {}...", self.address),
+			(false, Some(_)) => "Connected, waiting for the host...".into(),
+		}
+	}
+}
@@ -0,0 +1,397 @@
+use crate::components as comps;
+use crate::error::Result;
+use crate::{game, game_state, utils};
+
+use allegro::*;
+use nalgebra::{Isometry3, Matrix4, Perspective3, Vector3};
+use serde_derive::{Deserialize, Serialize};
+
+use std::f32::consts::PI;
+use std::fs;
+
+// Third-party ships live in packs/<id>/, described by a manifest.cfg next to them. Paths in the
+// pack's ship descs are relative to the game, e.g. packs/<id>/sloop.glb.
+
+pub const PACKS_DIR: &str = "packs";
+const THUMBNAIL_SIZE: i32 = 192;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PackShip
+{
+	// Relative to the pack.
+	pub desc: String,
+	// Which factions sail her, all of the warring ones if empty.
+	#[serde(default)]
+	pub teams: Vec<comps::Team>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Manifest
+{
+	pub name: String,
+	#[serde(default)]
+	pub author: String,
+	#[serde(default)]
+	pub description: String,
+	#[serde(default)]
+	pub ships: Vec<PackShip>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pack
+{
+	// The folder name, which is what the options remember.
+	pub id: String,
+	pub dir: String,
+	pub manifest: std::result::Result<Manifest, String>,
+	// Ships that were dropped from the manifest, and anything else wrong with it.
+	pub problems: Vec<String>,
+}
+
+impl Pack
+{
+	pub fn name(&self) -> &str
+	{
+		match &self.manifest
+		{
+			Ok(manifest) => &manifest.name,
+			Err(_) => &self.id,
+		}
+	}
+
+	// Only these can be enabled.
+	pub fn usable(&self) -> bool
+	{
+		self.manifest.is_ok() && self.problems.is_empty()
+	}
+
+	pub fn ship_descs(&self) -> Vec<String>
+	{
+		match &self.manifest
+		{
+			Ok(manifest) => manifest
+				.ships
+				.iter()
+				.map(|ship| format!("{}/{}", self.dir, ship.desc))
+				.collect(),
+			Err(_) => vec![],
+		}
+	}
+}
+
+// Everything in the packs directory, usable or not. Zipped packs get unzipped next to the zip,
+// unless that's been done already.
+pub fn scan() -> Vec<Pack>
+{
+	let mut packs = vec![];
+	let entries: Vec<_> = match fs::read_dir(PACKS_DIR)
+	{
+		Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+		Err(_) => return packs,
+	};
+	for path in &entries
+	{
+		let (id, manifest) = if path.is_dir()
+		{
+			let id = path.file_name().unwrap().to_string_lossy().into_owned();
+			let manifest = load_manifest(&format!("{PACKS_DIR}/{id}"));
+			(id, manifest)
+		}
+		else if path.extension().map(|e| e == "zip").unwrap_or(false)
+		{
+			let id = path.file_stem().unwrap().to_string_lossy().into_owned();
+			if entries.contains(&path.with_extension(""))
+			{
+				continue;
+			}
+			let dir = format!("{PACKS_DIR}/{id}");
+			let manifest = unzip(&path.to_string_lossy(), &dir)
+				.map_err(|e| e.to_string())
+				.and_then(|_| load_manifest(&dir));
+			(id, manifest)
+		}
+		else
+		{
+			continue;
+		};
+
+		let dir = format!("{PACKS_DIR}/{id}");
+		let mut problems = vec![];
+		let manifest = manifest.map(|mut manifest| {
+			manifest.ships.retain(|ship| {
+				let desc = format!("{dir}/{}", ship.desc);
+				match game::check_ship_desc(&desc)
+				{
+					Ok(()) => true,
+					Err(e) =>
+					{
+						problems.push(format!("{desc}: {e}"));
+						false
+					}
+				}
+			});
+			manifest
+		});
+		packs.push(Pack {
+			id: id,
+			dir: dir,
+			manifest: manifest,
+			problems: problems,
+		});
+	}
+	packs.sort_by(|a, b| a.id.cmp(&b.id));
+	packs
+}
+
+fn load_manifest(dir: &str) -> std::result::Result<Manifest, String>
+{
+	utils::load_config::<Manifest>(&format!("{dir}/manifest.cfg")).map_err(|e| e.to_string())
+}
+
+// Extracts the zip into the directory. A single folder around everything, as zipping up a pack's
+// folder tends to give, is left out.
+fn unzip(file: &str, dir: &str) -> Result<()>
+{
+	let data = fs::read(file)?;
+	let files = read_zip(&data).map_err(|e| format!("Couldn't read {file}: {e}"))?;
+	let root = files
+		.iter()
+		.map(|(name, _)| name)
+		.filter_map(|name| name.strip_suffix("manifest.cfg"))
+		.filter(|prefix| prefix.is_empty() || prefix.ends_with('/'))
+		.min_by_key(|prefix| prefix.len())
+		.ok_or_else(|| format!("{file} has no manifest.cfg"))?
+		.to_string();
+	for (name, contents) in &files
+	{
+		let name = match name.strip_prefix(&root)
+		{
+			Some(name) if !name.is_empty() && !name.ends_with('/') => name,
+			_ => continue,
+		};
+		let path = std::path::Path::new(dir).join(name);
+		if let Some(parent) = path.parent()
+		{
+			fs::create_dir_all(parent)?;
+		}
+		fs::write(&path, contents)?;
+	}
+	Ok(())
+}
+
+// The files in a zip, stored or deflated, with their paths. Anything that would land outside the
+// directory it's extracted to is an error.
+fn read_zip(data: &[u8]) -> std::result::Result<Vec<(String, Vec<u8>)>, String>
+{
+	let u16_at = |offset: usize| -> std::result::Result<usize, String> {
+		data.get(offset..offset + 2)
+			.map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+			.ok_or_else(|| "truncated".to_string())
+	};
+	let u32_at = |offset: usize| -> std::result::Result<usize, String> {
+		data.get(offset..offset + 4)
+			.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+			.ok_or_else(|| "truncated".to_string())
+	};
+
+	// The end of central directory record, which may be followed by a comment.
+	let end = (0..data.len().saturating_sub(21))
+		.rev()
+		.find(|&offset| u32_at(offset) == Ok(0x06054b50))
+		.ok_or_else(|| "not a zip file".to_string())?;
+	let num_files = u16_at(end + 10)?;
+	let mut offset = u32_at(end + 16)?;
+
+	let mut files = vec![];
+	for _ in 0..num_files
+	{
+		if u32_at(offset)? != 0x02014b50
+		{
+			return Err("bad central directory".into());
+		}
+		let flags = u16_at(offset + 8)?;
+		let method = u16_at(offset + 10)?;
+		let compressed_size = u32_at(offset + 20)?;
+		let size = u32_at(offset + 24)?;
+		let name_len = u16_at(offset + 28)?;
+		let extra_len = u16_at(offset + 30)?;
+		let comment_len = u16_at(offset + 32)?;
+		let local = u32_at(offset + 42)?;
+		let name = data
+			.get(offset + 46..offset + 46 + name_len)
+			.ok_or_else(|| "truncated".to_string())?;
+		let name = String::from_utf8_lossy(name).replace('\\', "/");
+		offset += 46 + name_len + extra_len + comment_len;
+
+		if std::path::Path::new(&name)
+			.components()
+			.any(|c| !matches!(c, std::path::Component::Normal(_)))
+		{
+			return Err(format!("'{name}' is outside the pack"));
+		}
+		if flags & 1 != 0
+		{
+			return Err(format!("'{name}' is encrypted"));
+		}
+		if u32_at(local)? != 0x04034b50
+		{
+			return Err(format!("bad header for '{name}'"));
+		}
+		let start = local + 30 + u16_at(local + 26)? + u16_at(local + 28)?;
+		let compressed = data
+			.get(start..start + compressed_size)
+			.ok_or_else(|| "truncated".to_string())?;
+		let contents = match method
+		{
+			0 => compressed.to_vec(),
+			8 => miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, size)
+				.map_err(|e| format!("couldn't inflate '{name}': {e:?}"))?,
+			_ => return Err(format!("'{name}' uses an unsupported compression method")),
+		};
+		files.push((name, contents));
+	}
+	Ok(files)
+}
+
+// Ship descs from the enabled packs that the team sails.
+pub fn enabled_ships(state: &game_state::GameState, team: comps::Team) -> Vec<String>
+{
+	let mut ships = vec![];
+	for pack in &state.packs
+	{
+		if !state.options.packs.contains(&pack.id) || !pack.usable()
+		{
+			continue;
+		}
+		if let Ok(manifest) = &pack.manifest
+		{
+			for ship in &manifest.ships
+			{
+				if ship.teams.is_empty() || ship.teams.contains(&team)
+				{
+					ships.push(format!("{}/{}", pack.dir, ship.desc));
+				}
+			}
+		}
+	}
+	ships
+}
+
+// Draws the ship's mesh from three quarters above with the basic shader.
+pub fn thumbnail(state: &mut game_state::GameState, ship_desc: &str) -> Result<Bitmap>
+{
+	let desc = game::load_ship_desc(ship_desc, state)?;
+
+	state.core.set_new_bitmap_depth(16);
+	let bitmap = Bitmap::new(&state.core, THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+	state.core.set_new_bitmap_depth(0);
+	let bitmap = bitmap.map_err(|_| "Couldn't create bitmap".to_string())?;
+
+	let mesh = state.get_mesh(&desc.mesh)?;
+	let (min, max) = mesh.bounds();
+	let center = min + (max - min) / 2.;
+	let radius = (max - min).norm() / 2.;
+	let eye = center + Vector3::new(1., 0.8, 1.).normalize() * radius * 2.5;
+	let project = Perspective3::new(1., PI / 4., radius / 10., radius * 10.);
+	let camera = Isometry3::look_at_rh(&eye, &center, &Vector3::y());
+
+	state.core.set_target_bitmap(Some(&bitmap));
+	state
+		.core
+		.clear_to_color(Color::from_rgba_f(0., 0., 0., 0.));
+	state.core.clear_depth_buffer(1.);
+	state.core.set_depth_test(Some(DepthFunction::Less));
+	state
+		.core
+		.use_shader(Some(&*state.basic_shader.upgrade().unwrap()))
+		.unwrap();
+	state
+		.core
+		.use_projection_transform(&utils::mat4_to_transform(project.to_homogeneous()));
+	state
+		.core
+		.use_transform(&utils::mat4_to_transform(camera.to_homogeneous()));
+	state
+		.core
+		.set_shader_transform(
+			"model_matrix",
+			&utils::mat4_to_transform(Matrix4::identity()),
+		)
+		.ok();
+	mesh.draw(&state.core, &state.prim, |material, texture| {
+		state.get_material_bitmap(material, texture)
+	});
+
+	state.core.set_depth_test(None);
+	state
+		.core
+		.use_shader(Some(&*state.default_shader.upgrade().unwrap()))
+		.unwrap();
+	state.core.set_target_bitmap(state.buffer.as_ref());
+	Ok(bitmap)
+}
+
+#[test]
+fn test_read_zip()
+{
+	// Just enough of a zip writer to feed it.
+	fn zip(files: &[(&str, u16, &[u8])]) -> Vec<u8>
+	{
+		let mut data = vec![];
+		let mut central = vec![];
+		for (name, method, contents) in files
+		{
+			let compressed = match method
+			{
+				8 => miniz_oxide::deflate::compress_to_vec(contents, 6),
+				_ => contents.to_vec(),
+			};
+			let local = data.len() as u32;
+			data.extend_from_slice(&0x04034b50u32.to_le_bytes());
+			data.extend_from_slice(&[0; 4]);
+			data.extend_from_slice(&method.to_le_bytes());
+			data.extend_from_slice(&[0; 8]);
+			data.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+			data.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+			data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+			data.extend_from_slice(&[0; 2]);
+			data.extend_from_slice(name.as_bytes());
+			data.extend_from_slice(&compressed);
+
+			central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+			central.extend_from_slice(&[0; 6]);
+			central.extend_from_slice(&method.to_le_bytes());
+			central.extend_from_slice(&[0; 8]);
+			central.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+			central.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+			central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+			central.extend_from_slice(&[0; 12]);
+			central.extend_from_slice(&local.to_le_bytes());
+			central.extend_from_slice(name.as_bytes());
+		}
+		let central_offset = data.len() as u32;
+		data.extend_from_slice(&central);
+		data.extend_from_slice(&0x06054b50u32.to_le_bytes());
+		data.extend_from_slice(&[0; 6]);
+		data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+		data.extend_from_slice(&(central.len() as u32).to_le_bytes());
+		data.extend_from_slice(&central_offset.to_le_bytes());
+		data.extend_from_slice(&[0; 2]);
+		data
+	}
+
+	let files = read_zip(&zip(&[
+		("sloop/manifest.cfg", 0, b"name = \"Sloop\""),
+		("sloop/sloop.cfg", 8, &[b'x'; 1000]),
+	]))
+	.unwrap();
+	assert_eq!(files.len(), 2);
+	assert_eq!(
+		files[0],
+		("sloop/manifest.cfg".into(), b"name = \"Sloop\"".to_vec())
+	);
+	assert_eq!(files[1], ("sloop/sloop.cfg".into(), vec![b'x'; 1000]));
+
+	assert!(read_zip(&zip(&[("../evil.cfg", 0, b"")])).is_err());
+	assert!(read_zip(b"not a zip").is_err());
+}
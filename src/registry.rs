@@ -0,0 +1,340 @@
+use crate::components as comps;
+use crate::error::{Error, Result};
+
+use na::{Point3, Vector3};
+use nalgebra as na;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use slr_config::{from_element, to_element, ConfigElement};
+
+use std::any::TypeId;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+// Which entity each position belongs to.
+pub type WorldPositions = Vec<(hecs::Entity, Point3<f32>)>;
+
+// A component type under an ID that stays put when the type gets renamed or moved, so anything
+// written out with it can still be read back. Components that point at other entities or at
+// loaded assets can only be described, not saved. So can Equipment, whose items aren't
+// serializable.
+pub struct ComponentDesc
+{
+	pub id: &'static str,
+	pub type_id: fn() -> TypeId,
+	pub describe: fn(&hecs::World, hecs::Entity) -> Option<String>,
+	pub save: Option<fn(&hecs::World, hecs::Entity) -> Option<Result<ConfigElement>>>,
+	pub load: Option<fn(&mut hecs::World, hecs::Entity, &ConfigElement) -> Result<()>>,
+	// For recentering, these do nothing unless the type implements comps::WorldSpace.
+	pub shift: fn(&mut hecs::World, Vector3<f32>),
+	pub world_positions: fn(&hecs::World) -> WorldPositions,
+}
+
+// Picks the comps::WorldSpace methods when the type has them and the no-ops otherwise, by having
+// the former take one more reference to the probe. This only works for a concrete type, so it's
+// spelled out by the component! macro for each entry.
+struct WorldSpaceProbe<T>(PhantomData<T>);
+
+trait IsWorldSpace
+{
+	fn shift(&self, world: &mut hecs::World, offt: Vector3<f32>);
+	fn world_positions(&self, world: &hecs::World) -> WorldPositions;
+}
+
+impl<T: comps::WorldSpace + hecs::Component> IsWorldSpace for &WorldSpaceProbe<T>
+{
+	fn shift(&self, world: &mut hecs::World, offt: Vector3<f32>)
+	{
+		for (_, component) in world.query_mut::<&mut T>()
+		{
+			component.shift(offt);
+		}
+	}
+
+	fn world_positions(&self, world: &hecs::World) -> WorldPositions
+	{
+		let mut positions = vec![];
+		for (id, component) in world.query::<&T>().iter()
+		{
+			positions.extend(component.world_positions().into_iter().map(|pos| (id, pos)));
+		}
+		positions
+	}
+}
+
+trait NotWorldSpace
+{
+	fn shift(&self, _world: &mut hecs::World, _offt: Vector3<f32>) {}
+
+	fn world_positions(&self, _world: &hecs::World) -> WorldPositions
+	{
+		vec![]
+	}
+}
+
+impl<T> NotWorldSpace for WorldSpaceProbe<T> {}
+
+fn describe<T: hecs::Component + Debug>(world: &hecs::World, entity: hecs::Entity)
+	-> Option<String>
+{
+	world
+		.get::<&T>(entity)
+		.ok()
+		.map(|component| format!("{:?}", *component))
+}
+
+fn save<T: hecs::Component + Serialize>(
+	world: &hecs::World, entity: hecs::Entity,
+) -> Option<Result<ConfigElement>>
+{
+	world.get::<&T>(entity).ok().map(|component| {
+		to_element(&*component).map_err(|e| {
+			Error::new(
+				format!("Couldn't save {}", std::any::type_name::<T>()),
+				Some(Box::new(e)),
+			)
+		})
+	})
+}
+
+fn load<T: hecs::Component + DeserializeOwned + Clone>(
+	world: &mut hecs::World, entity: hecs::Entity, element: &ConfigElement,
+) -> Result<()>
+{
+	let component = from_element::<T>(element, None).map_err(|e| {
+		Error::new(
+			format!("Couldn't load {}", std::any::type_name::<T>()),
+			Some(Box::new(e)),
+		)
+	})?;
+	world.insert_one(entity, component)?;
+	Ok(())
+}
+
+macro_rules! component {
+	($id:expr, $type:ty) => {
+		component!($id, $type, None, None)
+	};
+	($id:expr, $type:ty, saved) => {
+		component!($id, $type, Some(save::<$type>), Some(load::<$type>))
+	};
+	($id:expr, $type:ty, $save:expr, $load:expr) => {
+		ComponentDesc {
+			id: $id,
+			type_id: TypeId::of::<$type>,
+			describe: describe::<$type>,
+			save: $save,
+			load: $load,
+			shift: {
+				fn shift(world: &mut hecs::World, offt: Vector3<f32>)
+				{
+					(&&WorldSpaceProbe::<$type>(PhantomData)).shift(world, offt)
+				}
+				shift
+			},
+			world_positions: {
+				fn world_positions(world: &hecs::World) -> WorldPositions
+				{
+					(&&WorldSpaceProbe::<$type>(PhantomData)).world_positions(world)
+				}
+				world_positions
+			},
+		}
+	};
+}
+
+// Never change an ID once it's been used, add a new one instead.
+pub static COMPONENTS: &[ComponentDesc] = &[
+	component!("position", comps::Position),
+	component!("prev_position", comps::PrevPosition),
+	component!("spawned", comps::Spawned, saved),
+	component!("tilt", comps::Tilt, saved),
+	component!("attached", comps::Attached),
+	component!("scale", comps::Scale, saved),
+	component!("velocity", comps::Velocity),
+	component!("target", comps::Target),
+	component!("mesh", comps::Mesh),
+	component!("render_flags", comps::RenderFlags),
+	component!("flag", comps::Flag),
+	component!("ai", comps::AI),
+	component!("wisp_spawner", comps::WispSpawner, saved),
+	component!("escort_spawner", comps::EscortSpawner, saved),
+	component!("wisp", comps::Wisp, saved),
+	component!("creature", comps::Creature),
+	component!("solid", comps::Solid),
+	component!("equipment", comps::Equipment),
+	component!("time_to_die", comps::TimeToDie, saved),
+	component!("affected_by_gravity", comps::AffectedByGravity, saved),
+	component!("collides_with_water", comps::CollidesWithWater, saved),
+	component!("submerged", comps::Submerged, saved),
+	component!("splash", comps::Splash, saved),
+	component!("squall", comps::Squall, saved),
+	component!("wreck", comps::Wreck, saved),
+	component!("lighthouse", comps::Lighthouse, saved),
+	component!("rival", comps::Rival, saved),
+	component!("fleet", comps::Fleet, saved),
+	component!("station", comps::Station, saved),
+	component!("consort", comps::Consort, saved),
+	component!("ship_desc", comps::ShipDesc, saved),
+	component!("on_contact_effect", comps::OnContactEffect),
+	component!("projectile", comps::Projectile),
+	component!("ship_stats", comps::ShipStats, saved),
+	component!("ship_state", comps::ShipState, saved),
+	component!("lights", comps::Lights),
+];
+
+pub fn find(id: &str) -> Option<&'static ComponentDesc>
+{
+	COMPONENTS.iter().find(|desc| desc.id == id)
+}
+
+pub fn find_type(type_id: TypeId) -> Option<&'static ComponentDesc>
+{
+	COMPONENTS.iter().find(|desc| (desc.type_id)() == type_id)
+}
+
+// Moves every registered world-space component by -offt, see Map::recenter.
+pub fn shift_world_space(world: &mut hecs::World, offt: Vector3<f32>)
+{
+	for desc in COMPONENTS
+	{
+		(desc.shift)(world, offt);
+	}
+}
+
+// Every world-frame position held by any registered component, with the component's ID.
+pub fn world_positions(world: &hecs::World) -> Vec<(&'static str, hecs::Entity, Point3<f32>)>
+{
+	COMPONENTS
+		.iter()
+		.flat_map(|desc| {
+			(desc.world_positions)(world)
+				.into_iter()
+				.map(|(id, pos)| (desc.id, id, pos))
+		})
+		.collect()
+}
+
+// Components the registry doesn't know about, which recentering would never shift.
+pub fn unregistered_types(world: &hecs::World) -> Vec<TypeId>
+{
+	let mut types = vec![];
+	for archetype in world.archetypes().filter(|archetype| !archetype.is_empty())
+	{
+		for type_id in archetype.component_types()
+		{
+			if find_type(type_id).is_none() && !types.contains(&type_id)
+			{
+				types.push(type_id);
+			}
+		}
+	}
+	types
+}
+
+// Every registered component the entity has, by ID.
+pub fn describe_entity(world: &hecs::World, entity: hecs::Entity) -> Vec<(&'static str, String)>
+{
+	COMPONENTS
+		.iter()
+		.filter_map(|desc| (desc.describe)(world, entity).map(|text| (desc.id, text)))
+		.collect()
+}
+
+// A table of the entity's components with these IDs, keyed by ID. Ones it doesn't have are left
+// out.
+pub fn save_components(
+	world: &hecs::World, entity: hecs::Entity, ids: &[&str],
+) -> Result<ConfigElement>
+{
+	let mut table = ConfigElement::new_table();
+	for id in ids
+	{
+		let save = find(id)
+			.ok_or_else(|| format!("Unknown component '{id}'"))?
+			.save
+			.ok_or_else(|| format!("Component '{id}' can't be saved"))?;
+		if let Some(element) = save(world, entity)
+		{
+			table.insert(id, element?);
+		}
+	}
+	Ok(table)
+}
+
+pub fn load_entity(
+	world: &mut hecs::World, entity: hecs::Entity, table: &ConfigElement,
+) -> Result<()>
+{
+	let table = table
+		.as_table()
+		.ok_or_else(|| "Expected a table of components".to_string())?;
+	for (id, element) in table
+	{
+		let load = find(id)
+			.ok_or_else(|| format!("Unknown component '{id}'"))?
+			.load
+			.ok_or_else(|| format!("Component '{id}' can't be loaded"))?;
+		load(world, entity, element)?;
+	}
+	Ok(())
+}
+
+#[test]
+fn test_unique_ids()
+{
+	for (i, desc) in COMPONENTS.iter().enumerate()
+	{
+		assert!(
+			COMPONENTS[i + 1..].iter().all(|other| other.id != desc.id),
+			"Duplicate component ID '{}'",
+			desc.id
+		);
+	}
+}
+
+#[test]
+fn test_save_load()
+{
+	let mut world = hecs::World::new();
+	let entity = world.spawn((
+		comps::Squall {
+			radius: 12.,
+			time_to_lightning: 3.5,
+		},
+		comps::Rival,
+	));
+	let table = save_components(&world, entity, &["squall", "rival", "wreck"]).unwrap();
+	assert!(table.as_table().unwrap().get("wreck").is_none());
+
+	let copy = world.spawn(());
+	load_entity(&mut world, copy, &table).unwrap();
+	assert_eq!(world.get::<&comps::Squall>(copy).unwrap().radius, 12.);
+	assert!(world.get::<&comps::Rival>(copy).is_ok());
+}
+
+#[test]
+fn test_world_space()
+{
+	let mut world = hecs::World::new();
+	let entity = world.spawn((
+		comps::Position {
+			pos: Point3::new(100., 0., 50.),
+			dir: 0.,
+		},
+		comps::Rival,
+	));
+	shift_world_space(&mut world, Vector3::new(100., 0., 0.));
+	assert_eq!(
+		world.get::<&comps::Position>(entity).unwrap().pos,
+		Point3::new(0., 0., 50.)
+	);
+	assert_eq!(
+		world_positions(&world),
+		vec![("position", entity, Point3::new(0., 0., 50.))]
+	);
+	assert!(unregistered_types(&world).is_empty());
+
+	world.spawn((0u32,));
+	assert_eq!(unregistered_types(&world), vec![TypeId::of::<u32>()]);
+}
@@ -0,0 +1,127 @@
+use crate::{locale, utils};
+
+use nalgebra::{Point3, Vector2};
+use rand::prelude::*;
+
+// How long a sighting stays marked on the map.
+pub const MARKER_TIME: f64 = 180.;
+// Most that a single visit to a dock turns up.
+const MAX_RUMORS: usize = 4;
+// How far off the average price a market has to be to get talked about.
+const DEAR_PRICE: f32 = 1.25;
+const CHEAP_PRICE: f32 = 0.8;
+
+#[derive(Clone, Debug)]
+pub struct Rumor
+{
+	pub text: String,
+	// What it points at, relative to the global origin.
+	pub pos: Option<Point3<f32>>,
+}
+
+impl Rumor
+{
+	pub fn new(text: String) -> Self
+	{
+		Self {
+			text: text,
+			pos: None,
+		}
+	}
+
+	pub fn at(text: String, pos: Point3<f32>) -> Self
+	{
+		Self {
+			text: text,
+			pos: Some(pos),
+		}
+	}
+}
+
+// The rumors from the last dock, and the sightings they marked on the map.
+pub struct Rumors
+{
+	pub feed: Vec<Rumor>,
+	markers: Vec<(Point3<f32>, f64)>,
+}
+
+impl Rumors
+{
+	pub fn new() -> Self
+	{
+		Self {
+			feed: vec![],
+			markers: vec![],
+		}
+	}
+
+	pub fn hear(&mut self, mut rumors: Vec<Rumor>, time: f64, rng: &mut impl Rng)
+	{
+		rumors.shuffle(rng);
+		rumors.truncate(MAX_RUMORS);
+		for rumor in &rumors
+		{
+			if let Some(pos) = rumor.pos
+			{
+				self.markers.push((pos, time + MARKER_TIME));
+			}
+		}
+		self.feed = rumors;
+	}
+
+	pub fn logic(&mut self, time: f64)
+	{
+		self.markers.retain(|(_, expires)| *expires > time);
+	}
+
+	// Marked sightings, and how long until each fades (1 when fresh).
+	pub fn markers(&self, time: f64) -> impl Iterator<Item = (Point3<f32>, f32)> + '_
+	{
+		self.markers
+			.iter()
+			.map(move |(pos, expires)| (*pos, ((*expires - time) / MARKER_TIME) as f32))
+	}
+}
+
+// Roughly where something is, as the sailors would put it.
+pub fn whereabouts(diff: Vector2<f32>, cell_size: f32) -> String
+{
+	let cells = (diff.norm() / cell_size).round() as i32;
+	let dir = utils::compass_direction(diff);
+	match cells
+	{
+		0 => "close by".into(),
+		1 => format!("a day's sail to the {dir}"),
+		2 => format!("two days' sail to the {dir}"),
+		3 => format!("three days' sail to the {dir}"),
+		_ => format!("far to the {dir}"),
+	}
+}
+
+pub fn market(
+	name: &str, price: f32, average: f32, trend: i32, locale: &locale::Locale,
+) -> Option<String>
+{
+	let lower = name.to_lowercase();
+	let (key, name) = if price > DEAR_PRICE * average
+	{
+		("rumor_dear", name)
+	}
+	else if price < CHEAP_PRICE * average
+	{
+		("rumor_cheap", name)
+	}
+	else if trend > 0
+	{
+		("rumor_rising", &lower[..])
+	}
+	else if trend < 0
+	{
+		("rumor_falling", &lower[..])
+	}
+	else
+	{
+		return None;
+	};
+	Some(locale.text(key, &[("name", name)]))
+}
@@ -3,6 +3,8 @@ use crate::utils;
 use nalgebra::{Point2, Vector2};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 
 use allegro::*;
 use allegro_acodec::*;
@@ -10,18 +12,130 @@ use allegro_audio::*;
 
 use rand::prelude::*;
 
+// How many sounds can play at once, Allegro runs out of sample instances otherwise.
+const MAX_VOICES: usize = 32;
+const DEFAULT_MAX_VOICES_PER_SAMPLE: usize = 6;
+// Non-positional sounds are UI feedback, so they should always win.
+const UI_PRIORITY: f32 = 10.;
+// World sounds while a menu is up.
+const MUFFLED_GAIN: f32 = 0.4;
+const MUFFLED_FILTER: f32 = 0.1;
+const MUFFLE_RATE: f32 = 8.;
+// Captions for sounds, for those who can't hear them.
+const CAPTION_DURATION: f32 = 3.;
+const MAX_CAPTIONS: usize = 5;
+const CAPTION_NEARBY: f32 = 10.;
+
+pub struct Caption
+{
+	pub text: String,
+	pub time_left: f32,
+	pub count: i32,
+}
+
+impl Caption
+{
+	pub fn freshness(&self) -> f32
+	{
+		(self.time_left / CAPTION_DURATION).min(1.)
+	}
+}
+
+fn caption_text(name: &str) -> Option<&'static str>
+{
+	match name
+	{
+		"data/cannon_shot.ogg" => Some("Cannon fire"),
+		"data/explosion.ogg" => Some("Explosion"),
+		"data/screams.ogg" => Some("Screams"),
+		"data/sink.ogg" => Some("Ship sinking"),
+		"data/wisp_hum.wav" => Some("Wisp humming"),
+		"data/void_drone.wav" => Some("Ominous droning"),
+		_ => None,
+	}
+}
+
+// Which way the sound is, north being up the screen.
+fn caption_direction(diff: Vector2<f32>) -> &'static str
+{
+	if diff.norm() < CAPTION_NEARBY
+	{
+		return "nearby";
+	}
+	utils::compass_direction(diff)
+}
+
+// One-pole low-pass over the world mixer's output, which is interleaved stereo f32. The
+// filter coefficient is shared with the game thread, 1 means no filtering.
+struct Muffler
+{
+	filter: Arc<AtomicU32>,
+	prev: [f32; 2],
+}
+
+impl PostProcessCallback for Muffler
+{
+	fn process(&mut self, data: &mut [u8], _num_samples: u32)
+	{
+		let filter = f32::from_bits(self.filter.load(Ordering::Relaxed));
+		let (_, samples, _) = unsafe { data.align_to_mut::<f32>() };
+		for frame in samples.chunks_exact_mut(2)
+		{
+			for (sample, prev) in frame.iter_mut().zip(self.prev.iter_mut())
+			{
+				*prev += filter * (*sample - *prev);
+				*sample = *prev;
+			}
+		}
+	}
+}
+
+struct Voice
+{
+	instance: SampleInstance,
+	name: String,
+	priority: f32,
+}
+
+impl Voice
+{
+	// Nearly finished sounds are cheap to cut off.
+	fn effective_priority(&self) -> f32
+	{
+		let pos = self.instance.get_position().unwrap_or(0) as f32;
+		let len = self.instance.get_length().unwrap_or(1).max(1) as f32;
+		self.priority * (1. - pos / len).max(0.)
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+struct VoiceLimits
+{
+	importance: f32,
+	max_voices: usize,
+}
+
 pub struct Sfx
 {
 	audio: AudioAddon,
 	acodec: AcodecAddon,
+	// Positional and ambient sounds go here, so they can be muffled separately from UI sounds
+	// and music.
+	world_mixer: Mixer,
 	sink: Sink,
 	stream: Option<AudioStream>,
 	music_file: String,
-	sample_instances: Vec<SampleInstance>,
+	voices: Vec<Voice>,
+	voice_limits: HashMap<String, VoiceLimits>,
 	exclusive_sounds: Vec<String>,
 	exclusive_instance: Option<SampleInstance>,
 	sfx_volume: f32,
 	music_volume: f32,
+	muffle: f32,
+	muffle_target: f32,
+	muffle_filter: Arc<AtomicU32>,
+	captions_enabled: bool,
+	captions: Vec<Caption>,
 
 	samples: HashMap<String, Sample>,
 }
@@ -32,20 +146,39 @@ impl Sfx
 	{
 		let audio = AudioAddon::init(&core)?;
 		let acodec = AcodecAddon::init(&audio)?;
-		let sink = Sink::new(&audio).map_err(|_| "Couldn't create audio sink".to_string())?;
+		let mut sink = Sink::new(&audio).map_err(|_| "Couldn't create audio sink".to_string())?;
+		let mut world_mixer =
+			Mixer::new(&audio).map_err(|_| "Couldn't create world mixer".to_string())?;
+		world_mixer
+			.attach(&mut sink)
+			.map_err(|_| "Couldn't attach world mixer".to_string())?;
+		let muffle_filter = Arc::new(AtomicU32::new(1f32.to_bits()));
+		world_mixer
+			.set_postprocess_callback(Some(Box::new(Muffler {
+				filter: muffle_filter.clone(),
+				prev: [0.; 2],
+			})))
+			.map_err(|_| "Couldn't set world mixer callback".to_string())?;
 
 		let mut sfx = Sfx {
 			sfx_volume: 0.,
 			music_volume: 0.,
 			audio: audio,
 			acodec: acodec,
+			world_mixer: world_mixer,
 			sink: sink,
-			sample_instances: vec![],
+			voices: vec![],
+			voice_limits: HashMap::new(),
 			stream: None,
 			exclusive_instance: None,
 			exclusive_sounds: vec![],
 			samples: HashMap::new(),
 			music_file: "".into(),
+			muffle: 0.,
+			muffle_target: 0.,
+			muffle_filter: muffle_filter,
+			captions_enabled: false,
+			captions: vec![],
 		};
 		sfx.set_sfx_volume(sfx_volume);
 		sfx.set_music_volume(music_volume);
@@ -67,6 +200,73 @@ impl Sfx
 		})
 	}
 
+	// Importance multiplies the (distance attenuated) volume when deciding which sound to cut.
+	pub fn set_voice_limits(&mut self, name: &str, importance: f32, max_voices: usize)
+	{
+		self.voice_limits.insert(
+			name.to_string(),
+			VoiceLimits {
+				importance: importance,
+				max_voices: max_voices,
+			},
+		);
+	}
+
+	fn voice_limits(&self, name: &str) -> VoiceLimits
+	{
+		self.voice_limits.get(name).copied().unwrap_or(VoiceLimits {
+			importance: 1.,
+			max_voices: DEFAULT_MAX_VOICES_PER_SAMPLE,
+		})
+	}
+
+	// Makes room for a new voice, stealing the least important one if needed. Returns false if
+	// the new sound isn't important enough to play.
+	fn allocate_voice(&mut self, name: &str, priority: f32) -> bool
+	{
+		let max_voices = self.voice_limits(name).max_voices;
+		let same_sample = self.voices.iter().filter(|v| v.name == name).count();
+		let candidates: Vec<_> = if same_sample >= max_voices
+		{
+			self.voices
+				.iter()
+				.enumerate()
+				.filter(|(_, v)| v.name == name)
+				.collect()
+		}
+		else if self.voices.len() >= MAX_VOICES
+		{
+			self.voices.iter().enumerate().collect()
+		}
+		else
+		{
+			return true;
+		};
+		let weakest = candidates
+			.iter()
+			.map(|(i, v)| (*i, v.effective_priority()))
+			.min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+		match weakest
+		{
+			Some((i, weakest_priority)) if weakest_priority < priority =>
+			{
+				let voice = self.voices.swap_remove(i);
+				voice.instance.set_playing(false).ok();
+				true
+			}
+			_ => false,
+		}
+	}
+
+	fn add_voice(&mut self, instance: SampleInstance, name: &str, priority: f32)
+	{
+		self.voices.push(Voice {
+			instance: instance,
+			name: name.to_string(),
+			priority: priority,
+		});
+	}
+
 	pub fn get_sample<'l>(&'l self, name: &str) -> Result<&'l Sample>
 	{
 		Ok(self
@@ -75,9 +275,89 @@ impl Sfx
 			.ok_or_else(|| format!("{name} is not cached!"))?)
 	}
 
-	pub fn update_sounds(&mut self) -> Result<()>
+	// Muffles world sounds, e.g. while a menu is open.
+	pub fn set_muffled(&mut self, muffled: bool)
+	{
+		self.muffle_target = if muffled { 1. } else { 0. };
+	}
+
+	pub fn set_captions(&mut self, enabled: bool)
+	{
+		self.captions_enabled = enabled;
+		if !enabled
+		{
+			self.captions.clear();
+		}
+	}
+
+	pub fn captions(&self) -> &[Caption]
+	{
+		&self.captions
+	}
+
+	fn add_caption(&mut self, name: &str, direction: Option<&str>)
+	{
+		if !self.captions_enabled
+		{
+			return;
+		}
+		let text = if let Some(text) = caption_text(name)
+		{
+			text
+		}
+		else
+		{
+			return;
+		};
+		let text = match direction
+		{
+			Some(direction) => format!("{text} ({direction})"),
+			None => text.to_string(),
+		};
+		if let Some(idx) = self.captions.iter().position(|c| c.text == text)
+		{
+			let mut caption = self.captions.remove(idx);
+			caption.time_left = CAPTION_DURATION;
+			caption.count += 1;
+			self.captions.push(caption);
+		}
+		else
+		{
+			self.captions.push(Caption {
+				text: text,
+				time_left: CAPTION_DURATION,
+				count: 1,
+			});
+		}
+		if self.captions.len() > MAX_CAPTIONS
+		{
+			self.captions.remove(0);
+		}
+	}
+
+	pub fn update_sounds(&mut self, dt: f32) -> Result<()>
 	{
-		self.sample_instances.retain(|s| s.get_playing().unwrap());
+		self.voices.retain(|v| v.instance.get_playing().unwrap());
+		for caption in &mut self.captions
+		{
+			caption.time_left -= dt;
+		}
+		self.captions.retain(|c| c.time_left > 0.);
+		if self.muffle != self.muffle_target
+		{
+			self.muffle +=
+				(self.muffle_target - self.muffle) * utils::approach_frac(MUFFLE_RATE, dt);
+			if (self.muffle - self.muffle_target).abs() < 0.01
+			{
+				self.muffle = self.muffle_target;
+			}
+			self.world_mixer
+				.set_gain(1. - (1. - MUFFLED_GAIN) * self.muffle)
+				.ok();
+			let filter = 1. - (1. - MUFFLED_FILTER) * self.muffle;
+			self.muffle_filter
+				.store(filter.to_bits(), Ordering::Relaxed);
+		}
 		if let Some(ref stream) = self.stream
 		{
 			if !stream.get_playing()
@@ -118,6 +398,10 @@ impl Sfx
 	pub fn play_sound(&mut self, name: &str) -> Result<()>
 	{
 		self.cache_sample(name)?;
+		if !self.allocate_voice(name, UI_PRIORITY)
+		{
+			return Ok(());
+		}
 		let sample = self.samples.get(name).unwrap();
 		let instance = self
 			.sink
@@ -129,16 +413,17 @@ impl Sfx
 				Playmode::Once,
 			)
 			.map_err(|_| "Couldn't play sound".to_string())?;
-		self.sample_instances.push(instance);
+		self.add_voice(instance, name, UI_PRIORITY);
 		Ok(())
 	}
 
 	pub fn play_continuous_sound(&mut self, name: &str, volume: f32) -> Result<SampleInstance>
 	{
 		self.cache_sample(name)?;
+		self.add_caption(name, None);
 		let sample = self.samples.get(name).unwrap();
 		let instance = self
-			.sink
+			.world_mixer
 			.play_sample(sample, self.sfx_volume * volume, None, 1., Playmode::Loop)
 			.map_err(|_| "Couldn't play sound".to_string())?;
 		Ok(instance)
@@ -151,6 +436,11 @@ impl Sfx
 		self.cache_sample(name)?;
 		let dist_sq = (sound_pos - camera_pos).norm_squared();
 		let nominal_dist = 50.0;
+		// Captions go by what could be heard, not by the volume setting.
+		if volume * nominal_dist * nominal_dist / dist_sq >= 0.01
+		{
+			self.add_caption(name, Some(caption_direction(sound_pos - camera_pos)));
+		}
 		let volume = self.sfx_volume
 			* utils::clamp(
 				self.sfx_volume * volume * nominal_dist * nominal_dist / dist_sq,
@@ -162,7 +452,8 @@ impl Sfx
 			return Ok(());
 		}
 
-		if self.sample_instances.len() < 50
+		let priority = volume * self.voice_limits(name).importance;
+		if self.allocate_voice(name, priority)
 		{
 			let sample = self.samples.get(name).unwrap();
 
@@ -171,7 +462,7 @@ impl Sfx
 			let pan = (diff.x / 20.).tanh();
 
 			let instance = self
-				.sink
+				.world_mixer
 				.play_sample(
 					sample,
 					volume,
@@ -180,7 +471,7 @@ impl Sfx
 					Playmode::Once,
 				)
 				.map_err(|_| "Couldn't play sound".to_string())?;
-			self.sample_instances.push(instance);
+			self.add_voice(instance, name, priority);
 		}
 		Ok(())
 	}
@@ -211,6 +502,12 @@ impl Sfx
 		}
 	}
 
+	// Silences everything, including music, for the rest of the session.
+	pub fn mute(&mut self)
+	{
+		self.sink.set_gain(0.).ok();
+	}
+
 	pub fn set_sfx_volume(&mut self, new_volume: f32)
 	{
 		self.sfx_volume = new_volume;
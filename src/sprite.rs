@@ -5,6 +5,8 @@ use allegro::*;
 use na::Point2;
 use nalgebra as na;
 use serde_derive::{Deserialize, Serialize};
+use slr_config::ConfigElement;
+use std::path;
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 struct SpriteDesc
@@ -18,6 +20,41 @@ struct SpriteDesc
 	center_y: i32,
 }
 
+fn validate_sprite_desc<'l>(
+	desc: &SpriteDesc, element: &'l ConfigElement,
+) -> Vec<(&'l ConfigElement, String)>
+{
+	let mut problems = vec![];
+	if !path::Path::new(&desc.bitmap).exists()
+	{
+		problems.push((
+			utils::config_field(element, &["bitmap"]),
+			format!("bitmap '{}' does not exist", desc.bitmap),
+		));
+	}
+	if desc.width <= 0
+	{
+		problems.push((
+			utils::config_field(element, &["width"]),
+			"width must be positive".to_string(),
+		));
+	}
+	if desc.height <= 0
+	{
+		problems.push((
+			utils::config_field(element, &["height"]),
+			"height must be positive".to_string(),
+		));
+	}
+	problems
+}
+
+pub fn validate_sprite(sprite: &str) -> Result<()>
+{
+	utils::load_validated_config::<SpriteDesc, _>(sprite, validate_sprite_desc)?;
+	Ok(())
+}
+
 #[derive(Clone, Debug)]
 pub struct Sprite
 {
@@ -29,7 +66,7 @@ impl Sprite
 {
 	pub fn load(sprite: &str, core: &Core, atlas: &mut atlas::Atlas) -> Result<Sprite>
 	{
-		let desc: SpriteDesc = utils::load_config(sprite)?;
+		let desc: SpriteDesc = utils::load_validated_config(sprite, validate_sprite_desc)?;
 
 		let bitmap = utils::load_bitmap(&core, &desc.bitmap)?;
 
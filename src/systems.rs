@@ -0,0 +1,236 @@
+use crate::components as comps;
+use crate::utils;
+
+use hecs::{Entity, PreparedQuery, World};
+
+// Queries that run every tick. Preparing them once lets hecs remember which archetypes match
+// instead of working it out again each time.
+#[derive(Default)]
+pub struct Queries
+{
+	positions: PreparedQuery<(
+		&'static comps::Position,
+		Option<&'static mut comps::PrevPosition>,
+	)>,
+	gravity: PreparedQuery<(
+		&'static comps::AffectedByGravity,
+		&'static mut comps::Velocity,
+	)>,
+	movement: PreparedQuery<(&'static mut comps::Position, &'static comps::Velocity)>,
+	submerged: PreparedQuery<(&'static mut comps::Submerged, &'static mut comps::Velocity)>,
+	time_to_die: PreparedQuery<&'static comps::TimeToDie>,
+	attached: PreparedQuery<&'static comps::Attached>,
+}
+
+impl Queries
+{
+	pub fn new() -> Self
+	{
+		Self::default()
+	}
+
+	// Remember where everything was, so drawing can interpolate. Returns the entities that don't
+	// have a previous position yet.
+	pub fn prev_positions(&mut self, world: &mut World) -> Vec<(Entity, comps::PrevPosition)>
+	{
+		let mut new_prev = vec![];
+		for (id, (pos, prev)) in self.positions.query_mut(world)
+		{
+			if let Some(prev) = prev
+			{
+				prev.pos = pos.pos;
+				prev.dir = pos.dir;
+			}
+			else
+			{
+				new_prev.push((
+					id,
+					comps::PrevPosition {
+						pos: pos.pos,
+						dir: pos.dir,
+					},
+				));
+			}
+		}
+		new_prev
+	}
+
+	pub fn physics(&mut self, world: &mut World, dt: f32, gravity: f32)
+	{
+		for (_, (_, vel)) in self.gravity.query_mut(world)
+		{
+			vel.vel.y -= dt * gravity;
+		}
+		for (_, (pos, vel)) in self.movement.query_mut(world)
+		{
+			pos.pos += dt * vel.vel;
+			pos.dir += dt * vel.dir_vel;
+		}
+	}
+
+	// Moves attached things to wherever their parents ended up. If the parent is gone, they stay
+	// where they were last.
+	pub fn attachments(&mut self, world: &mut World)
+	{
+		let mut moves = vec![];
+		for (id, attached) in self.attached.query(world).iter()
+		{
+			if let Ok(mut parent) =
+				world.query_one::<(&comps::Position, Option<&comps::Tilt>)>(attached.parent)
+			{
+				if let Some((pos, tilt)) = parent.get()
+				{
+					let iso = pos.to_isometry(tilt);
+					moves.push((id, iso.transform_point(&attached.offset), pos.dir));
+				}
+			}
+		}
+		for (id, new_pos, dir) in moves
+		{
+			if let Ok(mut pos) = world.get::<&mut comps::Position>(id)
+			{
+				pos.pos = new_pos;
+				pos.dir = dir;
+			}
+		}
+	}
+
+	pub fn submerged(&mut self, world: &mut World, dt: f32, to_die: &mut Vec<Entity>)
+	{
+		for (id, (submerged, vel)) in self.submerged.query_mut(world)
+		{
+			submerged.time_left -= dt;
+			vel.vel *= 1. - utils::approach_frac(submerged.drag, dt);
+			if submerged.time_left <= 0.
+			{
+				to_die.push(id);
+			}
+		}
+	}
+
+	pub fn expired(&mut self, world: &mut World, time: f64, to_die: &mut Vec<Entity>)
+	{
+		for (id, time_to_die) in self.time_to_die.query_mut(world)
+		{
+			if time > time_to_die.time_to_die
+			{
+				to_die.push(id);
+			}
+		}
+	}
+}
+
+// Compares the prepared physics queries against building them every tick. Run with:
+// cargo test --release bench_physics -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_physics()
+{
+	use nalgebra::{Point3, Vector3};
+	use std::time::Instant;
+
+	const ENTITIES: usize = 600;
+	const TICKS: usize = 2000;
+	let dt = 1. / 60.;
+
+	let make_world = || {
+		let mut world = World::new();
+		for i in 0..ENTITIES
+		{
+			let pos = comps::Position {
+				pos: Point3::new(i as f32, 0., 0.),
+				dir: 0.,
+			};
+			let vel = comps::Velocity {
+				vel: Vector3::new(1., 2., 3.),
+				dir_vel: 0.1,
+			};
+			// Spread things over a few archetypes, like the game does.
+			match i % 3
+			{
+				0 => world.spawn((pos, vel, comps::AffectedByGravity)),
+				1 => world.spawn((pos, vel, comps::TimeToDie { time_to_die: 1. })),
+				_ => world.spawn((pos, vel)),
+			};
+		}
+		world
+	};
+
+	let mut world = make_world();
+	let start = Instant::now();
+	for _ in 0..TICKS
+	{
+		for (_, (_, vel)) in world
+			.query::<(&comps::AffectedByGravity, &mut comps::Velocity)>()
+			.iter()
+		{
+			vel.vel.y -= dt * 9.8;
+		}
+		for (_, (pos, vel)) in world
+			.query::<(&mut comps::Position, &comps::Velocity)>()
+			.iter()
+		{
+			pos.pos += dt * vel.vel;
+			pos.dir += dt * vel.dir_vel;
+		}
+	}
+	let adhoc = start.elapsed();
+
+	let mut prepared_world = make_world();
+	let mut queries = Queries::new();
+	let start = Instant::now();
+	for _ in 0..TICKS
+	{
+		queries.physics(&mut prepared_world, dt, 9.8);
+	}
+	let prepared = start.elapsed();
+
+	println!("{ENTITIES} entities, {TICKS} ticks: query {adhoc:?}, prepared {prepared:?}");
+	let positions = |world: &World| {
+		world
+			.query::<&comps::Position>()
+			.iter()
+			.map(|(_, pos)| pos.pos)
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(positions(&world), positions(&prepared_world));
+}
+
+#[test]
+fn test_attachments()
+{
+	use nalgebra::Point3;
+
+	let mut world = World::new();
+	let ship = world.spawn((
+		comps::Position {
+			pos: Point3::new(1., 0., 0.),
+			dir: 0.,
+		},
+		comps::Tilt {
+			tilt: 0.,
+			target_tilt: 0.,
+			heel: std::f32::consts::FRAC_PI_2,
+		},
+	));
+	let flash = world.spawn((
+		comps::Position {
+			pos: Point3::origin(),
+			dir: 0.,
+		},
+		comps::Attached {
+			parent: ship,
+			offset: Point3::new(0., 1., 0.),
+		},
+	));
+	let mut queries = Queries::new();
+	queries.attachments(&mut world);
+	// Heeled all the way over, so the mast points sideways.
+	let pos = world.get::<&comps::Position>(flash).unwrap().pos;
+	assert!((pos - Point3::new(2., 0., 0.)).norm() < 1e-5, "{pos}");
+
+	world.despawn(ship).unwrap();
+	queries.attachments(&mut world);
+	let pos = world.get::<&comps::Position>(flash).unwrap().pos;
+	assert!((pos - Point3::new(2., 0., 0.)).norm() < 1e-5, "{pos}");
+}
@@ -0,0 +1,163 @@
+use crate::error::Result;
+use crate::{message, utils};
+
+use serde_derive::Deserialize;
+
+// Things that happen in the world that quests, tutorials and the like can react to.
+#[derive(Clone, Debug)]
+pub enum Event
+{
+	Spawned
+	{
+		boss: bool,
+	},
+	Disabled
+	{
+		boss: bool,
+		by_player: bool,
+	},
+	Docked
+	{
+		trade: bool,
+	},
+	BossPhase(i32),
+}
+
+impl Event
+{
+	fn hook(&self) -> Hook
+	{
+		match self
+		{
+			Event::Spawned { .. } => Hook::OnSpawn,
+			Event::Disabled { .. } => Hook::OnDisabled,
+			Event::Docked { .. } => Hook::OnDocked,
+			Event::BossPhase(_) => Hook::OnBossPhase,
+		}
+	}
+}
+
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Hook
+{
+	OnSpawn,
+	OnDisabled,
+	OnDocked,
+	OnBossPhase,
+}
+
+fn default_kind() -> message::MessageKind
+{
+	message::MessageKind::Quest
+}
+
+// One entry of a triggers config. The optional fields narrow down which events it fires on.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Trigger
+{
+	on: Hook,
+	// Only fire the first time.
+	#[serde(default)]
+	once: bool,
+	#[serde(default)]
+	phase: Option<i32>,
+	#[serde(default)]
+	boss: Option<bool>,
+	#[serde(default)]
+	by_player: Option<bool>,
+	#[serde(default)]
+	trade: Option<bool>,
+
+	message: String,
+	#[serde(default = "default_kind")]
+	kind: message::MessageKind,
+	#[serde(default)]
+	toast: bool,
+}
+
+impl Trigger
+{
+	fn matches(&self, event: &Event) -> bool
+	{
+		let check = |want: Option<bool>, have: bool| want.map(|w| w == have).unwrap_or(true);
+		self.on == event.hook()
+			&& match *event
+			{
+				Event::Spawned { boss } => check(self.boss, boss),
+				Event::Disabled { boss, by_player } =>
+				{
+					check(self.boss, boss) && check(self.by_player, by_player)
+				}
+				Event::Docked { trade } => check(self.trade, trade),
+				Event::BossPhase(phase) => self.phase.map(|p| p == phase).unwrap_or(true),
+			}
+	}
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct TriggersDesc
+{
+	triggers: Vec<Trigger>,
+}
+
+// What a trigger wants done, the Map carries it out.
+#[derive(Clone, Debug)]
+pub enum Effect
+{
+	Message(message::MessageKind, String),
+	Toast(message::MessageKind, String),
+}
+
+// Events are queued up as they happen and handled all at once at the end of the tick.
+pub struct Triggers
+{
+	triggers: Vec<Trigger>,
+	fired: Vec<bool>,
+	pending: Vec<Event>,
+}
+
+impl Triggers
+{
+	pub fn load(file: &str) -> Result<Self>
+	{
+		let desc = utils::load_config::<TriggersDesc>(file)?;
+		Ok(Self {
+			fired: vec![false; desc.triggers.len()],
+			triggers: desc.triggers,
+			pending: vec![],
+		})
+	}
+
+	pub fn fire(&mut self, event: Event)
+	{
+		self.pending.push(event);
+	}
+
+	pub fn process(&mut self) -> Vec<Effect>
+	{
+		let mut effects = vec![];
+		for event in self.pending.drain(..)
+		{
+			for (trigger, fired) in self.triggers.iter().zip(self.fired.iter_mut())
+			{
+				if (trigger.once && *fired) || !trigger.matches(&event)
+				{
+					continue;
+				}
+				*fired = true;
+				effects.push(
+					if trigger.toast
+					{
+						Effect::Toast(trigger.kind, trigger.message.clone())
+					}
+					else
+					{
+						Effect::Message(trigger.kind, trigger.message.clone())
+					},
+				);
+			}
+		}
+		effects
+	}
+}
@@ -1,5 +1,5 @@
 use crate::error::Result;
-use crate::{components, controls, game_state, utils};
+use crate::{components, controls, curses, exploration, game_state, net, packs, utils};
 
 use crate::utils::ColorExt;
 
@@ -8,35 +8,158 @@ use allegro_font::*;
 use allegro_sys::*;
 use nalgebra::{Matrix4, Point2, Vector2, Vector3};
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Mirrors Options::high_contrast, so the colors can be picked without the state at hand.
+static HIGH_CONTRAST: AtomicBool = AtomicBool::new(false);
+
+pub fn set_high_contrast(high_contrast: bool)
+{
+	HIGH_CONTRAST.store(high_contrast, Ordering::Relaxed);
+}
+
+pub fn high_contrast() -> bool
+{
+	HIGH_CONTRAST.load(Ordering::Relaxed)
+}
+
 pub fn ui_color() -> Color
 {
-	Color::from_rgb_f(0.6, 0.8, 0.9)
+	if high_contrast()
+	{
+		Color::from_rgb_f(1., 0.95, 0.3)
+	}
+	else
+	{
+		Color::from_rgb_f(0.6, 0.8, 0.9)
+	}
+}
+
+// What goes behind panels of text. High contrast makes them closer to opaque.
+pub fn backdrop(alpha: f32) -> Color
+{
+	let alpha = if high_contrast()
+	{
+		(1.5 * alpha).min(0.95)
+	}
+	else
+	{
+		alpha
+	};
+	Color::from_rgba_f(0., 0., 0., alpha)
+}
+
+// For text drawn straight over the scene, which gets a dark outline so it stands out against
+// bright water. It's heavier in high contrast.
+pub fn draw_floating_text(
+	state: &game_state::GameState, color: Color, x: f32, y: f32, align: FontAlign, text: &str,
+)
+{
+	let (r, opacity) = if high_contrast()
+	{
+		((state.m / 12.).max(1.), 1.)
+	}
+	else
+	{
+		(1., 0.6)
+	};
+	let (_, _, _, a) = color.to_rgba_f();
+	let outline = Color::from_rgba_f(0., 0., 0., a * opacity);
+	for (dx, dy) in [
+		(-1., -1.),
+		(0., -1.),
+		(1., -1.),
+		(-1., 0.),
+		(1., 0.),
+		(-1., 1.),
+		(0., 1.),
+		(1., 1.),
+	]
+	{
+		state
+			.core
+			.draw_text(&state.ui_font, outline, x + dx * r, y + dy * r, align, text);
+	}
+	state
+		.core
+		.draw_text(&state.ui_font, color, x, y, align, text);
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Action
 {
 	SelectMe,
+	// A button without an action of its own got pressed.
+	Pressed,
 	MainMenu,
 	Start,
+	Spectate,
 	Quit,
 	Back,
 	Forward(fn(&mut game_state::GameState) -> SubScreen),
 	ToggleFullscreen,
+	ToggleCaptions,
+	ToggleAdaptiveDifficulty,
+	ToggleFriendlyFire,
+	Monitor(i32),
 	ChangeInput(controls::Action, usize),
+	ControlsPreset(usize),
+	ImportControls,
+	ExportControls,
 	MouseSensitivity(f32),
 	MusicVolume(f32),
 	SfxVolume(f32),
+	FeedbackIntensity(f32),
+	FontScale(f32),
+	ToggleHighContrast,
+	MuteMessages(usize, bool),
+	ToggleCurse(usize, bool),
+	HostCoop,
+	JoinCoop,
+	TickRate(i32),
+	LootAction(components::LootAction),
+	LootKind(usize, bool),
+	LootRarity(usize),
+	LootLevel(f32),
+	TradeQuantity(f32),
+	BuyQuantity(i32),
+	CaptureCrew(f32),
+	CaptureWounded(f32),
+	CaptureOfficers(f32),
+	// Crew, wounded and officers to take across to the prize.
+	Capture(i32, i32, i32),
+	OpenFire,
+	PrevPack,
+	NextPack,
+	TogglePack(bool),
+	// Global cell to sail to.
+	SetCourse(Point2<i32>),
+	ClearCourse,
+	ChartNotes(Vec<exploration::Note>),
 }
 
+// How a button looks: a line of text, or a sprite with normal, hover and pressed variants.
 #[derive(Clone)]
-struct Button
+pub enum ButtonFace
 {
-	loc: Point2<f32>,
-	size: Vector2<f32>,
-	text: String,
+	Text(String),
+	Sprite(String),
+}
+
+// Clicks activate on release, but the press is already claimed so nothing underneath sees it.
+#[derive(Clone)]
+pub struct Button
+{
+	pub loc: Point2<f32>,
+	pub size: Vector2<f32>,
+	face: ButtonFace,
 	action: Action,
 	selected: bool,
+	pub hover: bool,
+	pressed: bool,
+	// Toggles stay on until clicked again, other buttons until `logic` sees them.
+	pub on: bool,
+	is_toggle: bool,
 }
 
 impl Button
@@ -46,9 +169,28 @@ impl Button
 		Self {
 			loc: Point2::new(x, y),
 			size: Vector2::new(w, h),
-			text: text.into(),
+			face: ButtonFace::Text(text.into()),
 			action: action,
 			selected: false,
+			hover: false,
+			pressed: false,
+			on: false,
+			is_toggle: false,
+		}
+	}
+
+	pub fn new_sprite(loc: Point2<f32>, size: Vector2<f32>, is_toggle: bool, sprite: &str) -> Self
+	{
+		Self {
+			loc: loc,
+			size: size,
+			face: ButtonFace::Sprite(sprite.into()),
+			action: Action::Pressed,
+			selected: false,
+			hover: false,
+			pressed: false,
+			on: false,
+			is_toggle: is_toggle,
 		}
 	}
 
@@ -62,37 +204,95 @@ impl Button
 		self.size.y
 	}
 
-	fn draw(&self, state: &game_state::GameState)
+	fn set_text(&mut self, text: String)
 	{
-		let c_ui = if self.selected
+		self.face = ButtonFace::Text(text);
+	}
+
+	fn contains(&self, x: f32, y: f32) -> bool
+	{
+		let start = self.loc - self.size / 2.;
+		let end = self.loc + self.size / 2.;
+		x > start.x && x < end.x && y > start.y && y < end.y
+	}
+
+	// Whether the button got pressed since the last call. Toggles just report their state.
+	pub fn logic(&mut self) -> bool
+	{
+		let old_on = self.on;
+		if !self.is_toggle
 		{
-			Color::from_rgb_f(1., 1., 1.)
+			self.on = false;
 		}
-		else
+		old_on
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		match &self.face
 		{
-			ui_color()
-		};
+			ButtonFace::Text(text) =>
+			{
+				let c_ui = if self.selected
+				{
+					Color::from_rgb_f(1., 1., 1.)
+				}
+				else
+				{
+					ui_color()
+				};
 
-		state.core.draw_text(
-			&state.ui_font,
-			c_ui,
-			self.loc.x,
-			self.loc.y - state.ui_font.get_line_height() as f32 / 2.,
-			FontAlign::Centre,
-			&self.text,
-		);
+				state.core.draw_text(
+					&state.ui_font,
+					c_ui,
+					self.loc.x,
+					self.loc.y - state.ui_font.get_line_height() as f32 / 2.,
+					FontAlign::Centre,
+					text,
+				);
+			}
+			ButtonFace::Sprite(sprite) =>
+			{
+				let variant = if self.on || self.pressed
+				{
+					2
+				}
+				else if self.hover || self.selected
+				{
+					1
+				}
+				else
+				{
+					0
+				};
+				state.get_sprite(sprite).unwrap().draw(
+					self.loc,
+					variant,
+					Color::from_rgb_f(1., 1., 1.),
+					state,
+				);
+			}
+		}
 	}
 
-	fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	fn activate(&mut self, state: &mut game_state::GameState) -> Option<Action>
+	{
+		self.pressed = false;
+		self.on = if self.is_toggle { !self.on } else { true };
+		state.sfx.play_sound("data/ui2.ogg").unwrap();
+		Some(self.action.clone())
+	}
+
+	// Returns SelectMe when the mouse is over the button or presses it, and the button's
+	// action once it's activated.
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
 	{
-		let start = self.loc - self.size / 2.;
-		let end = self.loc + self.size / 2.;
 		match event
 		{
 			Event::MouseAxes { x, y, .. } =>
 			{
-				let (x, y) = (*x as f32, *y as f32);
-				if x > start.x && x < end.x && y > start.y && y < end.y
+				self.hover = self.contains(*x as f32, *y as f32);
+				if self.hover
 				{
 					return Some(Action::SelectMe);
 				}
@@ -103,28 +303,37 @@ impl Button
 				{
 					if self.selected
 					{
-						state.sfx.play_sound("data/ui2.ogg").unwrap();
-						return Some(self.action.clone());
+						return self.activate(state);
 					}
 				}
 				KeyCode::Escape =>
 				{
 					if self.action == Action::Back
 					{
-						state.sfx.play_sound("data/ui2.ogg").unwrap();
-						return Some(self.action.clone());
+						return self.activate(state);
 					}
 				}
 				_ => (),
 			},
-			Event::MouseButtonUp { x, y, .. } =>
+			Event::MouseButtonDown {
+				button: 1, x, y, ..
+			} =>
 			{
-				let (x, y) = (*x as f32, *y as f32);
-				if x > start.x && x < end.x && y > start.y && y < end.y
+				if self.contains(*x as f32, *y as f32)
 				{
-					state.sfx.play_sound("data/ui2.ogg").unwrap();
-					return Some(self.action.clone());
+					self.pressed = true;
+					return Some(Action::SelectMe);
+				}
+			}
+			Event::MouseButtonUp {
+				button: 1, x, y, ..
+			} =>
+			{
+				if self.contains(*x as f32, *y as f32)
+				{
+					return self.activate(state);
 				}
+				self.pressed = false;
 			}
 			_ => (),
 		}
@@ -807,7 +1016,23 @@ impl MainMenu
 						w,
 						h,
 						"New Game",
-						Action::Start,
+						Action::Forward(|s| SubScreen::CursesMenu(CursesMenu::new(s))),
+					))],
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Co-op",
+						Action::Forward(|s| SubScreen::LobbyMenu(LobbyMenu::new(s))),
+					))],
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Spectate",
+						Action::Spectate,
 					))],
 					&[Widget::Button(Button::new(
 						0.,
@@ -825,6 +1050,14 @@ impl MainMenu
 						"Options",
 						Action::Forward(|s| SubScreen::OptionsMenu(OptionsMenu::new(s))),
 					))],
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Packs",
+						Action::Forward(|s| SubScreen::PacksMenu(PacksMenu::new(s))),
+					))],
 					&[Widget::Button(Button::new(
 						0.,
 						0.,
@@ -880,15 +1113,43 @@ impl ControlsMenu
 		// 	)),
 		// ]);
 
+		widgets.push(vec![
+			Widget::Label(Label::new(0., 0., w, h, "Preset")),
+			Widget::Toggle(Toggle::new(
+				0.,
+				0.,
+				w,
+				h,
+				Self::preset_index(state),
+				Self::preset_names(),
+				|i| Action::ControlsPreset(i),
+			)),
+			Widget::Button(Button::new(
+				0.,
+				0.,
+				w / 2.,
+				h,
+				"Import",
+				Action::ImportControls,
+			)),
+			Widget::Button(Button::new(
+				0.,
+				0.,
+				w / 2.,
+				h,
+				"Export",
+				Action::ExportControls,
+			)),
+		]);
+		widgets.push(vec![Widget::Label(Label::new(0., 0., w * 3., h, ""))]);
+
 		for (&action, &inputs) in state.controls.get_actions_to_inputs()
 		{
 			let mut row = vec![Widget::Label(Label::new(0., 0., w, h, &action.to_str()))];
 			for i in 0..2
 			{
 				let input = inputs[i];
-				let input_str = input
-					.map(|i| i.to_str().to_string())
-					.unwrap_or("None".into());
+				let input_str = input.map(|i| i.to_string()).unwrap_or("None".into());
 				row.push(Widget::Button(Button::new(
 					0.,
 					0.,
@@ -921,6 +1182,33 @@ impl ControlsMenu
 		}
 	}
 
+	// The presets, and Custom once the bindings no longer match any of them.
+	fn preset_names() -> Vec<String>
+	{
+		controls::Preset::ALL
+			.iter()
+			.map(|p| p.name().to_string())
+			.chain(["Custom".to_string()])
+			.collect()
+	}
+
+	fn preset_index(state: &game_state::GameState) -> usize
+	{
+		let preset = state.controls.get_controls().matching_preset();
+		controls::Preset::ALL
+			.iter()
+			.position(|p| Some(*p) == preset)
+			.unwrap_or(controls::Preset::ALL.len())
+	}
+
+	fn set_status(&mut self, text: String)
+	{
+		if let Widget::Label(label) = &mut self.widgets.widgets[1][0]
+		{
+			label.text = text;
+		}
+	}
+
 	pub fn draw(&self, state: &game_state::GameState)
 	{
 		self.widgets.draw(state);
@@ -981,7 +1269,7 @@ impl ControlsMenu
 					match &mut self.widgets.widgets[self.widgets.cur_selection.0]
 						[self.widgets.cur_selection.1]
 					{
-						Widget::Button(b) => b.text = "<Input>".into(),
+						Widget::Button(b) => b.set_text("<Input>".into()),
 						_ => (),
 					}
 				}
@@ -990,11 +1278,46 @@ impl ControlsMenu
 					state.controls.set_mouse_sensitivity(ms);
 					options_changed = true;
 				}
+				Some(Action::ControlsPreset(i)) =>
+				{
+					// Custom is just where the bindings end up, there's nothing to switch to.
+					if let Some(preset) = controls::Preset::ALL.get(i)
+					{
+						state
+							.controls
+							.set_controls(controls::Controls::preset(*preset));
+						self.set_status(format!("Switched to the {} preset.", preset.name()));
+						options_changed = true;
+					}
+				}
+				Some(Action::ImportControls) => match game_state::import_controls(&state.core)
+				{
+					Ok(controls) =>
+					{
+						state.controls.set_controls(controls);
+						self.set_status("Imported the controls.".into());
+						options_changed = true;
+					}
+					Err(e) => self.set_status(format!("Couldn't import the controls: {e}")),
+				},
+				Some(Action::ExportControls) =>
+				{
+					let status = match game_state::export_controls(
+						&state.core,
+						state.controls.get_controls(),
+					)
+					{
+						Ok(path) => format!("Exported the controls to {path}."),
+						Err(e) => format!("Couldn't export the controls: {e}"),
+					};
+					self.set_status(status);
+				}
 				_ => (),
 			}
 		}
 		if options_changed
 		{
+			let preset_index = Self::preset_index(state);
 			for widget_row in &mut self.widgets.widgets
 			{
 				for widget in widget_row
@@ -1005,11 +1328,14 @@ impl ControlsMenu
 						{
 							if let Action::ChangeInput(action, index) = b.action
 							{
-								b.text = state.controls.get_inputs(action).unwrap()[index]
-									.map(|a| a.to_str().to_string())
-									.unwrap_or("None".into());
+								b.set_text(
+									state.controls.get_inputs(action).unwrap()[index]
+										.map(|a| a.to_string())
+										.unwrap_or("None".into()),
+								);
 							}
 						}
+						Widget::Toggle(t) => t.cur_value = preset_index,
 						_ => (),
 					}
 				}
@@ -1024,6 +1350,7 @@ impl ControlsMenu
 pub struct OptionsMenu
 {
 	widgets: WidgetList,
+	error: Option<String>,
 }
 
 impl OptionsMenu
@@ -1049,6 +1376,21 @@ impl OptionsMenu
 					|_| Action::ToggleFullscreen,
 				)),
 			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Monitor")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					(state.options.monitor.max(0) as usize)
+						.min(state.core.get_num_video_adapters().max(1) as usize - 1),
+					(0..state.core.get_num_video_adapters().max(1))
+						.map(|i| format!("{}", i + 1))
+						.collect(),
+					|v| Action::Monitor(v as i32),
+				)),
+			],
 			vec![
 				Widget::Label(Label::new(0., 0., w, h, "Music")),
 				Widget::Slider(Slider::new(
@@ -1077,98 +1419,747 @@ impl OptionsMenu
 					|i| Action::SfxVolume(i),
 				)),
 			],
-			vec![Widget::Button(Button::new(
-				0.,
-				0.,
-				w,
-				h,
-				"Back",
-				Action::Back,
-			))],
-		];
-
-		Self {
-			widgets: WidgetList::new(
-				cx,
-				cy,
-				2. * h,
-				h,
-				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
-			),
-		}
-	}
-
-	pub fn draw(&self, state: &game_state::GameState)
-	{
-		self.widgets.draw(state);
-	}
-
-	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
-	{
-		let mut options_changed = false;
-		let action = self.widgets.input(state, event);
-		if let Some(action) = action
-		{
-			match action
-			{
-				Action::ToggleFullscreen =>
-				{
-					state.options.fullscreen = !state.options.fullscreen;
-					options_changed = true;
-				}
-				Action::MusicVolume(v) =>
-				{
-					state.options.music_volume = v;
-					state.sfx.set_music_volume(v);
-					options_changed = true;
-				}
-				Action::SfxVolume(v) =>
-				{
-					state.options.sfx_volume = v;
-					state.sfx.set_sfx_volume(v);
-					options_changed = true;
-				}
-				_ => return Some(action),
-			}
-		}
-		if options_changed
-		{
-			game_state::save_options(&state.core, &state.options).unwrap();
-		}
-		None
-	}
-}
-
-pub struct InGameMenu
-{
-	widgets: WidgetList,
-}
-
-impl InGameMenu
-{
-	pub fn new(state: &game_state::GameState) -> Self
-	{
-		let m = state.m;
-		let w = m * 6.;
-		let h = m;
-		let cx = state.display_width / 2.;
-		let cy = state.display_height / 2.;
-
-		Self {
-			widgets: WidgetList::new(
-				cx,
-				cy,
-				h,
-				h,
-				&[
-					&[Widget::Button(Button::new(
-						0.,
-						0.,
-						w,
-						h,
-						"Resume",
-						Action::Back,
-					))],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Sound Captions")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					state.options.captions as usize,
+					vec!["No".into(), "Yes".into()],
+					|_| Action::ToggleCaptions,
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Adaptive Difficulty")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					state.options.adaptive_difficulty as usize,
+					vec!["No".into(), "Yes".into()],
+					|_| Action::ToggleAdaptiveDifficulty,
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Friendly Fire")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					state.options.friendly_fire as usize,
+					vec!["No".into(), "Yes".into()],
+					|_| Action::ToggleFriendlyFire,
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Impact Effects")),
+				Widget::Slider(Slider::new(
+					0.,
+					0.,
+					w,
+					h,
+					state.options.feedback_intensity,
+					0.,
+					2.,
+					false,
+					|i| Action::FeedbackIntensity(i),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Text Size")),
+				Widget::Slider(Slider::new(
+					0.,
+					0.,
+					w,
+					h,
+					state.options.ui_font_scale,
+					1.,
+					1.5,
+					false,
+					|i| Action::FontScale(i),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "High Contrast")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					state.options.high_contrast as usize,
+					vec!["No".into(), "Yes".into()],
+					|_| Action::ToggleHighContrast,
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Combat Messages")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					!state.options.muted_messages[0] as usize,
+					vec!["Muted".into(), "Shown".into()],
+					|v| Action::MuteMessages(0, v == 0),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Trade Messages")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					!state.options.muted_messages[1] as usize,
+					vec!["Muted".into(), "Shown".into()],
+					|v| Action::MuteMessages(1, v == 0),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Quest Messages")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					!state.options.muted_messages[2] as usize,
+					vec!["Muted".into(), "Shown".into()],
+					|v| Action::MuteMessages(2, v == 0),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "System Messages")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					!state.options.muted_messages[3] as usize,
+					vec!["Muted".into(), "Shown".into()],
+					|v| Action::MuteMessages(3, v == 0),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Tick Rate")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					utils::TICK_RATES
+						.iter()
+						.position(|&r| r == state.options.tick_rate)
+						.unwrap_or(utils::TICK_RATES.len() - 1),
+					utils::TICK_RATES
+						.iter()
+						.map(|r| format!("{r} Hz"))
+						.collect(),
+					|v| Action::TickRate(utils::TICK_RATES[v]),
+				)),
+			],
+			vec![Widget::Button(Button::new(
+				0.,
+				0.,
+				w,
+				h,
+				"Back",
+				Action::Back,
+			))],
+		];
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				2. * h,
+				h,
+				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
+			),
+			error: None,
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		self.widgets.draw(state);
+		if let Some(error) = &self.error
+		{
+			state.core.draw_text(
+				&state.ui_font,
+				Color::from_rgb_f(1., 0.3, 0.3),
+				state.display_width / 2.,
+				state.display_height - 2. * state.m,
+				FontAlign::Centre,
+				error,
+			);
+		}
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		let mut options_changed = false;
+		let action = self.widgets.input(state, event);
+		if let Some(action) = action
+		{
+			match action
+			{
+				Action::ToggleFullscreen =>
+				{
+					state.options.fullscreen = !state.options.fullscreen;
+					options_changed = true;
+				}
+				Action::Monitor(monitor) =>
+				{
+					state.options.monitor = monitor;
+					options_changed = true;
+				}
+				Action::MusicVolume(v) =>
+				{
+					state.options.music_volume = v;
+					state.sfx.set_music_volume(v);
+					options_changed = true;
+				}
+				Action::SfxVolume(v) =>
+				{
+					state.options.sfx_volume = v;
+					state.sfx.set_sfx_volume(v);
+					options_changed = true;
+				}
+				Action::ToggleCaptions =>
+				{
+					state.options.captions = !state.options.captions;
+					state.sfx.set_captions(state.options.captions);
+					options_changed = true;
+				}
+				Action::ToggleAdaptiveDifficulty =>
+				{
+					state.options.adaptive_difficulty = !state.options.adaptive_difficulty;
+					options_changed = true;
+				}
+				Action::ToggleFriendlyFire =>
+				{
+					state.options.friendly_fire = !state.options.friendly_fire;
+					options_changed = true;
+				}
+				Action::FeedbackIntensity(v) =>
+				{
+					state.options.feedback_intensity = v;
+					options_changed = true;
+				}
+				Action::FontScale(v) =>
+				{
+					let old_scale = state.options.ui_font_scale;
+					state.options.ui_font_scale = v;
+					match state.load_fonts()
+					{
+						Ok(()) =>
+						{
+							self.error = None;
+							options_changed = true;
+						}
+						Err(e) =>
+						{
+							// The old fonts are still loaded.
+							state.options.ui_font_scale = old_scale;
+							self.error = Some(format!("Couldn't load fonts: {}", e.short()));
+						}
+					}
+				}
+				Action::ToggleHighContrast =>
+				{
+					state.options.high_contrast = !state.options.high_contrast;
+					set_high_contrast(state.options.high_contrast);
+					options_changed = true;
+				}
+				Action::MuteMessages(kind, muted) =>
+				{
+					state.options.muted_messages[kind] = muted;
+					options_changed = true;
+				}
+				Action::TickRate(tick_rate) =>
+				{
+					state.options.tick_rate = tick_rate;
+					options_changed = true;
+				}
+				_ => return Some(action),
+			}
+		}
+		if options_changed
+		{
+			game_state::save_options(&state.core, &state.options).unwrap();
+		}
+		None
+	}
+}
+
+pub struct CursesMenu
+{
+	widgets: WidgetList,
+}
+
+impl CursesMenu
+{
+	pub fn new(state: &game_state::GameState) -> Self
+	{
+		let m = state.m;
+		let w = m * 6.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height / 2.;
+
+		// Toggles take plain fn pointers, so one per curse.
+		let toggle_fns: [fn(usize) -> Action; curses::CURSES.len()] = [
+			|v| Action::ToggleCurse(0, v == 1),
+			|v| Action::ToggleCurse(1, v == 1),
+			|v| Action::ToggleCurse(2, v == 1),
+		];
+		let mut widgets = vec![];
+		for (curse, toggle_fn) in curses::CURSES.iter().zip(toggle_fns)
+		{
+			widgets.push(vec![
+				Widget::Label(Label::new(0., 0., w, h, curse.name())),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					state.options.curses.contains(curse) as usize,
+					vec!["No".into(), "Yes".into()],
+					toggle_fn,
+				)),
+			]);
+		}
+		widgets.push(vec![
+			Widget::Button(Button::new(0., 0., w, h, "Back", Action::Back)),
+			Widget::Button(Button::new(0., 0., w, h, "Set Sail", Action::Start)),
+		]);
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				2. * h,
+				h,
+				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
+			),
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		let m = state.m;
+		let lh = state.ui_font.get_line_height() as f32;
+		let (dw, dh) = (state.display_width, state.display_height);
+		state.core.draw_text(
+			&state.ui_font,
+			Color::from_rgb_f(1., 1., 1.),
+			dw / 2.,
+			dh / 2. - (curses::CURSES.len() as f32 + 3.) * m,
+			FontAlign::Centre,
+			"Curses",
+		);
+		self.widgets.draw(state);
+
+		let mut y = dh / 2. + (curses::CURSES.len() as f32 + 2.) * m;
+		for curse in &curses::CURSES
+		{
+			let color = if state.options.curses.contains(curse)
+			{
+				ui_color()
+			}
+			else
+			{
+				Color::from_rgb_f(0.5, 0.5, 0.5)
+			};
+			state.core.draw_text(
+				&state.ui_font,
+				color,
+				dw / 2.,
+				y,
+				FontAlign::Centre,
+				&format!("{}: {}", curse.name(), curse.description()),
+			);
+			y += lh;
+		}
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		let action = self.widgets.input(state, event);
+		if let Some(Action::ToggleCurse(idx, on)) = action
+		{
+			let curse = curses::CURSES[idx];
+			state.options.curses.retain(|c| *c != curse);
+			if on
+			{
+				state.options.curses.push(curse);
+			}
+			game_state::save_options(&state.core, &state.options).unwrap();
+			return None;
+		}
+		action
+	}
+}
+
+// Browses the content packs, with a look at their ships, and turns them on or off for the next
+// voyage.
+pub struct PacksMenu
+{
+	widgets: WidgetList,
+	cur: usize,
+	thumbnails: Vec<Bitmap>,
+	error: Option<String>,
+}
+
+impl PacksMenu
+{
+	pub fn new(state: &mut game_state::GameState) -> Self
+	{
+		// Pick up anything dropped in since the game started.
+		state.packs = packs::scan();
+
+		let m = state.m;
+		let w = m * 8.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height * 2. / 3.;
+
+		let widgets = [
+			vec![
+				Widget::Button(Button::new(0., 0., w / 4., h, "<", Action::PrevPack)),
+				Widget::Label(Label::new(0., 0., w, h, "")),
+				Widget::Button(Button::new(0., 0., w / 4., h, ">", Action::NextPack)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w / 2., h, "Enabled")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w / 2.,
+					h,
+					0,
+					vec!["No".into(), "Yes".into()],
+					|v| Action::TogglePack(v == 1),
+				)),
+			],
+			vec![Widget::Button(Button::new(
+				0.,
+				0.,
+				w,
+				h,
+				"Back",
+				Action::Back,
+			))],
+		];
+
+		let mut menu = Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				h,
+				h,
+				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
+			),
+			cur: 0,
+			thumbnails: vec![],
+			error: None,
+		};
+		menu.select(state, 0);
+		menu
+	}
+
+	fn select(&mut self, state: &mut game_state::GameState, cur: usize)
+	{
+		self.cur = cur;
+		self.thumbnails.clear();
+		self.error = None;
+		let pack = match state.packs.get(cur).cloned()
+		{
+			Some(pack) => pack,
+			None =>
+			{
+				self.error = Some(format!("No packs found in {}/", packs::PACKS_DIR));
+				return;
+			}
+		};
+		if let Err(error) = &pack.manifest
+		{
+			self.error = Some(error.clone());
+		}
+		else if let Some(problem) = pack.problems.first()
+		{
+			self.error = Some(problem.clone());
+		}
+		for ship_desc in pack.ship_descs()
+		{
+			match packs::thumbnail(state, &ship_desc)
+			{
+				Ok(thumbnail) => self.thumbnails.push(thumbnail),
+				Err(e) =>
+				{
+					// The game can't draw her either, so the pack is as good as broken.
+					let problem = format!("{ship_desc}: {e}");
+					state.packs[cur].problems.push(problem.clone());
+					self.error = Some(problem);
+					break;
+				}
+			}
+		}
+
+		let enabled = state.options.packs.contains(&pack.id);
+		if let Widget::Label(label) = &mut self.widgets.widgets[0][1]
+		{
+			label.text = pack.name().to_string();
+		}
+		if let Widget::Toggle(toggle) = &mut self.widgets.widgets[1][1]
+		{
+			toggle.cur_value = enabled as usize;
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		let m = state.m;
+		let lh = state.ui_font.get_line_height() as f32;
+		let (dw, dh) = (state.display_width, state.display_height);
+		state.core.draw_text(
+			&state.ui_font,
+			Color::from_rgb_f(1., 1., 1.),
+			dw / 2.,
+			m,
+			FontAlign::Centre,
+			&match game_state::profile()
+			{
+				Some(profile) => format!("Content Packs ({profile})"),
+				None => "Content Packs".to_string(),
+			},
+		);
+
+		if !self.thumbnails.is_empty()
+		{
+			let size = self.thumbnails[0].get_width() as f32;
+			let total = self.thumbnails.len() as f32 * (size + m) - m;
+			let mut x = dw / 2. - total / 2.;
+			for thumbnail in &self.thumbnails
+			{
+				state
+					.core
+					.draw_bitmap(thumbnail, x, dh / 3. - size / 2., Flag::zero());
+				x += size + m;
+			}
+		}
+
+		let mut y = dh / 3. + 4. * m;
+		if let Some(Ok(manifest)) = state.packs.get(self.cur).map(|pack| &pack.manifest)
+		{
+			for line in [
+				format!("By {}", manifest.author),
+				manifest.description.clone(),
+				format!("{} ship(s)", manifest.ships.len()),
+			]
+			{
+				state.core.draw_text(
+					&state.ui_font,
+					ui_color(),
+					dw / 2.,
+					y,
+					FontAlign::Centre,
+					&line,
+				);
+				y += lh;
+			}
+		}
+		if let Some(error) = &self.error
+		{
+			state.core.draw_text(
+				&state.ui_font,
+				Color::from_rgb_f(1., 0.3, 0.3),
+				dw / 2.,
+				y,
+				FontAlign::Centre,
+				error,
+			);
+		}
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		let num_packs = state.packs.len().max(1);
+		match self.widgets.input(state, event)
+		{
+			Some(Action::PrevPack) => self.select(state, (self.cur + num_packs - 1) % num_packs),
+			Some(Action::NextPack) => self.select(state, (self.cur + 1) % num_packs),
+			Some(Action::TogglePack(on)) =>
+			{
+				let (id, usable) = match state.packs.get(self.cur)
+				{
+					Some(pack) => (pack.id.clone(), pack.usable()),
+					None => return None,
+				};
+				state.options.packs.retain(|p| *p != id);
+				// Broken packs can only be turned off.
+				if on && usable
+				{
+					state.options.packs.push(id);
+				}
+				else if let Widget::Toggle(toggle) = &mut self.widgets.widgets[1][1]
+				{
+					toggle.cur_value = 0;
+				}
+				game_state::save_options(&state.core, &state.options).unwrap();
+			}
+			action => return action,
+		}
+		None
+	}
+}
+
+// Experimental LAN co-op: host a voyage, or join one at the address in the options.
+pub struct LobbyMenu
+{
+	widgets: WidgetList,
+	error: Option<String>,
+}
+
+impl LobbyMenu
+{
+	pub fn new(state: &game_state::GameState) -> Self
+	{
+		let m = state.m;
+		let w = m * 8.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height / 2.;
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				h,
+				h,
+				&[
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Host",
+						Action::HostCoop,
+					))],
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						&format!("Join {}", state.options.coop_address),
+						Action::JoinCoop,
+					))],
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Back",
+						Action::Back,
+					))],
+				],
+			),
+			error: None,
+		}
+	}
+
+	pub fn set_error(&mut self, error: String)
+	{
+		self.error = Some(error);
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		self.widgets.draw(state);
+		let status = match (&self.error, &state.net)
+		{
+			(Some(error), _) => error.clone(),
+			(None, Some(net)) => net.status(),
+			(None, None) => "Both players need the same version of the game.".into(),
+		};
+		state.core.draw_text(
+			&state.ui_font,
+			ui_color(),
+			state.display_width / 2.,
+			state.display_height / 2. + 3. * state.m,
+			FontAlign::Centre,
+			&status,
+		);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		let action = self.widgets.input(state, event);
+		match action
+		{
+			Some(Action::HostCoop) =>
+			{
+				state.net = None;
+				self.error = None;
+				match net::Net::host(&state.options.coop_host_address)
+				{
+					Ok(net) => state.net = Some(net),
+					Err(e) => self.error = Some(format!("Couldn't host: {}", e.short())),
+				}
+				None
+			}
+			Some(Action::JoinCoop) =>
+			{
+				state.net = None;
+				self.error = None;
+				state.net = Some(net::Net::join(&state.options.coop_address));
+				None
+			}
+			Some(Action::Back) =>
+			{
+				state.net = None;
+				action
+			}
+			_ => action,
+		}
+	}
+}
+
+pub struct InGameMenu
+{
+	widgets: WidgetList,
+}
+
+impl InGameMenu
+{
+	pub fn new(state: &game_state::GameState) -> Self
+	{
+		let m = state.m;
+		let w = m * 6.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height / 2.;
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				h,
+				h,
+				&[
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Resume",
+						Action::Back,
+					))],
 					&[Widget::Button(Button::new(
 						0.,
 						0.,
@@ -1185,6 +2176,82 @@ impl InGameMenu
 						"Options",
 						Action::Forward(|s| SubScreen::OptionsMenu(OptionsMenu::new(s))),
 					))],
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Loot Filter",
+						Action::Forward(|s| SubScreen::LootFilterMenu(LootFilterMenu::new(s))),
+					))],
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Restart",
+						Action::Start,
+					))],
+					&[Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Quit",
+						Action::MainMenu,
+					))],
+				],
+			),
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		self.widgets.input(state, event)
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct RunSummary
+{
+	pub victory: bool,
+	pub minutes: f64,
+	pub crew_level: i32,
+	pub money: i32,
+	pub ships_disabled: i32,
+	pub fleet_size: i32,
+	// What the adaptive difficulty ended up at, if it was on.
+	pub difficulty: Option<String>,
+}
+
+pub struct EndScreen
+{
+	widgets: WidgetList,
+	summary: RunSummary,
+}
+
+impl EndScreen
+{
+	pub fn new(state: &game_state::GameState, summary: RunSummary) -> Self
+	{
+		let m = state.m;
+		let w = m * 6.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height * 0.7;
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				h,
+				h,
+				&[
 					&[Widget::Button(Button::new(
 						0.,
 						0.,
@@ -1198,21 +2265,1314 @@ impl InGameMenu
 						0.,
 						w,
 						h,
-						"Quit",
-						Action::MainMenu,
-					))],
-				],
+						"Main Menu",
+						Action::Back,
+					))],
+				],
+			),
+			summary: summary,
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		let m = state.m;
+		let lh = state.ui_font.get_line_height() as f32;
+		let (dw, dh) = (state.display_width, state.display_height);
+		state
+			.prim
+			.draw_filled_rectangle(0., 0., dw, dh, backdrop(0.7));
+
+		let (title, color) = if self.summary.victory
+		{
+			("Victory", Color::from_rgb_f(0.9, 0.8, 0.3))
+		}
+		else
+		{
+			("Defeat", Color::from_rgb_f(0.8, 0.2, 0.2))
+		};
+		state.core.draw_text(
+			&state.title_font,
+			color,
+			dw / 2.,
+			dh / 4.,
+			FontAlign::Centre,
+			title,
+		);
+
+		let mut lines = vec![
+			format!("Voyage length: {:.1} minutes", self.summary.minutes),
+			format!("Crew level: {}", self.summary.crew_level),
+			format!("Money: £{}", self.summary.money),
+			format!("Ships disabled: {}", self.summary.ships_disabled),
+			format!("Fleet size: {}", self.summary.fleet_size),
+		];
+		if let Some(difficulty) = &self.summary.difficulty
+		{
+			lines.push(format!("Adaptive difficulty: {difficulty}"));
+		}
+		let mut y = dh / 4. + 3. * m;
+		for line in &lines
+		{
+			state.core.draw_text(
+				&state.ui_font,
+				ui_color(),
+				dw / 2.,
+				y,
+				FontAlign::Centre,
+				line,
+			);
+			y += lh;
+		}
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		self.widgets.input(state, event)
+	}
+}
+
+pub struct LootFilterMenu
+{
+	widgets: WidgetList,
+}
+
+impl LootFilterMenu
+{
+	pub fn new(state: &game_state::GameState) -> Self
+	{
+		let m = state.m;
+		let w = m * 6.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height / 2.;
+		let filter = &state.loot_filter;
+		let kind_texts = || vec!["Keep".to_string(), "Filter".to_string()];
+
+		let widgets = [
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "When Docking")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					filter.action as usize,
+					vec!["Keep".into(), "Discard".into(), "Sell".into()],
+					|v| {
+						Action::LootAction(
+							[
+								components::LootAction::Keep,
+								components::LootAction::Discard,
+								components::LootAction::Sell,
+							][v],
+						)
+					},
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Weapons")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					filter.weapons as usize,
+					kind_texts(),
+					|v| Action::LootKind(0, v == 1),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Officers")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					filter.officers as usize,
+					kind_texts(),
+					|v| Action::LootKind(1, v == 1),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Cargo")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					filter.cargo as usize,
+					kind_texts(),
+					|v| Action::LootKind(2, v == 1),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Rigging")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					filter.rigging as usize,
+					kind_texts(),
+					|v| Action::LootKind(3, v == 1),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Up To Rarity")),
+				Widget::Toggle(Toggle::new(
+					0.,
+					0.,
+					w,
+					h,
+					filter.max_rarity,
+					vec!["Normal".into(), "Magic".into(), "Rare".into()],
+					|v| Action::LootRarity(v),
+				)),
+			],
+			vec![
+				Widget::Label(Label::new(0., 0., w, h, "Up To Level")),
+				Widget::Slider(Slider::new(
+					0.,
+					0.,
+					w,
+					h,
+					filter.max_level as f32,
+					1.,
+					30.,
+					true,
+					|v| Action::LootLevel(v),
+				)),
+			],
+			vec![Widget::Button(Button::new(
+				0.,
+				0.,
+				w,
+				h,
+				"Back",
+				Action::Back,
+			))],
+		];
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				2. * h,
+				h,
+				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
+			),
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		let action = self.widgets.input(state, event);
+		if let Some(action) = action
+		{
+			let filter = &mut state.loot_filter;
+			match action
+			{
+				Action::LootAction(action) => filter.action = action,
+				Action::LootKind(0, v) => filter.weapons = v,
+				Action::LootKind(1, v) => filter.officers = v,
+				Action::LootKind(3, v) => filter.rigging = v,
+				Action::LootKind(_, v) => filter.cargo = v,
+				Action::LootRarity(v) => filter.max_rarity = v,
+				Action::LootLevel(v) => filter.max_level = (v + 0.5) as i32,
+				_ => return Some(action),
+			}
+		}
+		None
+	}
+}
+
+// How many of a kind of cargo to buy.
+pub struct QuantityMenu
+{
+	widgets: WidgetList,
+	count: i32,
+}
+
+impl QuantityMenu
+{
+	pub fn new(state: &game_state::GameState, name: &str, max_count: i32) -> Self
+	{
+		let m = state.m;
+		let w = m * 8.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height / 2.;
+
+		let widgets = [
+			vec![Widget::Label(Label::new(
+				0.,
+				0.,
+				w,
+				h,
+				&format!("Buy how much {name}?"),
+			))],
+			vec![Widget::Slider(Slider::new(
+				0.,
+				0.,
+				w,
+				h,
+				max_count as f32,
+				1.,
+				max_count as f32,
+				true,
+				|v| Action::TradeQuantity(v),
+			))],
+			vec![
+				Widget::Button(Button::new(
+					0.,
+					0.,
+					w / 2.,
+					h,
+					"Buy",
+					Action::BuyQuantity(0),
+				)),
+				Widget::Button(Button::new(0., 0., w / 2., h, "Cancel", Action::Back)),
+			],
+		];
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				2. * h,
+				h,
+				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
+			),
+			count: max_count,
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		match self.widgets.input(state, event)
+		{
+			Some(Action::TradeQuantity(v)) =>
+			{
+				self.count = (v + 0.5) as i32;
+				None
+			}
+			Some(Action::BuyQuantity(_)) => Some(Action::BuyQuantity(self.count)),
+			action => action,
+		}
+	}
+}
+
+// Asked before breaking the truce with a ship you're docked with.
+pub struct OpenFireMenu
+{
+	widgets: WidgetList,
+}
+
+impl OpenFireMenu
+{
+	pub fn new(state: &game_state::GameState, name: &str) -> Self
+	{
+		let m = state.m;
+		let w = m * 12.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height / 2.;
+
+		let widgets = [
+			vec![Widget::Label(Label::new(
+				0.,
+				0.,
+				w,
+				h,
+				&format!("You're docked with {name}. Break the truce?"),
+			))],
+			vec![
+				Widget::Button(Button::new(
+					0.,
+					0.,
+					w / 2.,
+					h,
+					"Open Fire",
+					Action::OpenFire,
+				)),
+				Widget::Button(Button::new(0., 0., w / 2., h, "Hold Fire", Action::Back)),
+			],
+		];
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				2. * h,
+				h,
+				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
+			),
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		self.widgets.input(state, event)
+	}
+}
+
+// Taking the Voidwind's helm would bind the player to her, so that's not allowed.
+pub struct VoidwindMenu
+{
+	widgets: WidgetList,
+}
+
+impl VoidwindMenu
+{
+	pub fn new(state: &game_state::GameState) -> Self
+	{
+		let m = state.m;
+		let w = m * 16.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height / 2.;
+
+		let widgets = [
+			vec![Widget::Label(Label::new(
+				0.,
+				0.,
+				w,
+				h,
+				"Your crew won't set foot on the Voidwind.",
+			))],
+			vec![Widget::Label(Label::new(
+				0.,
+				0.,
+				w,
+				h,
+				"Whoever takes her helm is bound to her, and can never leave.",
+			))],
+			vec![Widget::Label(Label::new(
+				0.,
+				0.,
+				w,
+				h,
+				"Send her to the bottom instead, the curse dies with her.",
+			))],
+			vec![Widget::Button(Button::new(
+				0.,
+				0.,
+				w / 2.,
+				h,
+				"Leave Her Be",
+				Action::Back,
+			))],
+		];
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				2. * h,
+				h,
+				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
+			),
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		self.widgets.input(state, event)
+	}
+}
+
+// What there is to split between the old ship and a prize when switching to her.
+#[derive(Clone, Debug)]
+pub struct CrewSplit
+{
+	pub crew: i32,
+	pub wounded: i32,
+	// Officers serving aboard the old ship.
+	pub officers: i32,
+	// Room for hands and officers aboard the prize.
+	pub berths: i32,
+	pub officer_berths: i32,
+	pub experience: f32,
+	// Hands still aboard the prize get pressed into the crew, as many as there's room for.
+	pub prize_crew: i32,
+	pub prize_wounded: i32,
+	pub prize_experience: f32,
+}
+
+impl CrewSplit
+{
+	// Prize hands pressed into service when taking this many across.
+	pub fn pressed(&self, crew: i32, wounded: i32) -> (i32, i32)
+	{
+		let room = (self.berths - crew - wounded).max(0);
+		let pressed_crew = self.prize_crew.min(room);
+		let pressed_wounded = self.prize_wounded.min(room - pressed_crew);
+		(pressed_crew, pressed_wounded)
+	}
+
+	// Same averaging as recruiting, with every head counting the same.
+	pub fn average(&self, crew: i32, wounded: i32, ours: f32, theirs: f32) -> f32
+	{
+		let (pressed_crew, pressed_wounded) = self.pressed(crew, wounded);
+		let count = (crew + wounded) as f32;
+		let pressed = (pressed_crew + pressed_wounded) as f32;
+		if count + pressed > 0.
+		{
+			(count * ours + pressed * theirs) / (count + pressed)
+		}
+		else
+		{
+			ours
+		}
+	}
+
+	pub fn problem(&self, crew: i32, wounded: i32, officers: i32) -> Option<String>
+	{
+		if crew < 1
+		{
+			Some("The prize needs at least one hand aboard!".into())
+		}
+		else if crew + wounded > self.berths
+		{
+			Some("No room for that many aboard the prize!".into())
+		}
+		else if crew == self.crew && (wounded < self.wounded || officers < self.officers)
+		{
+			Some("Your old ship needs at least one hand aboard!".into())
+		}
+		else
+		{
+			None
+		}
+	}
+}
+
+// How many hands and officers to take aboard a prize, the rest stay on the old ship.
+pub struct CaptureMenu
+{
+	widgets: WidgetList,
+	split: CrewSplit,
+	crew: i32,
+	wounded: i32,
+	officers: i32,
+}
+
+impl CaptureMenu
+{
+	pub fn new(state: &game_state::GameState, split: CrewSplit) -> Self
+	{
+		let m = state.m;
+		let w = m * 12.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height / 2.;
+
+		let crew = (split.crew - split.crew / 2).min(split.berths);
+		let wounded = split.wounded.min(split.berths - crew).max(0);
+		let officers = split.officers.min(split.officer_berths);
+
+		let mut widgets = vec![
+			vec![Widget::Label(Label::new(
+				0.,
+				0.,
+				w,
+				h,
+				"Take how many across to the prize?",
+			))],
+			vec![Widget::Label(Label::new(0., 0., w, h, ""))],
+			vec![
+				Widget::Label(Label::new(0., 0., w / 3., h, "Crew")),
+				Widget::Slider(Slider::new(
+					0.,
+					0.,
+					w * 2. / 3.,
+					h,
+					crew as f32,
+					1.,
+					split.crew as f32,
+					true,
+					|v| Action::CaptureCrew(v),
+				)),
+			],
+		];
+		if split.wounded > 0
+		{
+			widgets.push(vec![
+				Widget::Label(Label::new(0., 0., w / 3., h, "Wounded")),
+				Widget::Slider(Slider::new(
+					0.,
+					0.,
+					w * 2. / 3.,
+					h,
+					wounded as f32,
+					0.,
+					split.wounded as f32,
+					true,
+					|v| Action::CaptureWounded(v),
+				)),
+			]);
+		}
+		let max_officers = split.officers.min(split.officer_berths);
+		if max_officers > 0
+		{
+			widgets.push(vec![
+				Widget::Label(Label::new(0., 0., w / 3., h, "Officers")),
+				Widget::Slider(Slider::new(
+					0.,
+					0.,
+					w * 2. / 3.,
+					h,
+					officers as f32,
+					0.,
+					max_officers as f32,
+					true,
+					|v| Action::CaptureOfficers(v),
+				)),
+			]);
+		}
+		widgets.push(vec![
+			Widget::Button(Button::new(
+				0.,
+				0.,
+				w / 3.,
+				h,
+				"Switch",
+				Action::Capture(0, 0, 0),
+			)),
+			Widget::Button(Button::new(0., 0., w / 3., h, "Cancel", Action::Back)),
+		]);
+
+		let mut menu = Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				2. * h,
+				h,
+				&widgets.iter().map(|r| &r[..]).collect::<Vec<_>>(),
+			),
+			split: split,
+			crew: crew,
+			wounded: wounded,
+			officers: officers,
+		};
+		menu.update_status();
+		menu
+	}
+
+	fn update_status(&mut self)
+	{
+		let split = &self.split;
+		let text = if let Some(problem) = split.problem(self.crew, self.wounded, self.officers)
+		{
+			problem
+		}
+		else
+		{
+			let level = components::experience_level(split.experience);
+			let new_level = components::experience_level(split.average(
+				self.crew,
+				self.wounded,
+				split.experience,
+				split.prize_experience,
+			));
+			let (pressed_crew, pressed_wounded) = split.pressed(self.crew, self.wounded);
+			if new_level < level
+			{
+				format!(
+					"Pressing {} of her hands drops your crew to level {new_level}!",
+					pressed_crew + pressed_wounded
+				)
+			}
+			else if self.crew < split.crew
+			{
+				format!(
+					"Leaving {} crew and {} wounded aboard your old ship.",
+					split.crew - self.crew,
+					split.wounded - self.wounded
+				)
+			}
+			else
+			{
+				"Your old ship will be left adrift.".into()
+			}
+		};
+		if let Widget::Label(label) = &mut self.widgets.widgets[1][0]
+		{
+			label.text = text;
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		match self.widgets.input(state, event)
+		{
+			Some(Action::CaptureCrew(v)) => self.crew = (v + 0.5) as i32,
+			Some(Action::CaptureWounded(v)) => self.wounded = (v + 0.5) as i32,
+			Some(Action::CaptureOfficers(v)) => self.officers = (v + 0.5) as i32,
+			// The status line already says what's wrong.
+			Some(Action::Capture(_, _, _)) =>
+			{
+				return self
+					.split
+					.problem(self.crew, self.wounded, self.officers)
+					.is_none()
+					.then_some(Action::Capture(self.crew, self.wounded, self.officers));
+			}
+			action => return action,
+		}
+		self.update_status();
+		None
+	}
+}
+
+pub struct JournalMenu
+{
+	widgets: WidgetList,
+	title: String,
+	lines: Vec<String>,
+	// Number of lines scrolled up from the most recent entry.
+	scroll: usize,
+}
+
+impl JournalMenu
+{
+	pub fn new(state: &game_state::GameState, title: &str, lines: Vec<String>) -> Self
+	{
+		let m = state.m;
+		let w = m * 6.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height - 2. * m;
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				h,
+				h,
+				&[&[Widget::Button(Button::new(
+					0.,
+					0.,
+					w,
+					h,
+					"Back",
+					Action::Back,
+				))]],
+			),
+			title: title.into(),
+			lines: lines,
+			scroll: 0,
+		}
+	}
+
+	fn visible_lines(&self, state: &game_state::GameState) -> usize
+	{
+		let lh = state.ui_font.get_line_height() as f32;
+		((state.display_height - 6. * state.m) / lh).max(1.) as usize
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		let m = state.m;
+		let lh = state.ui_font.get_line_height() as f32;
+		state.core.draw_text(
+			&state.ui_font,
+			Color::from_rgb_f(1., 1., 1.),
+			state.display_width / 2.,
+			m,
+			FontAlign::Centre,
+			&self.title,
+		);
+
+		let num_visible = self.visible_lines(state);
+		let end = self.lines.len() - self.scroll.min(self.lines.len());
+		let start = end.saturating_sub(num_visible);
+		let mut y = 2.5 * m;
+		for line in &self.lines[start..end]
+		{
+			state
+				.core
+				.draw_text(&state.ui_font, ui_color(), m * 2., y, FontAlign::Left, line);
+			y += lh;
+		}
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		let max_scroll = self.lines.len().saturating_sub(self.visible_lines(state));
+		match event
+		{
+			Event::KeyDown {
+				keycode: KeyCode::Up,
+				..
+			} => self.scroll = (self.scroll + 1).min(max_scroll),
+			Event::KeyDown {
+				keycode: KeyCode::Down,
+				..
+			} => self.scroll = self.scroll.saturating_sub(1),
+			Event::MouseAxes { dz, .. } if *dz > 0 =>
+			{
+				self.scroll = (self.scroll + 3).min(max_scroll)
+			}
+			Event::MouseAxes { dz, .. } if *dz < 0 => self.scroll = self.scroll.saturating_sub(3),
+			_ => (),
+		}
+		self.widgets.input(state, event)
+	}
+}
+
+pub struct EconomyMenu
+{
+	widgets: WidgetList,
+	// Name, color and price history of each commodity.
+	series: Vec<(String, Color, Vec<f32>)>,
+}
+
+impl EconomyMenu
+{
+	pub fn new(state: &game_state::GameState, series: Vec<(String, Color, Vec<f32>)>) -> Self
+	{
+		let m = state.m;
+		let w = m * 6.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height - 2. * m;
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				h,
+				h,
+				&[&[Widget::Button(Button::new(
+					0.,
+					0.,
+					w,
+					h,
+					"Back",
+					Action::Back,
+				))]],
+			),
+			series: series,
+		}
+	}
+
+	pub fn draw(&self, state: &game_state::GameState)
+	{
+		let m = state.m;
+		let lh = state.ui_font.get_line_height() as f32;
+		let (dw, dh) = (state.display_width, state.display_height);
+		state.core.draw_text(
+			&state.ui_font,
+			Color::from_rgb_f(1., 1., 1.),
+			dw / 2.,
+			m,
+			FontAlign::Centre,
+			"Market Prices",
+		);
+
+		let (x1, y1) = (m * 3., m * 3.);
+		let (x2, y2) = (dw - m * 10., dh - m * 4.);
+		let max_price = self
+			.series
+			.iter()
+			.flat_map(|(_, _, prices)| prices.iter())
+			.fold(0_f32, |a, b| a.max(*b))
+			* 1.1;
+		let num_samples = self
+			.series
+			.iter()
+			.map(|(_, _, prices)| prices.len())
+			.max()
+			.unwrap_or(0);
+
+		let grid_color = ui_color().interpolate(Color::from_rgb(0, 0, 0), 0.7);
+		for i in 0..=4
+		{
+			let f = i as f32 / 4.;
+			let y = y2 - f * (y2 - y1);
+			state.prim.draw_line(x1, y, x2, y, grid_color, 1.);
+			state.core.draw_text(
+				&state.ui_font,
+				grid_color,
+				x1 - m / 2.,
+				y - lh / 2.,
+				FontAlign::Right,
+				&format!("£{}", (f * max_price) as i32),
+			);
+		}
+		state.prim.draw_line(x1, y1, x1, y2, ui_color(), 2.);
+		state.prim.draw_line(x1, y2, x2, y2, ui_color(), 2.);
+
+		if num_samples == 0 || max_price <= 0.
+		{
+			return;
+		}
+		let to_screen = |i: usize, price: f32| {
+			let fx = if num_samples > 1
+			{
+				i as f32 / (num_samples - 1) as f32
+			}
+			else
+			{
+				1.
+			};
+			Point2::new(x1 + fx * (x2 - x1), y2 - price / max_price * (y2 - y1))
+		};
+
+		let mut legend_y = y1;
+		for (name, color, prices) in &self.series
+		{
+			// Right-align the series so the latest price is always at the right edge.
+			let offset = num_samples - prices.len();
+			for (i, (p1, p2)) in prices.iter().zip(prices.iter().skip(1)).enumerate()
+			{
+				let s1 = to_screen(i + offset, *p1);
+				let s2 = to_screen(i + offset + 1, *p2);
+				state.prim.draw_line(s1.x, s1.y, s2.x, s2.y, *color, 3.);
+			}
+			if let Some(last) = prices.last()
+			{
+				let s = to_screen(num_samples - 1, *last);
+				state.prim.draw_filled_circle(s.x, s.y, 4., *color);
+				state.core.draw_text(
+					&state.ui_font,
+					*color,
+					x2 + m,
+					legend_y,
+					FontAlign::Left,
+					&format!("{name}: £{}", ((last / 10.) as i32) * 10),
+				);
+				legend_y += lh;
+			}
+		}
+		self.widgets.draw(state);
+	}
+
+	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
+	{
+		self.widgets.input(state, event)
+	}
+}
+
+// Most zoomed in the chart goes, in cells either side of the center.
+const MIN_CHART_VIEW_RADIUS: f32 = 3.;
+const MAX_NOTE_LENGTH: usize = 40;
+
+// What's known about a cell of the sea, for the chart.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChartCell
+{
+	Deep,
+	Shallows,
+	Storm,
+}
+
+impl ChartCell
+{
+	fn color(&self) -> Color
+	{
+		match self
+		{
+			ChartCell::Deep => Color::from_rgb_f(0.05, 0.08, 0.3),
+			ChartCell::Shallows => Color::from_rgb_f(0.15, 0.35, 0.45),
+			ChartCell::Storm => Color::from_rgb_f(0.25, 0.05, 0.35),
+		}
+	}
+}
+
+pub struct ChartMenu
+{
+	widgets: WidgetList,
+	// Global cell the player is in.
+	center: Point2<i32>,
+	// How far a course can be set.
+	radius: i32,
+	// Cell in the middle of the chart, and how many cells show either side of it.
+	view_center: Point2<f32>,
+	view_radius: f32,
+	max_radius: f32,
+	// Along with whether it's been visited.
+	cells: Vec<(Point2<i32>, ChartCell, bool)>,
+	// Positions in fractional global cells.
+	markers: Vec<(Point2<f32>, Color)>,
+	notes: Vec<exploration::Note>,
+	editing: Option<usize>,
+	player_pos: Point2<f32>,
+	course: Vec<Point2<i32>>,
+	hover: Option<Point2<i32>>,
+	// Where the mouse and the view were when the drag started, and whether it moved enough to
+	// not be a click.
+	drag: Option<(Point2<f32>, Point2<f32>, bool)>,
+}
+
+impl ChartMenu
+{
+	pub fn new(
+		state: &game_state::GameState, center: Point2<i32>, radius: i32,
+		cells: Vec<(Point2<i32>, ChartCell, bool)>, markers: Vec<(Point2<f32>, Color)>,
+		notes: Vec<exploration::Note>, player_pos: Point2<f32>, course: Vec<Point2<i32>>,
+	) -> Self
+	{
+		let m = state.m;
+		let w = m * 6.;
+		let h = m;
+		let cx = state.display_width / 2.;
+		let cy = state.display_height - 2. * m;
+		let max_radius = cells
+			.iter()
+			.map(|(pos, _, _)| (pos.x - center.x).abs().max((pos.y - center.y).abs()))
+			.max()
+			.unwrap_or(radius) as f32;
+
+		Self {
+			widgets: WidgetList::new(
+				cx,
+				cy,
+				h,
+				h,
+				&[&[
+					Widget::Button(Button::new(
+						0.,
+						0.,
+						w,
+						h,
+						"Clear Course",
+						Action::ClearCourse,
+					)),
+					Widget::Button(Button::new(0., 0., w, h, "Back", Action::Back)),
+				]],
 			),
+			center: center,
+			radius: radius,
+			view_center: center.cast::<f32>(),
+			view_radius: (radius as f32).min(max_radius),
+			max_radius: max_radius,
+			cells: cells,
+			markers: markers,
+			notes: notes,
+			editing: None,
+			player_pos: player_pos,
+			course: course,
+			hover: None,
+			drag: None,
+		}
+	}
+
+	// Top left corner of the chart, the size of each cell on it and the chart's width.
+	fn layout(&self, state: &game_state::GameState) -> (Point2<f32>, f32, f32)
+	{
+		let m = state.m;
+		let (dw, dh) = (state.display_width, state.display_height);
+		let extent = (dh - 6. * m).min(dw - 4. * m);
+		let size = extent / (2. * self.view_radius + 1.);
+		(Point2::new(dw / 2. - extent / 2., 2.5 * m), size, extent)
+	}
+
+	fn cell_center(&self, pos: Point2<f32>, state: &game_state::GameState) -> Point2<f32>
+	{
+		let (corner, size, _) = self.layout(state);
+		let offset = pos - self.view_center + Vector2::new(self.view_radius, self.view_radius);
+		corner + (offset + Vector2::new(0.5, 0.5)) * size
+	}
+
+	// Fractional global cell under the mouse, if it's on the chart.
+	fn chart_pos(&self, x: f32, y: f32, state: &game_state::GameState) -> Option<Point2<f32>>
+	{
+		let (corner, size, extent) = self.layout(state);
+		if x < corner.x || y < corner.y || x > corner.x + extent || y > corner.y + extent
+		{
+			return None;
+		}
+		let offset = (Point2::new(x, y) - corner) / size - Vector2::new(0.5, 0.5);
+		Some(self.view_center + offset - Vector2::new(self.view_radius, self.view_radius))
+	}
+
+	fn cell_at(&self, x: f32, y: f32, state: &game_state::GameState) -> Option<Point2<i32>>
+	{
+		let pos = self.chart_pos(x, y, state)?.map(|v| v.round() as i32);
+		let offset = pos - self.center;
+		(offset.x.abs() as f32 <= self.max_radius && offset.y.abs() as f32 <= self.max_radius)
+			.then_some(pos)
+	}
+
+	fn note_at(&self, x: f32, y: f32, state: &game_state::GameState) -> Option<usize>
+	{
+		self.notes.iter().position(|note| {
+			let c = self.cell_center(Point2::from(note.pos), state);
+			(c - Point2::new(x, y)).norm() < state.m / 2.
+		})
+	}
+
+	// Stops editing, throwing the note away if nothing got written.
+	fn finish_note(&mut self)
+	{
+		if let Some(idx) = self.editing.take()
+		{
+			if self.notes[idx].text.is_empty()
+			{
+				self.notes.remove(idx);
+			}
 		}
 	}
 
 	pub fn draw(&self, state: &game_state::GameState)
 	{
+		let m = state.m;
+		state.core.draw_text(
+			&state.ui_font,
+			Color::from_rgb_f(1., 1., 1.),
+			state.display_width / 2.,
+			m,
+			FontAlign::Centre,
+			"Chart (drag to pan, scroll to zoom, click to write a note, right-click to set a course)",
+		);
+		let (corner, size, extent) = self.layout(state);
+		state.core.set_clipping_rectangle(
+			corner.x as i32,
+			corner.y as i32,
+			extent as i32 + 1,
+			extent as i32 + 1,
+		);
+		let fog = Color::from_rgb_f(0.02, 0.02, 0.05);
+		for (pos, cell, visited) in &self.cells
+		{
+			let c = self.cell_center(pos.cast::<f32>(), state);
+			if (c.x - corner.x).min(c.y - corner.y) < -size
+				|| (c.x - corner.x).max(c.y - corner.y) > extent + size
+			{
+				continue;
+			}
+			let color = if *visited
+			{
+				cell.color()
+			}
+			else
+			{
+				cell.color().interpolate(fog, 0.75)
+			};
+			state.prim.draw_filled_rectangle(
+				c.x - size / 2.,
+				c.y - size / 2.,
+				c.x + size / 2.,
+				c.y + size / 2.,
+				color,
+			);
+			let outline = if Some(*pos) == self.hover
+			{
+				Color::from_rgb_f(1., 1., 1.)
+			}
+			else
+			{
+				ui_color().interpolate(Color::from_rgb(0, 0, 0), 0.7)
+			};
+			state.prim.draw_rectangle(
+				c.x - size / 2.,
+				c.y - size / 2.,
+				c.x + size / 2.,
+				c.y + size / 2.,
+				outline,
+				1.,
+			);
+		}
+
+		// What's left of the course, starting from where the player is.
+		let course_color = Color::from_rgb_f(0.9, 0.8, 0.4);
+		let mut prev = self.cell_center(self.player_pos, state);
+		for pos in &self.course
+		{
+			let c = self.cell_center(pos.cast::<f32>(), state);
+			state
+				.prim
+				.draw_line(prev.x, prev.y, c.x, c.y, course_color, 3.);
+			prev = c;
+		}
+		if let Some(dest) = self.course.last()
+		{
+			let c = self.cell_center(dest.cast::<f32>(), state);
+			state
+				.prim
+				.draw_circle(c.x, c.y, size / 4., course_color, 3.);
+		}
+
+		for (pos, color) in &self.markers
+		{
+			let c = self.cell_center(*pos, state);
+			state.prim.draw_filled_circle(c.x, c.y, size / 8., *color);
+		}
+		let c = self.cell_center(self.player_pos, state);
+		state
+			.prim
+			.draw_filled_circle(c.x, c.y, size / 6., Color::from_rgb_f(1., 1., 1.));
+
+		let note_color = Color::from_rgb_f(0.95, 0.9, 0.75);
+		for (i, note) in self.notes.iter().enumerate()
+		{
+			let c = self.cell_center(Point2::from(note.pos), state);
+			state.prim.draw_circle(c.x, c.y, m / 4., note_color, 2.);
+			let text = if self.editing == Some(i)
+			{
+				format!("{}_", note.text)
+			}
+			else
+			{
+				note.text.clone()
+			};
+			state.core.draw_text(
+				&state.ui_font,
+				note_color,
+				c.x + m / 2.,
+				c.y - state.ui_font.get_line_height() as f32 / 2.,
+				FontAlign::Left,
+				&text,
+			);
+		}
+		state.core.reset_clipping_rectangle();
 		self.widgets.draw(state);
 	}
 
 	pub fn input(&mut self, state: &mut game_state::GameState, event: &Event) -> Option<Action>
 	{
+		if let Some(idx) = self.editing
+		{
+			match event
+			{
+				Event::KeyChar {
+					keycode, unichar, ..
+				} =>
+				{
+					match keycode
+					{
+						KeyCode::Enter | KeyCode::Escape => self.finish_note(),
+						KeyCode::Backspace =>
+						{
+							self.notes[idx].text.pop();
+						}
+						_ =>
+						{
+							let text = &mut self.notes[idx].text;
+							if !unichar.is_control() && text.chars().count() < MAX_NOTE_LENGTH
+							{
+								text.push(*unichar);
+							}
+						}
+					}
+					return Some(Action::ChartNotes(self.notes.clone()));
+				}
+				// Typing shouldn't press any buttons.
+				Event::KeyDown { .. } => return None,
+				// Clicking on the chart just puts the pen down.
+				Event::MouseButtonDown { x, y, .. } =>
+				{
+					self.finish_note();
+					if self.chart_pos(*x as f32, *y as f32, state).is_some()
+					{
+						return Some(Action::ChartNotes(self.notes.clone()));
+					}
+				}
+				_ => (),
+			}
+		}
+		match event
+		{
+			Event::MouseAxes { x, y, dz, .. } =>
+			{
+				let (x, y) = (*x as f32, *y as f32);
+				let (_, size, _) = self.layout(state);
+				if let Some((mouse_start, view_start, dragged)) = self.drag.as_mut()
+				{
+					let delta = Point2::new(x, y) - *mouse_start;
+					*dragged |= delta.norm() > 4.;
+					let max = Vector2::new(self.max_radius, self.max_radius);
+					self.view_center = (*view_start - delta / size)
+						.coords
+						.sup(&(self.center.cast::<f32>().coords - max))
+						.inf(&(self.center.cast::<f32>().coords + max))
+						.into();
+				}
+				if *dz != 0 && self.chart_pos(x, y, state).is_some()
+				{
+					self.view_radius = (self.view_radius * 0.8f32.powi(*dz))
+						.max(MIN_CHART_VIEW_RADIUS)
+						.min(self.max_radius);
+				}
+				self.hover = self.cell_at(x, y, state);
+			}
+			Event::MouseButtonDown {
+				button: 1, x, y, ..
+			} =>
+			{
+				if self.chart_pos(*x as f32, *y as f32, state).is_some()
+				{
+					self.drag = Some((Point2::new(*x as f32, *y as f32), self.view_center, false));
+				}
+			}
+			Event::MouseButtonUp {
+				button: 1, x, y, ..
+			} =>
+			{
+				if let Some((_, _, false)) = self.drag.take()
+				{
+					let (x, y) = (*x as f32, *y as f32);
+					if let Some(idx) = self.note_at(x, y, state)
+					{
+						self.editing = Some(idx);
+					}
+					else if let Some(pos) = self.chart_pos(x, y, state)
+					{
+						self.notes.push(exploration::Note {
+							pos: [pos.x, pos.y],
+							text: String::new(),
+						});
+						self.editing = Some(self.notes.len() - 1);
+					}
+					state.sfx.play_sound("data/ui2.ogg").unwrap();
+				}
+			}
+			Event::MouseButtonDown {
+				button: 2, x, y, ..
+			} =>
+			{
+				if let Some(cell) = self.cell_at(*x as f32, *y as f32, state)
+				{
+					let offset = cell - self.center;
+					if offset.x.abs() <= self.radius && offset.y.abs() <= self.radius
+					{
+						state.sfx.play_sound("data/ui2.ogg").unwrap();
+						return Some(Action::SetCourse(cell));
+					}
+				}
+			}
+			_ => (),
+		}
 		self.widgets.input(state, event)
 	}
 }
@@ -1222,7 +3582,19 @@ pub enum SubScreen
 	MainMenu(MainMenu),
 	ControlsMenu(ControlsMenu),
 	OptionsMenu(OptionsMenu),
+	CursesMenu(CursesMenu),
+	LobbyMenu(LobbyMenu),
 	InGameMenu(InGameMenu),
+	JournalMenu(JournalMenu),
+	EconomyMenu(EconomyMenu),
+	ChartMenu(ChartMenu),
+	LootFilterMenu(LootFilterMenu),
+	QuantityMenu(QuantityMenu),
+	OpenFireMenu(OpenFireMenu),
+	VoidwindMenu(VoidwindMenu),
+	CaptureMenu(CaptureMenu),
+	PacksMenu(PacksMenu),
+	EndScreen(EndScreen),
 }
 
 impl SubScreen
@@ -1234,7 +3606,19 @@ impl SubScreen
 			SubScreen::MainMenu(s) => s.draw(state),
 			SubScreen::ControlsMenu(s) => s.draw(state),
 			SubScreen::OptionsMenu(s) => s.draw(state),
+			SubScreen::CursesMenu(s) => s.draw(state),
+			SubScreen::LobbyMenu(s) => s.draw(state),
 			SubScreen::InGameMenu(s) => s.draw(state),
+			SubScreen::JournalMenu(s) => s.draw(state),
+			SubScreen::EconomyMenu(s) => s.draw(state),
+			SubScreen::ChartMenu(s) => s.draw(state),
+			SubScreen::LootFilterMenu(s) => s.draw(state),
+			SubScreen::QuantityMenu(s) => s.draw(state),
+			SubScreen::OpenFireMenu(s) => s.draw(state),
+			SubScreen::VoidwindMenu(s) => s.draw(state),
+			SubScreen::CaptureMenu(s) => s.draw(state),
+			SubScreen::PacksMenu(s) => s.draw(state),
+			SubScreen::EndScreen(s) => s.draw(state),
 		}
 	}
 
@@ -1245,7 +3629,42 @@ impl SubScreen
 			SubScreen::MainMenu(s) => s.input(state, event),
 			SubScreen::ControlsMenu(s) => s.input(state, event),
 			SubScreen::OptionsMenu(s) => s.input(state, event),
+			SubScreen::CursesMenu(s) => s.input(state, event),
+			SubScreen::LobbyMenu(s) => s.input(state, event),
 			SubScreen::InGameMenu(s) => s.input(state, event),
+			SubScreen::JournalMenu(s) => s.input(state, event),
+			SubScreen::EconomyMenu(s) => s.input(state, event),
+			SubScreen::ChartMenu(s) => s.input(state, event),
+			SubScreen::LootFilterMenu(s) => s.input(state, event),
+			SubScreen::QuantityMenu(s) => s.input(state, event),
+			SubScreen::OpenFireMenu(s) => s.input(state, event),
+			SubScreen::VoidwindMenu(s) => s.input(state, event),
+			SubScreen::CaptureMenu(s) => s.input(state, event),
+			SubScreen::PacksMenu(s) => s.input(state, event),
+			SubScreen::EndScreen(s) => s.input(state, event),
+		}
+	}
+
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			SubScreen::MainMenu(_) => "MainMenu",
+			SubScreen::ControlsMenu(_) => "ControlsMenu",
+			SubScreen::OptionsMenu(_) => "OptionsMenu",
+			SubScreen::CursesMenu(_) => "CursesMenu",
+			SubScreen::LobbyMenu(_) => "LobbyMenu",
+			SubScreen::InGameMenu(_) => "InGameMenu",
+			SubScreen::JournalMenu(_) => "JournalMenu",
+			SubScreen::EconomyMenu(_) => "EconomyMenu",
+			SubScreen::ChartMenu(_) => "ChartMenu",
+			SubScreen::LootFilterMenu(_) => "LootFilterMenu",
+			SubScreen::QuantityMenu(_) => "QuantityMenu",
+			SubScreen::OpenFireMenu(_) => "OpenFireMenu",
+			SubScreen::VoidwindMenu(_) => "VoidwindMenu",
+			SubScreen::CaptureMenu(_) => "CaptureMenu",
+			SubScreen::PacksMenu(_) => "PacksMenu",
+			SubScreen::EndScreen(_) => "EndScreen",
 		}
 	}
 }
@@ -0,0 +1,258 @@
+use crate::{game_state, ui};
+
+use allegro::*;
+use allegro_font::*;
+use allegro_primitives::*;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::f32::consts::PI;
+use std::hash::{Hash, Hasher};
+
+struct Text
+{
+	color: Color,
+	x: f32,
+	y: f32,
+	align: FontAlign,
+	text: String,
+}
+
+// Shapes and text laid out ahead of time. Shapes become one triangle list, text is drawn with
+// held bitmap drawing so the glyphs get batched too.
+#[derive(Default)]
+pub struct Batch
+{
+	vtxs: Vec<Vertex>,
+	texts: Vec<Text>,
+}
+
+impl Batch
+{
+	fn vertex(&mut self, x: f32, y: f32, color: Color)
+	{
+		self.vtxs.push(Vertex {
+			x: x,
+			y: y,
+			z: 0.,
+			u: 0.,
+			v: 0.,
+			color: color,
+		});
+	}
+
+	fn quad(&mut self, corners: [(f32, f32); 4], color: Color)
+	{
+		for i in [0, 1, 2, 0, 2, 3]
+		{
+			self.vertex(corners[i].0, corners[i].1, color);
+		}
+	}
+
+	pub fn filled_rectangle(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, color: Color)
+	{
+		self.quad([(x1, y1), (x2, y1), (x2, y2), (x1, y2)], color);
+	}
+
+	pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, color: Color, thickness: f32)
+	{
+		let (dx, dy) = (x2 - x1, y2 - y1);
+		let len = (dx * dx + dy * dy).sqrt();
+		if len == 0.
+		{
+			return;
+		}
+		let (nx, ny) = (-dy / len * thickness / 2., dx / len * thickness / 2.);
+		self.quad(
+			[
+				(x1 + nx, y1 + ny),
+				(x2 + nx, y2 + ny),
+				(x2 - nx, y2 - ny),
+				(x1 - nx, y1 - ny),
+			],
+			color,
+		);
+	}
+
+	pub fn rectangle(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, color: Color, thickness: f32)
+	{
+		let t = thickness / 2.;
+		self.filled_rectangle(x1 - t, y1 - t, x2 + t, y1 + t, color);
+		self.filled_rectangle(x1 - t, y2 - t, x2 + t, y2 + t, color);
+		self.filled_rectangle(x1 - t, y1 + t, x1 + t, y2 - t, color);
+		self.filled_rectangle(x2 - t, y1 + t, x2 + t, y2 - t, color);
+	}
+
+	pub fn rounded_rectangle(
+		&mut self, x1: f32, y1: f32, x2: f32, y2: f32, r: f32, color: Color, thickness: f32,
+	)
+	{
+		self.line(x1 + r, y1, x2 - r, y1, color, thickness);
+		self.line(x1 + r, y2, x2 - r, y2, color, thickness);
+		self.line(x1, y1 + r, x1, y2 - r, color, thickness);
+		self.line(x2, y1 + r, x2, y2 - r, color, thickness);
+		self.arc(x2 - r, y1 + r, r, -PI / 2., PI / 2., color, thickness);
+		self.arc(x2 - r, y2 - r, r, 0., PI / 2., color, thickness);
+		self.arc(x1 + r, y2 - r, r, PI / 2., PI / 2., color, thickness);
+		self.arc(x1 + r, y1 + r, r, PI, PI / 2., color, thickness);
+	}
+
+	// Same angle convention as PrimitivesAddon::draw_arc.
+	pub fn arc(
+		&mut self, cx: f32, cy: f32, r: f32, start_theta: f32, delta_theta: f32, color: Color,
+		thickness: f32,
+	)
+	{
+		let num_segments = ((delta_theta.abs() * r / 4.).ceil() as i32).max(4);
+		let (r1, r2) = (r - thickness / 2., r + thickness / 2.);
+		let point = |theta: f32, r: f32| (cx + r * theta.cos(), cy + r * theta.sin());
+		for i in 0..num_segments
+		{
+			let theta1 = start_theta + delta_theta * i as f32 / num_segments as f32;
+			let theta2 = start_theta + delta_theta * (i + 1) as f32 / num_segments as f32;
+			self.quad(
+				[
+					point(theta1, r1),
+					point(theta1, r2),
+					point(theta2, r2),
+					point(theta2, r1),
+				],
+				color,
+			);
+		}
+	}
+
+	pub fn text(&mut self, color: Color, x: f32, y: f32, align: FontAlign, text: &str)
+	{
+		self.texts.push(Text {
+			color: color,
+			x: x,
+			y: y,
+			align: align,
+			text: text.to_string(),
+		});
+	}
+
+	fn draw(&self, state: &game_state::GameState)
+	{
+		if !self.vtxs.is_empty()
+		{
+			state.prim.draw_prim(
+				&self.vtxs[..],
+				Option::<&Bitmap>::None,
+				0,
+				self.vtxs.len() as u32,
+				PrimType::TriangleList,
+			);
+		}
+		if !self.texts.is_empty()
+		{
+			state.core.hold_bitmap_drawing(true);
+			for text in &self.texts
+			{
+				ui::draw_floating_text(state, text.color, text.x, text.y, text.align, &text.text);
+			}
+			state.core.hold_bitmap_drawing(false);
+		}
+	}
+}
+
+// What the layout depends on besides the key, i.e. the UI options.
+type Style = (bool, u32);
+
+fn style(state: &game_state::GameState) -> Style
+{
+	(ui::high_contrast(), state.options.ui_font_scale.to_bits())
+}
+
+// A batch that is only rebuilt when the key it was laid out for, or the style, changes.
+pub struct UiLayer<K>
+{
+	cache: RefCell<Option<((K, Style), Batch)>>,
+}
+
+impl<K: PartialEq> UiLayer<K>
+{
+	pub fn new() -> Self
+	{
+		Self {
+			cache: RefCell::new(None),
+		}
+	}
+
+	pub fn draw(&self, key: K, state: &game_state::GameState, layout: impl FnOnce(&mut Batch))
+	{
+		let key = (key, style(state));
+		let mut cache = self.cache.borrow_mut();
+		if cache.as_ref().map(|(k, _)| *k != key).unwrap_or(true)
+		{
+			let mut batch = Batch::default();
+			layout(&mut batch);
+			*cache = Some((key, batch));
+		}
+		cache.as_ref().unwrap().1.draw(state);
+	}
+}
+
+// A screen-sized bitmap that is only redrawn when the hash of what's shown on it, or the style,
+// changes.
+pub struct BitmapLayer
+{
+	cache: RefCell<Option<(u64, Bitmap)>>,
+}
+
+impl BitmapLayer
+{
+	pub fn new() -> Self
+	{
+		Self {
+			cache: RefCell::new(None),
+		}
+	}
+
+	pub fn draw(&self, key: impl Hash, state: &game_state::GameState, draw: impl FnOnce())
+	{
+		let mut hasher = DefaultHasher::new();
+		(key, style(state)).hash(&mut hasher);
+		let hash = hasher.finish();
+
+		let mut cache = self.cache.borrow_mut();
+		if cache.as_ref().map(|(h, _)| *h != hash).unwrap_or(true)
+		{
+			let bitmap = match cache.take()
+			{
+				Some((_, bitmap)) => Some(bitmap),
+				None => Bitmap::new(
+					&state.core,
+					state.display_width as i32,
+					state.display_height as i32,
+				)
+				.ok(),
+			};
+			let bitmap = if let Some(bitmap) = bitmap
+			{
+				bitmap
+			}
+			else
+			{
+				// No bitmap to spare, just draw it directly.
+				draw();
+				return;
+			};
+			state.core.set_target_bitmap(Some(&bitmap));
+			// Shaders are per target bitmap.
+			state
+				.core
+				.use_shader(Some(&*state.default_shader.upgrade().unwrap()))
+				.unwrap();
+			state
+				.core
+				.clear_to_color(Color::from_rgba_f(0., 0., 0., 0.));
+			draw();
+			state.core.set_target_bitmap(state.buffer.as_ref());
+			*cache = Some((hash, bitmap));
+		}
+		state
+			.core
+			.draw_bitmap(&cache.as_ref().unwrap().1, 0., 0., Flag::zero());
+	}
+}
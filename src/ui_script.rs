@@ -0,0 +1,358 @@
+use crate::error::Result;
+use crate::{controls, game_state, utils};
+
+use allegro::*;
+use std::ptr::null_mut;
+
+// What a script can check about the running game.
+pub struct Probe
+{
+	pub screen: &'static str,
+	pub subscreen: Option<&'static str>,
+	pub dragging: bool,
+}
+
+enum Input
+{
+	KeyDown(KeyCode),
+	KeyUp(KeyCode),
+	KeyChar(KeyCode),
+	MouseMove(i32, i32),
+	MouseDown(i32, i32, u32),
+	MouseUp(i32, i32, u32),
+}
+
+impl Input
+{
+	fn to_event(&self, timestamp: f64) -> Event
+	{
+		match *self
+		{
+			Input::KeyDown(keycode) => Event::KeyDown {
+				source: null_mut(),
+				timestamp: timestamp,
+				keycode: keycode,
+				display: null_mut(),
+			},
+			Input::KeyUp(keycode) => Event::KeyUp {
+				source: null_mut(),
+				timestamp: timestamp,
+				keycode: keycode,
+				display: null_mut(),
+			},
+			Input::KeyChar(keycode) => Event::KeyChar {
+				source: null_mut(),
+				timestamp: timestamp,
+				keycode: keycode,
+				display: null_mut(),
+				unichar: '\0',
+				repeat: false,
+				modifiers: KeyModifier::zero(),
+			},
+			Input::MouseMove(x, y) => Event::MouseAxes {
+				source: null_mut(),
+				timestamp: timestamp,
+				x: x,
+				y: y,
+				z: 0,
+				w: 0,
+				dx: 0,
+				dy: 0,
+				dz: 0,
+				dw: 0,
+				display: null_mut(),
+			},
+			Input::MouseDown(x, y, button) => Event::MouseButtonDown {
+				source: null_mut(),
+				timestamp: timestamp,
+				x: x,
+				y: y,
+				z: 0,
+				w: 0,
+				button: button,
+				display: null_mut(),
+			},
+			Input::MouseUp(x, y, button) => Event::MouseButtonUp {
+				source: null_mut(),
+				timestamp: timestamp,
+				x: x,
+				y: y,
+				z: 0,
+				w: 0,
+				button: button,
+				display: null_mut(),
+			},
+		}
+	}
+}
+
+enum Step
+{
+	Inputs(Vec<Input>),
+	Wait(i32),
+	ExpectScreen(String),
+	ExpectSubscreen(Option<String>),
+	ExpectDragging(bool),
+	ExpectControl(String, String),
+}
+
+// Synthetic input for testing the UI, one command per line:
+//
+// key <Key> / down <Key> / up <Key>
+// move <x> <y>
+// click <x> <y> [button] / press <x> <y> [button] / release <x> <y> [button]
+// wait <ticks>
+// expect screen <Menu|Game|Editor>
+// expect subscreen <Name|none>
+// expect dragging <yes|no>
+// expect control <Action> = <Keys>
+//
+// Each line of input gets its own logic tick.
+pub struct Script
+{
+	steps: Vec<(usize, Step)>,
+	cur: usize,
+	wait: i32,
+}
+
+fn parse_key(s: &str) -> Option<KeyCode>
+{
+	match controls::Input::from_str(s)?
+	{
+		controls::Input::Keyboard(keycode) => Some(keycode),
+		_ => None,
+	}
+}
+
+fn parse_mouse(args: &[&str]) -> Option<(i32, i32, u32)>
+{
+	let x = args.first()?.parse().ok()?;
+	let y = args.get(1)?.parse().ok()?;
+	let button = match args.get(2)
+	{
+		Some(b) => b.parse().ok()?,
+		None => 1,
+	};
+	Some((x, y, button))
+}
+
+fn parse_line(line: &str) -> Option<Step>
+{
+	let words: Vec<_> = line.split_whitespace().collect();
+	let (command, args) = words.split_first()?;
+	let step = match (*command, args)
+	{
+		("key", [key]) =>
+		{
+			let keycode = parse_key(key)?;
+			Step::Inputs(vec![
+				Input::KeyDown(keycode),
+				Input::KeyChar(keycode),
+				Input::KeyUp(keycode),
+			])
+		}
+		("down", [key]) => Step::Inputs(vec![Input::KeyDown(parse_key(key)?)]),
+		("up", [key]) => Step::Inputs(vec![Input::KeyUp(parse_key(key)?)]),
+		("move", args) =>
+		{
+			let (x, y, _) = parse_mouse(args)?;
+			Step::Inputs(vec![Input::MouseMove(x, y)])
+		}
+		("click", args) =>
+		{
+			let (x, y, button) = parse_mouse(args)?;
+			Step::Inputs(vec![
+				Input::MouseMove(x, y),
+				Input::MouseDown(x, y, button),
+				Input::MouseUp(x, y, button),
+			])
+		}
+		("press", args) =>
+		{
+			let (x, y, button) = parse_mouse(args)?;
+			Step::Inputs(vec![Input::MouseMove(x, y), Input::MouseDown(x, y, button)])
+		}
+		("release", args) =>
+		{
+			let (x, y, button) = parse_mouse(args)?;
+			Step::Inputs(vec![Input::MouseMove(x, y), Input::MouseUp(x, y, button)])
+		}
+		("wait", [ticks]) => Step::Wait(ticks.parse().ok()?),
+		("expect", ["screen", name]) => Step::ExpectScreen(name.to_string()),
+		("expect", ["subscreen", "none"]) => Step::ExpectSubscreen(None),
+		("expect", ["subscreen", name]) => Step::ExpectSubscreen(Some(name.to_string())),
+		("expect", ["dragging", "yes"]) => Step::ExpectDragging(true),
+		("expect", ["dragging", "no"]) => Step::ExpectDragging(false),
+		("expect", ["control", ..]) =>
+		{
+			let rest = line.trim_start().strip_prefix("expect")?.trim_start();
+			let rest = rest.strip_prefix("control")?;
+			let (action, keys) = rest.split_once('=')?;
+			Step::ExpectControl(action.trim().to_string(), keys.trim().to_string())
+		}
+		_ => return None,
+	};
+	Some(step)
+}
+
+impl Script
+{
+	pub fn load(filename: &str) -> Result<Self>
+	{
+		let contents = utils::read_to_string(filename)?;
+		let mut steps = vec![];
+		for (i, line) in contents.lines().enumerate()
+		{
+			let line = line.split('#').next().unwrap();
+			if line.trim().is_empty()
+			{
+				continue;
+			}
+			let step = parse_line(line)
+				.ok_or_else(|| format!("{filename}:{}: Can't parse '{}'", i + 1, line.trim()))?;
+			steps.push((i + 1, step));
+		}
+		Ok(Self {
+			steps: steps,
+			cur: 0,
+			wait: 0,
+		})
+	}
+
+	// Called once per logic tick. Returns the events to feed in, or None once the script is done.
+	pub fn tick(
+		&mut self, probe: &Probe, state: &game_state::GameState,
+	) -> Result<Option<Vec<Event>>>
+	{
+		if self.wait > 0
+		{
+			self.wait -= 1;
+			return Ok(Some(vec![]));
+		}
+		while let Some((line, step)) = self.steps.get(self.cur)
+		{
+			self.cur += 1;
+			let fail = |what: String| -> Result<Option<Vec<Event>>> {
+				Err(format!("UI script line {line}: {what}").into())
+			};
+			match step
+			{
+				Step::Inputs(inputs) =>
+				{
+					let time = state.core.get_time();
+					return Ok(Some(inputs.iter().map(|i| i.to_event(time)).collect()));
+				}
+				Step::Wait(ticks) =>
+				{
+					self.wait = *ticks;
+					return Ok(Some(vec![]));
+				}
+				Step::ExpectScreen(name) =>
+				{
+					if probe.screen != name
+					{
+						return fail(format!("expected screen {name}, got {}", probe.screen));
+					}
+				}
+				Step::ExpectSubscreen(name) =>
+				{
+					if probe.subscreen != name.as_deref()
+					{
+						return fail(format!(
+							"expected subscreen {}, got {}",
+							name.as_deref().unwrap_or("none"),
+							probe.subscreen.unwrap_or("none")
+						));
+					}
+				}
+				Step::ExpectDragging(dragging) =>
+				{
+					if probe.dragging != *dragging
+					{
+						return fail(format!(
+							"expected dragging to be {dragging}, got {}",
+							probe.dragging
+						));
+					}
+				}
+				Step::ExpectControl(action_name, keys) =>
+				{
+					let action = state
+						.controls
+						.get_actions_to_inputs()
+						.map(|(action, _)| *action)
+						.find(|action| action.to_str() == action_name);
+					let action = if let Some(action) = action
+					{
+						action
+					}
+					else
+					{
+						return fail(format!("unknown action {action_name}"));
+					};
+					let actual = state.controls.get_controls().get_action_string(action);
+					if actual != *keys
+					{
+						return fail(format!("expected {action_name} = {keys}, got {actual}"));
+					}
+				}
+			}
+		}
+		Ok(None)
+	}
+}
+
+#[test]
+fn test_parse_key()
+{
+	assert_eq!(parse_key("Enter"), Some(KeyCode::Enter));
+	assert_eq!(parse_key("Y"), Some(KeyCode::Y));
+	assert_eq!(parse_key("Mouse Left"), None);
+	assert_eq!(parse_key("Bogus"), None);
+}
+
+#[test]
+fn test_parse_mouse()
+{
+	assert_eq!(parse_mouse(&["10", "20"]), Some((10, 20, 1)));
+	assert_eq!(parse_mouse(&["10", "20", "2"]), Some((10, 20, 2)));
+	assert_eq!(parse_mouse(&["10"]), None);
+	assert_eq!(parse_mouse(&["10", "x"]), None);
+}
+
+#[test]
+fn test_parse_line()
+{
+	assert!(matches!(
+		parse_line("key Enter"),
+		Some(Step::Inputs(inputs)) if matches!(
+			inputs[..],
+			[
+				Input::KeyDown(KeyCode::Enter),
+				Input::KeyChar(KeyCode::Enter),
+				Input::KeyUp(KeyCode::Enter)
+			]
+		)
+	));
+	assert!(matches!(
+		parse_line("press 10 20"),
+		Some(Step::Inputs(inputs))
+			if matches!(inputs[..], [Input::MouseMove(10, 20), Input::MouseDown(10, 20, 1)])
+	));
+	assert!(matches!(parse_line("wait 3"), Some(Step::Wait(3))));
+	assert!(matches!(
+		parse_line("expect subscreen none"),
+		Some(Step::ExpectSubscreen(None))
+	));
+	assert!(matches!(
+		parse_line("expect dragging yes"),
+		Some(Step::ExpectDragging(true))
+	));
+	assert!(matches!(
+		parse_line("expect control Stop = Ctrl+Mouse Left"),
+		Some(Step::ExpectControl(action, keys)) if action == "Stop" && keys == "Ctrl+Mouse Left"
+	));
+	assert!(parse_line("expect dragging maybe").is_none());
+	assert!(parse_line("key").is_none());
+	assert!(parse_line("jump 1 2").is_none());
+}
@@ -8,10 +8,12 @@ use nalgebra;
 use rand::prelude::*;
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
-use slr_config::{from_element, to_element, ConfigElement, Source};
+use slr_config::{from_element, to_element, ConfigElement, ErrorKind, Source};
 use std::{fs, path};
 
-pub const DT: f32 = 1. / 120.;
+// Logic ticks per second that can be picked in the options.
+pub const TICK_RATES: [i32; 3] = [30, 60, 120];
+pub const DEFAULT_TICK_RATE: i32 = 120;
 pub const PI: f32 = std::f32::consts::PI;
 pub type Vec2D = nalgebra::Vector2<f32>;
 pub type Vec3D = nalgebra::Vector3<f32>;
@@ -45,6 +47,18 @@ pub fn make_camera(eye: Point3<f32>, target: Point3<f32>) -> Isometry3<f32>
 	Isometry3::look_at_rh(&eye, &target, &Vector3::y())
 }
 
+// A jittery offset for shaking the camera, intensity is in world units.
+pub fn camera_shake(intensity: f32, time: f64) -> Vector3<f32>
+{
+	let t = time as f32 * 40.;
+	intensity
+		* Vector3::new(
+			(1.1 * t).sin() * (0.37 * t).cos(),
+			0.,
+			(1.3 * t).cos() * (0.53 * t).sin(),
+		)
+}
+
 pub fn random_color(seed: u64, saturation: f32, value: f32) -> Color
 {
 	let mut rng = StdRng::seed_from_u64(seed);
@@ -112,6 +126,54 @@ pub fn clamp<T: PartialOrd>(x: T, min: T, max: T) -> T
 	}
 }
 
+pub fn dt(tick_rate: i32) -> f32
+{
+	1. / tick_rate.clamp(TICK_RATES[0], TICK_RATES[TICK_RATES.len() - 1]) as f32
+}
+
+// Chance of something happening within dt, given it happens `rate` times per second on average.
+// Unlike `rate * dt` this doesn't depend on the tick rate.
+pub fn rate_to_prob(rate: f64, dt: f64) -> f64
+{
+	1. - (-rate * dt).exp()
+}
+
+// How far to move towards a target within dt, when closing `rate` of the gap per second. The same
+// as the chance of reaching it, were that a random event.
+pub fn approach_frac(rate: f32, dt: f32) -> f32
+{
+	rate_to_prob(rate as f64, dt as f64) as f32
+}
+
+// Goes the short way around.
+pub fn lerp_angle(from: f32, to: f32, alpha: f32) -> f32
+{
+	let mut diff = (to - from).rem_euclid(2. * PI);
+	if diff > PI
+	{
+		diff -= 2. * PI;
+	}
+	from + alpha * diff
+}
+
+// Which way a world-space (x, z) offset points, north being up the screen.
+pub fn compass_direction(diff: Vector2<f32>) -> &'static str
+{
+	let dirs = [
+		"east",
+		"south-east",
+		"south",
+		"south-west",
+		"west",
+		"north-west",
+		"north",
+		"north-east",
+	];
+	let angle = diff.y.atan2(diff.x);
+	let idx = (angle / (PI / 4.)).round() as i32;
+	dirs[idx.rem_euclid(8) as usize]
+}
+
 pub fn round_point(vec: Point2<f32>) -> Point2<f32>
 {
 	Point2::new(vec.x.round(), vec.y.round())
@@ -128,16 +190,144 @@ pub fn read_to_string(path: &str) -> Result<String>
 		.map_err(|e| Error::new(format!("Couldn't read '{}'", path), Some(Box::new(e))))
 }
 
+// Layers `over` on top of `base`: tables are merged key by key, everything else is replaced.
+pub fn merge_config(base: &mut ConfigElement, over: ConfigElement)
+{
+	if base.as_table().is_some() && over.as_table().is_some()
+	{
+		let base_table = base.as_table_mut().unwrap();
+		for (name, over_elem) in over.into_table().unwrap()
+		{
+			match base_table.get_mut(&name)
+			{
+				Some(base_elem) => merge_config(base_elem, over_elem),
+				None =>
+				{
+					base_table.insert(name, over_elem);
+				}
+			}
+		}
+	}
+	else
+	{
+		*base = over;
+	}
+}
+
+const MAX_CONFIG_BASES: usize = 8;
+
+// A config can name another config in its `base` field, and only list what it changes about
+// it. Returns whether that happened, since then not all the spans point into this file.
+fn resolve_base(
+	file: &str, mut element: ConfigElement, depth: usize,
+) -> Result<(ConfigElement, bool)>
+{
+	let base = element
+		.as_table_mut()
+		.and_then(|t| t.remove("base"))
+		.and_then(|b| b.into_value());
+	let base = if let Some(base) = base
+	{
+		base
+	}
+	else
+	{
+		return Ok((element, false));
+	};
+	if depth >= MAX_CONFIG_BASES
+	{
+		return Err(format!("'{}' has too many levels of bases", file).into());
+	}
+	let contents = read_to_string(&base)?;
+	let base_element = ConfigElement::from_str(&contents).map_err(|e| {
+		Error::new(
+			format!("Config parsing error in '{}'", base),
+			Some(Box::new(e)),
+		)
+	})?;
+	let (mut merged, _) = resolve_base(&base, base_element, depth + 1)?;
+	merge_config(&mut merged, element);
+	Ok((merged, true))
+}
+
+// The config with its bases (if any) layered in.
+pub fn read_config(file: &str) -> Result<ConfigElement>
+{
+	let contents = read_to_string(file)?;
+	let element = ConfigElement::from_str(&contents)
+		.map_err(|e| Error::new(format!("Config parsing error"), Some(Box::new(e))))?;
+	Ok(resolve_base(file, element, 0)?.0)
+}
+
 pub fn load_config<T: DeserializeOwned + Clone>(file: &str) -> Result<T>
 {
 	let contents = read_to_string(file)?;
 	let mut source = Source::new(path::Path::new(file), &contents);
 	let element = ConfigElement::from_source(&mut source)
 		.map_err(|e| Error::new(format!("Config parsing error"), Some(Box::new(e))))?;
-	from_element::<T>(&element, Some(&source))
+	let (element, inherited) = resolve_base(file, element, 0)?;
+	from_element::<T>(&element, (!inherited).then_some(&source))
 		.map_err(|e| Error::new(format!("Config parsing error"), Some(Box::new(e))))
 }
 
+// Like load_config, but also runs a validator over the loaded value. The validator returns the
+// offending elements so that every problem is reported with the line it came from.
+pub fn load_validated_config<T, F>(file: &str, validate: F) -> Result<T>
+where
+	T: DeserializeOwned + Clone,
+	F: for<'l> FnOnce(&T, &'l ConfigElement) -> Vec<(&'l ConfigElement, String)>,
+{
+	let contents = read_to_string(file)?;
+	let mut source = Source::new(path::Path::new(file), &contents);
+	let element = ConfigElement::from_source(&mut source)
+		.map_err(|e| Error::new(format!("Config parsing error"), Some(Box::new(e))))?;
+	let (element, inherited) = resolve_base(file, element, 0)?;
+	let source = (!inherited).then_some(&source);
+	let val = from_element::<T>(&element, source)
+		.map_err(|e| Error::new(format!("Config parsing error"), Some(Box::new(e))))?;
+	let problems = validate(&val, &element);
+	if problems.is_empty()
+	{
+		return Ok(val);
+	}
+	let mut message = format!("'{}' has {} problem(s):\n", file, problems.len());
+	for (element, problem) in problems
+	{
+		let error =
+			slr_config::Error::from_span(element.span(), source, ErrorKind::Custom(0), &problem);
+		message.push_str(&error.text);
+	}
+	Err(message.into())
+}
+
+// Finds the element at the path, e.g. ["slots", "2", "pos"]. If the path doesn't fully exist,
+// returns the deepest element that does.
+pub fn config_field<'l>(element: &'l ConfigElement, path: &[&str]) -> &'l ConfigElement
+{
+	let mut cur = element;
+	for name in path
+	{
+		let next = if let Some(table) = cur.as_table()
+		{
+			table.get(*name)
+		}
+		else if let Some(array) = cur.as_array()
+		{
+			name.parse::<usize>().ok().and_then(|i| array.get(i))
+		}
+		else
+		{
+			None
+		};
+		match next
+		{
+			Some(next) => cur = next,
+			None => break,
+		}
+	}
+	cur
+}
+
 pub fn save_config<T: Serialize>(file: &str, val: T) -> Result<()>
 {
 	let element = to_element(&val)
@@ -426,3 +616,79 @@ fn test_segment_segment2()
 
 	assert!(intersect_segment_segment(start1, end1, start2, end2));
 }
+
+#[test]
+fn test_lerp_angle()
+{
+	assert!((lerp_angle(0., 1., 0.5) - 0.5).abs() < 1e-5);
+	assert!((lerp_angle(0.1, 2. * PI - 0.1, 0.5)).abs() < 1e-5);
+	assert!((lerp_angle(2. * PI - 0.1, 0.1, 0.5) - 2. * PI).abs() < 1e-5);
+	assert!((lerp_angle(1., 2., 1.) - 2.).abs() < 1e-5);
+}
+
+#[test]
+fn test_rate_to_prob_invariance()
+{
+	// Chance of nothing happening over a second shouldn't depend on the tick rate.
+	let survive = |tick_rate: i32| {
+		let dt = dt(tick_rate) as f64;
+		(1. - rate_to_prob(0.7, dt)).powi(tick_rate)
+	};
+	for &tick_rate in &TICK_RATES
+	{
+		assert!((survive(tick_rate) - (-0.7f64).exp()).abs() < 1e-9);
+	}
+}
+
+#[test]
+fn test_approach_frac_invariance()
+{
+	let approach = |tick_rate: i32| {
+		let dt = dt(tick_rate);
+		let mut x = 0.;
+		for _ in 0..tick_rate * 2
+		{
+			x += approach_frac(4., dt) * (1. - x);
+		}
+		x
+	};
+	for &tick_rate in &TICK_RATES
+	{
+		assert!((approach(tick_rate) - approach(DEFAULT_TICK_RATE)).abs() < 1e-4);
+	}
+}
+
+#[test]
+fn test_compass_direction()
+{
+	assert_eq!(compass_direction(Vector2::new(1., 0.)), "east");
+	assert_eq!(compass_direction(Vector2::new(0., -1.)), "north");
+	assert_eq!(compass_direction(Vector2::new(-1., 1.)), "south-west");
+	assert_eq!(compass_direction(Vector2::new(-1., -0.1)), "west");
+}
+
+#[test]
+fn test_dt_clamped()
+{
+	assert_eq!(dt(1000), dt(120));
+	assert_eq!(dt(0), dt(30));
+}
+
+#[test]
+fn test_merge_config()
+{
+	let mut base = ConfigElement::from_str(
+		"mesh = \"a.glb\"\nsize = 2\nstats { hull = 50\ncrew = 20 }\nlights = [1, 2]",
+	)
+	.unwrap();
+	let over = ConfigElement::from_str("size = 3\nstats { crew = 30 }\nlights = [3]").unwrap();
+	merge_config(&mut base, over);
+
+	let table = base.as_table().unwrap();
+	assert_eq!(table["mesh"].as_value().unwrap(), "a.glb");
+	assert_eq!(table["size"].as_value().unwrap(), "3");
+	let stats = table["stats"].as_table().unwrap();
+	assert_eq!(stats["hull"].as_value().unwrap(), "50");
+	assert_eq!(stats["crew"].as_value().unwrap(), "30");
+	assert_eq!(table["lights"].as_array().unwrap().len(), 1);
+}